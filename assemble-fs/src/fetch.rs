@@ -0,0 +1,82 @@
+use std::{
+	fs::{self, File},
+	io,
+	path::{Path, PathBuf},
+	process::Command,
+};
+
+use hash::{encoding::to_hex, Digest, Sha256};
+use serde::Deserialize;
+
+/// Where fetched files are cached, unless the config says otherwise.
+pub const DEFAULT_CACHE_DIR: &str = "./target/assemble-fs-cache";
+
+/// A file that is downloaded into the image, e.g. prebuilt kernel modules or firmware.
+#[derive(Debug, Deserialize)]
+pub struct RemoteFile {
+	/// Where to download the file from. Anything that curl understands works, including `file://` URLs.
+	pub url: String,
+
+	/// The hex encoded SHA-256 digest that the file must have.
+	pub sha256: String,
+}
+
+/// Fetches the file into the cache (unless it's already there), and verifies it, returning its path in the cache.
+/// Files are cached by their digest, so changing the URL of a file doesn't download it again.
+pub fn fetch(logger: &slog::Logger, remote: &RemoteFile, cache_dir: &Path) -> io::Result<PathBuf> {
+	let expected = remote.sha256.to_ascii_lowercase();
+	if expected.len() != 64 || !expected.chars().all(|c| c.is_ascii_hexdigit()) {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("invalid sha256 for {}: {}", remote.url, remote.sha256),
+		));
+	}
+
+	let cached = cache_dir.join(&expected);
+	if cached.exists() {
+		if sha256(&cached)? == expected {
+			slog::info!(logger, "Using cached file"; "url"=>&remote.url, "path"=>cached.display());
+			return Ok(cached);
+		}
+
+		slog::warn!(logger, "Cached file is corrupt, fetching it again"; "url"=>&remote.url, "path"=>cached.display());
+	}
+
+	fs::create_dir_all(cache_dir)?;
+
+	// Download next to the cached file, so that a failed download is never mistaken for a cached one.
+	let partial = cache_dir.join(format!("{}.partial", expected));
+	slog::info!(logger, "Fetching file"; "url"=>&remote.url);
+	let status = Command::new("curl")
+		.args(["--fail", "--silent", "--show-error", "--location", "--output"])
+		.arg(&partial)
+		.arg(&remote.url)
+		.status()?;
+
+	if !status.success() {
+		let _ = fs::remove_file(&partial);
+		return Err(io::Error::other(format!("failed to fetch {}", remote.url)));
+	}
+
+	let actual = sha256(&partial)?;
+	if actual != expected {
+		fs::remove_file(&partial)?;
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			format!(
+				"checksum mismatch for {}: expected {}, got {}",
+				remote.url, expected, actual
+			),
+		));
+	}
+
+	fs::rename(&partial, &cached)?;
+	Ok(cached)
+}
+
+/// The hex encoded SHA-256 digest of the file.
+fn sha256(path: &Path) -> io::Result<String> {
+	let mut hasher = Sha256::new();
+	io::copy(&mut File::open(path)?, &mut hasher)?;
+	Ok(to_hex(hasher.finalize().as_ref()))
+}
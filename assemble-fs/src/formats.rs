@@ -1,13 +1,66 @@
-use std::{fs::File, io, path::Path, process::Command};
+use std::{
+	collections::HashMap,
+	fs::{self, File},
+	io,
+	path::{Path, PathBuf},
+	process::Command,
+};
 
-use cpio::CPIOArchive;
+use common::fswalk::FsWalk;
+use cpio::{CPIOArchive, Entry};
+use superblocks::FatFilesystem;
 
-pub fn write_cpio(path: &Path, out_path: &Path) -> io::Result<()> {
+/// The directory in the early microcode archive that the kernel loads microcode from.
+const MICROCODE_DIR: &str = "kernel/x86/microcode";
+
+/// The CPU vendors that the kernel looks for early microcode for.
+const MICROCODE_VENDORS: &[&str] = &["GenuineIntel", "AuthenticAMD"];
+
+/// The smallest FAT image that's written, in KiB, which is big enough for mkfs.vfat to make it FAT32 as the UEFI
+/// specification expects of EFI system partitions.
+const MIN_VFAT_SIZE_KIB: u64 = 64 * 1024;
+
+/// Writes the given directory to a CPIO archive. If an early microcode archive is given, it's written first, as a
+/// separate uncompressed segment, so that the kernel can find it before unpacking the rest.
+pub fn write_cpio(path: &Path, out_path: &Path, microcode: Option<&CPIOArchive>) -> io::Result<()> {
 	let mut out_file = File::create(out_path)?;
+	if let Some(microcode) = microcode {
+		microcode.write_segment(&mut out_file)?;
+	}
+
 	let archive = CPIOArchive::from_path(path)?;
 	archive.write(&mut out_file)
 }
 
+/// Builds an early microcode archive, where each vendor's blobs are concatenated into `<vendor>.bin`.
+pub fn microcode_archive(microcode: &HashMap<String, Vec<PathBuf>>) -> io::Result<CPIOArchive> {
+	let mut entries = vec![
+		Entry::directory("kernel"),
+		Entry::directory("kernel/x86"),
+		Entry::directory(MICROCODE_DIR),
+	];
+
+	let mut vendors: Vec<&String> = microcode.keys().collect();
+	vendors.sort();
+	for vendor in vendors {
+		if !MICROCODE_VENDORS.contains(&vendor.as_str()) {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("unknown microcode vendor: {}", vendor),
+			));
+		}
+
+		let mut data = Vec::new();
+		for blob in microcode[vendor].iter() {
+			data.extend(fs::read(blob)?);
+		}
+
+		entries.push(Entry::file(&format!("{}/{}.bin", MICROCODE_DIR, vendor), data));
+	}
+
+	Ok(CPIOArchive { entries })
+}
+
 pub fn write_ext4(path: &Path, out_path: &Path) -> io::Result<()> {
 	// Shell out to mke2fs because writing an ext4 filesystem is hard.
 	let status = Command::new("mke2fs")
@@ -28,6 +81,80 @@ pub fn write_ext4(path: &Path, out_path: &Path) -> io::Result<()> {
 
 	match status.success() {
 		true => Ok(()),
-		false => Err(io::Error::new(io::ErrorKind::Other, "mke2fs failed")),
+		false => Err(io::Error::other("mke2fs failed")),
+	}
+}
+
+/// Writes the given directory to a FAT image, e.g. an EFI system partition, and then reads it back to check that
+/// everything made it in intact.
+pub fn write_vfat(path: &Path, out_path: &Path) -> io::Result<()> {
+	// Shell out to mkfs.vfat and mcopy, like mke2fs for ext4, leaving some room for the filesystem's own metadata.
+	let mut size = 0;
+	for entry in FsWalk::new(path) {
+		size += entry?.metadata.len();
 	}
+
+	let size_kib = (size / 1024 * 2).max(MIN_VFAT_SIZE_KIB);
+	if out_path.exists() {
+		fs::remove_file(out_path)?;
+	}
+
+	let status = Command::new("mkfs.vfat")
+		.arg("-C")
+		.arg("-n")
+		.arg("EFI")
+		.arg(out_path)
+		.arg(size_kib.to_string())
+		.status()?;
+	if !status.success() {
+		return Err(io::Error::other("mkfs.vfat failed"));
+	}
+
+	let mut contents = fs::read_dir(path)?
+		.map(|entry| entry.map(|entry| entry.path()))
+		.collect::<io::Result<Vec<_>>>()?;
+	if !contents.is_empty() {
+		contents.sort();
+		let status = Command::new("mcopy")
+			.arg("-s")
+			.arg("-i")
+			.arg(out_path)
+			.args(contents)
+			.arg("::/")
+			.status()?;
+		if !status.success() {
+			return Err(io::Error::other("mcopy failed"));
+		}
+	}
+
+	validate_vfat(path, out_path)
+}
+
+/// Checks that every file and directory under the given directory is in the FAT image, with the same contents.
+pub fn validate_vfat(path: &Path, image: &Path) -> io::Result<()> {
+	let mut filesystem = FatFilesystem::open(File::open(image)?).map_err(io::Error::other)?;
+	for entry in FsWalk::new(path) {
+		let entry = entry?;
+		let relative = match entry.path.strip_prefix(path) {
+			Ok(relative) => relative.to_string_lossy().into_owned(),
+			Err(_) => unreachable!("BUG: walked outside of the root"),
+		};
+
+		let mismatch = |reason: &str| {
+			io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("{} in {}: {}", relative, image.display(), reason),
+			)
+		};
+
+		if entry.metadata.is_dir() {
+			if !filesystem.metadata(&relative).map_err(io::Error::other)?.is_dir() {
+				return Err(mismatch("isn't a directory"));
+			}
+		} else if filesystem.read_file(&relative).map_err(io::Error::other)? != fs::read(&entry.path)? {
+			return Err(mismatch("contents differ"));
+		}
+	}
+
+	Ok(())
 }
@@ -1,3 +1,4 @@
+mod fetch;
 mod formats;
 
 use std::{
@@ -5,23 +6,39 @@ use std::{
 	fs::{self, File},
 	io::{self, stdout},
 	path::{Path, PathBuf},
+	process::Command,
 };
 
 use clap::Parser;
 
-use common::obs::assemble_logger;
+use common::{fsops, obs::assemble_logger};
 use slog::info;
 use std::process::ExitCode;
 
 use serde::Deserialize;
 
+use fetch::RemoteFile;
+
 #[derive(Deserialize)]
 struct Config {
 	libraries: Vec<PathBuf>,
 	binaries: Vec<PathBuf>,
 	secure_binaries: Vec<PathBuf>,
 	files: HashMap<String, PathBuf>,
+
+	/// Files to download into the image, by their destination, which are verified against their checksums.
+	remote_files: Option<HashMap<String, RemoteFile>>,
+
+	/// Where downloaded files are cached between builds.
+	cache_dir: Option<PathBuf>,
 	modules: Option<Vec<PathBuf>>,
+
+	/// The depmod binary to generate the metadata of the modules (modules.dep etc.) with, so that modprobe can load
+	/// them in the image.
+	depmod: Option<PathBuf>,
+
+	/// Microcode blobs to load early, by CPU vendor (`GenuineIntel` or `AuthenticAMD`).
+	microcode: Option<HashMap<String, Vec<PathBuf>>>,
 	output_file: PathBuf,
 }
 
@@ -32,7 +49,11 @@ impl Default for Config {
 			binaries: Vec::new(),
 			secure_binaries: Vec::new(),
 			files: HashMap::new(),
+			remote_files: None,
+			cache_dir: None,
 			modules: None,
+			depmod: None,
+			microcode: None,
 			output_file: PathBuf::from("./initramfs.cpio"),
 		}
 	}
@@ -104,17 +125,38 @@ fn main() -> ExitCode {
 		return ExitCode::FAILURE;
 	}
 
+	let mut module_folder = None;
 	if let Some(mods) = config.modules {
 		if cli.kernel_release.is_none() {
 			slog::error!(logger, "kernel modules specified, without a release");
 			return ExitCode::FAILURE;
 		}
 
-		let module_folder = PathBuf::from("/lib/modules").join(cli.kernel_release.unwrap());
+		let folder = PathBuf::from("/lib/modules").join(cli.kernel_release.unwrap());
 		for module in mods {
-			let mod_path = module_folder.join(module);
+			let mod_path = folder.join(module);
 			config.files.insert(mod_path.to_string_lossy().into_owned(), mod_path);
 		}
+
+		module_folder = Some(folder);
+	}
+
+	if let Some(remote_files) = config.remote_files.as_ref() {
+		let cache_dir = config
+			.cache_dir
+			.clone()
+			.unwrap_or_else(|| PathBuf::from(fetch::DEFAULT_CACHE_DIR));
+		for (dest, remote) in remote_files.iter() {
+			match fetch::fetch(&logger, remote, &cache_dir) {
+				Ok(path) => {
+					config.files.insert(dest.clone(), path);
+				}
+				Err(e) => {
+					slog::error!(logger, "Failed to fetch file"; "url"=>&remote.url, "error"=>e);
+					return ExitCode::FAILURE;
+				}
+			}
+		}
 	}
 
 	for (dest, src) in config.files.iter() {
@@ -149,12 +191,19 @@ fn main() -> ExitCode {
 				slog::error!(logger, "Failed to copy directory"; "src"=>src.display(), "dest"=>dest.display(), "error"=>e);
 				return ExitCode::FAILURE;
 			}
-		} else if let Err(e) = fs::copy(src, &dest) {
+		} else if let Err(e) = fsops::copy(src, &dest) {
 			slog::error!(logger, "Failed to copy file"; "src"=>src.display(), "dest"=>dest.display(), "error"=>e);
 			return ExitCode::FAILURE;
 		}
 	}
 
+	if let (Some(depmod), Some(module_folder)) = (config.depmod.as_ref(), module_folder.as_ref()) {
+		if let Err(e) = run_depmod(depmod, &base_dir, module_folder) {
+			slog::error!(logger, "Failed to generate module metadata"; "depmod"=>depmod.display(), "error"=>e);
+			return ExitCode::FAILURE;
+		}
+	}
+
 	let extension = config
 		.output_file
 		.extension()
@@ -162,9 +211,27 @@ fn main() -> ExitCode {
 		.to_str()
 		.expect("Output file extension must be a valid UTF-8 string");
 
+	let microcode = match config.microcode.as_ref().map(formats::microcode_archive) {
+		Some(Ok(archive)) => Some(archive),
+		Some(Err(e)) => {
+			slog::error!(logger, "Failed to assemble early microcode"; "error"=>e);
+			return ExitCode::FAILURE;
+		}
+		None => None,
+	};
+
 	let write = match extension {
-		"cpio" => formats::write_cpio(&base_dir, &config.output_file),
+		"cpio" => formats::write_cpio(&base_dir, &config.output_file, microcode.as_ref()),
+		"ext4" if microcode.is_some() => {
+			slog::error!(logger, "Early microcode can only be written to cpio archives");
+			return ExitCode::FAILURE;
+		}
 		"ext4" => formats::write_ext4(&base_dir, &config.output_file),
+		"vfat" if microcode.is_some() => {
+			slog::error!(logger, "Early microcode can only be written to cpio archives");
+			return ExitCode::FAILURE;
+		}
+		"vfat" => formats::write_vfat(&base_dir, &config.output_file),
 		_ => {
 			slog::error!(logger, "Unsupported output file extension"; "extension"=>extension);
 			return ExitCode::FAILURE;
@@ -184,11 +251,25 @@ fn main() -> ExitCode {
 	ExitCode::SUCCESS
 }
 
+/// Runs depmod over the modules staged in the base directory, writing their metadata next to them.
+fn run_depmod(depmod: &Path, base_dir: &Path, module_folder: &Path) -> io::Result<()> {
+	let status = Command::new(depmod)
+		.arg("--base-dir")
+		.arg(base_dir)
+		.arg(module_folder)
+		.status()?;
+
+	match status.success() {
+		true => Ok(()),
+		false => Err(io::Error::other("depmod failed")),
+	}
+}
+
 fn copy_all_to(logger: &slog::Logger, dest_dir: &Path, files: &[PathBuf]) -> io::Result<()> {
 	fs::create_dir_all(dest_dir)?;
 	for file in files {
 		slog::info!(logger, "Copying file {} to {}", file.display(), dest_dir.display());
-		fs::copy(file, dest_dir.join(file.file_name().unwrap()))?;
+		fsops::copy(file, &dest_dir.join(file.file_name().unwrap()))?;
 	}
 
 	Ok(())
@@ -1,6 +1,8 @@
-mod sha;
+mod session;
+mod totp;
 use chrono::DateTime;
-use sha::Sha2Mode;
+use hash::crypt::Sha2Mode;
+pub use session::{LoginSession, LOGINUID_ENV, SESSIONS_DIRECTORY, SESSION_ID_ENV};
 use std::{
 	fmt::{self, Display, Formatter, Write},
 	fs::read_to_string,
@@ -9,6 +11,7 @@ use std::{
 	path::PathBuf,
 };
 use thiserror::Error;
+pub use totp::{Totp, TOTP_DIR};
 
 /// The path to the passwd file.
 const PASSWD_PATH: &str = "/etc/passwd";
@@ -19,6 +22,21 @@ const SHADOW_PATH: &str = "/etc/shadow";
 /// The path to the group file.
 const GROUP_PATH: &str = "/etc/group";
 
+/// The path to the list of shells that users are allowed to log in with.
+const SHELLS_PATH: &str = "/etc/shells";
+
+/// The path to the message shown to users with a nologin shell.
+const NOLOGIN_MESSAGE_PATH: &str = "/etc/nologin.txt";
+
+/// The shells that users are allowed to log in with if there's no shells file.
+const DEFAULT_SHELLS: [&str; 2] = ["/bin/sh", "/bin/qsh"];
+
+/// The shells that mark an account as one that can't be logged in to, e.g. one that only exists to run a service.
+const NOLOGIN_SHELLS: [&str; 4] = ["/sbin/nologin", "/usr/sbin/nologin", "/bin/false", "/usr/bin/false"];
+
+/// The message shown to users with a nologin shell, if there's no nologin message file.
+const DEFAULT_NOLOGIN_MESSAGE: &str = "This account is currently not available.";
+
 /// The placeholder for a non-existent password (i.e an account that cannot be logged in).
 const NON_EXISTANT_PASSWORD: &str = "x";
 
@@ -156,6 +174,66 @@ impl User {
 	pub fn shadow(&self) -> Result<Option<ShadowEntry>, AuthError> {
 		ShadowEntry::from_username(&self.username)
 	}
+
+	/// Returns whether the user has a nologin shell, i.e. their account can't be logged in to.
+	pub fn has_nologin_shell(&self) -> bool {
+		NOLOGIN_SHELLS.iter().any(|shell| self.shell.as_os_str() == *shell)
+	}
+
+	/// Checks whether the user is allowed to log in, before they're authenticated. Returns `AuthError::NoLogin` with
+	/// the message to show them if they have a nologin shell, and `AuthError::UnlistedShell` if the policy requires
+	/// their shell to be in the shells file and it isn't.
+	pub fn is_login_allowed(&self, policy: &LoginPolicy) -> Result<(), AuthError> {
+		if self.has_nologin_shell() {
+			let message = match read_to_string(NOLOGIN_MESSAGE_PATH) {
+				Ok(message) => message.trim_end().to_owned(),
+				Err(e) if e.kind() == io::ErrorKind::NotFound => DEFAULT_NOLOGIN_MESSAGE.to_owned(),
+				Err(e) => return Err(e.into()),
+			};
+
+			return Err(AuthError::NoLogin(message));
+		}
+
+		if policy.require_listed_shell && !read_shells()?.contains(&self.shell) {
+			return Err(AuthError::UnlistedShell(self.shell.clone()));
+		}
+
+		Ok(())
+	}
+}
+
+/// The checks on a user's account that are made before they're allowed to log in.
+#[derive(Debug, Clone)]
+pub struct LoginPolicy {
+	/// Whether the user's shell must be listed in the shells file.
+	pub require_listed_shell: bool,
+}
+
+impl Default for LoginPolicy {
+	fn default() -> Self {
+		Self {
+			require_listed_shell: true,
+		}
+	}
+}
+
+/// Returns the shells that users are allowed to log in with, from the shells file.
+pub fn read_shells() -> Result<Vec<PathBuf>, AuthError> {
+	match read_to_string(SHELLS_PATH) {
+		Ok(shells) => Ok(parse_shells(&shells)),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(DEFAULT_SHELLS.iter().map(PathBuf::from).collect()),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Parses a shells file, which has one shell per line, with blank lines and `#` comments ignored.
+fn parse_shells(shells: &str) -> Vec<PathBuf> {
+	shells
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(PathBuf::from)
+		.collect()
 }
 
 pub struct ShadowEntry {
@@ -348,6 +426,9 @@ pub struct Group {
 
 	/// The name of the group.
 	pub name: String,
+
+	/// The users that have the group as a supplementary group.
+	pub members: Vec<String>,
 }
 
 impl Group {
@@ -356,6 +437,7 @@ impl Group {
 		let new = Self {
 			gid,
 			name: name.to_owned(),
+			members: Vec::new(),
 		};
 
 		new.write()?;
@@ -369,7 +451,7 @@ impl Group {
 		for line in group.lines() {
 			let group = Self::from_group_line(line)?;
 			if group.gid == self.gid {
-				lines_to_write.push(format!("{}:{}:{}:", self.name, "x", self.gid));
+				lines_to_write.push(self.to_group_line());
 				exists = true;
 			} else {
 				lines_to_write.push(line.to_owned());
@@ -377,7 +459,7 @@ impl Group {
 		}
 
 		if !exists {
-			lines_to_write.push(format!("{}:{}:{}:", self.name, "x", self.gid));
+			lines_to_write.push(self.to_group_line());
 		}
 
 		Ok(())
@@ -403,6 +485,21 @@ impl Group {
 		Ok(None)
 	}
 
+	/// Returns the groups that the given user is a member of, not including their primary group (unless they're also
+	/// listed as a member of it).
+	pub fn for_member(username: &str) -> Result<Vec<Self>, AuthError> {
+		let group = read_to_string(GROUP_PATH)?;
+		let mut groups = Vec::new();
+		for line in group.lines() {
+			let group = Self::from_group_line(line)?;
+			if group.members.iter().any(|member| member == username) {
+				groups.push(group);
+			}
+		}
+
+		Ok(groups)
+	}
+
 	/// Returns the group with the given name, if it exists.
 	pub fn from_groupname(name: &str) -> Result<Option<Self>, AuthError> {
 		let group = read_to_string(GROUP_PATH)?;
@@ -427,8 +524,18 @@ impl Group {
 		let gid = parts[2]
 			.parse()
 			.map_err(|_| AuthError::Malformed(format!("malformed gid: {}", parts[2])))?;
+		let members = parts[3]
+			.split(',')
+			.filter(|member| !member.is_empty())
+			.map(str::to_owned)
+			.collect();
+
+		Ok(Self { gid, name, members })
+	}
 
-		Ok(Self { gid, name })
+	/// Formats the group as a line of the group file.
+	fn to_group_line(&self) -> String {
+		format!("{}:x:{}:{}", self.name, self.gid, self.members.join(","))
 	}
 }
 
@@ -460,6 +567,18 @@ pub enum AuthError {
 
 	#[error("No more UIDs or GIDs available")]
 	NoMoreIDs,
+
+	#[error("{0}")]
+	NoLogin(String),
+
+	#[error("Shell {} is not listed in {}", .0.display(), SHELLS_PATH)]
+	UnlistedShell(PathBuf),
+
+	#[error("Second factor secret {} must only be readable by root", .0.display())]
+	InsecureSecret(PathBuf),
+
+	#[error("Too many failed attempts, try again in {0} seconds")]
+	LockedOut(u64),
 }
 
 fn days_since_epoch() -> u32 {
@@ -529,6 +648,28 @@ mod test {
 		assert_eq!(user.shell, PathBuf::from("/bin/bash"));
 	}
 
+	#[test]
+	fn test_login_allowed() {
+		let nologin = User::from_passwd_line("daemon:x:2:2:daemon:/:/sbin/nologin").unwrap();
+		assert!(nologin.has_nologin_shell());
+		let policy = LoginPolicy {
+			require_listed_shell: false,
+		};
+		assert!(matches!(nologin.is_login_allowed(&policy), Err(AuthError::NoLogin(_))));
+
+		let user = User::from_passwd_line("colin:x:1000:1000:Colin:/home/colin:/bin/zsh").unwrap();
+		assert!(!user.has_nologin_shell());
+		assert!(user.is_login_allowed(&policy).is_ok());
+	}
+
+	#[test]
+	fn test_parse_shells() {
+		assert_eq!(
+			parse_shells("# valid login shells\n/bin/sh\n\n  /bin/qsh  \n"),
+			vec![PathBuf::from("/bin/sh"), PathBuf::from("/bin/qsh")]
+		);
+	}
+
 	#[test]
 	fn test_shadow_entry_from_username() {
 		let entry = ShadowEntry::from_shadow_line(
@@ -560,6 +701,12 @@ mod test {
 		let group = Group::from_group_line("root:x:0:").unwrap();
 		assert_eq!(group.gid, 0);
 		assert_eq!(group.name, "root");
+		assert!(group.members.is_empty());
+		assert_eq!(group.to_group_line(), "root:x:0:");
+
+		let group = Group::from_group_line("wheel:x:10:colin,root").unwrap();
+		assert_eq!(group.members, vec!["colin".to_owned(), "root".to_owned()]);
+		assert_eq!(group.to_group_line(), "wheel:x:10:colin,root");
 
 		assert!(Group::from_group_line("YY").is_err());
 	}
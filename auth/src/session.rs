@@ -0,0 +1,192 @@
+use std::{
+	env,
+	fs::{self, OpenOptions},
+	io::{self, Read, Seek, SeekFrom, Write},
+	os::fd::AsRawFd,
+	path::Path,
+};
+
+use nix::fcntl::{flock, FlockArg};
+
+use crate::{AuthError, User};
+
+/// The directory that holds the counter that session IDs are allocated from when the kernel doesn't allocate them.
+pub const SESSIONS_DIRECTORY: &str = "/run/qos/sessions";
+
+/// The environment variable that carries the login UID down to the processes of a session, on kernels that can't
+/// track it themselves.
+pub const LOGINUID_ENV: &str = "QOS_LOGINUID";
+
+/// The environment variable that carries the session ID down to the processes of a session, on kernels that can't
+/// track it themselves.
+pub const SESSION_ID_ENV: &str = "QOS_SESSION_ID";
+
+/// The login UID (and session ID) that the kernel reports for processes that aren't part of a login.
+const UNSET_ID: u32 = u32::MAX;
+
+/// The file in the sessions directory that holds the next session ID to allocate.
+const NEXT_ID_FILE: &str = "next-id";
+
+/// A login, which every process started from it is part of, so that what they do can be traced back to the user that
+/// logged in, even once they've changed user.
+///
+/// With a kernel built with audit support, the login UID and session ID are tracked by the kernel in
+/// `/proc/<pid>/loginuid` and `/proc/<pid>/sessionid`, and can't be changed by the processes of the session. Without
+/// it, session IDs are allocated from the counter in `SESSIONS_DIRECTORY`, and passed down through the environment,
+/// so they're only as trustworthy as the user that logged in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoginSession {
+	/// The UID of the user that logged in.
+	pub loginuid: u32,
+
+	/// The ID of the session, unique since boot.
+	pub session_id: u32,
+}
+
+impl LoginSession {
+	/// Starts a new session for the user logging in, for this process and everything that it starts. This must be
+	/// called as root, before changing to the user. If this process is already part of a session that the kernel won't
+	/// let it leave, that session is returned instead.
+	pub fn begin(user: &User) -> Result<Self, AuthError> {
+		Ok(match fs::write("/proc/self/loginuid", user.uid.to_string()) {
+			Ok(()) => LoginSession {
+				loginuid: user.uid,
+				session_id: read_id(Path::new("/proc/self/sessionid"))?
+					.ok_or_else(|| io::Error::other("the kernel didn't allocate a session ID"))?,
+			},
+			Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+				// With `AUDIT_LOGINUID_IMMUTABLE`, the login UID can only be set once, by the first login.
+				from_kernel(Path::new("/proc/self")).ok_or(e)?
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {
+				fs::create_dir_all(SESSIONS_DIRECTORY)?;
+				let session = LoginSession {
+					loginuid: user.uid,
+					session_id: allocate_session_id(Path::new(SESSIONS_DIRECTORY))?,
+				};
+
+				env::set_var(LOGINUID_ENV, session.loginuid.to_string());
+				env::set_var(SESSION_ID_ENV, session.session_id.to_string());
+				session
+			}
+			Err(e) => return Err(e.into()),
+		})
+	}
+
+	/// Returns the session that this process is part of, if it's part of one.
+	pub fn current() -> Option<Self> {
+		from_kernel(Path::new("/proc/self")).or_else(|| {
+			let loginuid = env::var(LOGINUID_ENV).ok()?.parse().ok()?;
+			let session_id = env::var(SESSION_ID_ENV).ok()?.parse().ok()?;
+			Some(LoginSession { loginuid, session_id })
+		})
+	}
+
+	/// Returns the session that the process with the given PID is part of, if it's part of one.
+	pub fn of_process(pid: i32) -> Option<Self> {
+		let proc = Path::new("/proc").join(pid.to_string());
+		from_kernel(&proc).or_else(|| from_environ(&fs::read(proc.join("environ")).ok()?))
+	}
+
+	/// The environment variables that place a process in this session, for processes that are started outside of it
+	/// on its behalf.
+	pub fn environment(&self) -> [(&'static str, String); 2] {
+		[
+			(LOGINUID_ENV, self.loginuid.to_string()),
+			(SESSION_ID_ENV, self.session_id.to_string()),
+		]
+	}
+}
+
+/// Reads an ID from a file in procfs, returning None if it's unset.
+fn read_id(path: &Path) -> io::Result<Option<u32>> {
+	let id = fs::read_to_string(path)?
+		.trim()
+		.parse::<u32>()
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	Ok((id != UNSET_ID).then_some(id))
+}
+
+/// Returns the session of the process with the given procfs directory, as tracked by the kernel.
+fn from_kernel(proc: &Path) -> Option<LoginSession> {
+	let loginuid = read_id(&proc.join("loginuid")).ok()??;
+	let session_id = read_id(&proc.join("sessionid")).ok()??;
+	Some(LoginSession { loginuid, session_id })
+}
+
+/// Returns the session from the contents of a `/proc/<pid>/environ` file, if it's in one.
+fn from_environ(environ: &[u8]) -> Option<LoginSession> {
+	let (mut loginuid, mut session_id) = (None, None);
+	for variable in environ.split(|&b| b == 0) {
+		let variable = String::from_utf8_lossy(variable);
+		match variable.split_once('=') {
+			Some((LOGINUID_ENV, value)) => loginuid = value.parse().ok(),
+			Some((SESSION_ID_ENV, value)) => session_id = value.parse().ok(),
+			_ => {}
+		}
+	}
+
+	Some(LoginSession {
+		loginuid: loginuid?,
+		session_id: session_id?,
+	})
+}
+
+/// Allocates the next session ID from the counter in the given directory, locking it so that logins happening at the
+/// same time get different IDs. IDs start from 1.
+fn allocate_session_id(directory: &Path) -> io::Result<u32> {
+	let mut file = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.create(true)
+		.truncate(false)
+		.open(directory.join(NEXT_ID_FILE))?;
+	flock(file.as_raw_fd(), FlockArg::LockExclusive)?;
+
+	let mut contents = String::new();
+	file.read_to_string(&mut contents)?;
+	let id = match contents.trim() {
+		"" => 1,
+		id => id
+			.parse::<u32>()
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+	};
+
+	file.set_len(0)?;
+	file.seek(SeekFrom::Start(0))?;
+	write!(file, "{}", id + 1)?;
+
+	// The lock is released when the file is closed.
+	Ok(id)
+}
+
+#[cfg(test)]
+mod test {
+	use std::fs;
+
+	use common::testing::TempDir;
+
+	use super::{allocate_session_id, from_environ, LoginSession};
+
+	#[test]
+	fn test_allocate_session_id() {
+		let directory = TempDir::new("sessions").unwrap();
+
+		assert_eq!(allocate_session_id(&directory).unwrap(), 1);
+		assert_eq!(allocate_session_id(&directory).unwrap(), 2);
+		assert_eq!(fs::read_to_string(directory.join("next-id")).unwrap(), "3");
+	}
+
+	#[test]
+	fn test_from_environ() {
+		assert_eq!(
+			from_environ(b"HOME=/root\0QOS_LOGINUID=1000\0QOS_SESSION_ID=7\0"),
+			Some(LoginSession {
+				loginuid: 1000,
+				session_id: 7
+			})
+		);
+		assert_eq!(from_environ(b"HOME=/root\0QOS_SESSION_ID=7\0"), None);
+		assert_eq!(from_environ(b""), None);
+	}
+}
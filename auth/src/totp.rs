@@ -0,0 +1,333 @@
+use std::{
+	fs::{read_to_string, rename, File, OpenOptions},
+	io::{self, Write},
+	os::{
+		fd::AsRawFd,
+		unix::fs::{MetadataExt, OpenOptionsExt},
+	},
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use nix::fcntl::{flock, FlockArg};
+use ring::hmac;
+
+use crate::AuthError;
+
+/// The directory that holds the TOTP secrets of the users that have a second factor, one file per user named after
+/// them. It, and the files in it, must only be readable by root.
+pub const TOTP_DIR: &str = "/etc/qos/2fa";
+
+/// The number of seconds that each code is valid for.
+const TIME_STEP: u64 = 30;
+
+/// The number of digits in a code.
+const DIGITS: u32 = 6;
+
+/// The number of steps either side of the current one that codes are accepted from, to allow for clock skew between
+/// the system and the user's authenticator.
+const SKEW_STEPS: u64 = 1;
+
+/// The number of consecutive failed codes after which the user is locked out.
+const MAX_FAILURES: u32 = 5;
+
+/// How long a user is locked out for after too many failed codes.
+const LOCKOUT_DURATION: Duration = Duration::from_secs(300);
+
+/// A time-based one time password (RFC 6238) second factor for a user.
+pub struct Totp {
+	/// The shared secret of the user's authenticator.
+	secret: Vec<u8>,
+
+	/// Where the failures and last used code are recorded between logins.
+	state_path: PathBuf,
+	state: TotpState,
+
+	/// The file that is locked while the state is updated, so that logins at the same time don't lose each other's
+	/// failures.
+	lock_path: PathBuf,
+}
+
+/// What is remembered about a user's codes between logins.
+#[derive(Debug, Default, PartialEq)]
+struct TotpState {
+	/// The number of consecutive failed codes.
+	failures: u32,
+
+	/// When the last failed code was entered, in seconds since the epoch.
+	last_failure: u64,
+
+	/// The time step of the last accepted code, so that a code can't be used twice.
+	last_step: u64,
+}
+
+impl TotpState {
+	/// Parses the state file, which has the failures, last failure and last step separated by spaces.
+	fn parse(state: &str) -> Option<Self> {
+		let mut parts = state.split_whitespace();
+		Some(Self {
+			failures: parts.next()?.parse().ok()?,
+			last_failure: parts.next()?.parse().ok()?,
+			last_step: parts.next()?.parse().ok()?,
+		})
+	}
+
+	/// Reads the state file, starting afresh if there isn't one (or it can't be parsed).
+	fn read(path: &Path) -> io::Result<Self> {
+		match read_to_string(path) {
+			Ok(state) => Ok(Self::parse(&state).unwrap_or_default()),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+			Err(e) => Err(e),
+		}
+	}
+}
+
+impl Totp {
+	/// Returns the second factor of the given user, or `None` if they haven't opted in to one.
+	pub fn for_user(username: &str) -> Result<Option<Self>, AuthError> {
+		Self::from_dir(Path::new(TOTP_DIR), username, 0)
+	}
+
+	/// Returns the second factor of the given user from the secrets in the given directory, which have to be owned by
+	/// the given user ID.
+	fn from_dir(dir: &Path, username: &str, owner: u32) -> Result<Option<Self>, AuthError> {
+		let path = dir.join(username);
+		let metadata = match path.metadata() {
+			Ok(metadata) => metadata,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(e.into()),
+		};
+
+		// Anyone that can read the secret can generate codes, so refuse to use one that isn't kept private.
+		if metadata.uid() != owner || metadata.mode() & 0o077 != 0 {
+			return Err(AuthError::InsecureSecret(path));
+		}
+
+		let secret = decode_base32(read_to_string(&path)?.trim())
+			.ok_or_else(|| AuthError::Malformed(format!("invalid TOTP secret in {}", path.display())))?;
+
+		let state_path = dir.join(format!("{}.state", username));
+		let state = TotpState::read(&state_path)?;
+
+		Ok(Some(Self {
+			secret,
+			state_path,
+			state,
+			lock_path: dir.join(format!("{}.lock", username)),
+		}))
+	}
+
+	/// Returns how much longer the user is locked out for, if they've entered too many bad codes.
+	pub fn locked_out(&self, now: SystemTime) -> Option<Duration> {
+		if self.state.failures < MAX_FAILURES {
+			return None;
+		}
+
+		let unlocked = UNIX_EPOCH + Duration::from_secs(self.state.last_failure) + LOCKOUT_DURATION;
+		unlocked.duration_since(now).ok()
+	}
+
+	/// Verifies a code that the user entered, recording the result so that repeated failures lock the user out, and
+	/// accepted codes can't be used again. Returns `AuthError::LockedOut` without checking the code if the user is
+	/// locked out.
+	pub fn verify(&mut self, code: &str, now: SystemTime) -> Result<bool, AuthError> {
+		if let Some(remaining) = self.locked_out(now) {
+			return Err(AuthError::LockedOut(remaining.as_secs() + 1));
+		}
+
+		// Another login may have recorded a failure (or used a code) since the state was read, so read it again under
+		// the lock. The lock is released when the file is closed.
+		let _lock = self.lock_state()?;
+		self.state = TotpState::read(&self.state_path)?;
+		if let Some(remaining) = self.locked_out(now) {
+			return Err(AuthError::LockedOut(remaining.as_secs() + 1));
+		}
+
+		let seconds = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		let step = seconds / TIME_STEP;
+		let matched = (step.saturating_sub(SKEW_STEPS)..=step + SKEW_STEPS)
+			.filter(|candidate| *candidate > self.state.last_step)
+			.find(|candidate| format!("{:0width$}", hotp(&self.secret, *candidate), width = DIGITS as usize) == code);
+
+		match matched {
+			Some(step) => {
+				self.state = TotpState {
+					failures: 0,
+					last_failure: 0,
+					last_step: step,
+				};
+			}
+			None => {
+				// Failures from before a lockout expired start the count again.
+				if self.state.failures >= MAX_FAILURES {
+					self.state.failures = 0;
+				}
+
+				self.state.failures += 1;
+				self.state.last_failure = seconds;
+			}
+		}
+
+		self.write_state()?;
+		Ok(matched.is_some())
+	}
+
+	/// Takes the lock on the state, waiting for any other login that holds it.
+	fn lock_state(&self) -> io::Result<File> {
+		let file = OpenOptions::new()
+			.write(true)
+			.create(true)
+			.truncate(false)
+			.mode(0o600)
+			.open(&self.lock_path)?;
+		flock(file.as_raw_fd(), FlockArg::LockExclusive)?;
+		Ok(file)
+	}
+
+	/// Writes the state to a temporary file and renames it over the old one, so that a crash part way through can't
+	/// leave the state empty and reset the failures. The lock on the state has to be held.
+	fn write_state(&self) -> Result<(), AuthError> {
+		let temporary_path = self.state_path.with_extension("state.tmp");
+		let mut file = OpenOptions::new()
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.mode(0o600)
+			.open(&temporary_path)?;
+
+		writeln!(
+			file,
+			"{} {} {}",
+			self.state.failures, self.state.last_failure, self.state.last_step
+		)?;
+		file.sync_all()?;
+		rename(&temporary_path, &self.state_path)?;
+		Ok(())
+	}
+}
+
+/// Computes the HOTP (RFC 4226) code for the given counter, before it's truncated to a number of digits.
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+	let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+	let tag = hmac::sign(&key, &counter.to_be_bytes());
+	let digest = tag.as_ref();
+
+	let offset = (digest[digest.len() - 1] & 0xF) as usize;
+	let code = u32::from_be_bytes([
+		digest[offset],
+		digest[offset + 1],
+		digest[offset + 2],
+		digest[offset + 3],
+	]);
+	(code & 0x7FFF_FFFF) % 10u32.pow(DIGITS)
+}
+
+/// Decodes an RFC 4648 base32 string, as authenticator apps show secrets. Padding, spaces, and case are ignored.
+fn decode_base32(encoded: &str) -> Option<Vec<u8>> {
+	let mut decoded = Vec::new();
+	let mut buffer: u32 = 0;
+	let mut bits = 0;
+	for c in encoded.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+		let value = match c.to_ascii_uppercase() {
+			c @ 'A'..='Z' => c as u32 - 'A' as u32,
+			c @ '2'..='7' => c as u32 - '2' as u32 + 26,
+			_ => return None,
+		};
+
+		buffer = (buffer << 5) | value;
+		bits += 5;
+		if bits >= 8 {
+			bits -= 8;
+			decoded.push((buffer >> bits) as u8);
+			buffer &= (1 << bits) - 1;
+		}
+	}
+
+	if decoded.is_empty() {
+		return None;
+	}
+
+	Some(decoded)
+}
+
+#[cfg(test)]
+mod test {
+	use std::{
+		fs::{set_permissions, write, Permissions},
+		os::unix::fs::PermissionsExt,
+		time::{Duration, UNIX_EPOCH},
+	};
+
+	use common::testing::TempDir;
+	use nix::unistd::getuid;
+
+	use super::{decode_base32, hotp, Totp, MAX_FAILURES};
+	use crate::AuthError;
+
+	/// The secret used by the test vectors in RFC 6238.
+	const SECRET: &[u8] = b"12345678901234567890";
+
+	#[test]
+	fn test_hotp() {
+		// The SHA-1 test vectors from RFC 6238, truncated to six digits.
+		assert_eq!(hotp(SECRET, 59 / 30), 287082);
+		assert_eq!(hotp(SECRET, 1111111109 / 30), 81804);
+		assert_eq!(hotp(SECRET, 1234567890 / 30), 5924);
+	}
+
+	#[test]
+	fn test_decode_base32() {
+		assert_eq!(decode_base32("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap(), SECRET);
+		assert_eq!(decode_base32("mzxw6 ytb oi======").unwrap(), b"foobar");
+		assert!(decode_base32("not base32!").is_none());
+		assert!(decode_base32("").is_none());
+	}
+
+	#[test]
+	fn test_verify() {
+		let dir = TempDir::new("auth-totp").unwrap();
+		write(dir.join("colin"), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\n").unwrap();
+		set_permissions(dir.join("colin"), Permissions::from_mode(0o600)).unwrap();
+		let owner = getuid().as_raw();
+
+		assert!(Totp::from_dir(&dir, "nobody", owner).unwrap().is_none());
+
+		let now = UNIX_EPOCH + Duration::from_secs(1111111109);
+		let mut totp = Totp::from_dir(&dir, "colin", owner).unwrap().unwrap();
+
+		// Codes from a step either side are accepted, but not from further away, and not twice.
+		assert!(!totp.verify("081804", now + Duration::from_secs(90)).unwrap());
+		assert!(totp.verify("081804", now + Duration::from_secs(30)).unwrap());
+		assert!(!totp.verify("081804", now).unwrap());
+
+		// Failures are remembered between logins, and lock the user out.
+		for _ in 1..MAX_FAILURES {
+			assert!(!totp.verify("000000", now).unwrap());
+		}
+
+		let mut totp = Totp::from_dir(&dir, "colin", owner).unwrap().unwrap();
+		assert!(totp.locked_out(now).is_some());
+		assert!(matches!(totp.verify("081804", now), Err(AuthError::LockedOut(_))));
+		assert!(totp.locked_out(now + Duration::from_secs(3600)).is_none());
+
+		// Two logins at once don't lose each other's failures, and the state is replaced rather than rewritten.
+		let mut first = Totp::from_dir(&dir, "colin", owner).unwrap().unwrap();
+		let mut second = Totp::from_dir(&dir, "colin", owner).unwrap().unwrap();
+		let later = now + Duration::from_secs(3600);
+		assert!(!first.verify("000000", later).unwrap());
+		assert!(!second.verify("000000", later).unwrap());
+		assert_eq!(Totp::from_dir(&dir, "colin", owner).unwrap().unwrap().state.failures, 2);
+		assert!(!dir.join("colin.state.tmp").exists());
+
+		assert!(matches!(
+			Totp::from_dir(&dir, "colin", owner + 1),
+			Err(AuthError::InsecureSecret(_))
+		));
+
+		set_permissions(dir.join("colin"), Permissions::from_mode(0o644)).unwrap();
+		assert!(matches!(
+			Totp::from_dir(&dir, "colin", owner),
+			Err(AuthError::InsecureSecret(_))
+		));
+	}
+}
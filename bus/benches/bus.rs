@@ -0,0 +1,121 @@
+//! Benchmarks of the framing that every message on the bus goes through, to catch regressions in the hot path of
+//! publishing and subscribing.
+
+use std::io::Cursor;
+
+use bus::{read_message, read_message_into, write_message, Framing};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::{
+	io::{duplex, sink, BufReader},
+	runtime::Runtime,
+};
+
+/// The sizes of the messages that are benchmarked, from a small udev event up to one that takes several chunks.
+const MESSAGE_SIZES: [usize; 3] = [256, 4096, 256 * 1024];
+
+/// The number of messages sent in each iteration.
+const MESSAGES: usize = 64;
+
+fn runtime() -> Runtime {
+	tokio::runtime::Builder::new_current_thread().build().unwrap()
+}
+
+/// Encodes `MESSAGES` messages of the given size, as a subscriber would receive them.
+fn encoded_messages(size: usize) -> Vec<u8> {
+	let runtime = runtime();
+	let message = vec![0xAB; size];
+	let mut encoded = Vec::new();
+	for _ in 0..MESSAGES {
+		runtime
+			.block_on(write_message(&mut encoded, &message, Framing::Chunked))
+			.unwrap();
+	}
+
+	encoded
+}
+
+/// Publishes messages into a sink, to measure the cost of framing them on its own.
+fn bench_publish(c: &mut Criterion) {
+	let runtime = runtime();
+	let mut group = c.benchmark_group("publish");
+	for size in MESSAGE_SIZES {
+		let message = vec![0xAB; size];
+		group.throughput(Throughput::Bytes((size * MESSAGES) as u64));
+		group.bench_with_input(BenchmarkId::from_parameter(size), &message, |b, message| {
+			b.to_async(&runtime).iter(|| async {
+				let mut writer = sink();
+				for _ in 0..MESSAGES {
+					write_message(&mut writer, message, Framing::Chunked).await.unwrap();
+				}
+			})
+		});
+	}
+}
+
+/// Reads messages that have already been received, both into new buffers and into one that's reused.
+fn bench_subscribe(c: &mut Criterion) {
+	let runtime = runtime();
+	let mut group = c.benchmark_group("subscribe");
+	for size in MESSAGE_SIZES {
+		let encoded = encoded_messages(size);
+		group.throughput(Throughput::Bytes((size * MESSAGES) as u64));
+
+		group.bench_with_input(BenchmarkId::new("read_message", size), &encoded, |b, encoded| {
+			b.to_async(&runtime).iter(|| async {
+				let mut reader = Cursor::new(encoded.as_slice());
+				for _ in 0..MESSAGES {
+					read_message(&mut reader, Framing::Chunked).await.unwrap();
+				}
+			})
+		});
+
+		group.bench_with_input(BenchmarkId::new("read_message_into", size), &encoded, |b, encoded| {
+			b.to_async(&runtime).iter(|| async {
+				let mut reader = Cursor::new(encoded.as_slice());
+				let mut buf = Vec::new();
+				for _ in 0..MESSAGES {
+					read_message_into(&mut reader, Framing::Chunked, &mut buf)
+						.await
+						.unwrap();
+				}
+			})
+		});
+	}
+}
+
+/// Publishes messages through a pipe to a subscriber reading them at the same time, like busd forwarding them.
+fn bench_round_trip(c: &mut Criterion) {
+	let runtime = tokio::runtime::Builder::new_multi_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+	let mut group = c.benchmark_group("round_trip");
+	for size in MESSAGE_SIZES {
+		let message = vec![0xAB; size];
+		group.throughput(Throughput::Bytes((size * MESSAGES) as u64));
+		group.bench_with_input(BenchmarkId::from_parameter(size), &message, |b, message| {
+			b.to_async(&runtime).iter(|| async {
+				let (mut publisher, subscriber) = duplex(64 * 1024);
+				let message = message.clone();
+				let publish = tokio::spawn(async move {
+					for _ in 0..MESSAGES {
+						write_message(&mut publisher, &message, Framing::Chunked).await.unwrap();
+					}
+				});
+
+				let mut reader = BufReader::new(subscriber);
+				let mut buf = Vec::new();
+				for _ in 0..MESSAGES {
+					read_message_into(&mut reader, Framing::Chunked, &mut buf)
+						.await
+						.unwrap();
+				}
+
+				publish.await.unwrap();
+			})
+		});
+	}
+}
+
+criterion_group!(benches, bench_publish, bench_subscribe, bench_round_trip);
+criterion_main!(benches);
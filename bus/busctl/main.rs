@@ -1,6 +1,8 @@
-use bus::{BusClient, DEFAULT_BUSD_SOCKET};
-use clap::{Arg, Command};
-use tokio::io::{self, AsyncBufReadExt, BufReader};
+use std::time::{Duration, UNIX_EPOCH};
+
+use bus::{BusClient, TraceEvent, DEFAULT_BUSD_SOCKET, DEFAULT_CALL_TIMEOUT, MAX_TRACE_RATE};
+use clap::{value_parser, Arg, ArgAction, Command};
+use tokio::io::{self, AsyncBufReadExt, AsyncReadExt, BufReader};
 
 #[tokio::main]
 async fn main() {
@@ -18,8 +20,46 @@ async fn main() {
 			Arg::new("topic")
 				.long("topic")
 				.num_args(1)
-				.required(true)
-				.help("The topic to talk to"),
+				.help("The topic to talk to. Subscriptions can use wildcards, e.g. udev/+/sda or udev/#"),
+		)
+		.arg(
+			Arg::new("timeout")
+				.long("timeout")
+				.num_args(1)
+				.value_parser(value_parser!(u64))
+				.help("The number of milliseconds to wait for a reply to a call"),
+		)
+		.arg(
+			Arg::new("retain")
+				.long("retain")
+				.num_args(1)
+				.value_parser(value_parser!(usize))
+				.help("When publishing, the number of messages that busd should retain for late subscribers"),
+		)
+		.arg(Arg::new("will").long("will").num_args(1).help(
+			"When publishing, a message that busd should publish if busctl exits without reaching the end of stdin",
+		))
+		.arg(
+			Arg::new("replay")
+				.long("replay")
+				.action(ArgAction::SetTrue)
+				.help("When subscribing, receive the messages retained on the topic first"),
+		)
+		.arg(Arg::new("filter").long("filter").num_args(1).help(
+			"When subscribing, only receive the messages that match the filter, e.g. 'SUBSYSTEM=block ACTION!=remove'",
+		))
+		.arg(
+			Arg::new("rate")
+				.long("rate")
+				.num_args(1)
+				.value_parser(value_parser!(u32).range(1..=MAX_TRACE_RATE as i64))
+				.help("When tracing, the maximum number of messages a second to receive. Messages over the rate are dropped"),
+		)
+		.arg(
+			Arg::new("stream")
+				.long("stream")
+				.action(ArgAction::SetTrue)
+				.help("When publishing, send the whole of stdin as a single message, rather than a message per line"),
 		)
 		.arg(
 			Arg::new("action")
@@ -30,14 +70,39 @@ async fn main() {
 		.get_matches();
 
 	let socket_path: &String = app.get_one("socket").unwrap();
-	let topic: &String = app.get_one("topic").unwrap();
 	let action: &String = app.get_one("action").unwrap();
+	let timeout = app
+		.get_one::<u64>("timeout")
+		.map_or(DEFAULT_CALL_TIMEOUT, |t| Duration::from_millis(*t));
 
 	let client = BusClient::new_from_path(socket_path).await.unwrap();
 
+	if action == "topics" {
+		show_topics(client).await;
+		return;
+	}
+
+	let topic: &String = match app.get_one("topic") {
+		Some(topic) => topic,
+		None => {
+			eprintln!("--topic is required for {}", action);
+			return;
+		}
+	};
+
 	match action.as_str() {
 		"subscribe" => {
-			let mut reader = client.subscribe(topic).await.unwrap();
+			let filter = app.get_one::<String>("filter").map(String::as_str);
+			let reader = client.subscribe_filtered(topic, app.get_flag("replay"), filter).await;
+
+			let mut reader = match reader {
+				Ok(reader) => reader,
+				Err(e) => {
+					eprintln!("Failed to subscribe: {}", e);
+					return;
+				}
+			};
+
 			while let Ok(msg) = reader.read_message().await {
 				let msg = match String::from_utf8(msg) {
 					Ok(msg) => msg,
@@ -51,15 +116,64 @@ async fn main() {
 			}
 		}
 		"publish" => {
-			let mut writer = client.publish(topic).await.unwrap();
-			let mut reader = BufReader::new(io::stdin());
+			let retain = app.get_one::<usize>("retain").copied().unwrap_or(0);
+			let writer = match app.get_one::<String>("will") {
+				Some(will) => client.publish_with_will(topic, retain, will.as_bytes()).await,
+				None => client.publish_retained(topic, retain).await,
+			};
+
+			let mut writer = match writer {
+				Ok(writer) => writer,
+				Err(e) => {
+					eprintln!("Failed to publish: {}", e);
+					return;
+				}
+			};
 
-			let mut line = String::new();
-			while reader.read_line(&mut line).await.unwrap() > 0 {
-				if let Err(e) = writer.publish_message(line.as_bytes()).await {
-					println!("Failed to publish message: {}", e);
+			if app.get_flag("stream") {
+				if let Err(e) = writer.publish_stream(&mut io::stdin()).await {
+					eprintln!("Failed to publish message: {}", e);
+					return;
 				}
-				line.clear();
+			} else {
+				let mut reader = BufReader::new(io::stdin());
+
+				let mut line = String::new();
+				while reader.read_line(&mut line).await.unwrap() > 0 {
+					if let Err(e) = writer.publish_message(line.as_bytes()).await {
+						println!("Failed to publish message: {}", e);
+					}
+					line.clear();
+				}
+			}
+
+			if let Err(e) = writer.disconnect().await {
+				eprintln!("Failed to disconnect: {}", e);
+			}
+		}
+		"trace" => {
+			let mut tracer = match client.trace(topic, app.get_one::<u32>("rate").copied()).await {
+				Ok(tracer) => tracer,
+				Err(e) => {
+					eprintln!("Failed to trace: {}", e);
+					return;
+				}
+			};
+
+			while let Ok((event, message)) = tracer.read_event().await {
+				print_trace_event(&event, &message);
+			}
+		}
+		"call" => {
+			let mut payload = Vec::new();
+			if let Err(e) = io::stdin().read_to_end(&mut payload).await {
+				eprintln!("Failed to read payload: {}", e);
+				return;
+			}
+
+			match client.call(topic, &payload, timeout).await {
+				Ok(reply) => println!("{}", String::from_utf8_lossy(&reply).trim()),
+				Err(e) => eprintln!("Call failed: {}", e),
 			}
 		}
 		_ => {
@@ -67,3 +181,49 @@ async fn main() {
 		}
 	}
 }
+
+/// Prints a traced message, prefixed with its metadata, noting any messages that were dropped before it.
+fn print_trace_event(event: &TraceEvent, message: &[u8]) {
+	if event.dropped > 0 {
+		println!("... {} messages dropped", event.dropped);
+	}
+
+	let timestamp = event.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+	let pid = event.pid.map_or_else(|| String::from("?"), |pid| pid.to_string());
+	println!(
+		"#{} {}.{:06} {} pid={} uid={} len={}: {}",
+		event.sequence,
+		timestamp.as_secs(),
+		timestamp.subsec_micros(),
+		event.topic,
+		pid,
+		event.uid,
+		message.len(),
+		String::from_utf8_lossy(message).trim()
+	);
+}
+
+/// Prints a table of the active topics on the bus.
+async fn show_topics(client: BusClient) {
+	let topics = match client.topics().await {
+		Ok(topics) => topics,
+		Err(e) => {
+			eprintln!("Failed to list topics: {}", e);
+			return;
+		}
+	};
+
+	let mut table = tables::Table::new_with_headers(["Topic", "Subscribers", "Publishers"])
+		.with_setting(tables::TableSetting::ColumnSeperators)
+		.with_setting(tables::TableSetting::HeaderSeperator);
+
+	for topic in topics {
+		table.add_row([
+			&topic.name,
+			&topic.subscribers.to_string(),
+			&topic.publishers.to_string(),
+		]);
+	}
+
+	print!("{}", table);
+}
@@ -1,21 +1,42 @@
-use std::{collections::HashMap, io::ErrorKind, sync::Arc};
+use std::{
+	cell::OnceCell,
+	collections::{HashMap, VecDeque},
+	io::ErrorKind,
+	str::FromStr,
+	sync::Arc,
+	time::{Duration, Instant, SystemTime},
+};
 
-use bus::{PUBLISH_ACTION, SUBSCRIBE_ACTION};
-use control::listen::Action;
-use slog::{info, o};
+use bus::{
+	filter::{parse_payload, MessageFilter},
+	is_valid_filter, is_wildcard, read_message, read_publication_into, topic_matches, write_message, BusRequest,
+	CallStatus, Framing, TopicInfo, TraceEvent, CALL_ACTION, DEFAULT_CALL_TIMEOUT, DEFAULT_TRACE_RATE,
+	MAX_RETAINED_MESSAGES, MAX_TRACE_RATE, MAX_WILL_LENGTH, MULTI_LEVEL_WILDCARD, PROTOCOL_ARG, PUBLISH_ACTION,
+	SERVE_ACTION, SINGLE_LEVEL_WILDCARD, SUBSCRIBE_ACTION, TOPICS_ACTION, TOPIC_SEPARATOR, TRACE_ACTION,
+};
+use control::{
+	listen::{Action, Peer, RequestContext},
+	protocol::{self, ErrorReply},
+};
+use slog::{debug, info, o, warn, Logger};
 use std::fmt;
 use tokio::{
-	io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
-	net::unix::UCred,
-	sync::{mpsc, Mutex},
+	io::{self, AsyncBufRead, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+	sync::{mpsc, oneshot, Mutex},
 };
 
 use thiserror::Error;
 
+use crate::policy::{Access, Policy};
+
 /// The type of action to perform.
 pub enum BusActionType {
 	Subscribe,
 	Publish,
+	Serve,
+	Call,
+	Topics,
+	Trace,
 }
 
 impl fmt::Display for BusActionType {
@@ -23,6 +44,10 @@ impl fmt::Display for BusActionType {
 		match self {
 			BusActionType::Subscribe => write!(f, "{}", SUBSCRIBE_ACTION),
 			BusActionType::Publish => write!(f, "{}", PUBLISH_ACTION),
+			BusActionType::Serve => write!(f, "{}", SERVE_ACTION),
+			BusActionType::Call => write!(f, "{}", CALL_ACTION),
+			BusActionType::Topics => write!(f, "{}", TOPICS_ACTION),
+			BusActionType::Trace => write!(f, "{}", TRACE_ACTION),
 		}
 	}
 }
@@ -33,6 +58,10 @@ impl TryFrom<&str> for BusActionType {
 		match value {
 			SUBSCRIBE_ACTION => Ok(Self::Subscribe),
 			PUBLISH_ACTION => Ok(Self::Publish),
+			SERVE_ACTION => Ok(Self::Serve),
+			CALL_ACTION => Ok(Self::Call),
+			TOPICS_ACTION => Ok(Self::Topics),
+			TRACE_ACTION => Ok(Self::Trace),
 			_ => Err(BusError::UnknownAction(value.to_string())),
 		}
 	}
@@ -41,53 +70,270 @@ impl TryFrom<&str> for BusActionType {
 /// An action to perform on the bus.
 pub struct BusAction {
 	pub api: Arc<Mutex<BusAPI>>,
+
+	/// The policy that decides whether the peer is allowed to perform the action.
+	pub policy: Arc<Policy>,
+
+	/// The topic to act on. For subscribes, this is a filter that may contain wildcards.
 	pub topic: String,
 	pub action: BusActionType,
+
+	/// How long to wait for a reply to a call.
+	pub timeout: Duration,
+
+	/// For publishes, how many of the latest messages on the topic to retain for late subscribers. If zero, the
+	/// published messages aren't retained.
+	pub retain: usize,
+
+	/// For publishes, the message to publish to the topic if the publisher goes away without disconnecting cleanly.
+	pub will: Option<Vec<u8>>,
+
+	/// For subscribes, whether to replay the retained messages on the topic before any new ones.
+	pub replay: bool,
+
+	/// For subscribes, the filter that messages have to match to be delivered.
+	pub filter: Option<MessageFilter>,
+
+	/// For traces, the maximum number of messages to send a second.
+	pub rate: u32,
+
+	/// How messages are framed on the connection. Clients that don't ask for a protocol get the legacy framing.
+	pub framing: Framing,
 }
 
 impl BusAction {
-	pub fn try_new(api: Arc<Mutex<BusAPI>>, action: BusActionType, args: &[(&str, &str)]) -> Result<Self, BusError> {
-		let topic = args
-			.iter()
-			.find(|(k, _)| k == &"topic")
-			.ok_or(BusError::MissingArgument("topic"))?
-			.1;
+	/// Builds an action from a typed request, validating its arguments.
+	pub fn new(
+		api: Arc<Mutex<BusAPI>>,
+		policy: Arc<Policy>,
+		request: BusRequest,
+		framing: Framing,
+	) -> Result<Self, BusError> {
+		let rate = match &request {
+			BusRequest::Trace { rate, .. } => rate.unwrap_or(DEFAULT_TRACE_RATE),
+			_ => DEFAULT_TRACE_RATE,
+		};
+
+		let filter = match &request {
+			BusRequest::Subscribe {
+				filter: Some(filter), ..
+			} => match MessageFilter::parse(filter) {
+				Some(filter) => Some(filter),
+				None => return Err(BusError::InvalidArgument("filter", filter.clone())),
+			},
+			_ => None,
+		};
+
+		let (action, topic, timeout, retain, replay, will) = match request {
+			BusRequest::Subscribe { topic, replay, .. } => (BusActionType::Subscribe, topic, None, 0, replay, None),
+			BusRequest::Publish { topic, retain, will } => (BusActionType::Publish, topic, None, retain, false, will),
+			BusRequest::Serve { topic } => (BusActionType::Serve, topic, None, 0, false, None),
+			BusRequest::Call { topic, timeout_ms } => (BusActionType::Call, topic, timeout_ms, 0, false, None),
+			BusRequest::Topics => (BusActionType::Topics, String::new(), None, 0, false, None),
+			BusRequest::Trace { topic, .. } => (BusActionType::Trace, topic, None, 0, false, None),
+		};
+
+		let valid_topic = match action {
+			BusActionType::Topics => true,
+			BusActionType::Subscribe | BusActionType::Trace => is_valid_filter(&topic),
+			_ => is_valid_filter(&topic) && !is_wildcard(&topic),
+		};
+
+		if !valid_topic {
+			return Err(BusError::InvalidArgument("topic", topic));
+		}
+
+		if retain > MAX_RETAINED_MESSAGES {
+			return Err(BusError::InvalidArgument("retain", retain.to_string()));
+		}
+
+		if rate == 0 || rate > MAX_TRACE_RATE {
+			return Err(BusError::InvalidArgument("rate", rate.to_string()));
+		}
+
+		// Traces send each message after its metadata, which the legacy framing has no room for on large messages.
+		if matches!(action, BusActionType::Trace) && framing == Framing::Legacy {
+			return Err(BusError::MissingArgument(PROTOCOL_ARG));
+		}
+
+		if let Some(will) = &will {
+			if will.len() > MAX_WILL_LENGTH {
+				return Err(BusError::InvalidArgument("will", format!("{} bytes", will.len())));
+			}
+
+			// The legacy framing has no way to disconnect cleanly, so the will would always be published.
+			if framing == Framing::Legacy {
+				return Err(BusError::MissingArgument(PROTOCOL_ARG));
+			}
+		}
+
 		Ok(Self {
 			api,
-			topic: topic.to_string(),
+			policy,
+			topic,
 			action,
+			timeout: timeout.map(Duration::from_millis).unwrap_or(DEFAULT_CALL_TIMEOUT),
+			retain,
+			will,
+			replay,
+			filter,
+			rate,
+			framing,
 		})
 	}
+
+	/// Builds an action from the arguments of a text or binary header, as sent by older clients.
+	pub fn from_args(
+		api: Arc<Mutex<BusAPI>>,
+		policy: Arc<Policy>,
+		action: BusActionType,
+		args: &[(&str, &str)],
+	) -> Result<Self, BusError> {
+		let topic = || match find_arg(args, "topic") {
+			Some(topic) => Ok(topic.to_owned()),
+			None => Err(BusError::MissingArgument("topic")),
+		};
+
+		let request = match action {
+			BusActionType::Subscribe => BusRequest::Subscribe {
+				topic: topic()?,
+				replay: parse_arg(args, "replay")?.unwrap_or(false),
+				filter: find_arg(args, "filter").map(str::to_owned),
+			},
+			BusActionType::Publish => BusRequest::Publish {
+				topic: topic()?,
+				retain: parse_arg(args, "retain")?.unwrap_or(0),
+				will: find_arg(args, "will").map(|will| will.as_bytes().to_vec()),
+			},
+			BusActionType::Serve => BusRequest::Serve { topic: topic()? },
+			BusActionType::Call => BusRequest::Call {
+				topic: topic()?,
+				timeout_ms: parse_arg(args, "timeout")?,
+			},
+			BusActionType::Topics => BusRequest::Topics,
+			BusActionType::Trace => BusRequest::Trace {
+				topic: topic()?,
+				rate: parse_arg(args, "rate")?,
+			},
+		};
+
+		let protocol = find_arg(args, PROTOCOL_ARG);
+		let framing = match Framing::from_protocol(protocol) {
+			Some(framing) => framing,
+			None => {
+				return Err(BusError::InvalidArgument(
+					PROTOCOL_ARG,
+					protocol.unwrap_or("").to_string(),
+				))
+			}
+		};
+
+		Self::new(api, policy, request, framing)
+	}
+
+	/// Reads messages from the publisher, publishing them to the topic until the publisher goes away. Returns whether
+	/// the publisher disconnected cleanly.
+	async fn publish_messages<R: AsyncBufRead + Unpin>(&self, reader: R, publisher: &Peer) -> Result<bool, BusError> {
+		let mut reader = reader;
+		let mut buffer = Vec::new();
+		loop {
+			match read_publication_into(&mut reader, self.framing, &mut buffer).await {
+				Ok(true) => {}
+				Ok(false) => return Ok(true),
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+				Err(e) => return Err(e.into()),
+			};
+
+			self.api
+				.lock()
+				.await
+				.publish(&self.topic, &buffer, self.retain > 0, publisher);
+		}
+	}
+
+	/// Writes a message to a subscriber, dropping it if it's too large for the framing the subscriber speaks.
+	async fn forward<W: AsyncWrite + Unpin>(&self, logger: &Logger, writer: &mut W, message: &[u8]) -> io::Result<()> {
+		if message.len() > self.framing.max_message_length() {
+			warn!(logger, "Dropping message that is too large for subscriber"; "topic" => &self.topic, "length" => message.len());
+			return Ok(());
+		}
+
+		write_message(writer, message, self.framing).await
+	}
+
+	/// Routes the payload to the service on the topic, waiting for its reply.
+	async fn call(&self, payload: Vec<u8>) -> Result<Vec<u8>, CallStatus> {
+		let (id, service) = self
+			.api
+			.lock()
+			.await
+			.get_service(&self.topic)
+			.ok_or(CallStatus::NoService)?;
+
+		let (reply, reply_rx) = oneshot::channel();
+		service
+			.send(PendingCall { id, payload, reply })
+			.await
+			.map_err(|_| CallStatus::NoService)?;
+
+		match tokio::time::timeout(self.timeout, reply_rx).await {
+			Ok(Ok(reply)) => Ok(reply),
+			Ok(Err(_)) => Err(CallStatus::ServiceGone),
+			Err(_) => Err(CallStatus::Timeout),
+		}
+	}
 }
 
 impl Action for BusAction {
 	type Error = BusError;
+
+	async fn authorize(&self, ctx: &RequestContext) -> Result<(), Self::Error> {
+		let peer = &ctx.peer;
+		// Traces show who published each message as well as the message, so need both.
+		let accesses: &[Access] = match self.action {
+			BusActionType::Publish | BusActionType::Serve => &[Access::Publish],
+			BusActionType::Subscribe | BusActionType::Call => &[Access::Subscribe],
+			BusActionType::Trace => &[Access::Subscribe, Access::Trace],
+			BusActionType::Topics => &[],
+		};
+
+		for access in accesses {
+			if !self.policy.allows(peer.uid(), peer.gid(), &self.topic, *access) {
+				warn!(ctx.logger, "Denied access to topic"; "topic" => &self.topic, "action" => self.action.to_string(), "uid" => peer.uid(), "gid" => peer.gid());
+				return Err(BusError::PermissionDenied(self.action.to_string(), self.topic.clone()));
+			}
+		}
+
+		Ok(())
+	}
+
 	async fn run<
 		R: tokio::io::AsyncBufRead + Unpin + Send + 'static,
 		W: tokio::io::AsyncWrite + Unpin + Send + 'static,
 	>(
 		self,
-		_peer: UCred,
+		ctx: RequestContext,
 		reader: R,
 		writer: W,
 	) -> Result<(), Self::Error> {
+		debug!(ctx.logger, "Running action"; "action" => self.action.to_string(), "topic" => &self.topic, "pid" => ctx.peer.pid());
 		match self.action {
 			BusActionType::Subscribe => {
-				self.api.lock().await.create_topic(&self.topic);
-				let rx = self
+				let (mut rx, retained) = self
 					.api
 					.lock()
 					.await
-					.subscribe(&self.topic)
-					.ok_or(BusError::TopicNotFound)?;
+					.subscribe(&self.topic, self.replay, self.filter.clone());
 
 				let mut writer = BufWriter::new(writer);
-				let mut rx = rx;
+				for message in retained {
+					if self.forward(&ctx.logger, &mut writer, &message).await.is_err() {
+						return Ok(());
+					}
+				}
+
 				while let Some(message) = rx.recv().await {
-					let len = message.len() as u16;
-					writer.write_u16(len).await?;
-					writer.write_all(&message).await?;
-					if writer.flush().await.is_err() {
+					if self.forward(&ctx.logger, &mut writer, &message).await.is_err() {
 						return Ok(());
 					}
 				}
@@ -95,77 +341,363 @@ impl Action for BusAction {
 				Ok(())
 			}
 			BusActionType::Publish => {
-				self.api.lock().await.create_topic(&self.topic);
-				let mut reader = BufReader::new(reader);
-				loop {
-					let len = match reader.read_u16().await {
-						Ok(len) => len as usize,
-						Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
-						Err(e) => return Err(e.into()),
-					};
+				self.api.lock().await.add_publisher(&self.topic, self.retain);
+				let result = self.publish_messages(BufReader::new(reader), &ctx.peer).await;
 
-					let mut buffer = vec![0; len];
-					match reader.read_exact(&mut buffer).await {
-						Ok(_) => {}
-						Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
-						Err(e) => return Err(e.into()),
-					};
+				let mut api = self.api.lock().await;
+				if let (Some(will), false) = (&self.will, matches!(result, Ok(true))) {
+					info!(ctx.logger, "Publishing last will"; "topic" => &self.topic, "pid" => ctx.peer.pid());
+					api.publish(&self.topic, will, self.retain > 0, &ctx.peer);
+				}
+
+				api.remove_publisher(&self.topic);
+				result.map(|_| ())
+			}
+			BusActionType::Serve => {
+				let mut calls = self.api.lock().await.register_service(&self.topic)?;
+
+				// Replies are read in their own task, because reading them isn't cancel safe.
+				let (reply_tx, mut replies) = mpsc::channel(100);
+				tokio::spawn(read_replies(reader, self.framing, reply_tx));
+
+				let mut writer = BufWriter::new(writer);
+				let mut in_flight: HashMap<u64, oneshot::Sender<Vec<u8>>> = HashMap::new();
+				let result = loop {
+					tokio::select! {
+						call = calls.recv() => {
+							let call = match call {
+								Some(call) => call,
+								None => break Ok(()),
+							};
+
+							// Forget about any calls whose callers have given up waiting.
+							in_flight.retain(|_, reply| !reply.is_closed());
+
+							if let Err(e) = writer.write_u64(call.id).await {
+								break Err(e.into());
+							}
+
+							if let Err(e) = write_message(&mut writer, &call.payload, self.framing).await {
+								break Err(e.into());
+							}
+
+							in_flight.insert(call.id, call.reply);
+						}
+						reply = replies.recv() => {
+							let (id, reply) = match reply {
+								Some(reply) => reply,
+								None => break Ok(()),
+							};
+
+							if let Some(caller) = in_flight.remove(&id) {
+								// The caller may have timed out, in which case there's nobody to tell.
+								let _ = caller.send(reply);
+							}
+						}
+					}
+				};
 
-					let mut api = self.api.lock().await;
-					let topic = api.topics.get_mut(&self.topic).ok_or(BusError::TopicNotFound)?;
+				drop(calls);
+				self.api.lock().await.unregister_service(&self.topic);
+				result
+			}
+			BusActionType::Call => {
+				let mut reader = reader;
+				let payload = read_message(&mut reader, self.framing).await?;
+				let mut writer = BufWriter::new(writer);
+				match self.call(payload).await {
+					Ok(reply) if reply.len() > self.framing.max_message_length() => {
+						writer.write_u8(CallStatus::TooLarge as u8).await?;
+						writer.flush().await?;
+					}
+					Ok(reply) => {
+						writer.write_u8(CallStatus::Ok as u8).await?;
+						write_message(&mut writer, &reply, self.framing).await?;
+					}
+					Err(status) => {
+						writer.write_u8(status as u8).await?;
+						writer.flush().await?;
+					}
+				}
+
+				Ok(())
+			}
+			BusActionType::Topics => {
+				let topics = self.api.lock().await.topics();
+				let mut writer = BufWriter::new(writer);
+				for topic in topics {
+					write_message(&mut writer, topic.encode().as_bytes(), self.framing).await?;
+				}
+
+				Ok(())
+			}
+			BusActionType::Trace => {
+				let mut rx = self.api.lock().await.trace(&self.topic, self.rate);
 
-					topic.publish(&buffer).await;
+				let mut writer = BufWriter::new(writer);
+				while let Some((event, message)) = rx.recv().await {
+					let event = event.encode();
+					if write_message(&mut writer, event.as_bytes(), self.framing)
+						.await
+						.is_err() || write_message(&mut writer, &message, self.framing).await.is_err()
+					{
+						break;
+					}
 				}
 
+				info!(ctx.logger, "Stopping trace"; "topic" => &self.topic, "pid" => ctx.peer.pid());
 				Ok(())
 			}
 		}
 	}
 }
 
-/// A topic to publish and subscribe to.
+/// Finds the value of the given argument in a header.
+fn find_arg<'a>(args: &[(&str, &'a str)], key: &str) -> Option<&'a str> {
+	args.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// Parses the value of the given argument in a header, if it's there.
+fn parse_arg<T: FromStr>(args: &[(&str, &str)], key: &'static str) -> Result<Option<T>, BusError> {
+	match find_arg(args, key) {
+		Some(value) => match value.parse() {
+			Ok(value) => Ok(Some(value)),
+			Err(_) => Err(BusError::InvalidArgument(key, value.to_owned())),
+		},
+		None => Ok(None),
+	}
+}
+
+/// Reads replies from a service connection, forwarding them into the given channel.
+async fn read_replies<R: AsyncRead + Unpin>(mut reader: R, framing: Framing, replies: mpsc::Sender<(u64, Vec<u8>)>) {
+	loop {
+		let id = match reader.read_u64().await {
+			Ok(id) => id,
+			Err(_) => return,
+		};
+
+		let reply = match read_message(&mut reader, framing).await {
+			Ok(reply) => reply,
+			Err(_) => return,
+		};
+
+		if replies.send((id, reply)).await.is_err() {
+			return;
+		}
+	}
+}
+
+/// A topic to publish to.
 struct Topic {
 	logger: slog::Logger,
 
 	/// The name of the topic.
 	name: String,
 
-	/// The subscribers to the topic.
-	subscribers: Vec<Subscription>,
+	/// The number of publishers connected to the topic.
+	publishers: usize,
+
+	/// The latest retained messages published to the topic, oldest first.
+	retained: VecDeque<Vec<u8>>,
+
+	/// The maximum number of messages to retain.
+	retain_limit: usize,
 }
 
 impl Topic {
-	/// Publish a message to every subscriber.
-	async fn publish(&mut self, message: &[u8]) {
-		let mut num_sucessfully_sent = 0;
-		self.subscribers.retain(|r| {
-			if r.connection.try_send(message.to_owned()).is_ok() {
-				num_sucessfully_sent += 1;
-				true
-			} else {
-				info!(self.logger, "Removing reader"; "topic" => self.name.as_str());
-				false
-			}
-		});
+	/// Sets the number of messages to retain, dropping the oldest retained messages if there are now too many.
+	fn set_retention(&mut self, limit: usize) {
+		if limit != self.retain_limit {
+			info!(self.logger, "Setting retention"; "topic" => self.name.as_str(), "limit" => limit);
+		}
+
+		self.retain_limit = limit;
+		while self.retained.len() > self.retain_limit {
+			self.retained.pop_front();
+		}
 	}
 
-	/// Subscribe to the topic.
-	fn subscribe(&mut self) -> mpsc::Receiver<Vec<u8>> {
-		let (tx, rx) = mpsc::channel(100);
-		self.subscribers.push(Subscription { connection: tx });
-		rx
+	/// Retains the message to replay to later subscribers, if the topic has retention enabled.
+	fn retain(&mut self, message: &[u8]) {
+		if self.retain_limit == 0 {
+			return;
+		}
+
+		if self.retained.len() >= self.retain_limit {
+			self.retained.pop_front();
+		}
+
+		self.retained.push_back(message.to_owned());
 	}
 }
 
+/// The channel that new messages are sent to a subscriber over, along with the retained messages to replay first.
+type NewSubscription = (mpsc::Receiver<Vec<u8>>, Vec<Vec<u8>>);
+
 /// A subscription to a topic that we can send published messages to.
 struct Subscription {
 	connection: mpsc::Sender<Vec<u8>>,
+
+	/// The filter that messages have to match to be sent to the subscriber, if it gave one.
+	filter: Option<MessageFilter>,
+}
+
+/// The subscriptions on the bus, in a tree keyed by the levels of their topic filters.
+#[derive(Default)]
+struct SubscriptionTrie {
+	/// The subscriptions whose filters end at this level.
+	subscribers: Vec<Subscription>,
+
+	/// The next levels of the filters below this one, which may be wildcards.
+	children: HashMap<String, SubscriptionTrie>,
+}
+
+impl SubscriptionTrie {
+	/// Adds a subscription with the given filter.
+	fn insert(&mut self, filter: &str, subscription: Subscription) {
+		let mut node = self;
+		for level in filter.split(TOPIC_SEPARATOR) {
+			node = node.children.entry(level.to_owned()).or_default();
+		}
+
+		node.subscribers.push(subscription);
+	}
+
+	/// Calls `f` with the subscribers of every filter that matches the topic with the given levels.
+	fn visit_matches<F: FnMut(&mut Vec<Subscription>)>(&mut self, levels: &[&str], f: &mut F) {
+		if let Some(node) = self.children.get_mut(MULTI_LEVEL_WILDCARD) {
+			f(&mut node.subscribers);
+		}
+
+		match levels.split_first() {
+			None => f(&mut self.subscribers),
+			Some((level, rest)) => {
+				if let Some(node) = self.children.get_mut(*level) {
+					node.visit_matches(rest, f);
+				}
+
+				if let Some(node) = self.children.get_mut(SINGLE_LEVEL_WILDCARD) {
+					node.visit_matches(rest, f);
+				}
+			}
+		}
+	}
+
+	/// Removes the subscriptions that have gone away, along with any levels left without subscriptions. Returns
+	/// whether this level is now empty.
+	fn prune(&mut self) -> bool {
+		self.subscribers.retain(|s| !s.connection.is_closed());
+		self.children.retain(|_, child| !child.prune());
+		self.subscribers.is_empty() && self.children.is_empty()
+	}
+
+	/// Collects every filter that has subscriptions, along with the number of subscriptions.
+	fn filters(&self, prefix: Option<&str>, filters: &mut Vec<(String, usize)>) {
+		if let Some(prefix) = prefix {
+			if !self.subscribers.is_empty() {
+				filters.push((prefix.to_owned(), self.subscribers.len()));
+			}
+		}
+
+		for (level, child) in self.children.iter() {
+			let filter = match prefix {
+				Some(prefix) => format!("{}{}{}", prefix, TOPIC_SEPARATOR, level),
+				None => level.clone(),
+			};
+
+			child.filters(Some(&filter), filters);
+		}
+	}
+}
+
+/// A traced message, and its metadata.
+type Traced = (TraceEvent, Vec<u8>);
+
+/// A tracer of the messages published to the topics that match a filter.
+struct Tracer {
+	filter: String,
+	connection: mpsc::Sender<Traced>,
+
+	/// The maximum number of messages to send the tracer a second.
+	rate: u32,
+
+	/// When the current second of the rate started, and the number of messages sent to the tracer in it.
+	window: (Instant, u32),
+
+	/// The number of messages that have been dropped since the last one that was sent to the tracer.
+	dropped: u64,
+}
+
+impl Tracer {
+	/// Sends the message to the tracer, unless it's over its rate or isn't keeping up, in which case the message is
+	/// counted as dropped. Returns false if the tracer has gone away.
+	fn trace(&mut self, event: &TraceEvent, message: &[u8]) -> bool {
+		let now = Instant::now();
+		if now.duration_since(self.window.0) >= Duration::from_secs(1) {
+			self.window = (now, 0);
+		}
+
+		if self.window.1 >= self.rate {
+			self.dropped += 1;
+			return true;
+		}
+
+		let event = TraceEvent {
+			dropped: self.dropped,
+			..event.clone()
+		};
+
+		match self.connection.try_send((event, message.to_owned())) {
+			Ok(()) => {
+				self.window.1 += 1;
+				self.dropped = 0;
+				true
+			}
+			Err(mpsc::error::TrySendError::Full(_)) => {
+				self.dropped += 1;
+				true
+			}
+			Err(mpsc::error::TrySendError::Closed(_)) => false,
+		}
+	}
+}
+
+/// A call that has been routed to a service, and is waiting for a reply.
+struct PendingCall {
+	/// The ID of the call, which the service sends back with its reply.
+	id: u64,
+
+	/// The payload of the call.
+	payload: Vec<u8>,
+
+	/// The channel to send the reply to the caller over.
+	reply: oneshot::Sender<Vec<u8>>,
+}
+
+/// A service that answers calls made on a topic.
+struct Service {
+	calls: mpsc::Sender<PendingCall>,
 }
 
 /// The API for the message bus.
 pub struct BusAPI {
 	logger: slog::Logger,
 	topics: HashMap<String, Topic>,
+
+	/// The subscriptions to topics, keyed by their filters.
+	subscriptions: SubscriptionTrie,
+
+	/// The services that answer calls, keyed by topic.
+	services: HashMap<String, Service>,
+
+	/// The tracers of the messages published on the bus.
+	tracers: Vec<Tracer>,
+
+	/// The number of messages that have been published on the bus, used to order traced messages.
+	sequence: u64,
+
+	/// The ID to give to the next call.
+	next_call_id: u64,
 }
 
 impl BusAPI {
@@ -173,9 +705,43 @@ impl BusAPI {
 		Self {
 			logger,
 			topics: HashMap::new(),
+			subscriptions: SubscriptionTrie::default(),
+			services: HashMap::new(),
+			tracers: Vec::new(),
+			sequence: 0,
+			next_call_id: 0,
+		}
+	}
+
+	/// Registers a service for the given topic, returning a channel that calls to the topic are sent over.
+	fn register_service(&mut self, topic: &str) -> Result<mpsc::Receiver<PendingCall>, BusError> {
+		if let Some(service) = self.services.get(topic) {
+			if !service.calls.is_closed() {
+				return Err(BusError::ServiceExists(topic.to_owned()));
+			}
+		}
+
+		info!(self.logger, "Registering service"; "topic" => topic);
+		let (tx, rx) = mpsc::channel(100);
+		self.services.insert(topic.to_owned(), Service { calls: tx });
+		Ok(rx)
+	}
+
+	/// Removes the service for the given topic, if it has gone away.
+	fn unregister_service(&mut self, topic: &str) {
+		if self.services.get(topic).is_some_and(|s| s.calls.is_closed()) {
+			info!(self.logger, "Unregistering service"; "topic" => topic);
+			self.services.remove(topic);
 		}
 	}
 
+	/// Gets a channel to the service for the given topic, along with a new ID for a call to it.
+	fn get_service(&mut self, topic: &str) -> Option<(u64, mpsc::Sender<PendingCall>)> {
+		let service = self.services.get(topic)?.calls.clone();
+		self.next_call_id += 1;
+		Some((self.next_call_id, service))
+	}
+
 	/// Create a new topic, if it doesn't already exist.
 	fn create_topic(&mut self, name: &str) {
 		if self.topics.contains_key(name) {
@@ -187,14 +753,186 @@ impl BusAPI {
 			Topic {
 				logger: self.logger.new(o!("topic" => name.to_owned())),
 				name: name.to_owned(),
-				subscribers: Vec::new(),
+				publishers: 0,
+				retained: VecDeque::new(),
+				retain_limit: 0,
 			},
 		);
 	}
 
-	fn subscribe(&mut self, topic_name: &str) -> Option<mpsc::Receiver<Vec<u8>>> {
-		let topic = self.topics.get_mut(topic_name)?;
-		Some(topic.subscribe())
+	/// Registers a publisher to the given topic, creating the topic if it doesn't exist. If `retain` is non-zero, the
+	/// topic retains that many messages.
+	fn add_publisher(&mut self, name: &str, retain: usize) {
+		self.create_topic(name);
+		if let Some(topic) = self.topics.get_mut(name) {
+			topic.publishers += 1;
+			if retain > 0 {
+				topic.set_retention(retain);
+			}
+		}
+	}
+
+	/// Removes a publisher from the given topic.
+	fn remove_publisher(&mut self, name: &str) {
+		if let Some(topic) = self.topics.get_mut(name) {
+			topic.publishers = topic.publishers.saturating_sub(1);
+		}
+	}
+
+	/// Publishes a message to every subscriber whose filter matches the topic, optionally retaining it to replay to
+	/// later subscribers. The publisher is passed on to any tracers of the topic.
+	fn publish(&mut self, name: &str, message: &[u8], retain: bool, publisher: &Peer) {
+		self.sequence += 1;
+		if !self.tracers.is_empty() {
+			let event = TraceEvent {
+				sequence: self.sequence,
+				timestamp: SystemTime::now(),
+				pid: publisher.pid(),
+				uid: publisher.uid(),
+				dropped: 0,
+				topic: name.to_owned(),
+			};
+
+			let logger = &self.logger;
+			self.tracers.retain_mut(|tracer| {
+				if !topic_matches(&tracer.filter, name) || tracer.trace(&event, message) {
+					true
+				} else {
+					info!(logger, "Removing tracer"; "filter" => &tracer.filter);
+					false
+				}
+			});
+		}
+
+		if retain {
+			if let Some(topic) = self.topics.get_mut(name) {
+				topic.retain(message);
+			}
+		}
+
+		let levels: Vec<&str> = name.split(TOPIC_SEPARATOR).collect();
+		let logger = &self.logger;
+
+		// The message is only parsed if a subscriber's filter needs it, and then only once.
+		let payload = OnceCell::new();
+		self.subscriptions.visit_matches(&levels, &mut |subscribers| {
+			subscribers.retain(|r| {
+				if let Some(filter) = &r.filter {
+					let payload = if filter.needs_payload() {
+						payload.get_or_init(|| parse_payload(message)).as_ref()
+					} else {
+						None
+					};
+
+					if !filter.matches(name, payload) {
+						return !r.connection.is_closed();
+					}
+				}
+
+				if r.connection.try_send(message.to_owned()).is_ok() {
+					true
+				} else {
+					info!(logger, "Removing reader"; "topic" => name);
+					false
+				}
+			});
+		});
+	}
+
+	/// Subscribes to every topic that matches the given filter, optionally replaying the messages retained on them.
+	/// Only the messages that match the message filter, if there is one, are sent to the subscriber.
+	fn subscribe(&mut self, filter: &str, replay: bool, message_filter: Option<MessageFilter>) -> NewSubscription {
+		self.subscriptions.prune();
+
+		let (tx, rx) = mpsc::channel(100);
+		self.subscriptions.insert(
+			filter,
+			Subscription {
+				connection: tx,
+				filter: message_filter.clone(),
+			},
+		);
+
+		let mut retained = Vec::new();
+		if replay {
+			let mut topics: Vec<&Topic> = self
+				.topics
+				.values()
+				.filter(|topic| topic_matches(filter, &topic.name))
+				.collect();
+			topics.sort_by(|a, b| a.name.cmp(&b.name));
+
+			for topic in topics {
+				retained.extend(
+					topic
+						.retained
+						.iter()
+						.filter(|message| match &message_filter {
+							Some(message_filter) => message_filter.matches_message(&topic.name, message),
+							None => true,
+						})
+						.cloned(),
+				);
+			}
+		}
+
+		(rx, retained)
+	}
+
+	/// Traces the messages published to every topic that matches the given filter, sending at most `rate` a second.
+	fn trace(&mut self, filter: &str, rate: u32) -> mpsc::Receiver<Traced> {
+		self.tracers.retain(|tracer| !tracer.connection.is_closed());
+
+		info!(self.logger, "Starting trace"; "filter" => filter, "rate" => rate);
+		let (tx, rx) = mpsc::channel(100);
+		self.tracers.push(Tracer {
+			filter: filter.to_owned(),
+			connection: tx,
+			rate,
+			window: (Instant::now(), 0),
+			dropped: 0,
+		});
+
+		rx
+	}
+
+	/// Lists the topics that have subscribers, publishers, or retained messages, along with any subscribed filters
+	/// that don't correspond to a topic.
+	fn topics(&mut self) -> Vec<TopicInfo> {
+		self.subscriptions.prune();
+
+		let mut topics = Vec::new();
+		for topic in self.topics.values() {
+			let levels: Vec<&str> = topic.name.split(TOPIC_SEPARATOR).collect();
+			let mut subscribers = 0;
+			self.subscriptions
+				.visit_matches(&levels, &mut |s| subscribers += s.len());
+
+			if subscribers == 0 && topic.publishers == 0 && topic.retained.is_empty() {
+				continue;
+			}
+
+			topics.push(TopicInfo {
+				name: topic.name.clone(),
+				subscribers,
+				publishers: topic.publishers,
+			});
+		}
+
+		let mut filters = Vec::new();
+		self.subscriptions.filters(None, &mut filters);
+		for (filter, subscribers) in filters {
+			if !self.topics.contains_key(&filter) {
+				topics.push(TopicInfo {
+					name: filter,
+					subscribers,
+					publishers: 0,
+				});
+			}
+		}
+
+		topics.sort_by(|a, b| a.name.cmp(&b.name));
+		topics
 	}
 }
 
@@ -203,12 +941,32 @@ pub enum BusError {
 	#[error("Missing argument: {0}")]
 	MissingArgument(&'static str),
 
-	#[error("Topic not found")]
-	TopicNotFound,
-
 	#[error("Unknown action: {0}")]
 	UnknownAction(String),
 
+	#[error("Invalid argument {0}: {1}")]
+	InvalidArgument(&'static str, String),
+
+	#[error("A service is already registered for {0}")]
+	ServiceExists(String),
+
+	#[error("Permission denied to {0} on {1}")]
+	PermissionDenied(String, String),
+
 	#[error("IO error: {0}")]
 	IOError(#[from] std::io::Error),
 }
+
+impl From<BusError> for ErrorReply {
+	fn from(value: BusError) -> Self {
+		let kind = match value {
+			BusError::MissingArgument(_) | BusError::InvalidArgument(_, _) => protocol::ErrorKind::InvalidRequest,
+			BusError::UnknownAction(_) => protocol::ErrorKind::UnknownRequest,
+			BusError::ServiceExists(_) => protocol::ErrorKind::Conflict,
+			BusError::PermissionDenied(_, _) => protocol::ErrorKind::PermissionDenied,
+			BusError::IOError(_) => protocol::ErrorKind::Internal,
+		};
+
+		ErrorReply::new(kind, value)
+	}
+}
@@ -1,14 +1,27 @@
 mod api;
+mod policy;
 use api::{BusAPI, BusAction, BusActionType};
-use bus::DEFAULT_BUSD_SOCKET;
+use bus::{BusRequest, Framing, DEFAULT_BUSD_SOCKET};
 use clap::{Arg, Command};
-use common::{obs::assemble_logger, qinit::mark_running};
+use common::{config::ConfigSource, obs::assemble_logger, qinit::mark_running};
 use control::listen::{Action, ActionFactory, ControlSocket};
-use std::{io::stderr, path::PathBuf, str::FromStr, sync::Arc};
-use tokio::sync::Mutex;
+use policy::{Policy, DEFAULT_POLICY_PATH};
+use slog::{error, info, Logger};
+use std::{
+	io::stderr,
+	path::PathBuf,
+	process::ExitCode,
+	str::FromStr,
+	sync::{Arc, RwLock},
+	thread,
+};
+use tokio::{
+	signal::unix::{signal, SignalKind},
+	sync::Mutex,
+};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
 	let app = Command::new("busd")
 		.version("0.1.0")
 		.about("A message bus daemon")
@@ -19,28 +32,110 @@ async fn main() {
 				.default_value(DEFAULT_BUSD_SOCKET)
 				.help("The path to the control socket"),
 		)
+		.arg(
+			Arg::new("policy")
+				.long("policy")
+				.num_args(1)
+				.default_value(DEFAULT_POLICY_PATH)
+				.help("The path to the policy file that restricts who can use which topics. Drop-ins in the .d directory next to it are merged into it, and it's reloaded when any of them change, or on SIGHUP"),
+		)
 		.get_matches();
 	let logger = assemble_logger(stderr());
+	let policy_path: &String = app.get_one("policy").unwrap();
+	let source = ConfigSource::new(policy_path);
+	let policy = match Policy::load(&source) {
+		Ok(policy) => Arc::new(RwLock::new(Arc::new(policy))),
+		Err(e) => {
+			error!(logger, "failed to load policy"; "path" => policy_path, "error" => e.to_string());
+			return ExitCode::FAILURE;
+		}
+	};
+
+	watch_policy(source, policy.clone(), logger.clone());
+
 	let api = Arc::new(Mutex::new(BusAPI::new(logger.clone())));
-	let factory: BusControlActionFactory = BusControlActionFactory { api };
+	let factory: BusControlActionFactory = BusControlActionFactory { api, policy };
 	let socket_path: &String = app.get_one("socket").unwrap();
 
-	let socket = ControlSocket::open(&PathBuf::from_str(socket_path).unwrap(), factory).unwrap();
+	let socket = ControlSocket::open(&PathBuf::from_str(socket_path).unwrap(), factory, logger.clone()).unwrap();
+
+	if let Err(e) = socket.shutdown_handle().shutdown_on_signals() {
+		error!(logger, "failed to handle SIGTERM, so busd won't shut down cleanly"; "error" => e.to_string());
+	}
 
 	mark_running().unwrap();
 
 	socket.listen().await;
+	info!(logger, "shut down");
+
+	ExitCode::SUCCESS
+}
+
+/// Reloads the policy whenever its files change, or busd is sent SIGHUP. If the new policy is invalid, the current
+/// one is kept.
+fn watch_policy(source: ConfigSource, policy: Arc<RwLock<Arc<Policy>>>, logger: Logger) {
+	let watcher = source
+		.watch()
+		.map_err(
+			|e| error!(logger, "failed to watch policy, so it won't be reloaded when it changes"; "error" => e.to_string()),
+		)
+		.ok();
+	let hangups = signal(SignalKind::hangup())
+		.map_err(
+			|e| error!(logger, "failed to handle SIGHUP, so the policy won't be reloaded by it"; "error" => e.to_string()),
+		)
+		.ok();
+
+	let reload = move || match Policy::load(&source) {
+		Ok(new) => {
+			*policy.write().unwrap() = Arc::new(new);
+			info!(logger, "reloaded policy");
+		}
+		Err(e) => error!(logger, "failed to reload policy, keeping the current one"; "error" => e.to_string()),
+	};
+
+	if let Some(mut watcher) = watcher {
+		let reload = reload.clone();
+		thread::spawn(move || {
+			while watcher.wait().is_ok() {
+				reload();
+			}
+		});
+	}
+
+	if let Some(mut hangups) = hangups {
+		tokio::spawn(async move {
+			while hangups.recv().await.is_some() {
+				reload();
+			}
+		});
+	}
 }
 
 #[derive(Clone)]
 struct BusControlActionFactory {
 	api: Arc<Mutex<BusAPI>>,
+
+	/// The current policy, which is replaced when it's reloaded. Each action keeps the policy that was current when
+	/// it started.
+	policy: Arc<RwLock<Arc<Policy>>>,
 }
 
 impl ActionFactory for BusControlActionFactory {
 	type Action = BusAction;
+	type Request = BusRequest;
+
 	fn build(&self, action: &str, args: &[(&str, &str)]) -> Result<Self::Action, <Self::Action as Action>::Error> {
 		let action = BusActionType::try_from(action)?;
-		BusAction::try_new(self.api.clone(), action, args)
+		BusAction::from_args(self.api.clone(), self.policy.read().unwrap().clone(), action, args)
+	}
+
+	fn build_request(&self, request: BusRequest) -> Result<Self::Action, <Self::Action as Action>::Error> {
+		BusAction::new(
+			self.api.clone(),
+			self.policy.read().unwrap().clone(),
+			request,
+			Framing::Chunked,
+		)
 	}
 }
@@ -0,0 +1,270 @@
+use bus::{MULTI_LEVEL_WILDCARD, SINGLE_LEVEL_WILDCARD, TOPIC_SEPARATOR};
+use common::config::{ConfigError, ConfigSource};
+use serde::Deserialize;
+use thiserror::Error;
+
+/// The default path of the policy file. Drop-ins that add rules to it are read from `/etc/busd/policy.d`.
+pub const DEFAULT_POLICY_PATH: &str = "/etc/busd/policy.toml";
+
+/// The topics that system daemons publish to, and that other processes trust, e.g. udev events that qinit starts
+/// services from. Only root can publish to them unless a rule says otherwise, so that a missing policy file doesn't
+/// let anyone spoof them.
+const PRIVILEGED_TOPICS: [&str; 1] = ["udev_events"];
+
+/// Whether an action reads from a topic, or writes to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+	/// Publishing to, or serving calls on, a topic.
+	Publish,
+
+	/// Subscribing to, or making calls on, a topic.
+	Subscribe,
+
+	/// Tracing the messages published to a topic, which also shows who published them, and when.
+	Trace,
+}
+
+/// The uids and gids that are allowed to access a topic.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Principals {
+	#[serde(default)]
+	pub uids: Vec<u32>,
+
+	#[serde(default)]
+	pub gids: Vec<u32>,
+}
+
+impl Principals {
+	/// Whether the user or group is one of the principals. Root is always allowed.
+	fn allows(&self, uid: u32, gid: u32) -> bool {
+		uid == 0 || self.uids.contains(&uid) || self.gids.contains(&gid)
+	}
+}
+
+/// A rule restricting access to the topics that match a filter.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+	/// The topics that the rule applies to, which may contain wildcards.
+	pub topic: String,
+
+	/// Who can publish to, or serve calls on, the topics. If not set, anyone can.
+	pub publish: Option<Principals>,
+
+	/// Who can subscribe to, or make calls on, the topics. If not set, anyone can.
+	pub subscribe: Option<Principals>,
+
+	/// Who can trace the topics. If not set, only root can.
+	pub trace: Option<Principals>,
+}
+
+impl Rule {
+	fn principals(&self, access: Access) -> Option<&Principals> {
+		match access {
+			Access::Publish => self.publish.as_ref(),
+			Access::Subscribe => self.subscribe.as_ref(),
+			Access::Trace => self.trace.as_ref(),
+		}
+	}
+}
+
+/// The authorization policy for the bus. Once a rule restricts access to a topic, access is denied to anyone not
+/// listed in it (and every other rule that applies). Topics that no rule restricts can be used by anyone, apart from
+/// publishing to the `PRIVILEGED_TOPICS`, and tracing, which are denied to everyone but root.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+	#[serde(default, rename = "rule")]
+	pub rules: Vec<Rule>,
+}
+
+impl Policy {
+	/// Loads the policy from the given file and its drop-ins. If there are none, the policy only restricts the
+	/// `PRIVILEGED_TOPICS` and tracing.
+	pub fn load(source: &ConfigSource) -> Result<Self, PolicyError> {
+		let policy: Policy = source.load()?;
+		for rule in policy.rules.iter() {
+			if !bus::is_valid_filter(&rule.topic) {
+				return Err(PolicyError::InvalidTopic(rule.topic.clone()));
+			}
+		}
+
+		Ok(policy)
+	}
+
+	/// Whether the given user and (primary) group are allowed the given access to the given topic. For subscriptions,
+	/// the topic may be a filter, in which case access must be allowed to every restricted topic the filter matches.
+	pub fn allows(&self, uid: u32, gid: u32, topic: &str, access: Access) -> bool {
+		// Access that's only root's by default has to be granted for every topic the filter matches, so a rule about
+		// some of them can't open up the rest.
+		if uid != 0
+			&& restricted_by_default(topic, access)
+			&& !self
+				.rules
+				.iter()
+				.any(|rule| filter_covers(&rule.topic, topic) && rule.principals(access).is_some())
+		{
+			return false;
+		}
+
+		self.rules
+			.iter()
+			.filter(|rule| filters_overlap(&rule.topic, topic))
+			.filter_map(|rule| rule.principals(access))
+			.all(|principals| principals.allows(uid, gid))
+	}
+}
+
+/// Whether the given access to the given topic is only allowed to root when no rule says who it's allowed to.
+fn restricted_by_default(topic: &str, access: Access) -> bool {
+	match access {
+		Access::Publish => PRIVILEGED_TOPICS
+			.iter()
+			.any(|privileged| filters_overlap(privileged, topic)),
+		Access::Subscribe => false,
+		Access::Trace => true,
+	}
+}
+
+/// Whether every topic that matches the second filter also matches the first.
+fn filter_covers(a: &str, b: &str) -> bool {
+	let mut a = a.split(TOPIC_SEPARATOR);
+	let mut b = b.split(TOPIC_SEPARATOR);
+	loop {
+		match (a.next(), b.next()) {
+			(Some(MULTI_LEVEL_WILDCARD), _) => return true,
+			(None, None) => return true,
+			(_, Some(MULTI_LEVEL_WILDCARD)) => return false,
+			(Some(a), Some(b)) if a == b || a == SINGLE_LEVEL_WILDCARD => {}
+			_ => return false,
+		}
+	}
+}
+
+/// Whether there is any topic that would match both of the given filters.
+fn filters_overlap(a: &str, b: &str) -> bool {
+	let mut a = a.split(TOPIC_SEPARATOR);
+	let mut b = b.split(TOPIC_SEPARATOR);
+	loop {
+		match (a.next(), b.next()) {
+			(Some(MULTI_LEVEL_WILDCARD), _) | (_, Some(MULTI_LEVEL_WILDCARD)) => return true,
+			(None, None) => return true,
+			(Some(a), Some(b)) if a == b || a == SINGLE_LEVEL_WILDCARD || b == SINGLE_LEVEL_WILDCARD => {}
+			_ => return false,
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum PolicyError {
+	#[error("failed to load policy: {0}")]
+	ConfigError(#[from] ConfigError),
+
+	#[error("invalid topic in policy: {0}")]
+	InvalidTopic(String),
+}
+
+#[cfg(test)]
+mod test {
+	use super::{filter_covers, filters_overlap, Access, Policy};
+
+	#[test]
+	fn test_filters_overlap() {
+		assert!(filters_overlap("udev_events", "udev_events"));
+		assert!(filters_overlap("udev/#", "udev/block/sda"));
+		assert!(filters_overlap("udev/block/sda", "#"));
+		assert!(filters_overlap("udev/+/sda", "udev/block/+"));
+		assert!(!filters_overlap("udev/+/sda", "udev/block"));
+		assert!(!filters_overlap("udev/#", "net/eth0"));
+	}
+
+	#[test]
+	fn test_filter_covers() {
+		assert!(filter_covers("udev_events", "udev_events"));
+		assert!(filter_covers("udev/#", "udev/block/+"));
+		assert!(filter_covers("udev/+/sda", "udev/block/sda"));
+		assert!(filter_covers("#", "udev/#"));
+		assert!(!filter_covers("udev/#", "#"));
+		assert!(!filter_covers("udev/block/sda", "udev/+/sda"));
+		assert!(!filter_covers("udev/+", "udev/block/sda"));
+	}
+
+	#[test]
+	fn test_allows() {
+		let policy: Policy = toml::from_str(
+			r#"
+			[[rule]]
+			topic = "udev_events"
+			publish = { uids = [0] }
+
+			[[rule]]
+			topic = "secrets/#"
+			publish = { uids = [1000] }
+			subscribe = { gids = [1000] }
+		"#,
+		)
+		.unwrap();
+
+		// Only root can publish udev events, but anyone can subscribe to them.
+		assert!(policy.allows(0, 0, "udev_events", Access::Publish));
+		assert!(!policy.allows(1000, 1000, "udev_events", Access::Publish));
+		assert!(policy.allows(1000, 1000, "udev_events", Access::Subscribe));
+
+		assert!(policy.allows(1000, 100, "secrets/key", Access::Publish));
+		assert!(!policy.allows(1001, 100, "secrets/key", Access::Publish));
+		assert!(policy.allows(1001, 1000, "secrets/key", Access::Subscribe));
+		assert!(!policy.allows(1001, 100, "secrets/key", Access::Subscribe));
+
+		// Wildcard subscriptions that could see restricted topics are denied.
+		assert!(!policy.allows(1001, 100, "#", Access::Subscribe));
+		assert!(policy.allows(1001, 100, "other/#", Access::Subscribe));
+
+		// Topics without rules are open to everyone.
+		assert!(policy.allows(1001, 100, "other", Access::Publish));
+	}
+
+	#[test]
+	fn test_privileged_topics() {
+		// Without a policy, only root can publish udev events, but anyone can still subscribe to them.
+		let policy = Policy::default();
+		assert!(policy.allows(0, 0, "udev_events", Access::Publish));
+		assert!(!policy.allows(1000, 1000, "udev_events", Access::Publish));
+		assert!(!policy.allows(1000, 1000, "#", Access::Publish));
+		assert!(policy.allows(1000, 1000, "udev_events", Access::Subscribe));
+		assert!(policy.allows(1000, 1000, "other", Access::Publish));
+
+		// Rules about other topics don't open them up.
+		let policy: Policy = toml::from_str(
+			r#"
+			[[rule]]
+			topic = "other"
+			publish = { uids = [1000] }
+		"#,
+		)
+		.unwrap();
+		assert!(!policy.allows(1000, 1000, "udev_events", Access::Publish));
+	}
+
+	#[test]
+	fn test_trace() {
+		// Being able to subscribe to a topic isn't enough to trace it.
+		let policy = Policy::default();
+		assert!(policy.allows(0, 0, "other", Access::Trace));
+		assert!(policy.allows(1000, 1000, "other", Access::Subscribe));
+		assert!(!policy.allows(1000, 1000, "other", Access::Trace));
+
+		let policy: Policy = toml::from_str(
+			r#"
+			[[rule]]
+			topic = "debug/#"
+			trace = { gids = [10] }
+		"#,
+		)
+		.unwrap();
+		assert!(policy.allows(1000, 10, "debug/net", Access::Trace));
+		assert!(!policy.allows(1000, 1000, "debug/net", Access::Trace));
+		assert!(!policy.allows(1000, 10, "other", Access::Trace));
+		assert!(!policy.allows(1000, 10, "#", Access::Trace));
+	}
+}
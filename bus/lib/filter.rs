@@ -0,0 +1,188 @@
+use std::fmt;
+
+use common::glob::Glob;
+use serde_json::{Map, Value};
+
+/// The key in a message filter that refers to the topic a message was published to, rather than a key in its
+/// payload. It's only useful for subscriptions to topic filters with wildcards.
+pub const TOPIC_KEY: &str = "$topic";
+
+/// What a single term of a message filter checks.
+#[derive(Debug, Clone)]
+enum Condition {
+	/// The key is present, e.g. `MODALIAS`.
+	Present,
+
+	/// The key is absent, e.g. `!MODALIAS`.
+	Absent,
+
+	/// The key is present, and its value matches the glob, e.g. `SUBSYSTEM=block`.
+	Matches(Glob),
+
+	/// The key is absent, or its value doesn't match the glob, e.g. `ACTION!=remove`.
+	DoesNotMatch(Glob),
+}
+
+#[derive(Debug, Clone)]
+struct Term {
+	key: String,
+	condition: Condition,
+}
+
+impl Term {
+	fn parse(term: &str) -> Option<Self> {
+		let (key, condition) = if let Some((key, value)) = term.split_once("!=") {
+			(key, Condition::DoesNotMatch(Glob::new(value)))
+		} else if let Some((key, value)) = term.split_once('=') {
+			(key, Condition::Matches(Glob::new(value)))
+		} else if let Some(key) = term.strip_prefix('!') {
+			(key, Condition::Absent)
+		} else {
+			(term, Condition::Present)
+		};
+
+		if key.is_empty() || key.contains(['!', '=']) {
+			return None;
+		}
+
+		Some(Self {
+			key: key.to_owned(),
+			condition,
+		})
+	}
+
+	fn matches(&self, value: Option<&str>) -> bool {
+		match (&self.condition, value) {
+			(Condition::Present, value) => value.is_some(),
+			(Condition::Absent, value) => value.is_none(),
+			(Condition::Matches(glob), Some(value)) => glob.matches(value),
+			(Condition::Matches(_), None) => false,
+			(Condition::DoesNotMatch(glob), Some(value)) => !glob.matches(value),
+			(Condition::DoesNotMatch(_), None) => true,
+		}
+	}
+}
+
+/// A filter on the messages delivered to a subscriber, which busd evaluates so that subscribers aren't woken for
+/// messages they'd throw away.
+///
+/// A filter is a list of terms separated by whitespace, all of which have to match:
+///
+///  - `KEY` matches messages that have the key
+///  - `!KEY` matches messages that don't have the key
+///  - `KEY=VALUE` matches messages where the key's value matches the glob `VALUE`
+///  - `KEY!=VALUE` matches messages where the key is missing, or its value doesn't match the glob `VALUE`
+///
+/// Keys are the top-level keys of messages that are JSON objects, or `$topic` for the topic the message was
+/// published to. Values that aren't strings are compared as JSON, e.g. `true` or `42`. Messages that aren't JSON
+/// objects don't have any keys.
+#[derive(Debug, Clone)]
+pub struct MessageFilter {
+	source: String,
+	terms: Vec<Term>,
+}
+
+impl MessageFilter {
+	/// Parses a filter, returning None if it's empty or has invalid terms.
+	pub fn parse(filter: &str) -> Option<Self> {
+		let terms = filter.split_whitespace().map(Term::parse).collect::<Option<Vec<_>>>()?;
+		if terms.is_empty() {
+			return None;
+		}
+
+		Some(Self {
+			source: filter.to_owned(),
+			terms,
+		})
+	}
+
+	/// Whether any of the terms look at the payload of messages, which then has to be parsed.
+	pub fn needs_payload(&self) -> bool {
+		self.terms.iter().any(|term| term.key != TOPIC_KEY)
+	}
+
+	/// Whether the message published to the given topic matches the filter. The payload is the message parsed as a
+	/// JSON object, or None if it isn't one.
+	pub fn matches(&self, topic: &str, payload: Option<&Map<String, Value>>) -> bool {
+		self.terms.iter().all(|term| {
+			if term.key == TOPIC_KEY {
+				return term.matches(Some(topic));
+			}
+
+			match payload.and_then(|payload| payload.get(&term.key)) {
+				Some(Value::String(value)) => term.matches(Some(value)),
+				Some(value) => term.matches(Some(&value.to_string())),
+				None => term.matches(None),
+			}
+		})
+	}
+
+	/// Whether the raw message published to the given topic matches the filter.
+	pub fn matches_message(&self, topic: &str, message: &[u8]) -> bool {
+		let payload = if self.needs_payload() {
+			parse_payload(message)
+		} else {
+			None
+		};
+
+		self.matches(topic, payload.as_ref())
+	}
+}
+
+impl fmt::Display for MessageFilter {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.source)
+	}
+}
+
+/// Parses a message as a JSON object, for matching against filters.
+pub fn parse_payload(message: &[u8]) -> Option<Map<String, Value>> {
+	match serde_json::from_slice(message) {
+		Ok(Value::Object(payload)) => Some(payload),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::MessageFilter;
+
+	#[test]
+	fn test_parse() {
+		assert!(MessageFilter::parse("MODALIAS").is_some());
+		assert!(MessageFilter::parse("SUBSYSTEM=block ACTION!=remove !SYNTHETIC").is_some());
+		assert!(MessageFilter::parse("").is_none());
+		assert!(MessageFilter::parse("   ").is_none());
+		assert!(MessageFilter::parse("=block").is_none());
+		assert!(MessageFilter::parse("!").is_none());
+		assert!(MessageFilter::parse("!KEY=value").is_none());
+	}
+
+	#[test]
+	fn test_matches() {
+		let add =
+			br#"{"ACTION": "add", "SUBSYSTEM": "block", "DEVNAME": "sda1", "MODALIAS": "scsi:t-0x00", "SEQNUM": 12}"#;
+		let remove = br#"{"ACTION": "remove", "SUBSYSTEM": "block", "DEVNAME": "sda1"}"#;
+
+		let filter = MessageFilter::parse("MODALIAS").unwrap();
+		assert!(filter.matches_message("udev_events", add));
+		assert!(!filter.matches_message("udev_events", remove));
+		assert!(!filter.matches_message("udev_events", b"MODALIAS"));
+
+		let filter = MessageFilter::parse("SUBSYSTEM=block DEVNAME=sd* ACTION!=remove").unwrap();
+		assert!(filter.matches_message("udev_events", add));
+		assert!(!filter.matches_message("udev_events", remove));
+
+		let filter = MessageFilter::parse("!MODALIAS SEQNUM!=12").unwrap();
+		assert!(!filter.matches_message("udev_events", add));
+		assert!(filter.matches_message("udev_events", remove));
+
+		let filter = MessageFilter::parse("SEQNUM=1?").unwrap();
+		assert!(filter.matches_message("udev_events", add));
+
+		let filter = MessageFilter::parse("$topic=udev/block/*").unwrap();
+		assert!(!filter.needs_payload());
+		assert!(filter.matches_message("udev/block/sda", b"not json"));
+		assert!(!filter.matches_message("udev/net/eth0", b"not json"));
+	}
+}
@@ -1,19 +1,367 @@
-use std::{io::ErrorKind, path::Path};
+use std::{
+	io::{Cursor, ErrorKind},
+	path::Path,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+use bytestruct::{ReadFrom, WriteTo};
+use bytestruct_derive::{ByteStruct, Size};
+use control::protocol::{send_request, DEFAULT_REQUEST_TIMEOUT};
+use serde::{Deserialize, Serialize};
 use tokio::{
 	io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
-	net::UnixStream,
+	net::{
+		unix::{OwnedReadHalf, OwnedWriteHalf},
+		UnixStream,
+	},
 };
 
+pub mod filter;
+
 /// The action to subscribe to a topic.
 pub const SUBSCRIBE_ACTION: &str = "subscribe";
 
 /// The action to publish to a topic.
 pub const PUBLISH_ACTION: &str = "publish";
 
+/// The action to register as the service that answers calls on a topic.
+pub const SERVE_ACTION: &str = "serve";
+
+/// The action to make a call to the service on a topic, and wait for its reply.
+pub const CALL_ACTION: &str = "call";
+
+/// The action to list the active topics on the bus.
+pub const TOPICS_ACTION: &str = "topics";
+
+/// The action to trace the messages published to a topic, along with who published them.
+pub const TRACE_ACTION: &str = "trace";
+
+/// The separator between the levels of a hierarchical topic, e.g. `udev/block/sda`.
+pub const TOPIC_SEPARATOR: char = '/';
+
+/// A wildcard in a topic filter that matches exactly one level of a topic.
+pub const SINGLE_LEVEL_WILDCARD: &str = "+";
+
+/// A wildcard at the end of a topic filter that matches any number of levels (including none) of a topic.
+pub const MULTI_LEVEL_WILDCARD: &str = "#";
+
 pub const DEFAULT_BUSD_SOCKET: &str = "/run/busd/control.sock";
 
-const MAX_MESSAGE_LENGTH: usize = u16::MAX as usize;
+/// The default amount of time to wait for a reply to a call.
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default number of messages per second that busd will send to a tracer.
+pub const DEFAULT_TRACE_RATE: u32 = 100;
+
+/// The maximum number of messages per second that busd will send to a tracer. Messages over the rate are dropped, so
+/// that tracing a busy topic can't slow down the bus.
+pub const MAX_TRACE_RATE: u32 = 10000;
+
+/// The maximum number of messages that busd will retain on a single topic.
+pub const MAX_RETAINED_MESSAGES: usize = 4096;
+
+/// The header argument that selects the framing of the messages on a connection. Connections without it use the
+/// legacy framing.
+pub const PROTOCOL_ARG: &str = "protocol";
+
+/// The protocol version that selects chunked framing.
+pub const CHUNKED_PROTOCOL: &str = "2";
+
+/// The maximum length of a message, in bytes.
+pub const MAX_MESSAGE_LENGTH: usize = 16 * 1024 * 1024;
+
+/// The maximum length of a message that can be sent to (or received from) a legacy client, in bytes.
+pub const LEGACY_MAX_MESSAGE_LENGTH: usize = u16::MAX as usize;
+
+/// The maximum length of the data in a single frame, in bytes. Longer messages are split across continuation frames.
+pub const MAX_CHUNK_LENGTH: usize = 64 * 1024;
+
+/// The maximum length of the last will of a publisher, in bytes. Wills are sent in the request that opens the
+/// connection, so are kept well under the size of a request.
+pub const MAX_WILL_LENGTH: usize = 64 * 1024;
+
+/// Set in the flags of a frame if the message continues in the next frame.
+const FRAME_CONTINUES: u8 = 1;
+
+/// Set in the flags of an empty frame sent by a publisher that is disconnecting cleanly, so that busd doesn't publish
+/// its last will.
+const FRAME_DISCONNECT: u8 = 2;
+
+/// The header of a single frame of a message in the chunked framing.
+#[derive(Debug, ByteStruct, Size)]
+#[big_endian]
+struct FrameHeader {
+	flags: u8,
+
+	/// The length of the data in this frame.
+	length: u32,
+}
+
+impl FrameHeader {
+	/// The size of an encoded frame header, in bytes.
+	const SIZE: usize = 5;
+}
+
+/// How messages are framed on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+	/// Every message is prefixed with its length as a big endian u16, as spoken by old clients.
+	Legacy,
+
+	/// Messages are split into frames of up to `MAX_CHUNK_LENGTH` bytes, each prefixed by a `FrameHeader`.
+	Chunked,
+}
+
+impl Framing {
+	/// Gets the framing selected by the given protocol argument, if it is a known protocol.
+	pub fn from_protocol(protocol: Option<&str>) -> Option<Self> {
+		match protocol {
+			None => Some(Self::Legacy),
+			Some(CHUNKED_PROTOCOL) => Some(Self::Chunked),
+			Some(_) => None,
+		}
+	}
+
+	/// The maximum length of a message that can be sent with this framing.
+	pub fn max_message_length(&self) -> usize {
+		match self {
+			Self::Legacy => LEGACY_MAX_MESSAGE_LENGTH,
+			Self::Chunked => MAX_MESSAGE_LENGTH,
+		}
+	}
+}
+
+/// The requests that busd's control socket accepts. Connections opened with a typed request always use the chunked
+/// framing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum BusRequest {
+	/// Subscribes to a topic, which may be a filter containing wildcards, optionally replaying the messages retained
+	/// on the matching topics first. If a `filter` is given (see `filter::MessageFilter`), only the messages that
+	/// match it are delivered.
+	Subscribe {
+		topic: String,
+		#[serde(default)]
+		replay: bool,
+		#[serde(default)]
+		filter: Option<String>,
+	},
+
+	/// Publishes to a topic, retaining the latest `retain` messages for subscribers that connect later. If the
+	/// publisher goes away without disconnecting cleanly, busd publishes its `will` to the topic.
+	Publish {
+		topic: String,
+		#[serde(default)]
+		retain: usize,
+		#[serde(default)]
+		will: Option<Vec<u8>>,
+	},
+
+	/// Registers as the service that answers calls on a topic.
+	Serve { topic: String },
+
+	/// Makes a call to the service on a topic, waiting up to `timeout_ms` (or `DEFAULT_CALL_TIMEOUT`) for its reply.
+	Call {
+		topic: String,
+		#[serde(default)]
+		timeout_ms: Option<u64>,
+	},
+
+	/// Lists the active topics on the bus.
+	Topics,
+
+	/// Traces the messages published to a topic, which may be a filter containing wildcards, sending at most `rate`
+	/// (or `DEFAULT_TRACE_RATE`) messages a second.
+	Trace {
+		topic: String,
+		#[serde(default)]
+		rate: Option<u32>,
+	},
+}
+
+/// The status of a call, sent by busd before the reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CallStatus {
+	/// The service replied, and the reply follows.
+	Ok = 0,
+
+	/// There is no service registered on the topic.
+	NoService = 1,
+
+	/// The service didn't reply within the timeout.
+	Timeout = 2,
+
+	/// The service went away before replying.
+	ServiceGone = 3,
+
+	/// The reply was too large to send to the caller.
+	TooLarge = 4,
+}
+
+impl TryFrom<u8> for CallStatus {
+	type Error = io::Error;
+
+	fn try_from(value: u8) -> Result<Self, Self::Error> {
+		match value {
+			0 => Ok(Self::Ok),
+			1 => Ok(Self::NoService),
+			2 => Ok(Self::Timeout),
+			3 => Ok(Self::ServiceGone),
+			4 => Ok(Self::TooLarge),
+			_ => Err(io::Error::new(
+				ErrorKind::InvalidData,
+				format!("invalid call status: {}", value),
+			)),
+		}
+	}
+}
+
+/// Whether the given topic (or topic filter) contains any wildcards.
+pub fn is_wildcard(topic: &str) -> bool {
+	topic
+		.split(TOPIC_SEPARATOR)
+		.any(|level| level == SINGLE_LEVEL_WILDCARD || level == MULTI_LEVEL_WILDCARD)
+}
+
+/// Whether the given topic filter is valid, i.e. it's not empty, wildcards take up an entire level, and the
+/// multi-level wildcard only appears at the end.
+pub fn is_valid_filter(filter: &str) -> bool {
+	if filter.is_empty() {
+		return false;
+	}
+
+	let levels: Vec<&str> = filter.split(TOPIC_SEPARATOR).collect();
+	levels.iter().enumerate().all(|(i, level)| {
+		if *level == MULTI_LEVEL_WILDCARD {
+			i == levels.len() - 1
+		} else {
+			*level == SINGLE_LEVEL_WILDCARD || !level.contains(['+', '#'])
+		}
+	})
+}
+
+/// Whether the given topic matches the given topic filter.
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+	let mut topic_levels = topic.split(TOPIC_SEPARATOR);
+	for level in filter.split(TOPIC_SEPARATOR) {
+		if level == MULTI_LEVEL_WILDCARD {
+			return true;
+		}
+
+		match topic_levels.next() {
+			Some(topic_level) if level == SINGLE_LEVEL_WILDCARD || level == topic_level => {}
+			_ => return false,
+		}
+	}
+
+	topic_levels.next().is_none()
+}
+
+/// A summary of a topic on the bus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicInfo {
+	/// The name of the topic, which may be a filter with wildcards if the topic only has wildcard subscribers.
+	pub name: String,
+
+	/// The number of subscribers that receive messages published to the topic.
+	pub subscribers: usize,
+
+	/// The number of publishers currently connected to the topic.
+	pub publishers: usize,
+}
+
+impl TopicInfo {
+	/// Encodes the topic info in the form `name subscribers publishers`, as sent by busd.
+	pub fn encode(&self) -> String {
+		format!("{} {} {}", self.name, self.subscribers, self.publishers)
+	}
+
+	/// Decodes topic info in the form sent by busd.
+	pub fn decode(encoded: &str) -> io::Result<Self> {
+		let invalid = || io::Error::new(ErrorKind::InvalidData, format!("invalid topic info: {}", encoded));
+
+		// Topic names can contain spaces, so parse from the end.
+		let mut parts = encoded.rsplitn(3, ' ');
+		let publishers = parts.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+		let subscribers = parts.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+		let name = parts.next().ok_or_else(invalid)?.to_owned();
+
+		Ok(Self {
+			name,
+			subscribers,
+			publishers,
+		})
+	}
+}
+
+/// The metadata of a message seen by a tracer, sent by busd before the message itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+	/// The position of the message in everything published on the bus, so that the order of messages on different
+	/// topics can be told apart.
+	pub sequence: u64,
+
+	/// When the message was published.
+	pub timestamp: SystemTime,
+
+	/// The pid of the publisher, if it's known.
+	pub pid: Option<i32>,
+
+	/// The uid of the publisher.
+	pub uid: u32,
+
+	/// The number of messages that were dropped since the last one sent to the tracer, because they were over the
+	/// tracer's rate, or it wasn't keeping up.
+	pub dropped: u64,
+
+	/// The topic the message was published to.
+	pub topic: String,
+}
+
+impl TraceEvent {
+	/// Encodes the event in the form `sequence timestamp pid uid dropped topic`, as sent by busd. The timestamp is in
+	/// microseconds since the epoch, and the pid is `-` if it isn't known.
+	pub fn encode(&self) -> String {
+		let timestamp = self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+		let pid = self.pid.map_or_else(|| String::from("-"), |pid| pid.to_string());
+		format!(
+			"{} {} {} {} {} {}",
+			self.sequence,
+			timestamp.as_micros(),
+			pid,
+			self.uid,
+			self.dropped,
+			self.topic
+		)
+	}
+
+	/// Decodes an event in the form sent by busd.
+	pub fn decode(encoded: &str) -> io::Result<Self> {
+		let invalid = || io::Error::new(ErrorKind::InvalidData, format!("invalid trace event: {}", encoded));
+
+		// Topic names can contain spaces, so they come last.
+		let mut parts = encoded.splitn(6, ' ');
+		let sequence = parts.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+		let timestamp: u64 = parts.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+		let pid = match parts.next().ok_or_else(invalid)? {
+			"-" => None,
+			pid => Some(pid.parse().map_err(|_| invalid())?),
+		};
+		let uid = parts.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+		let dropped = parts.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+		let topic = parts.next().ok_or_else(invalid)?.to_owned();
+
+		Ok(Self {
+			sequence,
+			timestamp: UNIX_EPOCH + Duration::from_micros(timestamp),
+			pid,
+			uid,
+			dropped,
+			topic,
+		})
+	}
+}
 
 pub struct BusClient {
 	socket: UnixStream,
@@ -32,22 +380,159 @@ impl BusClient {
 		Ok(BusClient { socket: stream })
 	}
 
-	fn assemble_header(action: &str, topic: &str) -> String {
-		format!("ACTION={} topic={}\n", action, topic)
+	/// Sends the request that opens the connection, waiting for busd to accept it.
+	async fn send_request(&mut self, request: BusRequest) -> io::Result<()> {
+		send_request(&mut self.socket, &request, DEFAULT_REQUEST_TIMEOUT).await?;
+		Ok(())
+	}
+
+	/// Makes a call to the service on the given topic, waiting up to `timeout` for a reply.
+	/// Returns a `NotFound` error if there is no service on the topic, and `TimedOut` if it doesn't reply in time.
+	pub async fn call(mut self, topic: &str, payload: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+		self.send_request(BusRequest::Call {
+			topic: topic.to_owned(),
+			timeout_ms: Some(timeout.as_millis() as u64),
+		})
+		.await?;
+		write_message(&mut self.socket, payload, Framing::Chunked).await?;
+
+		let mut reader = BufReader::new(self.socket);
+		match CallStatus::try_from(reader.read_u8().await?)? {
+			CallStatus::Ok => read_message(&mut reader, Framing::Chunked).await,
+			CallStatus::NoService => Err(io::Error::new(
+				ErrorKind::NotFound,
+				format!("no service registered for {}", topic),
+			)),
+			CallStatus::Timeout => Err(io::Error::new(
+				ErrorKind::TimedOut,
+				format!("service for {} didn't reply in time", topic),
+			)),
+			CallStatus::ServiceGone => Err(io::Error::new(
+				ErrorKind::ConnectionAborted,
+				format!("service for {} went away before replying", topic),
+			)),
+			CallStatus::TooLarge => Err(io::Error::new(
+				ErrorKind::InvalidData,
+				format!("reply from the service for {} was too large", topic),
+			)),
+		}
+	}
+
+	/// Registers as the service for the given topic, returning a hook that can be used to answer calls.
+	pub async fn serve(mut self, topic: &str) -> io::Result<ServeHook> {
+		self.send_request(BusRequest::Serve {
+			topic: topic.to_owned(),
+		})
+		.await?;
+
+		let (reader, writer) = self.socket.into_split();
+		Ok(ServeHook {
+			reader: BufReader::new(reader),
+			writer,
+		})
+	}
+
+	/// Lists the active topics on the bus.
+	pub async fn topics(mut self) -> io::Result<Vec<TopicInfo>> {
+		self.send_request(BusRequest::Topics).await?;
+
+		let mut reader = BufReader::new(self.socket);
+		let mut topics = Vec::new();
+		loop {
+			match read_message(&mut reader, Framing::Chunked).await {
+				Ok(message) => topics.push(TopicInfo::decode(&String::from_utf8_lossy(&message))?),
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(topics),
+				Err(e) => return Err(e),
+			}
+		}
+	}
+
+	/// Traces the messages published to the given topic, which may be a filter containing wildcards, receiving at
+	/// most `rate` messages a second.
+	pub async fn trace(mut self, topic: &str, rate: Option<u32>) -> io::Result<TraceHook> {
+		self.send_request(BusRequest::Trace {
+			topic: topic.to_owned(),
+			rate,
+		})
+		.await?;
+
+		Ok(TraceHook(BufReader::new(self.socket)))
+	}
+
+	/// Subscribes to the given topic, which may be a filter containing wildcards, e.g. `udev/+/sda` or `udev/#`.
+	pub async fn subscribe(self, topic: &str) -> io::Result<SubscribeHook<BufReader<UnixStream>>> {
+		self.subscribe_filtered(topic, false, None).await
 	}
 
-	pub async fn subscribe(mut self, topic: &str) -> io::Result<SubscribeHook<impl AsyncRead>> {
-		self.socket
-			.write_all(BusClient::assemble_header(SUBSCRIBE_ACTION, topic).as_bytes())
-			.await?;
+	/// Subscribes to the given topic, first receiving the messages that are retained on it, followed by any new ones.
+	pub async fn subscribe_with_replay(self, topic: &str) -> io::Result<SubscribeHook<BufReader<UnixStream>>> {
+		self.subscribe_filtered(topic, true, None).await
+	}
+
+	/// Subscribes to the given topic, optionally replaying the messages retained on it, and only receiving the
+	/// messages that match the given filter, e.g. `MODALIAS` or `SUBSYSTEM=block ACTION!=remove`. See
+	/// `filter::MessageFilter` for the syntax. busd rejects filters that aren't valid.
+	pub async fn subscribe_filtered(
+		mut self,
+		topic: &str,
+		replay: bool,
+		filter: Option<&str>,
+	) -> io::Result<SubscribeHook<BufReader<UnixStream>>> {
+		self.send_request(BusRequest::Subscribe {
+			topic: topic.to_owned(),
+			replay,
+			filter: filter.map(str::to_owned),
+		})
+		.await?;
 
 		Ok(SubscribeHook(BufReader::new(self.socket)))
 	}
 
-	pub async fn publish(mut self, topic: &str) -> io::Result<PublishHook<impl AsyncWrite>> {
-		self.socket
-			.write_all(BusClient::assemble_header(PUBLISH_ACTION, topic).as_bytes())
-			.await?;
+	pub async fn publish(mut self, topic: &str) -> io::Result<PublishHook<UnixStream>> {
+		self.send_request(BusRequest::Publish {
+			topic: topic.to_owned(),
+			retain: 0,
+			will: None,
+		})
+		.await?;
+
+		Ok(PublishHook(self.socket))
+	}
+
+	/// Publishes to the given topic, with busd retaining the latest `retain` messages on the topic to replay to
+	/// subscribers that connect later. A `retain` of 1 caches only the last value.
+	pub async fn publish_retained(mut self, topic: &str, retain: usize) -> io::Result<PublishHook<UnixStream>> {
+		self.send_request(BusRequest::Publish {
+			topic: topic.to_owned(),
+			retain,
+			will: None,
+		})
+		.await?;
+
+		Ok(PublishHook(self.socket))
+	}
+
+	/// Publishes to the given topic like `publish_retained`, registering a last will that busd publishes to the topic
+	/// if the connection drops without the hook being disconnected, e.g. because the publisher crashed.
+	pub async fn publish_with_will(
+		mut self,
+		topic: &str,
+		retain: usize,
+		will: &[u8],
+	) -> io::Result<PublishHook<UnixStream>> {
+		if will.len() > MAX_WILL_LENGTH {
+			return Err(io::Error::new(
+				ErrorKind::InvalidInput,
+				"will length is greater than maximum length",
+			));
+		}
+
+		self.send_request(BusRequest::Publish {
+			topic: topic.to_owned(),
+			retain,
+			will: Some(will.to_owned()),
+		})
+		.await?;
 
 		Ok(PublishHook(self.socket))
 	}
@@ -57,29 +542,387 @@ pub struct PublishHook<T: AsyncWrite + Unpin>(T);
 
 impl<T: AsyncWrite + Unpin> PublishHook<T> {
 	pub async fn publish_message(&mut self, data: &[u8]) -> io::Result<()> {
-		if data.len() > MAX_MESSAGE_LENGTH {
+		write_message(&mut self.0, data, Framing::Chunked).await
+	}
+
+	/// Publishes everything read from the given reader as a single message, streaming it to busd in chunks rather
+	/// than reading it all into memory first.
+	pub async fn publish_stream<R: AsyncRead + Unpin>(&mut self, reader: &mut R) -> io::Result<()> {
+		write_message_from(&mut self.0, reader).await
+	}
+
+	/// Disconnects cleanly from busd, so that the last will of the publisher (if it has one) isn't published.
+	pub async fn disconnect(mut self) -> io::Result<()> {
+		write_disconnect(&mut self.0).await?;
+		self.0.shutdown().await
+	}
+}
+
+pub struct SubscribeHook<T: AsyncRead + Unpin>(T);
+
+impl<T: AsyncRead + Unpin> SubscribeHook<T> {
+	pub async fn read_message(&mut self) -> io::Result<Vec<u8>> {
+		read_message(&mut self.0, Framing::Chunked).await
+	}
+
+	/// Reads the next message into the given buffer, replacing what was in it. Reusing the same buffer for every
+	/// message saves allocating a new one each time.
+	pub async fn read_message_into(&mut self, buf: &mut Vec<u8>) -> io::Result<()> {
+		read_message_into(&mut self.0, Framing::Chunked, buf).await
+	}
+}
+
+/// A trace of the messages published to a topic.
+pub struct TraceHook(BufReader<UnixStream>);
+
+impl TraceHook {
+	/// Reads the next traced message, along with its metadata.
+	pub async fn read_event(&mut self) -> io::Result<(TraceEvent, Vec<u8>)> {
+		let event = read_message(&mut self.0, Framing::Chunked).await?;
+		let event = TraceEvent::decode(&String::from_utf8_lossy(&event))?;
+		let message = read_message(&mut self.0, Framing::Chunked).await?;
+		Ok((event, message))
+	}
+}
+
+/// A call to a service, received from busd.
+pub struct Request {
+	/// The ID of the request, which must be passed back with the reply.
+	pub id: u64,
+
+	/// The payload of the call.
+	pub payload: Vec<u8>,
+}
+
+/// A registration as the service for a topic, which receives calls and sends replies.
+pub struct ServeHook {
+	reader: BufReader<OwnedReadHalf>,
+	writer: OwnedWriteHalf,
+}
+
+impl ServeHook {
+	/// Reads the next call made to the service.
+	pub async fn read_request(&mut self) -> io::Result<Request> {
+		let id = self.reader.read_u64().await?;
+		let payload = read_message(&mut self.reader, Framing::Chunked).await?;
+
+		Ok(Request { id, payload })
+	}
+
+	/// Replies to the call with the given ID.
+	pub async fn reply(&mut self, id: u64, data: &[u8]) -> io::Result<()> {
+		self.writer.write_u64(id).await?;
+		write_message(&mut self.writer, data, Framing::Chunked).await
+	}
+}
+
+/// Reads a single message from the reader, in the given framing.
+pub async fn read_message<R: AsyncRead + Unpin>(reader: &mut R, framing: Framing) -> io::Result<Vec<u8>> {
+	let mut buf = Vec::new();
+	read_message_into(reader, framing, &mut buf).await?;
+	Ok(buf)
+}
+
+/// Reads a single message from the reader, in the given framing, into the given buffer, replacing what was in it.
+pub async fn read_message_into<R: AsyncRead + Unpin>(
+	reader: &mut R,
+	framing: Framing,
+	buf: &mut Vec<u8>,
+) -> io::Result<()> {
+	if read_publication_into(reader, framing, buf).await? {
+		Ok(())
+	} else {
+		Err(io::Error::new(ErrorKind::InvalidData, "unexpected disconnect frame"))
+	}
+}
+
+/// Reads a single message from a publisher, in the given framing. Returns `None` if the publisher disconnected
+/// cleanly, which is only possible in the chunked framing.
+pub async fn read_publication<R: AsyncRead + Unpin>(reader: &mut R, framing: Framing) -> io::Result<Option<Vec<u8>>> {
+	let mut buf = Vec::new();
+	match read_publication_into(reader, framing, &mut buf).await? {
+		true => Ok(Some(buf)),
+		false => Ok(None),
+	}
+}
+
+/// Reads a single message from a publisher, in the given framing, into the given buffer, replacing what was in it.
+/// Returns false if the publisher disconnected cleanly instead, which is only possible in the chunked framing.
+pub async fn read_publication_into<R: AsyncRead + Unpin>(
+	reader: &mut R,
+	framing: Framing,
+	buf: &mut Vec<u8>,
+) -> io::Result<bool> {
+	buf.clear();
+	if framing == Framing::Legacy {
+		let len = reader.read_u16().await? as usize;
+		buf.resize(len, 0);
+		reader.read_exact(buf).await?;
+
+		return Ok(true);
+	}
+
+	loop {
+		let mut header = [0; FrameHeader::SIZE];
+		reader.read_exact(&mut header).await?;
+		let header = FrameHeader::read_from(&mut Cursor::new(header))?;
+		if header.flags & FRAME_DISCONNECT != 0 {
+			if header.length != 0 || !buf.is_empty() {
+				return Err(io::Error::new(ErrorKind::InvalidData, "invalid disconnect frame"));
+			}
+
+			return Ok(false);
+		}
+
+		let len = header.length as usize;
+		if len > MAX_CHUNK_LENGTH || buf.len() + len > MAX_MESSAGE_LENGTH {
 			return Err(io::Error::new(
 				ErrorKind::InvalidData,
 				"data length is greater than maximum length",
 			));
 		}
 
-		self.0.write_u16(data.len() as u16).await?;
-		self.0.write_all(data).await?;
-		self.0.flush().await?;
+		let start = buf.len();
+		buf.resize(start + len, 0);
+		reader.read_exact(&mut buf[start..]).await?;
 
-		Ok(())
+		if header.flags & FRAME_CONTINUES == 0 {
+			return Ok(true);
+		}
 	}
 }
 
-pub struct SubscribeHook<T: AsyncRead + Unpin>(T);
+/// Writes a single frame of a chunked message to the writer.
+async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, data: &[u8], continues: bool) -> io::Result<()> {
+	let flags = if continues { FRAME_CONTINUES } else { 0 };
+	write_header(writer, flags, data.len()).await?;
+	writer.write_all(data).await
+}
 
-impl<T: AsyncRead + Unpin> SubscribeHook<T> {
-	pub async fn read_message(&mut self) -> io::Result<Vec<u8>> {
-		let len = self.0.read_u16().await? as usize;
-		let mut buf = vec![0; len];
-		self.0.read_exact(&mut buf).await?;
+async fn write_header<W: AsyncWrite + Unpin>(writer: &mut W, flags: u8, length: usize) -> io::Result<()> {
+	let header = FrameHeader {
+		flags,
+		length: length as u32,
+	};
+
+	let mut encoded = Vec::with_capacity(FrameHeader::SIZE);
+	header.write_to(&mut encoded)?;
+	writer.write_all(&encoded).await
+}
+
+/// Writes the frame that tells busd that a publisher is disconnecting cleanly.
+pub async fn write_disconnect<W: AsyncWrite + Unpin>(writer: &mut W) -> io::Result<()> {
+	write_header(writer, FRAME_DISCONNECT, 0).await?;
+	writer.flush().await
+}
+
+/// Writes a single message to the writer, in the given framing.
+pub async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, data: &[u8], framing: Framing) -> io::Result<()> {
+	if data.len() > framing.max_message_length() {
+		return Err(io::Error::new(
+			ErrorKind::InvalidData,
+			"data length is greater than maximum length",
+		));
+	}
+
+	match framing {
+		Framing::Legacy => {
+			writer.write_u16(data.len() as u16).await?;
+			writer.write_all(data).await?;
+		}
+		Framing::Chunked if data.is_empty() => write_frame(writer, data, false).await?,
+		Framing::Chunked => {
+			let mut chunks = data.chunks(MAX_CHUNK_LENGTH).peekable();
+			while let Some(chunk) = chunks.next() {
+				write_frame(writer, chunk, chunks.peek().is_some()).await?;
+			}
+		}
+	}
+
+	writer.flush().await
+}
+
+/// Writes everything read from the reader to the writer as a single chunked message, without knowing its length
+/// up front. Each read is sent as a continuation frame, with an empty frame marking the end of the message.
+pub async fn write_message_from<W: AsyncWrite + Unpin, R: AsyncRead + Unpin>(
+	writer: &mut W,
+	reader: &mut R,
+) -> io::Result<()> {
+	let mut buf = vec![0; MAX_CHUNK_LENGTH];
+	let mut total = 0;
+	loop {
+		let len = reader.read(&mut buf).await?;
+		if len == 0 {
+			write_frame(writer, &[], false).await?;
+			return writer.flush().await;
+		}
+
+		total += len;
+		if total > MAX_MESSAGE_LENGTH {
+			return Err(io::Error::new(
+				ErrorKind::InvalidData,
+				"data length is greater than maximum length",
+			));
+		}
+
+		write_frame(writer, &buf[..len], true).await?;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{
+		io::Cursor,
+		time::{Duration, UNIX_EPOCH},
+	};
+
+	use super::{
+		is_valid_filter, is_wildcard, read_message, read_message_into, read_publication, topic_matches,
+		write_disconnect, write_message, write_message_from, Framing, TopicInfo, TraceEvent, LEGACY_MAX_MESSAGE_LENGTH,
+		MAX_CHUNK_LENGTH,
+	};
+
+	#[test]
+	fn test_topic_matches() {
+		assert!(topic_matches("udev/block/sda", "udev/block/sda"));
+		assert!(!topic_matches("udev/block/sda", "udev/block/sdb"));
+		assert!(!topic_matches("udev/block", "udev/block/sda"));
+		assert!(topic_matches("udev/+/sda", "udev/block/sda"));
+		assert!(!topic_matches("udev/+/sda", "udev/sda"));
+		assert!(topic_matches("udev/#", "udev/block/sda"));
+		assert!(topic_matches("udev/#", "udev"));
+		assert!(topic_matches("#", "udev_events"));
+		assert!(!topic_matches("udev/#", "net/eth0"));
+	}
+
+	#[test]
+	fn test_valid_filters() {
+		assert!(is_valid_filter("udev/+/sda"));
+		assert!(is_valid_filter("udev/#"));
+		assert!(!is_valid_filter("udev/#/sda"));
+		assert!(!is_valid_filter("udev/sd+"));
+		assert!(!is_valid_filter(""));
+
+		assert!(is_wildcard("udev/#"));
+		assert!(!is_wildcard("udev/sda#1"));
+	}
+
+	#[test]
+	fn test_topic_info() {
+		let info = TopicInfo {
+			name: String::from("udev/block"),
+			subscribers: 2,
+			publishers: 1,
+		};
+
+		assert_eq!(TopicInfo::decode(&info.encode()).unwrap(), info);
+		assert!(TopicInfo::decode("udev/block 2").is_err());
+
+		let info = TopicInfo {
+			name: String::from("has spaces"),
+			subscribers: 0,
+			publishers: 3,
+		};
+
+		assert_eq!(TopicInfo::decode(&info.encode()).unwrap(), info);
+	}
+
+	#[test]
+	fn test_trace_event() {
+		let event = TraceEvent {
+			sequence: 42,
+			timestamp: UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456),
+			pid: Some(1234),
+			uid: 0,
+			dropped: 3,
+			topic: String::from("udev/block sda"),
+		};
+
+		assert_eq!(event.encode(), "42 1700000000123456 1234 0 3 udev/block sda");
+		assert_eq!(TraceEvent::decode(&event.encode()).unwrap(), event);
+
+		let event = TraceEvent { pid: None, ..event };
+		assert_eq!(TraceEvent::decode(&event.encode()).unwrap(), event);
+
+		assert!(TraceEvent::decode("42 1700000000123456 1234 0 3").is_err());
+		assert!(TraceEvent::decode("42 1700000000123456 pid 0 3 udev").is_err());
+	}
+
+	#[tokio::test]
+	async fn test_chunked_framing() {
+		let message: Vec<u8> = (0..MAX_CHUNK_LENGTH * 2 + 10).map(|i| i as u8).collect();
+		let mut encoded = Vec::new();
+		write_message(&mut encoded, &message, Framing::Chunked).await.unwrap();
+		write_message(&mut encoded, b"", Framing::Chunked).await.unwrap();
+		write_message_from(&mut encoded, &mut Cursor::new(b"streamed".to_vec()))
+			.await
+			.unwrap();
+
+		let mut reader = Cursor::new(encoded);
+		assert_eq!(read_message(&mut reader, Framing::Chunked).await.unwrap(), message);
+		assert_eq!(read_message(&mut reader, Framing::Chunked).await.unwrap(), b"");
+		assert_eq!(read_message(&mut reader, Framing::Chunked).await.unwrap(), b"streamed");
+	}
+
+	#[tokio::test]
+	async fn test_read_message_into() {
+		let mut encoded = Vec::new();
+		write_message(&mut encoded, b"a longer message", Framing::Chunked)
+			.await
+			.unwrap();
+		write_message(&mut encoded, b"short", Framing::Chunked).await.unwrap();
+		write_message(&mut encoded, b"legacy", Framing::Legacy).await.unwrap();
+
+		// The buffer is replaced by each message, keeping the space it already has.
+		let mut reader = Cursor::new(encoded);
+		let mut buf = Vec::new();
+		read_message_into(&mut reader, Framing::Chunked, &mut buf)
+			.await
+			.unwrap();
+		assert_eq!(buf, b"a longer message");
+		let capacity = buf.capacity();
+
+		read_message_into(&mut reader, Framing::Chunked, &mut buf)
+			.await
+			.unwrap();
+		assert_eq!(buf, b"short");
+		assert_eq!(buf.capacity(), capacity);
+
+		read_message_into(&mut reader, Framing::Legacy, &mut buf).await.unwrap();
+		assert_eq!(buf, b"legacy");
+		assert_eq!(buf.capacity(), capacity);
+	}
+
+	#[tokio::test]
+	async fn test_disconnect_frame() {
+		let mut encoded = Vec::new();
+		write_message(&mut encoded, b"last", Framing::Chunked).await.unwrap();
+		write_disconnect(&mut encoded).await.unwrap();
+		write_disconnect(&mut encoded).await.unwrap();
+
+		let mut reader = Cursor::new(encoded);
+		assert_eq!(
+			read_publication(&mut reader, Framing::Chunked).await.unwrap(),
+			Some(b"last".to_vec())
+		);
+		assert_eq!(read_publication(&mut reader, Framing::Chunked).await.unwrap(), None);
+
+		// Only publishers disconnect, so anything else reading one is an error.
+		assert!(read_message(&mut reader, Framing::Chunked).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_legacy_framing() {
+		let mut encoded = Vec::new();
+		write_message(&mut encoded, b"hello", Framing::Legacy).await.unwrap();
+		assert_eq!(encoded, b"\x00\x05hello");
 
-		Ok(buf)
+		let too_long = vec![0; LEGACY_MAX_MESSAGE_LENGTH + 1];
+		assert!(write_message(&mut Vec::new(), &too_long, Framing::Legacy)
+			.await
+			.is_err());
+		assert!(write_message(&mut Vec::new(), &too_long, Framing::Chunked)
+			.await
+			.is_ok());
 	}
 }
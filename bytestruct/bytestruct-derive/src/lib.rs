@@ -1,12 +1,291 @@
 extern crate proc_macro2;
 
-use std::str::FromStr;
+use std::{ptr, str::FromStr};
 
-use proc_macro2::TokenStream;
+use proc_macro2::{Ident, TokenStream};
 use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Expr};
+use syn::{parse_macro_input, Data, DataEnum, DeriveInput, Expr, Fields, GenericArgument, LitStr, PathArguments, Type};
 
-#[proc_macro_derive(ByteStruct, attributes(big_endian, little_endian, ty))]
+/// How an `Option<T>` field decides whether it's present, from a field that comes before it.
+enum Presence {
+	/// `#[present_if(field = "flags", bit = 0x1)]`: the field is present if any of the given bits are set.
+	Bit(Ident, Expr),
+
+	/// `#[present_if(field = "length", min = 16)]`: the field is present if the given field (e.g. the length of the
+	/// struct, or its version) is at least the given value.
+	Min(Ident, Expr),
+}
+
+impl Presence {
+	/// Parses the `#[present_if]` attribute of a field, if it has one.
+	fn from_attrs(attrs: &[syn::Attribute]) -> Option<Self> {
+		let attr = attrs.iter().find(|attr| attr.path().is_ident("present_if"))?;
+		let (mut field, mut bit, mut min) = (None, None, None);
+		attr.parse_nested_meta(|meta| {
+			if meta.path.is_ident("field") {
+				let name: LitStr = meta.value()?.parse()?;
+				field = Some(Ident::new(&name.value(), name.span()));
+			} else if meta.path.is_ident("bit") {
+				bit = Some(meta.value()?.parse::<Expr>()?);
+			} else if meta.path.is_ident("min") {
+				min = Some(meta.value()?.parse::<Expr>()?);
+			} else {
+				return Err(meta.error("expected `field`, `bit`, or `min`"));
+			}
+
+			Ok(())
+		})
+		.unwrap_or_else(|e| panic!("invalid #[present_if]: {}", e));
+
+		let field = field.expect("#[present_if] requires a `field`");
+		match (bit, min) {
+			(Some(bit), None) => Some(Presence::Bit(field, bit)),
+			(None, Some(min)) => Some(Presence::Min(field, min)),
+			_ => panic!("#[present_if] requires exactly one of `bit` or `min`"),
+		}
+	}
+
+	fn field(&self) -> &Ident {
+		match self {
+			Presence::Bit(field, _) | Presence::Min(field, _) => field,
+		}
+	}
+
+	/// The condition for the field to be present, given an expression for the value of the field it depends on.
+	fn condition(&self, value: TokenStream) -> TokenStream {
+		match self {
+			Presence::Bit(_, bit) => quote! { (#value & (#bit)) != 0 },
+			Presence::Min(_, min) => quote! { #value >= (#min) },
+		}
+	}
+}
+
+/// Where the number of items in a length or count prefixed collection comes from.
+enum Prefix {
+	/// `#[len(u16)]`: the collection is prefixed with the given integer type.
+	Type(Type),
+
+	/// `#[len(field = "length")]`: a field before the collection says how long it is.
+	Field(Ident),
+}
+
+impl Prefix {
+	fn parse(attr: &syn::Attribute) -> Self {
+		if let Ok(ty) = attr.parse_args::<Type>() {
+			return Prefix::Type(ty);
+		}
+
+		let mut field = None;
+		attr.parse_nested_meta(|meta| {
+			if meta.path.is_ident("field") {
+				let name: LitStr = meta.value()?.parse()?;
+				field = Some(Ident::new(&name.value(), name.span()));
+				Ok(())
+			} else {
+				Err(meta.error("expected an integer type or `field`"))
+			}
+		})
+		.unwrap_or_else(|e| panic!("invalid #[len] or #[count]: {}", e));
+
+		Prefix::Field(field.expect("#[len] and #[count] require an integer type or a `field`"))
+	}
+}
+
+/// How a `Vec<T>` field is laid out, if it isn't the default of a u64 count followed by the items.
+enum Collection {
+	/// `#[len(...)]`: the number of bytes the items take up.
+	Len(Prefix),
+
+	/// `#[count(...)]`: the number of items.
+	Count(Prefix),
+
+	/// `#[rest]`: the items take up the rest of the bytes.
+	Rest,
+}
+
+impl Collection {
+	/// Parses the `#[len]`, `#[count]`, or `#[rest]` attribute of a field, if it has one.
+	fn from_attrs(attrs: &[syn::Attribute]) -> Option<Self> {
+		let mut collections = attrs.iter().filter_map(|attr| {
+			if attr.path().is_ident("len") {
+				Some(Collection::Len(Prefix::parse(attr)))
+			} else if attr.path().is_ident("count") {
+				Some(Collection::Count(Prefix::parse(attr)))
+			} else if attr.path().is_ident("rest") {
+				Some(Collection::Rest)
+			} else {
+				None
+			}
+		});
+
+		let collection = collections.next();
+		if collections.next().is_some() {
+			panic!("only one of #[len], #[count], or #[rest] can be used on a field");
+		}
+
+		collection
+	}
+
+	/// The field this collection depends on, if it depends on one.
+	fn field(&self) -> Option<&Ident> {
+		match self {
+			Collection::Len(Prefix::Field(field)) | Collection::Count(Prefix::Field(field)) => Some(field),
+			_ => None,
+		}
+	}
+
+	fn read(&self, inner: &Type, endian: &TokenStream) -> TokenStream {
+		match self {
+			Collection::Len(Prefix::Type(prefix)) => quote! {{
+				let len = ::bytestruct::read_prefix::<#prefix, _>(source, #endian)?;
+				::bytestruct::read_items_in::<#inner, _>(source, len, #endian)?
+			}},
+			Collection::Len(Prefix::Field(field)) => quote! {
+				::bytestruct::read_items_in::<#inner, _>(source, #field as usize, #endian)?
+			},
+			Collection::Count(Prefix::Type(prefix)) => quote! {{
+				let count = ::bytestruct::read_prefix::<#prefix, _>(source, #endian)?;
+				::bytestruct::read_items::<#inner, _>(source, count, #endian)?
+			}},
+			Collection::Count(Prefix::Field(field)) => quote! {
+				::bytestruct::read_items::<#inner, _>(source, #field as usize, #endian)?
+			},
+			Collection::Rest => quote! {
+				::bytestruct::read_remaining_items::<#inner, _>(source, #endian)?
+			},
+		}
+	}
+
+	fn write(&self, name: &Ident, endian: &TokenStream) -> TokenStream {
+		match self {
+			Collection::Len(prefix) => {
+				let write_len = match prefix {
+					Prefix::Type(prefix) => quote! {
+						::bytestruct::write_prefix::<#prefix, _>(bytes.len(), writer, #endian)?;
+					},
+					Prefix::Field(field) => {
+						let mismatch = format!("{} must be as long as {} says it is", name, field);
+						quote! {
+							if self.#field as usize != bytes.len() {
+								return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData, #mismatch));
+							}
+						}
+					}
+				};
+
+				quote! {{
+					let mut bytes = Vec::new();
+					::bytestruct::write_items(&self.#name, &mut bytes, #endian)?;
+					#write_len
+					::std::io::Write::write_all(writer, &bytes)?;
+				}}
+			}
+			Collection::Count(prefix) => {
+				let write_count = match prefix {
+					Prefix::Type(prefix) => quote! {
+						::bytestruct::write_prefix::<#prefix, _>(self.#name.len(), writer, #endian)?;
+					},
+					Prefix::Field(field) => {
+						let mismatch = format!("{} must have as many items as {} says it does", name, field);
+						quote! {
+							if self.#field as usize != self.#name.len() {
+								return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData, #mismatch));
+							}
+						}
+					}
+				};
+
+				quote! {
+					#write_count
+					::bytestruct::write_items(&self.#name, writer, #endian)?;
+				}
+			}
+			Collection::Rest => quote! {
+				::bytestruct::write_items(&self.#name, writer, #endian)?;
+			},
+		}
+	}
+
+	/// Parses the collection from the start of `bytes` (see `bytestruct::CollectionFromBytes`), as an expression for a
+	/// tuple of it and the bytes after it.
+	fn parse_bytes(&self, ty: &Type, lifetime: &TokenStream, endian: &TokenStream) -> TokenStream {
+		let collection = quote! { <#ty as ::bytestruct::CollectionFromBytes<#lifetime>> };
+		let count = match self {
+			Collection::Len(Prefix::Type(prefix)) | Collection::Count(Prefix::Type(prefix)) => quote! {
+				let (count, bytes) = ::bytestruct::prefix_from_bytes::<#prefix>(bytes, #endian)?;
+			},
+			Collection::Len(Prefix::Field(field)) | Collection::Count(Prefix::Field(field)) => quote! {
+				let count = #field as usize;
+			},
+			Collection::Rest => quote! {
+				let count = bytes.len();
+			},
+		};
+
+		match self {
+			Collection::Count(_) => quote! {{
+				#count
+				#collection::from_bytes_counted(bytes, count, #endian)?
+			}},
+			Collection::Len(_) | Collection::Rest => quote! {{
+				#count
+				let (items, bytes) = ::bytestruct::split_bytes(bytes, count)?;
+				(#collection::from_all_bytes(items, #endian)?, bytes)
+			}},
+		}
+	}
+
+	/// The size of the collection, including its prefix if it has one, given an expression for its value.
+	fn size(&self, ty: &Type, value: TokenStream) -> TokenStream {
+		match self {
+			Collection::Len(Prefix::Type(prefix)) | Collection::Count(Prefix::Type(prefix)) => quote! {
+				(<#prefix as ::bytestruct::Size>::size(&0) + <#ty as ::bytestruct::Size>::size(&#value))
+			},
+			_ => quote! { <#ty as ::bytestruct::Size>::size(&#value) },
+		}
+	}
+}
+
+/// The `T` of an `Option<T>` or `Vec<T>` (or any other single parameter `wrapper`), if the type is one.
+fn wrapped_type<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+	let Type::Path(path) = ty else {
+		return None;
+	};
+
+	let segment = path.path.segments.last()?;
+	if segment.ident != wrapper {
+		return None;
+	}
+
+	match &segment.arguments {
+		PathArguments::AngleBracketed(args) => match args.args.first()? {
+			GenericArgument::Type(inner) => Some(inner),
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+/// Derives reading and writing a struct as its fields, one after the other, or an enum as its discriminant.
+///
+/// `Option<T>` fields are only in the bytes if a field before them says they are, as given by a
+/// `#[present_if(field = "flags", bit = 0x1)]` (present if the bit is set) or
+/// `#[present_if(field = "length", min = 16)]` (present if the field is at least 16) attribute. Writing a struct
+/// fails if whether the field is `Some` doesn't agree with the field it depends on.
+///
+/// `Vec<T>` fields are a u64 count followed by the items, unless they're given one of:
+///  - `#[len(u16)]` or `#[len(field = "length")]`: the items take up the number of bytes in a prefix of the given type,
+///    or in a field before them.
+///  - `#[count(u16)]` or `#[count(field = "n_entries")]`: the number of items is in a prefix of the given type, or in a
+///    field before them.
+///  - `#[rest]`: the items take up the rest of the bytes. This can only be used on the last field.
+///
+/// Enums fail to read discriminants that aren't one of their variants', unless they have a `#[fallback]` variant that
+/// holds the discriminant (e.g. `Unknown(u8)`), which any other discriminant is read as.
+#[proc_macro_derive(
+	ByteStruct,
+	attributes(big_endian, little_endian, ty, present_if, len, count, rest, fallback)
+)]
 pub fn derive_byte_struct(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 	let input = parse_macro_input!(input as DeriveInput);
 
@@ -23,13 +302,82 @@ pub fn derive_byte_struct(input: proc_macro::TokenStream) -> proc_macro::TokenSt
 		let mut set_endian_fields = Vec::new();
 		let mut write_fields = Vec::new();
 		let mut prev_fields = Vec::new();
+		let mut read_names = Vec::new();
 		for field in data.fields.iter() {
 			let name = field.ident.as_ref().unwrap();
 			let ty = &field.ty;
 
 			let type_name = quote! { #ty }.to_string();
 
-			let read_field = if type_name.starts_with("Padding <") || type_name.starts_with("bytestruct::Padding <") {
+			let endian = if little_endian {
+				quote! { ::bytestruct::Endian::Little }
+			} else if big_endian {
+				quote! { ::bytestruct::Endian::Big }
+			} else {
+				quote! { endian }
+			};
+
+			let presence = Presence::from_attrs(&field.attrs);
+			let inner = wrapped_type(ty, "Option");
+			if presence.is_some() && inner.is_none() {
+				panic!("#[present_if] can only be used on Option fields, not {}", name);
+			}
+
+			let collection = Collection::from_attrs(&field.attrs);
+			if let Some(collection) = &collection {
+				if let Some(field) = collection.field() {
+					if !read_names.contains(field) {
+						panic!("{} can only get its length from a field before it, not {}", name, field);
+					}
+				}
+
+				if matches!(collection, Collection::Rest) && !ptr::eq(field, data.fields.iter().next_back().unwrap()) {
+					panic!("#[rest] can only be used on the last field, not {}", name);
+				}
+			}
+
+			let read_field = if let Some(collection) = &collection {
+				let items = wrapped_type(ty, "Vec").unwrap_or_else(|| {
+					panic!(
+						"#[len], #[count], and #[rest] can only be used on Vec fields, not {}",
+						name
+					)
+				});
+				write_fields.push(collection.write(name, &endian));
+
+				let read = collection.read(items, &endian);
+				quote! {
+					let #name = #read;
+				}
+			} else if let Some(inner) = inner {
+				let presence = presence.unwrap_or_else(|| panic!("Option field {} requires a #[present_if]", name));
+				let field = presence.field();
+				if !read_names.contains(field) {
+					panic!(
+						"{} can only be present depending on a field before it, not {}",
+						name, field
+					);
+				}
+
+				let read_condition = presence.condition(quote! { #field });
+				let write_condition = presence.condition(quote! { self.#field });
+				let mismatch = format!("{} must be present exactly when {} says it is", name, field);
+				write_fields.push(quote! {
+					match (&self.#name, #write_condition) {
+						(Some(value), true) => <#inner as ::bytestruct::WriteToWithEndian>::write_to_with_endian(value, writer, #endian)?,
+						(None, false) => {}
+						_ => return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData, #mismatch)),
+					}
+				});
+
+				quote! {
+					let #name = if #read_condition {
+						Some(<#inner as ::bytestruct::ReadFromWithEndian>::read_from_with_endian(source, #endian)?)
+					} else {
+						None
+					};
+				}
+			} else if type_name.starts_with("Padding <") || type_name.starts_with("bytestruct::Padding <") {
 				let out = quote! {
 					let #name = ::bytestruct::Padding::read(0 #(+ #prev_fields)*, source)?;
 				};
@@ -50,24 +398,19 @@ pub fn derive_byte_struct(input: proc_macro::TokenStream) -> proc_macro::TokenSt
 				}
 			};
 
-			let write_field = if little_endian {
-				quote! {
-					<#ty as ::bytestruct::WriteToWithEndian>::write_to_with_endian(&self.#name, writer, ::bytestruct::Endian::Little)?;
-				}
-			} else if big_endian {
-				quote! {
-					<#ty as ::bytestruct::WriteToWithEndian>::write_to_with_endian(&self.#name, writer, ::bytestruct::Endian::Big)?;
-				}
-			} else {
-				quote! {
-					<#ty as ::bytestruct::WriteToWithEndian>::write_to_with_endian(&self.#name, writer, endian)?;
-				}
-			};
+			if inner.is_none() && collection.is_none() {
+				write_fields.push(quote! {
+					<#ty as ::bytestruct::WriteToWithEndian>::write_to_with_endian(&self.#name, writer, #endian)?;
+				});
+			}
 
-			prev_fields.push(quote! {<#ty as ::bytestruct::Size>::size(&#name)});
+			prev_fields.push(match &collection {
+				Some(collection) => collection.size(ty, quote! { #name }),
+				None => quote! {<#ty as ::bytestruct::Size>::size(&#name)},
+			});
 
 			set_endian_fields.push(read_field);
-			write_fields.push(write_field);
+			read_names.push(name.clone());
 		}
 
 		let names = data.fields.iter().map(|field| {
@@ -137,27 +480,19 @@ pub fn derive_byte_struct(input: proc_macro::TokenStream) -> proc_macro::TokenSt
 
 		gen.into()
 	} else if let Data::Enum(data) = &input.data {
-		let mut read_matches = Vec::new();
-		let mut write_matches = Vec::new();
-
 		let ty = get_repr(&input.attrs);
-
-		for (i, variant) in data.variants.iter().enumerate() {
-			let ident = &variant.ident;
-			let discriminant = if let Some((_, v)) = &variant.discriminant {
-				quote! {#v}
-			} else {
-				TokenStream::from_str(&i.to_string()).unwrap()
-			};
-
-			read_matches.push(quote! {
-				#discriminant => #name::#ident,
-			});
-
-			write_matches.push(quote! {
-				#name::#ident => #discriminant,
-			});
-		}
+		let EnumArms {
+			read: read_matches,
+			write: write_matches,
+			fallback,
+		} = enum_arms(&name, data);
+
+		let unknown = match fallback {
+			Some(fallback) => quote! { discriminant => #name::#fallback(discriminant), },
+			None => quote! {
+				_ => return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData, format!("invalid discriminant for {}: {}", ::std::any::type_name::<#name>(), discriminant)))
+			},
+		};
 
 		quote! {
 			impl ::bytestruct::ReadFromWithEndian for #name {
@@ -165,7 +500,7 @@ pub fn derive_byte_struct(input: proc_macro::TokenStream) -> proc_macro::TokenSt
 					let discriminant = <#ty as ::bytestruct::ReadFromWithEndian>::read_from_with_endian(source, endian)?;
 					let variant = match discriminant {
 						#(#read_matches)*
-						_ => return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData, format!("invalid discriminant for {}: {}", ::std::any::type_name::<#name>(), discriminant)))
+						#unknown
 					};
 
 					Ok(variant)
@@ -188,7 +523,178 @@ pub fn derive_byte_struct(input: proc_macro::TokenStream) -> proc_macro::TokenSt
 	}
 }
 
-#[proc_macro_derive(Size)]
+/// The endianness that a type is read with, from its `#[big_endian]` or `#[little_endian]` attribute, falling back to
+/// an `endian` variable.
+fn endian_of(attrs: &[syn::Attribute]) -> TokenStream {
+	let little_endian = attrs.iter().any(|attr| attr.path().is_ident("little_endian"));
+	let big_endian = attrs.iter().any(|attr| attr.path().is_ident("big_endian"));
+	match (little_endian, big_endian) {
+		(true, true) => panic!("Only one of little_endian or big_endian can be specified"),
+		(true, false) => quote! { ::bytestruct::Endian::Little },
+		(false, true) => quote! { ::bytestruct::Endian::Big },
+		(false, false) => quote! { endian },
+	}
+}
+
+/// Derives parsing a struct from a byte slice as its fields, one after the other, or an enum as its discriminant,
+/// without copying anything (see `bytestruct::FromBytes`).
+///
+/// Fields are laid out as they are for `ByteStruct`, with the same attributes, except that `#[len]`, `#[count]`, and
+/// `#[rest]` can also be used on `&'a [u8]` and `&'a str` fields, which borrow from the bytes. The lifetime of the
+/// bytes is the one the struct has, if it has one.
+#[proc_macro_derive(
+	FromBytes,
+	attributes(big_endian, little_endian, present_if, len, count, rest, fallback)
+)]
+pub fn derive_from_bytes(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	let name = &input.ident;
+	let endian = endian_of(&input.attrs);
+
+	let lifetimes = input.generics.lifetimes().collect::<Vec<_>>();
+	let (lifetime, struct_lifetime) = match lifetimes.as_slice() {
+		[] => (quote! {'a}, None),
+		[lifetime] => {
+			let lifetime = &lifetime.lifetime;
+			(quote! {#lifetime}, Some(quote! {#lifetime}))
+		}
+		_ => panic!("FromBytes can only be derived for types with at most one lifetime"),
+	};
+
+	// Type parameters keep the bounds they're declared with, and each field is bounded by what it's parsed with, so
+	// that fields can use the parameters in any way (e.g. `T::MessageType`).
+	let type_params = input.generics.type_params().collect::<Vec<_>>();
+	let type_generics = struct_lifetime
+		.into_iter()
+		.chain(type_params.iter().map(|param| {
+			let ident = &param.ident;
+			quote! {#ident}
+		}))
+		.collect::<Vec<_>>();
+	let mut bounds = input
+		.generics
+		.where_clause
+		.iter()
+		.flat_map(|clause| clause.predicates.iter())
+		.map(|predicate| quote! {#predicate})
+		.collect::<Vec<_>>();
+
+	let body = match &input.data {
+		Data::Struct(data) => {
+			let mut read_fields = Vec::new();
+			let mut read_names = Vec::new();
+			for field in data.fields.iter() {
+				let name = field.ident.as_ref().unwrap();
+				let ty = &field.ty;
+				let type_name = quote! { #ty }.to_string();
+
+				let presence = Presence::from_attrs(&field.attrs);
+				let inner = wrapped_type(ty, "Option");
+				if presence.is_some() && inner.is_none() {
+					panic!("#[present_if] can only be used on Option fields, not {}", name);
+				}
+
+				let collection = Collection::from_attrs(&field.attrs);
+				let depends_on = presence
+					.as_ref()
+					.map(Presence::field)
+					.or(collection.as_ref().and_then(Collection::field));
+				if let Some(field) = depends_on {
+					if !read_names.contains(field) {
+						panic!("{} can only depend on a field before it, not {}", name, field);
+					}
+				}
+
+				let read = if let Some(collection) = &collection {
+					if matches!(collection, Collection::Rest)
+						&& !ptr::eq(field, data.fields.iter().next_back().unwrap())
+					{
+						panic!("#[rest] can only be used on the last field, not {}", name);
+					}
+
+					bounds.push(quote! { #ty: ::bytestruct::CollectionFromBytes<#lifetime> });
+					collection.parse_bytes(ty, &lifetime, &endian)
+				} else if let Some(inner) = inner {
+					bounds.push(quote! { #inner: ::bytestruct::FromBytes<#lifetime> });
+					let presence = presence.unwrap_or_else(|| panic!("Option field {} requires a #[present_if]", name));
+					let field = presence.field();
+					let condition = presence.condition(quote! { #field });
+					quote! {
+						if #condition {
+							let (value, bytes) = <#inner as ::bytestruct::FromBytes<#lifetime>>::from_bytes(bytes, #endian)?;
+							(Some(value), bytes)
+						} else {
+							(None, bytes)
+						}
+					}
+				} else if type_name.starts_with("Padding <") || type_name.starts_with("bytestruct::Padding <") {
+					// Padding only pads the fields since the last padding, so start counting again after it.
+					read_fields.push(quote! {
+						let (#name, bytes) = <#ty>::from_bytes(segment.len() - bytes.len(), bytes)?;
+						let segment = bytes;
+					});
+					read_names.push(name.clone());
+					continue;
+				} else {
+					bounds.push(quote! { #ty: ::bytestruct::FromBytes<#lifetime> });
+					quote! { <#ty as ::bytestruct::FromBytes<#lifetime>>::from_bytes(bytes, #endian)? }
+				};
+
+				read_fields.push(quote! {
+					let (#name, bytes) = #read;
+				});
+				read_names.push(name.clone());
+			}
+
+			quote! {
+				let segment = bytes;
+				#(#read_fields)*
+				Ok((Self { #(#read_names),* }, bytes))
+			}
+		}
+		Data::Enum(data) => {
+			let ty = get_repr(&input.attrs);
+			let EnumArms {
+				read: variants,
+				fallback,
+				..
+			} = enum_arms(name, data);
+
+			let unknown = match fallback {
+				Some(fallback) => quote! { discriminant => #name::#fallback(discriminant), },
+				None => quote! {
+					_ => return Err(::bytestruct::FromBytesError::Invalid(concat!("invalid discriminant for ", stringify!(#name)))),
+				},
+			};
+
+			quote! {
+				let (discriminant, bytes) = <#ty as ::bytestruct::FromBytes<#lifetime>>::from_bytes(bytes, #endian)?;
+				let variant = match discriminant {
+					#(#variants)*
+					#unknown
+				};
+
+				Ok((variant, bytes))
+			}
+		}
+		Data::Union(_) => panic!("Only structs and enums are supported"),
+	};
+
+	quote! {
+		impl<#lifetime, #(#type_params),*> ::bytestruct::FromBytes<#lifetime> for #name<#(#type_generics),*>
+		where
+			#(#bounds),*
+		{
+			#[allow(unused_variables)]
+			fn from_bytes(bytes: &#lifetime [u8], endian: ::bytestruct::Endian) -> ::core::result::Result<(Self, &#lifetime [u8]), ::bytestruct::FromBytesError> {
+				#body
+			}
+		}
+	}
+	.into()
+}
+
+#[proc_macro_derive(Size, attributes(len, count, rest))]
 pub fn derive_size(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 	let input = parse_macro_input!(input as DeriveInput);
 
@@ -221,8 +727,11 @@ pub fn derive_size(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 			let ty = &field.ty;
 			let name = field.ident.as_ref().unwrap();
 
-			quote! {
-				<#ty as ::bytestruct::Size>::size(&self.#name)
+			match Collection::from_attrs(&field.attrs) {
+				Some(collection) => collection.size(ty, quote! { self.#name }),
+				None => quote! {
+					<#ty as ::bytestruct::Size>::size(&self.#name)
+				},
 			}
 		});
 
@@ -251,6 +760,61 @@ pub fn derive_size(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 	}
 }
 
+/// The arms that map the discriminants of an enum to its variants, and back.
+struct EnumArms {
+	read: Vec<TokenStream>,
+	write: Vec<TokenStream>,
+
+	/// The `#[fallback]` variant, if there is one, which holds any discriminant that isn't one of the other variants'.
+	/// There's no arm to read it, as it has to come after all of the others.
+	fallback: Option<Ident>,
+}
+
+fn enum_arms(name: &Ident, data: &DataEnum) -> EnumArms {
+	let mut arms = EnumArms {
+		read: Vec::new(),
+		write: Vec::new(),
+		fallback: None,
+	};
+
+	for (i, variant) in data.variants.iter().enumerate() {
+		let ident = &variant.ident;
+		if variant.attrs.iter().any(|attr| attr.path().is_ident("fallback")) {
+			if !matches!(&variant.fields, Fields::Unnamed(fields) if fields.unnamed.len() == 1) {
+				panic!(
+					"the #[fallback] variant must hold the discriminant, e.g. Unknown(u8), not {}",
+					ident
+				);
+			}
+
+			if arms.fallback.replace(ident.clone()).is_some() {
+				panic!("only one variant of {} can be the #[fallback]", name);
+			}
+
+			arms.write.push(quote! {
+				#name::#ident(discriminant) => *discriminant,
+			});
+			continue;
+		}
+
+		let discriminant = if let Some((_, v)) = &variant.discriminant {
+			quote! {#v}
+		} else {
+			TokenStream::from_str(&i.to_string()).unwrap()
+		};
+
+		arms.read.push(quote! {
+			#discriminant => #name::#ident,
+		});
+
+		arms.write.push(quote! {
+			#name::#ident => #discriminant,
+		});
+	}
+
+	arms
+}
+
 fn get_repr(attrs: &[syn::Attribute]) -> proc_macro2::Ident {
 	let ty = match attrs.iter().find(|attr| attr.path().is_ident("repr")) {
 		Some(repr) => repr,
@@ -0,0 +1,337 @@
+use core::{array, ffi::CStr, fmt, str};
+
+use crate::{Endian, Padding};
+
+/// Why a value couldn't be parsed from a byte slice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FromBytesError {
+	/// The bytes ended before the value did.
+	UnexpectedEnd { needed: usize, remaining: usize },
+
+	/// The bytes aren't a valid value, e.g. a string that isn't UTF-8, or an enum with an unknown discriminant.
+	Invalid(&'static str),
+}
+
+impl fmt::Display for FromBytesError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FromBytesError::UnexpectedEnd { needed, remaining } => {
+				write!(f, "expected {} bytes, but only {} remain", needed, remaining)
+			}
+			FromBytesError::Invalid(reason) => f.write_str(reason),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FromBytesError {}
+
+#[cfg(feature = "std")]
+impl From<FromBytesError> for std::io::Error {
+	fn from(e: FromBytesError) -> Self {
+		let kind = match e {
+			FromBytesError::UnexpectedEnd { .. } => std::io::ErrorKind::UnexpectedEof,
+			FromBytesError::Invalid(_) => std::io::ErrorKind::InvalidData,
+		};
+
+		std::io::Error::new(kind, e)
+	}
+}
+
+/// A trait for parsing data from the start of a byte slice, without copying it. Unlike `ReadFromWithEndian`, values
+/// can borrow from the bytes (e.g. `&'a [u8]`, `&'a str`, or `&'a CStr` fields), and nothing has to be allocated, so
+/// this works without std.
+pub trait FromBytes<'a>: Sized {
+	/// Parses a value from the start of the bytes, returning it along with the bytes after it.
+	fn from_bytes(bytes: &'a [u8], endian: Endian) -> Result<(Self, &'a [u8]), FromBytesError>;
+}
+
+/// A trait for collections that borrow from, or are parsed from, a run of bytes whose extent is known from elsewhere.
+/// This is what the `FromBytes` derive parses `#[len]`, `#[count]`, and `#[rest]` fields with.
+pub trait CollectionFromBytes<'a>: Sized {
+	/// Parses the collection from exactly the given bytes.
+	fn from_all_bytes(bytes: &'a [u8], endian: Endian) -> Result<Self, FromBytesError>;
+
+	/// Parses the given number of items from the start of the bytes, returning the collection along with the bytes
+	/// after it.
+	fn from_bytes_counted(bytes: &'a [u8], count: usize, endian: Endian) -> Result<(Self, &'a [u8]), FromBytesError>;
+}
+
+/// Splits the given number of bytes off of the start of the bytes.
+pub fn split_bytes(bytes: &[u8], len: usize) -> Result<(&[u8], &[u8]), FromBytesError> {
+	if bytes.len() < len {
+		return Err(FromBytesError::UnexpectedEnd {
+			needed: len,
+			remaining: bytes.len(),
+		});
+	}
+
+	Ok(bytes.split_at(len))
+}
+
+/// Parses a length or count prefix of the given type from the start of the bytes.
+pub fn prefix_from_bytes<'a, P: FromBytes<'a> + TryInto<usize>>(
+	bytes: &'a [u8],
+	endian: Endian,
+) -> Result<(usize, &'a [u8]), FromBytesError> {
+	let (prefix, rest) = P::from_bytes(bytes, endian)?;
+	let prefix = prefix
+		.try_into()
+		.map_err(|_| FromBytesError::Invalid("prefix is too large"))?;
+
+	Ok((prefix, rest))
+}
+
+macro_rules! int_from_bytes {
+	($($ty:ty),+) => {
+		$(
+			impl<'a> FromBytes<'a> for $ty {
+				fn from_bytes(bytes: &'a [u8], endian: Endian) -> Result<(Self, &'a [u8]), FromBytesError> {
+					let (value, rest) = split_bytes(bytes, core::mem::size_of::<$ty>())?;
+					let value = value.try_into().expect("BUG: split the size of the int");
+					let value = match endian {
+						Endian::Big => <$ty>::from_be_bytes(value),
+						Endian::Little => <$ty>::from_le_bytes(value),
+					};
+
+					Ok((value, rest))
+				}
+			}
+		)+
+	};
+}
+
+int_from_bytes!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl<'a, const SIZE: usize, T: FromBytes<'a>> FromBytes<'a> for [T; SIZE] {
+	fn from_bytes(mut bytes: &'a [u8], endian: Endian) -> Result<(Self, &'a [u8]), FromBytesError> {
+		let items = array::try_from_fn(|_| {
+			let (item, rest) = T::from_bytes(bytes, endian)?;
+			bytes = rest;
+			Ok(item)
+		})?;
+
+		Ok((items, bytes))
+	}
+}
+
+/// A null-terminated string, borrowed up to (but not including) the null.
+impl<'a> FromBytes<'a> for &'a CStr {
+	fn from_bytes(bytes: &'a [u8], _: Endian) -> Result<(Self, &'a [u8]), FromBytesError> {
+		let string =
+			CStr::from_bytes_until_nul(bytes).map_err(|_| FromBytesError::Invalid("string is not null terminated"))?;
+		Ok((string, &bytes[string.to_bytes_with_nul().len()..]))
+	}
+}
+
+impl<'a> CollectionFromBytes<'a> for &'a [u8] {
+	fn from_all_bytes(bytes: &'a [u8], _: Endian) -> Result<Self, FromBytesError> {
+		Ok(bytes)
+	}
+
+	fn from_bytes_counted(bytes: &'a [u8], count: usize, _: Endian) -> Result<(Self, &'a [u8]), FromBytesError> {
+		split_bytes(bytes, count)
+	}
+}
+
+impl<'a> CollectionFromBytes<'a> for &'a str {
+	fn from_all_bytes(bytes: &'a [u8], _: Endian) -> Result<Self, FromBytesError> {
+		str::from_utf8(bytes).map_err(|_| FromBytesError::Invalid("string is not valid utf8"))
+	}
+
+	fn from_bytes_counted(bytes: &'a [u8], count: usize, endian: Endian) -> Result<(Self, &'a [u8]), FromBytesError> {
+		let (string, rest) = split_bytes(bytes, count)?;
+		Ok((Self::from_all_bytes(string, endian)?, rest))
+	}
+}
+
+/// Items that are parsed into a `Vec`, which is the only place that parsing from bytes allocates.
+#[cfg(feature = "std")]
+impl<'a, T: FromBytes<'a>> CollectionFromBytes<'a> for Vec<T> {
+	fn from_all_bytes(mut bytes: &'a [u8], endian: Endian) -> Result<Self, FromBytesError> {
+		let mut items = Vec::new();
+		while !bytes.is_empty() {
+			let (item, rest) = T::from_bytes(bytes, endian)?;
+
+			// An item that takes up no bytes would otherwise be parsed forever.
+			if rest.len() == bytes.len() {
+				return Err(FromBytesError::Invalid(
+					"items that take up no bytes can't be parsed until the end of their bytes",
+				));
+			}
+
+			items.push(item);
+			bytes = rest;
+		}
+
+		Ok(items)
+	}
+
+	fn from_bytes_counted(
+		mut bytes: &'a [u8],
+		count: usize,
+		endian: Endian,
+	) -> Result<(Self, &'a [u8]), FromBytesError> {
+		// The count comes from the bytes, so don't trust it enough to allocate all of it up front.
+		let mut items = Vec::with_capacity(count.min(bytes.len()));
+		for _ in 0..count {
+			let (item, rest) = T::from_bytes(bytes, endian)?;
+			items.push(item);
+			bytes = rest;
+		}
+
+		Ok((items, bytes))
+	}
+}
+
+impl<const ALIGN: usize> Padding<ALIGN> {
+	/// Skips the padding after the given number of bytes, from the start of the bytes.
+	pub fn from_bytes(prev_size: usize, bytes: &[u8]) -> Result<(Self, &[u8]), FromBytesError> {
+		let padding = Self::new(prev_size);
+		let (_, rest) = split_bytes(bytes, padding.amt)?;
+		Ok((padding, rest))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use core::ffi::CStr;
+
+	use bytestruct_derive::FromBytes;
+
+	use super::{CollectionFromBytes, FromBytes, FromBytesError};
+	use crate::Endian;
+
+	#[test]
+	fn test_ints_from_bytes() {
+		let bytes = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0xFF];
+
+		assert_eq!(u8::from_bytes(&bytes, Endian::Big).unwrap(), (0x01, &bytes[1..]));
+		assert_eq!(u16::from_bytes(&bytes, Endian::Big).unwrap(), (0x0102, &bytes[2..]));
+		assert_eq!(u16::from_bytes(&bytes, Endian::Little).unwrap(), (0x0201, &bytes[2..]));
+		assert_eq!(u32::from_bytes(&bytes, Endian::Big).unwrap(), (0x01020304, &bytes[4..]));
+		assert_eq!(
+			u32::from_bytes(&bytes, Endian::Little).unwrap(),
+			(0x04030201, &bytes[4..])
+		);
+		assert_eq!(
+			u64::from_bytes(&bytes, Endian::Big).unwrap(),
+			(0x0102030405060708, &bytes[8..])
+		);
+		assert_eq!(
+			u64::from_bytes(&bytes, Endian::Little).unwrap(),
+			(0x0807060504030201, &bytes[8..])
+		);
+
+		let negative = [0xFF, 0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+		assert_eq!(i8::from_bytes(&negative, Endian::Big).unwrap().0, -1);
+		assert_eq!(i16::from_bytes(&negative, Endian::Big).unwrap().0, -2);
+		assert_eq!(i16::from_bytes(&negative, Endian::Little).unwrap().0, -257);
+		assert_eq!(i32::from_bytes(&negative, Endian::Big).unwrap().0, -65537);
+		assert_eq!(i32::from_bytes(&negative, Endian::Little).unwrap().0, -257);
+		assert_eq!(i64::from_bytes(&negative, Endian::Big).unwrap().0, -281474976710657);
+		assert_eq!(i64::from_bytes(&negative, Endian::Little).unwrap().0, -257);
+
+		assert_eq!(
+			<[u16; 2]>::from_bytes(&bytes, Endian::Little).unwrap(),
+			([0x0201, 0x0403], &bytes[4..])
+		);
+	}
+
+	#[test]
+	fn test_strings_from_bytes() {
+		let bytes = b"eth0\0rest";
+		let (name, rest) = <&CStr>::from_bytes(bytes, Endian::Little).unwrap();
+		assert_eq!(name.to_bytes(), b"eth0");
+		assert_eq!(rest, b"rest");
+		assert_eq!(
+			<&CStr>::from_bytes(b"eth0", Endian::Little),
+			Err(FromBytesError::Invalid("string is not null terminated"))
+		);
+
+		assert_eq!(<&str>::from_all_bytes(b"eth0", Endian::Little).unwrap(), "eth0");
+		assert_eq!(
+			<&str>::from_bytes_counted(bytes, 4, Endian::Little).unwrap(),
+			("eth0", &bytes[4..])
+		);
+		assert!(matches!(
+			<&str>::from_all_bytes(&[0xFF, 0xFE], Endian::Little),
+			Err(FromBytesError::Invalid(_))
+		));
+
+		assert_eq!(
+			<&[u8]>::from_bytes_counted(bytes, 5, Endian::Little).unwrap(),
+			(&bytes[..5], &bytes[5..])
+		);
+		assert_eq!(
+			<Vec<u16>>::from_bytes_counted(&[1, 0, 2, 0, 3], 2, Endian::Little).unwrap(),
+			(vec![1, 2], &[3][..])
+		);
+	}
+
+	#[test]
+	fn test_short_input() {
+		assert_eq!(
+			u32::from_bytes(&[1, 2, 3], Endian::Little),
+			Err(FromBytesError::UnexpectedEnd {
+				needed: 4,
+				remaining: 3
+			})
+		);
+		assert_eq!(
+			<&[u8]>::from_bytes_counted(b"eth", 4, Endian::Little),
+			Err(FromBytesError::UnexpectedEnd {
+				needed: 4,
+				remaining: 3
+			})
+		);
+		assert!(matches!(
+			<Vec<u16>>::from_all_bytes(&[1, 0, 2], Endian::Little),
+			Err(FromBytesError::UnexpectedEnd { .. })
+		));
+
+		let error = std::io::Error::from(u64::from_bytes(&[], Endian::Little).unwrap_err());
+		assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+	}
+
+	#[derive(Debug, PartialEq, FromBytes)]
+	#[big_endian]
+	struct Entry<'a> {
+		kind: u8,
+		#[len(u16)]
+		name: &'a str,
+		n_ids: u8,
+		#[count(field = "n_ids")]
+		ids: Vec<u16>,
+		label: &'a CStr,
+		#[rest]
+		data: &'a [u8],
+	}
+
+	#[test]
+	fn test_derive_from_bytes() {
+		let bytes = b"\x07\x00\x04eth0\x02\x00\x01\x01\x00lo\0\xAA\xBB";
+		let (entry, rest) = Entry::from_bytes(bytes, Endian::Little).unwrap();
+		assert_eq!(
+			entry,
+			Entry {
+				kind: 7,
+				name: "eth0",
+				n_ids: 2,
+				ids: vec![1, 256],
+				label: c"lo",
+				data: &[0xAA, 0xBB],
+			}
+		);
+		assert!(rest.is_empty());
+
+		// Every field is needed, up to the end of the label.
+		for len in 0..bytes.len() - 2 {
+			assert!(
+				Entry::from_bytes(&bytes[..len], Endian::Little).is_err(),
+				"parsed {} bytes",
+				len
+			);
+		}
+	}
+}
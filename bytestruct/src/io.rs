@@ -0,0 +1,732 @@
+use std::{
+	array,
+	io::{self, Read, Write},
+};
+
+use crate::{Endian, Padding, Size};
+
+/// A string that is null-terminated (C-style), with some maximum size.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NullTerminatedString<const SIZE: usize>(pub String);
+
+/// A trait for reading data from a source with a specified endianness.
+pub trait ReadFromWithEndian {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self>
+	where
+		Self: Sized;
+}
+
+/// A trait for reading data from a source with an implied endianess.
+pub trait ReadFrom {
+	fn read_from<T: Read>(source: &mut T) -> io::Result<Self>
+	where
+		Self: Sized;
+}
+
+/// A trait for writing data to a target with a specified endianness.
+pub trait WriteToWithEndian {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: Endian) -> io::Result<()>;
+}
+
+/// A trait for writing data to a target with an implied endianness.
+pub trait WriteTo {
+	fn write_to<T: Write>(&self, target: &mut T) -> io::Result<()>;
+}
+
+impl ReadFromWithEndian for u8 {
+	fn read_from_with_endian<T: Read>(source: &mut T, _: Endian) -> io::Result<Self> {
+		u8::read_from(source)
+	}
+}
+
+impl ReadFrom for u8 {
+	fn read_from<T: Read>(source: &mut T) -> io::Result<Self> {
+		let mut buf = [0u8; 1];
+		source.read_exact(&mut buf)?;
+		Ok(buf[0])
+	}
+}
+
+impl Size for u8 {
+	fn size(&self) -> usize {
+		1
+	}
+}
+
+impl WriteTo for u8 {
+	fn write_to<T: Write>(&self, target: &mut T) -> io::Result<()> {
+		target.write_all(&[*self])
+	}
+}
+
+impl WriteToWithEndian for u8 {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, _endian: Endian) -> io::Result<()> {
+		u8::write_to(self, target)
+	}
+}
+
+impl ReadFromWithEndian for u16 {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut buf = [0u8; 2];
+		source.read_exact(&mut buf)?;
+		Ok(match endian {
+			Endian::Big => u16::from_be_bytes(buf),
+			Endian::Little => u16::from_le_bytes(buf),
+		})
+	}
+}
+
+impl Size for u16 {
+	fn size(&self) -> usize {
+		2
+	}
+}
+
+impl WriteToWithEndian for u16 {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: Endian) -> io::Result<()> {
+		match endian {
+			Endian::Big => target.write_all(&self.to_be_bytes()),
+			Endian::Little => target.write_all(&self.to_le_bytes()),
+		}
+	}
+}
+
+impl ReadFromWithEndian for u32 {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut buf = [0u8; 4];
+		source.read_exact(&mut buf)?;
+		Ok(match endian {
+			Endian::Big => u32::from_be_bytes(buf),
+			Endian::Little => u32::from_le_bytes(buf),
+		})
+	}
+}
+
+impl Size for u32 {
+	fn size(&self) -> usize {
+		4
+	}
+}
+
+impl WriteToWithEndian for u32 {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: Endian) -> io::Result<()> {
+		match endian {
+			Endian::Big => target.write_all(&self.to_be_bytes()),
+			Endian::Little => target.write_all(&self.to_le_bytes()),
+		}
+	}
+}
+
+impl ReadFromWithEndian for u64 {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut buf = [0u8; 8];
+		source.read_exact(&mut buf)?;
+		Ok(match endian {
+			Endian::Big => u64::from_be_bytes(buf),
+			Endian::Little => u64::from_le_bytes(buf),
+		})
+	}
+}
+
+impl Size for u64 {
+	fn size(&self) -> usize {
+		8
+	}
+}
+
+impl WriteToWithEndian for u64 {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: Endian) -> io::Result<()> {
+		match endian {
+			Endian::Big => target.write_all(&self.to_be_bytes()),
+			Endian::Little => target.write_all(&self.to_le_bytes()),
+		}
+	}
+}
+
+impl ReadFromWithEndian for i16 {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut buf = [0u8; 2];
+		source.read_exact(&mut buf)?;
+		Ok(match endian {
+			Endian::Big => i16::from_be_bytes(buf),
+			Endian::Little => i16::from_le_bytes(buf),
+		})
+	}
+}
+
+impl Size for i16 {
+	fn size(&self) -> usize {
+		2
+	}
+}
+
+impl WriteToWithEndian for i16 {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: Endian) -> io::Result<()> {
+		match endian {
+			Endian::Big => target.write_all(&self.to_be_bytes()),
+			Endian::Little => target.write_all(&self.to_le_bytes()),
+		}
+	}
+}
+
+impl ReadFromWithEndian for i32 {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut buf = [0u8; 4];
+		source.read_exact(&mut buf)?;
+		Ok(match endian {
+			Endian::Big => i32::from_be_bytes(buf),
+			Endian::Little => i32::from_le_bytes(buf),
+		})
+	}
+}
+
+impl Size for i32 {
+	fn size(&self) -> usize {
+		4
+	}
+}
+
+impl WriteToWithEndian for i32 {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: Endian) -> io::Result<()> {
+		match endian {
+			Endian::Big => target.write_all(&self.to_be_bytes()),
+			Endian::Little => target.write_all(&self.to_le_bytes()),
+		}
+	}
+}
+
+impl ReadFromWithEndian for i64 {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut buf = [0u8; 8];
+		source.read_exact(&mut buf)?;
+		Ok(match endian {
+			Endian::Big => i64::from_be_bytes(buf),
+			Endian::Little => i64::from_le_bytes(buf),
+		})
+	}
+}
+
+impl Size for i64 {
+	fn size(&self) -> usize {
+		8
+	}
+}
+
+impl WriteToWithEndian for i64 {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: Endian) -> io::Result<()> {
+		match endian {
+			Endian::Big => target.write_all(&self.to_be_bytes()),
+			Endian::Little => target.write_all(&self.to_le_bytes()),
+		}
+	}
+}
+
+impl<const MAX_SIZE: usize> ReadFromWithEndian for NullTerminatedString<MAX_SIZE> {
+	fn read_from_with_endian<T: Read>(source: &mut T, _: Endian) -> io::Result<Self> {
+		let mut buf = [0u8; MAX_SIZE];
+		source.read_exact(&mut buf)?;
+		let mut len = 0;
+		for c in buf.iter().take(MAX_SIZE) {
+			if *c == 0 {
+				break;
+			}
+			len += 1;
+		}
+
+		if len == MAX_SIZE {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"String is not null terminated",
+			));
+		}
+
+		match std::str::from_utf8(&buf[..len]) {
+			Ok(s) => Ok(NullTerminatedString(s.to_string())),
+			Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "String is not valid utf8")),
+		}
+	}
+}
+
+impl<const MAX_SIZE: usize> Size for NullTerminatedString<MAX_SIZE> {
+	fn size(&self) -> usize {
+		self.0.len() + 1
+	}
+}
+
+impl<const MAX_SIZE: usize> WriteToWithEndian for NullTerminatedString<MAX_SIZE> {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, _: Endian) -> io::Result<()> {
+		target.write_all(self.0.as_bytes())?;
+		target.write_all(&[0])?;
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LengthPrefixedString<const MAX_SIZE: usize>(pub String);
+
+impl<const MAX_SIZE: usize> ReadFromWithEndian for LengthPrefixedString<MAX_SIZE> {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let len = match MAX_SIZE {
+			0..=0xFF => u8::read_from_with_endian(source, endian)? as usize,
+			256..=0xFFFF => u16::read_from_with_endian(source, endian)? as usize,
+			65536..=0xFFFFFFFF => u32::read_from_with_endian(source, endian)? as usize,
+			_ => u64::read_from_with_endian(source, endian)? as usize,
+		};
+
+		let mut buf = vec![0u8; len];
+		source.read_exact(&mut buf)?;
+		match std::str::from_utf8(&buf) {
+			Ok(s) => Ok(LengthPrefixedString(s.to_string())),
+			Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "String is not valid utf8")),
+		}
+	}
+}
+
+impl<const MAX_SIZE: usize> Size for LengthPrefixedString<MAX_SIZE> {
+	fn size(&self) -> usize {
+		self.0.len()
+			+ match MAX_SIZE {
+				0..=0xFF => 1,
+				256..=0xFFFF => 2,
+				65536..=0xFFFFFFFF => 4,
+				_ => 8,
+			}
+	}
+}
+
+impl<const MAX_SIZE: usize> WriteToWithEndian for LengthPrefixedString<MAX_SIZE> {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: Endian) -> io::Result<()> {
+		match MAX_SIZE {
+			0..=0xFF => (self.0.len() as u8).write_to_with_endian(target, endian)?,
+			256..=0xFFFF => (self.0.len() as u16).write_to_with_endian(target, endian)?,
+			65536..=0xFFFFFFFF => (self.0.len() as u32).write_to_with_endian(target, endian)?,
+			_ => (self.0.len() as u64).write_to_with_endian(target, endian)?,
+		}
+
+		target.write_all(self.0.as_bytes())?;
+		Ok(())
+	}
+}
+
+impl<const SIZE: usize, T: ReadFromWithEndian> ReadFromWithEndian for [T; SIZE] {
+	fn read_from_with_endian<R: Read>(source: &mut R, endian: Endian) -> io::Result<Self> {
+		array::try_from_fn(|_| T::read_from_with_endian(source, endian))
+	}
+}
+
+impl<const SIZE: usize, T: ReadFrom> ReadFrom for [T; SIZE] {
+	fn read_from<R: Read>(source: &mut R) -> io::Result<Self> {
+		array::try_from_fn(|_| T::read_from(source))
+	}
+}
+
+impl<const SIZE: usize, T: Size> Size for [T; SIZE] {
+	fn size(&self) -> usize {
+		self.iter().map(Size::size).sum()
+	}
+}
+
+impl<const SIZE: usize, T: WriteTo> WriteTo for [T; SIZE] {
+	fn write_to<W: Write>(&self, target: &mut W) -> io::Result<()> {
+		for item in self.iter() {
+			item.write_to(target)?;
+		}
+		Ok(())
+	}
+}
+
+impl<const SIZE: usize, T: WriteToWithEndian> WriteToWithEndian for [T; SIZE] {
+	fn write_to_with_endian<W: Write>(&self, target: &mut W, endian: Endian) -> io::Result<()> {
+		for item in self.iter() {
+			item.write_to_with_endian(target, endian)?;
+		}
+		Ok(())
+	}
+}
+
+impl<I: ReadFromWithEndian> ReadFromWithEndian for Vec<I> {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self>
+	where
+		Self: Sized,
+	{
+		let count = read_prefix::<u64, _>(source, endian)?;
+		read_items(source, count, endian)
+	}
+}
+
+impl<T: Size> Size for Vec<T> {
+	fn size(&self) -> usize {
+		self.iter().map(Size::size).sum()
+	}
+}
+
+impl<T: WriteToWithEndian> WriteToWithEndian for Vec<T> {
+	fn write_to_with_endian<W: Write>(&self, target: &mut W, endian: Endian) -> io::Result<()> {
+		(self.len() as u64).write_to_with_endian(target, endian)?;
+		write_items(self, target, endian)
+	}
+}
+
+/// Reads a length or count prefix of the given type. This (along with the rest of the functions below) is what the
+/// derive reads and writes `#[len]`, `#[count]`, and `#[rest]` fields with.
+pub fn read_prefix<P: ReadFromWithEndian + TryInto<usize>, R: Read>(
+	source: &mut R,
+	endian: Endian,
+) -> io::Result<usize> {
+	P::read_from_with_endian(source, endian)?
+		.try_into()
+		.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "prefix is too large"))
+}
+
+/// Writes a length or count prefix of the given type, failing if the value doesn't fit in it.
+pub fn write_prefix<P: WriteToWithEndian + TryFrom<usize>, W: Write>(
+	value: usize,
+	target: &mut W,
+	endian: Endian,
+) -> io::Result<()> {
+	let prefix = P::try_from(value).map_err(|_| {
+		io::Error::new(
+			io::ErrorKind::InvalidData,
+			format!("{} doesn't fit in a {} prefix", value, std::any::type_name::<P>()),
+		)
+	})?;
+
+	prefix.write_to_with_endian(target, endian)
+}
+
+/// Reads the given number of items, one after the other.
+pub fn read_items<I: ReadFromWithEndian, R: Read>(source: &mut R, count: usize, endian: Endian) -> io::Result<Vec<I>> {
+	// The count comes from the source, so don't trust it enough to allocate all of it up front.
+	let mut items = Vec::with_capacity(count.min(1024));
+	for _ in 0..count {
+		items.push(I::read_from_with_endian(source, endian)?);
+	}
+
+	Ok(items)
+}
+
+/// Reads as many items as fit in the given number of bytes, failing if they don't fill it exactly.
+pub fn read_items_in<I: ReadFromWithEndian, R: Read>(source: &mut R, len: usize, endian: Endian) -> io::Result<Vec<I>> {
+	let mut bytes = Vec::with_capacity(len.min(4096));
+	source.take(len as u64).read_to_end(&mut bytes)?;
+	if bytes.len() != len {
+		return Err(io::Error::new(
+			io::ErrorKind::UnexpectedEof,
+			format!("expected {} bytes of items, but only got {}", len, bytes.len()),
+		));
+	}
+
+	items_from_bytes(&bytes, endian)
+}
+
+/// Reads items until the source runs out. Nothing can come after the items, so `#[rest]` can only be used on the last
+/// field of a struct:
+///
+/// ```compile_fail
+/// #[derive(bytestruct_derive::ByteStruct)]
+/// struct Message {
+///     #[rest]
+///     data: Vec<u8>,
+///     checksum: u32,
+/// }
+/// ```
+pub fn read_remaining_items<I: ReadFromWithEndian, R: Read>(source: &mut R, endian: Endian) -> io::Result<Vec<I>> {
+	let mut bytes = Vec::new();
+	source.read_to_end(&mut bytes)?;
+	items_from_bytes(&bytes, endian)
+}
+
+fn items_from_bytes<I: ReadFromWithEndian>(bytes: &[u8], endian: Endian) -> io::Result<Vec<I>> {
+	let mut cursor = io::Cursor::new(bytes);
+	let mut items = Vec::new();
+	while (cursor.position() as usize) < bytes.len() {
+		let start = cursor.position();
+		items.push(I::read_from_with_endian(&mut cursor, endian)?);
+
+		// An item that takes up no bytes would otherwise be read forever.
+		if cursor.position() == start {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"items that take up no bytes can't be read until the end of their bytes",
+			));
+		}
+	}
+
+	Ok(items)
+}
+
+/// Writes the items one after the other, without any prefix.
+pub fn write_items<I: WriteToWithEndian, W: Write>(items: &[I], target: &mut W, endian: Endian) -> io::Result<()> {
+	for item in items {
+		item.write_to_with_endian(target, endian)?;
+	}
+
+	Ok(())
+}
+
+/// Optional fields are only read by structs that know whether they're there (see `#[present_if]` in the derive), so
+/// there's no way to read one on its own, but they take up no space, and write nothing, if they're missing.
+impl<T: Size> Size for Option<T> {
+	fn size(&self) -> usize {
+		self.as_ref().map_or(0, Size::size)
+	}
+}
+
+impl<T: WriteToWithEndian> WriteToWithEndian for Option<T> {
+	fn write_to_with_endian<W: Write>(&self, target: &mut W, endian: Endian) -> io::Result<()> {
+		match self {
+			Some(value) => value.write_to_with_endian(target, endian),
+			None => Ok(()),
+		}
+	}
+}
+
+#[cfg(feature = "time")]
+impl ReadFromWithEndian for chrono::DateTime<chrono::Utc> {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let time = i64::read_from_with_endian(source, endian)?;
+		Ok(chrono::DateTime::from_timestamp_nanos(time))
+	}
+}
+
+#[cfg(feature = "time")]
+impl Size for chrono::DateTime<chrono::Utc> {
+	fn size(&self) -> usize {
+		8
+	}
+}
+
+#[cfg(feature = "time")]
+impl WriteToWithEndian for chrono::DateTime<chrono::Utc> {
+	fn write_to_with_endian<W: Write>(&self, target: &mut W, endian: Endian) -> io::Result<()> {
+		let nanos = match self.timestamp_nanos_opt() {
+			Some(nanos) => nanos,
+			None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid timestamp")),
+		};
+
+		nanos.write_to_with_endian(target, endian)
+	}
+}
+
+impl<const ALIGN: usize> Padding<ALIGN> {
+	pub fn read<R: Read>(prev_size: usize, r: &mut R) -> io::Result<Self> {
+		let amt = ALIGN - (prev_size % ALIGN);
+		let mut buf = vec![0u8; amt];
+		r.read_exact(&mut buf)?;
+
+		Ok(Padding { amt })
+	}
+}
+
+impl<const ALIGN: usize> WriteTo for Padding<ALIGN> {
+	fn write_to<W: Write>(&self, target: &mut W) -> io::Result<()> {
+		target.write_all(&vec![0u8; self.amt])
+	}
+}
+
+impl<const ALIGN: usize> WriteToWithEndian for Padding<ALIGN> {
+	fn write_to_with_endian<W: Write>(&self, target: &mut W, _: Endian) -> io::Result<()> {
+		self.write_to(target)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::{Cursor, ErrorKind};
+
+	use bytestruct_derive::{ByteStruct, FromBytes, Size};
+
+	use crate::{Endian, FromBytes, ReadFromWithEndian, Size, WriteToWithEndian};
+
+	/// Reads the value back from the bytes that it's written as, checking that it's the size that it says it is.
+	fn round_trip<T: ReadFromWithEndian + WriteToWithEndian + Size>(value: &T) -> (T, Vec<u8>) {
+		let mut bytes = Vec::new();
+		value.write_to_with_endian(&mut bytes, Endian::Little).unwrap();
+		assert_eq!(bytes.len(), value.size());
+
+		let read = T::read_from_with_endian(&mut Cursor::new(&bytes), Endian::Little).unwrap();
+		(read, bytes)
+	}
+
+	#[derive(Debug, PartialEq, ByteStruct, FromBytes, Size)]
+	struct Flagged {
+		flags: u8,
+		#[present_if(field = "flags", bit = 0x2)]
+		checksum: Option<u32>,
+	}
+
+	#[derive(Debug, PartialEq, ByteStruct, FromBytes, Size)]
+	struct Versioned {
+		length: u16,
+		id: u16,
+		#[present_if(field = "length", min = 8)]
+		extra: Option<u32>,
+	}
+
+	#[derive(Debug, PartialEq, ByteStruct, Size)]
+	struct Prefixed {
+		#[len(u16)]
+		name: Vec<u8>,
+		#[count(u8)]
+		ids: Vec<u16>,
+		#[rest]
+		data: Vec<u32>,
+	}
+
+	#[derive(Debug, PartialEq, ByteStruct, Size)]
+	struct FromFields {
+		name_len: u8,
+		n_ids: u32,
+		#[len(field = "name_len")]
+		name: Vec<u8>,
+		#[count(field = "n_ids")]
+		ids: Vec<u16>,
+	}
+
+	#[test]
+	fn test_collections_with_prefixes() {
+		let value = Prefixed {
+			name: b"eth0".to_vec(),
+			ids: vec![1, 2, 3],
+			data: vec![0xAABBCCDD, 1],
+		};
+		let (read, bytes) = round_trip(&value);
+		assert_eq!(read, value);
+		assert_eq!(&bytes[..7], b"\x04\x00eth0\x03");
+		assert_eq!(bytes.len(), 2 + 4 + 1 + 6 + 8);
+
+		let empty = Prefixed {
+			name: Vec::new(),
+			ids: Vec::new(),
+			data: Vec::new(),
+		};
+		let (read, bytes) = round_trip(&empty);
+		assert_eq!(read, empty);
+		assert_eq!(bytes, [0, 0, 0]);
+
+		// The data has to be made up of whole items.
+		let mut bytes = Vec::new();
+		value.write_to_with_endian(&mut bytes, Endian::Little).unwrap();
+		bytes.pop();
+		assert!(Prefixed::read_from_with_endian(&mut Cursor::new(&bytes), Endian::Little).is_err());
+
+		// Prefixes have to fit in their type.
+		let too_many = Prefixed {
+			name: Vec::new(),
+			ids: vec![0; 256],
+			data: Vec::new(),
+		};
+		let err = too_many
+			.write_to_with_endian(&mut Vec::new(), Endian::Little)
+			.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn test_collections_from_fields() {
+		let value = FromFields {
+			name_len: 4,
+			n_ids: 2,
+			name: b"eth0".to_vec(),
+			ids: vec![1, 2],
+		};
+		let (read, bytes) = round_trip(&value);
+		assert_eq!(read, value);
+		assert_eq!(bytes, b"\x04\x02\x00\x00\x00eth0\x01\x00\x02\x00");
+
+		// The fields have to agree with the collections.
+		for value in [
+			FromFields { name_len: 3, ..value },
+			FromFields {
+				name_len: 4,
+				n_ids: 3,
+				name: b"eth0".to_vec(),
+				ids: vec![1, 2],
+			},
+		] {
+			let err = value.write_to_with_endian(&mut Vec::new(), Endian::Little).unwrap_err();
+			assert_eq!(err.kind(), ErrorKind::InvalidData);
+		}
+
+		// A name that's shorter than its length runs into the items after it.
+		let bytes = b"\x04\x01\x00\x00\x00eth";
+		let err = FromFields::read_from_with_endian(&mut Cursor::new(bytes), Endian::Little).unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+	}
+
+	#[test]
+	fn test_present_if_bit() {
+		let present = Flagged {
+			flags: 0x3,
+			checksum: Some(0xDEADBEEF),
+		};
+		let (read, bytes) = round_trip(&present);
+		assert_eq!(read, present);
+		assert_eq!(bytes, [0x3, 0xEF, 0xBE, 0xAD, 0xDE]);
+		assert_eq!(Flagged::from_bytes(&bytes, Endian::Little).unwrap(), (present, &[][..]));
+
+		let missing = Flagged {
+			flags: 0x1,
+			checksum: None,
+		};
+		let (read, bytes) = round_trip(&missing);
+		assert_eq!(read, missing);
+		assert_eq!(bytes, [0x1]);
+		assert_eq!(Flagged::from_bytes(&bytes, Endian::Little).unwrap(), (missing, &[][..]));
+	}
+
+	#[test]
+	fn test_present_if_min() {
+		let present = Versioned {
+			length: 8,
+			id: 1,
+			extra: Some(2),
+		};
+		let (read, bytes) = round_trip(&present);
+		assert_eq!(read, present);
+		assert_eq!(bytes.len(), 8);
+		assert_eq!(
+			Versioned::from_bytes(&bytes, Endian::Little).unwrap(),
+			(present, &[][..])
+		);
+
+		let missing = Versioned {
+			length: 4,
+			id: 1,
+			extra: None,
+		};
+		let (read, bytes) = round_trip(&missing);
+		assert_eq!(read, missing);
+		assert_eq!(bytes.len(), 4);
+		assert_eq!(
+			Versioned::from_bytes(&bytes, Endian::Little).unwrap(),
+			(missing, &[][..])
+		);
+	}
+
+	#[test]
+	fn test_present_if_mismatch() {
+		for value in [
+			Flagged {
+				flags: 0x2,
+				checksum: None,
+			},
+			Flagged {
+				flags: 0x1,
+				checksum: Some(1),
+			},
+		] {
+			let err = value.write_to_with_endian(&mut Vec::new(), Endian::Little).unwrap_err();
+			assert_eq!(err.kind(), ErrorKind::InvalidData);
+		}
+
+		let err = Versioned {
+			length: 4,
+			id: 1,
+			extra: Some(2),
+		}
+		.write_to_with_endian(&mut Vec::new(), Endian::Little)
+		.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidData);
+	}
+}
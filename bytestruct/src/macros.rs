@@ -1,6 +1,7 @@
 #[macro_export]
 /// int_enum provides a macro to derive an enum from a set of numbers that can be read/write.
 macro_rules! int_enum {
+    // Without a fallback variant, values that aren't one of the variants are errors.
     (
         $(#[$outer:meta])*
         $v:vis enum $EnumName:ident : $Type:ty{
@@ -27,28 +28,66 @@ macro_rules! int_enum {
                     $(
                         $Value => Ok($EnumName::$Variant),
                     )+
-                    i => Err(format!("{:?} is not a valid {}", value, stringify!($EnumName)))
+                    _ => Err(format!("{:?} is not a valid {}", value, stringify!($EnumName)))
                 }
             }
         }
 
+        $crate::int_enum!(@impls $EnumName : $Type { $($Variant = $Value,)+ });
+    };
+
+    // With a fallback variant (`_ => Unknown(u16),`), values that aren't one of the other variants are captured by it,
+    // so reading the enum never fails on an unknown value.
+    (
+        $(#[$outer:meta])*
+        $v:vis enum $EnumName:ident : $Type:ty{
+            $(
+                $(#[$inner:ident $($args:tt)*])*
+                $Variant:ident = $Value:expr,
+            )+
+            _ => $Fallback:ident($FallbackType:ty),
+        }
+    ) => {
+        $(#[$outer])*
+        $v enum $EnumName {
+            $(
+                $(#[$inner $($args)*])*
+                $Variant,
+            )+
+            $Fallback($FallbackType),
+        }
+
+        impl From<$Type> for $EnumName {
+            fn from(value: $Type) -> Self {
+                match value {
+                    $(
+                        $Value => $EnumName::$Variant,
+                    )+
+                    value => $EnumName::$Fallback(value),
+                }
+            }
+        }
+
+        $crate::int_enum!(@impls $EnumName : $Type { $($Variant = $Value,)+ } $Fallback);
+    };
+
+    (@impls $EnumName:ident : $Type:ty { $($Variant:ident = $Value:expr,)+ } $($Fallback:ident)?) => {
         impl From<&$EnumName> for $Type {
             fn from(e: &$EnumName) -> $Type {
                 match e {
                     $(
                         $EnumName::$Variant => $Value,
                     )+
+                    $(
+                        $EnumName::$Fallback(value) => *value,
+                    )?
                 }
             }
         }
 
         impl From<$EnumName> for $Type {
             fn from(e: $EnumName) -> $Type {
-                match e {
-                    $(
-                        $EnumName::$Variant => $Value,
-                    )+
-                }
+                (&e).into()
             }
         }
 
@@ -63,10 +102,14 @@ macro_rules! int_enum {
             fn read_from_with_endian<T: ::std::io::Read>(source: &mut T, endian: ::bytestruct::Endian) -> ::std::io::Result<Self> {
                 let val = <$Type>::read_from_with_endian(source, endian)?;
 
+                #[allow(unreachable_patterns)]
                 match val {
                     $(
                         $Value => Ok($EnumName::$Variant),
                     )+
+                    $(
+                        val => Ok($EnumName::$Fallback(val)),
+                    )?
                     _ => {
                         Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData, format!("invalid value for {}: {}", stringify!($EnumName), val)))
                     }
@@ -74,16 +117,126 @@ macro_rules! int_enum {
             }
         }
 
-        impl ::bytestruct::WriteToWithEndian for $EnumName {
-            fn write_to_with_endian<W: ::std::io::Write>(&self, writer: &mut W, endian: ::bytestruct::Endian) -> ::std::io::Result<()> {
-                let val: $Type = match self {
+        impl<'a> ::bytestruct::FromBytes<'a> for $EnumName {
+            fn from_bytes(bytes: &'a [u8], endian: ::bytestruct::Endian) -> Result<(Self, &'a [u8]), ::bytestruct::FromBytesError> {
+                let (val, rest) = <$Type as ::bytestruct::FromBytes>::from_bytes(bytes, endian)?;
+
+                #[allow(unreachable_patterns)]
+                match val {
                     $(
-                        $EnumName::$Variant => $Value,
+                        $Value => Ok(($EnumName::$Variant, rest)),
                     )+
-                };
+                    $(
+                        val => Ok(($EnumName::$Fallback(val), rest)),
+                    )?
+                    _ => Err(::bytestruct::FromBytesError::Invalid(concat!("invalid value for ", stringify!($EnumName)))),
+                }
+            }
+        }
 
+        impl ::bytestruct::WriteToWithEndian for $EnumName {
+            fn write_to_with_endian<W: ::std::io::Write>(&self, writer: &mut W, endian: ::bytestruct::Endian) -> ::std::io::Result<()> {
+                let val: $Type = self.into();
                 val.write_to_with_endian(writer, endian)
             }
         }
-    }
+    };
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::{Cursor, ErrorKind};
+
+	use bytestruct_derive::{ByteStruct, FromBytes};
+
+	use crate::{Endian, FromBytes, FromBytesError, ReadFromWithEndian, Size, WriteToWithEndian};
+
+	int_enum! {
+		#[derive(Debug, PartialEq)]
+		enum Known: u16 {
+			First = 1,
+			Second = 2,
+		}
+	}
+
+	int_enum! {
+		#[derive(Debug, PartialEq)]
+		enum WithFallback: u16 {
+			First = 1,
+			_ => Unknown(u16),
+		}
+	}
+
+	#[derive(Debug, PartialEq, ByteStruct, FromBytes)]
+	#[repr(u8)]
+	enum DerivedKnown {
+		First = 1,
+		Second = 2,
+	}
+
+	#[derive(Debug, PartialEq, ByteStruct, FromBytes)]
+	#[repr(u8)]
+	enum DerivedWithFallback {
+		First = 1,
+		#[fallback]
+		Unknown(u8),
+	}
+
+	fn write<T: WriteToWithEndian>(value: &T) -> Vec<u8> {
+		let mut bytes = Vec::new();
+		value.write_to_with_endian(&mut bytes, Endian::Big).unwrap();
+		bytes
+	}
+
+	#[test]
+	fn test_int_enum_fallback() {
+		let read = |bytes: &[u8]| WithFallback::read_from_with_endian(&mut Cursor::new(bytes), Endian::Big).unwrap();
+		assert_eq!(read(&[0, 1]), WithFallback::First);
+		assert_eq!(read(&[0x12, 0x34]), WithFallback::Unknown(0x1234));
+		assert_eq!(
+			WithFallback::from_bytes(&[0x12, 0x34], Endian::Big).unwrap().0,
+			WithFallback::Unknown(0x1234)
+		);
+
+		assert_eq!(write(&WithFallback::Unknown(0x1234)), [0x12, 0x34]);
+		assert_eq!(u16::from(WithFallback::Unknown(7)), 7);
+		assert_eq!(WithFallback::Unknown(7).size(), 2);
+	}
+
+	#[test]
+	fn test_int_enum_without_fallback() {
+		assert_eq!(
+			Known::read_from_with_endian(&mut Cursor::new([0, 2]), Endian::Big).unwrap(),
+			Known::Second
+		);
+		assert_eq!(write(&Known::Second), [0, 2]);
+
+		let err = Known::read_from_with_endian(&mut Cursor::new([0, 3]), Endian::Big).unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidData);
+		assert!(matches!(
+			Known::from_bytes(&[0, 3], Endian::Big),
+			Err(FromBytesError::Invalid(_))
+		));
+		assert!(Known::try_from(3).is_err());
+	}
+
+	#[test]
+	fn test_derived_fallback() {
+		let read = |bytes: &[u8]| DerivedWithFallback::read_from_with_endian(&mut Cursor::new(bytes), Endian::Big);
+		assert_eq!(read(&[1]).unwrap(), DerivedWithFallback::First);
+		assert_eq!(read(&[9]).unwrap(), DerivedWithFallback::Unknown(9));
+		assert_eq!(
+			DerivedWithFallback::from_bytes(&[9], Endian::Big).unwrap().0,
+			DerivedWithFallback::Unknown(9)
+		);
+		assert_eq!(write(&DerivedWithFallback::Unknown(9)), [9]);
+
+		let err = DerivedKnown::read_from_with_endian(&mut Cursor::new([3]), Endian::Big).unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidData);
+		assert!(matches!(
+			DerivedKnown::from_bytes(&[3], Endian::Big),
+			Err(FromBytesError::Invalid(_))
+		));
+		assert_eq!(write(&DerivedKnown::Second), [2]);
+	}
 }
@@ -1,6 +1,10 @@
-use std::{fs, io::Read};
+use std::{
+	fs::{self, File},
+	io::{self, Read, Write},
+};
 
 use clap::{Arg, ArgAction, Command};
+use common::fsops::copy_data;
 
 fn main() {
 	let matches = Command::new("cat")
@@ -27,6 +31,20 @@ fn main() {
 
 	let mut i = 0;
 	for file in files {
+		// Files that are copied as they are can be copied by the kernel, without reading them in here.
+		if !number && file != "-" {
+			let result = File::open(file).and_then(|mut src| {
+				let mut stdout = io::stdout().lock();
+				stdout.flush()?;
+				copy_data(&mut src, &mut stdout)
+			});
+			if let Err(e) = result {
+				eprintln!("cat: {}: {}", file, e);
+			}
+
+			continue;
+		}
+
 		let file_contents = match file.as_str() {
 			"-" => {
 				// This technically isn't the same support as the real cat, but it's close enough.
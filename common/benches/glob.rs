@@ -0,0 +1,71 @@
+//! Benchmarks of matching module aliases, which udev does for every alias against every device that's added, against
+//! translating the aliases into regexes (which is what udev used to do).
+
+use common::glob::Glob;
+use criterion::{criterion_group, criterion_main, Criterion};
+use regex::Regex;
+
+/// A sample of the kinds of patterns in modules.alias.
+const ALIASES: [&str; 8] = [
+	"pci:v00008086d00001533sv*sd*bc*sc*i*",
+	"pci:v*d*sv*sd*bc01sc06i01*",
+	"usb:v*p*d*dc*dsc*dp*ic03isc01ip01in*",
+	"usb:v0BDAp8153d[2-2]*dc*dsc*dp*ic*isc*ip*in*",
+	"acpi*:PNP0C0A:*",
+	"of:N*T*Cnvidia,tegra30-i2c*",
+	"virtio:d00000001v*",
+	"input:b*v*p*e*-e*1,*2,*k*r*a*m*l*s*f*w*",
+];
+
+/// Device modaliases, some of which match.
+const DEVICES: [&str; 4] = [
+	"pci:v00008086d00001533sv00008086sd00000000bc02sc00i00",
+	"usb:v1D6Bp0002d0515dc09dsc00dp03ic09isc00ip00in00",
+	"virtio:d00000001v00001AF4",
+	"acpi:PNP0C0A:PNP0C0B:",
+];
+
+/// The old translation, which is only here to compare against.
+fn glob_to_regex(s: &str) -> Regex {
+	let regex = s.replace('*', ".*");
+	let regex = regex.replace('?', ".");
+	Regex::new(&format!("^{}$", regex)).unwrap()
+}
+
+fn bench_compile(c: &mut Criterion) {
+	let mut group = c.benchmark_group("compile");
+	group.bench_function("glob", |b| {
+		b.iter(|| ALIASES.iter().map(|alias| Glob::new(alias)).collect::<Vec<_>>())
+	});
+	group.bench_function("regex", |b| {
+		b.iter(|| ALIASES.iter().map(|alias| glob_to_regex(alias)).collect::<Vec<_>>())
+	});
+	group.finish();
+}
+
+fn bench_match(c: &mut Criterion) {
+	let globs: Vec<Glob> = ALIASES.iter().map(|alias| Glob::new(alias)).collect();
+	let regexes: Vec<Regex> = ALIASES.iter().map(|alias| glob_to_regex(alias)).collect();
+
+	let mut group = c.benchmark_group("match");
+	group.bench_function("glob", |b| {
+		b.iter(|| {
+			DEVICES
+				.iter()
+				.map(|device| globs.iter().filter(|glob| glob.matches(device)).count())
+				.sum::<usize>()
+		})
+	});
+	group.bench_function("regex", |b| {
+		b.iter(|| {
+			DEVICES
+				.iter()
+				.map(|device| regexes.iter().filter(|regex| regex.is_match(device)).count())
+				.sum::<usize>()
+		})
+	});
+	group.finish();
+}
+
+criterion_group!(benches, bench_compile, bench_match);
+criterion_main!(benches);
@@ -0,0 +1,102 @@
+use std::{fs::read_to_string, io};
+
+/// The command line that the kernel was booted with.
+pub const CMDLINE_PATH: &str = "/proc/cmdline";
+
+/// The parameters on a kernel command line, e.g. `root=/dev/sda1 quiet qinit.sphere=rescue`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KernelCmdline {
+	/// The parameters in the order they appear, with their values if they have them.
+	params: Vec<(String, Option<String>)>,
+}
+
+impl KernelCmdline {
+	/// Reads the command line that the kernel was booted with.
+	pub fn read() -> io::Result<Self> {
+		Ok(Self::parse(&read_to_string(CMDLINE_PATH)?))
+	}
+
+	/// Parses a kernel command line. Parameters are separated by whitespace, and values can be wrapped in double
+	/// quotes to include spaces, e.g. `qinit.sphere="my sphere"`.
+	pub fn parse(cmdline: &str) -> Self {
+		let mut params = Vec::new();
+		let mut chars = cmdline.trim().chars().peekable();
+		while chars.peek().is_some() {
+			let mut param = String::new();
+			let mut quoted = false;
+			for c in chars.by_ref() {
+				match c {
+					'"' => quoted = !quoted,
+					c if c.is_whitespace() && !quoted => break,
+					c => param.push(c),
+				}
+			}
+
+			while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+			if param.is_empty() {
+				continue;
+			}
+
+			params.push(match param.split_once('=') {
+				Some((key, value)) => (key.to_owned(), Some(value.to_owned())),
+				None => (param, None),
+			});
+		}
+
+		Self { params }
+	}
+
+	/// Whether the given parameter was given, with or without a value.
+	pub fn has(&self, key: &str) -> bool {
+		self.params.iter().any(|(k, _)| k == key)
+	}
+
+	/// Gets the value of the given parameter. If it was given more than once, the last value wins, as it does for the
+	/// kernel's own parameters.
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.params
+			.iter()
+			.rev()
+			.find(|(k, _)| k == key)
+			.and_then(|(_, value)| value.as_deref())
+	}
+
+	/// Gets the parameters for the given program, which are prefixed by its name and a dot (e.g. `qinit.debug`),
+	/// with the prefix removed.
+	pub fn options<'a>(&'a self, program: &'a str) -> impl Iterator<Item = (&'a str, Option<&'a str>)> {
+		self.params.iter().filter_map(move |(key, value)| {
+			let option = key.strip_prefix(program)?.strip_prefix('.')?;
+			Some((option, value.as_deref()))
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::KernelCmdline;
+
+	#[test]
+	fn test_parse() {
+		let cmdline = KernelCmdline::parse(
+			"BOOT_IMAGE=/vmlinuz root=/dev/sda1  quiet qinit.sphere=\"my sphere\" single qinit.debug root=/dev/sda2\n",
+		);
+
+		assert!(cmdline.has("quiet"));
+		assert!(cmdline.has("single"));
+		assert!(!cmdline.has("qinit"));
+		assert_eq!(cmdline.get("root"), Some("/dev/sda2"));
+		assert_eq!(cmdline.get("qinit.sphere"), Some("my sphere"));
+		assert_eq!(cmdline.get("quiet"), None);
+		assert_eq!(
+			cmdline.options("qinit").collect::<Vec<_>>(),
+			vec![("sphere", Some("my sphere")), ("debug", None)]
+		);
+	}
+
+	#[test]
+	fn test_parse_empty() {
+		assert_eq!(KernelCmdline::parse(""), KernelCmdline::default());
+		assert_eq!(KernelCmdline::parse(" \n"), KernelCmdline::default());
+	}
+}
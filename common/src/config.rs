@@ -0,0 +1,429 @@
+use std::{
+	collections::HashMap,
+	ffi::OsStr,
+	fs, io,
+	os::fd::{AsFd, BorrowedFd},
+	path::{Path, PathBuf},
+};
+
+use nix::{
+	errno::Errno,
+	poll::{poll, PollFd, PollFlags},
+	sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor},
+};
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+use toml::{Table, Value};
+
+/// The extension of the files that are read from a drop-in directory. Anything else in it is ignored.
+pub const CONFIG_EXTENSION: &str = "toml";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+	#[error("{}: {1}", .0.display())]
+	Read(PathBuf, io::Error),
+
+	#[error("{}:{line}:{column}: {message}", path.display())]
+	Parse {
+		path: PathBuf,
+		line: usize,
+		column: usize,
+		message: String,
+	},
+
+	/// The files parsed, but what they add up to isn't a valid configuration. Which of them is at fault isn't known
+	/// once they've been merged, so every file that was read is listed.
+	#[error("invalid configuration in {}: {message}", display_paths(.paths))]
+	Invalid { paths: Vec<PathBuf>, message: String },
+
+	#[error("failed to watch configuration: {0}")]
+	Watch(#[from] Errno),
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+	match paths {
+		[] => "defaults".to_owned(),
+		paths => paths
+			.iter()
+			.map(|path| path.display().to_string())
+			.collect::<Vec<_>>()
+			.join(", "),
+	}
+}
+
+/// Where a TOML configuration is loaded from: a main file, and a directory of drop-in files that are merged over it,
+/// so that packages (or the image build) can extend a configuration without editing the main file.
+///
+/// Drop-ins are merged in the order of their names, so `10-base.toml` is overridden by `20-local.toml`. Tables are
+/// merged key by key, arrays are appended to (so every file can add e.g. `[[rule]]`s), and anything else is replaced.
+/// Neither the main file nor the directory have to exist, in which case the configuration is whatever the type
+/// defaults to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigSource {
+	path: PathBuf,
+	drop_in_directory: PathBuf,
+}
+
+impl ConfigSource {
+	/// A configuration loaded from the given file, with drop-ins in the directory next to it named after it, e.g.
+	/// `/etc/busd/policy.toml` and `/etc/busd/policy.d`.
+	pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+		let path = path.into();
+		ConfigSource {
+			drop_in_directory: path.with_extension("d"),
+			path,
+		}
+	}
+
+	/// Reads drop-ins from the given directory, instead of the one named after the main file.
+	pub fn with_drop_in_directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+		self.drop_in_directory = directory.into();
+		self
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	pub fn drop_in_directory(&self) -> &Path {
+		&self.drop_in_directory
+	}
+
+	/// The files that make up the configuration, in the order they're merged: the main file, then the drop-ins.
+	pub fn files(&self) -> Result<Vec<PathBuf>, ConfigError> {
+		let mut files = Vec::new();
+		if self.path.is_file() {
+			files.push(self.path.clone());
+		}
+
+		let entries = match fs::read_dir(&self.drop_in_directory) {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(files),
+			Err(e) => return Err(ConfigError::Read(self.drop_in_directory.clone(), e)),
+		};
+
+		let mut drop_ins = Vec::new();
+		for entry in entries {
+			let path = entry
+				.map_err(|e| ConfigError::Read(self.drop_in_directory.clone(), e))?
+				.path();
+			if is_drop_in(&path) && path.is_file() {
+				drop_ins.push(path);
+			}
+		}
+
+		drop_ins.sort();
+		files.extend(drop_ins);
+		Ok(files)
+	}
+
+	/// Reads and merges every file, and deserializes the result.
+	pub fn load<T: DeserializeOwned>(&self) -> Result<T, ConfigError> {
+		let files = self.files()?;
+		let mut merged = Table::new();
+		for path in files.iter() {
+			let contents = fs::read_to_string(path).map_err(|e| ConfigError::Read(path.clone(), e))?;
+			merge(&mut merged, parse(path, &contents)?);
+		}
+
+		T::deserialize(Value::Table(merged)).map_err(|e| ConfigError::Invalid {
+			paths: files,
+			message: e.message().to_owned(),
+		})
+	}
+
+	/// Starts watching the configuration for changes, so that it can be reloaded.
+	pub fn watch(&self) -> Result<ConfigWatcher, ConfigError> {
+		ConfigWatcher::new(self.clone())
+	}
+}
+
+/// Whether the file is read from a drop-in directory.
+fn is_drop_in(path: &Path) -> bool {
+	path.extension() == Some(OsStr::new(CONFIG_EXTENSION))
+		&& !path
+			.file_name()
+			.is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// Parses a single file, turning where its errors are into a line and column.
+fn parse(path: &Path, contents: &str) -> Result<Table, ConfigError> {
+	contents.parse::<Table>().map_err(|e| {
+		let offset = e.span().map_or(0, |span| span.start).min(contents.len());
+		let before = &contents[..offset];
+		let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+		ConfigError::Parse {
+			path: path.to_path_buf(),
+			line: before.matches('\n').count() + 1,
+			column: before[line_start..].chars().count() + 1,
+			message: e.message().to_owned(),
+		}
+	})
+}
+
+/// Merges the overlay into the base: tables are merged key by key, arrays are appended to, and anything else in the
+/// overlay replaces what's in the base.
+fn merge(base: &mut Table, overlay: Table) {
+	for (key, value) in overlay {
+		match (base.get_mut(&key), value) {
+			(Some(Value::Table(base)), Value::Table(overlay)) => merge(base, overlay),
+			(Some(Value::Array(base)), Value::Array(overlay)) => base.extend(overlay),
+			(_, value) => {
+				base.insert(key, value);
+			}
+		}
+	}
+}
+
+/// Which of the configuration's paths a watched directory holds.
+#[derive(Debug, Clone)]
+struct WatchedDirectory {
+	path: PathBuf,
+
+	/// Whether this is the drop-in directory itself, rather than a directory that holds the main file or the drop-in
+	/// directory.
+	drop_ins: bool,
+}
+
+/// Watches a configuration for changes with inotify, so that daemons can reload it when it's edited, rather than
+/// (or as well as) on SIGHUP.
+///
+/// The directories holding the files are watched, rather than the files themselves, so that files that are replaced
+/// by renaming over them (as most editors and package managers do) are still seen, as is a drop-in directory that is
+/// created after the watch started.
+pub struct ConfigWatcher {
+	source: ConfigSource,
+	inotify: Inotify,
+	watches: HashMap<WatchDescriptor, WatchedDirectory>,
+}
+
+impl ConfigWatcher {
+	fn new(source: ConfigSource) -> Result<Self, ConfigError> {
+		let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+		let mut watcher = ConfigWatcher {
+			source,
+			inotify,
+			watches: HashMap::new(),
+		};
+
+		let mut parents = vec![parent(&watcher.source.path)];
+		let drop_in_parent = parent(&watcher.source.drop_in_directory);
+		if !parents.contains(&drop_in_parent) {
+			parents.push(drop_in_parent);
+		}
+
+		for directory in parents {
+			watcher.add_watch(directory, false)?;
+		}
+
+		match watcher.add_watch(watcher.source.drop_in_directory.clone(), true) {
+			Err(Errno::ENOENT | Errno::ENOTDIR) => {}
+			result => result?,
+		}
+
+		Ok(watcher)
+	}
+
+	fn add_watch(&mut self, path: PathBuf, drop_ins: bool) -> Result<(), Errno> {
+		let mask = AddWatchFlags::IN_CLOSE_WRITE
+			| AddWatchFlags::IN_CREATE
+			| AddWatchFlags::IN_DELETE
+			| AddWatchFlags::IN_MOVED_FROM
+			| AddWatchFlags::IN_MOVED_TO
+			| AddWatchFlags::IN_ONLYDIR;
+		let wd = self.inotify.add_watch(&path, mask)?;
+		self.watches.insert(wd, WatchedDirectory { path, drop_ins });
+		Ok(())
+	}
+
+	/// Reads every pending event, without blocking, returning whether any of them changed the configuration.
+	pub fn changed(&mut self) -> Result<bool, ConfigError> {
+		let mut changed = false;
+		loop {
+			let events = match self.inotify.read_events() {
+				Ok(events) => events,
+				Err(Errno::EAGAIN) => return Ok(changed),
+				Err(e) => return Err(e.into()),
+			};
+
+			for event in events {
+				if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+					// The directory was removed, so it can't be watched any more.
+					self.watches.remove(&event.wd);
+					continue;
+				}
+
+				let (Some(directory), Some(name)) = (self.watches.get(&event.wd).cloned(), event.name) else {
+					continue;
+				};
+
+				let path = directory.path.join(name);
+				let is_dir = event.mask.contains(AddWatchFlags::IN_ISDIR);
+				if directory.drop_ins {
+					// Files that have just been created are empty until they're written and closed.
+					changed |= is_drop_in(&path) && !is_dir && !event.mask.contains(AddWatchFlags::IN_CREATE);
+				} else if path == self.source.drop_in_directory {
+					if is_dir
+						&& event
+							.mask
+							.intersects(AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO)
+					{
+						match self.add_watch(path, true) {
+							Err(Errno::ENOENT | Errno::ENOTDIR) => {}
+							result => result?,
+						}
+					}
+
+					changed = true;
+				} else if path == self.source.path {
+					changed |= !event.mask.contains(AddWatchFlags::IN_CREATE);
+				}
+			}
+		}
+	}
+
+	/// Blocks until the configuration changes.
+	pub fn wait(&mut self) -> Result<(), ConfigError> {
+		while !self.changed()? {
+			let mut fds = [PollFd::new(&self.inotify, PollFlags::POLLIN)];
+			match poll(&mut fds, -1) {
+				Ok(_) | Err(Errno::EINTR) => {}
+				Err(e) => return Err(e.into()),
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl AsFd for ConfigWatcher {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.inotify.as_fd()
+	}
+}
+
+/// The directory that holds the path, which is the working directory for relative paths without one.
+fn parent(path: &Path) -> PathBuf {
+	match path.parent() {
+		Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+		_ => PathBuf::from("."),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use serde::Deserialize;
+
+	use super::{ConfigError, ConfigSource};
+	use crate::testing::TempDir;
+
+	#[derive(Deserialize, Debug, Default, PartialEq)]
+	struct Config {
+		#[serde(default)]
+		name: String,
+
+		#[serde(default)]
+		ports: Vec<u16>,
+
+		#[serde(default)]
+		limits: Limits,
+	}
+
+	#[derive(Deserialize, Debug, Default, PartialEq)]
+	struct Limits {
+		files: Option<u32>,
+		memory: Option<u32>,
+	}
+
+	#[test]
+	fn test_load() {
+		let directory = TempDir::new("config").unwrap();
+		let source = ConfigSource::new(directory.join("daemon.toml"));
+
+		// Nothing to read is the defaults.
+		assert_eq!(source.load::<Config>().unwrap(), Config::default());
+
+		fs::write(
+			directory.join("daemon.toml"),
+			"name = \"main\"\nports = [1]\n[limits]\nfiles = 10\n",
+		)
+		.unwrap();
+		fs::create_dir(directory.join("daemon.d")).unwrap();
+		fs::write(
+			directory.join("daemon.d/20-local.toml"),
+			"name = \"local\"\nports = [3]\n",
+		)
+		.unwrap();
+		fs::write(
+			directory.join("daemon.d/10-base.toml"),
+			"ports = [2]\n[limits]\nmemory = 5\n",
+		)
+		.unwrap();
+		fs::write(directory.join("daemon.d/30-ignored.conf"), "name = \"ignored\"\n").unwrap();
+
+		assert_eq!(
+			source.files().unwrap(),
+			vec![
+				directory.join("daemon.toml"),
+				directory.join("daemon.d/10-base.toml"),
+				directory.join("daemon.d/20-local.toml"),
+			]
+		);
+		assert_eq!(
+			source.load::<Config>().unwrap(),
+			Config {
+				name: "local".to_owned(),
+				ports: vec![1, 2, 3],
+				limits: Limits {
+					files: Some(10),
+					memory: Some(5)
+				},
+			}
+		);
+	}
+
+	#[test]
+	fn test_load_errors() {
+		let directory = TempDir::new("config").unwrap();
+		let source = ConfigSource::new(directory.join("daemon.toml"));
+
+		fs::write(directory.join("daemon.toml"), "name = \"main\"\n\nports = [1,\n").unwrap();
+		match source.load::<Config>() {
+			Err(ConfigError::Parse { path, line, .. }) => {
+				assert_eq!(path, directory.join("daemon.toml"));
+				assert_eq!(line, 4);
+			}
+			result => panic!("expected a parse error, got {:?}", result),
+		}
+
+		fs::write(directory.join("daemon.toml"), "name = 1\n").unwrap();
+		match source.load::<Config>() {
+			Err(ConfigError::Invalid { paths, .. }) => assert_eq!(paths, vec![directory.join("daemon.toml")]),
+			result => panic!("expected an invalid configuration, got {:?}", result),
+		}
+	}
+
+	#[test]
+	fn test_watch() {
+		let directory = TempDir::new("config").unwrap();
+		let source = ConfigSource::new(directory.join("daemon.toml"));
+		let mut watcher = source.watch().unwrap();
+		assert!(!watcher.changed().unwrap());
+
+		fs::write(directory.join("unrelated.toml"), "").unwrap();
+		assert!(!watcher.changed().unwrap());
+
+		fs::write(directory.join("daemon.toml"), "name = \"main\"\n").unwrap();
+		watcher.wait().unwrap();
+
+		// The drop-in directory is picked up once it's created.
+		fs::create_dir(directory.join("daemon.d")).unwrap();
+		assert!(watcher.changed().unwrap());
+		fs::write(directory.join("daemon.d/10-base.toml"), "").unwrap();
+		assert!(watcher.changed().unwrap());
+		fs::write(directory.join("daemon.d/notes.txt"), "").unwrap();
+		assert!(!watcher.changed().unwrap());
+	}
+}
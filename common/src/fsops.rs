@@ -0,0 +1,322 @@
+use std::{
+	fs::{self, File, Metadata, Permissions},
+	io::{self, Seek, SeekFrom, Write},
+	os::{
+		fd::{AsFd, AsRawFd},
+		unix::fs::{symlink, MetadataExt, PermissionsExt},
+	},
+	path::{Path, PathBuf},
+};
+
+use nix::{
+	errno::Errno,
+	fcntl::copy_file_range,
+	libc,
+	sys::{
+		sendfile::sendfile,
+		stat::{fstat, utimensat, SFlag, UtimensatFlags},
+		time::TimeSpec,
+	},
+	unistd::{lseek, Whence},
+};
+
+use crate::fswalk::FsWalk;
+
+/// Returns where `src` ends up if it's copied or moved to `dst`: inside of `dst` if it's a directory, or `dst`
+/// itself otherwise.
+pub fn destination(src: &Path, dst: &Path) -> PathBuf {
+	match src.file_name() {
+		Some(name) if dst.is_dir() => dst.join(name),
+		_ => dst.to_path_buf(),
+	}
+}
+
+/// Copies the file, directory, or symlink at `src` to `dst`, descending into directories. Everything copied keeps
+/// its permissions, and the times it was last accessed and modified. Symlinks are copied as symlinks, rather than
+/// the files they point at.
+pub fn copy_tree(src: &Path, dst: &Path) -> io::Result<()> {
+	// A directory copied into itself would keep finding the copy, and copying that too.
+	if fs::symlink_metadata(src)?.is_dir() && is_inside(dst, src) {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!(
+				"cannot copy a directory, {}, into itself, {}",
+				src.display(),
+				dst.display()
+			),
+		));
+	}
+
+	// Directories are only given their permissions and times once everything has been copied into them, as copying
+	// into them would change their times, and they might not be writable.
+	let mut directories = Vec::new();
+	for entry in FsWalk::new(src) {
+		let entry = entry?;
+		let target = match entry.path.strip_prefix(src) {
+			Ok(relative) if relative.as_os_str().is_empty() => dst.to_path_buf(),
+			Ok(relative) => dst.join(relative),
+			Err(_) => unreachable!("BUG: walked outside of the root"),
+		};
+
+		if entry.is_symlink {
+			symlink(fs::read_link(&entry.path)?, &target)?;
+			copy_times(&target, &entry.metadata)?;
+		} else if entry.metadata.is_dir() {
+			fs::create_dir(&target)?;
+			directories.push((target, entry.metadata));
+		} else if entry.metadata.is_file() {
+			copy_file(&entry.path, &target, &entry.metadata)?;
+		} else {
+			return Err(io::Error::new(
+				io::ErrorKind::Unsupported,
+				format!("{}: can't copy special files", entry.path.display()),
+			));
+		}
+	}
+
+	for (directory, metadata) in directories.iter().rev() {
+		fs::set_permissions(directory, Permissions::from_mode(metadata.mode()))?;
+		copy_times(directory, metadata)?;
+	}
+
+	Ok(())
+}
+
+/// Whether the path, which may not exist yet, would be inside the given directory.
+fn is_inside(path: &Path, directory: &Path) -> bool {
+	let parent = match path.parent() {
+		Some(parent) if !parent.as_os_str().is_empty() => parent,
+		_ => Path::new("."),
+	};
+
+	match (parent.canonicalize(), directory.canonicalize()) {
+		(Ok(parent), Ok(directory)) => parent.starts_with(directory),
+		_ => false,
+	}
+}
+
+/// Copies a regular file, along with its permissions and times.
+pub fn copy_file(src: &Path, dst: &Path, metadata: &Metadata) -> io::Result<()> {
+	// Creating the copy would empty the original before it could be read.
+	if fs::metadata(dst).is_ok_and(|existing| existing.dev() == metadata.dev() && existing.ino() == metadata.ino()) {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("{} and {} are the same file", src.display(), dst.display()),
+		));
+	}
+
+	copy_data(&mut File::open(src)?, &mut File::create(dst)?)?;
+	fs::set_permissions(dst, Permissions::from_mode(metadata.mode()))?;
+	copy_times(dst, metadata)
+}
+
+/// Copies the contents and permissions of the file at `src` to `dst`, like `fs::copy`, but with `copy_data`.
+pub fn copy(src: &Path, dst: &Path) -> io::Result<u64> {
+	let mut src_file = File::open(src)?;
+	let permissions = src_file.metadata()?.permissions();
+	let copied = copy_data(&mut src_file, &mut File::create(dst)?)?;
+	fs::set_permissions(dst, permissions)?;
+	Ok(copied)
+}
+
+nix::ioctl_write_int_bad!(ficlone, libc::FICLONE);
+
+/// How much is asked to be copied by each system call.
+const COPY_CHUNK_SIZE: usize = 1 << 30;
+
+/// The errors that mean a system call can't copy between the given files at all, rather than that copying failed.
+const UNSUPPORTED: [Errno; 5] = [
+	Errno::ENOSYS,
+	Errno::EXDEV,
+	Errno::EINVAL,
+	Errno::EOPNOTSUPP,
+	Errno::EBADF,
+];
+
+/// Copies everything from the current position of `src` to `dst`, returning how many bytes were copied. The kernel
+/// is asked to do as much of the work as it can: an empty destination file is reflinked to the source if the
+/// filesystem supports it (e.g. btrfs and xfs), so the data isn't copied at all. Otherwise the data is copied with
+/// copy_file_range, which can copy on the server for network filesystems, or with sendfile, which works for any
+/// destination. Only if neither can be used is the data read into, and written from, a buffer.
+pub fn copy_data<W: Write + AsFd>(src: &mut File, dst: &mut W) -> io::Result<u64> {
+	let dst_fd = dst.as_fd().as_raw_fd();
+	if src.stream_position()? == 0 {
+		let stat = fstat(dst_fd)?;
+		let is_file = SFlag::from_bits_truncate(stat.st_mode & SFlag::S_IFMT.bits()) == SFlag::S_IFREG;
+		// A reflink replaces everything in the destination, so is only the same as appending to an empty file.
+		if is_file && stat.st_size == 0 && unsafe { ficlone(dst_fd, src.as_raw_fd()) }.is_ok() {
+			let copied = src.seek(SeekFrom::End(0))?;
+			lseek(dst_fd, 0, Whence::SeekEnd)?;
+			return Ok(copied);
+		}
+	}
+
+	let copied = match copy_with(|| copy_file_range(src.as_fd(), None, dst.as_fd(), None, COPY_CHUNK_SIZE))? {
+		Some(copied) => copied,
+		None => copy_with(|| sendfile(dst.as_fd(), src.as_fd(), None, COPY_CHUNK_SIZE))?.unwrap_or(0),
+	};
+
+	// Some files (e.g. in procfs) look empty to the kernel, so copying them stops early. Reading whatever is left
+	// catches those, and is a single empty read for everything else.
+	Ok(copied + io::copy(src, dst)?)
+}
+
+/// Copies with the system call until it reaches the end of the source, returning how much it copied, or None if it
+/// can't copy between the files at all.
+fn copy_with(mut copy: impl FnMut() -> nix::Result<usize>) -> io::Result<Option<u64>> {
+	let mut copied = 0;
+	loop {
+		match copy() {
+			Ok(0) => return Ok(Some(copied)),
+			Ok(n) => copied += n as u64,
+			Err(Errno::EINTR) => {}
+			Err(e) if copied == 0 && UNSUPPORTED.contains(&e) => return Ok(None),
+			Err(e) => return Err(e.into()),
+		}
+	}
+}
+
+/// Sets the access and modification times of the path (not what it points at, if it's a symlink) to those in the
+/// metadata.
+fn copy_times(path: &Path, metadata: &Metadata) -> io::Result<()> {
+	let atime = TimeSpec::new(metadata.atime(), metadata.atime_nsec());
+	let mtime = TimeSpec::new(metadata.mtime(), metadata.mtime_nsec());
+	utimensat(None, path, &atime, &mtime, UtimensatFlags::NoFollowSymlink)?;
+	Ok(())
+}
+
+/// Removes the file, directory, or symlink at the path, along with everything in it. Symlinks are removed, rather
+/// than followed.
+pub fn remove_tree(path: &Path) -> io::Result<()> {
+	// The walk yields directories before their contents, so going through it backwards empties each directory
+	// before removing it.
+	let entries = FsWalk::new(path).collect::<io::Result<Vec<_>>>()?;
+	for entry in entries.iter().rev() {
+		if entry.metadata.is_dir() && !entry.is_symlink {
+			fs::remove_dir(&entry.path)?;
+		} else {
+			fs::remove_file(&entry.path)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Moves the path to `dst`, by renaming it if they're on the same filesystem, or by copying it over and removing the
+/// original if they aren't.
+pub fn move_path(src: &Path, dst: &Path) -> io::Result<()> {
+	match fs::rename(src, dst) {
+		Err(e) if e.raw_os_error() == Some(Errno::EXDEV as i32) => {
+			copy_tree(src, dst)?;
+			remove_tree(src)
+		}
+		result => result,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		fs::{self, File, Permissions},
+		os::unix::fs::{symlink, MetadataExt, PermissionsExt},
+		path::PathBuf,
+		time::{Duration, UNIX_EPOCH},
+	};
+
+	use super::{copy_data, copy_file, copy_tree, move_path, remove_tree};
+	use crate::testing::TempDir;
+
+	/// Creates a fresh directory tree to copy:
+	/// root/src/file (0640, modified at 1000000000)
+	/// root/src/dir/nested
+	/// root/src/dir/link -> ../file
+	fn make_tree() -> TempDir {
+		let root = TempDir::new("fsops").unwrap();
+		let src = root.join("src");
+		fs::create_dir_all(src.join("dir")).unwrap();
+		fs::write(src.join("file"), "contents").unwrap();
+		fs::set_permissions(src.join("file"), Permissions::from_mode(0o640)).unwrap();
+		File::options()
+			.write(true)
+			.open(src.join("file"))
+			.unwrap()
+			.set_modified(UNIX_EPOCH + Duration::from_secs(1000000000))
+			.unwrap();
+		fs::write(src.join("dir/nested"), "nested").unwrap();
+		symlink("../file", src.join("dir/link")).unwrap();
+		root
+	}
+
+	#[test]
+	fn test_copy_tree() {
+		let root = make_tree();
+		let (src, dst) = (root.join("src"), root.join("dst"));
+		copy_tree(&src, &dst).unwrap();
+
+		assert_eq!(fs::read_to_string(dst.join("file")).unwrap(), "contents");
+		assert_eq!(fs::read_to_string(dst.join("dir/nested")).unwrap(), "nested");
+		assert_eq!(fs::read_link(dst.join("dir/link")).unwrap(), PathBuf::from("../file"));
+
+		let metadata = fs::metadata(dst.join("file")).unwrap();
+		assert_eq!(metadata.mode() & 0o777, 0o640);
+		assert_eq!(metadata.mtime(), 1000000000);
+		assert_eq!(
+			fs::metadata(dst.join("dir")).unwrap().mtime(),
+			fs::metadata(src.join("dir")).unwrap().mtime()
+		);
+
+		// Copying a file over itself is refused, rather than emptying it.
+		let metadata = fs::metadata(src.join("file")).unwrap();
+		assert!(copy_file(&src.join("file"), &src.join("dir/../file"), &metadata).is_err());
+		assert_eq!(fs::read_to_string(src.join("file")).unwrap(), "contents");
+
+		// Copying a directory into itself is refused, rather than going on forever.
+		assert!(copy_tree(&src, &src.join("dir/copy")).is_err());
+		assert!(!src.join("dir/copy").exists());
+	}
+
+	#[test]
+	fn test_copy_data() {
+		let root = make_tree();
+		let contents = vec![7_u8; 3 << 20];
+		fs::write(root.join("big"), &contents).unwrap();
+
+		let mut dst = File::create(root.join("big-copy")).unwrap();
+		assert_eq!(
+			copy_data(&mut File::open(root.join("big")).unwrap(), &mut dst).unwrap(),
+			3 << 20
+		);
+		assert_eq!(fs::read(root.join("big-copy")).unwrap(), contents);
+
+		// Copying into a file that already has something in it appends.
+		copy_data(&mut File::open(root.join("src/file")).unwrap(), &mut dst).unwrap();
+		assert_eq!(fs::metadata(root.join("big-copy")).unwrap().len(), (3 << 20) + 8);
+
+		// Files that look empty to the kernel are still copied.
+		let mut status = File::create(root.join("status")).unwrap();
+		copy_data(&mut File::open("/proc/self/status").unwrap(), &mut status).unwrap();
+		assert!(fs::read_to_string(root.join("status")).unwrap().starts_with("Name:"));
+	}
+
+	#[test]
+	fn test_remove_tree() {
+		let root = make_tree();
+
+		// The symlink is removed, not what it points at.
+		remove_tree(&root.join("src/dir")).unwrap();
+		assert!(!root.join("src/dir").exists());
+		assert!(root.join("src/file").exists());
+
+		remove_tree(&root).unwrap();
+		assert!(!root.exists());
+		assert!(remove_tree(&root).is_err());
+	}
+
+	#[test]
+	fn test_move_path() {
+		let root = make_tree();
+		move_path(&root.join("src"), &root.join("moved")).unwrap();
+		assert!(!root.join("src").exists());
+		assert_eq!(fs::read_to_string(root.join("moved/dir/nested")).unwrap(), "nested");
+	}
+}
@@ -0,0 +1,297 @@
+use std::{
+	collections::HashSet,
+	fs::{self, Metadata},
+	io,
+	os::unix::fs::MetadataExt,
+	path::{Path, PathBuf},
+};
+
+/// What the walk does when it encounters a symlink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+	/// Yield the symlink itself, without descending into it if it points at a directory.
+	Yield,
+
+	/// Follow symlinks, descending into the directories they point at. Dangling symlinks are yielded as themselves.
+	Follow,
+
+	/// Skip symlinks entirely.
+	Skip,
+}
+
+/// What the walk does after it encounters an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+	/// Ignore the error and carry on walking.
+	Skip,
+
+	/// Yield the error, and stop walking.
+	Fail,
+}
+
+/// A single file or directory found by the walk.
+#[derive(Debug)]
+pub struct WalkEntry {
+	/// The path of the entry, which is always prefixed with the root of the walk.
+	pub path: PathBuf,
+
+	/// How deep into the walk the entry is. The root has a depth of 0.
+	pub depth: usize,
+
+	/// The metadata of the entry. If symlinks are followed, this is the metadata of the file the symlink points at.
+	pub metadata: Metadata,
+
+	/// Whether the path itself is a symlink.
+	pub is_symlink: bool,
+}
+
+type ErrorCallback = Box<dyn FnMut(&Path, &io::Error) -> ErrorAction>;
+
+/// An iterator over a directory tree, yielding each directory before its contents, with the contents of each
+/// directory in sorted order.
+/// Every directory is descended into at most once, so cycles created by symlinks (or bind mounts) can't cause
+/// the walk to loop forever.
+pub struct FsWalk {
+	/// The paths that are still to be yielded, along with their depth.
+	stack: Vec<(PathBuf, usize)>,
+
+	/// What to do with symlinks.
+	symlinks: SymlinkPolicy,
+
+	/// The maximum depth to descend to, if any.
+	max_depth: Option<usize>,
+
+	/// A callback that decides what to do with errors. If not set, errors stop the walk.
+	on_error: Option<ErrorCallback>,
+
+	/// The (device, inode) pairs of the directories that have been descended into.
+	visited: HashSet<(u64, u64)>,
+
+	/// Whether the walk has stopped due to an error.
+	done: bool,
+}
+
+impl FsWalk {
+	/// Creates a new walk, starting at (and including) the given root.
+	/// By default, symlinks are yielded but not followed, there is no maximum depth, and errors stop the walk.
+	pub fn new<P: AsRef<Path>>(root: P) -> Self {
+		Self {
+			stack: vec![(root.as_ref().to_path_buf(), 0)],
+			symlinks: SymlinkPolicy::Yield,
+			max_depth: None,
+			on_error: None,
+			visited: HashSet::new(),
+			done: false,
+		}
+	}
+
+	/// Sets what the walk does when it encounters a symlink.
+	pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+		self.symlinks = policy;
+		self
+	}
+
+	/// Sets the maximum depth the walk descends to. Entries at `max_depth` are yielded, but not descended into.
+	pub fn max_depth(mut self, max_depth: usize) -> Self {
+		self.max_depth = Some(max_depth);
+		self
+	}
+
+	/// Sets a callback that is called with every error the walk encounters, that decides whether the walk
+	/// continues or fails.
+	pub fn on_error<F: FnMut(&Path, &io::Error) -> ErrorAction + 'static>(mut self, callback: F) -> Self {
+		self.on_error = Some(Box::new(callback));
+		self
+	}
+
+	/// Passes the error to the error callback, returning it if the walk should fail.
+	fn handle_error(&mut self, path: &Path, error: io::Error) -> Option<io::Error> {
+		let action = match self.on_error.as_mut() {
+			Some(callback) => callback(path, &error),
+			None => ErrorAction::Fail,
+		};
+
+		match action {
+			ErrorAction::Skip => None,
+			ErrorAction::Fail => {
+				self.done = true;
+				Some(io::Error::new(error.kind(), format!("{}: {}", path.display(), error)))
+			}
+		}
+	}
+
+	/// Reads the entry at the given path, applying the symlink policy. Returns None if the entry should be skipped.
+	fn read_entry(&self, path: PathBuf, depth: usize) -> io::Result<Option<WalkEntry>> {
+		let metadata = fs::symlink_metadata(&path)?;
+		if !metadata.is_symlink() {
+			return Ok(Some(WalkEntry {
+				path,
+				depth,
+				metadata,
+				is_symlink: false,
+			}));
+		}
+
+		let metadata = match self.symlinks {
+			SymlinkPolicy::Skip => return Ok(None),
+			SymlinkPolicy::Yield => metadata,
+			SymlinkPolicy::Follow => match fs::metadata(&path) {
+				Ok(metadata) => metadata,
+				Err(e) if e.kind() == io::ErrorKind::NotFound => metadata,
+				Err(e) => return Err(e),
+			},
+		};
+
+		Ok(Some(WalkEntry {
+			path,
+			depth,
+			metadata,
+			is_symlink: true,
+		}))
+	}
+
+	/// Queues up the contents of the given directory to be walked.
+	fn descend(&mut self, entry: &WalkEntry) -> io::Result<()> {
+		if !entry.metadata.is_dir() || self.max_depth.is_some_and(|max| entry.depth >= max) {
+			return Ok(());
+		}
+
+		if entry.is_symlink && self.symlinks != SymlinkPolicy::Follow {
+			return Ok(());
+		}
+
+		if !self.visited.insert((entry.metadata.dev(), entry.metadata.ino())) {
+			// We've already been in this directory, so this is a cycle (or a second path to the same place).
+			return Ok(());
+		}
+
+		let mut children = Vec::new();
+		for child in fs::read_dir(&entry.path)? {
+			children.push(child?.path());
+		}
+
+		// The stack is popped from the end, so reverse sorting it means we walk in sorted order.
+		children.sort_unstable_by(|a, b| b.cmp(a));
+		self.stack
+			.extend(children.into_iter().map(|child| (child, entry.depth + 1)));
+
+		Ok(())
+	}
+}
+
+impl Iterator for FsWalk {
+	type Item = io::Result<WalkEntry>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		while !self.done {
+			let (path, depth) = self.stack.pop()?;
+			let entry = match self.read_entry(path.clone(), depth) {
+				Ok(Some(entry)) => entry,
+				Ok(None) => continue,
+				Err(e) => match self.handle_error(&path, e) {
+					Some(e) => return Some(Err(e)),
+					None => continue,
+				},
+			};
+
+			if let Err(e) = self.descend(&entry) {
+				if let Some(e) = self.handle_error(&entry.path, e) {
+					return Some(Err(e));
+				}
+			}
+
+			return Some(Ok(entry));
+		}
+
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		fs::{self, File},
+		os::unix::fs::symlink,
+		path::Path,
+	};
+
+	use super::{FsWalk, SymlinkPolicy};
+	use crate::testing::TempDir;
+
+	/// Creates a fresh directory tree to walk:
+	/// root/a/file
+	/// root/a/loop -> root
+	/// root/b
+	/// root/link -> root/a/file
+	fn make_tree() -> TempDir {
+		let root = TempDir::new("fswalk").unwrap();
+		fs::create_dir_all(root.join("a")).unwrap();
+		fs::create_dir_all(root.join("b")).unwrap();
+		File::create(root.join("a/file")).unwrap();
+		symlink(root.path(), root.join("a/loop")).unwrap();
+		symlink(root.join("a/file"), root.join("link")).unwrap();
+		root
+	}
+
+	fn walk_names(root: &Path, walk: FsWalk) -> Vec<String> {
+		walk.map(|e| e.unwrap().path.strip_prefix(root).unwrap().display().to_string())
+			.collect()
+	}
+
+	#[test]
+	fn test_yield_symlinks() {
+		let root = make_tree();
+		assert_eq!(
+			walk_names(&root, FsWalk::new(&root)),
+			vec!["", "a", "a/file", "a/loop", "b", "link"]
+		);
+	}
+
+	#[test]
+	fn test_skip_symlinks() {
+		let root = make_tree();
+		assert_eq!(
+			walk_names(&root, FsWalk::new(&root).symlinks(SymlinkPolicy::Skip)),
+			vec!["", "a", "a/file", "b"]
+		);
+	}
+
+	#[test]
+	fn test_follow_symlinks_with_cycle() {
+		let root = make_tree();
+		let entries = FsWalk::new(&root)
+			.symlinks(SymlinkPolicy::Follow)
+			.map(|e| e.unwrap())
+			.collect::<Vec<_>>();
+
+		// The loop is yielded (as a directory), but not descended into.
+		let names = entries
+			.iter()
+			.map(|e| e.path.strip_prefix(&root).unwrap().display().to_string())
+			.collect::<Vec<_>>();
+		assert_eq!(names, vec!["", "a", "a/file", "a/loop", "b", "link"]);
+		assert!(entries[3].is_symlink && entries[3].metadata.is_dir());
+		assert!(entries[5].is_symlink && entries[5].metadata.is_file());
+	}
+
+	#[test]
+	fn test_max_depth() {
+		let root = make_tree();
+		assert_eq!(
+			walk_names(&root, FsWalk::new(&root).max_depth(1)),
+			vec!["", "a", "b", "link"]
+		);
+	}
+
+	#[test]
+	fn test_errors() {
+		let directory = TempDir::new("fswalk").unwrap();
+		let root = directory.join("missing");
+		let mut walk = FsWalk::new(&root);
+		assert!(walk.next().unwrap().is_err());
+		assert!(walk.next().is_none());
+
+		let mut walk = FsWalk::new(&root).on_error(|_, _| super::ErrorAction::Skip);
+		assert!(walk.next().is_none());
+	}
+}
@@ -0,0 +1,219 @@
+/// A part of a glob pattern. Every part matches a single character, apart from `*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+	/// A character that only matches itself.
+	Literal(char),
+
+	/// `?`, which matches any character.
+	Any,
+
+	/// `*`, which matches any number of characters, including none.
+	Star,
+
+	/// A bracket expression, e.g. `[a-z]` or `[!0-9]`, which matches any character in (or, if it's negated, not in)
+	/// its ranges. Single characters are ranges that start and end with themselves.
+	Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+/// A shell style glob pattern, supporting `*`, `?`, and bracket expressions (e.g. `[a-z]`, `[!0-9]`), but not braces.
+/// Patterns are parsed once, and matched without backtracking more than one `*` at a time, so that matching lots of
+/// names against lots of patterns (e.g. every module alias against every device) stays cheap.
+#[derive(Debug, Clone)]
+pub struct Glob {
+	tokens: Vec<Token>,
+	case_insensitive: bool,
+}
+
+impl Glob {
+	pub fn new(pattern: &str) -> Self {
+		Self::from_quoted(&pattern.chars().map(|c| (c, false)).collect::<Vec<_>>())
+	}
+
+	/// Parses a pattern where each character is paired with whether it was quoted. Quoted characters always match
+	/// themselves, so that e.g. a quoted `*` isn't a wildcard.
+	pub fn from_quoted(pattern: &[(char, bool)]) -> Self {
+		let mut tokens = Vec::new();
+		let mut i = 0;
+		while i < pattern.len() {
+			let (c, quoted) = pattern[i];
+			i += 1;
+
+			let token = match c {
+				_ if quoted => Token::Literal(c),
+				'?' => Token::Any,
+				// Runs of stars match the same as one star, but are slower to match.
+				'*' if tokens.last() == Some(&Token::Star) => continue,
+				'*' => Token::Star,
+				'[' => match parse_class(&pattern[i..]) {
+					Some((class, length)) => {
+						i += length;
+						class
+					}
+					// A `[` without a closing `]` is just a `[`.
+					None => Token::Literal('['),
+				},
+				_ => Token::Literal(c),
+			};
+
+			tokens.push(token);
+		}
+
+		Self {
+			tokens,
+			case_insensitive: false,
+		}
+	}
+
+	/// Sets whether letters match regardless of their case, including in ranges (so `[a-c]` matches `B`).
+	pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+		self.case_insensitive = case_insensitive;
+		self
+	}
+
+	/// Whether the whole of the name matches the pattern.
+	pub fn matches(&self, name: &str) -> bool {
+		// The position of the last `*` in the pattern, along with the position in the name that it's matched up to, so
+		// we can backtrack to it and have it consume one more character. Only the last star ever needs to be retried,
+		// as everything else matches a single character.
+		let mut star: Option<(usize, usize)> = None;
+		let (mut t, mut n) = (0, 0);
+		while let Some(c) = name[n..].chars().next() {
+			match self.tokens.get(t) {
+				Some(Token::Star) => {
+					star = Some((t, n));
+					t += 1;
+					continue;
+				}
+				Some(token) if self.matches_char(token, c) => {
+					t += 1;
+					n += c.len_utf8();
+					continue;
+				}
+				_ => {}
+			}
+
+			let Some((star_t, star_n)) = star else {
+				return false;
+			};
+
+			let skipped = name[star_n..]
+				.chars()
+				.next()
+				.expect("BUG: the star matched up to the end of the name");
+			t = star_t + 1;
+			n = star_n + skipped.len_utf8();
+			star = Some((star_t, n));
+		}
+
+		self.tokens[t..].iter().all(|token| *token == Token::Star)
+	}
+
+	fn matches_char(&self, token: &Token, c: char) -> bool {
+		match token {
+			Token::Literal(literal) => *literal == c || (self.case_insensitive && lowercase(*literal) == lowercase(c)),
+			Token::Any => true,
+			Token::Star => false,
+			Token::Class { negated, ranges } => {
+				let in_ranges = |c: char| ranges.iter().any(|(low, high)| *low <= c && c <= *high);
+				let matched =
+					in_ranges(c) || (self.case_insensitive && (in_ranges(lowercase(c)) || in_ranges(uppercase(c))));
+				matched != *negated
+			}
+		}
+	}
+}
+
+fn lowercase(c: char) -> char {
+	c.to_lowercase().next().unwrap_or(c)
+}
+
+fn uppercase(c: char) -> char {
+	c.to_uppercase().next().unwrap_or(c)
+}
+
+/// Parses the bracket expression that follows a `[`, returning it along with its length (including the closing `]`),
+/// or None if it isn't closed.
+fn parse_class(pattern: &[(char, bool)]) -> Option<(Token, usize)> {
+	let negated = matches!(pattern.first(), Some(('!' | '^', false)));
+	let mut i = usize::from(negated);
+	let mut ranges = Vec::new();
+
+	// A `]` straight after the `[` (or the negation) is part of the set, rather than closing it.
+	let start = i;
+	while i < pattern.len() {
+		let (low, quoted) = pattern[i];
+		if low == ']' && !quoted && i > start {
+			return Some((Token::Class { negated, ranges }, i + 1));
+		}
+
+		match (pattern.get(i + 1), pattern.get(i + 2)) {
+			(Some(('-', false)), Some(&(high, quoted))) if high != ']' || quoted => {
+				ranges.push((low, high));
+				i += 3;
+			}
+			_ => {
+				ranges.push((low, low));
+				i += 1;
+			}
+		}
+	}
+
+	None
+}
+
+#[cfg(test)]
+mod test {
+	use super::Glob;
+
+	fn is_match(pattern: &str, name: &str) -> bool {
+		Glob::new(pattern).matches(name)
+	}
+
+	#[test]
+	fn test_matches() {
+		assert!(is_match("*.rs", "main.rs"));
+		assert!(is_match("*", ""));
+		assert!(!is_match("*.rs", "main.rso"));
+		assert!(is_match("a?c", "abc"));
+		assert!(!is_match("a?c", "ac"));
+		assert!(is_match("*a*b", "xaaab"));
+		assert!(!is_match("*a*b", "xaaabc"));
+		assert!(is_match("é?*", "éñ"));
+
+		// Characters that mean something in a regex don't here.
+		assert!(is_match("pci:v.*", "pci:v.0000"));
+		assert!(!is_match("pci:v.*", "pci:vX0000"));
+		assert!(is_match("a+b", "a+b"));
+	}
+
+	#[test]
+	fn test_classes() {
+		assert!(is_match("[a-c]x", "bx"));
+		assert!(!is_match("[!a-c]x", "bx"));
+		assert!(is_match("[^a-c]x", "dx"));
+		assert!(is_match("[]]", "]"));
+		assert!(is_match("[!]]", "a"));
+		assert!(is_match("[a-]", "-"));
+		assert!(is_match("[0-9A-F]*", "Fa"));
+		assert!(!is_match("[0-9A-F]*", "fa"));
+		assert!(is_match("a[b", "a[b"));
+		assert!(is_match("usb:v*p*d*dc[0-9]*", "usb:v1D6Bp0002d0515dc09"));
+
+		// Quoted characters only match themselves, even in a class.
+		let quoted = Glob::from_quoted(&[('*', true), ('.', false)]);
+		assert!(quoted.matches("*."));
+		assert!(!quoted.matches("a."));
+		let class = Glob::from_quoted(&[('[', false), ('a', false), ('-', true), ('c', false), (']', false)]);
+		assert!(class.matches("-"));
+		assert!(!class.matches("b"));
+	}
+
+	#[test]
+	fn test_case_insensitive() {
+		let glob = Glob::new("PCI:v[a-f]*").with_case_insensitive(true);
+		assert!(glob.matches("pci:vB1"));
+		assert!(glob.matches("Pci:Ve"));
+		assert!(!glob.matches("pci:vg"));
+		assert!(!Glob::new("PCI:*").matches("pci:v"));
+	}
+}
@@ -1,10 +1,20 @@
 use std::{
 	fs::File,
-	io::{self, BufRead, BufReader, Read, Write},
-	os::fd::{FromRawFd, RawFd},
+	io::{self, Read, Write},
+	os::fd::{BorrowedFd, FromRawFd, RawFd},
+	time::{Duration, Instant},
 };
 
-use nix::unistd::{pipe, read};
+use nix::{
+	errno::Errno,
+	libc,
+	poll::{poll, PollFd, PollFlags},
+	unistd::{pipe, read},
+};
+
+use crate::lineedit::{with_editor, Echo};
+
+nix::ioctl_read_bad!(get_window_size, libc::TIOCGWINSZ, libc::winsize);
 
 /// The standard input file descriptor.
 pub const STDIN_FD: i32 = 0;
@@ -68,14 +78,6 @@ impl IOTriple {
 
 		Ok((read, write))
 	}
-
-	/// Prompts the user for input on the stdout fd, reads a line from the stdin fd, and returns the input.
-	pub fn prompt(&self, prompt: &str) -> io::Result<String> {
-		write!(self.stdout(), "{} ", prompt)?;
-		let mut input = String::new();
-		BufReader::new(self.stdin()).read_line(&mut input)?;
-		Ok(input.trim_end().to_owned())
-	}
 }
 
 impl Default for IOTriple {
@@ -102,3 +104,119 @@ impl Read for RawFdReader {
 		read(self.0, buf).map_err(io::Error::from)
 	}
 }
+
+/// Returns the size of the terminal that the given file descriptor refers to, as (rows, columns), or None if it isn't a
+/// terminal.
+pub fn terminal_size(fd: RawFd) -> Option<(usize, usize)> {
+	let mut size = libc::winsize {
+		ws_row: 0,
+		ws_col: 0,
+		ws_xpixel: 0,
+		ws_ypixel: 0,
+	};
+
+	match unsafe { get_window_size(fd, &mut size) } {
+		Ok(_) if size.ws_row > 0 => Some((size.ws_row as usize, size.ws_col as usize)),
+		_ => None,
+	}
+}
+
+/// Waits up to the timeout for the file descriptor to have something to read (or to reach the end of its input),
+/// returning whether it does. Waits that are interrupted by a signal carry on for the rest of the timeout.
+pub fn wait_readable(fd: RawFd, timeout: Duration) -> io::Result<bool> {
+	let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+	let deadline = Instant::now() + timeout;
+	loop {
+		// poll only takes whole milliseconds, and can wake up a little early, so round up, and check the deadline.
+		let remaining = deadline.saturating_duration_since(Instant::now());
+		let milliseconds = remaining.as_micros().div_ceil(1000).min(libc::c_int::MAX as u128) as libc::c_int;
+		let mut fds = [PollFd::new(&fd, PollFlags::POLLIN)];
+		match poll(&mut fds, milliseconds) {
+			Ok(0) if Instant::now() < deadline => continue,
+			Ok(0) => return Ok(false),
+			Ok(_) => return Ok(true),
+			Err(Errno::EINTR) => continue,
+			Err(e) => return Err(e.into()),
+		}
+	}
+}
+
+/// Prompts for a line on the terminal of the IO triple like `lineedit::prompt`, but gives up and returns the default
+/// if nothing is typed before the timeout, e.g. so that booting can carry on when there's nobody at the console. Once
+/// something has been typed, the rest of the line is waited for however long it takes. An empty line also takes the
+/// default.
+pub fn prompt_with_timeout(triple: &IOTriple, prompt: &str, timeout: Duration, default: &str) -> io::Result<String> {
+	// The terminal has to hand over input a key at a time, so that the first key ends the wait rather than the whole
+	// line.
+	with_editor(triple, Echo::Normal, |editor| {
+		editor.start(&format!("{} ", prompt));
+		if !wait_readable(triple.stdin, timeout)? {
+			editor.finish();
+			return Ok(default.to_owned());
+		}
+
+		loop {
+			let key = editor.read_key()?;
+			if let Some(line) = editor.edit(key) {
+				return match line.trim() {
+					"" => Ok(default.to_owned()),
+					line => Ok(line.to_owned()),
+				};
+			}
+		}
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use std::{
+		fs::File,
+		os::fd::{AsRawFd, FromRawFd},
+		time::{Duration, Instant},
+	};
+
+	use nix::unistd::{close, pipe, write};
+
+	use super::{prompt_with_timeout, wait_readable, IOTriple};
+
+	/// Prompts with the given input piped in, returning the answer.
+	fn prompt(input: Option<&[u8]>, timeout: Duration) -> String {
+		let (read_end, write_end) = pipe().unwrap();
+		if let Some(input) = input {
+			write(write_end, input).unwrap();
+		}
+
+		let output = File::options().write(true).open("/dev/null").unwrap();
+		let triple = IOTriple {
+			stdin: read_end,
+			stdout: output.as_raw_fd(),
+			stderr: output.as_raw_fd(),
+		};
+
+		let answer = prompt_with_timeout(&triple, "sphere? [user]", timeout, "user").unwrap();
+		close(write_end).unwrap();
+		drop(unsafe { File::from_raw_fd(read_end) });
+		answer
+	}
+
+	#[test]
+	fn test_prompt_with_timeout() {
+		let start = Instant::now();
+		assert_eq!(prompt(None, Duration::from_millis(50)), "user");
+		assert!(start.elapsed() >= Duration::from_millis(50));
+
+		assert_eq!(prompt(Some(b"rescue\n"), Duration::from_secs(5)), "rescue");
+		assert_eq!(prompt(Some(b"minx\x7fimal \n"), Duration::from_secs(5)), "minimal");
+		assert_eq!(prompt(Some(b"\n"), Duration::from_secs(5)), "user");
+	}
+
+	#[test]
+	fn test_wait_readable() {
+		let (read_end, write_end) = pipe().unwrap();
+		assert!(!wait_readable(read_end, Duration::from_millis(10)).unwrap());
+		write(write_end, b"x").unwrap();
+		assert!(wait_readable(read_end, Duration::ZERO).unwrap());
+		close(read_end).unwrap();
+		close(write_end).unwrap();
+	}
+}
@@ -1,5 +1,12 @@
+pub mod cmdline;
+pub mod config;
+pub mod fsops;
+pub mod fswalk;
+pub mod glob;
 pub mod io;
 pub mod iter;
+pub mod lineedit;
 pub mod obs;
 pub mod qinit;
 pub mod rand;
+pub mod testing;
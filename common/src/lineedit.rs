@@ -0,0 +1,507 @@
+use std::{
+	cmp::Ordering,
+	fs::File,
+	io::{self, Read, Write},
+	mem::ManuallyDrop,
+	os::fd::{BorrowedFd, FromRawFd},
+};
+
+use escapes::{ANSIEscapeSequence, AnsiParserError, Capabilities, CursorBack, CursorForward, EraseInLine, ESC};
+use nix::{
+	errno::Errno,
+	sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg, Termios},
+};
+
+use crate::io::IOTriple;
+
+/// The ASCII character for DEL, which most terminals send for Backspace.
+const DELETE_CHAR: u8 = 0x7f;
+
+/// The ASCII character for BS, which some terminals send for Backspace instead.
+const BACKSPACE_CHAR: u8 = 0x08;
+
+/// The escape sequence that ends the line in terminals that send it for the End key.
+const END_KEY: char = 'F';
+
+/// Puts a terminal into raw(ish) mode, reading input a character at a time without echoing it, and puts it back how
+/// it was when dropped. Signals are still generated, so that Ctrl-C works as usual.
+pub struct RawMode<'fd> {
+	fd: BorrowedFd<'fd>,
+	original: Termios,
+}
+
+impl<'fd> RawMode<'fd> {
+	/// Turns off canonical mode and echo on the terminal.
+	pub fn enable(fd: BorrowedFd<'fd>) -> io::Result<Self> {
+		let original = tcgetattr(fd)?;
+
+		// Canonical mode buffers input until a newline is received, and echo prints input back to the user. Without
+		// them, input is read one character at a time, and it's up to the reader to show it.
+		let mut raw = original.clone();
+		raw.local_flags &= !(LocalFlags::ICANON | LocalFlags::ECHO);
+		tcsetattr(fd, SetArg::TCSANOW, &raw)?;
+
+		Ok(Self { fd, original })
+	}
+}
+
+impl Drop for RawMode<'_> {
+	fn drop(&mut self) {
+		// There's nothing to be done if the terminal can't be restored, e.g. because it has gone away.
+		let _ = tcsetattr(self.fd, SetArg::TCSANOW, &self.original);
+	}
+}
+
+/// How the characters that are typed are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Echo {
+	/// As they are.
+	Normal,
+
+	/// As the given character, e.g. `*` for passwords.
+	Masked(char),
+
+	/// Not at all, not even how many there are.
+	Hidden,
+}
+
+/// A key pressed at the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+	Char(char),
+	Enter,
+	Tab,
+	Backspace,
+	Up,
+	Down,
+	Left,
+	Right,
+	Home,
+	End,
+
+	/// A control character, by the (lowercase) letter it's typed with, e.g. `Ctrl('r')` for Ctrl-R.
+	Ctrl(char),
+
+	/// An escape sequence that isn't a key that's understood here.
+	Unknown,
+}
+
+/// Reads lines from a terminal that's in raw mode, with the basic editing keys: moving around the line with the arrow
+/// keys, Home and End (or Ctrl-A and Ctrl-E), deleting with Backspace, and clearing before or after the cursor with
+/// Ctrl-U and Ctrl-K. Callers that want more (e.g. history) can read keys themselves, handling the ones they want and
+/// passing the rest to `edit`.
+pub struct LineEditor<R: Read, W: Write> {
+	/// The line being edited.
+	buffer: String,
+
+	/// The position of the cursor in the buffer.
+	position: usize,
+	reader: R,
+	writer: W,
+
+	/// The prompt of the line being read, to redraw it along with the line.
+	prompt: String,
+
+	/// What the terminal can do. Without cursor addressing, the whole line is rewritten after every change, using only
+	/// carriage returns and backspaces.
+	capabilities: Capabilities,
+
+	/// How typed characters are shown.
+	echo: Echo,
+
+	/// The number of characters on the line on the screen, so that a shorter line can be written over it on terminals
+	/// that can't erase lines.
+	drawn: usize,
+}
+
+impl<R: Read, W: Write> LineEditor<R, W> {
+	pub fn new(reader: R, writer: W) -> Self {
+		LineEditor {
+			buffer: String::new(),
+			position: 0,
+			reader,
+			writer,
+			prompt: String::new(),
+			capabilities: Capabilities::VT100,
+			echo: Echo::Normal,
+			drawn: 0,
+		}
+	}
+
+	pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+		self.capabilities = capabilities;
+		self
+	}
+
+	pub fn with_echo(mut self, echo: Echo) -> Self {
+		self.echo = echo;
+		self
+	}
+
+	/// Reads a line, after showing the prompt.
+	pub fn read_line(&mut self, prompt: &str) -> io::Result<String> {
+		self.start(prompt);
+		loop {
+			let key = self.read_key()?;
+			if let Some(line) = self.edit(key) {
+				return Ok(line);
+			}
+		}
+	}
+
+	/// Starts reading a new line, showing the prompt.
+	pub fn start(&mut self, prompt: &str) {
+		prompt.clone_into(&mut self.prompt);
+		self.write(prompt);
+		self.drawn = prompt.chars().count();
+	}
+
+	/// Applies a key to the line, returning the line if it's finished. Keys that aren't editing keys are ignored.
+	pub fn edit(&mut self, key: Key) -> Option<String> {
+		match key {
+			Key::Enter => return Some(self.finish()),
+			Key::Char(c) => self.push_char(c),
+			Key::Backspace => self.backspace(),
+			Key::Left => self.move_cursor(-1),
+			Key::Right => self.move_cursor(1),
+			Key::Home | Key::Ctrl('a') => self.move_cursor(-(self.position as isize)),
+			Key::End | Key::Ctrl('e') => self.move_cursor((self.buffer.len() - self.position) as isize),
+			Key::Ctrl('u') => {
+				self.buffer.replace_range(..self.position, "");
+				self.position = 0;
+				self.redraw_line();
+			}
+			Key::Ctrl('k') => {
+				self.buffer.truncate(self.position);
+				self.redraw_line();
+			}
+			_ => {}
+		}
+
+		None
+	}
+
+	/// Reads a single key from the input. Unlike `read_exact`, this returns reads that are interrupted by a signal, so
+	/// the caller can react to it.
+	pub fn read_key(&mut self) -> io::Result<Key> {
+		let key = match self.read_byte()? {
+			b'\n' | b'\r' => Key::Enter,
+			b'\t' => Key::Tab,
+			DELETE_CHAR | BACKSPACE_CHAR => Key::Backspace,
+			b if b == ESC as u8 => match ANSIEscapeSequence::read(&mut self.reader) {
+				Ok(ANSIEscapeSequence::CursorUp(_)) => Key::Up,
+				Ok(ANSIEscapeSequence::CursorDown(_)) => Key::Down,
+				Ok(ANSIEscapeSequence::CursorForward(_)) => Key::Right,
+				Ok(ANSIEscapeSequence::CursorBack(_)) => Key::Left,
+				Ok(ANSIEscapeSequence::CursorPosition(_)) => Key::Home,
+				Err(AnsiParserError::Unsupported(END_KEY)) => Key::End,
+				Err(AnsiParserError::IO(e)) if e.kind() != io::ErrorKind::InvalidData => return Err(e),
+				_ => Key::Unknown,
+			},
+			b if b < 0x20 => Key::Ctrl((b + 0x60) as char),
+			b => Key::Char(b as char),
+		};
+
+		Ok(key)
+	}
+
+	/// Reads a single byte from the input.
+	fn read_byte(&mut self) -> io::Result<u8> {
+		let mut byte = [0; 1];
+		match self.reader.read(&mut byte)? {
+			0 => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "end of input")),
+			_ => Ok(byte[0]),
+		}
+	}
+
+	/// The line being edited.
+	pub fn line(&self) -> &str {
+		&self.buffer
+	}
+
+	/// The position of the cursor in the line.
+	pub fn position(&self) -> usize {
+		self.position
+	}
+
+	/// Ends the line, returning it, and leaving the cursor at the start of the next one.
+	pub fn finish(&mut self) -> String {
+		self.write("\n");
+		self.position = 0;
+		std::mem::take(&mut self.buffer)
+	}
+
+	/// Writes the text above the line being edited, e.g. to list completions, and then redraws the line under it.
+	pub fn print_above(&mut self, text: &str) {
+		self.write(&format!("\r\n{}", text.replace('\n', "\r\n")));
+		self.drawn = 0;
+		self.redraw_line();
+	}
+
+	/// Inserts the text into the line at the cursor, leaving the cursor after it.
+	pub fn insert_str(&mut self, text: &str) {
+		self.buffer.insert_str(self.position, text);
+		if !self.capabilities.cursor_addressing {
+			self.position += text.len();
+			self.redraw_line();
+			return;
+		}
+
+		let rest = self.shown(&self.buffer[self.position..]);
+		self.write(&format!("{}{}", EraseInLine(0), rest));
+		self.position += text.len();
+		self.cursor_back(self.width(&self.buffer[self.position..]));
+	}
+
+	/// Replaces the line being edited with the given one, leaving the cursor at the end of it.
+	pub fn replace_line(&mut self, line: &str) {
+		if !self.capabilities.cursor_addressing {
+			self.redraw(line.to_owned());
+			return;
+		}
+
+		self.move_cursor(-(self.position as isize));
+		self.write(&format!("{}{}", EraseInLine(0), self.shown(line)));
+		line.clone_into(&mut self.buffer);
+		self.position = self.buffer.len();
+	}
+
+	/// Redraws the whole line, including the prompt, with the given contents, leaving the cursor at the end of it.
+	pub fn redraw(&mut self, line: String) {
+		self.buffer = line;
+		self.position = self.buffer.len();
+		self.redraw_line();
+	}
+
+	/// Redraws the whole line, including the prompt, and puts the cursor back where it was.
+	pub fn redraw_line(&mut self) {
+		self.write_line(&format!("{}{}", self.prompt, self.shown(&self.buffer)));
+		self.cursor_back(self.width(&self.buffer[self.position..]));
+	}
+
+	/// Writes over the line on the screen with the given one (e.g. a search prompt), leaving the cursor at the end of
+	/// it.
+	pub fn write_line(&mut self, line: &str) {
+		if self.capabilities.cursor_addressing {
+			self.write(&format!("\r{}{}", EraseInLine(0), line));
+			return;
+		}
+
+		// Whatever is left of a longer line is blanked out with spaces, and then the cursor is moved back over them.
+		let len = line.chars().count();
+		let blank = self.drawn.saturating_sub(len);
+		self.write(&format!("\r{}{}{}", line, " ".repeat(blank), "\x08".repeat(blank)));
+		self.drawn = len;
+	}
+
+	/// Returns the text as it's shown on the screen.
+	fn shown(&self, text: &str) -> String {
+		match self.echo {
+			Echo::Normal => text.to_owned(),
+			Echo::Masked(mask) => mask.to_string().repeat(text.chars().count()),
+			Echo::Hidden => String::new(),
+		}
+	}
+
+	/// Returns how many columns the text takes up on the screen.
+	fn width(&self, text: &str) -> usize {
+		match self.echo {
+			Echo::Normal | Echo::Masked(_) => text.chars().count(),
+			Echo::Hidden => 0,
+		}
+	}
+
+	/// Moves the cursor back by the given number of columns.
+	fn cursor_back(&mut self, amt: usize) {
+		if amt == 0 {
+			return;
+		}
+
+		if self.capabilities.cursor_addressing {
+			self.write(&CursorBack(amt as u8).to_string());
+		} else {
+			self.write(&"\x08".repeat(amt));
+		}
+	}
+
+	/// Move the cursor by the given amount across the line.
+	fn move_cursor(&mut self, amt: isize) {
+		// Find the new position and clamp it to the bounds of the line.
+		let new_position = (self.position as isize + amt).clamp(0, self.buffer.len() as isize) as usize;
+		let (start, end) = (self.position.min(new_position), self.position.max(new_position));
+		let passed = self.width(&self.buffer[start..end]);
+
+		match new_position.cmp(&self.position) {
+			Ordering::Less => self.cursor_back(passed),
+			// Without cursor addressing, moving forward rewrites the characters that are passed over.
+			Ordering::Greater if !self.capabilities.cursor_addressing => {
+				let shown = self.shown(&self.buffer[start..end]);
+				self.write(&shown);
+			}
+			Ordering::Greater if passed > 0 => self.write(&CursorForward(passed as u8).to_string()),
+			_ => {}
+		}
+
+		self.position = new_position;
+	}
+
+	/// Add a character to the line at the cursor.
+	fn push_char(&mut self, c: char) {
+		self.buffer.insert(self.position, c);
+		self.position += c.len_utf8();
+
+		// Typing at the end of the line only needs the character to be written.
+		if self.position == self.buffer.len() {
+			let shown = self.shown(&self.buffer[self.position - c.len_utf8()..]);
+			self.drawn += shown.chars().count();
+			self.write(&shown);
+			return;
+		}
+
+		self.rerender_from(self.position - c.len_utf8());
+	}
+
+	/// Remove the character before the cursor.
+	fn backspace(&mut self) {
+		let Some(c) = self.buffer[..self.position].chars().next_back() else {
+			return;
+		};
+
+		self.position -= c.len_utf8();
+		self.buffer.remove(self.position);
+		if !self.capabilities.cursor_addressing {
+			self.redraw_line();
+			return;
+		}
+
+		self.cursor_back(self.width(&c.to_string()));
+		self.rerender_from(self.position);
+	}
+
+	/// Rewrites the line from the given position, and then puts the cursor back where it was.
+	fn rerender_from(&mut self, start: usize) {
+		if !self.capabilities.cursor_addressing {
+			self.redraw_line();
+			return;
+		}
+
+		let rest = self.shown(&self.buffer[start..]);
+		self.write(&format!("{}{}", EraseInLine(0), rest));
+		self.cursor_back(self.width(&self.buffer[self.position..]));
+	}
+
+	/// Writes to the terminal. Line editing can't carry on without it, so failures are fatal.
+	fn write(&mut self, text: &str) {
+		write!(self.writer, "{}", text).expect("Failed to write to the terminal");
+	}
+}
+
+/// Prompts for a line on the terminal of the IO triple, with line editing, putting the terminal back how it was once
+/// the line has been read. The prompt is followed by a space.
+pub fn prompt(triple: &IOTriple, prompt: &str, echo: Echo) -> io::Result<String> {
+	with_editor(triple, echo, |editor| {
+		let line = editor.read_line(&format!("{} ", prompt))?;
+		Ok(line.trim_end().to_owned())
+	})
+}
+
+/// Calls the function with a line editor on the terminal of the IO triple, which is put into raw mode so that input is
+/// handed over a key at a time, and put back how it was once the function returns. If the input isn't a terminal
+/// (e.g. it's piped in), nothing is echoed, as it wouldn't be by the terminal either.
+pub fn with_editor<T>(
+	triple: &IOTriple,
+	echo: Echo,
+	f: impl FnOnce(&mut LineEditor<&File, &File>) -> io::Result<T>,
+) -> io::Result<T> {
+	// The file descriptors belong to the triple, so mustn't be closed here.
+	let (stdin, stdout) = unsafe {
+		(
+			ManuallyDrop::new(File::from_raw_fd(triple.stdin)),
+			ManuallyDrop::new(File::from_raw_fd(triple.stdout)),
+		)
+	};
+
+	let (_raw, echo) = match RawMode::enable(unsafe { BorrowedFd::borrow_raw(triple.stdin) }) {
+		Ok(raw) => (Some(raw), echo),
+		Err(e) if e.raw_os_error() == Some(Errno::ENOTTY as i32) => (None, Echo::Hidden),
+		Err(e) => return Err(e),
+	};
+
+	let mut editor = LineEditor::new(&*stdin, &*stdout)
+		.with_capabilities(Capabilities::from_env())
+		.with_echo(echo);
+	f(&mut editor)
+}
+
+#[cfg(test)]
+mod test {
+	use escapes::Capabilities;
+
+	use super::{Echo, Key, LineEditor};
+
+	/// Reads a line from the input, as if it was typed at the terminal, returning it and what was written.
+	fn read_line(input: &[u8], capabilities: Capabilities, echo: Echo) -> (String, String) {
+		let mut output = Vec::new();
+		let mut editor = LineEditor::new(input, &mut output)
+			.with_capabilities(capabilities)
+			.with_echo(echo);
+		let line = editor.read_line("> ").unwrap();
+		drop(editor);
+		(line, String::from_utf8(output).unwrap())
+	}
+
+	#[test]
+	fn test_read_key() {
+		let mut editor = LineEditor::new(&b"a\r\t\x7f\x1b[A\x1b[D\x1b[H\x1b[F\x12\x1b[3~"[..], Vec::new());
+		let keys = (0..10).map(|_| editor.read_key().unwrap()).collect::<Vec<_>>();
+		assert_eq!(
+			keys,
+			vec![
+				Key::Char('a'),
+				Key::Enter,
+				Key::Tab,
+				Key::Backspace,
+				Key::Up,
+				Key::Left,
+				Key::Home,
+				Key::End,
+				Key::Ctrl('r'),
+				Key::Unknown
+			]
+		);
+		assert!(editor.read_key().is_err());
+	}
+
+	#[test]
+	fn test_editing() {
+		// Insert in the middle, jump to either end, and delete around the cursor.
+		let (line, _) = read_line(b"ac\x1b[Db\x01>\x05<\n", Capabilities::VT100, Echo::Normal);
+		assert_eq!(line, ">abc<");
+
+		let (line, _) = read_line(
+			b"hello world\x1b[D\x1b[D\x1b[D\x1b[D\x1b[D\x15\x0b\n",
+			Capabilities::VT100,
+			Echo::Normal,
+		);
+		assert_eq!(line, "");
+
+		let (line, _) = read_line(
+			b"hello world\x1b[D\x1b[D\x1b[D\x1b[D\x1b[D\x0b\n",
+			Capabilities::VT100,
+			Echo::Normal,
+		);
+		assert_eq!(line, "hello ");
+	}
+
+	#[test]
+	fn test_echo() {
+		let (line, output) = read_line(b"pass\x7f\x7fss\n", Capabilities::VT100, Echo::Masked('*'));
+		assert_eq!(line, "pass");
+		assert!(!output.contains("pa"));
+		assert!(output.starts_with("> ****"));
+
+		let (line, output) = read_line(b"secret\x1b[D\x7f\n", Capabilities::DUMB, Echo::Hidden);
+		assert_eq!(line, "secrt");
+		assert_eq!(output.trim_end(), "> \r>");
+	}
+}
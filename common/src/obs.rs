@@ -1,8 +1,230 @@
-use std::{io::Write, sync::Mutex};
+use std::{
+	any::Any,
+	backtrace::Backtrace,
+	env,
+	future::Future,
+	io::Write,
+	panic,
+	sync::{Mutex, OnceLock},
+	thread,
+};
 
-use slog::{o, Drain};
+use slog::{crit, o, Discard, Drain, Level, Logger, OwnedKV, OwnedKVList, Record, SendSyncRefUnwindSafeKV};
+use tokio::task::JoinHandle;
 
-/// Assemble a logger that writes to the given writer.
+/// The environment variable that sets the log levels, as a comma separated list of a default level and
+/// `module=level` overrides, e.g. `QOS_LOG=info,netlink=debug,udevd::enrich=trace`.
+pub const LOG_LEVELS_ENV: &str = "QOS_LOG";
+
+/// The logger of the process, used outside of tasks that have their own.
+static ROOT_LOGGER: OnceLock<Logger> = OnceLock::new();
+
+tokio::task_local! {
+	/// The logger of the current task, carrying the context of everything that spawned it.
+	static TASK_LOGGER: Logger;
+}
+
+/// Assemble a logger that writes to the given writer, filtered by the levels in `QOS_LOG`.
 pub fn assemble_logger<W: Write + Send + 'static>(w: W) -> slog::Logger {
-	slog::Logger::root(Mutex::new(slog_json::Json::default(w)).fuse(), o!())
+	assemble_logger_with_level(w, Level::Trace)
+}
+
+/// Assemble a logger that writes to the given writer, dropping any logs less important than the given level, unless
+/// `QOS_LOG` says otherwise.
+pub fn assemble_logger_with_level<W: Write + Send + 'static>(w: W, level: Level) -> slog::Logger {
+	let levels = match env::var(LOG_LEVELS_ENV) {
+		Ok(spec) => LogLevels::parse(&spec, level).unwrap_or_else(|invalid| {
+			eprintln!("invalid {}, ignoring it: {}", LOG_LEVELS_ENV, invalid);
+			LogLevels::new(level)
+		}),
+		Err(_) => LogLevels::new(level),
+	};
+
+	let drain = Mutex::new(slog_json::Json::default(w)).fuse();
+	let logger = slog::Logger::root(ModuleLevelFilter { drain, levels }.fuse(), o!());
+	let _ = ROOT_LOGGER.set(logger.clone());
+	logger
+}
+
+/// Returns the logger of the current task, with the context attached by `spawn` and `with_logger`, or the logger of
+/// the process outside of such a task.
+pub fn current() -> Logger {
+	TASK_LOGGER
+		.try_with(Logger::clone)
+		.ok()
+		.or_else(|| ROOT_LOGGER.get().cloned())
+		.unwrap_or_else(|| Logger::root(Discard, o!()))
+}
+
+/// Runs the future with the given logger as the logger of the task, so that `current` (and any tasks it spawns with
+/// `spawn`) use it.
+pub async fn with_logger<F: Future>(logger: Logger, future: F) -> F::Output {
+	TASK_LOGGER.scope(logger, future).await
+}
+
+/// Spawns a task whose logger is the current one with the given context attached, e.g.
+/// `obs::spawn(o!("connection" => id), handle(stream))`.
+pub fn spawn<T, F>(values: OwnedKV<T>, future: F) -> JoinHandle<F::Output>
+where
+	T: SendSyncRefUnwindSafeKV + 'static,
+	F: Future + Send + 'static,
+	F::Output: Send + 'static,
+{
+	tokio::spawn(with_logger(current().new(values), future))
+}
+
+/// Replaces the panic hook with one that logs panics, along with a backtrace, through the logger of the task that
+/// panicked, so that they end up alongside the rest of its logs. Panics before a logger has been assembled go to
+/// stderr, as they would otherwise.
+pub fn install_panic_hook() {
+	let default_hook = panic::take_hook();
+	panic::set_hook(Box::new(move |info| {
+		let Some(logger) = TASK_LOGGER
+			.try_with(Logger::clone)
+			.ok()
+			.or_else(|| ROOT_LOGGER.get().cloned())
+		else {
+			default_hook(info);
+			return;
+		};
+
+		let location = info.location().map(ToString::to_string).unwrap_or_default();
+		crit!(logger, "panicked";
+			"message" => panic_message(info.payload()),
+			"location" => location,
+			"thread" => thread::current().name().unwrap_or("<unnamed>"),
+			"backtrace" => Backtrace::force_capture().to_string());
+	}));
+}
+
+/// Returns the message that a panic was raised with, from its payload.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+	payload
+		.downcast_ref::<&str>()
+		.copied()
+		.or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+		.unwrap_or("<non-string panic payload>")
+}
+
+/// The minimum level of the logs that are kept, overridden for some modules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLevels {
+	default: Level,
+
+	/// The modules with their own level, and their levels. Overrides apply to the module, and everything in it.
+	modules: Vec<(String, Level)>,
+}
+
+impl LogLevels {
+	pub fn new(default: Level) -> Self {
+		Self {
+			default,
+			modules: Vec::new(),
+		}
+	}
+
+	/// Parses a comma separated list of a default level and `module=level` overrides, starting from the given
+	/// default. Returns the part of the spec that's invalid if it can't be parsed.
+	pub fn parse(spec: &str, default: Level) -> Result<Self, String> {
+		let mut levels = Self::new(default);
+		for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+			let parse_level = |level: &str| level.parse::<Level>().map_err(|_| part.to_owned());
+			match part.split_once('=') {
+				// Module paths use underscores where crate names have dashes.
+				Some((module, level)) => levels
+					.modules
+					.push((module.trim().replace('-', "_"), parse_level(level.trim())?)),
+				None => levels.default = parse_level(part)?,
+			}
+		}
+
+		Ok(levels)
+	}
+
+	/// Returns the level for the given module path, from the most specific override that applies to it.
+	pub fn level_for(&self, module: &str) -> Level {
+		self.modules
+			.iter()
+			.filter(|(name, _)| {
+				module
+					.strip_prefix(name.as_str())
+					.is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+			})
+			.max_by_key(|(name, _)| name.len())
+			.map_or(self.default, |(_, level)| *level)
+	}
+}
+
+/// A drain that drops logs that are less important than the level of the module that they came from.
+struct ModuleLevelFilter<D: Drain> {
+	drain: D,
+	levels: LogLevels,
+}
+
+impl<D: Drain> Drain for ModuleLevelFilter<D> {
+	type Ok = Option<D::Ok>;
+	type Err = D::Err;
+
+	fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+		if record.level().is_at_least(self.levels.level_for(record.module())) {
+			self.drain.log(record, values).map(Some)
+		} else {
+			Ok(None)
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use slog::{o, Level};
+
+	use super::{current, panic_message, spawn, with_logger, LogLevels};
+
+	#[test]
+	fn test_log_levels() {
+		let levels =
+			LogLevels::parse("warn, netlink=debug,udevd::enrich=trace,assemble-fs=error", Level::Info).unwrap();
+		assert_eq!(levels.level_for("qinit::service"), Level::Warning);
+		assert_eq!(levels.level_for("netlink"), Level::Debug);
+		assert_eq!(levels.level_for("netlink::rtnetlink"), Level::Debug);
+		assert_eq!(levels.level_for("netlinkx"), Level::Warning);
+		assert_eq!(levels.level_for("udevd"), Level::Warning);
+		assert_eq!(levels.level_for("udevd::enrich::block"), Level::Trace);
+		assert_eq!(levels.level_for("assemble_fs"), Level::Error);
+
+		assert_eq!(LogLevels::parse("", Level::Info).unwrap(), LogLevels::new(Level::Info));
+		assert_eq!(
+			LogLevels::parse("netlink=loud", Level::Info),
+			Err(String::from("netlink=loud"))
+		);
+	}
+
+	#[test]
+	fn test_panic_message() {
+		let payload = std::panic::catch_unwind(|| panic!("static")).unwrap_err();
+		assert_eq!(panic_message(&*payload), "static");
+
+		let payload = std::panic::catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+		assert_eq!(panic_message(&*payload), "formatted 1");
+
+		let payload = std::panic::catch_unwind(|| std::panic::panic_any(1)).unwrap_err();
+		assert_eq!(panic_message(&*payload), "<non-string panic payload>");
+	}
+
+	#[tokio::test]
+	async fn test_context_propagation() {
+		let logger = slog::Logger::root(slog::Discard, o!("service" => "udevd"));
+		let keys = with_logger(logger, async {
+			spawn(o!("connection" => 1), async {
+				spawn(o!("request" => 2), async { format!("{:?}", current().list()) })
+					.await
+					.unwrap()
+			})
+			.await
+			.unwrap()
+		})
+		.await;
+
+		assert_eq!(keys, "(request, connection, service)");
+	}
 }
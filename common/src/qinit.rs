@@ -1,12 +1,144 @@
 use std::{
-	io::{self, Write},
-	os::unix::net::UnixStream,
+	collections::BTreeMap,
+	fmt::{self, Display, Formatter},
+	io,
 };
 
+use control::protocol::{request_sync, DEFAULT_REQUEST_TIMEOUT};
+use serde::{Deserialize, Serialize};
+
+/// The path of qinit's control socket.
+pub const QINIT_CONTROL_SOCKET: &str = "/run/qinit/control.sock";
+
+/// An instance of a service: the service, and the arguments that it's templated with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceInstance {
+	/// The name of the service.
+	pub name: String,
+
+	/// The arguments of the instance.
+	#[serde(default)]
+	pub arguments: BTreeMap<String, String>,
+}
+
+impl Display for ServiceInstance {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.name)?;
+		for (key, value) in self.arguments.iter() {
+			write!(f, " {}={}", key, value)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// The requests that qinit's control socket accepts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum QinitRequest {
+	/// Signals that the service making the request has finished its initialization routines.
+	Running,
+
+	/// Starts an instance of a service (and the services it needs), whether or not it's enabled.
+	Start(ServiceInstance),
+
+	/// Stops an instance of a service, along with every process it started.
+	Stop(ServiceInstance),
+
+	/// Enables an instance of a service, so that it's started on boot.
+	Enable(ServiceInstance),
+
+	/// Disables an instance of a service, so that it isn't started on boot, even if it's in the boot sphere.
+	Disable(ServiceInstance),
+
+	/// Lists the status of every service that qinit knows about.
+	List,
+
+	/// Gets the status of the instances of a service that match the given arguments, which don't need to be
+	/// complete.
+	Status(ServiceInstance),
+}
+
+/// The state of a service instance, as reported by qinit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum RunState {
+	/// The service is waiting for its dependencies or devices before it's started.
+	Queued,
+
+	/// The service has been started, but isn't ready yet.
+	Starting,
+
+	/// The service is running.
+	Running,
+
+	/// The service failed to start, or exited unsuccessfully. The exit code is missing if the service never ran, and
+	/// is 128 plus the signal if it was killed.
+	Failed { exit_code: Option<i32>, reason: String },
+
+	/// The service was stopped, or exited successfully.
+	Stopped,
+}
+
+impl Display for RunState {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			RunState::Queued => write!(f, "queued"),
+			RunState::Starting => write!(f, "starting"),
+			RunState::Running => write!(f, "running"),
+			RunState::Failed {
+				exit_code: Some(code), ..
+			} => write!(f, "failed ({})", code),
+			RunState::Failed { exit_code: None, .. } => write!(f, "failed"),
+			RunState::Stopped => write!(f, "stopped"),
+		}
+	}
+}
+
+/// The status of a service instance, as reported by qinit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceStatus {
+	pub instance: ServiceInstance,
+
+	#[serde(flatten)]
+	pub state: RunState,
+
+	/// The PID of the process that qinit started for the service, while it's running.
+	pub pid: Option<i32>,
+
+	/// When the service entered its current state, in seconds since the Unix epoch.
+	pub since: u64,
+
+	/// How many times the service has been started again after the first time, e.g. after being stopped.
+	pub restarts: u32,
+
+	/// The services that this service needs, which may only have some of the arguments of their instances.
+	#[serde(default)]
+	pub needs: Vec<ServiceInstance>,
+
+	/// The sphere that the service was started as part of, if it wasn't started on its own.
+	#[serde(default)]
+	pub sphere: Option<String>,
+
+	/// The ID of the login session that asked for the service to be started, if it was started on request from one.
+	#[serde(default)]
+	pub session: Option<u32>,
+}
+
+impl ServiceStatus {
+	/// Whether this is the status of the given instance, or an instance with more arguments than it.
+	pub fn matches(&self, instance: &ServiceInstance) -> bool {
+		self.instance.name == instance.name
+			&& instance
+				.arguments
+				.iter()
+				.all(|(key, value)| self.instance.arguments.get(key) == Some(value))
+	}
+}
+
 /// Signals to qinit that the service has finished its initialization routines.
 pub fn mark_running() -> io::Result<()> {
-	let mut sock = UnixStream::connect("/run/qinit/control.sock")?;
-	sock.write_all(b"ACTION=running\n")?;
+	request_sync(QINIT_CONTROL_SOCKET, &QinitRequest::Running, DEFAULT_REQUEST_TIMEOUT)?;
 
 	Ok(())
 }
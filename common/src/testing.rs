@@ -0,0 +1,84 @@
+use std::{
+	env, fs, io,
+	ops::Deref,
+	path::{Path, PathBuf},
+	process,
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The number of temporary directories made by this process so far, so that tests running at the same time get
+/// directories of their own.
+static TEMP_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory under the system's temporary directory for tests to work in, which is deleted along with
+/// everything in it when it's dropped, so that tests clean up after themselves even when they fail.
+#[derive(Debug)]
+pub struct TempDir {
+	path: PathBuf,
+}
+
+impl TempDir {
+	/// Creates a directory named with the given prefix, followed by something unique to this call.
+	pub fn new(prefix: &str) -> io::Result<Self> {
+		let path = env::temp_dir().join(format!(
+			"{}-{}-{}",
+			prefix,
+			process::id(),
+			TEMP_DIRS.fetch_add(1, Ordering::Relaxed)
+		));
+
+		// Clear out anything left behind by an earlier process with the same ID that didn't get to clean up.
+		match fs::remove_dir_all(&path) {
+			Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+			_ => {}
+		}
+
+		fs::create_dir_all(&path)?;
+		Ok(Self { path })
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+}
+
+impl Deref for TempDir {
+	type Target = Path;
+
+	fn deref(&self) -> &Path {
+		&self.path
+	}
+}
+
+impl AsRef<Path> for TempDir {
+	fn as_ref(&self) -> &Path {
+		&self.path
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		// Tests can leave things that can't be deleted (e.g. read-only directories), which shouldn't fail them.
+		let _ = fs::remove_dir_all(&self.path);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::fs;
+
+	use super::TempDir;
+
+	#[test]
+	fn test_temp_dir() {
+		let (first, second) = (TempDir::new("temp-dir").unwrap(), TempDir::new("temp-dir").unwrap());
+		assert_ne!(first.path(), second.path());
+		assert!(first.is_dir());
+
+		fs::create_dir(first.join("nested")).unwrap();
+		fs::write(first.join("nested").join("file"), "").unwrap();
+		let path = first.to_path_buf();
+		drop(first);
+		assert!(!path.exists());
+	}
+}
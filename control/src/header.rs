@@ -0,0 +1,109 @@
+use std::io::{Cursor, ErrorKind};
+
+use bytestruct::{LengthPrefixedString, ReadFrom, WriteTo};
+use bytestruct_derive::{ByteStruct, Size};
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+
+/// The first byte of a binary header. Text headers start with a key, so can never start with a NUL.
+pub const BINARY_HEADER_MAGIC: u8 = 0;
+
+/// The maximum size of an encoded binary header, in bytes.
+pub const MAX_BINARY_HEADER_SIZE: usize = 1 << 20;
+
+/// The maximum size of the key of an argument in a binary header, in bytes.
+const MAX_KEY_SIZE: usize = 0xFFFF;
+
+/// The maximum size of the value of an argument in a binary header, in bytes.
+const MAX_VALUE_SIZE: usize = 0xFFFFFFFF;
+
+/// A single key/value argument in a binary header.
+#[derive(Debug, ByteStruct, Size)]
+pub struct HeaderArg {
+	pub key: LengthPrefixedString<MAX_KEY_SIZE>,
+	pub value: LengthPrefixedString<MAX_VALUE_SIZE>,
+}
+
+/// A header that opens a connection to a control socket, in place of the text line of `k=v` pairs. Values in a
+/// binary header can contain any characters, including whitespace and newlines.
+///
+/// On the wire, a binary header is `BINARY_HEADER_MAGIC`, followed by the length of the encoded header as a big
+/// endian u32, followed by the encoded header.
+#[derive(Debug, ByteStruct, Size)]
+#[big_endian]
+pub struct BinaryHeader {
+	pub args: Vec<HeaderArg>,
+}
+
+impl BinaryHeader {
+	pub fn new(args: &[(&str, &str)]) -> Self {
+		Self {
+			args: args
+				.iter()
+				.map(|(key, value)| HeaderArg {
+					key: LengthPrefixedString(key.to_string()),
+					value: LengthPrefixedString(value.to_string()),
+				})
+				.collect(),
+		}
+	}
+
+	/// Encodes the header, including the magic byte and length prefix, ready to be written to a control socket.
+	pub fn encode(&self) -> io::Result<Vec<u8>> {
+		let mut body = Vec::new();
+		self.write_to(&mut body)?;
+		if body.len() > MAX_BINARY_HEADER_SIZE {
+			return Err(io::Error::new(ErrorKind::InvalidInput, "header is too large"));
+		}
+
+		let mut encoded = Vec::with_capacity(body.len() + 5);
+		encoded.push(BINARY_HEADER_MAGIC);
+		encoded.extend_from_slice(&(body.len() as u32).to_be_bytes());
+		encoded.extend_from_slice(&body);
+		Ok(encoded)
+	}
+
+	/// Reads a header from the reader, which must start with the magic byte.
+	pub async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Self> {
+		if reader.read_u8().await? != BINARY_HEADER_MAGIC {
+			return Err(io::Error::new(ErrorKind::InvalidData, "not a binary header"));
+		}
+
+		let len = reader.read_u32().await? as usize;
+		if len > MAX_BINARY_HEADER_SIZE {
+			return Err(io::Error::new(
+				ErrorKind::InvalidData,
+				format!("header of {} bytes is too large", len),
+			));
+		}
+
+		let mut body = vec![0; len];
+		reader.read_exact(&mut body).await?;
+		Self::read_from(&mut Cursor::new(body))
+	}
+
+	/// The arguments in the header, as key/value pairs.
+	pub fn pairs(&self) -> Vec<(&str, &str)> {
+		self.args
+			.iter()
+			.map(|arg| (arg.key.0.as_str(), arg.value.0.as_str()))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::Cursor;
+
+	use super::BinaryHeader;
+
+	#[tokio::test]
+	async fn test_round_trip() {
+		let header = BinaryHeader::new(&[("ACTION", "publish"), ("topic", "has spaces\nand newlines")]);
+		let encoded = header.encode().unwrap();
+		let decoded = BinaryHeader::read(&mut Cursor::new(encoded)).await.unwrap();
+		assert_eq!(
+			decoded.pairs(),
+			vec![("ACTION", "publish"), ("topic", "has spaces\nand newlines")]
+		);
+	}
+}
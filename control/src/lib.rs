@@ -1 +1,3 @@
+pub mod header;
 pub mod listen;
+pub mod protocol;
@@ -1,36 +1,347 @@
-use std::{fmt::Debug, fs, future::Future, path::Path};
+use std::{
+	collections::HashMap,
+	fmt::Debug,
+	fs,
+	future::Future,
+	net::{IpAddr, SocketAddr},
+	path::Path,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
 
+use serde::de::DeserializeOwned;
+use slog::{info, o, warn, Logger};
 use tokio::{
-	io::{self, AsyncBufRead, AsyncBufReadExt, AsyncWrite, BufReader},
-	net::{unix::UCred, UnixListener, UnixStream},
+	io::{self, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, BufReader},
+	net::{
+		tcp,
+		unix::{OwnedReadHalf, OwnedWriteHalf, UCred},
+		TcpListener, ToSocketAddrs, UnixListener,
+	},
+	signal::unix::{signal, SignalKind},
+	sync::watch,
+	task::JoinSet,
+};
+
+use crate::{
+	header::{BinaryHeader, BINARY_HEADER_MAGIC},
+	protocol::{
+		read_frame, write_frame, Envelope, ErrorKind, ErrorReply, ProtocolError, Reply, RequestId, REQUEST_MAGIC,
+	},
 };
 
 /// The key that is used to indicate the action to be run in a control socket message.
 const ACTION_KEY: &str = "ACTION";
 
+/// How long a connection can take to send its request before it's closed, by default.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long actions that are still running when a socket shuts down are given to finish, by default.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The shortest token that a remote listener accepts, so that it can't be guessed in any reasonable time.
+pub const MIN_TOKEN_LENGTH: usize = 16;
+
+/// How long to wait before replying to a request with an invalid token, to slow down guessing it.
+const REJECTED_TOKEN_DELAY: Duration = Duration::from_secs(1);
+
 /// A factory for creating actions to be run in response to control socket messages.
 pub trait ActionFactory: Clone {
 	/// The type of action that this factory produces.
 	type Action: Action;
 
-	/// Builds an action from the given action name and arguments.
+	/// The typed requests that this factory can build actions from.
+	type Request: DeserializeOwned + Send;
+
+	/// Builds an action from the given action name and arguments, as sent in a text or binary header.
 	fn build(&self, action: &str, args: &[(&str, &str)]) -> Result<Self::Action, <Self::Action as Action>::Error>;
+
+	/// Builds an action from a typed request.
+	fn build_request(&self, request: Self::Request) -> Result<Self::Action, <Self::Action as Action>::Error>;
+}
+
+/// Who made a request: either a process on this machine, or a client that connected over TCP.
+#[derive(Debug, Clone, Copy)]
+pub struct Peer {
+	uid: u32,
+	gid: u32,
+	pid: Option<i32>,
+	remote: Option<SocketAddr>,
+}
+
+impl Peer {
+	/// A process that connected to the unix socket, with the given credentials.
+	pub fn local(cred: UCred) -> Self {
+		Self {
+			uid: cred.uid(),
+			gid: cred.gid(),
+			pid: cred.pid(),
+			remote: None,
+		}
+	}
+
+	/// A client that connected over TCP from the given address, and authenticated with the listener's token. Having
+	/// the token is as good as being root, so remote peers act as uid and gid 0.
+	pub fn remote(addr: SocketAddr) -> Self {
+		Self {
+			uid: 0,
+			gid: 0,
+			pid: None,
+			remote: Some(addr),
+		}
+	}
+
+	pub fn uid(&self) -> u32 {
+		self.uid
+	}
+
+	pub fn gid(&self) -> u32 {
+		self.gid
+	}
+
+	/// The PID of the process that made the request, which is only known for local peers.
+	pub fn pid(&self) -> Option<i32> {
+		self.pid
+	}
+
+	/// The address that a remote peer connected from, or None for local peers.
+	pub fn remote_addr(&self) -> Option<SocketAddr> {
+		self.remote
+	}
+}
+
+/// Everything that is known about the request that an action is running for.
+pub struct RequestContext {
+	/// The ID of the request, as chosen by the client for typed requests.
+	pub id: RequestId,
+
+	/// Who made the request.
+	pub peer: Peer,
+
+	/// The control socket's logger, with the ID of the request attached. Actions should log through this, so that
+	/// their logs can be matched up with the client that made the request.
+	pub logger: Logger,
 }
 
 /// An action that can be run in response to a control socket message.
 pub trait Action: Send {
-	/// The type of error that this action can produce.
-	type Error: Sync + Send + Debug;
+	/// The type of error that this action can produce. Errors are sent back to clients that made typed requests.
+	type Error: Sync + Send + Debug + Into<ErrorReply>;
+
+	/// Checks that the peer is allowed to run the action. This happens before a typed request is accepted, so that
+	/// clients are told why they were turned away.
+	fn authorize(&self, _ctx: &RequestContext) -> impl Future<Output = Result<(), Self::Error>> + Send {
+		async { Ok(()) }
+	}
 
 	/// Runs the action with the given reader.
 	fn run<R: AsyncBufRead + Unpin + Send + 'static, W: AsyncWrite + Unpin + Send + 'static>(
 		self,
-		peer: UCred,
+		ctx: RequestContext,
 		reader: R,
 		writer: W,
 	) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
+/// Tells a control socket to stop accepting connections and shut down. Handles can be cloned and moved into signal
+/// handlers, and every clone shuts down the same socket.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+	sender: Arc<watch::Sender<bool>>,
+}
+
+impl ShutdownHandle {
+	fn new() -> Self {
+		Self {
+			sender: Arc::new(watch::Sender::new(false)),
+		}
+	}
+
+	/// Stops the socket from accepting connections. Its `listen` returns once the actions that are still running
+	/// have finished, or the drain timeout has passed.
+	pub fn shutdown(&self) {
+		self.sender.send_replace(true);
+	}
+
+	/// Whether the socket has been told to shut down.
+	pub fn is_shutdown(&self) -> bool {
+		*self.sender.borrow()
+	}
+
+	/// Shuts the socket down when the process is sent SIGTERM or SIGINT, which is how qinit stops services.
+	pub fn shutdown_on_signals(&self) -> io::Result<()> {
+		let mut terminate = signal(SignalKind::terminate())?;
+		let mut interrupt = signal(SignalKind::interrupt())?;
+		let handle = self.clone();
+		tokio::spawn(async move {
+			tokio::select! {
+				_ = terminate.recv() => {},
+				_ = interrupt.recv() => {},
+			}
+
+			handle.shutdown();
+		});
+
+		Ok(())
+	}
+
+	/// Waits until the socket is told to shut down.
+	async fn wait(&self) {
+		let mut receiver = self.sender.subscribe();
+		// The sender lives as long as we do, so this can't fail.
+		let _ = receiver.wait_for(|stopped| *stopped).await;
+	}
+}
+
+/// What connections are counted by for the per-peer limit: local peers by their user, so that a user can't get around
+/// the limit by starting more processes, and remote peers by their address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PeerKey {
+	Uid(u32),
+	Addr(IpAddr),
+}
+
+impl From<&Peer> for PeerKey {
+	fn from(peer: &Peer) -> Self {
+		match peer.remote_addr() {
+			Some(addr) => Self::Addr(addr.ip()),
+			None => Self::Uid(peer.uid()),
+		}
+	}
+}
+
+/// The number of open connections from each peer.
+#[derive(Default, Clone)]
+struct ConnectionCounts {
+	counts: Arc<Mutex<HashMap<PeerKey, usize>>>,
+}
+
+impl ConnectionCounts {
+	/// Counts a new connection from the peer, returning None if the peer already has the most connections it's
+	/// allowed. The connection is counted until the returned guard is dropped.
+	fn acquire(&self, peer: &Peer, limit: Option<usize>) -> Option<ConnectionGuard> {
+		let key = PeerKey::from(peer);
+		let mut counts = self.counts.lock().unwrap();
+		let count = counts.entry(key).or_default();
+		if limit.is_some_and(|limit| *count >= limit) {
+			return None;
+		}
+
+		*count += 1;
+		Some(ConnectionGuard {
+			counts: self.clone(),
+			key,
+		})
+	}
+
+	fn count(&self, peer: &Peer) -> usize {
+		let counts = self.counts.lock().unwrap();
+		counts.get(&PeerKey::from(peer)).copied().unwrap_or(0)
+	}
+}
+
+/// A connection that is being counted against its peer's limit.
+struct ConnectionGuard {
+	counts: ConnectionCounts,
+	key: PeerKey,
+}
+
+impl Drop for ConnectionGuard {
+	fn drop(&mut self) {
+		let mut counts = self.counts.counts.lock().unwrap();
+		if let Some(count) = counts.get_mut(&self.key) {
+			*count -= 1;
+			if *count == 0 {
+				counts.remove(&self.key);
+			}
+		}
+	}
+}
+
+/// How a listener treats the connections it accepts, which is shared between local and remote listeners.
+struct Connections {
+	shutdown: ShutdownHandle,
+	counts: ConnectionCounts,
+
+	/// The most connections that a peer can have open at once, or None for no limit.
+	max_per_peer: Option<usize>,
+
+	/// How long a connection can take to send its request before it's closed, or None to wait forever. Once the
+	/// request has been read, the action decides how long the connection stays open, as actions like streams are
+	/// meant to be idle for long stretches.
+	idle_timeout: Option<Duration>,
+
+	/// How long actions that are still running are given to finish once the listener is shut down, before they're
+	/// cancelled.
+	drain_timeout: Duration,
+}
+
+impl Connections {
+	fn new() -> Self {
+		Self {
+			shutdown: ShutdownHandle::new(),
+			counts: ConnectionCounts::default(),
+			max_per_peer: None,
+			idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+			drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+		}
+	}
+
+	/// Accepts connections and handles each of them in a new task until the listener is shut down, and then waits for
+	/// the tasks to finish.
+	async fn serve<F, R, W, A, Fut>(&self, factory: &F, token: Option<Arc<str>>, logger: &Logger, mut accept: A)
+	where
+		F: ActionFactory + Send + 'static,
+		R: AsyncRead + Unpin + Send + 'static,
+		W: AsyncWrite + Unpin + Send + 'static,
+		A: FnMut() -> Fut,
+		Fut: Future<Output = Option<(R, W, Peer)>>,
+	{
+		let mut tasks = JoinSet::new();
+		loop {
+			tokio::select! {
+				_ = self.shutdown.wait() => break,
+				// Reap finished connections, so that the set doesn't grow forever.
+				Some(_) = tasks.join_next(), if !tasks.is_empty() => continue,
+				accepted = accept() => {
+					let Some((read, write, peer)) = accepted else {
+						continue;
+					};
+
+					let Some(guard) = self.counts.acquire(&peer, self.max_per_peer) else {
+						warn!(logger, "rejected connection from a peer with too many connections"; "pid" => peer.pid(), "uid" => peer.uid(), "addr" => peer.remote_addr().map(|addr| addr.to_string()), "connections" => self.counts.count(&peer));
+						continue;
+					};
+
+					tasks.spawn(handler(
+						factory.clone(),
+						read,
+						write,
+						peer,
+						token.clone(),
+						self.idle_timeout,
+						logger.clone(),
+						guard,
+					));
+				}
+			}
+		}
+
+		if tasks.is_empty() {
+			return;
+		}
+
+		info!(logger, "waiting for running actions to finish"; "actions" => tasks.len());
+		let drained =
+			tokio::time::timeout(self.drain_timeout, async { while tasks.join_next().await.is_some() {} }).await;
+
+		if drained.is_err() {
+			warn!(logger, "cancelling actions that didn't finish in time"; "actions" => tasks.len());
+			tasks.shutdown().await;
+		}
+	}
+}
+
 /// A control socket that listens for messages and runs actions in response.
 pub struct ControlSocket<F: ActionFactory> {
 	/// The socket that is being listened on.
@@ -38,11 +349,16 @@ pub struct ControlSocket<F: ActionFactory> {
 
 	/// The factory that is used to create actions to run.
 	factory: F,
+
+	/// The logger that failed requests are logged to, and that actions are given.
+	logger: Logger,
+
+	connections: Connections,
 }
 
 impl<F: ActionFactory + Send + 'static> ControlSocket<F> {
 	/// Opens a new control socket at the given path with the given action factory.
-	pub fn open(path: &Path, factory: F) -> io::Result<Self> {
+	pub fn open(path: &Path, factory: F, logger: Logger) -> io::Result<Self> {
 		if path.exists() {
 			// TODO: Check if the socket is actually a socket and not a file before removing it.
 			fs::remove_file(path)?;
@@ -51,51 +367,387 @@ impl<F: ActionFactory + Send + 'static> ControlSocket<F> {
 		Ok(Self {
 			socket: UnixListener::bind(path)?,
 			factory,
+			logger,
+			connections: Connections::new(),
 		})
 	}
 
-	/// Listens for incoming connections and runs actions in response.
-	/// This function will block the current thread, looping indefinitely.
+	/// Limits the number of connections that each user can have open at once. Connections past the limit are closed
+	/// as soon as they're accepted.
+	pub fn with_max_connections_per_peer(mut self, max: usize) -> Self {
+		self.connections.max_per_peer = Some(max);
+		self
+	}
+
+	/// Sets how long a connection can take to send its request before it's closed, or None to wait forever.
+	pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+		self.connections.idle_timeout = timeout;
+		self
+	}
+
+	/// Sets how long actions that are still running are given to finish once the socket is shut down.
+	pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+		self.connections.drain_timeout = timeout;
+		self
+	}
+
+	/// A handle that shuts the socket down.
+	pub fn shutdown_handle(&self) -> ShutdownHandle {
+		self.connections.shutdown.clone()
+	}
+
+	/// Listens for incoming connections and runs actions in response, until the socket is shut down through its
+	/// shutdown handle. Once it has been, this waits for the actions that are still running to finish, or cancels
+	/// them if they take longer than the drain timeout.
 	pub async fn listen(&self) {
-		loop {
-			let (stream, _) = self.socket.accept().await.unwrap();
-			tokio::spawn(handler(self.factory.clone(), stream));
+		self.connections
+			.serve(&self.factory, None, &self.logger, || self.accept())
+			.await
+	}
+
+	/// Accepts a connection, returning None if it couldn't be.
+	async fn accept(&self) -> Option<(OwnedReadHalf, OwnedWriteHalf, Peer)> {
+		let stream = match self.socket.accept().await {
+			Ok((stream, _)) => stream,
+			Err(e) => {
+				warn!(self.logger, "failed to accept connection"; "error" => e.to_string());
+				return None;
+			}
+		};
+
+		let peer = match stream.peer_cred() {
+			Ok(cred) => Peer::local(cred),
+			Err(e) => {
+				warn!(self.logger, "failed to read peer credentials"; "error" => e.to_string());
+				return None;
+			}
+		};
+
+		let (read, write) = stream.into_split();
+		Some((read, write, peer))
+	}
+}
+
+/// A TCP listener for the same requests as a control socket, so that a machine can be managed remotely. Every
+/// request has to carry the listener's token, and only typed requests are accepted, as headers can't carry one.
+///
+/// Requests aren't encrypted, so the listener should either be bound to localhost and reached through an SSH tunnel,
+/// or sit behind something that terminates TLS.
+pub struct RemoteControlSocket<F: ActionFactory> {
+	/// The socket that is being listened on.
+	listener: TcpListener,
+
+	/// The token that clients have to send with their requests.
+	token: Arc<str>,
+
+	/// The factory that is used to create actions to run.
+	factory: F,
+
+	/// The logger that failed requests are logged to, and that actions are given.
+	logger: Logger,
+
+	connections: Connections,
+}
+
+impl<F: ActionFactory + Send + 'static> RemoteControlSocket<F> {
+	/// Listens on the given address, accepting requests that carry the given token, which has to be at least
+	/// `MIN_TOKEN_LENGTH` bytes long.
+	pub async fn open<A: ToSocketAddrs>(addr: A, token: &str, factory: F, logger: Logger) -> io::Result<Self> {
+		if token.len() < MIN_TOKEN_LENGTH {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("the token has to be at least {} bytes long", MIN_TOKEN_LENGTH),
+			));
 		}
+
+		Ok(Self {
+			listener: TcpListener::bind(addr).await?,
+			token: Arc::from(token),
+			factory,
+			logger,
+			connections: Connections::new(),
+		})
+	}
+
+	/// Limits the number of connections that each address can have open at once. Connections past the limit are
+	/// closed as soon as they're accepted.
+	pub fn with_max_connections_per_peer(mut self, max: usize) -> Self {
+		self.connections.max_per_peer = Some(max);
+		self
+	}
+
+	/// Sets how long a connection can take to send its request before it's closed, or None to wait forever.
+	pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+		self.connections.idle_timeout = timeout;
+		self
+	}
+
+	/// Sets how long actions that are still running are given to finish once the listener is shut down.
+	pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+		self.connections.drain_timeout = timeout;
+		self
 	}
+
+	/// A handle that shuts the listener down.
+	pub fn shutdown_handle(&self) -> ShutdownHandle {
+		self.connections.shutdown.clone()
+	}
+
+	/// The address that the listener is bound to.
+	pub fn local_addr(&self) -> io::Result<SocketAddr> {
+		self.listener.local_addr()
+	}
+
+	/// Listens for incoming connections and runs actions in response, until the listener is shut down through its
+	/// shutdown handle. Once it has been, this waits for the actions that are still running to finish, or cancels
+	/// them if they take longer than the drain timeout.
+	pub async fn listen(&self) {
+		self.connections
+			.serve(&self.factory, Some(self.token.clone()), &self.logger, || self.accept())
+			.await
+	}
+
+	/// Accepts a connection, returning None if it couldn't be.
+	async fn accept(&self) -> Option<(tcp::OwnedReadHalf, tcp::OwnedWriteHalf, Peer)> {
+		let (stream, addr) = match self.listener.accept().await {
+			Ok(accepted) => accepted,
+			Err(e) => {
+				warn!(self.logger, "failed to accept remote connection"; "error" => e.to_string());
+				return None;
+			}
+		};
+
+		let _ = stream.set_nodelay(true);
+		let (read, write) = stream.into_split();
+		Some((read, write, Peer::remote(addr)))
+	}
+}
+
+/// Compares tokens in an amount of time that only depends on their lengths, so that a remote client can't guess the
+/// token a byte at a time by timing rejections.
+fn tokens_match(expected: &str, given: &str) -> bool {
+	expected.len() == given.len()
+		&& expected
+			.bytes()
+			.zip(given.bytes())
+			.fold(0, |diff, (a, b)| diff | (a ^ b))
+			== 0
 }
 
-/// Handles a single incoming connection.
-async fn handler<F: ActionFactory>(factory: F, stream: UnixStream) {
-	let peer = stream.peer_cred().unwrap();
-	let (read, write) = stream.into_split();
+/// Handles a single incoming connection. If a token is given, the connection is a remote one, which has to make a
+/// typed request carrying the token. The connection is counted against its peer's limit until the guard is dropped.
+#[allow(clippy::too_many_arguments)]
+async fn handler<F, R, W>(
+	factory: F,
+	read: R,
+	mut write: W,
+	peer: Peer,
+	token: Option<Arc<str>>,
+	idle_timeout: Option<Duration>,
+	logger: Logger,
+	_guard: ConnectionGuard,
+) where
+	F: ActionFactory,
+	R: AsyncRead + Unpin + Send + 'static,
+	W: AsyncWrite + Unpin + Send + 'static,
+{
 	let mut reader = BufReader::new(read);
+	let request = read_request(factory, &mut reader, &mut write, &peer, token.as_deref(), &logger);
+	let request = match idle_timeout {
+		Some(idle_timeout) => match tokio::time::timeout(idle_timeout, request).await {
+			Ok(request) => request,
+			Err(_) => {
+				warn!(logger, "closed a connection that didn't send a request in time"; "pid" => peer.pid(), "uid" => peer.uid(), "addr" => peer.remote_addr().map(|addr| addr.to_string()));
+				return;
+			}
+		},
+		None => request.await,
+	};
+
+	let Some((typed, id, action)) = request else {
+		return;
+	};
+
+	let ctx = RequestContext {
+		id,
+		peer,
+		logger: logger.new(o!("request_id" => id.to_string())),
+	};
+
+	let accepted = match action {
+		Ok(action) => action.authorize(&ctx).await.map(|_| action),
+		Err(e) => Err(e),
+	};
+
+	let action = match accepted {
+		Ok(action) => action,
+		Err(e) => {
+			warn!(ctx.logger, "rejected request"; "pid" => ctx.peer.pid(), "uid" => ctx.peer.uid(), "error" => format!("{:?}", e));
+			if typed {
+				let reply: Reply = Err(e.into().with_request_id(id));
+				let _ = write_frame(&mut write, &reply).await;
+			}
+			return;
+		}
+	};
+
+	if typed {
+		let reply: Reply = Ok(());
+		if let Err(e) = write_frame(&mut write, &reply).await {
+			warn!(ctx.logger, "failed to accept request"; "error" => e.to_string());
+			return;
+		}
+	}
+
+	let logger = ctx.logger.clone();
+	if let Err(e) = action.run(ctx, reader, write).await {
+		warn!(logger, "failed to run action"; "error" => format!("{:?}", e));
+	}
+}
+
+/// An action built from a request, or the error that building it failed with.
+type BuiltAction<F> = Result<<F as ActionFactory>::Action, <<F as ActionFactory>::Action as Action>::Error>;
+
+/// Reads the request that a connection makes, returning whether it was a typed request, its ID, and the action that
+/// was built from it. Requests that can't be read are rejected here, returning None.
+async fn read_request<F, R, W>(
+	factory: F,
+	reader: &mut BufReader<R>,
+	write: &mut W,
+	peer: &Peer,
+	token: Option<&str>,
+	logger: &Logger,
+) -> Option<(bool, RequestId, BuiltAction<F>)>
+where
+	F: ActionFactory,
+	R: AsyncRead + Unpin,
+	W: AsyncWrite + Unpin,
+{
+	// Typed requests and binary headers start with magic bytes that can't start a text header.
+	let magic = match reader.fill_buf().await {
+		Ok(buf) => buf.first().copied(),
+		Err(_) => return None,
+	};
+
+	let typed = magic == Some(REQUEST_MAGIC);
+	if token.is_some() && !typed {
+		warn!(logger, "rejected remote connection without a typed request"; "addr" => peer.remote_addr().map(|addr| addr.to_string()));
+		return None;
+	}
+
+	let (id, action) = if typed {
+		// The envelope is read before the request itself, so that a request that can't be decoded can still be
+		// rejected with the ID the client gave it.
+		let envelope: Result<Envelope<serde_json::Value>, _> = match reader.read_u8().await {
+			Ok(_) => read_frame(reader).await,
+			Err(e) => Err(e.into()),
+		};
+
+		let (id, request) = match envelope {
+			Ok(envelope) => {
+				let id = envelope.request_id.unwrap_or_default();
+				if let Some(token) = token {
+					if !envelope
+						.token
+						.as_deref()
+						.is_some_and(|given| tokens_match(token, given))
+					{
+						warn!(logger, "rejected remote request with an invalid token"; "request_id" => id.to_string(), "addr" => peer.remote_addr().map(|addr| addr.to_string()));
+						tokio::time::sleep(REJECTED_TOKEN_DELAY).await;
+						let reply: Reply =
+							Err(ErrorReply::new(ErrorKind::PermissionDenied, "invalid token").with_request_id(id));
+						let _ = write_frame(write, &reply).await;
+						return None;
+					}
+				}
+
+				(
+					id,
+					serde_json::from_value::<F::Request>(envelope.request).map_err(ProtocolError::from),
+				)
+			}
+			Err(e) => (RequestId::new(), Err(e)),
+		};
+
+		match request {
+			Ok(request) => (id, factory.build_request(request)),
+			Err(e) => {
+				warn!(logger, "failed to read request"; "request_id" => id.to_string(), "error" => e.to_string());
+				let reply: Reply = Err(ErrorReply::new(ErrorKind::UnknownRequest, e).with_request_id(id));
+				let _ = write_frame(write, &reply).await;
+				return None;
+			}
+		}
+	} else {
+		match read_args(reader, magic == Some(BINARY_HEADER_MAGIC)).await {
+			Ok(args) => {
+				let args: Vec<(&str, &str)> = args.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+				let action = args.iter().find(|(k, _)| *k == ACTION_KEY).map(|(_, v)| *v);
+				(RequestId::new(), factory.build(action.unwrap_or(""), &args))
+			}
+			Err(e) => {
+				warn!(logger, "failed to read header"; "error" => e.to_string());
+				return None;
+			}
+		}
+	};
+
+	Some((typed, id, action))
+}
+
+/// Reads the arguments of a text or binary header.
+async fn read_args<R: AsyncBufRead + Unpin>(reader: &mut R, binary: bool) -> io::Result<Vec<(String, String)>> {
+	if binary {
+		let header = BinaryHeader::read(reader).await?;
+		return Ok(header
+			.pairs()
+			.into_iter()
+			.map(|(k, v)| (k.to_owned(), v.to_owned()))
+			.collect());
+	}
 
 	// Read the first line, which will be a whitespace seperated list of k=v pairs that
 	// are arguments to the control socket, indicating what the connection wants to do.
 	// e.g. "ACTION=start-stream FILE=/var/log/messages"
 	let mut arg_string = String::new();
-	reader.read_line(&mut arg_string).await.unwrap();
-	let mut action = None;
+	reader.read_line(&mut arg_string).await?;
 
 	let mut args = Vec::new();
 	for arg in arg_string.split_whitespace() {
-		let (k, v) = arg.split_once('=').unwrap();
-		args.push((k, v));
-
-		if k == ACTION_KEY {
-			action = Some(v);
-		}
+		let (k, v) = arg.split_once('=').ok_or_else(|| {
+			io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("invalid argument `{}`: expected key=value", arg),
+			)
+		})?;
+		args.push((k.to_owned(), v.to_owned()));
 	}
 
-	let action = match factory.build(action.unwrap_or(""), &args) {
-		Ok(action) => action,
-		Err(e) => {
-			eprintln!("Failed to build action: {:?}", e);
-			return;
-		}
-	};
+	Ok(args)
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::{Cursor, ErrorKind};
+
+	use super::read_args;
+
+	#[tokio::test]
+	async fn test_read_args() {
+		let args = read_args(&mut Cursor::new("ACTION=start-stream FILE=/var/log/messages\n"), false)
+			.await
+			.unwrap();
+		assert_eq!(
+			args,
+			vec![
+				(String::from("ACTION"), String::from("start-stream")),
+				(String::from("FILE"), String::from("/var/log/messages"))
+			]
+		);
 
-	if let Err(e) = action.run(peer, reader, write).await {
-		eprintln!("Failed to run action: {:?}", e);
+		let err = read_args(&mut Cursor::new("ACTION=start-stream FILE\n"), false)
+			.await
+			.unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::InvalidData);
 	}
 }
@@ -0,0 +1,544 @@
+use std::{
+	fmt,
+	io::{self, Read, Write},
+	os::unix::net::UnixStream as StdUnixStream,
+	path::Path,
+	time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use tokio::{
+	io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+	net::{TcpStream, ToSocketAddrs, UnixStream},
+};
+
+/// The first byte of a typed request. Binary headers start with `BINARY_HEADER_MAGIC`, and text headers with a key,
+/// so neither can be mistaken for a typed request.
+pub const REQUEST_MAGIC: u8 = 1;
+
+/// The maximum size of an encoded request or reply, in bytes.
+pub const MAX_FRAME_SIZE: usize = 1 << 20;
+
+/// The default amount of time that clients wait for a request to be accepted.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Identifies a single request, so that a client's view of it can be matched up with the daemon's logs. Clients
+/// pick the ID of a typed request, and the daemon picks one for requests made with a header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(u64);
+
+impl RequestId {
+	/// Generates a new random request ID.
+	pub fn new() -> Self {
+		Self(rand::random())
+	}
+}
+
+impl Default for RequestId {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl fmt::Display for RequestId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:016x}", self.0)
+	}
+}
+
+// Request IDs are sent as hex strings, as some JSON decoders can't represent every u64.
+impl Serialize for RequestId {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl<'de> Deserialize<'de> for RequestId {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let id = String::deserialize(deserializer)?;
+		u64::from_str_radix(&id, 16)
+			.map(Self)
+			.map_err(|_| serde::de::Error::custom(format!("invalid request id: {}", id)))
+	}
+}
+
+/// A typed request as it is sent on the wire: the request itself, alongside its ID.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Envelope<T> {
+	/// The ID of the request. Older clients don't send one, in which case the daemon picks one.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub request_id: Option<RequestId>,
+
+	/// The token that authenticates requests made over TCP. Requests to unix sockets don't need one.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub token: Option<String>,
+
+	#[serde(flatten)]
+	pub request: T,
+}
+
+/// The kind of failure that caused a request to be rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+	/// The request couldn't be decoded, or isn't one that the socket knows about.
+	UnknownRequest,
+
+	/// The request was understood, but its arguments weren't valid.
+	InvalidRequest,
+
+	/// The peer isn't allowed to make the request.
+	PermissionDenied,
+
+	/// The request conflicts with the current state of the daemon.
+	Conflict,
+
+	/// The daemon failed to handle the request.
+	Internal,
+}
+
+/// The reason that a request was rejected, as sent back to the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
+pub struct ErrorReply {
+	pub kind: ErrorKind,
+	pub message: String,
+
+	/// The ID of the rejected request, filled in by the control socket.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub request_id: Option<RequestId>,
+}
+
+impl ErrorReply {
+	pub fn new<S: ToString>(kind: ErrorKind, message: S) -> Self {
+		Self {
+			kind,
+			message: message.to_string(),
+			request_id: None,
+		}
+	}
+
+	/// Sets the ID of the request that was rejected.
+	pub fn with_request_id(mut self, request_id: RequestId) -> Self {
+		self.request_id = Some(request_id);
+		self
+	}
+}
+
+impl fmt::Display for ErrorReply {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.request_id {
+			Some(id) => write!(f, "{} (request {})", self.message, id),
+			None => write!(f, "{}", self.message),
+		}
+	}
+}
+
+/// The reply to a typed request, sent once the request has either been accepted or rejected. After an accepted
+/// request, the connection carries whatever the action speaks, e.g. a stream of logs.
+pub type Reply<T = ()> = Result<T, ErrorReply>;
+
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+	#[error("IO error: {0}")]
+	IOError(#[from] io::Error),
+
+	#[error("failed to encode or decode frame: {0}")]
+	EncodingError(#[from] serde_json::Error),
+
+	#[error("frame of {0} bytes is too large")]
+	TooLarge(usize),
+
+	#[error("timed out waiting for a reply to request {0}")]
+	Timeout(RequestId),
+
+	#[error("request rejected: {0}")]
+	Rejected(#[from] ErrorReply),
+}
+
+impl From<ProtocolError> for io::Error {
+	fn from(value: ProtocolError) -> Self {
+		let kind = match &value {
+			ProtocolError::IOError(e) => return io::Error::new(e.kind(), value),
+			ProtocolError::EncodingError(_) | ProtocolError::TooLarge(_) => io::ErrorKind::InvalidData,
+			ProtocolError::Timeout(_) => io::ErrorKind::TimedOut,
+			ProtocolError::Rejected(reply) => match reply.kind {
+				ErrorKind::PermissionDenied => io::ErrorKind::PermissionDenied,
+				ErrorKind::UnknownRequest | ErrorKind::InvalidRequest => io::ErrorKind::InvalidInput,
+				ErrorKind::Conflict => io::ErrorKind::AlreadyExists,
+				ErrorKind::Internal => io::ErrorKind::Other,
+			},
+		};
+
+		io::Error::new(kind, value)
+	}
+}
+
+/// Encodes a frame, i.e. the JSON encoding of the value prefixed with its length as a big endian u32.
+fn encode_frame<T: Serialize>(value: &T) -> Result<Vec<u8>, ProtocolError> {
+	let body = serde_json::to_vec(value)?;
+	if body.len() > MAX_FRAME_SIZE {
+		return Err(ProtocolError::TooLarge(body.len()));
+	}
+
+	let mut encoded = Vec::with_capacity(body.len() + 4);
+	encoded.extend_from_slice(&(body.len() as u32).to_be_bytes());
+	encoded.extend_from_slice(&body);
+	Ok(encoded)
+}
+
+/// Encodes a typed request with the given ID and token, including the magic byte, ready to be written to a control
+/// socket.
+fn encode_request<T: Serialize>(
+	request_id: RequestId,
+	token: Option<&str>,
+	request: &T,
+) -> Result<Vec<u8>, ProtocolError> {
+	let mut encoded = vec![REQUEST_MAGIC];
+	encoded.extend(encode_frame(&Envelope {
+		request_id: Some(request_id),
+		token: token.map(str::to_owned),
+		request,
+	})?);
+	Ok(encoded)
+}
+
+/// Writes a single frame to the writer.
+pub async fn write_frame<W: AsyncWrite + Unpin, T: Serialize>(writer: &mut W, value: &T) -> Result<(), ProtocolError> {
+	writer.write_all(&encode_frame(value)?).await?;
+	writer.flush().await?;
+	Ok(())
+}
+
+/// Reads a single frame from the reader.
+pub async fn read_frame<R: AsyncRead + Unpin, T: DeserializeOwned>(reader: &mut R) -> Result<T, ProtocolError> {
+	let len = reader.read_u32().await? as usize;
+	if len > MAX_FRAME_SIZE {
+		return Err(ProtocolError::TooLarge(len));
+	}
+
+	let mut body = vec![0; len];
+	reader.read_exact(&mut body).await?;
+	Ok(serde_json::from_slice(&body)?)
+}
+
+/// Sends a typed request over an open connection to a control socket, waiting up to `timeout` for it to be accepted.
+/// Returns the ID that the request was sent with, which the daemon logs alongside anything it does for the request.
+pub async fn send_request<S: AsyncRead + AsyncWrite + Unpin, T: Serialize>(
+	stream: &mut S,
+	request: &T,
+	timeout: Duration,
+) -> Result<RequestId, ProtocolError> {
+	let request_id = RequestId::new();
+	exchange(stream, request_id, None, request, timeout).await?;
+	Ok(request_id)
+}
+
+/// Sends a typed request with the given ID and token, and waits for the reply.
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin, T: Serialize>(
+	stream: &mut S,
+	request_id: RequestId,
+	token: Option<&str>,
+	request: &T,
+	timeout: Duration,
+) -> Result<(), ProtocolError> {
+	let exchange = async {
+		stream.write_all(&encode_request(request_id, token, request)?).await?;
+		stream.flush().await?;
+		let reply: Reply = read_frame(stream).await?;
+		Ok(reply?)
+	};
+
+	tokio::time::timeout(timeout, exchange)
+		.await
+		.map_err(|_| ProtocolError::Timeout(request_id))?
+}
+
+/// Connects to the control socket at the given path and sends it a typed request, returning the connection once the
+/// request has been accepted.
+pub async fn request<P: AsRef<Path>, T: Serialize>(
+	socket_path: P,
+	request: &T,
+	timeout: Duration,
+) -> Result<UnixStream, ProtocolError> {
+	let request_id = RequestId::new();
+	let mut stream = tokio::time::timeout(timeout, UnixStream::connect(socket_path))
+		.await
+		.map_err(|_| ProtocolError::Timeout(request_id))??;
+
+	exchange(&mut stream, request_id, None, request, timeout).await?;
+	Ok(stream)
+}
+
+/// The same as `request`, but for a control socket that's listening on TCP, which needs the given token.
+pub async fn request_remote<A: ToSocketAddrs, T: Serialize>(
+	addr: A,
+	token: &str,
+	request: &T,
+	timeout: Duration,
+) -> Result<TcpStream, ProtocolError> {
+	let request_id = RequestId::new();
+	let mut stream = tokio::time::timeout(timeout, TcpStream::connect(addr))
+		.await
+		.map_err(|_| ProtocolError::Timeout(request_id))??;
+	stream.set_nodelay(true)?;
+
+	exchange(&mut stream, request_id, Some(token), request, timeout).await?;
+	Ok(stream)
+}
+
+/// The same as `request`, but for clients that aren't running in an async runtime.
+pub fn request_sync<P: AsRef<Path>, T: Serialize>(
+	socket_path: P,
+	request: &T,
+	timeout: Duration,
+) -> Result<StdUnixStream, ProtocolError> {
+	let request_id = RequestId::new();
+	let timed_out = |e: io::Error| match e.kind() {
+		io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ProtocolError::Timeout(request_id),
+		_ => ProtocolError::IOError(e),
+	};
+
+	let mut stream = StdUnixStream::connect(socket_path)?;
+	stream.set_read_timeout(Some(timeout))?;
+	stream.set_write_timeout(Some(timeout))?;
+
+	stream
+		.write_all(&encode_request(request_id, None, request)?)
+		.map_err(timed_out)?;
+
+	let mut len = [0; 4];
+	stream.read_exact(&mut len).map_err(timed_out)?;
+	let len = u32::from_be_bytes(len) as usize;
+	if len > MAX_FRAME_SIZE {
+		return Err(ProtocolError::TooLarge(len));
+	}
+
+	let mut body = vec![0; len];
+	stream.read_exact(&mut body).map_err(timed_out)?;
+	let reply: Reply = serde_json::from_slice(&body)?;
+	reply?;
+
+	// The connection is handed over to the caller, who may not expect it to time out.
+	stream.set_read_timeout(None)?;
+	stream.set_write_timeout(None)?;
+	Ok(stream)
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use common::testing::TempDir;
+	use serde::{Deserialize, Serialize};
+	use slog::{o, Discard, Logger};
+	use tokio::{
+		io::{AsyncBufRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+		time::timeout,
+	};
+
+	use super::{
+		request, request_remote, request_sync, Envelope, ErrorKind, ErrorReply, ProtocolError, RequestId,
+		DEFAULT_REQUEST_TIMEOUT,
+	};
+	use crate::listen::{Action, ActionFactory, ControlSocket, RemoteControlSocket, RequestContext};
+
+	/// A token that's long enough for remote listeners to accept.
+	const TOKEN: &str = "correct-horse-battery-staple";
+
+	#[derive(Debug, Serialize, Deserialize)]
+	#[serde(tag = "action", rename_all = "kebab-case")]
+	enum TestRequest {
+		Echo,
+		Forbidden,
+	}
+
+	#[derive(Debug)]
+	struct Denied;
+
+	impl From<Denied> for ErrorReply {
+		fn from(_: Denied) -> Self {
+			ErrorReply::new(ErrorKind::PermissionDenied, "denied")
+		}
+	}
+
+	/// Echoes everything the client sends back to it, if allowed.
+	struct EchoAction(bool);
+
+	impl Action for EchoAction {
+		type Error = Denied;
+
+		async fn authorize(&self, _ctx: &RequestContext) -> Result<(), Self::Error> {
+			if self.0 {
+				Ok(())
+			} else {
+				Err(Denied)
+			}
+		}
+
+		async fn run<R: AsyncBufRead + Unpin + Send + 'static, W: AsyncWrite + Unpin + Send + 'static>(
+			self,
+			_ctx: RequestContext,
+			mut reader: R,
+			mut writer: W,
+		) -> Result<(), Self::Error> {
+			let mut buf = Vec::new();
+			reader.read_to_end(&mut buf).await.unwrap();
+			writer.write_all(&buf).await.unwrap();
+			Ok(())
+		}
+	}
+
+	#[derive(Clone)]
+	struct EchoFactory;
+
+	impl ActionFactory for EchoFactory {
+		type Action = EchoAction;
+		type Request = TestRequest;
+
+		fn build(&self, _action: &str, _args: &[(&str, &str)]) -> Result<Self::Action, Denied> {
+			Err(Denied)
+		}
+
+		fn build_request(&self, request: TestRequest) -> Result<Self::Action, Denied> {
+			Ok(EchoAction(matches!(request, TestRequest::Echo)))
+		}
+	}
+
+	#[test]
+	fn test_envelope() {
+		let id = RequestId::new();
+		let envelope = Envelope {
+			request_id: Some(id),
+			token: None,
+			request: TestRequest::Echo,
+		};
+
+		let encoded = serde_json::to_value(&envelope).unwrap();
+		assert_eq!(encoded["action"], "echo");
+		assert_eq!(encoded["request_id"], id.to_string());
+
+		// Requests from clients that don't send an ID are still understood.
+		let decoded: Envelope<TestRequest> =
+			serde_json::from_value(serde_json::json!({ "action": "forbidden" })).unwrap();
+		assert!(matches!(decoded.request, TestRequest::Forbidden));
+		assert_eq!(decoded.request_id, None);
+	}
+
+	#[tokio::test]
+	async fn test_requests() {
+		let directory = TempDir::new("control").unwrap();
+		let path = directory.join("control.sock");
+		let socket = ControlSocket::open(&path, EchoFactory, Logger::root(Discard, o!())).unwrap();
+		tokio::spawn(async move { socket.listen().await });
+
+		let mut stream = request(&path, &TestRequest::Echo, DEFAULT_REQUEST_TIMEOUT)
+			.await
+			.unwrap();
+		stream.write_all(b"hello").await.unwrap();
+		stream.shutdown().await.unwrap();
+		let mut echoed = Vec::new();
+		stream.read_to_end(&mut echoed).await.unwrap();
+		assert_eq!(echoed, b"hello");
+
+		match request(&path, &TestRequest::Forbidden, DEFAULT_REQUEST_TIMEOUT).await {
+			Err(ProtocolError::Rejected(reply)) => {
+				assert_eq!(reply.kind, ErrorKind::PermissionDenied);
+				assert!(reply.request_id.is_some());
+			}
+			other => panic!("expected a rejection, got {:?}", other),
+		}
+
+		let unknown = serde_json::json!({ "action": "unknown" });
+		match request(&path, &unknown, DEFAULT_REQUEST_TIMEOUT).await {
+			Err(ProtocolError::Rejected(reply)) => assert_eq!(reply.kind, ErrorKind::UnknownRequest),
+			other => panic!("expected a rejection, got {:?}", other),
+		}
+
+		let sync_path = path.clone();
+		let accepted =
+			tokio::task::spawn_blocking(move || request_sync(sync_path, &TestRequest::Echo, DEFAULT_REQUEST_TIMEOUT))
+				.await
+				.unwrap();
+		assert!(accepted.is_ok());
+	}
+
+	#[tokio::test]
+	async fn test_remote_requests() {
+		// Tokens that are short enough to guess are refused.
+		assert!(
+			RemoteControlSocket::open("127.0.0.1:0", "secret", EchoFactory, Logger::root(Discard, o!()))
+				.await
+				.is_err()
+		);
+
+		let socket = RemoteControlSocket::open("127.0.0.1:0", TOKEN, EchoFactory, Logger::root(Discard, o!()))
+			.await
+			.unwrap();
+		let addr = socket.local_addr().unwrap();
+		tokio::spawn(async move { socket.listen().await });
+
+		let mut stream = request_remote(addr, TOKEN, &TestRequest::Echo, DEFAULT_REQUEST_TIMEOUT)
+			.await
+			.unwrap();
+		stream.write_all(b"hello").await.unwrap();
+		stream.shutdown().await.unwrap();
+		let mut echoed = Vec::new();
+		stream.read_to_end(&mut echoed).await.unwrap();
+		assert_eq!(echoed, b"hello");
+
+		for token in ["wrong", &TOKEN[1..], ""] {
+			match request_remote(addr, token, &TestRequest::Echo, DEFAULT_REQUEST_TIMEOUT).await {
+				Err(ProtocolError::Rejected(reply)) => assert_eq!(reply.kind, ErrorKind::PermissionDenied),
+				other => panic!("expected a rejection, got {:?}", other),
+			}
+		}
+
+		// Headers can't carry a token, so remote connections that send one are closed without running anything.
+		let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+		stream.write_all(b"ACTION=echo\nhello").await.unwrap();
+		let mut echoed = Vec::new();
+		// The connection may be reset rather than closed, as the header is never read.
+		let _ = stream.read_to_end(&mut echoed).await;
+		assert!(echoed.is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_limits_and_shutdown() {
+		let socket = RemoteControlSocket::open("127.0.0.1:0", TOKEN, EchoFactory, Logger::root(Discard, o!()))
+			.await
+			.unwrap()
+			.with_max_connections_per_peer(1)
+			.with_drain_timeout(Duration::from_millis(100));
+		let addr = socket.local_addr().unwrap();
+		let shutdown = socket.shutdown_handle();
+		let listener = tokio::spawn(async move { socket.listen().await });
+
+		// The echo keeps running until the client closes its side, so the connection stays open.
+		let _stream = request_remote(addr, TOKEN, &TestRequest::Echo, DEFAULT_REQUEST_TIMEOUT)
+			.await
+			.unwrap();
+		assert!(request_remote(addr, TOKEN, &TestRequest::Echo, DEFAULT_REQUEST_TIMEOUT)
+			.await
+			.is_err());
+
+		// The echo doesn't finish by itself, so it's cancelled once the drain timeout passes.
+		shutdown.shutdown();
+		assert!(shutdown.is_shutdown());
+		timeout(Duration::from_secs(5), listener).await.unwrap().unwrap();
+
+		let socket = RemoteControlSocket::open("127.0.0.1:0", TOKEN, EchoFactory, Logger::root(Discard, o!()))
+			.await
+			.unwrap()
+			.with_idle_timeout(Some(Duration::from_millis(100)));
+		let addr = socket.local_addr().unwrap();
+		tokio::spawn(async move { socket.listen().await });
+
+		// Connections that never send a request are closed.
+		let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+		let mut buf = Vec::new();
+		assert!(timeout(Duration::from_secs(5), stream.read_to_end(&mut buf))
+			.await
+			.is_ok());
+	}
+}
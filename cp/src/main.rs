@@ -0,0 +1,68 @@
+use std::{fs, path::PathBuf, process::ExitCode};
+
+use clap::{Arg, ArgAction, Command};
+use common::fsops::{copy_file, copy_tree, destination};
+
+fn main() -> ExitCode {
+	let matches = Command::new("cp")
+		.about("copy files and directories")
+		.author("Colin Douch")
+		.version("0.1")
+		.arg(
+			Arg::new("recursive")
+				.short('r')
+				.short_alias('R')
+				.long("recursive")
+				.help("copy directories recursively")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("paths")
+				.required(true)
+				.num_args(2..)
+				.help("the files to copy, and then where to copy them to"),
+		)
+		.get_matches();
+
+	let recursive = matches.get_flag("recursive");
+	let mut paths: Vec<PathBuf> = matches
+		.get_many::<String>("paths")
+		.unwrap()
+		.map(PathBuf::from)
+		.collect();
+	let dst = paths.pop().expect("BUG: at least two paths are required");
+	if paths.len() > 1 && !dst.is_dir() {
+		eprintln!("cp: target '{}' is not a directory", dst.display());
+		return ExitCode::FAILURE;
+	}
+
+	let mut code = ExitCode::SUCCESS;
+	for src in paths {
+		let target = destination(&src, &dst);
+		let metadata = match fs::metadata(&src) {
+			Ok(metadata) => metadata,
+			Err(e) => {
+				eprintln!("cp: cannot stat '{}': {}", src.display(), e);
+				code = ExitCode::FAILURE;
+				continue;
+			}
+		};
+
+		let result = if !metadata.is_dir() {
+			copy_file(&src, &target, &metadata)
+		} else if recursive {
+			copy_tree(&src, &target)
+		} else {
+			eprintln!("cp: -r not specified; omitting directory '{}'", src.display());
+			code = ExitCode::FAILURE;
+			continue;
+		};
+
+		if let Err(e) = result {
+			eprintln!("cp: cannot copy '{}' to '{}': {}", src.display(), target.display(), e);
+			code = ExitCode::FAILURE;
+		}
+	}
+
+	code
+}
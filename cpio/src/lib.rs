@@ -1,10 +1,14 @@
 use std::{
-	fs::{self, File},
-	io::{self, Read},
-	os::unix::fs::{FileTypeExt, MetadataExt},
-	path::Path,
+	fs::{self, File, OpenOptions, Permissions},
+	io::{self, BufRead, BufReader, Read, Write},
+	os::unix::fs::{symlink, FileTypeExt, MetadataExt, OpenOptionsExt, PermissionsExt},
+	path::{Component, Path},
 };
 
+use common::fswalk::{FsWalk, SymlinkPolicy};
+use flate2::bufread::GzDecoder;
+use thiserror::Error;
+
 // The magic number for a CPIO archive.
 const CPIO_MAGIC: &[u8; 6] = b"070701";
 
@@ -22,30 +26,122 @@ const S_IFREG: u32 = 0o100000; // regular file
 const S_IFIFO: u32 = 0o010000; // fifo (named pipe)
 const S_IFLNK: u32 = 0o120000; // symbolic link
 const S_IFSOCK: u32 = 0o140000; // socket file
+const S_IFMT: u32 = 0o170000; // the bits of the mode that hold the file type
 
 const ALIGNMENT: usize = 4;
 
+// The size of the blocks that archives are padded out to when concatenating them, as cpio(1) does.
+const SEGMENT_ALIGNMENT: usize = 512;
+
+// The kind of data at the start of a segment of a concatenated archive, detected from its first byte.
+#[derive(Debug, PartialEq)]
+enum SegmentKind {
+	// An uncompressed CPIO archive.
+	Cpio,
+	// A gzip compressed run of CPIO archives.
+	Gzip,
+	// A compression format that the kernel understands, but we don't.
+	Unsupported(&'static str),
+}
+
+impl SegmentKind {
+	fn detect(first_byte: u8) -> io::Result<SegmentKind> {
+		match first_byte {
+			b'0' => Ok(SegmentKind::Cpio),
+			0x1f => Ok(SegmentKind::Gzip),
+			b'B' => Ok(SegmentKind::Unsupported("bzip2")),
+			0x5d => Ok(SegmentKind::Unsupported("lzma")),
+			0xfd => Ok(SegmentKind::Unsupported("xz")),
+			0x89 => Ok(SegmentKind::Unsupported("lzo")),
+			0x02 => Ok(SegmentKind::Unsupported("lz4")),
+			0x28 => Ok(SegmentKind::Unsupported("zstd")),
+			_ => Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("Unknown segment type starting with {:#04x}", first_byte),
+			)),
+		}
+	}
+}
+
+// How entries with unsafe names (see `sanitize_name`) are handled when reading or extracting an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NamePolicy {
+	// Fail with an `InvalidData` error, wrapping an `UnsafeName`, at the first unsafe entry.
+	Strict,
+	// Skip unsafe entries.
+	Lenient,
+}
+
+impl NamePolicy {
+	// Decide what to do with an unsafe entry, returning an error to fail with, or Ok if the entry should be skipped.
+	fn reject(self, unsafe_name: UnsafeName) -> io::Result<()> {
+		match self {
+			NamePolicy::Strict => Err(io::Error::new(io::ErrorKind::InvalidData, unsafe_name)),
+			NamePolicy::Lenient => Ok(()),
+		}
+	}
+}
+
+// Why an entry name can't be used safely.
+#[derive(Debug, Error, PartialEq)]
+pub enum UnsafeName {
+	#[error("entry has an empty name")]
+	Empty,
+
+	#[error("entry name contains a NUL: {0:?}")]
+	ContainsNul(String),
+
+	#[error("entry name is absolute: {0}")]
+	Absolute(String),
+
+	#[error("entry name escapes the archive with `..`: {0}")]
+	Traversal(String),
+
+	#[error("entry would be extracted through a symlink: {0}")]
+	ThroughSymlink(String),
+}
+
 #[derive(Debug)]
 pub struct CPIOArchive {
 	pub entries: Vec<Entry>,
 }
 
 impl CPIOArchive {
-	// Read a CPIO archive from the reader.
+	// Read CPIO archives from the reader until it runs out, merging them into one archive in the same way the kernel
+	// unpacks an initramfs. Archives can be back-to-back, padded out with NULs, and gzip compressed.
+	// Entries with unsafe names are an error.
 	pub fn read<T>(reader: &mut T) -> io::Result<CPIOArchive>
 	where
 		T: io::Read,
 	{
-		let mut entries = Vec::new();
+		CPIOArchive::read_with_policy(reader, NamePolicy::Strict)
+	}
 
-		loop {
-			let entry = Entry::read(reader)?;
+	// Read CPIO archives from the reader, like `read`, handling entries with unsafe names with the given policy.
+	// The names of the entries that are kept are normalized by `sanitize_name`.
+	pub fn read_with_policy<T>(reader: &mut T, policy: NamePolicy) -> io::Result<CPIOArchive>
+	where
+		T: io::Read,
+	{
+		let mut reader = BufReader::new(reader);
+		let mut entries = Vec::new();
 
-			if entry.name == TRAILER_ENTRY_NAME {
-				break;
+		while let Some(first_byte) = skip_padding(&mut reader)? {
+			match SegmentKind::detect(first_byte)? {
+				SegmentKind::Cpio => entries.extend(read_archive(&mut reader, policy)?),
+				SegmentKind::Gzip => {
+					let mut decoder = BufReader::new(GzDecoder::new(&mut reader));
+					while skip_padding(&mut decoder)?.is_some() {
+						entries.extend(read_archive(&mut decoder, policy)?);
+					}
+				}
+				SegmentKind::Unsupported(compression) => {
+					return Err(io::Error::new(
+						io::ErrorKind::Unsupported,
+						format!("{} compressed archives are unsupported", compression),
+					))
+				}
 			}
-
-			entries.push(entry);
 		}
 
 		Ok(CPIOArchive { entries })
@@ -65,24 +161,28 @@ impl CPIOArchive {
 		Ok(())
 	}
 
+	// Write a CPIO archive to the writer, padded out to a whole number of blocks so that another archive can be
+	// concatenated after it.
+	pub fn write_segment<T>(&self, writer: &mut T) -> io::Result<()>
+	where
+		T: io::Write,
+	{
+		let mut counter = CountingWriter {
+			inner: writer,
+			count: 0,
+		};
+		self.write(&mut counter)?;
+
+		let padding = num_padding_bytes(counter.count, SEGMENT_ALIGNMENT);
+		counter.inner.write_all(&vec![0; padding])
+	}
+
 	// Create a CPIO archive from a directory, reading all files and subdirectories recursively.
 	// The paths in the archive will be relative to the given path.
 	pub fn from_path(path: &Path) -> io::Result<CPIOArchive> {
-		let mut dirs_to_scan = vec![path.to_path_buf()];
 		let mut entries = Vec::new();
-
-		while let Some(dir) = dirs_to_scan.pop() {
-			entries.push(Entry::from_file(&dir)?);
-			for entry in fs::read_dir(dir)? {
-				let entry = entry?;
-				let path = entry.path();
-
-				if path.is_dir() {
-					dirs_to_scan.push(path);
-				} else {
-					entries.push(Entry::from_file(&path)?);
-				}
-			}
+		for entry in FsWalk::new(path).symlinks(SymlinkPolicy::Follow) {
+			entries.push(Entry::from_file(&entry?.path)?);
 		}
 
 		// Trim the file prefix from the paths.
@@ -92,6 +192,115 @@ impl CPIOArchive {
 
 		Ok(CPIOArchive { entries })
 	}
+
+	// Extract the archive into the given directory, which must already exist. Directories, regular files, and symlinks
+	// are created with the permissions in the archive, and other kinds of files are skipped, as creating them needs
+	// privileges that extracting an archive shouldn't. Entries with unsafe names, or that would be written through a
+	// symlink (possibly one created earlier in the archive), are handled with the given policy, so that an archive
+	// can't write outside of the directory.
+	pub fn extract(&self, dest: &Path, policy: NamePolicy) -> io::Result<()> {
+		for entry in &self.entries {
+			let name = match sanitize_name(&entry.name) {
+				Ok(name) => name,
+				Err(unsafe_name) => {
+					policy.reject(unsafe_name)?;
+					continue;
+				}
+			};
+
+			if name == "." {
+				continue;
+			}
+
+			// Symlinks in the archive can point anywhere, so never follow one that's already in the destination.
+			let through_symlink = Path::new(&name)
+				.ancestors()
+				.skip(1)
+				.filter(|ancestor| !ancestor.as_os_str().is_empty())
+				.any(|ancestor| is_symlink(&dest.join(ancestor)));
+			if through_symlink {
+				policy.reject(UnsafeName::ThroughSymlink(name))?;
+				continue;
+			}
+
+			let path = dest.join(&name);
+			if let Some(parent) = path.parent() {
+				fs::create_dir_all(parent)?;
+			}
+
+			// Replace whatever is in the way, rather than writing to the target of a symlink.
+			if is_symlink(&path) {
+				fs::remove_file(&path)?;
+			}
+
+			let permissions = Permissions::from_mode(entry.header.mode & 0o7777);
+			match entry.header.mode & S_IFMT {
+				S_IFDIR => {
+					fs::create_dir_all(&path)?;
+					fs::set_permissions(&path, permissions)?;
+				}
+				S_IFREG => {
+					let mut file = OpenOptions::new()
+						.write(true)
+						.create(true)
+						.truncate(true)
+						.mode(permissions.mode())
+						.open(&path)?;
+					file.write_all(&entry.data)?;
+				}
+				S_IFLNK => {
+					let target = std::str::from_utf8(&entry.data)
+						.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "symlink target is invalid UTF-8"))?;
+					if path.exists() {
+						fs::remove_file(&path)?;
+					}
+
+					symlink(target, &path)?;
+				}
+				_ => {}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+// Check that an entry name is safe to extract, returning it normalized: relative, without `.` components, repeated
+// slashes, or trailing slashes, and with `..` components resolved. The root of the archive is `.`.
+// Names that are absolute, or that `..` takes outside of the archive, are unsafe.
+pub fn sanitize_name(name: &str) -> Result<String, UnsafeName> {
+	if name.is_empty() {
+		return Err(UnsafeName::Empty);
+	}
+
+	if name.contains('\0') {
+		return Err(UnsafeName::ContainsNul(name.to_owned()));
+	}
+
+	let mut components = Vec::new();
+	for component in Path::new(name).components() {
+		match component {
+			Component::RootDir | Component::Prefix(_) => return Err(UnsafeName::Absolute(name.to_owned())),
+			Component::CurDir => {}
+			Component::ParentDir => {
+				if components.pop().is_none() {
+					return Err(UnsafeName::Traversal(name.to_owned()));
+				}
+			}
+			Component::Normal(part) => components.push(part.to_str().expect("name is valid unicode")),
+		}
+	}
+
+	if components.is_empty() {
+		return Ok(String::from("."));
+	}
+
+	Ok(components.join("/"))
+}
+
+// Whether the path is a symlink, without following it.
+fn is_symlink(path: &Path) -> bool {
+	fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
 }
 
 // The header for a CPIO entry.
@@ -208,11 +417,12 @@ impl Entry {
 
 		reader.read_exact(&mut vec![0; num_padding_bytes(header.size as usize, ALIGNMENT)])?;
 
-		Ok(Entry {
-			header,
-			name: String::from_utf8(namebuf).unwrap().trim_end_matches('\0').to_string(),
-			data,
-		})
+		let name = String::from_utf8(namebuf)
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "entry name is invalid UTF-8"))?
+			.trim_end_matches('\0')
+			.to_string();
+
+		Ok(Entry { header, name, data })
 	}
 
 	pub fn write(&self, writer: &mut dyn io::Write) -> io::Result<()> {
@@ -235,6 +445,12 @@ impl Entry {
 		Ok(())
 	}
 
+	// Replace the name of the entry, keeping the header consistent with it.
+	pub fn set_name(&mut self, name: String) {
+		self.header.namesize = name.len() as u32 + 1;
+		self.name = name;
+	}
+
 	// Trim the given prefix from the file name.
 	pub fn trim_file_prefix(&mut self, prefix: &Path) {
 		let path = Path::new(&self.name);
@@ -247,6 +463,37 @@ impl Entry {
 		}
 	}
 
+	// Create a root owned directory entry, with the given path inside the archive.
+	pub fn directory(name: &str) -> Entry {
+		Entry::new(name, S_IFDIR | 0o755, 2, Vec::new())
+	}
+
+	// Create a root owned regular file entry with the given contents, with the given path inside the archive.
+	pub fn file(name: &str, data: Vec<u8>) -> Entry {
+		Entry::new(name, S_IFREG | 0o644, 1, data)
+	}
+
+	fn new(name: &str, mode: u32, nlink: u32, data: Vec<u8>) -> Entry {
+		Entry {
+			header: EntryHeader {
+				inode: 0,
+				mode,
+				uid: 0,
+				gid: 0,
+				nlink,
+				mtime: 0,
+				size: data.len() as u32,
+				devmajor: 0,
+				devminor: 0,
+				rdevmajor: 0,
+				rdevminor: 0,
+				namesize: name.len() as u32 + 1,
+			},
+			name: name.to_owned(),
+			data,
+		}
+	}
+
 	// Create a CPIO entry from a file.
 	pub fn from_file(path: &Path) -> io::Result<Entry> {
 		let metadata = fs::metadata(path)?;
@@ -281,6 +528,80 @@ impl Entry {
 	}
 }
 
+// Read a single CPIO archive from the reader, up to and including its trailer, handling entries with unsafe names
+// with the given policy.
+fn read_archive<T>(reader: &mut T, policy: NamePolicy) -> io::Result<Vec<Entry>>
+where
+	T: io::Read,
+{
+	let mut entries = Vec::new();
+
+	loop {
+		let mut entry = Entry::read(reader)?;
+
+		if entry.name == TRAILER_ENTRY_NAME {
+			break;
+		}
+
+		match sanitize_name(&entry.name) {
+			Ok(name) => {
+				if name != entry.name {
+					entry.set_name(name);
+				}
+
+				entries.push(entry);
+			}
+			Err(unsafe_name) => policy.reject(unsafe_name)?,
+		}
+	}
+
+	Ok(entries)
+}
+
+// Skip over the NULs that pad out the space between archives, returning the first byte after them,
+// or None if the reader ran out.
+fn skip_padding<T>(reader: &mut T) -> io::Result<Option<u8>>
+where
+	T: BufRead,
+{
+	loop {
+		let buf = reader.fill_buf()?;
+		if buf.is_empty() {
+			return Ok(None);
+		}
+
+		match buf.iter().position(|b| *b != 0) {
+			Some(position) => {
+				let first_byte = buf[position];
+				reader.consume(position);
+				return Ok(Some(first_byte));
+			}
+			None => {
+				let len = buf.len();
+				reader.consume(len);
+			}
+		}
+	}
+}
+
+// A writer that counts the number of bytes written through it.
+struct CountingWriter<'a, T: io::Write> {
+	inner: &'a mut T,
+	count: usize,
+}
+
+impl<T: io::Write> io::Write for CountingWriter<'_, T> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+		self.count += written;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
 // Calculate the number of padding bytes needed to pad num_bytes to pad_to.
 fn num_padding_bytes(num_bytes: usize, pad_to: usize) -> usize {
 	(pad_to - (num_bytes % pad_to)) % pad_to
@@ -324,22 +645,144 @@ fn mode(metadata: &fs::Metadata) -> u32 {
 }
 
 fn trailer() -> Entry {
-	Entry {
-		header: EntryHeader {
-			inode: 0,
-			mode: 0,
-			uid: 0,
-			gid: 0,
-			nlink: 1,
-			mtime: 0,
-			size: 0,
-			devmajor: 0,
-			devminor: 0,
-			rdevmajor: 0,
-			rdevminor: 0,
-			namesize: TRAILER_ENTRY_NAME.len() as u32 + 1,
-		},
-		name: String::from(TRAILER_ENTRY_NAME),
-		data: vec![],
+	Entry::new(TRAILER_ENTRY_NAME, 0, 1, vec![])
+}
+
+#[cfg(test)]
+mod test {
+	use std::{
+		fs,
+		io::{Cursor, Write},
+	};
+
+	use common::testing::TempDir;
+	use flate2::{write::GzEncoder, Compression};
+
+	use super::{sanitize_name, CPIOArchive, Entry, NamePolicy, UnsafeName, S_IFLNK};
+
+	fn archive(names: &[&str]) -> CPIOArchive {
+		CPIOArchive {
+			entries: names
+				.iter()
+				.map(|name| Entry::file(name, name.as_bytes().to_vec()))
+				.collect(),
+		}
+	}
+
+	fn names(archive: &CPIOArchive) -> Vec<&str> {
+		archive.entries.iter().map(|e| e.name.as_str()).collect()
+	}
+
+	#[test]
+	fn test_read_single() {
+		let mut buf = Vec::new();
+		archive(&["a", "b"]).write(&mut buf).unwrap();
+
+		let read = CPIOArchive::read(&mut Cursor::new(buf)).unwrap();
+		assert_eq!(names(&read), vec!["a", "b"]);
+		assert_eq!(read.entries[1].data, b"b");
+	}
+
+	#[test]
+	fn test_read_concatenated() {
+		let mut buf = Vec::new();
+
+		// An uncompressed segment, padded out to a block, like early microcode.
+		archive(&["kernel/x86/microcode/GenuineIntel.bin"])
+			.write_segment(&mut buf)
+			.unwrap();
+		assert_eq!(buf.len() % 512, 0);
+
+		// Two back-to-back archives with no padding between them.
+		archive(&["a"]).write(&mut buf).unwrap();
+		archive(&["b"]).write(&mut buf).unwrap();
+
+		// A compressed segment, containing two archives itself.
+		let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+		archive(&["c"]).write(&mut encoder).unwrap();
+		archive(&["d"]).write(&mut encoder).unwrap();
+		buf.write_all(&encoder.finish().unwrap()).unwrap();
+
+		// Trailing padding is ignored.
+		buf.extend_from_slice(&[0; 100]);
+
+		let read = CPIOArchive::read(&mut Cursor::new(buf)).unwrap();
+		assert_eq!(
+			names(&read),
+			vec!["kernel/x86/microcode/GenuineIntel.bin", "a", "b", "c", "d"]
+		);
+	}
+
+	#[test]
+	fn test_read_unsupported() {
+		let err = CPIOArchive::read(&mut Cursor::new(vec![0xfd, b'7', b'z', b'X', b'Z', 0])).unwrap_err();
+		assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+	}
+
+	#[test]
+	fn test_sanitize_name() {
+		assert_eq!(sanitize_name("."), Ok(String::from(".")));
+		assert_eq!(sanitize_name("./bin//sh/"), Ok(String::from("bin/sh")));
+		assert_eq!(sanitize_name("usr/lib/../bin"), Ok(String::from("usr/bin")));
+		assert_eq!(sanitize_name("a/.."), Ok(String::from(".")));
+
+		assert_eq!(sanitize_name(""), Err(UnsafeName::Empty));
+		assert_eq!(
+			sanitize_name("/etc/passwd"),
+			Err(UnsafeName::Absolute(String::from("/etc/passwd")))
+		);
+		assert_eq!(
+			sanitize_name("a/../../etc"),
+			Err(UnsafeName::Traversal(String::from("a/../../etc")))
+		);
+		assert_eq!(
+			sanitize_name("a\0b"),
+			Err(UnsafeName::ContainsNul(String::from("a\0b")))
+		);
+	}
+
+	#[test]
+	fn test_read_unsafe_names() {
+		let mut buf = Vec::new();
+		archive(&["./a", "/etc/passwd", "../b", "c/../d"])
+			.write(&mut buf)
+			.unwrap();
+
+		let err = CPIOArchive::read(&mut Cursor::new(&buf)).unwrap_err();
+		assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+		assert_eq!(
+			err.get_ref().and_then(|e| e.downcast_ref::<UnsafeName>()),
+			Some(&UnsafeName::Absolute(String::from("/etc/passwd")))
+		);
+
+		let read = CPIOArchive::read_with_policy(&mut Cursor::new(&buf), NamePolicy::Lenient).unwrap();
+		assert_eq!(names(&read), vec!["a", "d"]);
+		assert_eq!(read.entries[0].header.namesize, 2);
+	}
+
+	#[test]
+	fn test_extract() {
+		let dir = TempDir::new("cpio-extract").unwrap();
+		let dest = dir.join("dest");
+		fs::create_dir_all(&dest).unwrap();
+
+		// A symlink out of the destination, and a file that would be written through it.
+		let mut archive = archive(&["etc/hostname"]);
+		archive.entries.push(Entry::directory("etc"));
+		let mut link = Entry::file("escape", dir.to_str().unwrap().as_bytes().to_vec());
+		link.header.mode = S_IFLNK | 0o777;
+		archive.entries.push(link);
+		archive.entries.push(Entry::file("escape/evil", b"evil".to_vec()));
+
+		let err = archive.extract(&dest, NamePolicy::Strict).unwrap_err();
+		assert_eq!(
+			err.get_ref().and_then(|e| e.downcast_ref::<UnsafeName>()),
+			Some(&UnsafeName::ThroughSymlink(String::from("escape/evil")))
+		);
+
+		archive.extract(&dest, NamePolicy::Lenient).unwrap();
+		assert_eq!(fs::read(dest.join("etc/hostname")).unwrap(), b"etc/hostname");
+		assert!(dest.join("escape").is_symlink());
+		assert!(!dir.join("evil").exists());
 	}
 }
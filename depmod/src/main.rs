@@ -1,22 +1,75 @@
 use std::{
 	borrow::Cow,
 	collections::HashMap,
-	fs::{read_dir, File},
-	io::{stderr, BufReader, Cursor, ErrorKind, Read, Seek, Write},
+	fs::{self, File},
+	io::{stderr, stdout, BufReader, Cursor, ErrorKind, Read, Seek, Write},
 	path::{Path, PathBuf},
 	process::ExitCode,
 };
 
 use anyhow::anyhow;
 use clap::{Arg, ArgAction, Command};
+use common::fswalk::{FsWalk, SymlinkPolicy};
 use common::iter::SplitOn;
 use common::obs::assemble_logger;
-use elf::{ElfFile, ElfSymbolBinding, ElfSymbolType};
+use elf::{ElfError, ElfFile, ElfSymbolBinding, ElfSymbolType, SectionHeader};
 use lzma_rs::xz_decompress;
 use nix::sys::utsname::uname;
 use slog::{debug, error, info};
 use std::io;
 
+/// The files that depmod generates, in the order they're printed with `--stdout`.
+const OUTPUT_FILES: [&str; 5] = [
+	"modules.dep",
+	"modules.alias",
+	"modules.symbols",
+	"modules.name",
+	"modules.devname",
+];
+
+/// The contents of the files that depmod generates, which are built up in memory so that nothing is written (or
+/// truncated) until every module has been read.
+#[derive(Default)]
+struct Outputs {
+	deps: Vec<u8>,
+	aliases: Vec<u8>,
+	symbols: Vec<u8>,
+	names: Vec<u8>,
+	devnames: Vec<u8>,
+}
+
+impl Outputs {
+	fn files(&self) -> [(&'static str, &[u8]); 5] {
+		[
+			(OUTPUT_FILES[0], &self.deps),
+			(OUTPUT_FILES[1], &self.aliases),
+			(OUTPUT_FILES[2], &self.symbols),
+			(OUTPUT_FILES[3], &self.names),
+			(OUTPUT_FILES[4], &self.devnames),
+		]
+	}
+
+	/// Writes each file into the given directory, creating it if it doesn't exist.
+	fn write_to(&self, directory: &Path) -> io::Result<()> {
+		fs::create_dir_all(directory)?;
+		for (name, contents) in self.files() {
+			fs::write(directory.join(name), contents)?;
+		}
+
+		Ok(())
+	}
+
+	/// Writes every file to the writer, one after the other, each after a comment naming it.
+	fn print<W: Write>(&self, mut writer: W) -> io::Result<()> {
+		for (name, contents) in self.files() {
+			writeln!(writer, "# {}", name)?;
+			writer.write_all(contents)?;
+		}
+
+		writer.flush()
+	}
+}
+
 fn main() -> ExitCode {
 	let logger = assemble_logger(stderr());
 	let name = match uname() {
@@ -34,6 +87,27 @@ fn main() -> ExitCode {
 				.action(ArgAction::Set)
 				.help("the path to scan for modules"),
 		)
+		.arg(
+			Arg::new("base_dir")
+				.short('b')
+				.long("base-dir")
+				.action(ArgAction::Set)
+				.help("the root that the modules path is under, e.g. a staging tree for an image. The paths of modules are recorded relative to it, as they'll be once it's the root"),
+		)
+		.arg(
+			Arg::new("output_dir")
+				.short('o')
+				.long("output-dir")
+				.action(ArgAction::Set)
+				.help("the directory to write the generated files into, instead of the modules path"),
+		)
+		.arg(
+			Arg::new("stdout")
+				.long("stdout")
+				.action(ArgAction::SetTrue)
+				.conflicts_with("output_dir")
+				.help("print the generated files, instead of writing them"),
+		)
 		.get_matches();
 
 	let modules_path = matches
@@ -41,39 +115,18 @@ fn main() -> ExitCode {
 		.map(PathBuf::from)
 		.unwrap_or(default_module_path);
 
-	let mut deps_out = match File::create(modules_path.join("modules.dep")) {
-		Ok(f) => f,
-		Err(e) => {
-			error!(logger, "failed to open modules.dep"; "error" => e.to_string());
-			return ExitCode::FAILURE;
-		}
+	let base_dir = matches.get_one::<String>("base_dir").map(PathBuf::from);
+	let modules_path = match &base_dir {
+		Some(base_dir) => base_dir.join(modules_path.strip_prefix("/").unwrap_or(&modules_path)),
+		None => modules_path,
 	};
 
-	let mut aliases_out = match File::create(modules_path.join("modules.alias")) {
-		Ok(f) => f,
-		Err(e) => {
-			error!(logger, "failed to open modules.alias"; "error" => e.to_string());
-			return ExitCode::FAILURE;
-		}
-	};
-
-	let mut symbols_out = match File::create(modules_path.join("modules.symbols")) {
-		Ok(f) => f,
-		Err(e) => {
-			error!(logger, "failed to open modules.symbols"; "error" => e.to_string());
-			return ExitCode::FAILURE;
-		}
-	};
+	let mut outputs = Outputs::default();
+	outputs
+		.devnames
+		.extend_from_slice(b"# Device nodes to trigger on-demand module loading.\n");
 
-	let mut names_out = match File::create(modules_path.join("modules.name")) {
-		Ok(f) => f,
-		Err(e) => {
-			error!(logger, "failed to open modules.name"; "error" => e.to_string());
-			return ExitCode::FAILURE;
-		}
-	};
-
-	let found_modules = match find_modules(&logger, modules_path) {
+	let found_modules = match find_modules(&logger, modules_path.clone()) {
 		Ok(modules) => modules,
 		Err(e) => {
 			error!(logger, "failed to find kernel modules"; "error" => e.to_string());
@@ -82,11 +135,18 @@ fn main() -> ExitCode {
 	};
 
 	for module_path in found_modules {
-		let data = load_file(&module_path).unwrap();
+		let data = match load_file(&module_path) {
+			Ok(data) => data,
+			Err(e) => {
+				error!(logger, "failed to read {}", module_path.display(); "error" => e.to_string());
+				continue;
+			}
+		};
+
 		let elffile = match ElfFile::new(Cursor::new(data)) {
 			Ok(e) => e,
 			Err(e) => {
-				error!(logger, "failed to read {} as an ELF file", module_path.display(); "error" => e.to_string());
+				log_elf_error(&logger, &module_path, &e);
 				continue;
 			}
 		};
@@ -99,15 +159,61 @@ fn main() -> ExitCode {
 			}
 		};
 
-		write_aliases(&modinfo, &mut aliases_out).expect("failed to write aliases");
-		write_deps(&modinfo, &mut deps_out).expect("failed to write dependencies");
-		write_name(&module_path, &modinfo, &mut names_out).expect("failed to write names");
-		write_symbols(&logger, &modinfo, &elffile, &mut symbols_out).expect("failed to write symbols");
+		// The path the module will be at once the base directory is the root.
+		let installed_path = match base_dir
+			.as_ref()
+			.and_then(|base_dir| module_path.strip_prefix(base_dir).ok())
+		{
+			Some(relative) => Path::new("/").join(relative),
+			None => module_path.clone(),
+		};
+
+		write_aliases(&modinfo, &mut outputs.aliases).expect("failed to write aliases");
+		write_deps(&modinfo, &mut outputs.deps).expect("failed to write dependencies");
+		write_name(&installed_path, &modinfo, &mut outputs.names).expect("failed to write names");
+		write_devname(&modinfo, &mut outputs.devnames).expect("failed to write device names");
+		if let Err(e) = write_symbols(&logger, &modinfo, &elffile, &mut outputs.symbols) {
+			error!(logger, "failed to write symbols for {}", module_path.display(); "error" => e.to_string());
+		}
+	}
+
+	let result = if matches.get_flag("stdout") {
+		outputs.print(stdout().lock())
+	} else {
+		let output_dir = matches
+			.get_one::<String>("output_dir")
+			.map(PathBuf::from)
+			.unwrap_or(modules_path);
+		outputs.write_to(&output_dir)
+	};
+
+	if let Err(e) = result {
+		error!(logger, "failed to write module metadata"; "error" => e.to_string());
+		return ExitCode::FAILURE;
 	}
 
 	ExitCode::SUCCESS
 }
 
+/// Logs why a module couldn't be read as an ELF file, with the details of what's wrong with it as fields.
+fn log_elf_error(logger: &slog::Logger, path: &Path, error: &ElfError) {
+	let path = path.display().to_string();
+	match error {
+		ElfError::InvalidField { field, expected, found } => {
+			error!(logger, "module is not a valid ELF file"; "path" => path, "field" => field, "expected" => expected, "found" => found)
+		}
+		ElfError::OutOfBounds {
+			what,
+			offset,
+			end,
+			file_size,
+		} => {
+			error!(logger, "module is truncated"; "path" => path, "what" => what, "offset" => offset, "end" => end, "file_size" => file_size)
+		}
+		_ => error!(logger, "failed to read module as an ELF file"; "path" => path, "error" => error.to_string()),
+	}
+}
+
 fn load_file(path: &Path) -> io::Result<Vec<u8>> {
 	let mut file = BufReader::new(File::open(path)?);
 	let mut buffer = Vec::new();
@@ -129,49 +235,23 @@ fn load_file(path: &Path) -> io::Result<Vec<u8>> {
 fn find_modules(logger: &slog::Logger, module_path: PathBuf) -> anyhow::Result<Vec<PathBuf>> {
 	info!(logger, "Reading modules from {}", module_path.display());
 
-	let mut to_search = vec![module_path];
 	let mut found_modules = Vec::new();
-	while let Some(search_path) = to_search.pop() {
-		let dir = match read_dir(&search_path) {
-			Ok(dir) => dir,
-			Err(e) => {
-				return Err(anyhow!("failed to read directory: {}: {}", search_path.display(), e));
-			}
-		};
-
-		for file in dir {
-			let file = match file {
-				Ok(entry) => entry,
-				Err(e) => {
-					return Err(anyhow!("failed to read file: {}: {}", search_path.display(), e));
-				}
-			};
-
-			let ty = match file.file_type() {
-				Ok(f) => f,
-				Err(e) => {
-					return Err(anyhow!("failed to get file type: {}: {}", file.path().display(), e));
-				}
-			};
-
-			let path = file.path();
-
-			if ty.is_symlink() {
-				continue; // Ignore symlinks to avoid loops
-			} else if ty.is_dir() {
-				to_search.push(file.path());
-			} else if ty.is_file() {
-				let extension = path
-					.extension()
-					.map(|o| o.to_string_lossy())
-					.unwrap_or(Cow::Borrowed(""));
-
-				if extension == "ko" || extension == "xz" {
-					found_modules.push(path);
-				}
-			} else {
-				debug!(logger, "skipping file {} {}", path.display(), path.ends_with(".ko.xz"));
+	// Symlinks are skipped to avoid loops, and to avoid finding the same module twice.
+	for entry in FsWalk::new(module_path).symlinks(SymlinkPolicy::Skip) {
+		let entry = entry.map_err(|e| anyhow!("failed to search for modules: {}", e))?;
+		let path = entry.path;
+
+		if entry.metadata.is_file() {
+			let extension = path
+				.extension()
+				.map(|o| o.to_string_lossy())
+				.unwrap_or(Cow::Borrowed(""));
+
+			if extension == "ko" || extension == "xz" {
+				found_modules.push(path);
 			}
+		} else if !entry.metadata.is_dir() {
+			debug!(logger, "skipping file {} {}", path.display(), path.ends_with(".ko.xz"));
 		}
 	}
 
@@ -195,7 +275,18 @@ fn write_deps<W: Write>(modinfo: &ModInfo, mut writer: W) -> io::Result<()> {
 // Writes an entry into the module.name file. This is technically non standard - modprobe
 // etc will find modules themselves, but having this memoization helps make those simpler.
 fn write_name<W: Write>(path: &Path, modinfo: &ModInfo, mut writer: W) -> io::Result<()> {
-	writer.write_all(format!("{}:{}", modinfo.name, path.display()).as_bytes())
+	writer.write_all(format!("{}:{}\n", modinfo.name, path.display()).as_bytes())
+}
+
+/// Writes an entry into the modules.devname file, if the module provides a device node that should exist before
+/// the module is loaded. Entries are in the form `<module> <devname> <c|b><major>:<minor>`.
+fn write_devname<W: Write>(modinfo: &ModInfo, mut writer: W) -> io::Result<()> {
+	match (&modinfo.devname, &modinfo.device_number) {
+		(Some(devname), Some((kind, major, minor))) => {
+			writer.write_all(format!("{} {} {}{}:{}\n", modinfo.name, devname, kind, major, minor).as_bytes())
+		}
+		_ => Ok(()),
+	}
 }
 
 fn write_symbols<T: Read + Seek, W: Write>(
@@ -204,30 +295,29 @@ fn write_symbols<T: Read + Seek, W: Write>(
 	file: &ElfFile<T>,
 	mut writer: W,
 ) -> Result<(), io::Error> {
-	let symbol_table_header = match file
-		.section_headers()
-		.map(|s| s.unwrap())
-		.find(|s| file.section_header_name(s) == Some(".symtab"))
-	{
+	let symbol_table_header = match find_section(file, ".symtab")? {
 		Some(s) => s,
 		None => {
 			return Err(io::Error::new(ErrorKind::InvalidData, "missing .symtab section"));
 		}
 	};
 
-	let string_table_header = match file
-		.section_headers()
-		.map(|s| s.unwrap())
-		.find(|s| file.section_header_name(s) == Some(".strtab"))
-	{
+	let string_table_header = match find_section(file, ".strtab")? {
 		Some(s) => s,
 		None => {
 			return Err(io::Error::new(ErrorKind::InvalidData, "missing .strtab section"));
 		}
 	};
 
-	let symbol_table = symbol_table_header.read_symbol_table_section(file).unwrap().unwrap();
-	let string_table = string_table_header.read_string_table_section(file).unwrap().unwrap();
+	let symbol_table = match symbol_table_header.read_symbol_table_section(file) {
+		Some(table) => table?,
+		None => return Err(io::Error::new(ErrorKind::InvalidData, ".symtab isn't a symbol table")),
+	};
+
+	let string_table = match string_table_header.read_string_table_section(file) {
+		Some(table) => table?,
+		None => return Err(io::Error::new(ErrorKind::InvalidData, ".strtab isn't a string table")),
+	};
 
 	for symbol in symbol_table.iter() {
 		let symbol_name = match string_table.get_string_at_offset(symbol.name_offset) {
@@ -250,6 +340,18 @@ fn write_symbols<T: Read + Seek, W: Write>(
 	Ok(())
 }
 
+/// Finds the section with the given name.
+fn find_section<T: Read + Seek>(file: &ElfFile<T>, name: &str) -> io::Result<Option<SectionHeader>> {
+	for header in file.section_headers() {
+		let header = header?;
+		if file.section_header_name(&header) == Some(name) {
+			return Ok(Some(header));
+		}
+	}
+
+	Ok(None)
+}
+
 #[derive(Default, Debug)]
 struct ModInfo {
 	name: String,
@@ -261,6 +363,10 @@ struct ModInfo {
 	parameter_descriptions: HashMap<String, String>,
 	parameter_types: HashMap<String, String>,
 	aliases: Vec<String>,
+	/// The name of the device node under /dev that the module provides, from a `devname:` alias.
+	devname: Option<String>,
+	/// The type ('c' or 'b'), major, and minor of that device node, from a `char-major-` or `block-major-` alias.
+	device_number: Option<(char, u32, u32)>,
 	dependencies: Vec<String>,
 	return_trampoline: bool,
 	in_tree: bool,
@@ -269,11 +375,7 @@ struct ModInfo {
 
 impl ModInfo {
 	fn read<T: Read + Seek>(elffile: &ElfFile<T>) -> io::Result<Self> {
-		let modinfo_section = match elffile
-			.section_headers()
-			.map(|s| s.expect("failed to read section headers"))
-			.find(|s| elffile.section_header_name(s) == Some(".modinfo"))
-		{
+		let modinfo_section = match find_section(elffile, ".modinfo")? {
 			Some(s) => s,
 			None => {
 				return Err(io::Error::new(ErrorKind::InvalidData, "missing .modinfo section"));
@@ -305,6 +407,7 @@ impl ModInfo {
 						if key == "depends" {
 							modinfo.dependencies.push(value)
 						} else if key == "alias" {
+							modinfo.parse_device_alias(&value);
 							modinfo.aliases.push(value)
 						}
 					}
@@ -330,4 +433,30 @@ impl ModInfo {
 
 		Ok(modinfo)
 	}
+
+	/// Picks out the aliases that describe the static device node of the module, if it has one. Modules that create
+	/// their device node on load declare its name with `MODULE_ALIAS("devname:<name>")`, and its number with
+	/// `MODULE_ALIAS_MISCDEV` or similar, which expand to `char-major-<major>-<minor>` aliases.
+	fn parse_device_alias(&mut self, alias: &str) {
+		if let Some(devname) = alias.strip_prefix("devname:") {
+			self.devname = Some(devname.to_owned());
+			return;
+		}
+
+		let (kind, number) = if let Some(number) = alias.strip_prefix("char-major-") {
+			('c', number)
+		} else if let Some(number) = alias.strip_prefix("block-major-") {
+			('b', number)
+		} else {
+			return;
+		};
+
+		// Aliases for a whole major, like `char-major-4-*`, don't describe a single node. If there are several that
+		// do, the first is the one the node is created with, as in kmod's depmod.
+		if let Some((major, minor)) = number.split_once('-') {
+			if let (Ok(major), Ok(minor)) = (major.parse(), minor.parse()) {
+				self.device_number.get_or_insert((kind, major, minor));
+			}
+		}
+	}
 }
@@ -0,0 +1,116 @@
+use std::{
+	fs::OpenOptions,
+	io::{self, ErrorKind, Read},
+	os::unix::fs::OpenOptionsExt,
+	process::ExitCode,
+};
+
+use clap::{Arg, ArgAction, Command};
+use loggerd::{
+	kmsg::{KmsgRecord, DEFAULT_KMSG_PATH, MAX_KMSG_RECORD_SIZE},
+	syslog::Priority,
+};
+use nix::{errno::Errno, fcntl::OFlag};
+
+fn main() -> ExitCode {
+	let matches = Command::new("dmesg")
+		.version("0.1.0")
+		.author("Colin Douch <colin@quirl.co.nz>")
+		.about("Print the kernel ring buffer")
+		.arg(
+			Arg::new("follow")
+				.short('f')
+				.long("follow")
+				.help("Wait for new messages")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("level")
+				.short('l')
+				.long("level")
+				.num_args(1)
+				.help("Restrict output to the given (comma separated) levels, e.g. err,warning"),
+		)
+		.arg(
+			Arg::new("kmsg-path")
+				.long("kmsg-path")
+				.num_args(1)
+				.default_value(DEFAULT_KMSG_PATH)
+				.help("The path to the kernel log device"),
+		)
+		.get_matches();
+
+	let levels = match matches.get_one::<String>("level") {
+		Some(levels) => {
+			let mut parsed = Vec::new();
+			for level in levels.split(',') {
+				match Priority::severity_from_name(level) {
+					Some(level) => parsed.push(level),
+					None => {
+						eprintln!("dmesg: unknown level '{}'", level);
+						return ExitCode::FAILURE;
+					}
+				}
+			}
+
+			Some(parsed)
+		}
+		None => None,
+	};
+
+	let follow = matches.get_flag("follow");
+	let path: &String = matches.get_one("kmsg-path").unwrap();
+
+	// Without follow, we want to stop once we've read everything that's currently in the buffer, which we detect
+	// by reads returning EAGAIN.
+	let flags = if follow { OFlag::empty() } else { OFlag::O_NONBLOCK };
+	let mut file = match OpenOptions::new().read(true).custom_flags(flags.bits()).open(path) {
+		Ok(file) => file,
+		Err(e) => {
+			eprintln!("dmesg: failed to open {}: {}", path, e);
+			return ExitCode::FAILURE;
+		}
+	};
+
+	match print_records(&mut file, levels.as_deref()) {
+		Ok(_) => ExitCode::SUCCESS,
+		Err(e) => {
+			eprintln!("dmesg: failed to read kernel buffer: {}", e);
+			ExitCode::FAILURE
+		}
+	}
+}
+
+/// Prints every record read from the given source, optionally only printing those whose severity is in `levels`.
+fn print_records<R: Read>(source: &mut R, levels: Option<&[u8]>) -> io::Result<()> {
+	// Each read from /dev/kmsg returns exactly one record.
+	let mut buffer = vec![0; MAX_KMSG_RECORD_SIZE];
+	loop {
+		let len = match source.read(&mut buffer) {
+			Ok(0) => return Ok(()),
+			Ok(len) => len,
+			Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+			// Records were overwritten before we could read them; carry on from the oldest one that's left.
+			Err(e) if e.raw_os_error() == Some(Errno::EPIPE as i32) => continue,
+			Err(e) => return Err(e),
+		};
+
+		let record = match KmsgRecord::parse(&String::from_utf8_lossy(&buffer[..len])) {
+			Some(record) => record,
+			None => continue,
+		};
+
+		if let Some(levels) = levels {
+			if !levels.contains(&record.priority.severity) {
+				continue;
+			}
+		}
+
+		println!(
+			"[{:5}.{:06}] {}",
+			record.timestamp.as_secs(),
+			record.timestamp.subsec_micros(),
+			record.message
+		);
+	}
+}
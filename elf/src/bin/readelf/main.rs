@@ -1,11 +1,12 @@
 use std::{
+	fs,
 	io::{self, Read, Seek},
 	process::ExitCode,
 };
 
 use clap::{Arg, ArgAction, Command};
 use elf::{ElfFile, SectionHeaderType, StringTableSection};
-use tables::{Table, TableSetting};
+use tables::{Alignment, Table, TableSetting};
 
 fn main() -> ExitCode {
 	let matches = Command::new("readelf")
@@ -35,6 +36,12 @@ fn main() -> ExitCode {
 				.help("Display the symbols")
 				.action(ArgAction::SetTrue),
 		)
+		.arg(
+			Arg::new("debug-sizes")
+				.long("debug-sizes")
+				.help("Display how much of the file is taken up by debugging information")
+				.action(ArgAction::SetTrue),
+		)
 		.arg(Arg::new("elffile").help("the file to load").num_args(1).required(true))
 		.get_matches();
 
@@ -42,7 +49,7 @@ fn main() -> ExitCode {
 	let elffile = match ElfFile::open(filepath) {
 		Ok(f) => f,
 		Err(e) => {
-			eprintln!("failed to open {}: {}", filepath, e);
+			eprintln!("readelf: {}: {}", filepath, e);
 			return ExitCode::FAILURE;
 		}
 	};
@@ -67,6 +74,19 @@ fn main() -> ExitCode {
 		print_symbols(&elffile);
 	}
 
+	let debug_sizes = matches.get_flag("debug-sizes");
+	if debug_sizes {
+		let file_size = match fs::metadata(filepath) {
+			Ok(metadata) => metadata.len(),
+			Err(e) => {
+				eprintln!("readelf: {}: {}", filepath, e);
+				return ExitCode::FAILURE;
+			}
+		};
+
+		print_debug_sizes(&elffile, file_size);
+	}
+
 	ExitCode::SUCCESS
 }
 
@@ -78,11 +98,16 @@ fn print_elf_file_header<T: Read + Seek>(file: &ElfFile<T>) {
 	println!("	OS/ABI: {:?}", file.header.abi);
 	println!("	ABI Version: {}", file.header.abi_version);
 	println!("	Type: {:?}", file.header.ty);
-	println!("	Machine {:?}", file.header.architecture);
+	println!("	Machine: {:?}", file.header.architecture);
 	println!("	Entry Point Address: {:#x}", file.header.entrypoint_offset);
 	println!("	Program Header Offset: {:#x}", file.header.program_header_offset);
 	println!("	Section Header Offset: {:#x}", file.header.section_header_offset);
-	println!("	Flags: {:?}", file.header.flags);
+	let machine_flags = file.header.machine_flags();
+	if machine_flags.is_empty() {
+		println!("	Flags: {:#x}", file.header.flags);
+	} else {
+		println!("	Flags: {:#x}, {}", file.header.flags, machine_flags.join(", "));
+	}
 	println!("	Header Size: {}", file.header.header_size);
 	println!("	Program Header Size: {}", file.header.program_header_size);
 	println!("	Number of Program Headers: {}", file.header.program_header_table_len);
@@ -111,7 +136,12 @@ fn print_program_headers<T: Read + Seek>(file: &ElfFile<T>) {
 		"Alignment",
 	])
 	.with_setting(TableSetting::HeaderSeperator)
-	.with_setting(TableSetting::ColumnSeperators);
+	.with_setting(TableSetting::ColumnSeperators)
+	.with_setting(TableSetting::Alignment(1, Alignment::Right))
+	.with_setting(TableSetting::Alignment(4, Alignment::Right))
+	.with_setting(TableSetting::Alignment(5, Alignment::Right))
+	.with_setting(TableSetting::Alignment(7, Alignment::Right))
+	.with_setting(TableSetting::FitTerminal);
 
 	for header in file.program_headers() {
 		if header.is_err() {
@@ -152,7 +182,13 @@ fn print_section_headers<T: Read + Seek>(file: &ElfFile<T>) {
 		"Alignment",
 	])
 	.with_setting(TableSetting::HeaderSeperator)
-	.with_setting(TableSetting::ColumnSeperators);
+	.with_setting(TableSetting::ColumnSeperators)
+	.with_setting(TableSetting::Alignment(4, Alignment::Right))
+	.with_setting(TableSetting::Alignment(5, Alignment::Right))
+	.with_setting(TableSetting::Alignment(7, Alignment::Right))
+	.with_setting(TableSetting::Alignment(8, Alignment::Right))
+	.with_setting(TableSetting::Alignment(9, Alignment::Right))
+	.with_setting(TableSetting::FitTerminal);
 
 	for header in file.section_headers() {
 		if header.is_err() {
@@ -264,3 +300,49 @@ fn print_symbols<T: Read + Seek>(file: &ElfFile<T>) {
 		println!("{}", table);
 	}
 }
+
+fn print_debug_sizes<T: Read + Seek>(file: &ElfFile<T>, file_size: u64) {
+	let sections = match file.debug_sections() {
+		Ok(sections) => sections,
+		Err(e) => {
+			eprintln!("failed to read section headers: {}", e);
+			return;
+		}
+	};
+
+	if sections.is_empty() {
+		println!("There is no debugging information in this file");
+		return;
+	}
+
+	let share = |size: u64| format!("{:.1}%", size as f64 * 100.0 / file_size.max(1) as f64);
+
+	let mut table = Table::new_with_headers(["Name", "Size", "Compressed", "Share"])
+		.with_setting(TableSetting::HeaderSeperator)
+		.with_setting(TableSetting::ColumnSeperators)
+		.with_setting(TableSetting::Alignment(1, Alignment::Right))
+		.with_setting(TableSetting::Alignment(3, Alignment::Right));
+
+	for section in sections.0.iter() {
+		table.add_row([
+			&section.name,
+			&section.size.to_string(),
+			if section.compressed { "yes" } else { "no" },
+			&share(section.size),
+		]);
+	}
+
+	println!("{}", table);
+	println!(
+		"Debugging information takes up {} of {} bytes ({})",
+		sections.total_size(),
+		file_size,
+		share(sections.total_size())
+	);
+
+	if sections.has_line_numbers() {
+		println!("Line numbers are present");
+	} else {
+		println!("Line numbers are not present");
+	}
+}
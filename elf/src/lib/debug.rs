@@ -0,0 +1,73 @@
+use crate::{SectionHeader, SectionHeaderFlags, SectionHeaderType};
+
+/// The prefixes of the names of sections that hold DWARF debugging information. `.zdebug_` sections are compressed in
+/// the GNU format that predates `SHF_COMPRESSED`.
+const DEBUG_SECTION_PREFIXES: [&str; 2] = [".debug_", ".zdebug_"];
+
+/// The name of the DWARF section that maps addresses to source lines, without its prefix.
+const LINE_NUMBER_SECTION: &str = "line";
+
+/// A section that holds debugging information.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugSection {
+	pub name: String,
+
+	/// The number of bytes that the section takes up in the file.
+	pub size: u64,
+
+	/// Whether the section is compressed, in which case it's larger once loaded by a debugger.
+	pub compressed: bool,
+}
+
+impl DebugSection {
+	/// Returns the section as a debug section, if it is one. Debugging information is never loaded into memory, so
+	/// allocated sections (e.g. Rust's `.debug_gdb_scripts`) aren't counted, whatever they're called.
+	pub fn from_header(name: &str, header: &SectionHeader) -> Option<Self> {
+		let prefix = DEBUG_SECTION_PREFIXES.iter().find(|prefix| name.starts_with(*prefix))?;
+		if header.flags.contains(SectionHeaderFlags::Allocated) {
+			return None;
+		}
+
+		// Files split off by `strip --only-keep-debug` keep the headers of the stripped sections, but not their contents.
+		let size = match header.ty {
+			SectionHeaderType::Blank => 0,
+			_ => header.size,
+		};
+
+		Some(Self {
+			name: name.to_owned(),
+			size,
+			compressed: prefix.starts_with(".z") || header.flags.contains(SectionHeaderFlags::Compressed),
+		})
+	}
+
+	/// The name of the section without its prefix, e.g. `info` for `.debug_info`, so that compressed and
+	/// uncompressed sections can be compared.
+	pub fn kind(&self) -> &str {
+		DEBUG_SECTION_PREFIXES
+			.iter()
+			.find_map(|prefix| self.name.strip_prefix(prefix))
+			.unwrap_or(&self.name)
+	}
+}
+
+/// The debugging information in an ELF file, which can be stripped without changing how it runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DebugSections(pub Vec<DebugSection>);
+
+impl DebugSections {
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	/// The number of bytes that debugging information takes up in the file.
+	pub fn total_size(&self) -> u64 {
+		self.0.iter().map(|section| section.size).sum()
+	}
+
+	/// Whether the file has line number information, which debuggers and backtraces need to map addresses back to
+	/// source lines.
+	pub fn has_line_numbers(&self) -> bool {
+		self.0.iter().any(|section| section.kind() == LINE_NUMBER_SECTION)
+	}
+}
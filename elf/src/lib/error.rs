@@ -0,0 +1,51 @@
+use std::io;
+
+use thiserror::Error;
+
+/// Errors that can occur when opening an ELF file, describing what about the file is wrong.
+#[derive(Debug, Error)]
+pub enum ElfError {
+	#[error("IO error: {0}")]
+	IOError(#[from] io::Error),
+
+	/// A field in the ELF header has a value that doesn't make sense, or is inconsistent with the rest of the header.
+	#[error("invalid {field}: expected {expected}, found {found}")]
+	InvalidField {
+		field: &'static str,
+		expected: String,
+		found: String,
+	},
+
+	/// Part of the file that the header points to is past the end of the file, usually because it was truncated.
+	#[error("{what} at {offset:#x}..{end:#x} is past the end of the file ({file_size:#x} bytes)")]
+	OutOfBounds {
+		what: &'static str,
+		offset: u64,
+		end: u64,
+		file_size: u64,
+	},
+
+	/// The header was well formed, but contained values that we don't understand.
+	#[error("failed to parse ELF header: {0}")]
+	InvalidHeader(io::Error),
+}
+
+impl ElfError {
+	pub(crate) fn invalid_field<E: ToString, F: ToString>(field: &'static str, expected: E, found: F) -> Self {
+		Self::InvalidField {
+			field,
+			expected: expected.to_string(),
+			found: found.to_string(),
+		}
+	}
+}
+
+impl From<ElfError> for io::Error {
+	fn from(value: ElfError) -> Self {
+		match value {
+			ElfError::IOError(e) => e,
+			ElfError::OutOfBounds { .. } => io::Error::new(io::ErrorKind::UnexpectedEof, value),
+			_ => io::Error::new(io::ErrorKind::InvalidData, value),
+		}
+	}
+}
@@ -1,14 +1,24 @@
 use std::{
 	fs::File,
-	io::{self, ErrorKind, Read, Seek, SeekFrom},
+	io::{self, Read, Seek, SeekFrom},
 	path::Path,
 	sync::Mutex,
 };
 
+mod debug;
+mod error;
 mod structs;
-use bytestruct::ReadFrom;
+use bytestruct::{Endian, ReadFrom, ReadFromWithEndian};
+pub use debug::*;
+pub use error::*;
 pub use structs::*;
 
+/// The magic number at the start of every ELF file.
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+
+/// The size of the identification bytes at the start of the header, which are the same for every class and endianness.
+const IDENT_SIZE: usize = 16;
+
 #[derive(Debug)]
 pub struct ElfFile<T: Read + Seek> {
 	inner: Mutex<T>,
@@ -19,40 +29,61 @@ pub struct ElfFile<T: Read + Seek> {
 }
 
 impl ElfFile<File> {
-	pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ElfError> {
 		Self::new(File::open(path)?)
 	}
 }
 
 impl<T: Read + Seek> ElfFile<T> {
-	pub fn new(mut file: T) -> io::Result<Self> {
-		let header = ElfHeader::read_from(&mut file)?;
+	/// Reads the ELF file, checking that its header is consistent with itself and with the size of the file, so that
+	/// corrupt files are rejected here with a description of what's wrong, rather than failing later on.
+	pub fn new(mut file: T) -> Result<Self, ElfError> {
+		let file_size = file.seek(SeekFrom::End(0))?;
+		file.seek(SeekFrom::Start(0))?;
+
+		let (class, header_size) = validate_ident(&mut file, file_size)?;
+		check_bounds("ELF header", 0, header_size, file_size)?;
+
+		file.seek(SeekFrom::Start(0))?;
+		let header = ElfHeader::read_from(&mut file).map_err(ElfError::InvalidHeader)?;
+		validate_header(&header, class, file_size)?;
 
 		let section_names_header = match read_section_header(&mut file, &header, header.section_header_table_name_idx) {
 			None => {
-				return Err(io::Error::new(
-					ErrorKind::InvalidData,
-					format!(
-						"can't read section names: section {} doesn't exist",
-						header.section_header_table_name_idx
-					),
+				return Err(ElfError::invalid_field(
+					"e_shstrndx",
+					format!("a section index below {}", header.section_header_table_len),
+					header.section_header_table_name_idx,
 				))
 			}
-			Some(Err(e)) => return Err(e),
+			Some(Err(e)) => return Err(e.into()),
 			Some(Ok(header)) => header,
 		};
 
+		if section_names_header.ty != SectionHeaderType::StringTable {
+			return Err(ElfError::invalid_field(
+				"section name table type",
+				"a string table",
+				format!("{:?}", section_names_header.ty),
+			));
+		}
+
+		check_bounds(
+			"section name table",
+			section_names_header.offset,
+			section_names_header.size,
+			file_size,
+		)?;
+
 		let section_names = match section_names_header.read_string_table_section(&mut file) {
 			None => {
-				return Err(io::Error::new(
-					ErrorKind::InvalidData,
-					format!(
-						"can't read section names: section isn't a string table: {:?}",
-						section_names_header.ty
-					),
+				return Err(ElfError::invalid_field(
+					"section name table",
+					"a readable section",
+					"none",
 				))
 			}
-			Some(Err(e)) => return Err(e),
+			Some(Err(e)) => return Err(e.into()),
 			Some(Ok(names)) => names,
 		};
 
@@ -74,6 +105,18 @@ impl<T: Read + Seek> ElfFile<T> {
 	pub fn section_header_name(&self, header: &SectionHeader) -> Option<&str> {
 		self.section_names.get_string_at_offset(header.name_offset as u64)
 	}
+
+	/// Returns the sections that hold debugging information, e.g. `.debug_info` and `.debug_line`.
+	pub fn debug_sections(&self) -> io::Result<DebugSections> {
+		let mut sections = Vec::new();
+		for header in self.section_headers() {
+			let header = header?;
+			let name = self.section_header_name(&header).unwrap_or_default();
+			sections.extend(DebugSection::from_header(name, &header));
+		}
+
+		Ok(DebugSections(sections))
+	}
 }
 
 impl<T: Read + Seek> Read for &ElfFile<T> {
@@ -90,6 +133,124 @@ impl<T: Read + Seek> Seek for &ElfFile<T> {
 	}
 }
 
+/// Validates the identification bytes at the start of the file, which have to be understood before the rest of the
+/// header can be read. Returns the class of the file, and the size of its header.
+fn validate_ident<T: Read>(file: &mut T, file_size: u64) -> Result<(Class, u64), ElfError> {
+	check_bounds("ELF identification", 0, IDENT_SIZE as u64, file_size)?;
+
+	let mut ident = [0; IDENT_SIZE];
+	file.read_exact(&mut ident)?;
+
+	if ident[0..4] != ELF_MAGIC {
+		return Err(ElfError::invalid_field(
+			"magic number",
+			format!("{:02x?}", ELF_MAGIC),
+			format!("{:02x?}", &ident[0..4]),
+		));
+	}
+
+	let class = match ident[4] {
+		1 => Class::ThirtyTwoBit,
+		2 => Class::SixtyFourBit,
+		other => return Err(ElfError::invalid_field("class", "1 (32 bit) or 2 (64 bit)", other)),
+	};
+
+	let endian = match ident[5] {
+		1 => Endian::Little,
+		2 => Endian::Big,
+		other => {
+			return Err(ElfError::invalid_field(
+				"endianness",
+				"1 (little endian) or 2 (big endian)",
+				other,
+			))
+		}
+	};
+
+	if ident[6] != 1 {
+		return Err(ElfError::invalid_field("version", 1, ident[6]));
+	}
+
+	// e_version repeats the version, but as a word, so reading it back catches a file whose endianness is wrong
+	// before the rest of the header is misread.
+	check_bounds("ELF header", 0, IDENT_SIZE as u64 + 8, file_size)?;
+	let _ty = u16::read_from_with_endian(file, endian)?;
+	let _machine = u16::read_from_with_endian(file, endian)?;
+	let version = u32::read_from_with_endian(file, endian)?;
+	if version != 1 {
+		return Err(ElfError::invalid_field(
+			"e_version",
+			format!("1 for a {:?} endian file", endian),
+			version,
+		));
+	}
+
+	Ok((class, class.header_size()))
+}
+
+/// Validates that the header is consistent with its class and endianness, and that the tables that it points to are
+/// inside the file. A header read with the wrong class or endianness has nonsensical sizes, which is caught here.
+fn validate_header(header: &ElfHeader, class: Class, file_size: u64) -> Result<(), ElfError> {
+	if header.header_size != class.header_size() {
+		return Err(ElfError::invalid_field(
+			"e_ehsize",
+			format!("{} for a {:?} {:?} file", class.header_size(), class, header.endian),
+			header.header_size,
+		));
+	}
+
+	if header.program_header_table_len > 0 {
+		if header.program_header_size != class.program_header_size() {
+			return Err(ElfError::invalid_field(
+				"e_phentsize",
+				format!("{} for a {:?} file", class.program_header_size(), class),
+				header.program_header_size,
+			));
+		}
+
+		check_bounds(
+			"program header table",
+			header.program_header_offset,
+			header.program_header_table_len * header.program_header_size,
+			file_size,
+		)?;
+	}
+
+	if header.section_header_table_len > 0 {
+		if header.section_header_size != class.section_header_size() {
+			return Err(ElfError::invalid_field(
+				"e_shentsize",
+				format!("{} for a {:?} file", class.section_header_size(), class),
+				header.section_header_size,
+			));
+		}
+
+		check_bounds(
+			"section header table",
+			header.section_header_offset,
+			header.section_header_table_len * header.section_header_size,
+			file_size,
+		)?;
+	}
+
+	Ok(())
+}
+
+/// Checks that the `size` bytes at `offset` are inside the file.
+fn check_bounds(what: &'static str, offset: u64, size: u64, file_size: u64) -> Result<(), ElfError> {
+	let end = offset.saturating_add(size);
+	if end > file_size {
+		return Err(ElfError::OutOfBounds {
+			what,
+			offset,
+			end,
+			file_size,
+		});
+	}
+
+	Ok(())
+}
+
 /// An iterator over a given ElfFile's program headers
 struct ProgramHeaderIterator<'a, T: Read + Seek> {
 	idx: u64,
@@ -181,3 +342,107 @@ fn read_section_header<T: Read + Seek>(
 		header.endian,
 	))
 }
+
+#[cfg(test)]
+mod test {
+	use std::io::Cursor;
+
+	use super::{describe_machine_flags, ElfError, ElfFile, ElfSymbolType, SectionHeaderType, TargetArch};
+
+	fn test_binary() -> Vec<u8> {
+		std::fs::read(std::env::current_exe().unwrap()).unwrap()
+	}
+
+	#[test]
+	fn test_validation() {
+		assert!(ElfFile::new(Cursor::new(test_binary())).is_ok());
+
+		let mut wrong_endian = test_binary();
+		wrong_endian[5] = if wrong_endian[5] == 1 { 2 } else { 1 };
+		match ElfFile::new(Cursor::new(wrong_endian)) {
+			Err(ElfError::InvalidField { field, .. }) => assert_eq!(field, "e_version"),
+			other => panic!("expected an invalid field, got {:?}", other.map(|_| ())),
+		}
+
+		let mut wrong_class = test_binary();
+		wrong_class[4] = if wrong_class[4] == 1 { 2 } else { 1 };
+		match ElfFile::new(Cursor::new(wrong_class)) {
+			Err(ElfError::InvalidField { field, .. }) => assert_eq!(field, "e_ehsize"),
+			other => panic!("expected an invalid field, got {:?}", other.map(|_| ())),
+		}
+
+		let mut truncated = test_binary();
+		truncated.truncate(4096);
+		assert!(matches!(
+			ElfFile::new(Cursor::new(truncated)),
+			Err(ElfError::OutOfBounds { .. })
+		));
+
+		assert!(matches!(
+			ElfFile::new(Cursor::new(b"\x7fELF".to_vec())),
+			Err(ElfError::OutOfBounds { .. })
+		));
+		assert!(matches!(
+			ElfFile::new(Cursor::new(b"#!/bin/sh\nexit 0\n".to_vec())),
+			Err(ElfError::InvalidField {
+				field: "magic number",
+				..
+			})
+		));
+	}
+
+	#[test]
+	fn test_debug_sections() {
+		let binary = test_binary();
+		let size = binary.len() as u64;
+		let sections = ElfFile::new(Cursor::new(binary)).unwrap().debug_sections().unwrap();
+
+		// Tests are built with debugging information.
+		assert!(sections.has_line_numbers());
+		assert!(sections.total_size() > 0 && sections.total_size() < size);
+		assert!(sections.0.iter().all(|section| section.name.starts_with(".debug_")));
+		assert!(!sections.0.iter().any(|section| section.name == ".debug_gdb_scripts"));
+	}
+
+	#[test]
+	fn test_symbols() {
+		let file = ElfFile::new(Cursor::new(test_binary())).unwrap();
+		let headers = file.section_headers().collect::<Result<Vec<_>, _>>().unwrap();
+		let names = headers
+			.iter()
+			.find(|header| file.section_header_name(header) == Some(".strtab"))
+			.unwrap()
+			.read_string_table_section(&file)
+			.unwrap()
+			.unwrap();
+		let table = headers
+			.iter()
+			.find(|header| header.ty == SectionHeaderType::SymbolTable)
+			.unwrap();
+		let symbols = table.read_symbol_table_section(&file).unwrap().unwrap();
+
+		assert_eq!(symbols.iter().count() as u64, table.size / table.entry_size);
+		let main = symbols
+			.iter()
+			.find(|symbol| names.get_string_at_offset(symbol.name_offset) == Some("main"))
+			.unwrap();
+		assert_eq!(main.ty, ElfSymbolType::Func);
+		assert!(main.value > 0 && main.size > 0);
+	}
+
+	#[test]
+	fn test_machine_flags() {
+		// rv64gc, as built for most boards.
+		assert_eq!(
+			describe_machine_flags(TargetArch::RiscV, 0x5),
+			vec!["RVC", "double-float ABI"]
+		);
+		assert_eq!(describe_machine_flags(TargetArch::RiscV, 0x0), vec!["soft-float ABI"]);
+		assert_eq!(
+			describe_machine_flags(TargetArch::Arm, 0x05000400),
+			vec!["Version5 EABI", "hard-float ABI"]
+		);
+		assert!(describe_machine_flags(TargetArch::ARM64, 0).is_empty());
+		assert_eq!(describe_machine_flags(TargetArch::AMD64, 0x20), vec!["0x20"]);
+	}
+}
@@ -2,7 +2,7 @@ use std::fmt::Debug;
 use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom};
 
 use bitflags::bitflags;
-use bytestruct::{Endian, ReadFrom, ReadFromWithEndian};
+use bytestruct::{Endian, ReadFrom, ReadFromWithEndian, WriteToWithEndian};
 use bytestruct_derive::ByteStruct;
 
 const ELF_VERSION: u8 = 1;
@@ -22,6 +22,30 @@ impl ReadFrom for Class {
 }
 
 impl Class {
+	/// The size of the ELF header in files of this class.
+	pub fn header_size(&self) -> u64 {
+		match self {
+			Self::ThirtyTwoBit => 52,
+			Self::SixtyFourBit => 64,
+		}
+	}
+
+	/// The size of each entry in the program header table in files of this class.
+	pub fn program_header_size(&self) -> u64 {
+		match self {
+			Self::ThirtyTwoBit => 32,
+			Self::SixtyFourBit => 56,
+		}
+	}
+
+	/// The size of each entry in the section header table in files of this class.
+	pub fn section_header_size(&self) -> u64 {
+		match self {
+			Self::ThirtyTwoBit => 40,
+			Self::SixtyFourBit => 64,
+		}
+	}
+
 	fn read_value<T: io::Read>(&self, source: &mut T, endian: Endian) -> io::Result<u64> {
 		match self {
 			Self::ThirtyTwoBit => u32::read_from_with_endian(source, endian).map(|v| v as u64),
@@ -82,13 +106,28 @@ impl ReadFromWithEndian for ElfType {
 			0xFF00..=0xFFFF => Ok(Self::ProcessorSpecific(val)),
 			_ => Err(io::Error::new(
 				ErrorKind::InvalidData,
-				format!("invalid ELF class: {}", val),
+				format!("invalid ELF type: {}", val),
 			)),
 		}
 	}
 }
 
-#[derive(Debug, Copy, Clone, ByteStruct)]
+/// The machine (e_machine) of files for 32 bit ARM.
+pub const EM_ARM: u16 = 0x28;
+
+/// The machine (e_machine) of files for x86-64.
+pub const EM_X86_64: u16 = 0x3E;
+
+/// The machine (e_machine) of files for 64 bit ARM.
+pub const EM_AARCH64: u16 = 0xB7;
+
+/// The machine (e_machine) of files for RISC-V, both 32 and 64 bit.
+pub const EM_RISCV: u16 = 0xF3;
+
+/// The machine (e_machine) of files for LoongArch.
+pub const EM_LOONGARCH: u16 = 0x102;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(u16)]
 pub enum TargetArch {
 	None = 0x0,
@@ -107,12 +146,130 @@ pub enum TargetArch {
 	PowerPC = 0x14,
 	PowerPC64 = 0x15,
 	S390 = 0x16,
-	Arm = 0x28,
+	Arm = EM_ARM,
 	SuperH = 0x2A,
 	IA64 = 0x32,
-	AMD64 = 0x3E,
-	ARM64 = 0xB7,
-	RiscV = 0xF3,
+	AMD64 = EM_X86_64,
+	ARM64 = EM_AARCH64,
+	RiscV = EM_RISCV,
+	LoongArch = EM_LOONGARCH,
+
+	/// A machine that we don't know about. Files for it can still be read, as nothing about the layout of an ELF file
+	/// depends on the machine.
+	Other(u16),
+}
+
+impl ReadFromWithEndian for TargetArch {
+	fn read_from_with_endian<T: io::Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let machine = match u16::read_from_with_endian(source, endian)? {
+			0x0 => Self::None,
+			0x1 => Self::WE32100,
+			0x2 => Self::Sparc,
+			0x3 => Self::Intelx86,
+			0x4 => Self::Motorolla68000,
+			0x5 => Self::Motorolla88000,
+			0x6 => Self::IntelMCU,
+			0x7 => Self::Intel80860,
+			0x08 => Self::Mips,
+			0x09 => Self::IBM370,
+			0x0A => Self::MipsLittleEndian,
+			0x0F => Self::HpPaRISC,
+			0x13 => Self::Intel80960,
+			0x14 => Self::PowerPC,
+			0x15 => Self::PowerPC64,
+			0x16 => Self::S390,
+			EM_ARM => Self::Arm,
+			0x2A => Self::SuperH,
+			0x32 => Self::IA64,
+			EM_X86_64 => Self::AMD64,
+			EM_AARCH64 => Self::ARM64,
+			EM_RISCV => Self::RiscV,
+			EM_LOONGARCH => Self::LoongArch,
+			other => Self::Other(other),
+		};
+
+		Ok(machine)
+	}
+}
+
+/// RISC-V: the file uses compressed instructions.
+const EF_RISCV_RVC: u32 = 0x1;
+
+/// RISC-V: the bits of the flags that give the float ABI.
+const EF_RISCV_FLOAT_ABI: u32 = 0x6;
+
+/// RISC-V: the file uses the embedded ABI, with only 16 registers.
+const EF_RISCV_RVE: u32 = 0x8;
+
+/// RISC-V: the file needs the total store ordering memory model.
+const EF_RISCV_TSO: u32 = 0x10;
+
+/// ARM: the bits of the flags that give the version of the EABI.
+const EF_ARM_EABI_MASK: u32 = 0xFF000000;
+
+/// ARM: the file passes floats in integer registers.
+const EF_ARM_ABI_FLOAT_SOFT: u32 = 0x200;
+
+/// ARM: the file passes floats in floating point registers.
+const EF_ARM_ABI_FLOAT_HARD: u32 = 0x400;
+
+/// ARM: the file is big endian, with little endian instructions.
+const EF_ARM_BE8: u32 = 0x00800000;
+
+/// Describes the machine specific flags (e_flags) of a file for the given machine, e.g. `["RVC", "double-float
+/// ABI"]` for a RISC-V file. Flags that we don't know how to decode are described by their value.
+pub fn describe_machine_flags(machine: TargetArch, flags: u32) -> Vec<String> {
+	let mut descriptions = Vec::new();
+	let mut unknown = flags;
+	match machine {
+		TargetArch::RiscV => {
+			if flags & EF_RISCV_RVC != 0 {
+				descriptions.push(String::from("RVC"));
+			}
+
+			descriptions.push(String::from(match flags & EF_RISCV_FLOAT_ABI {
+				0x0 => "soft-float ABI",
+				0x2 => "single-float ABI",
+				0x4 => "double-float ABI",
+				_ => "quad-float ABI",
+			}));
+
+			if flags & EF_RISCV_RVE != 0 {
+				descriptions.push(String::from("RVE"));
+			}
+
+			if flags & EF_RISCV_TSO != 0 {
+				descriptions.push(String::from("TSO"));
+			}
+
+			unknown &= !(EF_RISCV_RVC | EF_RISCV_FLOAT_ABI | EF_RISCV_RVE | EF_RISCV_TSO);
+		}
+		TargetArch::Arm => {
+			match (flags & EF_ARM_EABI_MASK) >> 24 {
+				0 => descriptions.push(String::from("GNU EABI")),
+				version => descriptions.push(format!("Version{} EABI", version)),
+			}
+
+			if flags & EF_ARM_ABI_FLOAT_HARD != 0 {
+				descriptions.push(String::from("hard-float ABI"));
+			} else if flags & EF_ARM_ABI_FLOAT_SOFT != 0 {
+				descriptions.push(String::from("soft-float ABI"));
+			}
+
+			if flags & EF_ARM_BE8 != 0 {
+				descriptions.push(String::from("BE8"));
+			}
+
+			unknown &= !(EF_ARM_EABI_MASK | EF_ARM_ABI_FLOAT_SOFT | EF_ARM_ABI_FLOAT_HARD | EF_ARM_BE8);
+		}
+		_ => {}
+	}
+
+	if unknown != 0 {
+		descriptions.push(format!("{:#x}", unknown));
+	}
+
+	descriptions
 }
 
 #[derive(Debug)]
@@ -136,6 +293,13 @@ pub struct ElfHeader {
 	pub section_header_table_name_idx: u64,
 }
 
+impl ElfHeader {
+	/// Describes the machine specific flags of the file. See `describe_machine_flags`.
+	pub fn machine_flags(&self) -> Vec<String> {
+		describe_machine_flags(self.architecture, self.flags)
+	}
+}
+
 impl ReadFrom for ElfHeader {
 	fn read_from<T: io::Read>(source: &mut T) -> io::Result<Self> {
 		let magic = <[u8; 4]>::read_from(source)?;
@@ -249,13 +413,16 @@ impl ReadFromWithEndian for ProgramHeaderType {
 	}
 }
 
+/// The bits of program header flags that are reserved for OS specific and processor specific meanings.
+const PF_MASKOS_MASKPROC: u32 = 0xFFF00000;
+
 #[derive(PartialEq)]
 pub struct ProgramHeaderFlags(u32);
 
 impl ReadFromWithEndian for ProgramHeaderFlags {
 	fn read_from_with_endian<T: io::Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
 		let val = u32::read_from_with_endian(source, endian)?;
-		if val > 7 {
+		if val & !PF_MASKOS_MASKPROC > 7 {
 			return Err(io::Error::new(
 				ErrorKind::InvalidData,
 				format!("invalid program header flags: {:#b}", val),
@@ -361,6 +528,12 @@ pub enum SectionHeaderType {
 	SectionIndices,
 	Number,
 	OSSpecific(u32),
+
+	/// A type specific to the machine, e.g. ARM's unwinding tables, or the attributes of a RISC-V file.
+	ProcessorSpecific(u32),
+
+	/// A type reserved for applications.
+	UserSpecific(u32),
 }
 
 impl ReadFromWithEndian for SectionHeaderType {
@@ -383,7 +556,9 @@ impl ReadFromWithEndian for SectionHeaderType {
 			0x11 => Ok(Self::SectionGroup),
 			0x12 => Ok(Self::SectionIndices),
 			0x13 => Ok(Self::Number),
-			n @ 0x60000000.. => Ok(Self::OSSpecific(n)),
+			n @ 0x60000000..=0x6FFFFFFF => Ok(Self::OSSpecific(n)),
+			n @ 0x70000000..=0x7FFFFFFF => Ok(Self::ProcessorSpecific(n)),
+			n @ 0x80000000.. => Ok(Self::UserSpecific(n)),
 			n => Err(io::Error::new(
 				ErrorKind::InvalidData,
 				format!("invalid section header type: {}", n),
@@ -405,14 +580,27 @@ bitflags! {
 		const OSNonconforming = 0x100;
 		const Group = 0x200;
 		const ThreadLocalStorage = 0x400;
+		const Compressed = 0x800;
+		/// GNU: the section mustn't be garbage collected by the linker.
+		const Retain = 0x200000;
+		/// x86-64: the section can be more than 2GiB from the code that uses it.
+		const X86_64Large = 0x10000000;
+		/// ARM: the section only holds code, and can't be read as data.
+		const ArmPureCode = 0x20000000;
+		/// GNU: the section is left out of the output of a final link.
+		const Exclude = 0x80000000;
 	}
 }
 
+/// The bits of section header flags that are reserved for OS specific and processor specific meanings, which are
+/// kept even if we don't know what they mean.
+const SHF_MASKOS_MASKPROC: u64 = 0xFFF00000;
+
 impl SectionHeaderFlags {
 	pub fn read_from_with_endian<T: io::Read>(source: &mut T, class: Class, endian: Endian) -> io::Result<Self> {
 		let flags = class.read_value(source, endian)?;
-		if let Some(s) = Self::from_bits(flags) {
-			return Ok(s);
+		if flags & !(Self::all().bits() | SHF_MASKOS_MASKPROC) == 0 {
+			return Ok(Self::from_bits_retain(flags));
 		}
 
 		Err(io::Error::new(
@@ -640,62 +828,92 @@ pub struct ElfSymbol {
 }
 
 impl ElfSymbol {
-	pub fn read_from_with_endian<T: io::Read>(source: &mut T, class: Class, endian: Endian) -> io::Result<Self> {
-		let name_offset: u32;
-		let value: u64;
-		let size: u64;
-		let info: u8;
-		let visibility: ElfSymbolVisibility;
-		let symbol_table_index: u64;
-
-		match class {
-			Class::ThirtyTwoBit => {
-				name_offset = u32::read_from_with_endian(source, endian)?;
-				value = class.read_value(source, endian)?;
-				size = class.read_value(source, endian)?;
-				info = u8::read_from(source)?;
-				visibility = ElfSymbolVisibility::read_from_with_endian(source, endian)?;
-				symbol_table_index = u16::read_from_with_endian(source, endian)? as u64;
-			}
-			Class::SixtyFourBit => {
-				name_offset = u32::read_from_with_endian(source, endian)?;
-				info = u8::read_from(source)?;
-				visibility = ElfSymbolVisibility::read_from_with_endian(source, endian)?;
-				symbol_table_index = u16::read_from_with_endian(source, endian)? as u64;
-				value = class.read_value(source, endian)?;
-				size = class.read_value(source, endian)?;
-			}
-		};
-
-		let binding = ElfSymbolBinding::try_from(info >> 4)?;
-		let ty = ElfSymbolType::try_from(info & 0xF)?;
-
+	fn new(
+		name_offset: u32,
+		value: u64,
+		size: u64,
+		info: u8,
+		visibility: ElfSymbolVisibility,
+		symbol_table_index: u16,
+	) -> io::Result<Self> {
 		Ok(Self {
 			name_offset: name_offset as u64,
-			binding,
-			size,
-			symbol_table_index,
-			ty,
 			value,
+			size,
+			ty: ElfSymbolType::try_from(info & 0xF)?,
+			binding: ElfSymbolBinding::try_from(info >> 4)?,
 			visibility,
+			symbol_table_index: symbol_table_index as u64,
 		})
 	}
 }
 
+/// A symbol in the symbol table of a 32 bit file, as it's laid out.
+#[derive(ByteStruct)]
+struct Symbol32 {
+	name_offset: u32,
+	value: u32,
+	size: u32,
+	info: u8,
+	visibility: ElfSymbolVisibility,
+	symbol_table_index: u16,
+}
+
+/// A symbol in the symbol table of a 64 bit file, as it's laid out. The fields are in a different order to a 32 bit
+/// symbol's, so that the 64 bit ones are aligned.
+#[derive(ByteStruct)]
+struct Symbol64 {
+	name_offset: u32,
+	info: u8,
+	visibility: ElfSymbolVisibility,
+	symbol_table_index: u16,
+	value: u64,
+	size: u64,
+}
+
+/// The symbols of a symbol table section, which fill the section.
+#[derive(ByteStruct)]
+struct SymbolTable<S: ReadFromWithEndian + WriteToWithEndian> {
+	#[rest]
+	symbols: Vec<S>,
+}
+
 #[derive(Debug)]
 pub struct SymbolTableSection(Vec<ElfSymbol>);
 
 impl SymbolTableSection {
 	fn read(bytes: &[u8], class: Class, endian: Endian) -> io::Result<Self> {
 		let mut source = Cursor::new(bytes);
-		let mut symbols = Vec::new();
-		loop {
-			match ElfSymbol::read_from_with_endian(&mut source, class, endian) {
-				Ok(symbol) => symbols.push(symbol),
-				Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
-				Err(e) => return Err(e),
-			};
-		}
+		let symbols = match class {
+			Class::ThirtyTwoBit => SymbolTable::<Symbol32>::read_from_with_endian(&mut source, endian)?
+				.symbols
+				.into_iter()
+				.map(|s| {
+					ElfSymbol::new(
+						s.name_offset,
+						s.value as u64,
+						s.size as u64,
+						s.info,
+						s.visibility,
+						s.symbol_table_index,
+					)
+				})
+				.collect::<io::Result<_>>()?,
+			Class::SixtyFourBit => SymbolTable::<Symbol64>::read_from_with_endian(&mut source, endian)?
+				.symbols
+				.into_iter()
+				.map(|s| {
+					ElfSymbol::new(
+						s.name_offset,
+						s.value,
+						s.size,
+						s.info,
+						s.visibility,
+						s.symbol_table_index,
+					)
+				})
+				.collect::<io::Result<_>>()?,
+		};
 
 		Ok(Self(symbols))
 	}
@@ -0,0 +1,248 @@
+use std::env;
+
+use crate::{Attribute, Color, SGR};
+
+/// The environment variable that names the type of the terminal.
+pub const TERM_ENV: &str = "TERM";
+
+/// The environment variable that terminals which support 24-bit color set to `truecolor` or `24bit`.
+pub const COLORTERM_ENV: &str = "COLORTERM";
+
+/// The environment variable that, if set to anything, turns colors off (see https://no-color.org).
+pub const NO_COLOR_ENV: &str = "NO_COLOR";
+
+/// The prefixes of the terminal types that emulate xterm (or close enough to it), so support everything that's
+/// asked of them here, with at least the eight standard colors.
+const XTERM_LIKE: [&str; 11] = [
+	"xterm",
+	"screen",
+	"tmux",
+	"rxvt",
+	"alacritty",
+	"kitty",
+	"foot",
+	"st",
+	"wezterm",
+	"vte",
+	"konsole",
+];
+
+/// How many colors a terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+	None,
+
+	/// The eight standard colors, and their bright versions.
+	Basic,
+
+	/// The 256 color palette.
+	Indexed,
+
+	/// 24-bit colors.
+	TrueColor,
+}
+
+impl ColorSupport {
+	/// Returns the closest color to the given one that can be shown, or None if colors can't be shown at all.
+	pub fn downgrade(&self, color: Color) -> Option<Color> {
+		match (self, color) {
+			(ColorSupport::None, _) => None,
+			(ColorSupport::TrueColor, color) => Some(color),
+			(_, Color::Default | Color::Standard(_) | Color::Bright(_)) => Some(color),
+			(ColorSupport::Indexed, Color::Indexed(_)) => Some(color),
+			(ColorSupport::Indexed, Color::Rgb(r, g, b)) => Some(Color::Indexed(rgb_to_indexed(r, g, b))),
+			(ColorSupport::Basic, Color::Indexed(index)) => Some(indexed_to_basic(index)),
+			(ColorSupport::Basic, Color::Rgb(r, g, b)) => Some(indexed_to_basic(rgb_to_indexed(r, g, b))),
+		}
+	}
+}
+
+/// What a terminal can do beyond printing text, so that programs can fall back to plain output on terminals that
+/// can't do what they'd like to, e.g. serial consoles. This is worked out from the name of the terminal, rather than a
+/// terminfo database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+	pub colors: ColorSupport,
+
+	/// Whether text can be bold, underlined, or reversed.
+	pub attributes: bool,
+
+	/// Whether the cursor can be moved around the screen, and the screen and lines erased.
+	pub cursor_addressing: bool,
+
+	/// Whether the terminal has an alternate screen that full screen programs can switch to, leaving what was on the
+	/// screen before alone.
+	pub alternate_screen: bool,
+}
+
+impl Capabilities {
+	/// A terminal that can only print text, e.g. `TERM=dumb`.
+	pub const DUMB: Capabilities = Capabilities {
+		colors: ColorSupport::None,
+		attributes: false,
+		cursor_addressing: false,
+		alternate_screen: false,
+	};
+
+	/// A VT100, and the many serial terminals that act like one.
+	pub const VT100: Capabilities = Capabilities {
+		colors: ColorSupport::None,
+		attributes: true,
+		cursor_addressing: true,
+		alternate_screen: false,
+	};
+
+	/// A modern terminal emulator.
+	pub const XTERM: Capabilities = Capabilities {
+		colors: ColorSupport::Basic,
+		attributes: true,
+		cursor_addressing: true,
+		alternate_screen: true,
+	};
+
+	/// Works out the capabilities of the terminal from `$TERM`, `$COLORTERM`, and `$NO_COLOR`.
+	pub fn from_env() -> Self {
+		let term = env::var(TERM_ENV).unwrap_or_default();
+		let mut capabilities = Self::from_term(&term);
+
+		let colorterm = env::var(COLORTERM_ENV).unwrap_or_default();
+		if capabilities.colors > ColorSupport::None && (colorterm == "truecolor" || colorterm == "24bit") {
+			capabilities.colors = ColorSupport::TrueColor;
+		}
+
+		if env::var_os(NO_COLOR_ENV).is_some_and(|value| !value.is_empty()) {
+			capabilities.colors = ColorSupport::None;
+		}
+
+		capabilities
+	}
+
+	/// Returns the capabilities of the given type of terminal. Terminals that aren't known are assumed to act like a
+	/// VT100, as nearly everything does.
+	pub fn from_term(term: &str) -> Self {
+		let (name, variant) = term.split_once('-').unwrap_or((term, ""));
+		let mut capabilities = match name {
+			"" | "dumb" | "unknown" => return Self::DUMB,
+			// The Linux console and ANSI terminals have colors, but no alternate screen.
+			"linux" | "ansi" => Capabilities {
+				colors: ColorSupport::Basic,
+				..Self::VT100
+			},
+			_ if XTERM_LIKE.iter().any(|prefix| name.starts_with(prefix)) => Self::XTERM,
+			_ => Self::VT100,
+		};
+
+		// Variants like `xterm-256color` and `xterm-direct` say how many colors there are, and `-mono` that there
+		// aren't any.
+		if variant.contains("mono") {
+			capabilities.colors = ColorSupport::None;
+		} else if variant.contains("direct") || variant.contains("truecolor") {
+			capabilities.colors = ColorSupport::TrueColor;
+		} else if variant.contains("256color") {
+			capabilities.colors = ColorSupport::Indexed;
+		} else if variant.contains("color") && capabilities.colors == ColorSupport::None {
+			capabilities.colors = ColorSupport::Basic;
+		}
+
+		capabilities
+	}
+
+	/// Returns the closest attributes to the given ones that the terminal can show, leaving out the ones that it
+	/// can't.
+	pub fn adapt(&self, sgr: &SGR) -> SGR {
+		let attributes = sgr
+			.0
+			.iter()
+			.filter_map(|attribute| match attribute {
+				Attribute::Foreground(color) => self.colors.downgrade(*color).map(Attribute::Foreground),
+				Attribute::Background(color) => self.colors.downgrade(*color).map(Attribute::Background),
+				Attribute::Reset => (self.attributes || self.colors > ColorSupport::None).then_some(Attribute::Reset),
+				attribute => self.attributes.then_some(*attribute),
+			})
+			.collect();
+
+		SGR(attributes)
+	}
+
+	/// Returns the text with the given attributes, as far as the terminal can show them. Text on a terminal that
+	/// can't show any of them is returned as it is.
+	pub fn paint(&self, sgr: &SGR, text: &str) -> String {
+		let sgr = self.adapt(sgr);
+		if sgr.0.is_empty() {
+			return text.to_owned();
+		}
+
+		sgr.paint(text)
+	}
+}
+
+/// Returns the closest color in the 6x6x6 cube of the 256 color palette to the given 24-bit color.
+fn rgb_to_indexed(r: u8, g: u8, b: u8) -> u8 {
+	let scale = |channel: u8| ((channel as u16 * 5 + 127) / 255) as u8;
+	16 + 36 * scale(r) + 6 * scale(g) + scale(b)
+}
+
+/// Returns the closest standard or bright color to the given color from the 256 color palette.
+fn indexed_to_basic(index: u8) -> Color {
+	match index {
+		0..=7 => Color::Standard(index),
+		8..=15 => Color::Bright(index - 8),
+		// The colors of the cube have each channel on for the standard color whose bit they're at least halfway up.
+		16..=231 => {
+			let cube = index - 16;
+			let (r, g, b) = (cube / 36, (cube / 6) % 6, cube % 6);
+			let color = (r >= 3) as u8 | ((g >= 3) as u8) << 1 | ((b >= 3) as u8) << 2;
+			Color::Standard(color)
+		}
+		// The rest is a grayscale ramp, from nearly black to nearly white.
+		232..=243 => Color::Standard(0),
+		244..=255 => Color::Standard(7),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Capabilities, ColorSupport};
+	use crate::{Attribute, Color, SGR};
+
+	#[test]
+	fn test_from_term() {
+		assert_eq!(Capabilities::from_term(""), Capabilities::DUMB);
+		assert_eq!(Capabilities::from_term("dumb"), Capabilities::DUMB);
+		assert_eq!(Capabilities::from_term("vt100"), Capabilities::VT100);
+		assert_eq!(Capabilities::from_term("vt220"), Capabilities::VT100);
+
+		let linux = Capabilities::from_term("linux");
+		assert_eq!(linux.colors, ColorSupport::Basic);
+		assert!(linux.cursor_addressing && !linux.alternate_screen);
+
+		assert_eq!(Capabilities::from_term("xterm"), Capabilities::XTERM);
+		assert_eq!(Capabilities::from_term("xterm-256color").colors, ColorSupport::Indexed);
+		assert_eq!(Capabilities::from_term("tmux-256color").colors, ColorSupport::Indexed);
+		assert_eq!(Capabilities::from_term("xterm-direct").colors, ColorSupport::TrueColor);
+		assert_eq!(Capabilities::from_term("xterm-mono").colors, ColorSupport::None);
+		assert_eq!(Capabilities::from_term("vt100-color").colors, ColorSupport::Basic);
+		assert_eq!(Capabilities::from_term("something-new"), Capabilities::VT100);
+	}
+
+	#[test]
+	fn test_adapt() {
+		let sgr = SGR::bold().and(Attribute::Foreground(Color::Rgb(255, 0, 0)));
+		assert_eq!(
+			Capabilities::from_term("xterm-direct").adapt(&sgr),
+			SGR::bold().and(Attribute::Foreground(Color::Rgb(255, 0, 0)))
+		);
+		assert_eq!(
+			Capabilities::from_term("xterm-256color").adapt(&sgr),
+			SGR::bold().and(Attribute::Foreground(Color::Indexed(196)))
+		);
+		assert_eq!(
+			Capabilities::XTERM.adapt(&sgr),
+			SGR::bold().and(Attribute::Foreground(Color::RED))
+		);
+		assert_eq!(Capabilities::VT100.adapt(&sgr), SGR::bold());
+
+		assert_eq!(Capabilities::DUMB.paint(&sgr, "text"), "text");
+		assert_eq!(Capabilities::VT100.paint(&sgr, "text"), "\x1b[1mtext\x1b[0m");
+	}
+}
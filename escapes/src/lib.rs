@@ -1,3 +1,6 @@
+mod capabilities;
+mod tokenizer;
+
 use escapes_derive::EscapeSequence;
 use std::{
 	fmt::{self, Display, Formatter},
@@ -5,6 +8,9 @@ use std::{
 };
 use thiserror::Error;
 
+pub use capabilities::*;
+pub use tokenizer::*;
+
 pub const ESC: char = '\x1b';
 
 /// The CSI (Control Sequence Introducer) character.
@@ -22,6 +28,9 @@ pub enum AnsiParserError {
 	#[error("Unsupported ANSI escape sequence: {0}")]
 	Unsupported(char),
 
+	#[error("Unsupported SGR parameter: {0}")]
+	UnsupportedAttribute(u8),
+
 	#[error("IO error: {0}")]
 	IO(#[from] io::Error),
 }
@@ -65,6 +74,186 @@ pub struct EraseInDisplay(#[default(0)] pub u8);
 #[escape('K')]
 pub struct EraseInLine(#[default(0)] pub u8);
 
+/// A color of text, or of the background behind it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+	/// The terminal's default color.
+	Default,
+
+	/// One of the eight standard colors, from black (0) to white (7).
+	Standard(u8),
+
+	/// The bright version of one of the eight standard colors.
+	Bright(u8),
+
+	/// A color from the 256 color palette, where the first 16 are the standard and bright colors.
+	Indexed(u8),
+
+	/// A 24-bit color.
+	Rgb(u8, u8, u8),
+}
+
+impl Color {
+	pub const BLACK: Color = Color::Standard(0);
+	pub const RED: Color = Color::Standard(1);
+	pub const GREEN: Color = Color::Standard(2);
+	pub const YELLOW: Color = Color::Standard(3);
+	pub const BLUE: Color = Color::Standard(4);
+	pub const MAGENTA: Color = Color::Standard(5);
+	pub const CYAN: Color = Color::Standard(6);
+	pub const WHITE: Color = Color::Standard(7);
+
+	/// Writes the parameters that set this color, where `base` is the parameter of the first standard color (30 for
+	/// foregrounds, and 40 for backgrounds).
+	fn write_params(&self, base: u8, params: &mut Vec<u8>) {
+		match *self {
+			Color::Default => params.push(base + 9),
+			Color::Standard(color) => params.push(base + color),
+			Color::Bright(color) => params.push(base + 60 + color),
+			Color::Indexed(color) => params.extend([base + 8, 5, color]),
+			Color::Rgb(r, g, b) => params.extend([base + 8, 2, r, g, b]),
+		}
+	}
+
+	/// Reads the color of an extended (38 or 48) parameter from the parameters that follow it.
+	fn read_extended(params: &mut impl Iterator<Item = u8>) -> Result<Color, AnsiParserError> {
+		let mut next = || params.next().ok_or(AnsiParserError::Malformed);
+		match next()? {
+			5 => Ok(Color::Indexed(next()?)),
+			2 => Ok(Color::Rgb(next()?, next()?, next()?)),
+			mode => Err(AnsiParserError::UnsupportedAttribute(mode)),
+		}
+	}
+}
+
+/// An attribute of the text that follows an SGR sequence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attribute {
+	/// Resets every attribute back to the terminal's default.
+	Reset,
+	Bold,
+	Faint,
+	Italic,
+	Underline,
+
+	/// Swaps the foreground and background colors.
+	Reverse,
+
+	/// Turns off bold and faint.
+	NormalIntensity,
+	NoItalic,
+	NoUnderline,
+	NoReverse,
+	Foreground(Color),
+	Background(Color),
+}
+
+impl Attribute {
+	fn write_params(&self, params: &mut Vec<u8>) {
+		match self {
+			Attribute::Reset => params.push(0),
+			Attribute::Bold => params.push(1),
+			Attribute::Faint => params.push(2),
+			Attribute::Italic => params.push(3),
+			Attribute::Underline => params.push(4),
+			Attribute::Reverse => params.push(7),
+			Attribute::NormalIntensity => params.push(22),
+			Attribute::NoItalic => params.push(23),
+			Attribute::NoUnderline => params.push(24),
+			Attribute::NoReverse => params.push(27),
+			Attribute::Foreground(color) => color.write_params(30, params),
+			Attribute::Background(color) => color.write_params(40, params),
+		}
+	}
+}
+
+/// Select Graphic Rendition, which sets the attributes (e.g. the color, or boldness) of the text that follows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SGR(pub Vec<Attribute>);
+
+impl SGR {
+	/// Resets the text back to the terminal's default.
+	pub fn reset() -> Self {
+		Self(vec![Attribute::Reset])
+	}
+
+	pub fn bold() -> Self {
+		Self(vec![Attribute::Bold])
+	}
+
+	pub fn underline() -> Self {
+		Self(vec![Attribute::Underline])
+	}
+
+	pub fn foreground(color: Color) -> Self {
+		Self(vec![Attribute::Foreground(color)])
+	}
+
+	pub fn background(color: Color) -> Self {
+		Self(vec![Attribute::Background(color)])
+	}
+
+	/// Adds another attribute to the sequence, e.g. `SGR::bold().and(Attribute::Foreground(Color::RED))`.
+	pub fn and(mut self, attribute: Attribute) -> Self {
+		self.0.push(attribute);
+		self
+	}
+
+	/// Returns the text with these attributes, followed by a reset.
+	pub fn paint(&self, text: &str) -> String {
+		format!("{}{}{}", self, text, SGR::reset())
+	}
+}
+
+impl EscapeSequence for SGR {
+	fn parse(params: &[u8]) -> Result<Self, AnsiParserError> {
+		// A sequence without any parameters is a reset.
+		if params.is_empty() {
+			return Ok(SGR::reset());
+		}
+
+		let mut attributes = Vec::new();
+		let mut params = params.iter().copied();
+		while let Some(param) = params.next() {
+			attributes.push(match param {
+				0 => Attribute::Reset,
+				1 => Attribute::Bold,
+				2 => Attribute::Faint,
+				3 => Attribute::Italic,
+				4 => Attribute::Underline,
+				7 => Attribute::Reverse,
+				22 => Attribute::NormalIntensity,
+				23 => Attribute::NoItalic,
+				24 => Attribute::NoUnderline,
+				27 => Attribute::NoReverse,
+				30..=37 => Attribute::Foreground(Color::Standard(param - 30)),
+				38 => Attribute::Foreground(Color::read_extended(&mut params)?),
+				39 => Attribute::Foreground(Color::Default),
+				40..=47 => Attribute::Background(Color::Standard(param - 40)),
+				48 => Attribute::Background(Color::read_extended(&mut params)?),
+				49 => Attribute::Background(Color::Default),
+				90..=97 => Attribute::Foreground(Color::Bright(param - 90)),
+				100..=107 => Attribute::Background(Color::Bright(param - 100)),
+				_ => return Err(AnsiParserError::UnsupportedAttribute(param)),
+			});
+		}
+
+		Ok(Self(attributes))
+	}
+}
+
+impl Display for SGR {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let mut params = Vec::new();
+		for attribute in self.0.iter() {
+			attribute.write_params(&mut params);
+		}
+
+		let joined = params.iter().map(u8::to_string).collect::<Vec<_>>().join(";");
+		write!(f, "{}{}{}m", ESC, CSI, joined)
+	}
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ANSIEscapeSequence {
 	CursorUp(CursorUp),
@@ -74,6 +263,7 @@ pub enum ANSIEscapeSequence {
 	EraseInLine(EraseInLine),
 	EraseInDisplay(EraseInDisplay),
 	CursorPosition(CursorPosition),
+	SGR(SGR),
 }
 
 impl ANSIEscapeSequence {
@@ -86,6 +276,7 @@ impl ANSIEscapeSequence {
 			'H' => Ok(ANSIEscapeSequence::CursorPosition(CursorPosition::parse(params)?)),
 			'J' => Ok(ANSIEscapeSequence::EraseInDisplay(EraseInDisplay::parse(params)?)),
 			'K' => Ok(ANSIEscapeSequence::EraseInLine(EraseInLine::parse(params)?)),
+			'm' => Ok(ANSIEscapeSequence::SGR(SGR::parse(params)?)),
 			_ => Err(AnsiParserError::Unsupported(c)),
 		}
 	}
@@ -126,10 +317,6 @@ impl ANSIEscapeSequence {
 			}
 		}
 
-		if params.is_empty() {
-			params.push(1);
-		}
-
 		ANSIEscapeSequence::new(char_buffer[0] as char, &params)
 	}
 }
@@ -144,6 +331,7 @@ impl Display for ANSIEscapeSequence {
 			ANSIEscapeSequence::EraseInLine(c) => write!(f, "{}", c),
 			ANSIEscapeSequence::EraseInDisplay(c) => write!(f, "{}", c),
 			ANSIEscapeSequence::CursorPosition(c) => write!(f, "{}", c),
+			ANSIEscapeSequence::SGR(c) => write!(f, "{}", c),
 		}
 	}
 }
@@ -253,4 +441,39 @@ mod test {
 			ANSIEscapeSequence::EraseInLine(EraseInLine(2))
 		);
 	}
+
+	#[test]
+	fn test_sgr() {
+		assert_eq!(SGR::reset().to_string(), "\x1b[0m");
+		assert_eq!(
+			SGR::bold().and(Attribute::Foreground(Color::RED)).to_string(),
+			"\x1b[1;31m"
+		);
+		assert_eq!(SGR::background(Color::Bright(4)).to_string(), "\x1b[104m");
+		assert_eq!(SGR::foreground(Color::Indexed(208)).to_string(), "\x1b[38;5;208m");
+		assert_eq!(
+			SGR::background(Color::Rgb(1, 2, 3))
+				.and(Attribute::Underline)
+				.to_string(),
+			"\x1b[48;2;1;2;3;4m"
+		);
+		assert_eq!(SGR::underline().paint("hi"), "\x1b[4mhi\x1b[0m");
+
+		assert_eq!(
+			ANSIEscapeSequence::read(&mut "[m".as_bytes()).unwrap(),
+			ANSIEscapeSequence::SGR(SGR::reset())
+		);
+		assert_eq!(
+			ANSIEscapeSequence::read(&mut "[1;38;2;255;128;0;49;93m".as_bytes()).unwrap(),
+			ANSIEscapeSequence::SGR(SGR(vec![
+				Attribute::Bold,
+				Attribute::Foreground(Color::Rgb(255, 128, 0)),
+				Attribute::Background(Color::Default),
+				Attribute::Foreground(Color::Bright(3)),
+			]))
+		);
+		assert!(ANSIEscapeSequence::read(&mut "[38;5m".as_bytes()).is_err());
+		assert!(ANSIEscapeSequence::read(&mut "[38;9;1m".as_bytes()).is_err());
+		assert!(ANSIEscapeSequence::read(&mut "[5m".as_bytes()).is_err());
+	}
 }
@@ -0,0 +1,251 @@
+use std::io::{self, ErrorKind, Read};
+
+use crate::{ANSIEscapeSequence, CSI, ESC};
+
+/// The most bytes that an escape sequence is read up to, so that a stream of garbage after an ESC can't be buffered
+/// forever. Anything longer is returned as unknown.
+const MAX_SEQUENCE_LENGTH: usize = 64;
+
+/// The number of bytes that are read at a time.
+const READ_SIZE: usize = 1024;
+
+/// The character that ends an OSC (Operating System Command) sequence, along with ST.
+const BEL: u8 = 0x07;
+
+/// A piece of terminal input or output.
+#[derive(Debug, PartialEq)]
+pub enum AnsiToken {
+	/// Characters that aren't part of an escape sequence.
+	Text(String),
+
+	/// A supported escape sequence.
+	Escape(ANSIEscapeSequence),
+
+	/// An escape sequence that isn't supported (or is malformed), as it was read, starting with the ESC.
+	Unknown(Vec<u8>),
+}
+
+/// Splits a stream into text and escape sequences. Text is returned as soon as it has been read, rather than waiting
+/// for the next escape sequence, so that the tokenizer can be used on a terminal. Escape sequences that can't be
+/// parsed are returned as unknown tokens, and tokenizing carries on after them.
+pub struct AnsiTokenizer<R: Read> {
+	reader: R,
+
+	/// The bytes that have been read, but not tokenized yet.
+	buffer: Vec<u8>,
+
+	/// Whether the reader has nothing more to read.
+	eof: bool,
+}
+
+impl<R: Read> AnsiTokenizer<R> {
+	pub fn new(reader: R) -> Self {
+		Self {
+			reader,
+			buffer: Vec::new(),
+			eof: false,
+		}
+	}
+
+	/// Reads more bytes into the buffer, returning false if there aren't any more.
+	fn fill(&mut self) -> io::Result<bool> {
+		if self.eof {
+			return Ok(false);
+		}
+
+		let mut chunk = [0; READ_SIZE];
+		let read = loop {
+			match self.reader.read(&mut chunk) {
+				Ok(read) => break read,
+				Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+				Err(e) => return Err(e),
+			}
+		};
+
+		self.eof = read == 0;
+		self.buffer.extend_from_slice(&chunk[..read]);
+		Ok(read > 0)
+	}
+
+	/// Returns the byte at the given index of the buffer, reading more if it hasn't been read yet.
+	fn byte_at(&mut self, index: usize) -> io::Result<Option<u8>> {
+		while self.buffer.len() <= index {
+			if !self.fill()? {
+				return Ok(None);
+			}
+		}
+
+		Ok(Some(self.buffer[index]))
+	}
+
+	/// Takes the text at the start of the buffer, up to the next ESC. An incomplete UTF-8 character at the end of the
+	/// buffer is left for the next read to complete, so the text can be empty.
+	fn take_text(&mut self) -> AnsiToken {
+		let end = self
+			.buffer
+			.iter()
+			.position(|&b| b == ESC as u8)
+			.unwrap_or(self.buffer.len());
+
+		let complete = match std::str::from_utf8(&self.buffer[..end]) {
+			Err(e) if e.error_len().is_none() && end == self.buffer.len() && !self.eof => e.valid_up_to(),
+			_ => end,
+		};
+
+		let text = self.buffer.drain(..complete).collect::<Vec<_>>();
+		AnsiToken::Text(String::from_utf8_lossy(&text).into_owned())
+	}
+
+	/// Returns the length of the escape sequence at the start of the buffer, reading the rest of it if need be.
+	/// Sequences that are cut off by the end of the input, or are too long, end where they do.
+	fn sequence_length(&mut self) -> io::Result<usize> {
+		let introducer = match self.byte_at(1)? {
+			Some(introducer) => introducer,
+			None => return Ok(1),
+		};
+
+		let mut length = 2;
+		match introducer {
+			// CSI sequences are parameters and intermediate bytes, ended by a byte in 0x40-0x7E.
+			b if b == CSI as u8 => {
+				while let Some(b) = self.byte_at(length)? {
+					length += 1;
+					if (0x40..=0x7E).contains(&b) || length >= MAX_SEQUENCE_LENGTH {
+						break;
+					}
+				}
+			}
+			// OSC sequences are ended by BEL, or ST (ESC \).
+			b']' => {
+				while let Some(b) = self.byte_at(length)? {
+					length += 1;
+					if b == BEL || length >= MAX_SEQUENCE_LENGTH {
+						break;
+					}
+
+					if b == ESC as u8 && self.byte_at(length)? == Some(b'\\') {
+						length += 1;
+						break;
+					}
+				}
+			}
+			// SS3 sequences (e.g. the arrow keys in application mode) are a single character.
+			b'O' if self.byte_at(length)?.is_some() => length += 1,
+			_ => {}
+		}
+
+		Ok(length)
+	}
+}
+
+impl<R: Read> Iterator for AnsiTokenizer<R> {
+	type Item = io::Result<AnsiToken>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.buffer.is_empty() {
+			match self.fill() {
+				Ok(true) => {}
+				Ok(false) => return None,
+				Err(e) => return Some(Err(e)),
+			}
+		}
+
+		if self.buffer[0] != ESC as u8 {
+			let token = self.take_text();
+
+			// The buffer only had part of a character, so wait for the rest of it. If there isn't any more, the
+			// partial character is taken on its own next time.
+			if token == AnsiToken::Text(String::new()) {
+				return match self.fill() {
+					Ok(_) => self.next(),
+					Err(e) => Some(Err(e)),
+				};
+			}
+
+			return Some(Ok(token));
+		}
+
+		let length = match self.sequence_length() {
+			Ok(length) => length,
+			Err(e) => return Some(Err(e)),
+		};
+
+		let sequence = self.buffer.drain(..length).collect::<Vec<_>>();
+		let mut rest = &sequence[1..];
+		match ANSIEscapeSequence::read(&mut rest) {
+			Ok(escape) if rest.is_empty() => Some(Ok(AnsiToken::Escape(escape))),
+			_ => Some(Ok(AnsiToken::Unknown(sequence))),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::Read;
+
+	use super::{AnsiToken, AnsiTokenizer};
+	use crate::{ANSIEscapeSequence, Attribute, Color, CursorUp, EraseInLine, SGR};
+
+	/// A reader that returns its input a few bytes at a time, like a terminal might.
+	struct Chunked<'a>(&'a [u8], usize);
+
+	impl Read for Chunked<'_> {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			let read = self.0.len().min(self.1).min(buf.len());
+			buf[..read].copy_from_slice(&self.0[..read]);
+			self.0 = &self.0[read..];
+			Ok(read)
+		}
+	}
+
+	fn tokenize(input: &[u8], chunk: usize) -> Vec<AnsiToken> {
+		AnsiTokenizer::new(Chunked(input, chunk))
+			.collect::<Result<Vec<_>, _>>()
+			.unwrap()
+	}
+
+	/// Joins adjacent text tokens, which are split up differently depending on how the input is read.
+	fn joined(tokens: Vec<AnsiToken>) -> Vec<AnsiToken> {
+		let mut joined = Vec::new();
+		for token in tokens {
+			match (joined.last_mut(), token) {
+				(Some(AnsiToken::Text(last)), AnsiToken::Text(text)) => last.push_str(&text),
+				(_, token) => joined.push(token),
+			}
+		}
+
+		joined
+	}
+
+	#[test]
+	fn test_tokenizer() {
+		let input = "héllo\x1b[1;31mworld\x1b[0K\x1b[?25h\x1b]0;title\x07\x1bOA\x1b[2A!\x1b".as_bytes();
+		let expected = vec![
+			AnsiToken::Text(String::from("héllo")),
+			AnsiToken::Escape(ANSIEscapeSequence::SGR(
+				SGR::bold().and(Attribute::Foreground(Color::RED)),
+			)),
+			AnsiToken::Text(String::from("world")),
+			AnsiToken::Escape(ANSIEscapeSequence::EraseInLine(EraseInLine(0))),
+			AnsiToken::Unknown(b"\x1b[?25h".to_vec()),
+			AnsiToken::Unknown(b"\x1b]0;title\x07".to_vec()),
+			AnsiToken::Unknown(b"\x1bOA".to_vec()),
+			AnsiToken::Escape(ANSIEscapeSequence::CursorUp(CursorUp(2))),
+			AnsiToken::Text(String::from("!")),
+			AnsiToken::Unknown(b"\x1b".to_vec()),
+		];
+
+		// However the input is split up, the same tokens come out of it.
+		for chunk in [1, 2, 3, 1024] {
+			assert_eq!(joined(tokenize(input, chunk)), expected, "chunk size {}", chunk);
+		}
+	}
+
+	#[test]
+	fn test_tokenizer_invalid_utf8() {
+		assert_eq!(
+			tokenize(b"a\xffb\xc3", 1024),
+			vec![AnsiToken::Text(String::from("a\u{fffd}b\u{fffd}"))]
+		);
+	}
+}
@@ -1,23 +1,23 @@
-use std::{
-	ffi::{CStr, CString},
-	io::stderr,
-	path::PathBuf,
-};
+mod tty;
+
+use std::{ffi::CString, io::stderr, path::PathBuf};
 
-use common::{io::IOTriple, obs::assemble_logger};
+use auth::{LoginPolicy, User};
+use common::{
+	io::IOTriple,
+	lineedit::{self, Echo},
+	obs::assemble_logger,
+};
 use slog::error;
 
 use anyhow::{Context, Result};
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
 use nix::{
-	fcntl::{fcntl, open, FcntlArg, OFlag},
-	libc::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO},
 	sys::{
 		signal::{signal, SigHandler, Signal},
-		stat::Mode,
 		utsname,
 	},
-	unistd::{close, dup2, execve},
+	unistd::execve,
 };
 
 fn ignore_signals() -> Result<()> {
@@ -60,26 +60,29 @@ fn print_issue(tty: &str) -> Result<()> {
 	Ok(())
 }
 
-fn open_tty(tty: &str) -> Result<()> {
-	// Open the given TTY and set it up to read/write.
-	if tty != "-" {
-		close(STDIN_FILENO).with_context(|| "failed to close stdin")?;
-		open(tty, OFlag::O_RDWR, Mode::empty()).with_context(|| format!("failed to open {}", tty))?;
-	}
-
-	// Make sure that stdin is opened in R/W mode.
-	let options = fcntl(STDIN_FILENO, FcntlArg::F_GETFL).with_context(|| "failed to get file descriptor flags")?;
-	if !OFlag::from_bits_retain(options).contains(OFlag::O_RDWR) {
-		return Err(anyhow::anyhow!("stdin is not opened in R/W mode"));
+/// Asks for the username to log in as until one that's allowed to log in is given, or returns None if it can't be
+/// read.
+fn prompt_username(policy: &LoginPolicy) -> Option<String> {
+	let triple = IOTriple::default();
+	loop {
+		let username = match lineedit::prompt(&triple, "login:", Echo::Normal) {
+			Ok(username) => username,
+			Err(e) => {
+				eprintln!("Failed to read username: {}", e);
+				return None;
+			}
+		};
+
+		// Turn away accounts that can't be logged in to before handing over to the login program. Users that don't
+		// exist are left to the login program, so that whether they exist isn't given away before authentication.
+		match User::from_username(username.trim()) {
+			Ok(Some(user)) => match user.is_login_allowed(policy) {
+				Ok(()) => return Some(username),
+				Err(e) => eprintln!("{}", e),
+			},
+			_ => return Some(username),
+		}
 	}
-
-	// Close the existing stdout and stderr, and copy the new TTY to them.
-	close(STDOUT_FILENO).with_context(|| "failed to close stdout")?;
-	close(STDERR_FILENO).with_context(|| "failed to close stderr")?;
-	dup2(STDIN_FILENO, STDOUT_FILENO).with_context(|| "failed to copy stdin to stdout")?;
-	dup2(STDIN_FILENO, STDERR_FILENO).with_context(|| "failed to copy stdin to stderr")?;
-
-	Ok(())
 }
 
 fn main() {
@@ -94,52 +97,117 @@ fn main() {
 				.default_value("/bin/login")
 				.help("The login program to run"),
 		)
-		.arg(Arg::new("tty").help("The tty to open").required(true).index(1))
+		.arg(
+			Arg::new("allow-unlisted-shell")
+				.long("allow-unlisted-shell")
+				.help("Allow users to log in with shells that aren't listed in /etc/shells")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("baud")
+				.long("baud")
+				.short('s')
+				.num_args(1)
+				.value_parser(tty::parse_baud)
+				.help("The baud rate to set serial lines to, e.g. 115200"),
+		)
+		.arg(
+			Arg::new("local-line")
+				.long("local-line")
+				.short('L')
+				.help("Ignore the modem control lines, for lines that are connected directly with no carrier detect")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("flow-control")
+				.long("flow-control")
+				.help("Use RTS/CTS hardware flow control")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("term")
+				.long("term")
+				.num_args(1)
+				.help("The terminal type to set TERM to for the login program, and the shell it starts"),
+		)
+		.arg(
+			Arg::new("autologin")
+				.long("autologin")
+				.short('a')
+				.num_args(1)
+				.help("Log the given user in without asking for a username or password, e.g. on embedded images"),
+		)
+		.arg(
+			Arg::new("tty")
+				.help("The name of the tty that getty is running on, which qinit has already set up as stdin, stdout, and stderr")
+				.required(true)
+				.index(1),
+		)
 		.get_matches();
 
 	let logger = assemble_logger(stderr());
 	let login_program: &String = matches.get_one("login-program").unwrap();
 	let tty: &String = matches.get_one("tty").unwrap();
+	let allow_unlisted_shell = matches.get_flag("allow-unlisted-shell");
+	let term: Option<&String> = matches.get_one("term");
+	let autologin: Option<&String> = matches.get_one("autologin");
+	let line = tty::LineOptions {
+		baud: matches.get_one("baud").copied(),
+		local: matches.get_flag("local-line"),
+		flow_control: matches.get_flag("flow-control"),
+	};
+	let policy = LoginPolicy {
+		require_listed_shell: !allow_unlisted_shell,
+	};
 
 	if let Err(e) = ignore_signals() {
 		error!(logger, "Failed to ignore signals"; "error" => format!("{:?}", e));
 		return;
 	}
 
-	if let Err(e) = print_issue(tty) {
-		error!(logger, "Failed to print issue"; "error" => format!("{:?}", e));
+	if let Err(e) = tty::reopen(&tty::tty_path(tty)) {
+		error!(logger, "Failed to reopen tty"; "error" => format!("{:?}", e));
 		return;
 	}
 
-	if let Err(e) = open_tty(tty) {
-		error!(logger, "Failed to open tty"; "error" => format!("{:?}", e));
+	if let Err(e) = tty::initialize(&line) {
+		error!(logger, "Failed to set up tty"; "error" => format!("{:?}", e));
 		return;
 	}
 
-	// After here, `logger` is no longer valid because we've swapped out the underlying file descriptors.
-	// Manually drop it here so that the compiler can tell us off if we try to use it again.
-	drop(logger);
+	if let Err(e) = print_issue(tty) {
+		error!(logger, "Failed to print issue"; "error" => format!("{:?}", e));
+		return;
+	}
 
-	let triple = IOTriple::default();
-	let username = match triple.prompt("login:") {
-		Ok(username) => username,
-		Err(e) => {
-			eprintln!("Failed to read username: {}", e);
-			return;
-		}
+	let username = match autologin {
+		Some(username) => username.clone(),
+		None => match prompt_username(&policy) {
+			Some(username) => username,
+			None => return,
+		},
 	};
 
 	// Run the login program.
 	let command = CString::new(login_program.as_str()).expect("login program contains null bytes");
-	let args = [
-		command.as_c_str(),
-		&CString::new(username.trim()).expect("username contains null bytes"),
-	];
-
-	if let Err(e) = execve::<_, &CStr>(&command, &args, &[]) {
-		eprintln!("Failed to execute {}: {}", login_program, e);
-		return;
+	let username = CString::new(username.trim()).expect("username contains null bytes");
+	let mut args = vec![command.as_c_str(), username.as_c_str()];
+	if allow_unlisted_shell {
+		args.push(c"--allow-unlisted-shell");
 	}
+	if autologin.is_some() {
+		args.push(c"--preauthenticated");
+	}
+
+	let env = match term.map(|term| CString::new(format!("TERM={}", term))).transpose() {
+		Ok(term) => Vec::from_iter(term),
+		Err(_) => {
+			eprintln!("TERM contains null bytes");
+			return;
+		}
+	};
 
-	unreachable!("execve failed")
+	// execve only ever returns on failure.
+	let Err(e) = execve(&command, &args, &env);
+	eprintln!("Failed to execute {}: {}", login_program, e);
 }
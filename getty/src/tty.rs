@@ -0,0 +1,138 @@
+use std::{
+	os::fd::{AsRawFd, BorrowedFd},
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use nix::{
+	errno::Errno,
+	fcntl::{open, OFlag},
+	libc,
+	sys::{
+		stat::Mode,
+		termios::{
+			cfsetspeed, tcgetattr, tcsetattr, BaudRate, ControlFlags, InputFlags, LocalFlags, OutputFlags, SetArg,
+			SpecialCharacterIndices,
+		},
+	},
+	unistd::{close, dup2},
+};
+
+nix::ioctl_write_int_bad!(set_controlling_tty, libc::TIOCSCTTY);
+
+/// How the line that the tty is on should be set up.
+pub struct LineOptions {
+	/// The speed of the line, if it's a serial line that needs one set.
+	pub baud: Option<BaudRate>,
+
+	/// Whether the modem control lines are ignored, for lines that are directly connected and so have no carrier.
+	pub local: bool,
+
+	/// Whether RTS/CTS hardware flow control is used.
+	pub flow_control: bool,
+}
+
+/// Parses a baud rate, e.g. `115200`.
+pub fn parse_baud(rate: &str) -> Result<BaudRate, String> {
+	let rate = match rate.parse::<u32>().map_err(|e| e.to_string())? {
+		1200 => BaudRate::B1200,
+		2400 => BaudRate::B2400,
+		4800 => BaudRate::B4800,
+		9600 => BaudRate::B9600,
+		19200 => BaudRate::B19200,
+		38400 => BaudRate::B38400,
+		57600 => BaudRate::B57600,
+		115200 => BaudRate::B115200,
+		230400 => BaudRate::B230400,
+		460800 => BaudRate::B460800,
+		500000 => BaudRate::B500000,
+		921600 => BaudRate::B921600,
+		1000000 => BaudRate::B1000000,
+		1500000 => BaudRate::B1500000,
+		2000000 => BaudRate::B2000000,
+		3000000 => BaudRate::B3000000,
+		4000000 => BaudRate::B4000000,
+		rate => return Err(format!("unsupported baud rate: {}", rate)),
+	};
+
+	Ok(rate)
+}
+
+/// The path to the device of the tty, which is given either as a path or as a name in /dev.
+pub fn tty_path(tty: &str) -> PathBuf {
+	Path::new("/dev").join(tty)
+}
+
+/// Hangs up the tty, so that nothing left over from the last session still has it open, and then opens it again as
+/// stdin, stdout, and stderr, and as our controlling terminal.
+pub fn reopen(tty: &Path) -> Result<()> {
+	// SIGHUP is ignored, so hanging up only cuts everything else off.
+	Errno::result(unsafe { libc::vhangup() }).with_context(|| "failed to hang up the tty")?;
+
+	let fd = open(tty, OFlag::O_RDWR | OFlag::O_NOCTTY, Mode::empty())
+		.with_context(|| format!("failed to open {}", tty.display()))?;
+	unsafe { set_controlling_tty(fd, 1) }.with_context(|| format!("failed to take {}", tty.display()))?;
+	for target in 0..3 {
+		dup2(fd, target).with_context(|| format!("failed to copy {} to fd {}", tty.display(), target))?;
+	}
+
+	if fd > 2 {
+		close(fd).with_context(|| "failed to close old tty fd")?;
+	}
+
+	Ok(())
+}
+
+/// Puts the tty on stdin into a sane state for logging in: canonical mode with echo, the usual control characters,
+/// and the line set up as asked, whatever the last session left it in.
+pub fn initialize(options: &LineOptions) -> Result<()> {
+	let stdin = std::io::stdin();
+	let fd = unsafe { BorrowedFd::borrow_raw(stdin.as_raw_fd()) };
+	let mut termios = tcgetattr(fd).with_context(|| "failed to read the tty attributes")?;
+
+	if let Some(baud) = options.baud {
+		cfsetspeed(&mut termios, baud).with_context(|| "failed to set the baud rate")?;
+	}
+
+	termios.control_flags &=
+		!(ControlFlags::CSIZE | ControlFlags::PARENB | ControlFlags::CLOCAL | ControlFlags::CRTSCTS);
+	termios.control_flags |= ControlFlags::CS8 | ControlFlags::CREAD | ControlFlags::HUPCL;
+	if options.local {
+		termios.control_flags |= ControlFlags::CLOCAL;
+	}
+	if options.flow_control {
+		termios.control_flags |= ControlFlags::CRTSCTS;
+	}
+
+	termios.input_flags = InputFlags::ICRNL | InputFlags::IXON | InputFlags::BRKINT | InputFlags::IUTF8;
+	termios.output_flags = OutputFlags::OPOST | OutputFlags::ONLCR;
+	termios.local_flags = LocalFlags::ISIG
+		| LocalFlags::ICANON
+		| LocalFlags::IEXTEN
+		| LocalFlags::ECHO
+		| LocalFlags::ECHOE
+		| LocalFlags::ECHOK
+		| LocalFlags::ECHOCTL
+		| LocalFlags::ECHOKE;
+
+	let control_characters = [
+		(SpecialCharacterIndices::VINTR, 0x03),   // ^C
+		(SpecialCharacterIndices::VQUIT, 0x1c),   // ^\
+		(SpecialCharacterIndices::VERASE, 0x7f),  // DEL
+		(SpecialCharacterIndices::VKILL, 0x15),   // ^U
+		(SpecialCharacterIndices::VEOF, 0x04),    // ^D
+		(SpecialCharacterIndices::VSTART, 0x11),  // ^Q
+		(SpecialCharacterIndices::VSTOP, 0x13),   // ^S
+		(SpecialCharacterIndices::VSUSP, 0x1a),   // ^Z
+		(SpecialCharacterIndices::VWERASE, 0x17), // ^W
+		(SpecialCharacterIndices::VMIN, 1),
+		(SpecialCharacterIndices::VTIME, 0),
+	];
+	for (index, value) in control_characters {
+		termios.control_chars[index as usize] = value;
+	}
+
+	tcsetattr(fd, SetArg::TCSAFLUSH, &termios).with_context(|| "failed to set the tty attributes")?;
+
+	Ok(())
+}
@@ -0,0 +1,310 @@
+use std::{
+	cell::Cell,
+	fs::File,
+	io::{self, stdin, stdout, BufRead, BufReader, BufWriter, ErrorKind, Write},
+	path::Path,
+	process::ExitCode,
+	rc::Rc,
+};
+
+use clap::{Arg, ArgAction, Command};
+use common::fswalk::{ErrorAction, FsWalk};
+use regex::bytes::{Regex, RegexBuilder};
+
+/// The exit code when a line was selected.
+const EXIT_MATCH: u8 = 0;
+
+/// The exit code when no lines were selected.
+const EXIT_NO_MATCH: u8 = 1;
+
+/// The exit code when an error occurred, whether or not any lines were selected.
+const EXIT_ERROR: u8 = 2;
+
+/// Why a search stopped early.
+enum SearchError {
+	/// The input couldn't be read, which only stops the search of that input.
+	Read(io::Error),
+
+	/// The output couldn't be written, which stops everything.
+	Write(io::Error),
+}
+
+/// How lines are picked out, and how they're printed.
+struct Searcher {
+	regex: Regex,
+
+	/// Whether the lines that don't match are selected, rather than the ones that do.
+	invert: bool,
+
+	/// Whether selected lines are prefixed with their line number.
+	line_numbers: bool,
+
+	/// Whether selected lines are prefixed with the name of the file they're from.
+	file_names: bool,
+}
+
+impl Searcher {
+	/// Writes the selected lines of the reader to the output, returning whether any were selected. Lines are matched
+	/// as bytes, so files that aren't UTF-8 can still be searched.
+	fn search<R: BufRead, W: Write>(&self, mut reader: R, name: &str, output: &mut W) -> Result<bool, SearchError> {
+		let mut selected = false;
+		let mut line = Vec::new();
+		let mut number = 0;
+		loop {
+			line.clear();
+			if reader.read_until(b'\n', &mut line).map_err(SearchError::Read)? == 0 {
+				break;
+			}
+
+			number += 1;
+			let content = line.strip_suffix(b"\n").unwrap_or(&line);
+			if self.regex.is_match(content) == self.invert {
+				continue;
+			}
+
+			selected = true;
+			self.write_line(output, name, number, content)
+				.map_err(SearchError::Write)?;
+		}
+
+		Ok(selected)
+	}
+
+	/// Writes a selected line, with whatever it's prefixed with.
+	fn write_line<W: Write>(&self, output: &mut W, name: &str, number: usize, line: &[u8]) -> io::Result<()> {
+		if self.file_names {
+			write!(output, "{}:", name)?;
+		}
+
+		if self.line_numbers {
+			write!(output, "{}:", number)?;
+		}
+
+		output.write_all(line)?;
+		output.write_all(b"\n")
+	}
+}
+
+/// The outcome of searching everything, which decides the exit code.
+#[derive(Default)]
+struct Outcome {
+	selected: bool,
+	failed: bool,
+}
+
+impl Outcome {
+	fn exit_code(&self) -> ExitCode {
+		match (self.failed, self.selected) {
+			(true, _) => ExitCode::from(EXIT_ERROR),
+			(false, true) => ExitCode::from(EXIT_MATCH),
+			(false, false) => ExitCode::from(EXIT_NO_MATCH),
+		}
+	}
+}
+
+/// Searches a single file (or stdin, if it's `-`), noting what happened in the outcome. Returns an error if the output
+/// couldn't be written, as there's no point in carrying on.
+fn search_file<W: Write>(searcher: &Searcher, path: &str, output: &mut W, outcome: &mut Outcome) -> io::Result<()> {
+	let result = if path == "-" {
+		searcher.search(stdin().lock(), "(standard input)", output)
+	} else {
+		match File::open(path) {
+			Ok(file) if file.metadata().is_ok_and(|metadata| metadata.is_dir()) => {
+				Err(SearchError::Read(io::Error::other("Is a directory")))
+			}
+			Ok(file) => searcher.search(BufReader::new(file), path, output),
+			Err(e) => Err(SearchError::Read(e)),
+		}
+	};
+
+	match result {
+		Ok(selected) => outcome.selected |= selected,
+		Err(SearchError::Write(e)) => return Err(e),
+		Err(SearchError::Read(e)) => {
+			eprintln!("grep: {}: {}", path, e);
+			outcome.failed = true;
+		}
+	}
+
+	Ok(())
+}
+
+/// Searches every regular file under the path, which is searched itself if it isn't a directory. Symlinks inside of
+/// directories aren't followed.
+fn search_tree<W: Write>(searcher: &Searcher, root: &str, output: &mut W, outcome: &mut Outcome) -> io::Result<()> {
+	if root == "-" || !Path::new(root).is_dir() {
+		return search_file(searcher, root, output, outcome);
+	}
+
+	let walk_failed = Rc::new(Cell::new(false));
+	let walk = FsWalk::new(root).on_error({
+		let walk_failed = walk_failed.clone();
+		move |path, e| {
+			eprintln!("grep: {}: {}", path.display(), e);
+			walk_failed.set(true);
+			ErrorAction::Skip
+		}
+	});
+
+	for entry in walk.flatten() {
+		if entry.is_symlink || !entry.metadata.is_file() {
+			continue;
+		}
+
+		search_file(searcher, &entry.path.to_string_lossy(), output, outcome)?;
+	}
+
+	outcome.failed |= walk_failed.get();
+	Ok(())
+}
+
+fn main() -> ExitCode {
+	let matches = Command::new("grep")
+		.about("print lines that match a pattern")
+		.author("Colin Douch")
+		.version("0.1")
+		.arg(
+			Arg::new("pattern")
+				.required(true)
+				.help("the regular expression to search for"),
+		)
+		.arg(
+			Arg::new("files")
+				.num_args(0..)
+				.help("the files to search, or - for standard input"),
+		)
+		.arg(
+			Arg::new("ignore-case")
+				.short('i')
+				.long("ignore-case")
+				.help("ignore case distinctions in patterns and data")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("invert-match")
+				.short('v')
+				.long("invert-match")
+				.help("select lines that don't match")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("line-number")
+				.short('n')
+				.long("line-number")
+				.help("print the line number of each selected line")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("recursive")
+				.short('r')
+				.short_alias('R')
+				.long("recursive")
+				.help("search the files in directories recursively")
+				.action(ArgAction::SetTrue),
+		)
+		.get_matches();
+
+	let pattern: &String = matches.get_one("pattern").unwrap();
+	let regex = match RegexBuilder::new(pattern)
+		.case_insensitive(matches.get_flag("ignore-case"))
+		.build()
+	{
+		Ok(regex) => regex,
+		Err(e) => {
+			eprintln!("grep: invalid pattern: {}", e);
+			return ExitCode::from(EXIT_ERROR);
+		}
+	};
+
+	let recursive = matches.get_flag("recursive");
+	let files: Vec<&str> = match matches.get_many::<String>("files") {
+		Some(files) => files.map(String::as_str).collect(),
+		// Like GNU grep, a recursive search without any files searches the working directory.
+		None if recursive => vec!["."],
+		None => vec!["-"],
+	};
+
+	let searcher = Searcher {
+		regex,
+		invert: matches.get_flag("invert-match"),
+		line_numbers: matches.get_flag("line-number"),
+		file_names: recursive || files.len() > 1,
+	};
+
+	let mut output = BufWriter::new(stdout().lock());
+	let mut outcome = Outcome::default();
+	for file in files {
+		let result = match recursive {
+			true => search_tree(&searcher, file, &mut output, &mut outcome),
+			false => search_file(&searcher, file, &mut output, &mut outcome),
+		};
+
+		if let Err(e) = result {
+			// Whatever was reading the output has gone away, so nothing else can be printed.
+			if e.kind() != ErrorKind::BrokenPipe {
+				eprintln!("grep: failed to write output: {}", e);
+			}
+
+			return ExitCode::from(EXIT_ERROR);
+		}
+	}
+
+	if let Err(e) = output.flush() {
+		if e.kind() != ErrorKind::BrokenPipe {
+			eprintln!("grep: failed to write output: {}", e);
+		}
+
+		return ExitCode::from(EXIT_ERROR);
+	}
+
+	outcome.exit_code()
+}
+
+#[cfg(test)]
+mod test {
+	use regex::bytes::RegexBuilder;
+
+	use super::Searcher;
+
+	fn searcher(pattern: &str, invert: bool, line_numbers: bool, file_names: bool) -> Searcher {
+		Searcher {
+			regex: RegexBuilder::new(pattern).case_insensitive(true).build().unwrap(),
+			invert,
+			line_numbers,
+			file_names,
+		}
+	}
+
+	fn search(searcher: &Searcher, input: &[u8]) -> (bool, Vec<u8>) {
+		let mut output = Vec::new();
+		let selected = match searcher.search(input, "file", &mut output) {
+			Ok(selected) => selected,
+			Err(_) => panic!("failed to search"),
+		};
+		(selected, output)
+	}
+
+	#[test]
+	fn test_search() {
+		let input = b"root:x:0:0\nColin:x:1000:1000\n\xffbinary\nnobody:x:65534:65534";
+
+		assert_eq!(
+			search(&searcher("^colin", false, false, false), input),
+			(true, b"Colin:x:1000:1000\n".to_vec())
+		);
+		assert_eq!(
+			search(&searcher(r"\d{5}", false, true, true), input),
+			(true, b"file:4:nobody:x:65534:65534\n".to_vec())
+		);
+
+		// Lines that aren't UTF-8 are printed as they are.
+		assert_eq!(
+			search(&searcher(":x:", true, true, false), input),
+			(true, b"3:\xffbinary\n".to_vec())
+		);
+		assert_eq!(
+			search(&searcher("missing", false, false, false), input),
+			(false, Vec::new())
+		);
+	}
+}
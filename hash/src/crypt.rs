@@ -1,7 +1,8 @@
 /// Entirely cargo culted from [SHA-crypt.txt](https://akkadia.org/drepper/SHA-crypt.txt), mirrored [here](../docs/SHA-crypt.txt).
-use ring::digest;
 use thiserror::Error;
 
+use crate::{Digest, Sha256, Sha512};
+
 const ROUNDS_MIN: u32 = 1000;
 const ROUNDS_MAX: u32 = 999_999_999;
 const ROUNDS_DEFAULT: u32 = 5000;
@@ -12,6 +13,14 @@ pub enum Sha2Mode {
 }
 
 impl Sha2Mode {
+	/// Hashes the data with the mode's hash function.
+	fn digest(&self, data: &[u8]) -> Vec<u8> {
+		match self {
+			Sha2Mode::Sha256 => Sha256::digest(data).to_vec(),
+			Sha2Mode::Sha512 => Sha512::digest(data).to_vec(),
+		}
+	}
+
 	/// Encodes the given data slice (A Sha digest), into a base64 string
 	/// using the given mode. Returns none if the given data slice is not
 	/// valid for the given mode (i.e. 32 bytes for sha256, 64 for sha512)
@@ -79,11 +88,6 @@ impl Sha2Mode {
 			return Err(Sha2Error::InvalidRounds(rounds));
 		}
 
-		let algorithm = match self {
-			Sha2Mode::Sha256 => &digest::SHA256,
-			Sha2Mode::Sha512 => &digest::SHA512,
-		};
-
 		// Based off of https://akkadia.org/drepper/SHA-crypt.txt
 
 		//  start digest A.
@@ -105,13 +109,13 @@ impl Sha2Mode {
 		digest_b.extend_from_slice(password);
 
 		// finish digest B.
-		let digest_b = digest::digest(algorithm, &digest_b);
+		let digest_b = self.digest(&digest_b);
 
 		// For each block of 32 or 64 bytes in the password string (excluding
 		// the terminating NUL in the C representation), add digest B to digest A.
 		// For the remaining N bytes of the password string add the first
 		// N bytes of digest B to digest A.
-		digest_a.extend(digest_b.as_ref().iter().cycle().take(password.len()));
+		digest_a.extend(digest_b.iter().cycle().take(password.len()));
 
 		// For each bit of the binary representation of the length of the
 		// password string up to and including the highest 1-digit, starting
@@ -120,7 +124,7 @@ impl Sha2Mode {
 		while len > 0 {
 			if len & 1 == 1 {
 				// a) for a 1-digit add digest B to digest A.
-				digest_a.extend(digest_b.as_ref());
+				digest_a.extend(&digest_b);
 			} else {
 				// for a 0-digit add the password string.
 				digest_a.extend_from_slice(password);
@@ -130,7 +134,7 @@ impl Sha2Mode {
 		}
 
 		// finish digest A
-		let digest_a = digest::digest(algorithm, &digest_a);
+		let digest_a = self.digest(&digest_a);
 
 		// start digest DP
 		let mut digest_dp = Vec::new();
@@ -142,36 +146,31 @@ impl Sha2Mode {
 		}
 
 		// finish digest DP.
-		let digest_dp = digest::digest(algorithm, &digest_dp);
+		let digest_dp = self.digest(&digest_dp);
 
 		//  produce byte sequence P of the same length as the password where
 		//  a) for each block of 32 or 64 bytes of length of the password string
 		//  the entire digest DP is used
 		//  b) for the remaining N (up to  31 or 63) bytes use the first N
 		//     bytes of digest DP
-		let p = digest_dp
-			.as_ref()
-			.iter()
-			.cycle()
-			.take(password.len())
-			.collect::<Vec<_>>();
+		let p = digest_dp.iter().cycle().take(password.len()).collect::<Vec<_>>();
 
 		// start digest DS
 		let mut digest_ds = Vec::new();
 
 		// repeat the following 16+A[0] times, where A[0] represents the first
 		// byte in digest A interpreted as an 8-bit unsigned value add the salt to digest DS.
-		for _ in 0..16 + digest_a.as_ref()[0] {
+		for _ in 0..16 + digest_a[0] {
 			digest_ds.extend_from_slice(salt);
 		}
 
 		// finish digest DS.
-		let digest_ds = digest::digest(algorithm, &digest_ds);
+		let digest_ds = self.digest(&digest_ds);
 
 		// produce byte sequence S of the same length as the salt string where
 		// a) for each block of 32 or 64 bytes of length of the salt string the entire digest DS is used
 		// b) for the remaining N (up to  31 or 63) bytes use the first N bytes of digest DS
-		let s: Vec<u8> = digest_ds.as_ref().iter().cycle().take(salt.len()).cloned().collect();
+		let s: Vec<u8> = digest_ds.iter().cycle().take(salt.len()).cloned().collect();
 		let mut previous_digest = digest_a;
 
 		// repeat a loop according to the number specified in the rounds=<N>
@@ -191,7 +190,7 @@ impl Sha2Mode {
 				digest_c.extend_from_slice(&p);
 			} else {
 				// for even round numbers add digest A/C.
-				digest_c.extend(previous_digest.as_ref());
+				digest_c.extend(&previous_digest);
 			}
 
 			// for all round numbers not divisible by 3 add the byte sequence S.
@@ -206,7 +205,7 @@ impl Sha2Mode {
 
 			if round % 2 == 1 {
 				// for odd round numbers add digest A/C
-				digest_c.extend(previous_digest.as_ref());
+				digest_c.extend(&previous_digest);
 			} else {
 				// for even round numbers add the byte sequence P
 				digest_c.extend_from_slice(&p);
@@ -214,10 +213,10 @@ impl Sha2Mode {
 
 			// finish digest C.
 			let digest_c: Vec<u8> = digest_c.into_iter().cloned().collect();
-			previous_digest = digest::digest(algorithm, &digest_c);
+			previous_digest = self.digest(&digest_c);
 		}
 
-		Ok(self.crypt_sha2_base64(previous_digest.as_ref()))
+		Ok(self.crypt_sha2_base64(&previous_digest))
 	}
 }
 
@@ -0,0 +1,139 @@
+use thiserror::Error;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// The standard base64 alphabet, from RFC 4648.
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const BASE64_PADDING: u8 = b'=';
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DecodeError {
+	#[error("invalid character {0:?} at {1}")]
+	InvalidCharacter(char, usize),
+
+	#[error("invalid length: {0}")]
+	InvalidLength(usize),
+}
+
+/// Encodes the bytes as lowercase hex, e.g. for showing a digest.
+pub fn to_hex(bytes: &[u8]) -> String {
+	let mut hex = String::with_capacity(bytes.len() * 2);
+	for byte in bytes {
+		hex.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+		hex.push(HEX_DIGITS[(byte & 0xF) as usize] as char);
+	}
+
+	hex
+}
+
+/// Decodes hex, in either case.
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, DecodeError> {
+	if !hex.len().is_multiple_of(2) {
+		return Err(DecodeError::InvalidLength(hex.len()));
+	}
+
+	let digit = |i: usize| {
+		let c = hex.as_bytes()[i];
+		(c as char)
+			.to_digit(16)
+			.map(|d| d as u8)
+			.ok_or(DecodeError::InvalidCharacter(c as char, i))
+	};
+
+	(0..hex.len())
+		.step_by(2)
+		.map(|i| Ok((digit(i)? << 4) | digit(i + 1)?))
+		.collect()
+}
+
+/// Encodes the bytes as padded base64, with the standard alphabet.
+pub fn to_base64(bytes: &[u8]) -> String {
+	let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+	for chunk in bytes.chunks(3) {
+		let group = chunk
+			.iter()
+			.enumerate()
+			.fold(0u32, |group, (i, byte)| group | (*byte as u32) << (16 - 8 * i));
+
+		// Every byte in the chunk spills into one more character than there are bytes.
+		for i in 0..4 {
+			if i <= chunk.len() {
+				encoded.push(BASE64_ALPHABET[((group >> (18 - 6 * i)) & 0x3F) as usize] as char);
+			} else {
+				encoded.push(BASE64_PADDING as char);
+			}
+		}
+	}
+
+	encoded
+}
+
+/// Decodes padded base64, with the standard alphabet.
+pub fn from_base64(encoded: &str) -> Result<Vec<u8>, DecodeError> {
+	let bytes = encoded.as_bytes();
+	if !bytes.len().is_multiple_of(4) {
+		return Err(DecodeError::InvalidLength(bytes.len()));
+	}
+
+	let padding = bytes.iter().rev().take_while(|c| **c == BASE64_PADDING).count();
+	if padding > 2 {
+		return Err(DecodeError::InvalidCharacter(
+			BASE64_PADDING as char,
+			bytes.len() - padding,
+		));
+	}
+
+	let mut decoded = Vec::with_capacity(bytes.len() / 4 * 3);
+	for (chunk_index, chunk) in bytes[..bytes.len() - padding].chunks(4).enumerate() {
+		let mut group = 0u32;
+		for (i, c) in chunk.iter().enumerate() {
+			let value = BASE64_ALPHABET
+				.iter()
+				.position(|a| a == c)
+				.ok_or(DecodeError::InvalidCharacter(*c as char, chunk_index * 4 + i))?;
+			group |= (value as u32) << (18 - 6 * i);
+		}
+
+		// A chunk of n characters holds n - 1 whole bytes.
+		decoded.extend(group.to_be_bytes()[1..chunk.len()].iter());
+	}
+
+	Ok(decoded)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{from_base64, from_hex, to_base64, to_hex, DecodeError};
+
+	#[test]
+	fn test_hex() {
+		assert_eq!(to_hex(&[0x00, 0x7f, 0xab, 0xff]), "007fabff");
+		assert_eq!(from_hex("007fABff").unwrap(), vec![0x00, 0x7f, 0xab, 0xff]);
+		assert_eq!(from_hex("abc"), Err(DecodeError::InvalidLength(3)));
+		assert_eq!(from_hex("zz"), Err(DecodeError::InvalidCharacter('z', 0)));
+	}
+
+	#[test]
+	fn test_base64() {
+		// From RFC 4648.
+		let cases = [
+			("", ""),
+			("f", "Zg=="),
+			("fo", "Zm8="),
+			("foo", "Zm9v"),
+			("foob", "Zm9vYg=="),
+			("fooba", "Zm9vYmE="),
+			("foobar", "Zm9vYmFy"),
+		];
+
+		for (decoded, encoded) in cases {
+			assert_eq!(to_base64(decoded.as_bytes()), encoded);
+			assert_eq!(from_base64(encoded).unwrap(), decoded.as_bytes());
+		}
+
+		assert_eq!(from_base64("Zm9"), Err(DecodeError::InvalidLength(3)));
+		assert_eq!(from_base64("Zm9*"), Err(DecodeError::InvalidCharacter('*', 3)));
+		assert_eq!(from_base64("Z==="), Err(DecodeError::InvalidCharacter('=', 1)));
+	}
+}
@@ -0,0 +1,107 @@
+use crate::Digest;
+
+/// The byte that's XOR'd into the key before hashing the message.
+const INNER_PAD: u8 = 0x36;
+
+/// The byte that's XOR'd into the key before hashing the inner digest.
+const OUTER_PAD: u8 = 0x5c;
+
+/// A streaming HMAC (RFC 2104) over the given hash function, e.g. `Hmac<Sha256>`.
+#[derive(Clone)]
+pub struct Hmac<D: Digest> {
+	/// The hash of the inner padded key, followed by the message.
+	inner: D,
+
+	/// The hash of the outer padded key, which the inner digest is added to when finishing.
+	outer: D,
+}
+
+impl<D: Digest> Hmac<D> {
+	pub fn new(key: &[u8]) -> Self {
+		// Keys longer than a block are hashed down first, and everything is padded with zeros to a block.
+		let mut block = vec![0; D::BLOCK_SIZE];
+		if key.len() > D::BLOCK_SIZE {
+			let digest = D::digest(key);
+			block[..digest.as_ref().len()].copy_from_slice(digest.as_ref());
+		} else {
+			block[..key.len()].copy_from_slice(key);
+		}
+
+		let mut inner = D::new();
+		inner.update(&block.iter().map(|b| b ^ INNER_PAD).collect::<Vec<_>>());
+		let mut outer = D::new();
+		outer.update(&block.iter().map(|b| b ^ OUTER_PAD).collect::<Vec<_>>());
+
+		Self { inner, outer }
+	}
+
+	/// Adds the data to the end of the message.
+	pub fn update(&mut self, data: &[u8]) {
+		self.inner.update(data);
+	}
+
+	/// Finishes the message, returning its tag.
+	pub fn finalize(self) -> D::Output {
+		let mut outer = self.outer;
+		outer.update(self.inner.finalize().as_ref());
+		outer.finalize()
+	}
+
+	/// Finishes the message, returning whether its tag is the given one. This takes the same amount of time however
+	/// much of the tag matches, so it can be used to check tags from untrusted sources.
+	pub fn verify(self, tag: &[u8]) -> bool {
+		let expected = self.finalize();
+		let expected = expected.as_ref();
+		expected.len() == tag.len() && expected.iter().zip(tag).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+	}
+
+	/// Signs the data in one go.
+	pub fn sign(key: &[u8], data: &[u8]) -> D::Output {
+		let mut hmac = Self::new(key);
+		hmac.update(data);
+		hmac.finalize()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Hmac;
+	use crate::{encoding::to_hex, Sha256, Sha512};
+
+	#[test]
+	fn test_hmac() {
+		// From RFC 4231.
+		let data = b"what do ya want for nothing?";
+		assert_eq!(
+			to_hex(&Hmac::<Sha256>::sign(b"Jefe", data)),
+			"5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+		);
+		assert_eq!(
+			to_hex(&Hmac::<Sha512>::sign(b"Jefe", data)),
+			"164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+		);
+
+		let long_key = [0xaa; 131];
+		let mut hmac = Hmac::<Sha256>::new(&long_key);
+		hmac.update(b"Test Using Larger Than ");
+		hmac.update(b"Block-Size Key - Hash Key First");
+		assert_eq!(
+			to_hex(&hmac.finalize()),
+			"60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+		);
+	}
+
+	#[test]
+	fn test_verify() {
+		let tag = Hmac::<Sha256>::sign(b"key", b"message");
+
+		let mut hmac = Hmac::<Sha256>::new(b"key");
+		hmac.update(b"message");
+		assert!(hmac.clone().verify(&tag));
+		assert!(!hmac.clone().verify(&tag[..16]));
+
+		let mut forged = tag;
+		forged[0] ^= 1;
+		assert!(!hmac.verify(&forged));
+	}
+}
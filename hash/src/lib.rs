@@ -0,0 +1,32 @@
+pub mod crypt;
+pub mod encoding;
+pub mod hmac;
+pub mod sha2;
+
+pub use hmac::Hmac;
+pub use sha2::{Sha256, Sha512};
+
+/// A streaming hash function, which data can be written to a bit at a time before the digest of all of it is taken.
+pub trait Digest: Clone {
+	/// The size of the blocks that the hash function works on, in bytes, which HMAC pads keys to.
+	const BLOCK_SIZE: usize;
+
+	/// The digest, which is a fixed number of bytes.
+	type Output: AsRef<[u8]> + Copy;
+
+	/// Starts hashing a new message.
+	fn new() -> Self;
+
+	/// Adds the data to the end of the message.
+	fn update(&mut self, data: &[u8]);
+
+	/// Finishes the message, returning its digest.
+	fn finalize(self) -> Self::Output;
+
+	/// Hashes the data in one go.
+	fn digest(data: &[u8]) -> Self::Output {
+		let mut hasher = Self::new();
+		hasher.update(data);
+		hasher.finalize()
+	}
+}
@@ -0,0 +1,380 @@
+use std::io;
+
+use crate::Digest;
+
+const SHA256_ROUND_CONSTANTS: [u32; 64] = [
+	0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5, 0xd807aa98,
+	0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786,
+	0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8,
+	0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13,
+	0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819,
+	0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a,
+	0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+	0xc67178f2,
+];
+
+const SHA256_INITIAL_STATE: [u32; 8] = [
+	0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA512_ROUND_CONSTANTS: [u64; 80] = [
+	0x428a2f98d728ae22,
+	0x7137449123ef65cd,
+	0xb5c0fbcfec4d3b2f,
+	0xe9b5dba58189dbbc,
+	0x3956c25bf348b538,
+	0x59f111f1b605d019,
+	0x923f82a4af194f9b,
+	0xab1c5ed5da6d8118,
+	0xd807aa98a3030242,
+	0x12835b0145706fbe,
+	0x243185be4ee4b28c,
+	0x550c7dc3d5ffb4e2,
+	0x72be5d74f27b896f,
+	0x80deb1fe3b1696b1,
+	0x9bdc06a725c71235,
+	0xc19bf174cf692694,
+	0xe49b69c19ef14ad2,
+	0xefbe4786384f25e3,
+	0x0fc19dc68b8cd5b5,
+	0x240ca1cc77ac9c65,
+	0x2de92c6f592b0275,
+	0x4a7484aa6ea6e483,
+	0x5cb0a9dcbd41fbd4,
+	0x76f988da831153b5,
+	0x983e5152ee66dfab,
+	0xa831c66d2db43210,
+	0xb00327c898fb213f,
+	0xbf597fc7beef0ee4,
+	0xc6e00bf33da88fc2,
+	0xd5a79147930aa725,
+	0x06ca6351e003826f,
+	0x142929670a0e6e70,
+	0x27b70a8546d22ffc,
+	0x2e1b21385c26c926,
+	0x4d2c6dfc5ac42aed,
+	0x53380d139d95b3df,
+	0x650a73548baf63de,
+	0x766a0abb3c77b2a8,
+	0x81c2c92e47edaee6,
+	0x92722c851482353b,
+	0xa2bfe8a14cf10364,
+	0xa81a664bbc423001,
+	0xc24b8b70d0f89791,
+	0xc76c51a30654be30,
+	0xd192e819d6ef5218,
+	0xd69906245565a910,
+	0xf40e35855771202a,
+	0x106aa07032bbd1b8,
+	0x19a4c116b8d2d0c8,
+	0x1e376c085141ab53,
+	0x2748774cdf8eeb99,
+	0x34b0bcb5e19b48a8,
+	0x391c0cb3c5c95a63,
+	0x4ed8aa4ae3418acb,
+	0x5b9cca4f7763e373,
+	0x682e6ff3d6b2b8a3,
+	0x748f82ee5defb2fc,
+	0x78a5636f43172f60,
+	0x84c87814a1f0ab72,
+	0x8cc702081a6439ec,
+	0x90befffa23631e28,
+	0xa4506cebde82bde9,
+	0xbef9a3f7b2c67915,
+	0xc67178f2e372532b,
+	0xca273eceea26619c,
+	0xd186b8c721c0c207,
+	0xeada7dd6cde0eb1e,
+	0xf57d4f7fee6ed178,
+	0x06f067aa72176fba,
+	0x0a637dc5a2c898a6,
+	0x113f9804bef90dae,
+	0x1b710b35131c471b,
+	0x28db77f523047d84,
+	0x32caab7b40c72493,
+	0x3c9ebe0a15c9bebc,
+	0x431d67c49c100d4c,
+	0x4cc5d4becb3e42b6,
+	0x597f299cfc657e2a,
+	0x5fcb6fab3ad6faec,
+	0x6c44198c4a475817,
+];
+
+const SHA512_INITIAL_STATE: [u64; 8] = [
+	0x6a09e667f3bcc908,
+	0xbb67ae8584caa73b,
+	0x3c6ef372fe94f82b,
+	0xa54ff53a5f1d36f1,
+	0x510e527fade682d1,
+	0x9b05688c2b3e6c1f,
+	0x1f83d9abfb41bd6b,
+	0x5be0cd19137e2179,
+];
+
+/// Defines a streaming SHA-2 hasher. SHA-256 and SHA-512 are the same algorithm over different word sizes, with
+/// different round constants and rotation amounts, and a length suffix that's as wide as two words.
+macro_rules! sha2 {
+	(
+		$(#[$meta:meta])*
+		$name:ident {
+			word: $word:ty,
+			length: $length:ty,
+			block_size: $block_size:literal,
+			output_size: $output_size:literal,
+			initial_state: $initial_state:expr,
+			round_constants: $round_constants:expr,
+			big_sigma: [$bs0a:literal, $bs0b:literal, $bs0c:literal, $bs1a:literal, $bs1b:literal, $bs1c:literal],
+			small_sigma: [$ss0a:literal, $ss0b:literal, $ss0c:literal, $ss1a:literal, $ss1b:literal, $ss1c:literal],
+		}
+	) => {
+		$(#[$meta])*
+		#[derive(Clone)]
+		pub struct $name {
+			state: [$word; 8],
+
+			/// The bytes that have been written, but don't yet fill a block.
+			block: [u8; $block_size],
+			buffered: usize,
+
+			/// The number of bytes that have been written in total.
+			length: $length,
+		}
+
+		impl $name {
+			fn compress(state: &mut [$word; 8], block: &[u8; $block_size]) {
+				const WORD_SIZE: usize = std::mem::size_of::<$word>();
+
+				let mut schedule = [0 as $word; $round_constants.len()];
+				for (word, bytes) in schedule.iter_mut().zip(block.chunks_exact(WORD_SIZE)) {
+					*word = <$word>::from_be_bytes(bytes.try_into().expect("BUG: chunk is the size of a word"));
+				}
+
+				for i in 16..schedule.len() {
+					let (w15, w2) = (schedule[i - 15], schedule[i - 2]);
+					let s0 = w15.rotate_right($ss0a) ^ w15.rotate_right($ss0b) ^ (w15 >> $ss0c);
+					let s1 = w2.rotate_right($ss1a) ^ w2.rotate_right($ss1b) ^ (w2 >> $ss1c);
+					schedule[i] = schedule[i - 16]
+						.wrapping_add(s0)
+						.wrapping_add(schedule[i - 7])
+						.wrapping_add(s1);
+				}
+
+				let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+				for (constant, word) in $round_constants.iter().zip(schedule) {
+					let s1 = e.rotate_right($bs1a) ^ e.rotate_right($bs1b) ^ e.rotate_right($bs1c);
+					let choice = (e & f) ^ (!e & g);
+					let temp1 = h
+						.wrapping_add(s1)
+						.wrapping_add(choice)
+						.wrapping_add(*constant)
+						.wrapping_add(word);
+					let s0 = a.rotate_right($bs0a) ^ a.rotate_right($bs0b) ^ a.rotate_right($bs0c);
+					let majority = (a & b) ^ (a & c) ^ (b & c);
+					let temp2 = s0.wrapping_add(majority);
+
+					h = g;
+					g = f;
+					f = e;
+					e = d.wrapping_add(temp1);
+					d = c;
+					c = b;
+					b = a;
+					a = temp1.wrapping_add(temp2);
+				}
+
+				for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+					*word = word.wrapping_add(value);
+				}
+			}
+		}
+
+		impl Default for $name {
+			fn default() -> Self {
+				Self {
+					state: $initial_state,
+					block: [0; $block_size],
+					buffered: 0,
+					length: 0,
+				}
+			}
+		}
+
+		impl Digest for $name {
+			const BLOCK_SIZE: usize = $block_size;
+
+			type Output = [u8; $output_size];
+
+			fn new() -> Self {
+				Self::default()
+			}
+
+			fn update(&mut self, mut data: &[u8]) {
+				self.length = self.length.wrapping_add(data.len() as $length);
+
+				// Top up a partially filled block first, so that the rest can be compressed straight from the data.
+				if self.buffered > 0 {
+					let take = data.len().min($block_size - self.buffered);
+					self.block[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+					self.buffered += take;
+					data = &data[take..];
+					if self.buffered < $block_size {
+						return;
+					}
+
+					Self::compress(&mut self.state, &self.block);
+					self.buffered = 0;
+				}
+
+				let mut blocks = data.chunks_exact($block_size);
+				for block in &mut blocks {
+					Self::compress(&mut self.state, block.try_into().expect("BUG: chunk is the size of a block"));
+				}
+
+				let remainder = blocks.remainder();
+				self.block[..remainder.len()].copy_from_slice(remainder);
+				self.buffered = remainder.len();
+			}
+
+			fn finalize(mut self) -> Self::Output {
+				// The message is padded with a 1 bit, then zeros up to the length (in bits) at the end of a block.
+				const LENGTH_SIZE: usize = std::mem::size_of::<$length>();
+				let bit_length = self.length.wrapping_mul(8);
+
+				self.block[self.buffered] = 0x80;
+				self.block[self.buffered + 1..].fill(0);
+				if self.buffered + 1 > $block_size - LENGTH_SIZE {
+					Self::compress(&mut self.state, &self.block);
+					self.block.fill(0);
+				}
+
+				self.block[$block_size - LENGTH_SIZE..].copy_from_slice(&bit_length.to_be_bytes());
+				Self::compress(&mut self.state, &self.block);
+
+				let mut output = [0; $output_size];
+				for (bytes, word) in output.chunks_exact_mut(std::mem::size_of::<$word>()).zip(self.state) {
+					bytes.copy_from_slice(&word.to_be_bytes());
+				}
+
+				output
+			}
+		}
+
+		/// Hashes everything that's written, so that e.g. a file can be hashed with `io::copy`.
+		impl io::Write for $name {
+			fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+				self.update(buf);
+				Ok(buf.len())
+			}
+
+			fn flush(&mut self) -> io::Result<()> {
+				Ok(())
+			}
+		}
+	};
+}
+
+sha2! {
+	/// A SHA-256 hasher, as specified in FIPS 180-4.
+	Sha256 {
+		word: u32,
+		length: u64,
+		block_size: 64,
+		output_size: 32,
+		initial_state: SHA256_INITIAL_STATE,
+		round_constants: SHA256_ROUND_CONSTANTS,
+		big_sigma: [2, 13, 22, 6, 11, 25],
+		small_sigma: [7, 18, 3, 17, 19, 10],
+	}
+}
+
+sha2! {
+	/// A SHA-512 hasher, as specified in FIPS 180-4.
+	Sha512 {
+		word: u64,
+		length: u128,
+		block_size: 128,
+		output_size: 64,
+		initial_state: SHA512_INITIAL_STATE,
+		round_constants: SHA512_ROUND_CONSTANTS,
+		big_sigma: [28, 34, 39, 14, 18, 41],
+		small_sigma: [1, 8, 7, 19, 61, 6],
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::{self, Cursor};
+
+	use super::{Sha256, Sha512};
+	use crate::{encoding::to_hex, Digest};
+
+	#[test]
+	fn test_sha256() {
+		let cases = [
+			("", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+			(
+				"abc",
+				"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+			),
+			(
+				"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+				"248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+			),
+		];
+
+		for (input, expected) in cases {
+			assert_eq!(to_hex(&Sha256::digest(input.as_bytes())), expected, "{:?}", input);
+		}
+
+		assert_eq!(
+			to_hex(&Sha256::digest(&[b'a'; 1_000_000])),
+			"cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+		);
+	}
+
+	#[test]
+	fn test_sha512() {
+		let cases = [
+			(
+				"",
+				"cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e",
+			),
+			(
+				"abc",
+				"ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+			),
+			(
+				"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu",
+				"8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909",
+			),
+		];
+
+		for (input, expected) in cases {
+			assert_eq!(to_hex(&Sha512::digest(input.as_bytes())), expected, "{:?}", input);
+		}
+	}
+
+	#[test]
+	fn test_streaming() {
+		// Lengths around the block size, where the padding spills into another block.
+		let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+		for len in [55, 56, 63, 64, 111, 112, 127, 128, 1000] {
+			let data = &data[..len];
+			for chunk_size in [1, 7, 64, 200] {
+				let mut sha256 = Sha256::new();
+				let mut sha512 = Sha512::new();
+				for chunk in data.chunks(chunk_size) {
+					sha256.update(chunk);
+					sha512.update(chunk);
+				}
+
+				assert_eq!(sha256.finalize(), Sha256::digest(data));
+				assert_eq!(sha512.finalize(), Sha512::digest(data));
+			}
+		}
+
+		let mut hasher = Sha256::new();
+		io::copy(&mut Cursor::new(b"abc"), &mut hasher).unwrap();
+		assert_eq!(hasher.finalize(), Sha256::digest(b"abc"));
+	}
+}
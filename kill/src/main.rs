@@ -0,0 +1,166 @@
+use std::{env, process::ExitCode, str::FromStr};
+
+use nix::{
+	sys::signal::{kill, Signal},
+	unistd::Pid,
+};
+
+const USAGE: &str = "usage: kill [-s SIGNAL | -SIGNAL] PID...
+       kill -l
+
+Sends a signal (TERM by default) to processes. SIGNAL is a name like TERM or SIGKILL, or a number like 9.
+A negative PID signals every process in the process group with that ID, e.g. `kill -TERM -1234`, or
+`kill -- -1234` to send the default signal.";
+
+/// What `kill` was asked to do.
+#[derive(Debug, PartialEq)]
+enum KillArgs {
+	/// Send the signal (or check that the processes exist, if it's None) to the processes.
+	Signal(Option<Signal>, Vec<i32>),
+
+	/// List the signals that can be sent.
+	List,
+
+	Help,
+}
+
+/// Parses a signal from its name (with or without the SIG prefix, in any case) or number. Signal 0 doesn't send
+/// anything, but checks that the processes can be signalled.
+fn parse_signal(s: &str) -> Result<Option<Signal>, String> {
+	if let Ok(number) = s.parse::<i32>() {
+		return match number {
+			0 => Ok(None),
+			number => Signal::try_from(number)
+				.map(Some)
+				.map_err(|_| format!("invalid signal: {}", s)),
+		};
+	}
+
+	let name = s.to_ascii_uppercase();
+	let name = if name.starts_with("SIG") {
+		name
+	} else {
+		format!("SIG{}", name)
+	};
+	Signal::from_str(&name)
+		.map(Some)
+		.map_err(|_| format!("invalid signal: {}", s))
+}
+
+/// Parses the arguments by hand, as clap can't tell `-9` and `-TERM` apart from flags, or from process groups.
+fn parse_args(args: &[String]) -> Result<KillArgs, String> {
+	let mut signal = Some(Signal::SIGTERM);
+	let mut args = args.iter().peekable();
+	match args.peek().map(|arg| arg.as_str()) {
+		Some("-l" | "--list") => return Ok(KillArgs::List),
+		Some("-h" | "--help") => return Ok(KillArgs::Help),
+		Some("-s") => {
+			args.next();
+			signal = parse_signal(args.next().ok_or("-s needs a signal")?)?;
+		}
+		Some("--") => {}
+		// Only the first argument can be a signal, so `kill -9 -1234` signals the process group 1234.
+		Some(arg) if arg.len() > 1 && arg.starts_with('-') => {
+			signal = parse_signal(&arg[1..])?;
+			args.next();
+		}
+		_ => {}
+	}
+
+	if args.peek().is_some_and(|arg| *arg == "--") {
+		args.next();
+	}
+
+	let pids = args
+		.map(|arg| arg.parse::<i32>().map_err(|_| format!("invalid process ID: {}", arg)))
+		.collect::<Result<Vec<_>, _>>()?;
+
+	if pids.is_empty() {
+		return Err(String::from("no process IDs given"));
+	}
+
+	Ok(KillArgs::Signal(signal, pids))
+}
+
+fn main() -> ExitCode {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let (signal, pids) = match parse_args(&args) {
+		Ok(KillArgs::Signal(signal, pids)) => (signal, pids),
+		Ok(KillArgs::List) => {
+			let names: Vec<_> = Signal::iterator()
+				.map(|signal| format!("{:2}) {}", signal as i32, &signal.as_str()[3..]))
+				.collect();
+			println!("{}", names.join("\n"));
+			return ExitCode::SUCCESS;
+		}
+		Ok(KillArgs::Help) => {
+			println!("{}", USAGE);
+			return ExitCode::SUCCESS;
+		}
+		Err(e) => {
+			eprintln!("kill: {}\n{}", e, USAGE);
+			return ExitCode::FAILURE;
+		}
+	};
+
+	let mut code = ExitCode::SUCCESS;
+	for pid in pids {
+		// A negative PID is passed straight to kill(2), which signals the process group.
+		if let Err(e) = kill(Pid::from_raw(pid), signal) {
+			eprintln!("kill: ({}): {}", pid, e.desc());
+			code = ExitCode::FAILURE;
+		}
+	}
+
+	code
+}
+
+#[cfg(test)]
+mod test {
+	use nix::sys::signal::Signal;
+
+	use super::{parse_args, parse_signal, KillArgs};
+
+	fn args(args: &[&str]) -> Vec<String> {
+		args.iter().map(|arg| arg.to_string()).collect()
+	}
+
+	#[test]
+	fn test_parse_signal() {
+		assert_eq!(parse_signal("9"), Ok(Some(Signal::SIGKILL)));
+		assert_eq!(parse_signal("TERM"), Ok(Some(Signal::SIGTERM)));
+		assert_eq!(parse_signal("sighup"), Ok(Some(Signal::SIGHUP)));
+		assert_eq!(parse_signal("0"), Ok(None));
+		assert!(parse_signal("FOO").is_err());
+		assert!(parse_signal("200").is_err());
+	}
+
+	#[test]
+	fn test_parse_args() {
+		assert_eq!(
+			parse_args(&args(&["1234"])),
+			Ok(KillArgs::Signal(Some(Signal::SIGTERM), vec![1234]))
+		);
+		assert_eq!(
+			parse_args(&args(&["-9", "1234", "5678"])),
+			Ok(KillArgs::Signal(Some(Signal::SIGKILL), vec![1234, 5678]))
+		);
+		assert_eq!(
+			parse_args(&args(&["-s", "INT", "-1234"])),
+			Ok(KillArgs::Signal(Some(Signal::SIGINT), vec![-1234]))
+		);
+		assert_eq!(
+			parse_args(&args(&["-TERM", "-1234"])),
+			Ok(KillArgs::Signal(Some(Signal::SIGTERM), vec![-1234]))
+		);
+		assert_eq!(
+			parse_args(&args(&["--", "-1234"])),
+			Ok(KillArgs::Signal(Some(Signal::SIGTERM), vec![-1234]))
+		);
+		assert_eq!(parse_args(&args(&["-l"])), Ok(KillArgs::List));
+
+		assert!(parse_args(&args(&[])).is_err());
+		assert!(parse_args(&args(&["-9"])).is_err());
+		assert!(parse_args(&args(&["abc"])).is_err());
+	}
+}
@@ -1,12 +1,18 @@
 use std::{
 	collections::HashMap,
+	fs,
 	io::{stderr, Cursor, ErrorKind},
 	path::{Path, PathBuf},
+	sync::Arc,
 };
 
 use bytestruct::{Endian, ReadFromWithEndian};
-use clap::{Arg, Command};
-use loggerd::{control::ReadStreamOpts, DEFAULT_CONTROL_SOCKET_PATH, KV};
+use clap::{value_parser, Arg, ArgAction, Command};
+use loggerd::{
+	control::{current_boot_id, Filter, ReadStreamOpts, BOOT_ID_FIELD},
+	crypto::{LogKey, DEFAULT_KEY_PATH},
+	OpenLogFile, DEFAULT_CONTROL_SOCKET_PATH, KV,
+};
 use slog::{error, Logger};
 use tokio::io::{self, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 
@@ -49,12 +55,51 @@ async fn main() {
 						.default_value("text")
 						.help("The format to write logs in"),
 				)
+				.arg(Arg::new("filter").short('f').long("filter").num_args(0..).help(
+					"Values to filter by, e.g. `IDENTIFIER=sshd` or `duration_ms>100`. Numbers compare numerically",
+				))
+				.arg(
+					Arg::new("field")
+						.long("field")
+						.num_args(1)
+						.action(ArgAction::Append)
+						.help("Only read entries that have the field with exactly the value, e.g. `IDENTIFIER=sshd`"),
+				)
+				.arg(
+					Arg::new("boot")
+						.short('b')
+						.long("boot")
+						.action(ArgAction::SetTrue)
+						.help("Only read entries from the current boot"),
+				)
+				.arg(
+					Arg::new("limit")
+						.short('n')
+						.long("limit")
+						.num_args(1)
+						.value_parser(value_parser!(usize))
+						.help("The most entries to read"),
+				)
+				.arg(
+					Arg::new("reverse")
+						.short('r')
+						.long("reverse")
+						.action(ArgAction::SetTrue)
+						.help("Read the newest entries first, so that --limit reads the newest entries"),
+				)
+				.arg(
+					Arg::new("data-dir")
+						.short('d')
+						.long("data-dir")
+						.num_args(1)
+						.help("Read the log files in the given directory directly, rather than through loggerd"),
+				)
 				.arg(
-					Arg::new("filter")
-						.short('f')
-						.long("filter")
-						.num_args(0..)
-						.help("Values to filter by"),
+					Arg::new("key-file")
+						.long("key-file")
+						.num_args(1)
+						.default_value(DEFAULT_KEY_PATH)
+						.help("The key to decrypt encrypted log files with, when reading them directly"),
 				),
 		)
 		.subcommand_required(true)
@@ -101,22 +146,48 @@ async fn main() {
 			}
 
 			if let Some(filters) = read_matches.get_many::<String>("filter") {
-				let mut kv_filters = Vec::new();
+				let mut parsed_filters = Vec::new();
 				for filter in filters.into_iter() {
-					if let Some((k, v)) = filter.split_once("=") {
-						kv_filters.push(KV {
-							key: k.to_owned(),
-							value: v.to_owned(),
-						})
-					} else {
-						error!(logger, "Failed to validate filters: {}", filter);
+					match filter.parse::<Filter>() {
+						Ok(filter) => parsed_filters.push(filter),
+						Err(e) => {
+							error!(logger, "Failed to validate filters: {}", e);
+							return;
+						}
+					}
+				}
+
+				opts = opts.with_filters(parsed_filters);
+			}
+
+			for field in read_matches.get_many::<String>("field").into_iter().flatten() {
+				match field.split_once('=') {
+					Some((key, value)) if !key.is_empty() => {
+						opts = opts.with_field(key.to_owned(), value.to_owned());
+					}
+					_ => {
+						error!(logger, "Invalid field, expected `key=value`"; "field" => field);
 						return;
 					}
 				}
+			}
+
+			if read_matches.get_flag("boot") {
+				match current_boot_id() {
+					Some(boot_id) => opts = opts.with_field(BOOT_ID_FIELD.to_owned(), boot_id),
+					None => {
+						error!(logger, "Failed to read the ID of the current boot");
+						return;
+					}
+				}
+			}
 
-				opts = opts.with_filters(kv_filters);
+			if let Some(limit) = read_matches.get_one::<usize>("limit") {
+				opts = opts.with_limit(*limit);
 			}
 
+			opts = opts.with_reverse(read_matches.get_flag("reverse"));
+
 			let log_format = read_matches.get_one::<String>("format").map_or("text", |s| s.as_str());
 
 			let log_format = match OutputLogFormat::try_from(log_format) {
@@ -127,7 +198,15 @@ async fn main() {
 				}
 			};
 
-			start_read_stream(logger, &socket_path, opts, log_format).await;
+			match read_matches.get_one::<String>("data-dir") {
+				Some(data_dir) => {
+					// The key is only readable by root, so other users can still read any unencrypted files.
+					let key_file: &String = read_matches.get_one("key-file").expect("key-file has a default");
+					let key = LogKey::load(Path::new(key_file)).ok().map(Arc::new);
+					read_data_dir(logger, Path::new(data_dir), key, opts, log_format).await;
+				}
+				None => start_read_stream(logger, &socket_path, opts, log_format).await,
+			}
 		}
 		_ => {
 			unreachable!("Subcommand is required")
@@ -147,7 +226,7 @@ fn validate_kvs(kvs: &Vec<String>) -> Result<Vec<KV>, String> {
 
 		result.push(KV {
 			key: key.to_string(),
-			value: value.to_string(),
+			value: value.into(),
 		});
 	}
 
@@ -269,3 +348,65 @@ async fn start_read_stream(logger: Logger, socket_path: &Path, opts: ReadStreamO
 		println!("{}", format.format_log(&msg));
 	}
 }
+
+/// Reads the logs from the log files in the given directory, decrypting them with the given key. Encrypted files are
+/// skipped if there is no key.
+async fn read_data_dir(
+	logger: Logger,
+	data_dir: &Path,
+	key: Option<Arc<LogKey>>,
+	opts: ReadStreamOpts,
+	format: OutputLogFormat,
+) {
+	let entries = match fs::read_dir(data_dir) {
+		Ok(entries) => entries,
+		Err(e) => {
+			error!(logger, "Failed to read data dir"; "path" => data_dir.display(), "error" => e.to_string());
+			return;
+		}
+	};
+
+	let mut log_files = Vec::new();
+	for entry in entries.flatten() {
+		if !entry.file_type().is_ok_and(|t| t.is_file()) {
+			continue;
+		}
+
+		match OpenLogFile::open_read_only(&entry.path(), key.clone()) {
+			Ok(file) if file.header.is_encrypted() && key.is_none() => {
+				error!(logger, "Skipping encrypted log file, as the key isn't readable"; "path" => entry.path().display());
+			}
+			Ok(file) if !opts.overlaps(file.header.time_min, file.header.time_max) => {}
+			Ok(file) => log_files.push(file),
+			Err(e) => {
+				error!(logger, "Failed to open log file"; "path" => entry.path().display(), "error" => e.to_string());
+			}
+		}
+	}
+
+	log_files.sort_by_key(|f| f.header.time_min);
+
+	let mut streams = Vec::new();
+	for file in log_files {
+		let path = file.path.clone();
+		let stream = file.read_log_stream(opts.clone()).await;
+
+		// Stop reading a file at its first error, without giving up on the rest.
+		let mut failed = false;
+		streams.push(stream.map_while(move |log| {
+			if failed {
+				return None;
+			}
+
+			failed = log.is_err();
+			Some(log.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e))))
+		}));
+	}
+
+	for log in opts.select(streams.into_iter().flatten()) {
+		match log {
+			Ok(log) => println!("{}", format.format_log(&log.to_map())),
+			Err(e) => error!(logger, "Failed to read log file"; "error" => e.to_string()),
+		}
+	}
+}
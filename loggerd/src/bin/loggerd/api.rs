@@ -1,14 +1,29 @@
-use std::path::{Path, PathBuf};
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
 
 use anyhow::{Context, Result};
 use futures::future::join_all;
-use loggerd::{control, LogMessage, OpenLogFile};
-use slog::error;
+use loggerd::{
+	control::{self, BOOT_ID_FIELD, MONOTONIC_TIME_FIELD},
+	crypto::LogKey,
+	limits::EntryLimits,
+	reorder::ReorderBuffer,
+	LogMessage, OpenLogFile, KV,
+};
+use nix::time::{clock_gettime, ClockId};
+use slog::{error, warn};
 use tokio::{
 	fs, io,
-	sync::{mpsc, Mutex},
+	sync::{mpsc, oneshot, Mutex},
+	time::{sleep_until, Instant},
 };
 
+/// A request to flush the logs to disk, answered once they are.
+type SyncRequest = oneshot::Sender<io::Result<()>>;
+
 pub struct Api {
 	logger: slog::Logger,
 	/// The pipe that the API receives logs over to write them to disk.
@@ -17,17 +32,46 @@ pub struct Api {
 	/// The pipe that producers can write logs to.
 	log_stream_write: mpsc::Sender<LogMessage>,
 
+	/// The pipe that the API receives requests to flush the logs to disk over.
+	sync_requests_read: Mutex<mpsc::Receiver<SyncRequest>>,
+	sync_requests_write: mpsc::Sender<SyncRequest>,
+
 	data_dir: PathBuf,
+
+	/// The limits that are enforced on entries before they are written.
+	limits: EntryLimits,
+
+	/// The key that new log files are encrypted with, if encryption is enabled.
+	key: Option<Arc<LogKey>>,
+
+	/// How long entries are held for so that ones that arrive out of order can be written in timestamp order.
+	reorder_window: Duration,
+
+	/// The ID of the current boot, which entries are tagged with, if the kernel exposes it.
+	boot_id: Option<String>,
 }
 
 impl Api {
-	pub fn new(data_dir: &Path, logger: slog::Logger) -> Self {
+	pub fn new(
+		data_dir: &Path,
+		limits: EntryLimits,
+		key: Option<LogKey>,
+		reorder_window: Duration,
+		logger: slog::Logger,
+	) -> Self {
 		let (sender, receiver) = mpsc::channel(1024);
+		let (sync_sender, sync_receiver) = mpsc::channel(64);
 		Self {
 			logger,
 			log_stream_read: Mutex::new(receiver),
 			log_stream_write: sender,
+			sync_requests_read: Mutex::new(sync_receiver),
+			sync_requests_write: sync_sender,
 			data_dir: data_dir.to_path_buf(),
+			limits,
+			key: key.map(Arc::new),
+			reorder_window,
+			boot_id: control::current_boot_id(),
 		}
 	}
 
@@ -38,8 +82,17 @@ impl Api {
 		while let Ok(Some(entry)) = log_file_files.next_entry().await {
 			let file_type = entry.file_type().await?;
 			if file_type.is_file() {
-				match OpenLogFile::open(&entry.path()).await {
-					Ok(file) => open_log_files.push(file),
+				match OpenLogFile::open(&entry.path(), self.key.clone()).await {
+					Ok(file) if file.header.is_encrypted() && self.key.is_none() => {
+						warn!(self.logger, "skipping encrypted log file, as no key was given"; "path" => entry.path().display());
+					}
+					Ok(file) => {
+						if file.is_damaged() {
+							warn!(self.logger, "cut off damaged entries at the end of log file"; "path" => entry.path().display());
+						}
+
+						open_log_files.push(file)
+					}
 					Err(e) => {
 						error!(self.logger, "Failed to open log file: {}", e);
 					}
@@ -63,25 +116,85 @@ impl Api {
 			.load_log_files()
 			.await
 			.with_context(|| "failed to load existing log files")?;
-		let last_log_file = match log_files.last_mut() {
-			Some(file) => file,
-			None => {
+
+		// Start a new file if encryption has been turned on or off since the last one was written.
+		let encrypted = self.key.is_some();
+		let mut last_log_file = match log_files.pop() {
+			Some(file) if file.header.is_encrypted() == encrypted => file,
+			_ => {
 				let log_file_path = self.data_dir.join(new_random_log_file_name());
-				let new_log_file = OpenLogFile::new(&log_file_path)
+				OpenLogFile::new(&log_file_path, self.key.clone())
 					.await
-					.with_context(|| "failed to open new log file")?;
-				log_files.push(new_log_file);
-				log_files.last_mut().unwrap()
+					.with_context(|| "failed to open new log file")?
 			}
 		};
 
 		let mut log_stream = self.log_stream_read.lock().await;
+		let mut sync_requests = self.sync_requests_read.lock().await;
+		let mut reorder = ReorderBuffer::new(self.reorder_window);
 		loop {
-			let message = log_stream.recv().await.unwrap();
-			last_log_file.write_log(message).await?;
+			let deadline = reorder.deadline();
+			tokio::select! {
+				message = log_stream.recv() => {
+					reorder.push(self.stamp(message.unwrap()), std::time::Instant::now());
+				}
+				_ = sleep_until(deadline.map(Instant::from_std).unwrap_or_else(Instant::now)), if deadline.is_some() => {}
+				Some(reply) = sync_requests.recv() => {
+					// Everything that was sent before the sync was asked for is already queued, so has to be written
+					// before the file is flushed.
+					while let Ok(message) = log_stream.try_recv() {
+						reorder.push(self.stamp(message), std::time::Instant::now());
+					}
+
+					for message in reorder.drain() {
+						self.write_log(&mut last_log_file, message).await?;
+					}
+
+					let _ = reply.send(last_log_file.sync().await);
+					continue;
+				}
+			}
+
+			for message in reorder.pop_ready(std::time::Instant::now()) {
+				self.write_log(&mut last_log_file, message).await?;
+			}
 		}
 	}
 
+	/// Tags the message with the time on the monotonic clock that it was received at, and the boot it was received in,
+	/// so that entries can be ordered even if the wall clock is stepped.
+	fn stamp(&self, mut message: LogMessage) -> LogMessage {
+		if let Ok(now) = clock_gettime(ClockId::CLOCK_MONOTONIC) {
+			let micros = Duration::from(now).as_micros() as i64;
+			message.fields.push(KV::new(MONOTONIC_TIME_FIELD.to_owned(), micros));
+		}
+
+		if let Some(boot_id) = &self.boot_id {
+			message.fields.push(KV::new(BOOT_ID_FIELD.to_owned(), boot_id.clone()));
+		}
+
+		message
+	}
+
+	/// Writes the message to the log file, once it has been checked against the limits.
+	async fn write_log(&self, log_file: &mut OpenLogFile, message: LogMessage) -> io::Result<()> {
+		match self.limits.apply(message) {
+			Ok(message) => log_file.write_log(message).await,
+			Err(e) => {
+				warn!(self.logger, "dropping log entry"; "error" => e.to_string());
+				Ok(())
+			}
+		}
+	}
+
+	/// Waits until everything that has been sent to the log stream so far has been written to disk.
+	pub async fn sync(&self) -> io::Result<()> {
+		let stopped = || io::Error::other("the log writer has stopped");
+		let (reply, synced) = oneshot::channel();
+		self.sync_requests_write.send(reply).await.map_err(|_| stopped())?;
+		synced.await.map_err(|_| stopped())?
+	}
+
 	pub async fn write_log_stream(&self) -> mpsc::Sender<LogMessage> {
 		self.log_stream_write.clone()
 	}
@@ -91,9 +204,13 @@ impl Api {
 		&self,
 		opts: control::ReadStreamOpts,
 	) -> Result<impl Iterator<Item = io::Result<LogMessage>>> {
-		let log_files = self.load_log_files().await?;
-		let future = join_all(log_files.into_iter().map(|f| f.read_log_stream(opts.clone()))).await;
-		Ok(future.into_iter().flatten())
+		let log_files = self
+			.load_log_files()
+			.await?
+			.into_iter()
+			.filter(|f| opts.overlaps(f.header.time_min, f.header.time_max));
+		let future = join_all(log_files.map(|f| f.read_log_stream(opts.clone()))).await;
+		Ok(opts.select(future.into_iter().flatten()))
 	}
 }
 
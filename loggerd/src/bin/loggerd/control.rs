@@ -1,15 +1,31 @@
-use std::sync::Arc;
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
 
 use anyhow::Result;
-use control::listen::{Action, ActionFactory};
+use auth::LoginSession;
+use common::obs;
+use control::{
+	listen::{Action, ActionFactory, RequestContext},
+	protocol::{ErrorKind, ErrorReply},
+};
+use futures::FutureExt;
 use loggerd::{
-	control::{ReadStreamOpts, ReadStreamOptsParseError, START_READ_STREAM_ACTION, START_WRITE_STREAM_ACTION},
+	coalesce::Coalescer,
+	control::{
+		encode_ack, AckMode, InvalidAckMode, LoggerdRequest, ReadStreamOpts, ReadStreamOptsParseError, StructuredEntry,
+		ACK_HEADER, COALESCE_HEADER, LOGINUID_FIELD, REQUEST_ID_FIELD, SESSION_ID_FIELD, START_READ_STREAM_ACTION,
+		START_WRITE_STREAM_ACTION, STRUCTURED_HEADER, TRUSTED_FIELDS,
+	},
 	LogMessage, KV,
 };
+use slog::warn;
 use thiserror::Error;
 use tokio::{
 	io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt},
-	net::unix::UCred,
+	sync::mpsc,
+	time::{self, sleep_until},
 };
 
 use crate::api::Api;
@@ -22,6 +38,29 @@ pub enum ControlError {
 
 	#[error("failed to read log stream: {0}")]
 	InvalidReadOpts(#[from] ReadStreamOptsParseError),
+
+	#[error("failed to start write stream: {0}")]
+	InvalidAckMode(#[from] InvalidAckMode),
+
+	#[error("failed to start write stream: invalid coalescing window: {0}")]
+	InvalidCoalesceWindow(String),
+
+	#[error("failed to start write stream: invalid structured flag: {0}")]
+	InvalidStructured(String),
+}
+
+impl From<ControlError> for ErrorReply {
+	fn from(value: ControlError) -> Self {
+		let kind = match value {
+			ControlError::UnknownAction => ErrorKind::UnknownRequest,
+			ControlError::InvalidReadOpts(_)
+			| ControlError::InvalidAckMode(_)
+			| ControlError::InvalidCoalesceWindow(_)
+			| ControlError::InvalidStructured(_) => ErrorKind::InvalidRequest,
+		};
+
+		ErrorReply::new(kind, value)
+	}
 }
 
 /// A controller for handling control actions.
@@ -38,35 +77,96 @@ impl Controller {
 
 impl ActionFactory for Controller {
 	type Action = ControlAction;
+	type Request = LoggerdRequest;
 
 	fn build(&self, action: &str, args: &[(&str, &str)]) -> Result<Self::Action, <Self::Action as Action>::Error> {
 		match action {
 			_ if action == START_WRITE_STREAM_ACTION => {
+				let ack = args
+					.iter()
+					.find(|kv| kv.0 == ACK_HEADER)
+					.map(|kv| kv.1.parse())
+					.transpose()?;
+
+				let coalesce = args
+					.iter()
+					.find(|kv| kv.0 == COALESCE_HEADER)
+					.map(|kv| {
+						kv.1.parse()
+							.map_err(|_| ControlError::InvalidCoalesceWindow(kv.1.to_owned()))
+					})
+					.transpose()?;
+
+				let structured = args
+					.iter()
+					.find(|kv| kv.0 == STRUCTURED_HEADER)
+					.map(|kv| {
+						kv.1.parse()
+							.map_err(|_| ControlError::InvalidStructured(kv.1.to_owned()))
+					})
+					.transpose()?
+					.unwrap_or(false);
+
+				let headers = ["ACTION", ACK_HEADER, COALESCE_HEADER, STRUCTURED_HEADER];
 				let fields = args
 					.iter()
 					.filter_map(|kv| match kv.0 {
-						key if key != "ACTION" => Some(KV {
+						key if !headers.contains(&key) && !TRUSTED_FIELDS.contains(&key) => Some(KV {
 							key: kv.0.to_owned(),
-							value: kv.1.to_owned(),
+							value: kv.1.into(),
 						}),
 						_ => None,
 					})
 					.collect();
 
-				Ok(ControlAction::StartWriteStream(self.api.clone(), fields))
+				self.build_request(LoggerdRequest::StartWriteStream {
+					fields,
+					ack,
+					coalesce,
+					structured,
+				})
 			}
 			_ if action == START_READ_STREAM_ACTION => {
 				let opts = ReadStreamOpts::from_kvs(args)?;
-				Ok(ControlAction::StartReadStream(self.api.clone(), opts))
+				self.build_request(LoggerdRequest::StartReadStream { opts })
 			}
 			_ => Err(ControlError::UnknownAction),
 		}
 	}
+
+	fn build_request(&self, request: LoggerdRequest) -> Result<Self::Action, <Self::Action as Action>::Error> {
+		match request {
+			LoggerdRequest::StartWriteStream {
+				fields,
+				ack,
+				coalesce,
+				structured,
+			} => {
+				if let Some(ack) = ack {
+					ack.validate()?;
+				}
+
+				let coalesce = match coalesce {
+					Some(0) => return Err(ControlError::InvalidCoalesceWindow(String::from("0"))),
+					coalesce => coalesce.map(|window| Duration::from_secs(u64::from(window))),
+				};
+
+				Ok(ControlAction::StartWriteStream(
+					self.api.clone(),
+					fields,
+					ack,
+					coalesce,
+					structured,
+				))
+			}
+			LoggerdRequest::StartReadStream { opts } => Ok(ControlAction::StartReadStream(self.api.clone(), opts)),
+		}
+	}
 }
 
 /// A control action that can be run by the controller.
 pub enum ControlAction {
-	StartWriteStream(Arc<Api>, Vec<KV>),
+	StartWriteStream(Arc<Api>, Vec<KV>, Option<AckMode>, Option<Duration>, bool),
 	StartReadStream(Arc<Api>, ReadStreamOpts),
 }
 
@@ -75,18 +175,44 @@ impl Action for ControlAction {
 
 	async fn run<R: AsyncBufRead + Unpin + Send + 'static, W: AsyncWrite + Unpin + Send + 'static>(
 		self,
-		_peer: UCred,
+		ctx: RequestContext,
 		reader: R,
 		writer: W,
 	) -> Result<(), Self::Error> {
 		match self {
-			ControlAction::StartWriteStream(api, fields) => {
-				let handler = WriteStreamHandler::new(reader, api, fields);
-				tokio::spawn(handler.run());
+			ControlAction::StartWriteStream(api, mut fields, ack, coalesce, structured) => {
+				fields.push(KV {
+					key: REQUEST_ID_FIELD.to_owned(),
+					value: ctx.id.to_string().into(),
+				});
+
+				// Entries are tagged with the login session of the writer, so that they can be traced back to the login
+				// that caused them.
+				if let Some(session) = ctx.peer.pid().and_then(LoginSession::of_process) {
+					fields.push(KV {
+						key: LOGINUID_FIELD.to_owned(),
+						value: session.loginuid.to_string().into(),
+					});
+					fields.push(KV {
+						key: SESSION_ID_FIELD.to_owned(),
+						value: session.session_id.to_string().into(),
+					});
+				}
+
+				let handler = WriteStreamHandler::new(
+					reader,
+					writer,
+					api,
+					fields,
+					ack,
+					coalesce.map(Coalescer::new),
+					structured,
+				);
+				tokio::spawn(obs::with_logger(ctx.logger, handler.run()));
 			}
 			ControlAction::StartReadStream(api, opts) => {
 				let handler = ReadStreamHandler::new(writer, api, opts);
-				tokio::spawn(handler.run());
+				tokio::spawn(obs::with_logger(ctx.logger, handler.run()));
 			}
 		};
 		Ok(())
@@ -94,35 +220,147 @@ impl Action for ControlAction {
 }
 
 /// A handler for streaming logs into a log file.
-struct WriteStreamHandler<R: AsyncBufRead> {
+struct WriteStreamHandler<R: AsyncBufRead, W: AsyncWrite> {
 	stream: R,
+
+	/// Where acknowledgements are written back to the writer.
+	acks: W,
 	api: Arc<Api>,
 	fields: Vec<KV>,
+
+	/// When entries are acknowledged, if the writer asked for them to be.
+	ack: Option<AckMode>,
+
+	/// What collapses repeated entries, if the writer asked for them to be.
+	coalescer: Option<Coalescer>,
+
+	/// Whether every line is a `StructuredEntry`, rather than a plain message.
+	structured: bool,
 }
 
-impl<R: AsyncBufRead + Unpin + Send> WriteStreamHandler<R> {
-	fn new(stream: R, api: Arc<Api>, fields: Vec<KV>) -> Self {
-		Self { stream, api, fields }
+impl<R: AsyncBufRead + Unpin + Send, W: AsyncWrite + Unpin + Send> WriteStreamHandler<R, W> {
+	fn new(
+		stream: R,
+		acks: W,
+		api: Arc<Api>,
+		fields: Vec<KV>,
+		ack: Option<AckMode>,
+		coalescer: Option<Coalescer>,
+		structured: bool,
+	) -> Self {
+		Self {
+			stream,
+			acks,
+			api,
+			fields,
+			ack,
+			coalescer,
+			structured,
+		}
 	}
 
 	async fn run(mut self) -> Result<()> {
 		let log_stream = self.api.write_log_stream().await;
 
+		// The number of entries that have been written, and the number of the last one that has been acknowledged.
+		let mut written = 0;
+		let mut acked = 0;
+
+		// A line that's only been partly read is kept between reads, in case the read is interrupted by having to write
+		// a coalesced entry.
+		let mut buffer = vec![];
 		loop {
-			let mut buffer = vec![];
-			let len = self.stream.read_until(b'\n', &mut buffer).await?;
-			if len == 0 {
-				break;
-			}
+			let deadline = self.coalescer.as_ref().and_then(Coalescer::deadline);
+			let held = sleep_until(deadline.map_or_else(time::Instant::now, time::Instant::from_std));
+			let read = tokio::select! {
+				read = self.stream.read_until(b'\n', &mut buffer) => Some(read?),
+				_ = held, if deadline.is_some() => None,
+			};
 
-			let message = LogMessage {
-				timestamp: chrono::Utc::now(),
-				fields: self.fields.clone(),
-				message: String::from_utf8_lossy(&buffer[0..len - 1]).to_string(),
+			let len = match read {
+				Some(0) => break,
+				Some(len) => len,
+				None => {
+					// No repeats came in time, so the held entry is written.
+					self.flush_coalesced(&log_stream).await?;
+					continue;
+				}
 			};
 
+			let message = self.parse_line(&buffer[0..len - 1]);
+			buffer.clear();
+
+			let ready = match self.coalescer.as_mut() {
+				Some(coalescer) => coalescer.push(message, Instant::now()),
+				None => Some(message),
+			};
+
+			if let Some(message) = ready {
+				log_stream.send(message).await?;
+			}
+
+			written += 1;
+
+			if self.is_ack_due(written) {
+				self.flush_coalesced(&log_stream).await?;
+				self.acknowledge(written).await?;
+				acked = written;
+			}
+		}
+
+		self.flush_coalesced(&log_stream).await?;
+
+		// Whatever is left is acknowledged once the writer has finished.
+		if self.ack.is_some() && acked < written {
+			self.acknowledge(written).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Turns a line of the stream into a log message. Lines of structured streams that aren't valid entries are
+	/// written as plain messages, so that nothing is lost.
+	fn parse_line(&self, line: &[u8]) -> LogMessage {
+		let now = chrono::Utc::now();
+		if self.structured {
+			match serde_json::from_slice::<StructuredEntry>(line) {
+				Ok(entry) => return entry.into_message(&self.fields, now),
+				Err(e) => warn!(obs::current(), "invalid structured entry"; "error" => e.to_string()),
+			}
+		}
+
+		LogMessage {
+			timestamp: now,
+			fields: self.fields.clone(),
+			message: String::from_utf8_lossy(line).to_string(),
+		}
+	}
+
+	/// Writes the entry that's being held in case it's repeated, if there is one.
+	async fn flush_coalesced(&mut self, log_stream: &mpsc::Sender<LogMessage>) -> Result<()> {
+		if let Some(message) = self.coalescer.as_mut().and_then(Coalescer::flush) {
 			log_stream.send(message).await?;
 		}
+
+		Ok(())
+	}
+
+	/// Whether the entries up to the given one should be acknowledged now.
+	fn is_ack_due(&mut self, written: u64) -> bool {
+		match self.ack {
+			None => false,
+			Some(AckMode::Every(entries)) => written.is_multiple_of(u64::from(entries)),
+			// A batch ends when there's nothing more to read straight away.
+			Some(AckMode::Batch) => {
+				!matches!(self.stream.fill_buf().now_or_never(), Some(Ok(buffer)) if !buffer.is_empty())
+			}
+		}
+	}
+
+	/// Waits for the entries up to the given one to be on disk, and then tells the writer that they are.
+	async fn acknowledge(&mut self, sequence: u64) -> Result<()> {
+		self.api.sync().await?;
+		self.acks.write_all(&encode_ack(sequence)).await?;
 		Ok(())
 	}
 }
@@ -142,7 +380,7 @@ impl<W: AsyncWrite + Unpin + Send + 'static> ReadStreamHandler<W> {
 		let iter = match self.api.read_logs(self.opts.clone()).await {
 			Ok(iter) => iter,
 			Err(e) => {
-				eprintln!("Failed to read logs: {}", e);
+				warn!(obs::current(), "failed to read logs"; "error" => e.to_string());
 				return Err(e);
 			}
 		};
@@ -151,7 +389,7 @@ impl<W: AsyncWrite + Unpin + Send + 'static> ReadStreamHandler<W> {
 			let log = match log {
 				Ok(log) => log,
 				Err(e) => {
-					eprintln!("Failed to read log: {}", e);
+					warn!(obs::current(), "failed to read log"; "error" => e.to_string());
 					break;
 				}
 			};
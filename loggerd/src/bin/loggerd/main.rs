@@ -1,14 +1,24 @@
 mod api;
 mod control;
+mod sources;
 
 use ::control::listen::ControlSocket;
 use api::Api;
-use loggerd::DEFAULT_CONTROL_SOCKET_PATH;
-use std::{io::stderr, path::PathBuf, sync::Arc};
+use loggerd::{
+	crypto::LogKey,
+	kmsg::DEFAULT_KMSG_PATH,
+	limits::{EntryLimits, DEFAULT_MAX_FIELDS, DEFAULT_MAX_KEY_SIZE, DEFAULT_MAX_VALUE_SIZE},
+	syslog::DEFAULT_SYSLOG_SOCKET_PATH,
+	DEFAULT_CONTROL_SOCKET_PATH,
+};
+use std::{io::stderr, path::PathBuf, sync::Arc, time::Duration};
 
-use clap::{Arg, Command};
-use common::{obs::assemble_logger, qinit::mark_running};
-use slog::{error, info};
+use clap::{value_parser, Arg, ArgAction, Command};
+use common::{
+	obs::{self, assemble_logger},
+	qinit::mark_running,
+};
+use slog::{error, info, o};
 
 use crate::control::Controller;
 
@@ -34,18 +44,102 @@ async fn main() {
 				.num_args(1)
 				.help("The directory to store log files in"),
 		)
+		.arg(
+			Arg::new("max-fields")
+				.long("max-fields")
+				.num_args(1)
+				.value_parser(value_parser!(usize))
+				.help("The maximum number of fields a log entry can have"),
+		)
+		.arg(
+			Arg::new("max-key-size")
+				.long("max-key-size")
+				.num_args(1)
+				.value_parser(value_parser!(usize))
+				.help("The maximum size of a field key, in bytes"),
+		)
+		.arg(
+			Arg::new("max-value-size")
+				.long("max-value-size")
+				.num_args(1)
+				.value_parser(value_parser!(usize))
+				.help("The maximum size of a field value, in bytes. Longer values are truncated"),
+		)
+		.arg(
+			Arg::new("reorder-window-ms")
+				.long("reorder-window-ms")
+				.num_args(1)
+				.default_value("250")
+				.value_parser(value_parser!(u64))
+				.help("How long to hold entries for so that ones that arrive out of order are written in order. 0 disables reordering"),
+		)
+		.arg(
+			Arg::new("key-file")
+				.long("key-file")
+				.num_args(1)
+				.help("Encrypt log files with the key in the given file, which must only be readable by root"),
+		)
+		.arg(
+			Arg::new("syslog-path")
+				.default_value(DEFAULT_SYSLOG_SOCKET_PATH)
+				.long("syslog-path")
+				.num_args(1)
+				.help("The path to the syslog socket to listen on"),
+		)
+		.arg(
+			Arg::new("no-syslog")
+				.long("no-syslog")
+				.action(ArgAction::SetTrue)
+				.help("Don't listen for syslog messages"),
+		)
+		.arg(
+			Arg::new("kmsg-path")
+				.default_value(DEFAULT_KMSG_PATH)
+				.long("kmsg-path")
+				.num_args(1)
+				.help("The path to the kernel log device to import messages from"),
+		)
+		.arg(
+			Arg::new("no-kmsg")
+				.long("no-kmsg")
+				.action(ArgAction::SetTrue)
+				.help("Don't import kernel messages"),
+		)
 		.get_matches();
 
 	let logger = assemble_logger(stderr());
+	obs::install_panic_hook();
 	let listen_path: &String = matches.get_one("listen-path").unwrap();
 	let listen_path = PathBuf::from(listen_path);
 	let data_dir: &String = matches.get_one("data-dir").unwrap();
 	let data_dir = PathBuf::from(data_dir);
+	let limits = EntryLimits::new(
+		matches.get_one("max-fields").copied().unwrap_or(DEFAULT_MAX_FIELDS),
+		matches.get_one("max-key-size").copied().unwrap_or(DEFAULT_MAX_KEY_SIZE),
+		matches
+			.get_one("max-value-size")
+			.copied()
+			.unwrap_or(DEFAULT_MAX_VALUE_SIZE),
+	);
+
+	let reorder_window = Duration::from_millis(*matches.get_one::<u64>("reorder-window-ms").unwrap());
+
+	let key = match matches.get_one::<String>("key-file") {
+		Some(path) => match LogKey::load(&PathBuf::from(path)) {
+			Ok(key) => Some(key),
+			Err(e) => {
+				error!(logger, "failed to load encryption key"; "path" => path, "error" => e.to_string());
+				return;
+			}
+		},
+		None => None,
+	};
+
 	info!(logger, "Listening on {}", listen_path.display());
 
-	let api = Arc::new(Api::new(&data_dir, logger.clone()));
+	let api = Arc::new(Api::new(&data_dir, limits, key, reorder_window, logger.clone()));
 
-	let control = match ControlSocket::open(&listen_path, Controller::new(api.clone())) {
+	let control = match ControlSocket::open(&listen_path, Controller::new(api.clone()), logger.clone()) {
 		Ok(socket) => socket,
 		Err(e) => {
 			error!(logger, "failed to open control socket"; "path" => listen_path.display(), "error" => e.to_string());
@@ -53,14 +147,36 @@ async fn main() {
 		}
 	};
 
+	if !matches.get_flag("no-syslog") {
+		let syslog_path = PathBuf::from(matches.get_one::<String>("syslog-path").unwrap());
+		let log_stream = api.write_log_stream().await;
+		obs::spawn(o!("source" => "syslog"), async move {
+			if let Err(e) = sources::listen_syslog(&syslog_path, log_stream).await {
+				error!(obs::current(), "failed to listen for syslog messages"; "path" => syslog_path.display(), "error" => e.to_string());
+			}
+		});
+	}
+
+	if !matches.get_flag("no-kmsg") {
+		let kmsg_path = PathBuf::from(matches.get_one::<String>("kmsg-path").unwrap());
+		let log_stream = api.write_log_stream().await;
+		let logger = logger.new(o!("source" => "kmsg"));
+		tokio::task::spawn_blocking(move || {
+			if let Err(e) = sources::read_kmsg(&kmsg_path, log_stream, logger.clone()) {
+				error!(logger, "failed to read kernel messages"; "path" => kmsg_path.display(), "error" => e.to_string());
+			}
+		});
+	}
+
+	if let Err(e) = control.shutdown_handle().shutdown_on_signals() {
+		error!(logger, "failed to handle SIGTERM, so loggerd won't shut down cleanly"; "error" => e.to_string());
+	}
+
 	mark_running().expect("marked running");
 
 	tokio::select! {
-		_ = tokio::signal::ctrl_c() => {
-			info!(logger, "Shutting down");
-		}
 		_ = control.listen() => {
-			error!(logger, "Control socket failed");
+			info!(logger, "Shutting down");
 		},
 		err = api.run() => {
 			if let Err(e) = err {
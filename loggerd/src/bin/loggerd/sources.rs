@@ -0,0 +1,82 @@
+use std::{
+	fs::{self, File, Permissions},
+	io::{self, Read},
+	os::unix::fs::PermissionsExt,
+	path::Path,
+};
+
+use chrono::{DateTime, Utc};
+use loggerd::{
+	kmsg::{KmsgRecord, MAX_KMSG_RECORD_SIZE},
+	syslog::parse_syslog_message,
+	LogMessage,
+};
+use nix::{errno::Errno, time::clock_gettime, time::ClockId};
+use slog::{warn, Logger};
+use tokio::{net::UnixDatagram, sync::mpsc};
+
+/// The maximum size of a syslog datagram that we will accept. Anything longer is truncated.
+const MAX_SYSLOG_MESSAGE_SIZE: usize = 8192;
+
+/// Listens for syslog datagrams on the unix socket at the given path, forwarding them into the log stream.
+pub async fn listen_syslog(path: &Path, log_stream: mpsc::Sender<LogMessage>) -> io::Result<()> {
+	if path.exists() {
+		fs::remove_file(path)?;
+	}
+
+	let socket = UnixDatagram::bind(path)?;
+
+	// Every process on the system should be able to log.
+	fs::set_permissions(path, Permissions::from_mode(0o666))?;
+
+	let mut buffer = vec![0; MAX_SYSLOG_MESSAGE_SIZE];
+	loop {
+		let len = socket.recv(&mut buffer).await?;
+		let message = parse_syslog_message(&String::from_utf8_lossy(&buffer[..len]), Utc::now());
+		if log_stream.send(message).await.is_err() {
+			return Ok(());
+		}
+	}
+}
+
+/// Reads kernel messages from the kmsg device at the given path, forwarding them into the log stream.
+/// This blocks, so should be run with `spawn_blocking`.
+pub fn read_kmsg(path: &Path, log_stream: mpsc::Sender<LogMessage>, logger: Logger) -> io::Result<()> {
+	let mut file = File::open(path)?;
+	let boot_time = boot_time()?;
+
+	// Each read from /dev/kmsg returns exactly one record.
+	let mut buffer = vec![0; MAX_KMSG_RECORD_SIZE];
+	loop {
+		let len = match file.read(&mut buffer) {
+			Ok(0) => return Ok(()),
+			Ok(len) => len,
+			// EPIPE indicates that records were overwritten before we could read them. The next read continues
+			// from the oldest record that is still available.
+			Err(e) if e.raw_os_error() == Some(Errno::EPIPE as i32) => {
+				warn!(logger, "kernel messages were lost before they could be read");
+				continue;
+			}
+			Err(e) => return Err(e),
+		};
+
+		let record = String::from_utf8_lossy(&buffer[..len]);
+		let record = match KmsgRecord::parse(&record) {
+			Some(record) => record,
+			None => {
+				warn!(logger, "failed to parse kernel message"; "record" => record.to_string());
+				continue;
+			}
+		};
+
+		if log_stream.blocking_send(record.into_log_message(boot_time)).is_err() {
+			return Ok(());
+		}
+	}
+}
+
+/// Returns the wall clock time that the system booted at, which kmsg timestamps are relative to.
+fn boot_time() -> io::Result<DateTime<Utc>> {
+	let uptime = clock_gettime(ClockId::CLOCK_MONOTONIC)?;
+	Ok(Utc::now() - std::time::Duration::from(uptime))
+}
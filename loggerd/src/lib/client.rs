@@ -0,0 +1,450 @@
+use std::{
+	collections::VecDeque,
+	fmt,
+	io::Write,
+	os::unix::net::UnixStream,
+	path::{Path, PathBuf},
+	sync::{Arc, Condvar, Mutex, MutexGuard},
+	thread,
+	time::Duration,
+};
+
+use chrono::Utc;
+use slog::{Key, Level, Never, OwnedKVList, Record, Serializer, KV as _};
+
+use crate::{
+	control::{start_structured_write_stream_sync, StructuredEntry},
+	syslog::Priority,
+	value::Value,
+	KV,
+};
+
+/// The default number of entries that are held while loggerd can't be reached, before the oldest are dropped.
+pub const DEFAULT_BUFFER_SIZE: usize = 4096;
+
+/// The field that entries are tagged with, holding the module that they were logged from.
+pub const MODULE_FIELD: &str = "MODULE";
+
+/// The field that the entry about dropped entries is tagged with, holding how many were dropped.
+pub const DROPPED_FIELD: &str = "DROPPED";
+
+/// The syslog facility of entries, as they come from system daemons.
+const DAEMON_FACILITY: u8 = 3;
+
+/// How long to wait before reconnecting to loggerd, doubling on every failure up to the maximum.
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// How long dropping the drain waits for the entries that are held to be sent.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The entries that are waiting to be sent to loggerd.
+struct Buffer {
+	entries: VecDeque<StructuredEntry>,
+	capacity: usize,
+
+	/// The number of entries that have been dropped since the last time that was reported.
+	dropped: u64,
+
+	/// Whether the sender is writing entries that have been taken out of the buffer.
+	sending: bool,
+
+	/// Whether the drain has been dropped, so the sender should stop once the buffer is empty.
+	closed: bool,
+}
+
+impl Buffer {
+	/// Adds an entry to the end of the buffer, dropping the oldest entry if it's full.
+	fn push(&mut self, entry: StructuredEntry) {
+		if self.entries.len() >= self.capacity {
+			self.entries.pop_front();
+			self.dropped += 1;
+		}
+
+		self.entries.push_back(entry);
+	}
+
+	/// Puts entries that couldn't be sent back at the front of the buffer, dropping the oldest of them if they don't
+	/// all fit.
+	fn restore(&mut self, entries: Vec<StructuredEntry>) {
+		let space = self.capacity.saturating_sub(self.entries.len());
+		let skip = entries.len().saturating_sub(space);
+		self.dropped += skip as u64;
+		for entry in entries.into_iter().skip(skip).rev() {
+			self.entries.push_front(entry);
+		}
+	}
+
+	/// Takes every entry out of the buffer, preceded by an entry saying how many were dropped, if any were.
+	fn take(&mut self) -> Vec<StructuredEntry> {
+		let mut entries = Vec::with_capacity(self.entries.len() + 1);
+		if self.dropped > 0 {
+			let mut fields = Priority {
+				facility: DAEMON_FACILITY,
+				severity: severity(Level::Warning),
+			}
+			.fields();
+			fields.push(KV::new(String::from(DROPPED_FIELD), self.dropped as i64));
+			entries.push(StructuredEntry {
+				timestamp: Some(Utc::now()),
+				message: format!("dropped {} log entries that couldn't be sent to loggerd", self.dropped),
+				fields,
+			});
+			self.dropped = 0;
+		}
+
+		entries.extend(self.entries.drain(..));
+		entries
+	}
+}
+
+/// The state shared between the drain and the thread that sends its entries.
+struct Shared {
+	buffer: Mutex<Buffer>,
+
+	/// Notified when entries are added, when a batch of them has been sent, and when the drain is dropped.
+	changed: Condvar,
+}
+
+impl Shared {
+	fn lock(&self) -> MutexGuard<'_, Buffer> {
+		// The buffer is always left consistent, so a panic while it was held doesn't matter.
+		self.buffer.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+/// An slog drain that writes records to loggerd as structured entries, so that daemons can log straight to it rather
+/// than writing JSON to stderr. Every entry is tagged with the fields of the drain, its syslog priority (in
+/// `PRIORITY` and `SEVERITY`), the module that it was logged from (in `MODULE_FIELD`), and the key-values of the
+/// record and its logger, keeping their types.
+///
+/// Logging never blocks on loggerd: records are held in a buffer that a background thread sends from, reconnecting
+/// to loggerd with a backoff if it can't be reached. If the buffer fills up, the oldest entries are dropped, and an
+/// entry saying how many were dropped is written once loggerd can be reached again.
+pub struct Drain {
+	shared: Arc<Shared>,
+}
+
+impl Drain {
+	/// Creates a drain that writes to the loggerd listening on the control socket at the given path, tagging every
+	/// entry with the given fields, e.g. an `IDENTIFIER`.
+	pub fn new<P: Into<PathBuf>>(socket_path: P, fields: Vec<KV>) -> Self {
+		let shared = Arc::new(Shared {
+			buffer: Mutex::new(Buffer {
+				entries: VecDeque::new(),
+				capacity: DEFAULT_BUFFER_SIZE,
+				dropped: 0,
+				sending: false,
+				closed: false,
+			}),
+			changed: Condvar::new(),
+		});
+
+		let sender = Sender {
+			shared: shared.clone(),
+			socket_path: socket_path.into(),
+			fields,
+		};
+		thread::spawn(move || sender.run());
+
+		Self { shared }
+	}
+
+	/// Sets the number of entries that are held while loggerd can't be reached, before the oldest are dropped.
+	pub fn with_buffer_size(self, size: usize) -> Self {
+		self.shared.lock().capacity = size.max(1);
+		self
+	}
+}
+
+impl slog::Drain for Drain {
+	type Ok = ();
+	type Err = Never;
+
+	fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+		let entry = to_entry(record, values);
+		self.shared.lock().push(entry);
+		self.shared.changed.notify_all();
+		Ok(())
+	}
+}
+
+impl Drop for Drain {
+	fn drop(&mut self) {
+		let mut buffer = self.shared.lock();
+		buffer.closed = true;
+		self.shared.changed.notify_all();
+
+		// Give the sender a chance to get the last entries out, without holding up exiting if loggerd is gone.
+		let _ = self.shared.changed.wait_timeout_while(buffer, FLUSH_TIMEOUT, |buffer| {
+			!buffer.entries.is_empty() || buffer.sending
+		});
+	}
+}
+
+/// Sends the entries of a drain to loggerd, from a thread of its own.
+struct Sender {
+	shared: Arc<Shared>,
+	socket_path: PathBuf,
+	fields: Vec<KV>,
+}
+
+impl Sender {
+	fn run(self) {
+		let mut stream = None;
+		let mut delay = MIN_RECONNECT_DELAY;
+		loop {
+			let (entries, closed) = {
+				let mut buffer = self.shared.lock();
+				buffer = self
+					.shared
+					.changed
+					.wait_while(buffer, |buffer| {
+						buffer.entries.is_empty() && buffer.dropped == 0 && !buffer.closed
+					})
+					.unwrap_or_else(|e| e.into_inner());
+				if buffer.entries.is_empty() && buffer.closed {
+					return;
+				}
+
+				buffer.sending = true;
+				(buffer.take(), buffer.closed)
+			};
+
+			let sent = self.send(&mut stream, &entries);
+			let mut buffer = self.shared.lock();
+			buffer.sending = false;
+			if sent.is_err() {
+				stream = None;
+
+				// Nothing is waiting for entries once the drain has gone, so don't try again.
+				if !closed {
+					buffer.restore(entries);
+				}
+			}
+			self.shared.changed.notify_all();
+			drop(buffer);
+
+			match sent {
+				Ok(()) => delay = MIN_RECONNECT_DELAY,
+				Err(_) if closed => return,
+				Err(_) => {
+					thread::sleep(delay);
+					delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+				}
+			}
+		}
+	}
+
+	/// Writes the entries to loggerd, connecting to it first if there isn't a connection.
+	fn send(&self, stream: &mut Option<UnixStream>, entries: &[StructuredEntry]) -> std::io::Result<()> {
+		let mut lines = Vec::new();
+		for entry in entries {
+			serde_json::to_writer(&mut lines, entry)?;
+			lines.push(b'\n');
+		}
+
+		let stream = match stream {
+			Some(stream) => stream,
+			None => stream.insert(start_structured_write_stream_sync(
+				Path::new(&self.socket_path),
+				self.fields.clone(),
+			)?),
+		};
+
+		stream.write_all(&lines)
+	}
+}
+
+/// The syslog severity of an slog level.
+fn severity(level: Level) -> u8 {
+	match level {
+		Level::Critical => 2,
+		Level::Error => 3,
+		Level::Warning => 4,
+		Level::Info => 6,
+		Level::Debug | Level::Trace => 7,
+	}
+}
+
+/// Turns a record, along with the key-values of its logger, into the entry that's sent to loggerd.
+fn to_entry(record: &Record, values: &OwnedKVList) -> StructuredEntry {
+	let mut fields = Priority {
+		facility: DAEMON_FACILITY,
+		severity: severity(record.level()),
+	}
+	.fields();
+	fields.push(KV::new(String::from(MODULE_FIELD), record.module().to_owned()));
+
+	// Serializing into a vector can't fail.
+	let mut serializer = FieldSerializer(fields);
+	let _ = record.kv().serialize(record, &mut serializer);
+	let _ = values.serialize(record, &mut serializer);
+
+	StructuredEntry {
+		timestamp: Some(Utc::now()),
+		message: record.msg().to_string(),
+		fields: serializer.0,
+	}
+}
+
+/// Collects key-values into fields, keeping the types of values that loggerd has types for.
+struct FieldSerializer(Vec<KV>);
+
+impl FieldSerializer {
+	fn push(&mut self, key: Key, value: impl Into<Value>) -> slog::Result {
+		self.0.push(KV::new(key.to_string(), value));
+		Ok(())
+	}
+}
+
+macro_rules! emit_int {
+	($($method:ident: $ty:ty),*) => {
+		$(
+			fn $method(&mut self, key: Key, value: $ty) -> slog::Result {
+				match i64::try_from(value) {
+					Ok(value) => self.push(key, value),
+					Err(_) => self.push(key, value.to_string()),
+				}
+			}
+		)*
+	};
+}
+
+impl Serializer for FieldSerializer {
+	fn emit_arguments(&mut self, key: Key, value: &fmt::Arguments) -> slog::Result {
+		self.push(key, value.to_string())
+	}
+
+	fn emit_str(&mut self, key: Key, value: &str) -> slog::Result {
+		self.push(key, value.to_owned())
+	}
+
+	fn emit_bool(&mut self, key: Key, value: bool) -> slog::Result {
+		self.push(key, value)
+	}
+
+	fn emit_f32(&mut self, key: Key, value: f32) -> slog::Result {
+		self.push(key, f64::from(value))
+	}
+
+	fn emit_f64(&mut self, key: Key, value: f64) -> slog::Result {
+		self.push(key, value)
+	}
+
+	emit_int!(
+		emit_i8: i8,
+		emit_i16: i16,
+		emit_i32: i32,
+		emit_i64: i64,
+		emit_isize: isize,
+		emit_u8: u8,
+		emit_u16: u16,
+		emit_u32: u32,
+		emit_u64: u64,
+		emit_usize: usize
+	);
+}
+
+#[cfg(test)]
+mod test {
+	use std::sync::{Arc, Mutex};
+
+	use chrono::Utc;
+	use slog::{info, o, warn, Logger, Never, OwnedKVList, Record};
+
+	use super::{to_entry, Buffer};
+	use crate::{
+		control::{StructuredEntry, REQUEST_ID_FIELD},
+		value::Value,
+		KV,
+	};
+
+	struct Capture(Arc<Mutex<Vec<StructuredEntry>>>);
+
+	impl slog::Drain for Capture {
+		type Ok = ();
+		type Err = Never;
+
+		fn log(&self, record: &Record, values: &OwnedKVList) -> Result<(), Never> {
+			self.0.lock().unwrap().push(to_entry(record, values));
+			Ok(())
+		}
+	}
+
+	fn field<'a>(fields: &'a [KV], key: &str) -> Option<&'a Value> {
+		fields.iter().find(|kv| kv.key == key).map(|kv| &kv.value)
+	}
+
+	#[test]
+	fn test_to_entry() {
+		let entries = Arc::new(Mutex::new(Vec::new()));
+		let logger = Logger::root(Capture(entries.clone()), o!("component" => "test"));
+		info!(logger, "hello"; "count" => 3_u32, "ratio" => 0.5, "ok" => true, "huge" => u64::MAX);
+		warn!(logger, "spoofed"; REQUEST_ID_FIELD => "1234");
+
+		let entries = entries.lock().unwrap();
+		let fields = &entries[0].fields;
+		assert_eq!(entries[0].message, "hello");
+		assert_eq!(field(fields, "PRIORITY"), Some(&Value::String(String::from("info"))));
+		assert_eq!(field(fields, "SEVERITY"), Some(&Value::Int(6)));
+		assert_eq!(
+			field(fields, "MODULE"),
+			Some(&Value::String(String::from(module_path!())))
+		);
+		assert_eq!(field(fields, "count"), Some(&Value::Int(3)));
+		assert_eq!(field(fields, "ratio"), Some(&Value::Float(0.5)));
+		assert_eq!(field(fields, "ok"), Some(&Value::Bool(true)));
+		assert_eq!(field(fields, "huge"), Some(&Value::String(u64::MAX.to_string())));
+		assert_eq!(field(fields, "component"), Some(&Value::String(String::from("test"))));
+
+		// The entry survives the trip through loggerd, but can't set the fields that loggerd sets itself.
+		let line = serde_json::to_string(&entries[1]).unwrap();
+		let entry: StructuredEntry = serde_json::from_str(&line).unwrap();
+		let stream_fields = vec![KV::new(String::from("IDENTIFIER"), "test")];
+		let message = entry.into_message(&stream_fields, Utc::now());
+		assert_eq!(message.message, "spoofed");
+		assert_eq!(message.timestamp, entries[1].timestamp.unwrap());
+		assert_eq!(
+			field(&message.fields, "IDENTIFIER"),
+			Some(&Value::String(String::from("test")))
+		);
+		assert_eq!(
+			field(&message.fields, "PRIORITY"),
+			Some(&Value::String(String::from("warning")))
+		);
+		assert_eq!(field(&message.fields, REQUEST_ID_FIELD), None);
+	}
+
+	#[test]
+	fn test_buffer_drops_oldest() {
+		let entry = |message: &str| StructuredEntry {
+			timestamp: None,
+			message: message.to_owned(),
+			fields: Vec::new(),
+		};
+		let mut buffer = Buffer {
+			entries: Default::default(),
+			capacity: 3,
+			dropped: 0,
+			sending: false,
+			closed: false,
+		};
+
+		for message in ["one", "two", "three", "four"] {
+			buffer.push(entry(message));
+		}
+		let taken = buffer.take();
+		let messages: Vec<&str> = taken.iter().map(|entry| entry.message.as_str()).collect();
+		assert_eq!(messages[1..], ["two", "three", "four"]);
+		assert_eq!(field(&taken[0].fields, "DROPPED"), Some(&Value::Int(1)));
+		assert_eq!(buffer.take().len(), 0);
+
+		// Entries that couldn't be sent go back in front of the ones logged since, as far as there's room.
+		buffer.push(entry("five"));
+		buffer.restore(vec![entry("two"), entry("three"), entry("four")]);
+		let messages: Vec<String> = buffer.entries.iter().map(|entry| entry.message.clone()).collect();
+		assert_eq!(messages, ["three", "four", "five"]);
+		assert_eq!(buffer.dropped, 1);
+	}
+}
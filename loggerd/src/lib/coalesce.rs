@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+use crate::{control::REPEAT_COUNT_FIELD, LogMessage, KV};
+
+/// An entry that's being held back, in case the same message is written again.
+struct Pending {
+	message: LogMessage,
+
+	/// How many times the message has been written, including the first.
+	count: i64,
+
+	/// When the message was first written, which the window starts from.
+	first_seen: Instant,
+}
+
+/// Collapses identical consecutive messages from a single write stream into one entry, so that floods of the same
+/// message don't fill the disk. Repeats of a message within the window of it first being written are counted rather
+/// than written, and the entry is tagged with how many times it was written (if that's more than once) in the
+/// `REPEAT_COUNT_FIELD`.
+///
+/// Entries are held until they can't be repeated any more: a different message is written, the window ends, or the
+/// stream is flushed, so an entry can be written up to a window late.
+pub struct Coalescer {
+	window: Duration,
+	pending: Option<Pending>,
+}
+
+impl Coalescer {
+	pub fn new(window: Duration) -> Self {
+		Self { window, pending: None }
+	}
+
+	/// Adds a message that was written at the given time, returning the entry that's ready to be written because of
+	/// it, if there is one.
+	pub fn push(&mut self, message: LogMessage, now: Instant) -> Option<LogMessage> {
+		if let Some(pending) = self.pending.as_mut() {
+			if pending.message.message == message.message && now < pending.first_seen + self.window {
+				pending.count += 1;
+				return None;
+			}
+		}
+
+		let ready = self.flush();
+		self.pending = Some(Pending {
+			message,
+			count: 1,
+			first_seen: now,
+		});
+
+		ready
+	}
+
+	/// When the entry that's being held has to be written by, if one is.
+	pub fn deadline(&self) -> Option<Instant> {
+		self.pending.as_ref().map(|pending| pending.first_seen + self.window)
+	}
+
+	/// Takes the entry that's being held, if there is one, tagged with how many times it was written.
+	pub fn flush(&mut self) -> Option<LogMessage> {
+		let Pending { mut message, count, .. } = self.pending.take()?;
+		if count > 1 {
+			message.fields.push(KV::new(REPEAT_COUNT_FIELD.to_owned(), count));
+		}
+
+		Some(message)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::{Duration, Instant};
+
+	use chrono::Utc;
+
+	use super::Coalescer;
+	use crate::{control::REPEAT_COUNT_FIELD, value::Value, LogMessage};
+
+	fn message(text: &str) -> LogMessage {
+		LogMessage::new(Utc::now(), Vec::new(), text.to_owned())
+	}
+
+	fn repeat_count(message: &LogMessage) -> Option<&Value> {
+		message
+			.fields
+			.iter()
+			.find(|kv| kv.key == REPEAT_COUNT_FIELD)
+			.map(|kv| &kv.value)
+	}
+
+	#[test]
+	fn test_coalesce() {
+		let start = Instant::now();
+		let mut coalescer = Coalescer::new(Duration::from_secs(5));
+
+		assert!(coalescer.push(message("disk error"), start).is_none());
+		assert_eq!(coalescer.deadline(), Some(start + Duration::from_secs(5)));
+		for i in 1..1000 {
+			assert!(coalescer
+				.push(message("disk error"), start + Duration::from_millis(i))
+				.is_none());
+		}
+
+		// A different message ends the run of repeats.
+		let ready = coalescer
+			.push(message("recovered"), start + Duration::from_secs(1))
+			.unwrap();
+		assert_eq!(ready.message, "disk error");
+		assert_eq!(repeat_count(&ready), Some(&Value::Int(1000)));
+
+		// As does the window ending, and messages that weren't repeated aren't tagged.
+		let ready = coalescer
+			.push(message("recovered"), start + Duration::from_secs(6))
+			.unwrap();
+		assert_eq!(ready.message, "recovered");
+		assert_eq!(repeat_count(&ready), None);
+
+		let ready = coalescer.flush().unwrap();
+		assert_eq!(ready.message, "recovered");
+		assert!(coalescer.flush().is_none());
+		assert!(coalescer.deadline().is_none());
+	}
+}
@@ -1,14 +1,22 @@
-use std::{collections::HashMap, io::Write, os::unix::net::UnixStream, path::Path};
+use std::{
+	cmp::Ordering,
+	collections::VecDeque,
+	fmt::{self, Display, Formatter},
+	io::{ErrorKind, Read, Write},
+	net::Shutdown,
+	os::unix::net::UnixStream,
+	path::Path,
+	str::FromStr,
+};
 
 use bytestruct::{Endian, WriteToWithEndian};
 use chrono::{DateTime, Utc};
+use control::protocol::{self, DEFAULT_REQUEST_TIMEOUT};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tokio::{
-	io::{self, AsyncWriteExt},
-	net::UnixSocket,
-};
+use tokio::io;
 
-use crate::{LogMessage, KV};
+use crate::{value::Value, LogMessage, KV};
 
 pub const START_WRITE_STREAM_ACTION: &str = "start-write-stream";
 
@@ -18,44 +26,313 @@ const MIN_TIME_HEADER: &str = "_MIN_TIME";
 const MAX_TIME_HEADER: &str = "_MAX_TIME";
 const FOLLOW_HEADER: &str = "_FOLLOW";
 
+/// The header that asks for the entries of a write stream to be acknowledged, set to an `AckMode`.
+pub const ACK_HEADER: &str = "_ACK";
+
+/// The header that asks for identical consecutive entries of a write stream to be coalesced, set to the window (in
+/// seconds) that repeats are collapsed within.
+pub const COALESCE_HEADER: &str = "_COALESCE";
+
+/// The header that asks for the lines of a write stream to be read as structured entries, set to `true` or `false`.
+pub const STRUCTURED_HEADER: &str = "_STRUCTURED";
+
+/// The field that coalesced entries are tagged with, holding the number of times the message was written.
+pub const REPEAT_COUNT_FIELD: &str = "_REPEAT_COUNT";
+
+/// The field that entries written through a write stream are tagged with, holding the ID of the control request that
+/// opened the stream.
+pub const REQUEST_ID_FIELD: &str = "_REQUEST_ID";
+
+/// The field that entries written through a write stream are tagged with, holding the login UID of the session that
+/// the writer is part of, if it's part of one.
+pub const LOGINUID_FIELD: &str = "_LOGINUID";
+
+/// The field that entries written through a write stream are tagged with, holding the ID of the login session that the
+/// writer is part of, if it's part of one.
+pub const SESSION_ID_FIELD: &str = "_SESSION_ID";
+
+/// The field that every entry is tagged with, holding the time (in microseconds) on the monotonic clock that loggerd
+/// received it at. Unlike the timestamp, it never goes backwards when the wall clock is stepped, so it orders entries
+/// within a boot.
+pub const MONOTONIC_TIME_FIELD: &str = "_MONOTONIC_US";
+
+/// The field that every entry is tagged with, holding the ID of the boot that loggerd received it in, which its
+/// monotonic time is relative to.
+pub const BOOT_ID_FIELD: &str = "_BOOT_ID";
+
+/// The file that the kernel exposes the ID of the current boot in.
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+
+/// The fields that loggerd tags entries with itself, which writers can't set.
+pub const TRUSTED_FIELDS: [&str; 6] = [
+	REQUEST_ID_FIELD,
+	LOGINUID_FIELD,
+	SESSION_ID_FIELD,
+	REPEAT_COUNT_FIELD,
+	MONOTONIC_TIME_FIELD,
+	BOOT_ID_FIELD,
+];
+
+/// The requests that loggerd's control socket accepts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum LoggerdRequest {
+	/// Starts streaming logs into loggerd, with every line tagged with the given fields. If an ack mode is given,
+	/// loggerd tells the writer when its entries are on disk. If a coalescing window (in seconds) is given, identical
+	/// consecutive lines within it are written as a single entry. If the stream is structured, every line is a JSON
+	/// `StructuredEntry` rather than a plain message.
+	StartWriteStream {
+		fields: Vec<KV>,
+
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		ack: Option<AckMode>,
+
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		coalesce: Option<u32>,
+
+		#[serde(default)]
+		structured: bool,
+	},
+
+	/// Starts streaming the logs that match the given options out of loggerd.
+	StartReadStream { opts: ReadStreamOpts },
+}
+
+/// Reads the ID of the current boot, which entries are tagged with in `BOOT_ID_FIELD`.
+pub fn current_boot_id() -> Option<String> {
+	std::fs::read_to_string(BOOT_ID_PATH)
+		.ok()
+		.map(|id| id.trim().to_owned())
+}
+
 /// Starts a write stream with the given fields, returning the socket that can then be used
 /// to stream logs to a loggerd instance.
 pub async fn start_write_stream(socket_path: &Path, fields: Vec<KV>) -> io::Result<tokio::net::UnixStream> {
-	let mut conn = UnixSocket::new_stream()?.connect(socket_path).await?;
-	let fields_str = fields
-		.iter()
-		.map(|kv| format!("{}={}", kv.key, kv.value))
-		.collect::<Vec<_>>()
-		.join(" ");
+	let request = LoggerdRequest::StartWriteStream {
+		fields,
+		ack: None,
+		coalesce: None,
+		structured: false,
+	};
+	Ok(protocol::request(socket_path, &request, DEFAULT_REQUEST_TIMEOUT).await?)
+}
+
+pub fn start_write_stream_sync(socket_path: &Path, fields: Vec<KV>) -> std::io::Result<UnixStream> {
+	let request = LoggerdRequest::StartWriteStream {
+		fields,
+		ack: None,
+		coalesce: None,
+		structured: false,
+	};
+	Ok(protocol::request_sync(socket_path, &request, DEFAULT_REQUEST_TIMEOUT)?)
+}
 
-	let header_string = format!("ACTION={} {}\n", START_WRITE_STREAM_ACTION, fields_str);
-	conn.write_all(header_string.as_bytes()).await?;
+/// Starts a write stream with the given fields whose identical consecutive lines are written as a single entry, if
+/// they're written within the given number of seconds of each other.
+pub fn start_coalesced_write_stream_sync(
+	socket_path: &Path,
+	fields: Vec<KV>,
+	window: u32,
+) -> std::io::Result<UnixStream> {
+	let request = LoggerdRequest::StartWriteStream {
+		fields,
+		ack: None,
+		coalesce: Some(window),
+		structured: false,
+	};
+	Ok(protocol::request_sync(socket_path, &request, DEFAULT_REQUEST_TIMEOUT)?)
+}
 
-	Ok(conn)
+/// Starts a write stream with the given fields whose entries are acknowledged by loggerd once they are on disk.
+pub fn start_acked_write_stream_sync(
+	socket_path: &Path,
+	fields: Vec<KV>,
+	mode: AckMode,
+) -> std::io::Result<AckedWriteStream> {
+	let request = LoggerdRequest::StartWriteStream {
+		fields,
+		ack: Some(mode),
+		coalesce: None,
+		structured: false,
+	};
+	let stream = protocol::request_sync(socket_path, &request, DEFAULT_REQUEST_TIMEOUT)?;
+	Ok(AckedWriteStream::new(stream, mode))
 }
 
-pub fn start_write_stream_sync(socket_path: &Path, fields: Vec<KV>) -> std::io::Result<UnixStream> {
-	let mut conn = UnixStream::connect(socket_path)?;
-	let fields_str = fields
-		.iter()
-		.map(|kv| format!("{}={}", kv.key, kv.value))
-		.collect::<Vec<_>>()
-		.join(" ");
+/// Starts a write stream with the given fields whose lines are JSON `StructuredEntry`s, so that every entry can carry
+/// fields of its own.
+pub fn start_structured_write_stream_sync(socket_path: &Path, fields: Vec<KV>) -> std::io::Result<UnixStream> {
+	let request = LoggerdRequest::StartWriteStream {
+		fields,
+		ack: None,
+		coalesce: None,
+		structured: true,
+	};
+	Ok(protocol::request_sync(socket_path, &request, DEFAULT_REQUEST_TIMEOUT)?)
+}
+
+/// An entry written to a structured write stream, as a line of JSON. Its fields are added to the fields of the
+/// stream, and it's timestamped when loggerd reads it if it doesn't have a timestamp of its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StructuredEntry {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub timestamp: Option<DateTime<Utc>>,
+
+	pub message: String,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub fields: Vec<KV>,
+}
+
+impl StructuredEntry {
+	/// Turns the entry into a log message tagged with the fields of the stream, as well as its own. Writers can't set
+	/// the fields that loggerd sets itself, so those are dropped.
+	pub fn into_message(self, stream_fields: &[KV], now: DateTime<Utc>) -> LogMessage {
+		let mut fields = stream_fields.to_vec();
+		fields.extend(
+			self.fields
+				.into_iter()
+				.filter(|kv| !TRUSTED_FIELDS.contains(&kv.key.as_str())),
+		);
+
+		LogMessage::new(self.timestamp.unwrap_or(now), fields, self.message)
+	}
+}
+
+/// Encodes the acknowledgement that every entry up to and including the given one is on disk, as it is sent back over
+/// a write stream. Entries are numbered from 1, in the order that they were written.
+pub fn encode_ack(sequence: u64) -> [u8; 8] {
+	sequence.to_le_bytes()
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("invalid ack mode: {0}")]
+pub struct InvalidAckMode(pub String);
+
+/// When loggerd acknowledges the entries written through a write stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AckMode {
+	/// After every N entries, and when the stream is closed.
+	Every(u32),
+
+	/// Whenever loggerd has caught up with the writer, i.e. at the end of every batch of entries that are written
+	/// together.
+	Batch,
+}
+
+impl AckMode {
+	pub fn validate(&self) -> Result<(), InvalidAckMode> {
+		match self {
+			AckMode::Every(0) => Err(InvalidAckMode(self.to_string())),
+			_ => Ok(()),
+		}
+	}
+}
+
+impl FromStr for AckMode {
+	type Err = InvalidAckMode;
 
-	let header_string = format!("ACTION={} {}\n", START_WRITE_STREAM_ACTION, fields_str);
-	conn.write_all(header_string.as_bytes())?;
+	/// Parses either `batch`, or the number of entries to acknowledge at a time.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mode = match s {
+			"batch" => AckMode::Batch,
+			_ => AckMode::Every(s.parse().map_err(|_| InvalidAckMode(s.to_owned()))?),
+		};
 
-	Ok(conn)
+		mode.validate()?;
+		Ok(mode)
+	}
+}
+
+impl Display for AckMode {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			AckMode::Every(entries) => write!(f, "{}", entries),
+			AckMode::Batch => write!(f, "batch"),
+		}
+	}
+}
+
+/// A write stream whose entries loggerd acknowledges once they are on disk. Writers block until their entries have
+/// been acknowledged, so a writer that's faster than the disk is slowed down rather than having its entries lost.
+/// Waiting for an acknowledgement times out after `DEFAULT_REQUEST_TIMEOUT`.
+pub struct AckedWriteStream {
+	stream: UnixStream,
+	mode: AckMode,
+
+	/// The number of entries that have been written.
+	written: u64,
+
+	/// The number of the last entry that loggerd has acknowledged.
+	acked: u64,
+}
+
+impl AckedWriteStream {
+	fn new(stream: UnixStream, mode: AckMode) -> Self {
+		Self {
+			stream,
+			mode,
+			written: 0,
+			acked: 0,
+		}
+	}
+
+	/// Writes an entry to the stream. In `Every` mode, this blocks until the entry is on disk if it is the Nth one.
+	pub fn write_entry(&mut self, message: &str) -> std::io::Result<()> {
+		// Entries are separated by newlines, so a message with newlines in it would become several entries.
+		let line = format!("{}\n", message.replace('\n', " "));
+		self.stream.write_all(line.as_bytes())?;
+		self.written += 1;
+
+		match self.mode {
+			AckMode::Every(entries) if self.written.is_multiple_of(u64::from(entries)) => self.wait_for(self.written),
+			_ => Ok(()),
+		}
+	}
+
+	/// Blocks until the entries that have been written are on disk. In `Every` mode, entries after the last multiple
+	/// of N are only acknowledged once the stream is closed.
+	pub fn sync(&mut self) -> std::io::Result<()> {
+		let sequence = match self.mode {
+			AckMode::Every(entries) => self.written - self.written % u64::from(entries),
+			AckMode::Batch => self.written,
+		};
+
+		self.wait_for(sequence)
+	}
+
+	/// Closes the stream, blocking until every entry that was written is on disk.
+	pub fn close(mut self) -> std::io::Result<()> {
+		self.stream.shutdown(Shutdown::Write)?;
+		self.wait_for(self.written)
+	}
+
+	/// Reads acknowledgements until the entry with the given number has been acknowledged.
+	fn wait_for(&mut self, sequence: u64) -> std::io::Result<()> {
+		while self.acked < sequence {
+			let mut ack = [0; 8];
+			match self.stream.read_exact(&mut ack) {
+				Ok(()) => self.acked = u64::from_le_bytes(ack),
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+					return Err(std::io::Error::new(
+						ErrorKind::UnexpectedEof,
+						format!("loggerd closed the stream before acknowledging entry {}", sequence),
+					));
+				}
+				Err(e) => return Err(e),
+			}
+		}
+
+		Ok(())
+	}
 }
 
 /// Starts a read stream with the given options, returning the socket that can then be used
 /// to read logs from a loggerd instance.
 pub async fn start_read_stream(socket_path: &Path, opts: ReadStreamOpts) -> io::Result<tokio::net::UnixStream> {
-	let mut conn = UnixSocket::new_stream()?.connect(socket_path).await?;
-	let header_string = format!("ACTION={} {}\n", START_READ_STREAM_ACTION, opts.to_header_string());
-	conn.write_all(header_string.as_bytes()).await?;
-
-	Ok(conn)
+	let request = LoggerdRequest::StartReadStream { opts };
+	Ok(protocol::request(socket_path, &request, DEFAULT_REQUEST_TIMEOUT).await?)
 }
 
 #[derive(Debug, Clone, Error)]
@@ -65,15 +342,128 @@ pub enum ReadStreamOptsParseError {
 
 	#[error("invalid follow: {0}")]
 	InvalidFollow(#[from] std::str::ParseBoolError),
+
+	#[error("invalid filter: {0}")]
+	InvalidFilter(String),
+}
+
+/// How a filter compares the value of a field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FilterOp {
+	#[default]
+	Eq,
+	Ne,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+}
+
+impl FilterOp {
+	/// The operators, in the order they have to be matched in so that e.g. `>=` isn't mistaken for `>`.
+	const OPERATORS: [(&'static str, FilterOp); 6] = [
+		("!=", FilterOp::Ne),
+		("<=", FilterOp::Le),
+		(">=", FilterOp::Ge),
+		("=", FilterOp::Eq),
+		("<", FilterOp::Lt),
+		(">", FilterOp::Gt),
+	];
+
+	fn as_str(&self) -> &'static str {
+		Self::OPERATORS
+			.iter()
+			.find(|(_, op)| op == self)
+			.map(|(s, _)| *s)
+			.expect("every operator has a string")
+	}
+
+	fn accepts(&self, ordering: Ordering) -> bool {
+		match self {
+			FilterOp::Eq => ordering.is_eq(),
+			FilterOp::Ne => ordering.is_ne(),
+			FilterOp::Lt => ordering.is_lt(),
+			FilterOp::Le => ordering.is_le(),
+			FilterOp::Gt => ordering.is_gt(),
+			FilterOp::Ge => ordering.is_ge(),
+		}
+	}
+}
+
+/// A filter on the value of a field, e.g. `IDENTIFIER=sshd` or `duration_ms>100`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Filter {
+	pub key: String,
+
+	/// The comparison to make, which is an equality check for clients that don't know about operators.
+	#[serde(default)]
+	pub op: FilterOp,
+	pub value: Value,
+}
+
+impl Filter {
+	pub fn new(key: String, op: FilterOp, value: Value) -> Self {
+		Self { key, op, value }
+	}
+
+	/// Whether the given value of the field passes the filter. See `Value::compare` for how values of different types
+	/// are compared.
+	pub fn matches(&self, value: &Value) -> bool {
+		value
+			.compare(&self.value)
+			.is_some_and(|ordering| self.op.accepts(ordering))
+	}
+}
+
+impl FromStr for Filter {
+	type Err = ReadStreamOptsParseError;
+
+	/// Parses a filter in the form `<key><op><value>`, where the type of the value is inferred.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let start = s
+			.find(['!', '<', '>', '='])
+			.ok_or_else(|| ReadStreamOptsParseError::InvalidFilter(s.to_owned()))?;
+		let (key, rest) = s.split_at(start);
+		let (op, value) = FilterOp::OPERATORS
+			.iter()
+			.find_map(|(prefix, op)| rest.strip_prefix(prefix).map(|value| (*op, value)))
+			.ok_or_else(|| ReadStreamOptsParseError::InvalidFilter(s.to_owned()))?;
+
+		if key.is_empty() {
+			return Err(ReadStreamOptsParseError::InvalidFilter(s.to_owned()));
+		}
+
+		Ok(Self::new(key.to_owned(), op, Value::parse(value)))
+	}
+}
+
+impl Display for Filter {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}{}{}", self.key, self.op.as_str(), self.value)
+	}
 }
 
 /// A Builder for the different ways you can filter a log stream.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReadStreamOpts {
 	min_time: Option<DateTime<Utc>>,
 	max_time: Option<DateTime<Utc>>,
-	filters: Option<Vec<KV>>,
+	filters: Option<Vec<Filter>>,
 	follow: bool,
+
+	/// Fields that entries must have, with exactly the given values. Unlike filters, entries without the field don't
+	/// match.
+	#[serde(default)]
+	fields: Vec<(String, String)>,
+
+	/// The most entries to read.
+	#[serde(default)]
+	limit: Option<usize>,
+
+	/// Whether to read the newest entries first. With a limit, this reads the newest entries, rather than the oldest.
+	#[serde(default)]
+	reverse: bool,
 }
 
 impl ReadStreamOpts {
@@ -83,6 +473,9 @@ impl ReadStreamOpts {
 			max_time: None,
 			filters: None,
 			follow: false,
+			fields: Vec::new(),
+			limit: None,
+			reverse: false,
 		}
 	}
 
@@ -103,10 +496,8 @@ impl ReadStreamOpts {
 					opts = opts.with_follow(value.parse()?);
 				}
 				key if key != "ACTION" => {
-					filters.push(KV {
-						key: key.to_string(),
-						value: value.to_string(),
-					});
+					// Headers are split on the first `=`, so put the filter back together to find its operator.
+					filters.push(format!("{}={}", key, value).parse()?);
 				}
 				_ => {}
 			}
@@ -125,7 +516,7 @@ impl ReadStreamOpts {
 		self
 	}
 
-	pub fn with_filters(mut self, filters: Vec<KV>) -> Self {
+	pub fn with_filters(mut self, filters: Vec<Filter>) -> Self {
 		self.filters = Some(filters);
 		self
 	}
@@ -135,15 +526,50 @@ impl ReadStreamOpts {
 		self
 	}
 
-	pub fn format_log(&self, log: &LogMessage) -> Vec<u8> {
-		let mut msg = HashMap::new();
-		msg.insert("__timestamp", log.timestamp.to_rfc3339());
-		msg.insert("__msg", log.message.clone());
-		for kv in log.fields.iter() {
-			msg.insert(&kv.key, kv.value.to_owned());
+	/// Only reads the entries that have the given field, with exactly the given value.
+	pub fn with_field(mut self, key: String, value: String) -> Self {
+		self.fields.push((key, value));
+		self
+	}
+
+	pub fn with_limit(mut self, limit: usize) -> Self {
+		self.limit = Some(limit);
+		self
+	}
+
+	pub fn with_reverse(mut self, reverse: bool) -> Self {
+		self.reverse = reverse;
+		self
+	}
+
+	/// Applies the limit and order of the options to the matching entries, which are given oldest first.
+	pub fn select<'a, I>(&self, entries: I) -> Box<dyn Iterator<Item = io::Result<LogMessage>> + Send + 'a>
+	where
+		I: Iterator<Item = io::Result<LogMessage>> + Send + 'a,
+	{
+		let limit = self.limit.unwrap_or(usize::MAX);
+		if !self.reverse {
+			return Box::new(entries.take(limit));
+		}
+
+		// The newest entries are at the end, so everything has to be read to find them, but only the ones that are
+		// going to be returned have to be kept.
+		let mut newest = VecDeque::new();
+		for entry in entries {
+			if newest.len() == limit {
+				newest.pop_front();
+			}
+
+			if limit > 0 {
+				newest.push_back(entry);
+			}
 		}
 
-		let log = serde_json::to_string(&msg).expect("failed to format log");
+		Box::new(newest.into_iter().rev())
+	}
+
+	pub fn format_log(&self, log: &LogMessage) -> Vec<u8> {
+		let log = serde_json::to_string(&log.to_map()).expect("failed to format log");
 		let log_bytes = log.as_bytes();
 		let frame = log.len() as u32;
 
@@ -157,6 +583,13 @@ impl ReadStreamOpts {
 		bytes
 	}
 
+	/// Whether any entries between the given times could match, so that files whose entries all fall outside the
+	/// requested times can be skipped.
+	pub fn overlaps(&self, time_min: DateTime<Utc>, time_max: DateTime<Utc>) -> bool {
+		self.min_time.is_none_or(|min_time| min_time <= time_max)
+			&& self.max_time.is_none_or(|max_time| max_time >= time_min)
+	}
+
 	pub fn matches(&self, log: &LogMessage) -> bool {
 		if let Some(min_time) = self.min_time {
 			if log.timestamp < min_time {
@@ -173,14 +606,18 @@ impl ReadStreamOpts {
 		if let Some(filters) = &self.filters {
 			for filter in filters {
 				if let Some(kv) = log.fields.iter().find(|f| f.key == filter.key) {
-					if kv.value != filter.value {
+					if !filter.matches(&kv.value) {
 						return false;
 					}
 				}
 			}
 		}
 
-		true
+		self.fields.iter().all(|(key, value)| {
+			log.fields
+				.iter()
+				.any(|kv| kv.key == *key && kv.value.to_string() == *value)
+		})
 	}
 
 	pub fn to_header_string(&self) -> String {
@@ -193,7 +630,7 @@ impl ReadStreamOpts {
 		}
 		if let Some(filters) = &self.filters {
 			for filter in filters {
-				parts.push(format!("{}={}", filter.key, filter.value));
+				parts.push(filter.to_string());
 			}
 		}
 		parts.push(format!("{}={}", FOLLOW_HEADER, self.follow));
@@ -206,3 +643,131 @@ impl Default for ReadStreamOpts {
 		Self::new()
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use std::{
+		io::{BufRead, BufReader, Write},
+		os::unix::net::UnixStream,
+		thread,
+	};
+
+	use chrono::Utc;
+
+	use super::{encode_ack, AckMode, AckedWriteStream, Filter, FilterOp, ReadStreamOpts};
+	use crate::{value::Value, LogMessage, KV};
+
+	#[test]
+	fn test_parse_filter() {
+		let filter: Filter = "duration_ms>=100".parse().unwrap();
+		assert_eq!(
+			filter,
+			Filter::new(String::from("duration_ms"), FilterOp::Ge, Value::Int(100))
+		);
+		assert_eq!(filter.to_string(), "duration_ms>=100");
+
+		let filter: Filter = "IDENTIFIER=sshd".parse().unwrap();
+		assert_eq!(filter.op, FilterOp::Eq);
+		assert_eq!(filter.value, Value::from("sshd"));
+
+		assert!("novalue".parse::<Filter>().is_err());
+		assert!(">3".parse::<Filter>().is_err());
+	}
+
+	#[test]
+	fn test_matches() {
+		let log = LogMessage::new(
+			Utc::now(),
+			vec![
+				KV::new(String::from("duration_ms"), 250),
+				KV::new(String::from("SEVERITY"), String::from("3")),
+			],
+			String::from("done"),
+		);
+
+		let opts =
+			|filters: &[&str]| ReadStreamOpts::new().with_filters(filters.iter().map(|f| f.parse().unwrap()).collect());
+		assert!(opts(&["duration_ms>100"]).matches(&log));
+		assert!(!opts(&["duration_ms<100"]).matches(&log));
+
+		// Strings that hold numbers compare numerically, so "3" < "10" even though it's lexically greater.
+		assert!(opts(&["SEVERITY<10"]).matches(&log));
+		assert!(opts(&["SEVERITY!=4", "duration_ms=250"]).matches(&log));
+
+		let opts = ReadStreamOpts::from_kvs(&[("duration_ms>", "250"), ("ACTION", "start-read-stream")]).unwrap();
+		assert!(opts.matches(&log));
+
+		// Fields have to be present, and match exactly.
+		let field = |key: &str, value: &str| ReadStreamOpts::new().with_field(key.to_owned(), value.to_owned());
+		assert!(field("SEVERITY", "3").matches(&log));
+		assert!(field("duration_ms", "250").matches(&log));
+		assert!(!field("SEVERITY", "03").matches(&log));
+		assert!(!field("_BOOT_ID", "3").matches(&log));
+	}
+
+	#[test]
+	fn test_select() {
+		let logs = || (0..5).map(|i| Ok(LogMessage::new(Utc::now(), Vec::new(), i.to_string())));
+		let messages =
+			|opts: ReadStreamOpts| -> Vec<String> { opts.select(logs()).map(|log| log.unwrap().message).collect() };
+
+		assert_eq!(messages(ReadStreamOpts::new()), vec!["0", "1", "2", "3", "4"]);
+		assert_eq!(messages(ReadStreamOpts::new().with_limit(2)), vec!["0", "1"]);
+		assert_eq!(
+			messages(ReadStreamOpts::new().with_reverse(true)),
+			vec!["4", "3", "2", "1", "0"]
+		);
+		assert_eq!(
+			messages(ReadStreamOpts::new().with_reverse(true).with_limit(2)),
+			vec!["4", "3"]
+		);
+		assert!(messages(ReadStreamOpts::new().with_reverse(true).with_limit(0)).is_empty());
+	}
+
+	#[test]
+	fn test_parse_ack_mode() {
+		assert_eq!("batch".parse::<AckMode>().unwrap(), AckMode::Batch);
+		assert_eq!("10".parse::<AckMode>().unwrap(), AckMode::Every(10));
+		assert!("0".parse::<AckMode>().is_err());
+		assert!("often".parse::<AckMode>().is_err());
+	}
+
+	#[test]
+	fn test_acked_write_stream() {
+		let (client, server) = UnixStream::pair().unwrap();
+
+		// Acknowledges every two entries, and everything that's left once the stream is closed.
+		let server = thread::spawn(move || {
+			let mut writer = server.try_clone().unwrap();
+			let mut lines = Vec::new();
+			for line in BufReader::new(server).lines() {
+				lines.push(line.unwrap());
+				if lines.len() % 2 == 0 {
+					writer.write_all(&encode_ack(lines.len() as u64)).unwrap();
+				}
+			}
+
+			writer.write_all(&encode_ack(lines.len() as u64)).unwrap();
+			lines
+		});
+
+		let mut stream = AckedWriteStream::new(client, AckMode::Every(2));
+		stream.write_entry("one").unwrap();
+		stream.write_entry("two").unwrap();
+		assert_eq!(stream.acked, 2);
+
+		stream.write_entry("three\nlines").unwrap();
+		stream.sync().unwrap();
+		assert_eq!(stream.acked, 2);
+
+		stream.close().unwrap();
+		assert_eq!(server.join().unwrap(), vec!["one", "two", "three lines"]);
+
+		// Closing without acknowledging everything is an error.
+		let (client, server) = UnixStream::pair().unwrap();
+		let mut stream = AckedWriteStream::new(client, AckMode::Batch);
+		stream.write_entry("lost").unwrap();
+		drop(server);
+		assert!(stream.close().is_err());
+	}
+}
@@ -0,0 +1,121 @@
+use std::{fmt, fs, io, os::unix::fs::MetadataExt, path::Path};
+
+use chacha20poly1305::{
+	aead::{Aead, KeyInit, Payload},
+	XChaCha20Poly1305, XNonce,
+};
+use thiserror::Error;
+
+/// The default path of the key that log files are encrypted with.
+pub const DEFAULT_KEY_PATH: &str = "/etc/loggerd/key";
+
+/// The size of the key, in bytes.
+pub const KEY_SIZE: usize = 32;
+
+/// The size of the nonce that is stored alongside every encrypted payload, in bytes.
+pub const NONCE_SIZE: usize = 24;
+
+#[derive(Debug, Error)]
+pub enum KeyError {
+	#[error("failed to read key: {0}")]
+	IOError(#[from] io::Error),
+
+	#[error("key file must be owned by root, but is owned by uid {0}")]
+	NotRootOwned(u32),
+
+	#[error("key file must only be accessible by its owner, but has mode {0:o}")]
+	InsecurePermissions(u32),
+
+	#[error("key must be {KEY_SIZE} bytes, but is {0} bytes")]
+	InvalidLength(usize),
+}
+
+/// A key used to encrypt and authenticate the payloads of log files.
+#[derive(Clone)]
+pub struct LogKey {
+	cipher: XChaCha20Poly1305,
+}
+
+impl LogKey {
+	pub fn new(key: &[u8; KEY_SIZE]) -> Self {
+		Self {
+			cipher: XChaCha20Poly1305::new(key.into()),
+		}
+	}
+
+	/// Loads the key from the given file, which must contain exactly `KEY_SIZE` raw bytes, be owned by root,
+	/// and not be accessible by anyone else.
+	pub fn load(path: &Path) -> Result<Self, KeyError> {
+		let metadata = fs::metadata(path)?;
+		if metadata.uid() != 0 {
+			return Err(KeyError::NotRootOwned(metadata.uid()));
+		}
+
+		if metadata.mode() & 0o077 != 0 {
+			return Err(KeyError::InsecurePermissions(metadata.mode() & 0o777));
+		}
+
+		let key = fs::read(path)?;
+		let key: &[u8; KEY_SIZE] = match key.as_slice().try_into() {
+			Ok(key) => key,
+			Err(_) => return Err(KeyError::InvalidLength(key.len())),
+		};
+
+		Ok(Self::new(key))
+	}
+
+	/// Encrypts the given plaintext under a fresh random nonce, authenticating it along with the associated data.
+	pub fn encrypt(&self, plaintext: &[u8], associated_data: &[u8]) -> io::Result<([u8; NONCE_SIZE], Vec<u8>)> {
+		let nonce: [u8; NONCE_SIZE] = rand::random();
+		let payload = Payload {
+			msg: plaintext,
+			aad: associated_data,
+		};
+
+		match self.cipher.encrypt(XNonce::from_slice(&nonce), payload) {
+			Ok(ciphertext) => Ok((nonce, ciphertext)),
+			Err(_) => Err(io::Error::other("failed to encrypt payload")),
+		}
+	}
+
+	/// Decrypts the given ciphertext, failing if it (or the associated data) has been tampered with, or was encrypted
+	/// with a different key.
+	pub fn decrypt(&self, nonce: &[u8; NONCE_SIZE], ciphertext: &[u8], associated_data: &[u8]) -> io::Result<Vec<u8>> {
+		let payload = Payload {
+			msg: ciphertext,
+			aad: associated_data,
+		};
+
+		self.cipher
+			.decrypt(XNonce::from_slice(nonce), payload)
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "failed to decrypt payload"))
+	}
+}
+
+impl fmt::Debug for LogKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("LogKey(..)")
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::LogKey;
+
+	#[test]
+	fn test_round_trip() {
+		let key = LogKey::new(&[7; 32]);
+		let (nonce, ciphertext) = key.encrypt(b"hello world", b"aad").unwrap();
+		assert_ne!(&ciphertext[..11], b"hello world");
+		assert_eq!(key.decrypt(&nonce, &ciphertext, b"aad").unwrap(), b"hello world");
+
+		// Changing the associated data, the ciphertext, or the key must all fail to decrypt.
+		assert!(key.decrypt(&nonce, &ciphertext, b"other").is_err());
+
+		let mut tampered = ciphertext.clone();
+		tampered[0] ^= 1;
+		assert!(key.decrypt(&nonce, &tampered, b"aad").is_err());
+
+		assert!(LogKey::new(&[8; 32]).decrypt(&nonce, &ciphertext, b"aad").is_err());
+	}
+}
@@ -1,11 +1,28 @@
-use bytestruct::{LengthPrefixedString, Padding, Size, WriteTo, WriteToWithEndian};
+use std::io::{self, ErrorKind, Read};
+
+use bytestruct::{Endian, LengthPrefixedString, Padding, ReadFromWithEndian, Size, WriteTo, WriteToWithEndian};
 use bytestruct_derive::{ByteStruct, Size};
 use chrono::{DateTime, Utc};
 
-const MAX_FIELD_SIZE: usize = 48000;
+use crate::{
+	crypto::NONCE_SIZE,
+	value::{Value, ValueType},
+};
+
+pub(crate) const MAX_FIELD_SIZE: usize = 48000;
 const VERSION: u8 = 1;
+
+/// The version of log files whose field payloads are encrypted. Everything else is laid out as in `VERSION`.
+const ENCRYPTED_VERSION: u8 = 2;
 const MAGIC: &[u8; 8] = b"QLOGFILE";
 
+/// The flag in the header of log files whose entry and field blocks are each followed by a CRC32 of the block, so
+/// that blocks left half written by a crash can be found. Files written before checksums were added don't have it.
+const FLAG_CHECKSUMS: u16 = 1;
+
+/// Every flag that this version knows how to read.
+const KNOWN_FLAGS: u16 = FLAG_CHECKSUMS;
+
 /// The compression algorithm used for the log file.
 #[derive(Debug, ByteStruct, Size)]
 #[repr(u8)]
@@ -20,6 +37,9 @@ pub enum BlockType {
 	Checkpoint,
 	Entry,
 	Field,
+	EncryptedField,
+	TypedField,
+	EncryptedTypedField,
 }
 
 impl WriteTo for BlockType {
@@ -39,7 +59,8 @@ pub struct HeaderBlock {
 	/// The compression algorithm used for the log file.
 	pub compression: Compression,
 
-	reserved: u16,
+	/// Flags for optional features of the file format, e.g. `FLAG_CHECKSUMS`.
+	flags: u16,
 
 	/// The ID of the machine that generated the log file.
 	pub machine_id: u32,
@@ -64,7 +85,7 @@ impl Default for HeaderBlock {
 			magic: *MAGIC,
 			version: VERSION,
 			compression: Compression::None,
-			reserved: 0,
+			flags: 0,
 			machine_id: 0,
 			time_min: Utc::now(),
 			time_max: Utc::now(),
@@ -76,16 +97,39 @@ impl Default for HeaderBlock {
 }
 
 impl HeaderBlock {
+	/// Creates the header of a new log file, whose field payloads are encrypted if `encrypted` is set.
+	pub fn new(encrypted: bool) -> Self {
+		Self {
+			version: if encrypted { ENCRYPTED_VERSION } else { VERSION },
+			flags: FLAG_CHECKSUMS,
+			..Self::default()
+		}
+	}
+
 	pub fn validate(&self) -> Result<(), String> {
 		if *MAGIC != self.magic {
 			return Err("Invalid magic number".to_string());
 		}
 
-		if VERSION != self.version {
+		if VERSION != self.version && ENCRYPTED_VERSION != self.version {
 			return Err("Invalid version number".to_string());
 		}
+
+		if self.flags & !KNOWN_FLAGS != 0 {
+			return Err(format!("Unknown flags: {:#x}", self.flags & !KNOWN_FLAGS));
+		}
 		Ok(())
 	}
+
+	/// Whether every entry and field block in the file is followed by a checksum.
+	pub fn has_checksums(&self) -> bool {
+		self.flags & FLAG_CHECKSUMS != 0
+	}
+
+	/// Whether the field payloads in the log file are encrypted.
+	pub fn is_encrypted(&self) -> bool {
+		self.version == ENCRYPTED_VERSION
+	}
 }
 
 #[derive(Debug, ByteStruct, Size)]
@@ -94,7 +138,14 @@ pub struct BlockHeader {
 	block_size: u64,
 }
 
+impl BlockHeader {
+	/// The size of an encoded block header. Padding after a block's fields is read as padding the header too, so
+	/// it has to be written that way, or the checksum after the block isn't where it's expected.
+	const SIZE: usize = 9;
+}
+
 /// A block containing a hash of the log entries that occur before this block.
+#[allow(dead_code)]
 #[derive(Debug, ByteStruct, Size)]
 #[little_endian]
 pub struct CheckpointBlock {
@@ -171,7 +222,7 @@ impl FieldBlock {
 	pub fn new(key: String, value: String) -> Self {
 		let key = LengthPrefixedString(key);
 		let value = LengthPrefixedString(value);
-		let padding = Padding::new(key.size() + value.size());
+		let padding = Padding::new(BlockHeader::SIZE + key.size() + value.size());
 		Self {
 			header: BlockHeader {
 				block_type: BlockType::Field,
@@ -183,3 +234,152 @@ impl FieldBlock {
 		}
 	}
 }
+
+/// A block containing a field of a log entry whose value isn't a string. String values are always stored in a
+/// `FieldBlock`.
+#[derive(Debug, ByteStruct, Size)]
+#[little_endian]
+pub struct TypedFieldBlock {
+	header: BlockHeader,
+
+	/// The key of the field.
+	pub key: LengthPrefixedString<MAX_FIELD_SIZE>,
+
+	/// The type of the value of the field.
+	pub value_type: ValueType,
+
+	/// The value of the field, as encoded by `Value::encode`.
+	pub value: Vec<u8>,
+
+	_unused: Padding<64>,
+}
+
+impl TypedFieldBlock {
+	pub fn new(key: String, value: &Value) -> Self {
+		let key = LengthPrefixedString(key);
+		let value_type = value.value_type();
+		let value = value.encode();
+		let padding = Padding::new(BlockHeader::SIZE + key.size() + value_type.size() + value.size());
+		Self {
+			header: BlockHeader {
+				block_type: BlockType::TypedField,
+				block_size: key.size() as u64 + value_type.size() as u64 + encoded_len(&value) + padding.size() as u64,
+			},
+			key,
+			value_type,
+			value,
+			_unused: padding,
+		}
+	}
+}
+
+/// A block containing a field of a log entry, whose key and value are encrypted. For an `EncryptedField` block, the
+/// plaintext is the key and value, encoded as they are in a `FieldBlock`. For an `EncryptedTypedField` block, it's
+/// the key, type, and value, encoded as they are in a `TypedFieldBlock`.
+#[derive(Debug, ByteStruct, Size)]
+#[little_endian]
+pub struct EncryptedFieldBlock {
+	header: BlockHeader,
+
+	/// The nonce that the field was encrypted with.
+	pub nonce: [u8; NONCE_SIZE],
+
+	/// The encrypted key and value, along with the authentication tag.
+	pub ciphertext: Vec<u8>,
+
+	_unused: Padding<64>,
+}
+
+impl EncryptedFieldBlock {
+	pub fn new(block_type: BlockType, nonce: [u8; NONCE_SIZE], ciphertext: Vec<u8>) -> Self {
+		let padding = Padding::new(BlockHeader::SIZE + nonce.size() + ciphertext.size());
+		Self {
+			header: BlockHeader {
+				block_type,
+				block_size: nonce.size() as u64 + encoded_len(&ciphertext) + padding.size() as u64,
+			},
+			nonce,
+			ciphertext,
+			_unused: padding,
+		}
+	}
+}
+
+/// The number of bytes that the bytes take up once they're written, which, unlike their `Size`, includes the length
+/// that they're prefixed with.
+fn encoded_len(bytes: &[u8]) -> u64 {
+	(size_of::<u64>() + bytes.len()) as u64
+}
+
+/// Appends the checksum of the encoded block to it.
+pub fn append_checksum(block: &mut Vec<u8>) {
+	let checksum = crc32fast::hash(block);
+	block.extend_from_slice(&checksum.to_le_bytes());
+}
+
+/// A reader that keeps a running checksum of everything read through it, so that the checksum after a block can be
+/// checked once the block has been read.
+pub struct ChecksumReader<R: Read> {
+	inner: R,
+	hasher: crc32fast::Hasher,
+}
+
+impl<R: Read> ChecksumReader<R> {
+	pub fn new(inner: R) -> Self {
+		Self {
+			inner,
+			hasher: crc32fast::Hasher::new(),
+		}
+	}
+
+	/// Reads the checksum that follows the block that has been read, and checks that it matches. Does nothing if
+	/// `enabled` isn't set, i.e. the file doesn't have checksums.
+	pub fn verify(self, enabled: bool) -> io::Result<()> {
+		if !enabled {
+			return Ok(());
+		}
+
+		let Self { mut inner, hasher } = self;
+		let expected = u32::read_from_with_endian(&mut inner, Endian::Little)?;
+		let actual = hasher.finalize();
+		if expected != actual {
+			return Err(io::Error::new(
+				ErrorKind::InvalidData,
+				format!("checksum mismatch: expected {:#010x}, got {:#010x}", expected, actual),
+			));
+		}
+
+		Ok(())
+	}
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.hasher.update(&buf[..read]);
+		Ok(read)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use bytestruct::WriteTo;
+
+	use super::{BlockHeader, BlockType, EncryptedFieldBlock, TypedFieldBlock};
+	use crate::value::Value;
+
+	#[test]
+	fn test_block_size() {
+		for value in [Value::from(42_i64), Value::from(vec![0_u8; 100])] {
+			let block = TypedFieldBlock::new(String::from("key"), &value);
+			let mut encoded = Vec::new();
+			block.write_to(&mut encoded).unwrap();
+			assert_eq!(encoded.len() as u64, BlockHeader::SIZE as u64 + block.header.block_size);
+		}
+
+		let block = EncryptedFieldBlock::new(BlockType::EncryptedField, [1; 24], vec![2; 40]);
+		let mut encoded = Vec::new();
+		block.write_to(&mut encoded).unwrap();
+		assert_eq!(encoded.len() as u64, BlockHeader::SIZE as u64 + block.header.block_size);
+	}
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{syslog::Priority, LogMessage, KV};
+
+/// The default path of the kernel log device.
+pub const DEFAULT_KMSG_PATH: &str = "/dev/kmsg";
+
+/// The maximum size of a single record in /dev/kmsg.
+pub const MAX_KMSG_RECORD_SIZE: usize = 8192;
+
+/// A single record read from /dev/kmsg.
+#[derive(Debug, Clone)]
+pub struct KmsgRecord {
+	/// The priority of the record.
+	pub priority: Priority,
+
+	/// The sequence number of the record, which increases by one for every record the kernel logs.
+	pub sequence: u64,
+
+	/// The time since boot that the record was logged at.
+	pub timestamp: Duration,
+
+	/// The message of the record.
+	pub message: String,
+
+	/// Any extra properties attached to the record (e.g. `SUBSYSTEM=pci`).
+	pub properties: Vec<KV>,
+}
+
+impl KmsgRecord {
+	/// Parses a record in the format documented in Documentation/ABI/testing/dev-kmsg, i.e.
+	/// `priority,sequence,timestamp,flags[,...];message\n[ KEY=value\n]...`.
+	pub fn parse(record: &str) -> Option<Self> {
+		let (header, rest) = record.split_once(';')?;
+		let mut header = header.split(',');
+		let priority = Priority::from_value(header.next()?.parse().ok()?)?;
+		let sequence = header.next()?.parse().ok()?;
+		let timestamp = Duration::from_micros(header.next()?.parse().ok()?);
+
+		let mut lines = rest.split('\n');
+		let message = unescape(lines.next()?);
+		let properties = lines
+			.filter_map(|line| line.strip_prefix(' '))
+			.filter_map(|line| line.split_once('='))
+			.map(|(k, v)| KV::new(k.to_owned(), unescape(v)))
+			.collect();
+
+		Some(Self {
+			priority,
+			sequence,
+			timestamp,
+			message,
+			properties,
+		})
+	}
+
+	/// Converts the record into a log message, given the wall clock time that the system booted at.
+	pub fn into_log_message(self, boot_time: DateTime<Utc>) -> LogMessage {
+		let mut fields = vec![KV::new(String::from("SOURCE"), String::from("kernel"))];
+		fields.extend(self.priority.fields());
+		fields.push(KV::new(String::from("SEQNUM"), self.sequence as i64));
+		fields.extend(self.properties);
+
+		let timestamp = boot_time + self.timestamp;
+		LogMessage::new(timestamp, fields, self.message)
+	}
+}
+
+/// Undoes the `\xNN` escaping that the kernel applies to non-printable characters in kmsg records.
+fn unescape(s: &str) -> String {
+	let mut out = Vec::with_capacity(s.len());
+	let bytes = s.as_bytes();
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'\\' && bytes.get(i + 1) == Some(&b'x') {
+			if let Some(byte) = s.get(i + 2..i + 4).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+				out.push(byte);
+				i += 4;
+				continue;
+			}
+		}
+
+		out.push(bytes[i]);
+		i += 1;
+	}
+
+	String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use super::KmsgRecord;
+
+	#[test]
+	fn test_parse() {
+		let record =
+			KmsgRecord::parse("6,339,5140900,-;NET: Registered protocol family 10\n SUBSYSTEM=net\n DEVICE=+net:lo\n")
+				.unwrap();
+		assert_eq!(record.priority.facility_name(), "kern");
+		assert_eq!(record.priority.severity_name(), "info");
+		assert_eq!(record.sequence, 339);
+		assert_eq!(record.timestamp, Duration::from_micros(5140900));
+		assert_eq!(record.message, "NET: Registered protocol family 10");
+		assert_eq!(record.properties.len(), 2);
+		assert_eq!(record.properties[0].key, "SUBSYSTEM");
+		assert_eq!(record.properties[1].value, "+net:lo");
+	}
+
+	#[test]
+	fn test_unescape() {
+		let record = KmsgRecord::parse("30,1,0,c;tab\\x09here\\xzz\n").unwrap();
+		assert_eq!(record.priority.facility_name(), "daemon");
+		assert_eq!(record.message, "tab\there\\xzz");
+	}
+
+	#[test]
+	fn test_invalid() {
+		assert!(KmsgRecord::parse("no header here").is_none());
+		assert!(KmsgRecord::parse("a,b,c,-;message").is_none());
+	}
+}
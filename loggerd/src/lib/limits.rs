@@ -0,0 +1,179 @@
+use thiserror::Error;
+
+use crate::{value::Value, LogMessage, KV};
+
+/// The default maximum number of fields (not including the message) that an entry can have.
+pub const DEFAULT_MAX_FIELDS: usize = 64;
+
+/// The default maximum size of a field key, in bytes.
+pub const DEFAULT_MAX_KEY_SIZE: usize = 64;
+
+/// The default maximum size of a field value, in bytes.
+pub const DEFAULT_MAX_VALUE_SIZE: usize = 32768;
+
+/// The suffix that is appended to values that were truncated to fit in the maximum value size.
+const TRUNCATED_SUFFIX: &str = "...";
+
+/// Errors that cause an entry to be rejected outright.
+#[derive(Debug, Clone, Error)]
+pub enum LimitError {
+	#[error("entry has {0} fields, which is more than the maximum of {1}")]
+	TooManyFields(usize, usize),
+
+	#[error("field key is empty")]
+	EmptyKey,
+
+	#[error("field key {0:?} is longer than the maximum of {1} bytes")]
+	KeyTooLong(String, usize),
+
+	#[error("field key {0:?} contains invalid characters")]
+	InvalidKey(String),
+}
+
+/// Limits that are enforced on every entry before it gets written to disk.
+#[derive(Debug, Clone)]
+pub struct EntryLimits {
+	/// The maximum number of fields (not including the message) that an entry can have.
+	pub max_fields: usize,
+
+	/// The maximum size of a field key, in bytes.
+	pub max_key_size: usize,
+
+	/// The maximum size of a field value (including the message), in bytes.
+	pub max_value_size: usize,
+}
+
+impl EntryLimits {
+	pub fn new(max_fields: usize, max_key_size: usize, max_value_size: usize) -> Self {
+		Self {
+			max_fields,
+			max_key_size,
+			max_value_size,
+		}
+	}
+
+	/// Validates the structure of the given message, and sanitizes its values.
+	/// Entries with too many fields, or with keys that are too long or malformed are rejected.
+	/// String values have control characters escaped, and string and byte values are truncated if they are too long.
+	pub fn apply(&self, message: LogMessage) -> Result<LogMessage, LimitError> {
+		if message.fields.len() > self.max_fields {
+			return Err(LimitError::TooManyFields(message.fields.len(), self.max_fields));
+		}
+
+		let mut fields = Vec::with_capacity(message.fields.len());
+		for field in message.fields {
+			self.validate_key(&field.key)?;
+			let value = match field.value {
+				Value::String(value) => Value::String(sanitize_value(&value, self.max_value_size)),
+				Value::Bytes(mut value) => {
+					value.truncate(self.max_value_size);
+					Value::Bytes(value)
+				}
+				value => value,
+			};
+
+			fields.push(KV::new(field.key, value));
+		}
+
+		Ok(LogMessage::new(
+			message.timestamp,
+			fields,
+			sanitize_value(&message.message, self.max_value_size),
+		))
+	}
+
+	/// Checks that the key is non empty, fits in the maximum key size, and is representable in a
+	/// control socket header (i.e. has no whitespace, control characters, or `=`s).
+	fn validate_key(&self, key: &str) -> Result<(), LimitError> {
+		if key.is_empty() {
+			return Err(LimitError::EmptyKey);
+		}
+
+		if key.len() > self.max_key_size {
+			return Err(LimitError::KeyTooLong(key.to_owned(), self.max_key_size));
+		}
+
+		if key.chars().any(|c| c.is_control() || c.is_whitespace() || c == '=') {
+			return Err(LimitError::InvalidKey(key.to_owned()));
+		}
+
+		Ok(())
+	}
+}
+
+impl Default for EntryLimits {
+	fn default() -> Self {
+		Self::new(DEFAULT_MAX_FIELDS, DEFAULT_MAX_KEY_SIZE, DEFAULT_MAX_VALUE_SIZE)
+	}
+}
+
+/// Escapes any control characters in the given value, truncating it (on a character boundary) if the
+/// result is longer than `max_size` bytes.
+fn sanitize_value(value: &str, max_size: usize) -> String {
+	let mut sanitized = String::with_capacity(value.len());
+	for c in value.chars() {
+		match c {
+			'\n' => sanitized.push_str("\\n"),
+			'\r' => sanitized.push_str("\\r"),
+			'\t' => sanitized.push_str("\\t"),
+			c if c.is_control() => sanitized.push_str(&format!("\\x{:02x}", c as u32)),
+			c => sanitized.push(c),
+		}
+	}
+
+	if sanitized.len() <= max_size {
+		return sanitized;
+	}
+
+	let mut end = max_size.saturating_sub(TRUNCATED_SUFFIX.len());
+	while !sanitized.is_char_boundary(end) {
+		end -= 1;
+	}
+
+	sanitized.truncate(end);
+	sanitized.push_str(TRUNCATED_SUFFIX);
+	sanitized
+}
+
+#[cfg(test)]
+mod test {
+	use chrono::Utc;
+
+	use super::{sanitize_value, EntryLimits, LimitError};
+	use crate::{LogMessage, KV};
+
+	#[test]
+	fn test_sanitize_value() {
+		assert_eq!(sanitize_value("hello", 100), "hello");
+		assert_eq!(sanitize_value("a\nb\tc", 100), "a\\nb\\tc");
+		assert_eq!(sanitize_value("\x1b[31mred", 100), "\\x1b[31mred");
+		assert_eq!(sanitize_value("abcdefghij", 8), "abcde...");
+		assert_eq!(sanitize_value("ééééé", 7), "éé...");
+	}
+
+	#[test]
+	fn test_apply() {
+		let limits = EntryLimits::new(2, 4, 100);
+		let message = LogMessage::new(
+			Utc::now(),
+			vec![KV::new("a".to_owned(), "b\x07".to_owned())],
+			"hi\r".to_owned(),
+		);
+
+		let message = limits.apply(message).unwrap();
+		assert_eq!(message.fields[0].value, "b\\x07");
+		assert_eq!(message.message, "hi\\r");
+
+		let too_many = LogMessage::new(
+			Utc::now(),
+			vec![KV::new("a".to_owned(), String::new()); 3],
+			String::new(),
+		);
+		assert!(matches!(limits.apply(too_many), Err(LimitError::TooManyFields(3, 2))));
+
+		for key in ["", "toolong", "a b", "a=b"] {
+			let message = LogMessage::new(Utc::now(), vec![KV::new(key.to_owned(), String::new())], String::new());
+			assert!(limits.apply(message).is_err(), "expected {:?} to be rejected", key);
+		}
+	}
+}
@@ -1,17 +1,29 @@
+pub mod client;
+pub mod coalesce;
 pub mod control;
+pub mod crypto;
 mod disk;
+pub mod kmsg;
+pub mod limits;
+pub mod reorder;
+pub mod syslog;
+pub mod value;
 
 use std::{
+	collections::HashMap,
 	fs::File,
-	io::{self, ErrorKind, Seek, SeekFrom},
+	io::{self, Cursor, ErrorKind, Seek, SeekFrom, Write},
 	path::{Path, PathBuf},
+	sync::Arc,
 };
 
-use bytestruct::{ReadFrom, ReadFromWithEndian, WriteTo};
+use bytestruct::{Endian, LengthPrefixedString, ReadFrom, ReadFromWithEndian, WriteTo, WriteToWithEndian};
 use chrono::{DateTime, Utc};
 use control::ReadStreamOpts;
-use disk::{BlockType, EntryBlock, FieldBlock};
+use crypto::LogKey;
+use disk::{BlockType, ChecksumReader, EncryptedFieldBlock, EntryBlock, FieldBlock, TypedFieldBlock, MAX_FIELD_SIZE};
 use serde::{Deserialize, Serialize};
+use value::{Value, ValueType};
 
 /// The default path to the control socket.
 pub const DEFAULT_CONTROL_SOCKET_PATH: &str = "/run/loggerd/loggerd.sock";
@@ -25,12 +37,15 @@ pub enum ConnectionHeader {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct KV {
 	pub key: String,
-	pub value: String,
+	pub value: Value,
 }
 
 impl KV {
-	pub fn new(key: String, value: String) -> KV {
-		KV { key, value }
+	pub fn new(key: String, value: impl Into<Value>) -> KV {
+		KV {
+			key,
+			value: value.into(),
+		}
 	}
 }
 
@@ -49,6 +64,25 @@ impl LogMessage {
 			message,
 		}
 	}
+
+	/// Flattens the message into a map of its fields, along with its timestamp (`__timestamp`) and message (`__msg`).
+	pub fn to_map(&self) -> HashMap<String, String> {
+		let mut map = HashMap::new();
+		map.insert(String::from("__timestamp"), self.timestamp.to_rfc3339());
+		map.insert(String::from("__msg"), self.message.clone());
+		for kv in self.fields.iter() {
+			map.insert(kv.key.clone(), kv.value.to_string());
+		}
+
+		map
+	}
+}
+
+/// A field block, as it's stored in the file.
+enum RawField {
+	Plain(FieldBlock),
+	Typed(TypedFieldBlock),
+	Encrypted(BlockType, EncryptedFieldBlock),
 }
 
 /// A log file that is open for writing.
@@ -62,51 +96,60 @@ pub struct OpenLogFile {
 
 	/// The offset and contents of the last entry block in the file.
 	last_entry_block: Option<(u64, EntryBlock)>,
+
+	/// The key that field payloads are encrypted with, if any.
+	key: Option<Arc<LogKey>>,
+
+	/// Whether the time range in the header has changed since the header was last written.
+	header_dirty: bool,
+
+	/// Whether entries were found to be damaged when the file was opened, e.g. because loggerd crashed while writing
+	/// them. Damaged entries, and everything after them, are cut off.
+	damaged: bool,
 }
 
 impl OpenLogFile {
-	/// Creates a new log file at the given path.
-	pub async fn new(path: &Path) -> io::Result<Self> {
+	/// Creates a new log file at the given path. If a key is given, the field payloads in the file are encrypted
+	/// with it.
+	pub async fn new(path: &Path, key: Option<Arc<LogKey>>) -> io::Result<Self> {
 		let file = File::create_new(path)?;
 		let mut file = OpenLogFile {
 			path: path.to_owned(),
 			file,
-			header: disk::HeaderBlock::default(),
+			header: disk::HeaderBlock::new(key.is_some()),
 			last_entry_block: None,
+			key,
+			header_dirty: false,
+			damaged: false,
 		};
 
-		file.write_header().await?;
+		file.write_header()?;
 
 		Ok(file)
 	}
 
+	/// Reads the entry block at the given offset, checking its checksum if the file has them. Returns the block, along
+	/// with the offset of the end of it.
+	fn read_entry_block_at(&mut self, offset: u64) -> io::Result<(EntryBlock, u64)> {
+		self.file.seek(SeekFrom::Start(offset))?;
+		let mut reader = ChecksumReader::new(&mut self.file);
+		let block = EntryBlock::read_from(&mut reader)?;
+		reader.verify(self.header.has_checksums())?;
+		Ok((block, self.file.stream_position()?))
+	}
+
 	pub fn read_entry_at(&mut self, offset: u64) -> io::Result<(LogMessage, u64)> {
 		let current_offset = self.file.stream_position()?;
-		self.file.seek(SeekFrom::Start(offset))?;
-		let res = EntryBlock::read_from(&mut self.file)?;
+		let (res, _) = self.read_entry_block_at(offset)?;
 		let mut message = None;
 		let mut fields = Vec::new();
 		for offset in res.field_offsets {
-			self.file.seek(SeekFrom::Start(offset))?;
-			let block_type = BlockType::read_from_with_endian(&mut self.file, bytestruct::Endian::Little)?;
-			if !matches!(block_type, BlockType::Field) {
-				return Err(io::Error::new(
-					ErrorKind::InvalidData,
-					format!("invalid block type. Expected Field, got: {:?}", block_type),
-				));
-			}
-
-			let field = FieldBlock::read_from(&mut self.file)?;
+			let field = self.read_field_at(offset)?;
 
-			if field.key.0 == "message" && !field.value.0.is_empty() {
-				message = Some(field.value.0);
-				continue;
+			match field.value {
+				Value::String(value) if field.key == "message" && !value.is_empty() => message = Some(value),
+				_ => fields.push(field),
 			}
-
-			fields.push(KV {
-				key: field.key.0,
-				value: field.value.0,
-			});
 		}
 
 		self.file.seek(SeekFrom::Start(current_offset))?;
@@ -118,64 +161,277 @@ impl OpenLogFile {
 		))
 	}
 
-	/// Open an existing log file at the given path.
-	pub async fn open(path: &Path) -> io::Result<Self> {
-		let mut file = File::options().read(true).write(true).open(path)?;
+	/// Reads the field block at the given offset, checking its checksum if the file has them, but without decrypting
+	/// it.
+	fn read_raw_field_at(&mut self, offset: u64) -> io::Result<RawField> {
+		self.file.seek(SeekFrom::Start(offset))?;
+		let mut reader = ChecksumReader::new(&mut self.file);
+		let block_type = BlockType::read_from_with_endian(&mut reader, bytestruct::Endian::Little)?;
+		let field = match block_type {
+			BlockType::Field if !self.header.is_encrypted() => RawField::Plain(FieldBlock::read_from(&mut reader)?),
+			BlockType::TypedField if !self.header.is_encrypted() => {
+				RawField::Typed(TypedFieldBlock::read_from(&mut reader)?)
+			}
+			BlockType::EncryptedField | BlockType::EncryptedTypedField if self.header.is_encrypted() => {
+				RawField::Encrypted(block_type, EncryptedFieldBlock::read_from(&mut reader)?)
+			}
+			block_type => {
+				return Err(io::Error::new(
+					ErrorKind::InvalidData,
+					format!("invalid block type. Expected Field, got: {:?}", block_type),
+				))
+			}
+		};
+
+		reader.verify(self.header.has_checksums())?;
+		Ok(field)
+	}
+
+	/// Reads the field block at the given offset, decrypting it if the file is encrypted.
+	fn read_field_at(&mut self, offset: u64) -> io::Result<KV> {
+		match (self.read_raw_field_at(offset)?, &self.key) {
+			(RawField::Plain(field), _) => Ok(KV::new(field.key.0, field.value.0)),
+			(RawField::Typed(field), _) => Ok(KV::new(field.key.0, Value::decode(field.value_type, field.value)?)),
+			(RawField::Encrypted(block_type, field), Some(key)) => {
+				let plaintext = key.decrypt(&field.nonce, &field.ciphertext, &offset.to_le_bytes())?;
+				let mut plaintext = Cursor::new(plaintext);
+				let field_key =
+					LengthPrefixedString::<MAX_FIELD_SIZE>::read_from_with_endian(&mut plaintext, Endian::Little)?;
+				let value = match block_type {
+					BlockType::EncryptedTypedField => {
+						let value_type = ValueType::read_from_with_endian(&mut plaintext, Endian::Little)?;
+						let value = Vec::<u8>::read_from_with_endian(&mut plaintext, Endian::Little)?;
+						Value::decode(value_type, value)?
+					}
+					_ => Value::String(
+						LengthPrefixedString::<MAX_FIELD_SIZE>::read_from_with_endian(&mut plaintext, Endian::Little)?
+							.0,
+					),
+				};
+				Ok(KV::new(field_key.0, value))
+			}
+			(RawField::Encrypted(..), None) => Err(io::Error::new(
+				ErrorKind::PermissionDenied,
+				format!("{} is encrypted, but no key was given", self.path.display()),
+			)),
+		}
+	}
+
+	/// Writes a field block to the end of the file, encrypting it if the file is encrypted, and returns its offset.
+	/// String values are written in the same blocks as they were before values were typed, so files that only have
+	/// string fields can still be read by older versions.
+	fn write_field(&mut self, key: String, value: Value) -> io::Result<u64> {
+		let offset = self.file.seek(SeekFrom::End(0))?;
+		let mut block = Vec::new();
+		match (&self.key, value) {
+			(Some(log_key), value) => {
+				let mut plaintext = Vec::new();
+				LengthPrefixedString::<MAX_FIELD_SIZE>(key).write_to_with_endian(&mut plaintext, Endian::Little)?;
+				let block_type = match value {
+					Value::String(value) => {
+						LengthPrefixedString::<MAX_FIELD_SIZE>(value)
+							.write_to_with_endian(&mut plaintext, Endian::Little)?;
+						BlockType::EncryptedField
+					}
+					value => {
+						value
+							.value_type()
+							.write_to_with_endian(&mut plaintext, Endian::Little)?;
+						value.encode().write_to_with_endian(&mut plaintext, Endian::Little)?;
+						BlockType::EncryptedTypedField
+					}
+				};
+
+				// Binding the field to its offset stops fields from being swapped around between entries.
+				let (nonce, ciphertext) = log_key.encrypt(&plaintext, &offset.to_le_bytes())?;
+				block_type.write_to(&mut block)?;
+				disk::EncryptedFieldBlock::new(block_type, nonce, ciphertext).write_to(&mut block)?;
+			}
+			(None, Value::String(value)) => {
+				BlockType::Field.write_to(&mut block)?;
+				disk::FieldBlock::new(key, value).write_to(&mut block)?;
+			}
+			(None, value) => {
+				BlockType::TypedField.write_to(&mut block)?;
+				disk::TypedFieldBlock::new(key, &value).write_to(&mut block)?;
+			}
+		}
+
+		self.write_block(block)?;
+		Ok(offset)
+	}
+
+	/// Writes an encoded block at the current position in the file, followed by its checksum if the file has them.
+	fn write_block(&mut self, mut block: Vec<u8>) -> io::Result<()> {
+		if self.header.has_checksums() {
+			disk::append_checksum(&mut block);
+		}
+
+		self.file.write_all(&block)
+	}
+
+	/// Writes the entry block at the given offset, which is either the end of the file, or where the block already
+	/// is.
+	fn write_entry_block(&mut self, offset: u64, entry: &EntryBlock) -> io::Result<()> {
+		let mut block = Vec::new();
+		entry.write_to(&mut block)?;
+		self.file.seek(SeekFrom::Start(offset))?;
+		self.write_block(block)
+	}
+
+	/// Open an existing log file at the given path, for reading and writing. If the file is encrypted, the key must
+	/// be given.
+	pub async fn open(path: &Path, key: Option<Arc<LogKey>>) -> io::Result<Self> {
+		let file = File::options().read(true).write(true).open(path)?;
+		Self::from_file(path, file, key, true)
+	}
+
+	/// Open an existing log file at the given path, for reading only. Entries in encrypted files can only be read if
+	/// the key is given.
+	pub fn open_read_only(path: &Path, key: Option<Arc<LogKey>>) -> io::Result<Self> {
+		let file = File::open(path)?;
+		Self::from_file(path, file, key, false)
+	}
+
+	fn from_file(path: &Path, mut file: File, key: Option<Arc<LogKey>>, writable: bool) -> io::Result<Self> {
 		let header = disk::HeaderBlock::read_from(&mut file)?;
 
 		if let Err(e) = header.validate() {
 			return Err(io::Error::new(io::ErrorKind::InvalidData, e));
 		}
 
-		// Find the last entry block by following the linked list.
-		let mut offset = header.first_entry_block_offset;
-		while offset != 0 {
-			file.seek(SeekFrom::Start(offset))?;
-			let block = EntryBlock::read_from(&mut file)?;
+		// Fields are only ever decrypted with the key, so never use it on a plaintext file.
+		let key = key.filter(|_| header.is_encrypted());
+
+		let mut file = OpenLogFile {
+			path: path.to_owned(),
+			file,
+			header,
+			last_entry_block: None,
+			key,
+			header_dirty: false,
+			damaged: false,
+		};
+
+		file.scan(writable)?;
+		file.file.seek(SeekFrom::End(0))?;
 
-			if block.entry_header.next_entry_block_offset == 0 {
+		Ok(file)
+	}
+
+	/// Finds the last entry block by following the linked list of them, checking each entry as it goes. Entries
+	/// aren't necessarily in time order, and the time range in the header can be behind if the file wasn't synced, so
+	/// this works out the range from the entries too.
+	///
+	/// The list ends at the first entry that's damaged, e.g. because loggerd crashed while writing it. If the file is
+	/// writable, everything after the last intact entry is cut off, so that new entries are linked to it.
+	fn scan(&mut self, writable: bool) -> io::Result<()> {
+		let time_range = (self.header.time_min, self.header.time_max);
+		let file_length = self.file.metadata()?.len();
+
+		// The end of the intact part of the file, which starts as the end of the header.
+		let mut end = self.file.stream_position()?;
+		let mut last: Option<(u64, EntryBlock)> = None;
+		let mut offset = self.header.first_entry_block_offset;
+		while offset != 0 {
+			// Entries are only ever appended, so every link points further into the file than the last. Anything else
+			// is damage, and following it could loop forever.
+			if offset < end || offset >= file_length {
+				self.damaged = true;
 				break;
 			}
 
-			offset = block.entry_header.next_entry_block_offset;
+			let (block, block_end) = match self.read_intact_entry(offset) {
+				Ok(entry) => entry,
+				Err(e) if matches!(e.kind(), ErrorKind::InvalidData | ErrorKind::UnexpectedEof) => {
+					self.damaged = true;
+					break;
+				}
+				Err(e) => return Err(e),
+			};
+
+			if last.is_none() {
+				self.header.time_min = block.entry_header.time;
+				self.header.time_max = block.entry_header.time;
+			} else {
+				self.header.time_min = self.header.time_min.min(block.entry_header.time);
+				self.header.time_max = self.header.time_max.max(block.entry_header.time);
+			}
+
+			end = block_end;
+			let next_offset = block.entry_header.next_entry_block_offset;
+			last = Some((offset, block));
+			offset = next_offset;
 		}
 
-		// Read the last entry block.
-		let block = if offset != 0 {
-			file.seek(SeekFrom::Start(offset))?;
-			Some((offset, EntryBlock::read_from(&mut file)?))
-		} else {
-			None
-		};
+		self.header_dirty = (self.header.time_min, self.header.time_max) != time_range;
+
+		// Anything after the last entry is either damaged, or the fields of an entry that was never finished.
+		if writable && (self.damaged || file_length > end) {
+			self.file.set_len(end)?;
+			match &mut last {
+				Some((offset, block)) => {
+					block.entry_header.next_entry_block_offset = 0;
+					self.write_entry_block(*offset, block)?;
+				}
+				None => self.header.first_entry_block_offset = 0,
+			}
 
-		file.seek(SeekFrom::End(0))?;
+			self.write_header()?;
+			self.header_dirty = false;
+			self.file.sync_data()?;
+		}
 
-		Ok(OpenLogFile {
-			path: path.to_owned(),
-			file,
-			header,
-			last_entry_block: block,
-		})
+		self.last_entry_block = last;
+		Ok(())
+	}
+
+	/// Reads the entry block at the given offset, along with every field block that it points to, checking that they
+	/// haven't been damaged. Returns the entry block, and the offset of the end of it.
+	fn read_intact_entry(&mut self, offset: u64) -> io::Result<(EntryBlock, u64)> {
+		let (block, end) = self.read_entry_block_at(offset)?;
+		for &field_offset in block.field_offsets.iter() {
+			// Fields are written before the entry that they're in.
+			if field_offset >= offset {
+				return Err(io::Error::new(
+					ErrorKind::InvalidData,
+					format!("field at {} is after its entry at {}", field_offset, offset),
+				));
+			}
+
+			self.read_raw_field_at(field_offset)?;
+		}
+
+		Ok((block, end))
+	}
+
+	/// Whether entries were found to be damaged when the file was opened. If the file was opened for writing, they
+	/// have been cut off.
+	pub fn is_damaged(&self) -> bool {
+		self.damaged
 	}
 
 	/// Writes a log message to the log file.
 	pub async fn write_log(&mut self, message: LogMessage) -> io::Result<()> {
 		// Write all the fields and collect the offsets.
+		if self.header.is_encrypted() && self.key.is_none() {
+			return Err(io::Error::new(
+				ErrorKind::PermissionDenied,
+				format!("{} is encrypted, but no key was given", self.path.display()),
+			));
+		}
+
 		let mut field_offsets = vec![];
 		for field in message.fields {
-			field_offsets.push(self.file.seek(SeekFrom::End(0))?);
-			BlockType::Field.write_to(&mut self.file)?;
-			disk::FieldBlock::new(field.key, field.value).write_to(&mut self.file)?;
+			field_offsets.push(self.write_field(field.key, field.value)?);
 		}
 
-		field_offsets.push(self.file.seek(SeekFrom::End(0))?);
-		BlockType::Field.write_to(&mut self.file)?;
-		disk::FieldBlock::new("message".to_string(), message.message).write_to(&mut self.file)?;
+		field_offsets.push(self.write_field("message".to_string(), Value::String(message.message))?);
 
 		// Write the entry block.
 		let next_offset = self.file.seek(SeekFrom::End(0))?;
 		let block = disk::EntryBlock::new(message.timestamp, field_offsets);
-		block.write_to(&mut self.file)?;
+		self.write_entry_block(next_offset, &block)?;
 
 		// Update the pointers in the file to the new entry block.
 		if self.header.first_entry_block_offset == 0 {
@@ -183,14 +439,23 @@ impl OpenLogFile {
 			self.header.time_min = message.timestamp;
 			self.header.time_max = message.timestamp;
 
-			self.write_header().await?;
+			self.write_header()?;
 		} else if let Some((offset, mut block)) = self.last_entry_block.take() {
 			block.entry_header.next_entry_block_offset = next_offset;
-			self.file.seek(SeekFrom::Start(offset))?;
-			block.write_to(&mut self.file)?;
+			self.write_entry_block(offset, &block)?;
+
+			// The wall clock can go backwards, so the entry can widen the range at either end. The header is only
+			// rewritten when the file is synced, rather than on every entry.
+			if message.timestamp < self.header.time_min {
+				self.header.time_min = message.timestamp;
+				self.header_dirty = true;
+			}
+			if message.timestamp > self.header.time_max {
+				self.header.time_max = message.timestamp;
+				self.header_dirty = true;
+			}
 		} else {
-			return Err(io::Error::new(
-				io::ErrorKind::Other,
+			return Err(io::Error::other(
 				"no last entry block, even though the header block thinks there is",
 			));
 		}
@@ -200,13 +465,23 @@ impl OpenLogFile {
 		Ok(())
 	}
 
+	/// Flushes everything that has been written to the log file to disk, along with its time range.
+	pub async fn sync(&mut self) -> io::Result<()> {
+		if self.header_dirty {
+			self.write_header()?;
+			self.header_dirty = false;
+		}
+
+		self.file.sync_data()
+	}
+
 	/// Reads the log stream from the log file.
 	pub async fn read_log_stream(self, opts: ReadStreamOpts) -> impl Iterator<Item = io::Result<LogMessage>> {
 		ReadIter::new(self, opts)
 	}
 
 	/// Writes the header block to the start of the file.
-	async fn write_header(&mut self) -> io::Result<()> {
+	fn write_header(&mut self) -> io::Result<()> {
 		self.file.seek(SeekFrom::Start(0))?;
 		self.header.write_to(&mut self.file)?;
 		self.file.seek(SeekFrom::End(0))?;
@@ -222,7 +497,12 @@ struct ReadIter {
 
 impl ReadIter {
 	fn new(file: OpenLogFile, opts: ReadStreamOpts) -> Self {
-		let offset = file.header.first_entry_block_offset;
+		let offset = match &file.last_entry_block {
+			// Every entry in the file is damaged.
+			None if file.damaged => 0,
+			_ => file.header.first_entry_block_offset,
+		};
+
 		ReadIter { file, opts, offset }
 	}
 }
@@ -241,6 +521,12 @@ impl Iterator for ReadIter {
 				Err(e) => return Some(Err(e)),
 			};
 
+			// The last intact entry can still link to a damaged one, if the file couldn't be repaired.
+			let next_offset = match &self.file.last_entry_block {
+				Some((last_offset, _)) if *last_offset == self.offset => 0,
+				_ => next_offset,
+			};
+
 			if self.opts.matches(&message) {
 				self.offset = next_offset;
 				return Some(Ok(message));
@@ -252,3 +538,165 @@ impl Iterator for ReadIter {
 		None
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use std::{fs, sync::Arc};
+
+	use chrono::Utc;
+	use common::testing::TempDir;
+
+	use crate::{control::ReadStreamOpts, crypto::LogKey, value::Value, LogMessage, OpenLogFile, KV};
+
+	#[tokio::test]
+	async fn test_typed_fields_round_trip() {
+		for key in [None, Some(Arc::new(LogKey::new(&[7; 32])))] {
+			let directory = TempDir::new("loggerd").unwrap();
+			let path = directory.join("test.log");
+			let fields = vec![
+				KV::new(String::from("name"), "value"),
+				KV::new(String::from("count"), 42),
+				KV::new(String::from("ratio"), 0.5),
+				KV::new(String::from("ok"), true),
+				KV::new(String::from("raw"), vec![0_u8, 255]),
+			];
+
+			let mut file = OpenLogFile::new(&path, key.clone()).await.unwrap();
+			file.write_log(LogMessage::new(Utc::now(), fields.clone(), String::from("hello")))
+				.await
+				.unwrap();
+
+			let file = OpenLogFile::open_read_only(&path, key).unwrap();
+			let logs = file
+				.read_log_stream(ReadStreamOpts::new())
+				.await
+				.collect::<Result<Vec<_>, _>>()
+				.unwrap();
+
+			assert_eq!(logs.len(), 1);
+			assert_eq!(logs[0].message, "hello");
+			let values: Vec<&Value> = logs[0].fields.iter().map(|f| &f.value).collect();
+			assert_eq!(values, fields.iter().map(|f| &f.value).collect::<Vec<_>>());
+		}
+	}
+
+	#[tokio::test]
+	async fn test_encrypted_round_trip() {
+		let directory = TempDir::new("loggerd").unwrap();
+		let path = directory.join("test.log");
+		let key = Arc::new(LogKey::new(&[7; 32]));
+		let fields = |i: i64| {
+			vec![
+				KV::new(String::from("user"), "secret-user"),
+				KV::new(String::from("attempt"), i),
+				KV::new(String::from("payload"), vec![i as u8; 100 + i as usize]),
+			]
+		};
+
+		let mut file = OpenLogFile::new(&path, Some(key.clone())).await.unwrap();
+		for i in 0..3 {
+			file.write_log(LogMessage::new(Utc::now(), fields(i), format!("login {}", i)))
+				.await
+				.unwrap();
+		}
+		drop(file);
+
+		// Reopening the file checks every block, and carries on after the last one.
+		let mut file = OpenLogFile::open(&path, Some(key.clone())).await.unwrap();
+		assert!(!file.is_damaged());
+		file.write_log(LogMessage::new(Utc::now(), fields(3), String::from("login 3")))
+			.await
+			.unwrap();
+		drop(file);
+
+		assert!(!fs::read(&path)
+			.unwrap()
+			.windows(b"secret-user".len())
+			.any(|w| w == b"secret-user"));
+
+		let logs = OpenLogFile::open_read_only(&path, Some(key))
+			.unwrap()
+			.read_log_stream(ReadStreamOpts::new())
+			.await
+			.collect::<Result<Vec<_>, _>>()
+			.unwrap();
+		assert_eq!(logs.len(), 4);
+		for (i, log) in logs.iter().enumerate() {
+			assert_eq!(log.message, format!("login {}", i));
+			let values: Vec<&Value> = log.fields.iter().map(|f| &f.value).collect();
+			assert_eq!(values, fields(i as i64).iter().map(|f| &f.value).collect::<Vec<_>>());
+		}
+
+		// Fields can't be read with the wrong key.
+		let wrong_key = Arc::new(LogKey::new(&[8; 32]));
+		assert!(OpenLogFile::open_read_only(&path, Some(wrong_key))
+			.unwrap()
+			.read_log_stream(ReadStreamOpts::new())
+			.await
+			.any(|log| log.is_err()));
+	}
+
+	fn read_messages(file: &OpenLogFile) -> Vec<String> {
+		let file = OpenLogFile::open_read_only(&file.path, None).unwrap();
+		let mut messages = Vec::new();
+		let mut offset = file.header.first_entry_block_offset;
+		let mut file = file;
+		while offset != 0 {
+			let (message, next_offset) = file.read_entry_at(offset).unwrap();
+			messages.push(message.message);
+			offset = match &file.last_entry_block {
+				Some((last_offset, _)) if *last_offset == offset => 0,
+				_ => next_offset,
+			};
+		}
+
+		messages
+	}
+
+	#[tokio::test]
+	async fn test_recovers_damaged_entries() {
+		let directory = TempDir::new("loggerd").unwrap();
+		let path = directory.join("test.log");
+		let mut file = OpenLogFile::new(&path, None).await.unwrap();
+		for message in ["one", "two", "three"] {
+			let fields = vec![KV::new(String::from("name"), "value")];
+			file.write_log(LogMessage::new(Utc::now(), fields, message.to_owned()))
+				.await
+				.unwrap();
+		}
+		file.sync().await.unwrap();
+
+		// Cut the last entry block off halfway through, as if loggerd crashed while writing it.
+		let length = fs::metadata(&path).unwrap().len();
+		let (third, _) = file.last_entry_block.take().unwrap();
+		fs::OpenOptions::new()
+			.write(true)
+			.open(&path)
+			.unwrap()
+			.set_len(third + (length - third) / 2)
+			.unwrap();
+
+		let read_only = OpenLogFile::open_read_only(&path, None).unwrap();
+		assert!(read_only.is_damaged());
+		assert_eq!(read_messages(&read_only), vec!["one", "two"]);
+
+		// Opening it for writing cuts off the damaged entry, and links new entries to the last intact one.
+		let mut file = OpenLogFile::open(&path, None).await.unwrap();
+		assert!(file.is_damaged());
+		file.write_log(LogMessage::new(Utc::now(), Vec::new(), String::from("four")))
+			.await
+			.unwrap();
+		assert!(!OpenLogFile::open_read_only(&path, None).unwrap().is_damaged());
+		assert_eq!(read_messages(&file), vec!["one", "two", "four"]);
+
+		// Flip a bit in a field of the second entry.
+		let mut contents = fs::read(&path).unwrap();
+		let position = contents.windows(3).position(|w| w == b"two").unwrap();
+		contents[position] ^= 1;
+		fs::write(&path, contents).unwrap();
+
+		let file = OpenLogFile::open(&path, None).await.unwrap();
+		assert!(file.is_damaged());
+		assert_eq!(read_messages(&file), vec!["one"]);
+	}
+}
@@ -0,0 +1,146 @@
+use std::{
+	collections::{BTreeMap, VecDeque},
+	time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::LogMessage;
+
+/// The position of an entry in the buffer: its timestamp, and then the order it arrived in, so that entries with the
+/// same timestamp keep their order.
+type Key = (DateTime<Utc>, u64);
+
+/// Puts entries from different writers back into timestamp order before they're written. Writers stamp entries
+/// themselves, and they reach loggerd through different streams, so they can arrive slightly out of order.
+///
+/// Every entry is held for the window after it arrives, and is written along with every entry that sorts before it
+/// once its window ends. Entries that arrive more than a window late are still written, just out of order, so readers
+/// can't assume that the entries in a file are sorted.
+pub struct ReorderBuffer {
+	window: Duration,
+
+	/// The entries that are being held, in timestamp order.
+	pending: BTreeMap<Key, LogMessage>,
+
+	/// When each pending entry arrived, in the order they arrived. Entries that have already been released because
+	/// an entry after them was are left here, and skipped.
+	arrivals: VecDeque<(Instant, Key)>,
+
+	/// How many entries have been pushed, to break ties between timestamps.
+	sequence: u64,
+}
+
+impl ReorderBuffer {
+	pub fn new(window: Duration) -> Self {
+		Self {
+			window,
+			pending: BTreeMap::new(),
+			arrivals: VecDeque::new(),
+			sequence: 0,
+		}
+	}
+
+	/// Adds a message that arrived at the given time.
+	pub fn push(&mut self, message: LogMessage, now: Instant) {
+		let key = (message.timestamp, self.sequence);
+		self.sequence += 1;
+		self.pending.insert(key, message);
+		self.arrivals.push_back((now, key));
+	}
+
+	/// Takes the entries that have been held for the whole window, along with every entry that sorts before them,
+	/// in timestamp order.
+	pub fn pop_ready(&mut self, now: Instant) -> Vec<LogMessage> {
+		let mut ready = Vec::new();
+		while let Some(&(arrived, key)) = self.arrivals.front() {
+			if arrived + self.window > now {
+				break;
+			}
+
+			self.arrivals.pop_front();
+			if !self.pending.contains_key(&key) {
+				continue;
+			}
+
+			let later = self.pending.split_off(&(key.0, key.1 + 1));
+			ready.extend(std::mem::replace(&mut self.pending, later).into_values());
+		}
+
+		ready
+	}
+
+	/// When the next entry has to be written by, if any are being held.
+	pub fn deadline(&self) -> Option<Instant> {
+		self.arrivals.front().map(|(arrived, _)| *arrived + self.window)
+	}
+
+	/// Takes every entry that's being held, in timestamp order.
+	pub fn drain(&mut self) -> Vec<LogMessage> {
+		self.arrivals.clear();
+		std::mem::take(&mut self.pending).into_values().collect()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::{Duration, Instant};
+
+	use chrono::{DateTime, TimeDelta, Utc};
+
+	use super::ReorderBuffer;
+	use crate::LogMessage;
+
+	fn message(timestamp: DateTime<Utc>, text: &str) -> LogMessage {
+		LogMessage::new(timestamp, Vec::new(), text.to_owned())
+	}
+
+	fn texts(messages: Vec<LogMessage>) -> Vec<String> {
+		messages.into_iter().map(|m| m.message).collect()
+	}
+
+	#[test]
+	fn test_reorders_within_window() {
+		let window = Duration::from_millis(100);
+		let mut buffer = ReorderBuffer::new(window);
+		let start = Instant::now();
+		let base = Utc::now();
+
+		buffer.push(message(base + TimeDelta::milliseconds(20), "b"), start);
+		buffer.push(message(base, "a"), start + Duration::from_millis(10));
+		buffer.push(
+			message(base + TimeDelta::milliseconds(20), "c"),
+			start + Duration::from_millis(50),
+		);
+		assert!(buffer.pop_ready(start + Duration::from_millis(99)).is_empty());
+		assert_eq!(buffer.deadline(), Some(start + window));
+
+		// "b" is due, and "a" sorts before it, but "c" arrived after it with the same timestamp.
+		assert_eq!(texts(buffer.pop_ready(start + window)), vec!["a", "b"]);
+		assert_eq!(buffer.deadline(), Some(start + Duration::from_millis(110)));
+		assert!(buffer.pop_ready(start + Duration::from_millis(120)).is_empty());
+		assert_eq!(texts(buffer.pop_ready(start + Duration::from_millis(150))), vec!["c"]);
+		assert_eq!(buffer.deadline(), None);
+	}
+
+	#[test]
+	fn test_late_entries_are_kept() {
+		let mut buffer = ReorderBuffer::new(Duration::from_millis(100));
+		let start = Instant::now();
+		let base = Utc::now();
+
+		buffer.push(message(base, "a"), start);
+		assert_eq!(texts(buffer.pop_ready(start + Duration::from_millis(100))), vec!["a"]);
+
+		buffer.push(
+			message(base - TimeDelta::seconds(60), "late"),
+			start + Duration::from_millis(110),
+		);
+		buffer.push(
+			message(base + TimeDelta::seconds(1), "b"),
+			start + Duration::from_millis(120),
+		);
+		assert_eq!(texts(buffer.drain()), vec!["late", "b"]);
+		assert_eq!(buffer.deadline(), None);
+	}
+}
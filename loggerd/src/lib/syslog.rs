@@ -0,0 +1,283 @@
+use std::fmt::{self, Display, Formatter};
+
+use chrono::{DateTime, Utc};
+
+use crate::{LogMessage, KV};
+
+/// The default path of the syslog socket.
+pub const DEFAULT_SYSLOG_SOCKET_PATH: &str = "/dev/log";
+
+/// The names of the syslog facilities, indexed by their numeric value.
+const FACILITY_NAMES: [&str; 24] = [
+	"kern",
+	"user",
+	"mail",
+	"daemon",
+	"auth",
+	"syslog",
+	"lpr",
+	"news",
+	"uucp",
+	"cron",
+	"authpriv",
+	"ftp",
+	"ntp",
+	"security",
+	"console",
+	"solaris-cron",
+	"local0",
+	"local1",
+	"local2",
+	"local3",
+	"local4",
+	"local5",
+	"local6",
+	"local7",
+];
+
+/// The names of the syslog severities, indexed by their numeric value.
+const SEVERITY_NAMES: [&str; 8] = ["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+
+/// A syslog priority, made up of a facility and a severity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Priority {
+	pub facility: u8,
+	pub severity: u8,
+}
+
+impl Priority {
+	/// Parses a priority value from the numeric form used in syslog and kmsg (`facility * 8 + severity`).
+	pub fn from_value(value: u32) -> Option<Self> {
+		let facility = value >> 3;
+		if facility as usize >= FACILITY_NAMES.len() {
+			return None;
+		}
+
+		Some(Self {
+			facility: facility as u8,
+			severity: (value & 0x7) as u8,
+		})
+	}
+
+	/// Parses a severity from its name (e.g. `err`), returning its numeric value.
+	pub fn severity_from_name(name: &str) -> Option<u8> {
+		SEVERITY_NAMES.iter().position(|n| *n == name).map(|i| i as u8)
+	}
+
+	/// Returns the name of the facility, e.g. `daemon`.
+	pub fn facility_name(&self) -> &'static str {
+		FACILITY_NAMES[self.facility as usize]
+	}
+
+	/// Returns the name of the severity, e.g. `err`.
+	pub fn severity_name(&self) -> &'static str {
+		SEVERITY_NAMES[self.severity as usize]
+	}
+
+	/// Returns the fields that represent this priority in a log message. The severity is given both by name, and by
+	/// number so that entries can be filtered by it, e.g. `SEVERITY<=3` for errors and worse.
+	pub fn fields(&self) -> Vec<KV> {
+		vec![
+			KV::new(String::from("FACILITY"), self.facility_name().to_owned()),
+			KV::new(String::from("PRIORITY"), self.severity_name().to_owned()),
+			KV::new(String::from("SEVERITY"), self.severity as i64),
+		]
+	}
+}
+
+impl Display for Priority {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}.{}", self.facility_name(), self.severity_name())
+	}
+}
+
+/// Parses a syslog message in either RFC5424 or RFC3164 format into a log message.
+/// Messages that don't have a valid priority header are treated as `user.notice`, as per RFC3164.
+/// `now` is used as the timestamp of messages that don't carry their own (or carry an ambiguous one).
+pub fn parse_syslog_message(message: &str, now: DateTime<Utc>) -> LogMessage {
+	let message = message.trim_end_matches(['\n', '\0']);
+	let (priority, rest) = match parse_priority(message) {
+		Some((priority, rest)) => (priority, rest),
+		None => (Priority::from_value(13).unwrap(), message),
+	};
+
+	let mut fields = vec![KV::new(String::from("SOURCE"), String::from("syslog"))];
+	fields.extend(priority.fields());
+
+	let (timestamp, message) = match rest.strip_prefix("1 ") {
+		Some(rest) => parse_rfc5424(rest, now, &mut fields),
+		None => (now, parse_rfc3164(rest, &mut fields)),
+	};
+
+	LogMessage::new(timestamp, fields, message.to_owned())
+}
+
+/// Parses the `<PRI>` header from the front of a syslog message, returning the priority and the rest of the message.
+fn parse_priority(message: &str) -> Option<(Priority, &str)> {
+	let rest = message.strip_prefix('<')?;
+	let (value, rest) = rest.split_once('>')?;
+	if value.is_empty() || value.len() > 3 {
+		return None;
+	}
+
+	Some((Priority::from_value(value.parse().ok()?)?, rest))
+}
+
+/// Parses the body of an RFC5424 message (everything after the version), i.e.
+/// `TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG`.
+fn parse_rfc5424<'a>(rest: &'a str, now: DateTime<Utc>, fields: &mut Vec<KV>) -> (DateTime<Utc>, &'a str) {
+	let mut parts = rest.splitn(6, ' ');
+	let timestamp = parts
+		.next()
+		.and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+		.map_or(now, |t| t.into());
+
+	for key in ["HOSTNAME", "IDENTIFIER", "PID", "MSGID"] {
+		match parts.next() {
+			Some("-") | None => {}
+			Some(value) => fields.push(KV::new(key.to_owned(), value.to_owned())),
+		}
+	}
+
+	let rest = parts.next().unwrap_or("");
+	(timestamp, skip_structured_data(rest))
+}
+
+/// Skips the structured data section of an RFC5424 message, returning the free-form message after it.
+fn skip_structured_data(rest: &str) -> &str {
+	if let Some(message) = rest.strip_prefix("- ") {
+		return message;
+	} else if rest == "-" {
+		return "";
+	}
+
+	// Structured data is a sequence of `[...]` elements, where `]` can be escaped inside param values.
+	let mut in_element = false;
+	let mut escaped = false;
+	for (i, c) in rest.char_indices() {
+		match c {
+			_ if escaped => escaped = false,
+			'\\' if in_element => escaped = true,
+			'[' if !in_element => in_element = true,
+			']' if in_element => in_element = false,
+			' ' if !in_element => return &rest[i + 1..],
+			_ if !in_element => return rest,
+			_ => {}
+		}
+	}
+
+	""
+}
+
+/// Parses the body of an RFC3164 message (everything after the priority), i.e. `Mmm dd hh:mm:ss [HOSTNAME] TAG: MSG`.
+/// Messages sent to the local socket by libc usually omit the hostname.
+fn parse_rfc3164<'a>(rest: &'a str, fields: &mut Vec<KV>) -> &'a str {
+	// The timestamp is a fixed 15 characters, e.g. `Oct 11 22:14:15`.
+	let rest = match rest.get(..16) {
+		Some(timestamp) if is_rfc3164_timestamp(timestamp) => &rest[16..],
+		_ => rest,
+	};
+
+	let (first, after_first) = rest.split_once(' ').unwrap_or((rest, ""));
+	let (tag, message) = if is_tag(first) {
+		(first, after_first)
+	} else {
+		match after_first.split_once(' ') {
+			Some((second, message)) if is_tag(second) => {
+				fields.push(KV::new(String::from("HOSTNAME"), first.to_owned()));
+				(second, message)
+			}
+			_ => return rest,
+		}
+	};
+
+	let tag = tag.trim_end_matches(':');
+	match tag.split_once('[') {
+		Some((identifier, pid)) => {
+			fields.push(KV::new(String::from("IDENTIFIER"), identifier.to_owned()));
+			fields.push(KV::new(String::from("PID"), pid.trim_end_matches(']').to_owned()));
+		}
+		None => fields.push(KV::new(String::from("IDENTIFIER"), tag.to_owned())),
+	}
+
+	message
+}
+
+/// Returns whether the given string looks like an RFC3164 timestamp followed by a space, e.g. `Oct 11 22:14:15 `.
+fn is_rfc3164_timestamp(timestamp: &str) -> bool {
+	let bytes = timestamp.as_bytes();
+	bytes[3] == b' ' && bytes[6] == b' ' && bytes[9] == b':' && bytes[12] == b':' && bytes[15] == b' '
+}
+
+/// Returns whether the given word is an RFC3164 tag, e.g. `sshd[123]:` or `cron:`.
+fn is_tag(word: &str) -> bool {
+	word.len() > 1 && word.ends_with(':')
+}
+
+#[cfg(test)]
+mod test {
+	use chrono::{DateTime, Utc};
+
+	use super::parse_syslog_message;
+
+	fn field<'a>(message: &'a crate::LogMessage, key: &str) -> Option<&'a str> {
+		message
+			.fields
+			.iter()
+			.find(|f| f.key == key)
+			.and_then(|f| f.value.as_str())
+	}
+
+	#[test]
+	fn test_rfc3164() {
+		let now = Utc::now();
+		let message = parse_syslog_message("<30>Oct 11 22:14:15 sshd[123]: Accepted publickey\n", now);
+		assert_eq!(message.message, "Accepted publickey");
+		assert_eq!(message.timestamp, now);
+		assert_eq!(field(&message, "FACILITY"), Some("daemon"));
+		assert_eq!(field(&message, "PRIORITY"), Some("info"));
+		assert_eq!(field(&message, "IDENTIFIER"), Some("sshd"));
+		assert_eq!(field(&message, "PID"), Some("123"));
+		assert_eq!(field(&message, "HOSTNAME"), None);
+
+		let message = parse_syslog_message("<34>Oct 11 22:14:15 mymachine su: 'su root' failed", now);
+		assert_eq!(message.message, "'su root' failed");
+		assert_eq!(field(&message, "FACILITY"), Some("auth"));
+		assert_eq!(field(&message, "PRIORITY"), Some("crit"));
+		assert_eq!(field(&message, "HOSTNAME"), Some("mymachine"));
+		assert_eq!(field(&message, "IDENTIFIER"), Some("su"));
+	}
+
+	#[test]
+	fn test_rfc5424() {
+		let now = Utc::now();
+		let message = parse_syslog_message(
+			"<165>1 2003-10-11T22:14:15.003Z mymachine evntslog - ID47 [exampleSDID@32473 iut=\"3\" eventID=\"1011\"] An application event",
+			now,
+		);
+
+		assert_eq!(message.message, "An application event");
+		assert_eq!(
+			message.timestamp,
+			DateTime::parse_from_rfc3339("2003-10-11T22:14:15.003Z").unwrap()
+		);
+		assert_eq!(field(&message, "FACILITY"), Some("local4"));
+		assert_eq!(field(&message, "PRIORITY"), Some("notice"));
+		assert_eq!(field(&message, "HOSTNAME"), Some("mymachine"));
+		assert_eq!(field(&message, "IDENTIFIER"), Some("evntslog"));
+		assert_eq!(field(&message, "PID"), None);
+		assert_eq!(field(&message, "MSGID"), Some("ID47"));
+
+		let message = parse_syslog_message("<34>1 - - su - - - 'su root' failed", now);
+		assert_eq!(message.message, "'su root' failed");
+		assert_eq!(message.timestamp, now);
+	}
+
+	#[test]
+	fn test_no_priority() {
+		let message = parse_syslog_message("just some text", Utc::now());
+		assert_eq!(message.message, "just some text");
+		assert_eq!(field(&message, "FACILITY"), Some("user"));
+		assert_eq!(field(&message, "PRIORITY"), Some("notice"));
+	}
+}
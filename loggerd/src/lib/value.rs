@@ -0,0 +1,236 @@
+use std::{
+	cmp::Ordering,
+	fmt::{self, Display, Formatter},
+	io::{self, ErrorKind},
+};
+
+use bytestruct_derive::{ByteStruct, Size};
+use serde::{Deserialize, Serialize};
+
+/// The type of a field value, as it is stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ByteStruct, Size)]
+#[repr(u8)]
+pub enum ValueType {
+	String,
+	Int,
+	Float,
+	Bool,
+	Bytes,
+}
+
+/// The value of a field of a log entry. Values are untagged when serialized, so that a plain string is still a valid
+/// value for clients that only know about string fields.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	String(String),
+	Bytes(Vec<u8>),
+}
+
+impl Value {
+	/// Parses a value from its textual form (e.g. in a filter), inferring its type. Anything that isn't a boolean or
+	/// a number is a string.
+	pub fn parse(s: &str) -> Self {
+		if let Ok(b) = s.parse() {
+			return Value::Bool(b);
+		}
+
+		if let Ok(i) = s.parse() {
+			return Value::Int(i);
+		}
+
+		// Don't let words like `inf` or `nan` turn into floats.
+		match s.parse::<f64>() {
+			Ok(f) if f.is_finite() => Value::Float(f),
+			_ => Value::String(s.to_owned()),
+		}
+	}
+
+	pub fn value_type(&self) -> ValueType {
+		match self {
+			Value::String(_) => ValueType::String,
+			Value::Int(_) => ValueType::Int,
+			Value::Float(_) => ValueType::Float,
+			Value::Bool(_) => ValueType::Bool,
+			Value::Bytes(_) => ValueType::Bytes,
+		}
+	}
+
+	/// Returns the value if it's a string.
+	pub fn as_str(&self) -> Option<&str> {
+		match self {
+			Value::String(s) => Some(s),
+			_ => None,
+		}
+	}
+
+	/// Returns the value as a number, if it's a number or a string containing one.
+	fn as_f64(&self) -> Option<f64> {
+		match self {
+			Value::Int(i) => Some(*i as f64),
+			Value::Float(f) => Some(*f),
+			Value::String(s) => s.parse().ok(),
+			_ => None,
+		}
+	}
+
+	/// Compares two values. Values of the same type compare naturally, and numbers compare numerically with other
+	/// numbers, including strings that hold numbers (as fields written before values were typed do). Anything else
+	/// compares lexically.
+	pub fn compare(&self, other: &Value) -> Option<Ordering> {
+		match (self, other) {
+			(Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+			(Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+			(Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+			(Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+			(a, b) => match (a.as_f64(), b.as_f64()) {
+				(Some(a), Some(b)) => a.partial_cmp(&b),
+				_ => Some(a.to_string().cmp(&b.to_string())),
+			},
+		}
+	}
+
+	/// Encodes the value into the bytes that are stored on disk. Numbers are little endian.
+	pub fn encode(&self) -> Vec<u8> {
+		match self {
+			Value::String(s) => s.as_bytes().to_vec(),
+			Value::Int(i) => i.to_le_bytes().to_vec(),
+			Value::Float(f) => f.to_le_bytes().to_vec(),
+			Value::Bool(b) => vec![*b as u8],
+			Value::Bytes(b) => b.clone(),
+		}
+	}
+
+	/// Decodes a value of the given type from the bytes that are stored on disk.
+	pub fn decode(value_type: ValueType, bytes: Vec<u8>) -> io::Result<Self> {
+		let invalid = || io::Error::new(ErrorKind::InvalidData, format!("invalid {:?} value", value_type));
+		Ok(match value_type {
+			ValueType::String => Value::String(String::from_utf8(bytes).map_err(|_| invalid())?),
+			ValueType::Int => Value::Int(i64::from_le_bytes(bytes.try_into().map_err(|_| invalid())?)),
+			ValueType::Float => Value::Float(f64::from_le_bytes(bytes.try_into().map_err(|_| invalid())?)),
+			ValueType::Bool => match bytes[..] {
+				[b] => Value::Bool(b != 0),
+				_ => return Err(invalid()),
+			},
+			ValueType::Bytes => Value::Bytes(bytes),
+		})
+	}
+}
+
+impl Display for Value {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Value::String(s) => f.write_str(s),
+			Value::Int(i) => write!(f, "{}", i),
+			Value::Float(n) => write!(f, "{}", n),
+			Value::Bool(b) => write!(f, "{}", b),
+			Value::Bytes(bytes) => {
+				for b in bytes {
+					write!(f, "{:02x}", b)?;
+				}
+
+				Ok(())
+			}
+		}
+	}
+}
+
+impl PartialEq<&str> for Value {
+	fn eq(&self, other: &&str) -> bool {
+		self.as_str() == Some(*other)
+	}
+}
+
+impl From<String> for Value {
+	fn from(value: String) -> Self {
+		Value::String(value)
+	}
+}
+
+impl From<&str> for Value {
+	fn from(value: &str) -> Self {
+		Value::String(value.to_owned())
+	}
+}
+
+impl From<i64> for Value {
+	fn from(value: i64) -> Self {
+		Value::Int(value)
+	}
+}
+
+impl From<f64> for Value {
+	fn from(value: f64) -> Self {
+		Value::Float(value)
+	}
+}
+
+impl From<bool> for Value {
+	fn from(value: bool) -> Self {
+		Value::Bool(value)
+	}
+}
+
+impl From<Vec<u8>> for Value {
+	fn from(value: Vec<u8>) -> Self {
+		Value::Bytes(value)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::cmp::Ordering;
+
+	use super::Value;
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(Value::parse("3"), Value::Int(3));
+		assert_eq!(Value::parse("-1.5"), Value::Float(-1.5));
+		assert_eq!(Value::parse("true"), Value::Bool(true));
+		assert_eq!(Value::parse("nan"), Value::String(String::from("nan")));
+		assert_eq!(Value::parse("sshd"), Value::String(String::from("sshd")));
+	}
+
+	#[test]
+	fn test_compare() {
+		assert_eq!(Value::Int(10).compare(&Value::Int(9)), Some(Ordering::Greater));
+		assert_eq!(Value::Float(2.5).compare(&Value::Int(3)), Some(Ordering::Less));
+		assert_eq!(Value::from("10").compare(&Value::Int(9)), Some(Ordering::Greater));
+		assert_eq!(Value::from("10").compare(&Value::from("9")), Some(Ordering::Less));
+		assert_eq!(Value::from("true").compare(&Value::Bool(true)), Some(Ordering::Equal));
+	}
+
+	#[test]
+	fn test_encode_decode() {
+		for value in [
+			Value::from("hello"),
+			Value::Int(-42),
+			Value::Float(0.25),
+			Value::Bool(true),
+			Value::Bytes(vec![0, 255]),
+		] {
+			assert_eq!(Value::decode(value.value_type(), value.encode()).unwrap(), value);
+		}
+
+		assert!(Value::decode(super::ValueType::Int, vec![1, 2]).is_err());
+	}
+
+	#[test]
+	fn test_deserialize_untagged() {
+		let values: Vec<Value> = serde_json::from_str(r#"["a", 1, 1.5, false, [1, 2]]"#).unwrap();
+		assert_eq!(
+			values,
+			vec![
+				Value::from("a"),
+				Value::Int(1),
+				Value::Float(1.5),
+				Value::Bool(false),
+				Value::Bytes(vec![1, 2])
+			]
+		);
+	}
+}
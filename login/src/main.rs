@@ -1,29 +1,128 @@
 use std::{
 	ffi::{CStr, CString},
-	io::{stderr, stdin},
+	io::stderr,
+	path::Path,
 	process::ExitCode,
+	time::SystemTime,
 };
 
-use anyhow::{Context, Result};
-use auth::User;
-use clap::{Arg, Command};
-use common::{io::IOTriple, obs::assemble_logger};
-use nix::{
-	sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg, Termios},
-	unistd::{chdir, execvp, setgid, setuid, Gid, Uid},
+use auth::{AuthError, LoginPolicy, LoginSession, Totp, User};
+use clap::{Arg, ArgAction, Command};
+use common::{
+	io::IOTriple,
+	lineedit::{self, Echo},
+	obs::assemble_logger,
 };
-use slog::error;
+use loggerd::{
+	control::{start_acked_write_stream_sync, AckMode},
+	DEFAULT_CONTROL_SOCKET_PATH, KV,
+};
+use nix::unistd::{chdir, execvp, getuid, setgid, setuid, Gid, Uid};
+use slog::{error, warn, Logger};
 
 const PASSWORD_ATTEMPTS: usize = 3;
 
-fn disable_echo() -> Result<Termios> {
-	let old_attrs = tcgetattr(stdin()).with_context(|| "failed to get terminal attributes")?;
+/// Asks the user for a code from their authenticator, if they have a second factor. Returns whether they're
+/// authenticated, which they are if they don't have one.
+fn verify_second_factor(logger: &Logger, username: &str) -> bool {
+	let mut totp = match Totp::for_user(username) {
+		Ok(Some(totp)) => totp,
+		Ok(None) => return true,
+		Err(e) => {
+			error!(logger, "Failed to read second factor"; "username" => username, "error" => e.to_string());
+			return false;
+		}
+	};
+
+	for _ in 0..PASSWORD_ATTEMPTS {
+		let triple = IOTriple::default();
+		let code = match lineedit::prompt(&triple, "verification code:", Echo::Normal) {
+			Ok(code) => code,
+			Err(e) => {
+				error!(logger, "Failed to read verification code"; "error" => format!("{:?}", e));
+				return false;
+			}
+		};
+
+		match totp.verify(code.trim(), SystemTime::now()) {
+			Ok(true) => return true,
+			Ok(false) => {
+				error!(logger, "Invalid verification code"; "username" => username);
+			}
+			Err(e) => {
+				error!(logger, "Failed to verify code"; "username" => username, "error" => e.to_string());
+				return false;
+			}
+		}
+	}
+
+	false
+}
+
+/// Asks the user for their password. Returns whether they got it right within the allowed attempts, or None if it
+/// couldn't be checked at all.
+fn verify_password(logger: &Logger, user: &User, username: &str) -> Option<bool> {
+	let shadow = match user.shadow() {
+		Ok(Some(shadow)) => shadow,
+		Ok(None) => {
+			error!(logger, "Shadow entry not found"; "username" => username);
+			return None;
+		}
+		Err(e) => {
+			error!(logger, "Failed to read shadow entry"; "username" => username, "error" => format!("{:?}", e));
+			return None;
+		}
+	};
+
+	for _ in 0..PASSWORD_ATTEMPTS {
+		let triple = IOTriple::default();
+		let password = match lineedit::prompt(&triple, "password:", Echo::Hidden) {
+			Ok(pass) => pass,
+			Err(e) => {
+				error!(logger, "Failed to read password"; "error" => format!("{:?}", e));
+				return None;
+			}
+		};
 
-	let mut new_attrs = old_attrs.clone();
-	new_attrs.local_flags.remove(LocalFlags::ECHO);
-	tcsetattr(stdin(), SetArg::TCSANOW, &new_attrs).with_context(|| "failed to set terminal attributes")?;
+		match shadow.verify_password(&password) {
+			Ok(true) => return Some(true),
+			Ok(false) => {
+				error!(logger, "Invalid password"; "username" => username);
+			}
+			Err(e) => {
+				error!(logger, "Failed to verify password"; "username" => username, "error" => format!("{:?}", e));
+				return None;
+			}
+		};
+	}
 
-	Ok(old_attrs)
+	Some(false)
+}
+
+/// Records the outcome of a login in loggerd, waiting until it's on disk so that it isn't lost if the machine goes
+/// down straight after. Logins still go ahead if it can't be recorded, so that a broken loggerd can't lock everyone out.
+/// Successful logins are recorded with the session that they started, so that what's done in it can be traced back.
+fn audit(logger: &Logger, username: &str, outcome: &str, session: Option<&LoginSession>) {
+	let mut fields = vec![
+		KV::new(String::from("IDENTIFIER"), String::from("login")),
+		KV::new(String::from("USERNAME"), username.to_owned()),
+		KV::new(String::from("OUTCOME"), outcome.to_owned()),
+	];
+
+	if let Some(session) = session {
+		fields.push(KV::new(String::from("LOGINUID"), session.loginuid.to_string()));
+		fields.push(KV::new(String::from("SESSION_ID"), session.session_id.to_string()));
+	}
+
+	let result = start_acked_write_stream_sync(Path::new(DEFAULT_CONTROL_SOCKET_PATH), fields, AckMode::Batch)
+		.and_then(|mut stream| {
+			stream.write_entry(&format!("login {} for {}", outcome, username))?;
+			stream.close()
+		});
+
+	if let Err(e) = result {
+		warn!(logger, "Failed to record login in the audit log"; "username" => username, "error" => e.to_string());
+	}
 }
 
 fn main() -> ExitCode {
@@ -37,18 +136,26 @@ fn main() -> ExitCode {
 				.required(true)
 				.index(1),
 		)
+		.arg(
+			Arg::new("allow-unlisted-shell")
+				.long("allow-unlisted-shell")
+				.help("Allow users to log in with shells that aren't listed in /etc/shells")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("preauthenticated")
+				.long("preauthenticated")
+				.short('f')
+				.help("Don't ask for a password, as the user has already been authenticated (e.g. by getty's autologin). Only root can do this")
+				.action(ArgAction::SetTrue),
+		)
 		.get_matches();
 
 	let username: &String = matches.get_one("username").unwrap();
-	let logger = assemble_logger(stderr());
-
-	let old_attrs = match disable_echo() {
-		Ok(attrs) => attrs,
-		Err(e) => {
-			error!(logger, "Failed to disable echo"; "error" => format!("{:?}", e));
-			return ExitCode::FAILURE;
-		}
+	let policy = LoginPolicy {
+		require_listed_shell: !matches.get_flag("allow-unlisted-shell"),
 	};
+	let logger = assemble_logger(stderr());
 
 	let user: User = match User::from_username(username) {
 		Ok(Some(user)) => user,
@@ -62,57 +169,53 @@ fn main() -> ExitCode {
 		}
 	};
 
-	let shadow = match user.shadow() {
-		Ok(Some(shadow)) => shadow,
-		Ok(None) => {
-			error!(logger, "Shadow entry not found"; "username" => username);
+	match user.is_login_allowed(&policy) {
+		Ok(()) => {}
+		Err(AuthError::NoLogin(message)) => {
+			println!("{}", message);
 			return ExitCode::FAILURE;
 		}
 		Err(e) => {
-			error!(logger, "Failed to read shadow entry"; "username" => username, "error" => format!("{:?}", e));
+			error!(logger, "Login not allowed"; "username" => username, "error" => e.to_string());
 			return ExitCode::FAILURE;
 		}
-	};
-
-	let mut successful = false;
-	for _ in 0..PASSWORD_ATTEMPTS {
-		let triple = IOTriple::default();
-		let password = match triple.prompt("password:") {
-			Ok(pass) => pass,
-			Err(e) => {
-				error!(logger, "Failed to read password"; "error" => format!("{:?}", e));
-				return ExitCode::FAILURE;
-			}
-		};
-
-		match shadow.verify_password(&password) {
-			Ok(true) => {
-				successful = true;
-				break;
-			}
-			Ok(false) => {
-				error!(logger, "Invalid password"; "username" => username);
-			}
-			Err(e) => {
-				error!(logger, "Failed to verify password"; "username" => username, "error" => format!("{:?}", e));
-				return ExitCode::FAILURE;
-			}
-		};
 	}
 
-	match tcsetattr(stdin(), SetArg::TCSANOW, &old_attrs) {
-		Ok(_) => (),
-		Err(e) => {
-			error!(logger, "Failed to restore terminal attributes"; "error" => format!("{:?}", e));
-			return ExitCode::FAILURE;
-		}
+	// Only root can skip authentication, as anyone else could use it to become anyone.
+	let preauthenticated = matches.get_flag("preauthenticated");
+	if preauthenticated && !getuid().is_root() {
+		error!(logger, "Only root can log in without a password"; "username" => username);
+		return ExitCode::FAILURE;
 	}
 
+	// The second factor is only asked for once the password is right, so that it doesn't tell anyone guessing
+	// passwords whether the user has one.
+	let successful = match preauthenticated {
+		true => true,
+		false => match verify_password(&logger, &user, username) {
+			Some(successful) => successful && verify_second_factor(&logger, username),
+			None => return ExitCode::FAILURE,
+		},
+	};
+
 	if !successful {
 		error!(logger, "Failed to login"; "username" => username);
+		audit(&logger, username, "failed", None);
 		return ExitCode::FAILURE;
 	}
 
+	// The session has to be started while still root, and before anything else is started, so that everything the
+	// user does is part of it.
+	let session = match LoginSession::begin(&user) {
+		Ok(session) => Some(session),
+		Err(e) => {
+			warn!(logger, "Failed to start a login session"; "username" => username, "error" => e.to_string());
+			None
+		}
+	};
+
+	audit(&logger, username, "succeeded", session.as_ref());
+
 	let shell = match CString::new(user.shell.to_string_lossy().into_owned()) {
 		Ok(shell) => shell,
 		Err(e) => {
@@ -1,4 +1,5 @@
 use std::{
+	cmp::Reverse,
 	fs,
 	os::unix::fs::MetadataExt,
 	path::{Path, PathBuf},
@@ -6,12 +7,42 @@ use std::{
 
 use anyhow::{Context, Result};
 use auth::{Group, User};
+use chrono::{DateTime, Local, TimeZone};
 use clap::{Arg, ArgAction, Command};
-use tables::{RowTable, Table};
+use tables::{Alignment, RowTable, Table, TableSetting};
+
+const S_IFMT: u32 = 0o170000; // the bits of the mode that hold the file type
+const S_IFSOCK: u32 = 0o140000; // socket
+const S_IFLNK: u32 = 0o120000; // symbolic link
+const S_IFBLK: u32 = 0o060000; // block device
+const S_IFDIR: u32 = 0o040000; // directory
+const S_IFCHR: u32 = 0o020000; // character device
+const S_IFIFO: u32 = 0o010000; // FIFO
+const S_ISUID: u32 = 0o4000; // set user ID on execution
+const S_ISGID: u32 = 0o2000; // set group ID on execution
+const S_ISVTX: u32 = 0o1000; // sticky
+
+/// How long ago a file can have been modified and still have the time it was modified at shown, rather than the
+/// year. This is half of an average Gregorian year, as in coreutils.
+const RECENT_SECONDS: i64 = 31556952 / 2;
+
+/// What the files in a directory are listed in order of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+	Name,
+
+	/// Newest first.
+	Mtime,
+
+	/// Largest first.
+	Size,
+}
 
 struct LsArgs {
 	all: bool,
 	recursive: bool,
+	sort: SortKey,
+	reverse: bool,
 }
 
 struct LsFile {
@@ -24,8 +55,14 @@ struct LsFile {
 	mtime: i64,
 }
 
+impl LsFile {
+	fn is_dir(&self) -> bool {
+		self.mode & S_IFMT == S_IFDIR
+	}
+}
+
 fn ls_dir(file: &Path, args: &LsArgs) -> Result<Vec<LsFile>> {
-	let mut result = Vec::new();
+	let mut files = Vec::new();
 	let entries = fs::read_dir(file).with_context(|| format!("failed to read directory {}", file.display()))?;
 	for entry in entries {
 		let entry = entry?;
@@ -44,22 +81,106 @@ fn ls_dir(file: &Path, args: &LsArgs) -> Result<Vec<LsFile>> {
 				mtime: metadata.mtime(),
 			};
 
-			result.push(ls_file);
+			files.push(ls_file);
+		}
+	}
 
-			if args.recursive
-				&& entry
-					.file_type()
-					.with_context(|| format!("failed to get file type for {}", name.to_string_lossy()))?
-					.is_dir()
-			{
-				result.append(&mut ls_dir(&entry.path(), args)?);
-			}
+	sort_files(&mut files, args);
+
+	let mut result = Vec::new();
+	for ls_file in files {
+		// Subdirectories are listed straight after themselves, so sorting them has to happen one directory at a time.
+		let subdirectory = (args.recursive && ls_file.is_dir()).then(|| file.join(&ls_file.name));
+		result.push(ls_file);
+
+		if let Some(subdirectory) = subdirectory {
+			result.append(&mut ls_dir(&subdirectory, args)?);
 		}
 	}
 
 	Ok(result)
 }
 
+/// Sorts the files in the order asked for. Files that are equal by the key are sorted by name, so the order is stable.
+fn sort_files(files: &mut [LsFile], args: &LsArgs) {
+	match args.sort {
+		SortKey::Name => files.sort_by(|a, b| a.name.cmp(&b.name)),
+		SortKey::Mtime => files.sort_by(|a, b| (Reverse(a.mtime), &a.name).cmp(&(Reverse(b.mtime), &b.name))),
+		SortKey::Size => files.sort_by(|a, b| (Reverse(a.size), &a.name).cmp(&(Reverse(b.size), &b.name))),
+	}
+
+	if args.reverse {
+		files.reverse();
+	}
+}
+
+/// Returns the mode of a file as a string like `drwxr-xr-x`: its type, then the permissions of its owner, its group,
+/// and everyone else.
+fn mode_string(mode: u32) -> String {
+	let file_type = match mode & S_IFMT {
+		S_IFDIR => 'd',
+		S_IFLNK => 'l',
+		S_IFCHR => 'c',
+		S_IFBLK => 'b',
+		S_IFIFO => 'p',
+		S_IFSOCK => 's',
+		_ => '-',
+	};
+
+	// Each class of user has its read, write, and execute bits, and a special bit that replaces its execute bit: `s`
+	// (or `S` if it can't execute) for setuid and setgid, and `t` (or `T`) for the sticky bit.
+	let classes = [(6, S_ISUID, 's'), (3, S_ISGID, 's'), (0, S_ISVTX, 't')];
+
+	let mut string = String::from(file_type);
+	for (shift, special, special_char) in classes {
+		let bits = (mode >> shift) & 0o7;
+		string.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+		string.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+		string.push(match (bits & 0o1 != 0, mode & special != 0) {
+			(true, true) => special_char,
+			(false, true) => special_char.to_ascii_uppercase(),
+			(true, false) => 'x',
+			(false, false) => '-',
+		});
+	}
+
+	string
+}
+
+/// Formats the time that a file was modified like coreutils does: the date and time for files modified in the last
+/// six months, and the date and year for older files (or ones modified in the future).
+fn format_mtime<Tz: TimeZone>(mtime: DateTime<Tz>, now: DateTime<Tz>) -> String
+where
+	Tz::Offset: std::fmt::Display,
+{
+	let age = now.timestamp() - mtime.timestamp();
+	if (0..RECENT_SECONDS).contains(&age) {
+		mtime.format("%b %e %H:%M").to_string()
+	} else {
+		mtime.format("%b %e  %Y").to_string()
+	}
+}
+
+/// Formats a size in bytes with a binary unit suffix, e.g. 1.5K, rounding up like coreutils.
+fn human_size(bytes: u64) -> String {
+	const UNITS: [&str; 7] = ["", "K", "M", "G", "T", "P", "E"];
+
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+
+	if unit == 0 {
+		bytes.to_string()
+	} else if size < 10.0 {
+		format!("{:.1}{}", (size * 10.0).ceil() / 10.0, UNITS[unit])
+	} else {
+		format!("{}{}", size.ceil(), UNITS[unit])
+	}
+}
+
 fn ls_file(file: &Path) -> Result<LsFile> {
 	let stat = fs::metadata(file).with_context(|| format!("failed to get metadata for {}", file.display()))?;
 	Ok(LsFile {
@@ -114,15 +235,56 @@ fn main() {
 				.help("do not ignore entries starting with .")
 				.action(ArgAction::SetTrue),
 		)
+		.arg(
+			Arg::new("time")
+				.short('t')
+				.help("sort by modification time, newest first")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("size")
+				.short('S')
+				.help("sort by file size, largest first")
+				.action(ArgAction::SetTrue)
+				.overrides_with("time"),
+		)
+		.arg(
+			Arg::new("reverse")
+				.short('r')
+				.long("reverse")
+				.help("reverse the order of the sort")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("human-readable")
+				.short('h')
+				.long("human-readable")
+				.help("with -l, print sizes like 1K 234M 2G")
+				.action(ArgAction::SetTrue),
+		)
+		.disable_help_flag(true)
+		.arg(Arg::new("help").long("help").help("print help").action(ArgAction::Help))
 		.get_matches();
 
+	let sort = if matches.get_flag("time") {
+		SortKey::Mtime
+	} else if matches.get_flag("size") {
+		SortKey::Size
+	} else {
+		SortKey::Name
+	};
+
 	let args = LsArgs {
 		all: *matches.get_one("all").expect("all is missing"),
 		recursive: *matches.get_one("recursive").expect("recursive is missing"),
+		sort,
+		reverse: matches.get_flag("reverse"),
 	};
 
 	let paths: Vec<String> = matches.get_many("file").expect("file is missing").cloned().collect();
 	let long = *matches.get_one("long").expect("long is missing");
+	let human_readable = matches.get_flag("human-readable");
+	let now = Local::now();
 	for path in paths {
 		let files = match ls(&PathBuf::from(&path), &args) {
 			Ok(files) => files,
@@ -133,7 +295,10 @@ fn main() {
 		};
 
 		if long {
-			let mut table = Table::new();
+			let mut table = Table::new()
+				.with_setting(TableSetting::Alignment(1, Alignment::Right))
+				.with_setting(TableSetting::Alignment(4, Alignment::Right))
+				.with_setting(TableSetting::FitTerminal);
 			for file in files {
 				let username = match User::from_uid(file.uid) {
 					Ok(Some(user)) => user.username,
@@ -145,13 +310,24 @@ fn main() {
 					_ => file.gid.to_string(),
 				};
 
+				let size = if human_readable {
+					human_size(file.size)
+				} else {
+					file.size.to_string()
+				};
+
+				let mtime = match Local.timestamp_opt(file.mtime, 0).single() {
+					Some(mtime) => format_mtime(mtime, now),
+					None => file.mtime.to_string(),
+				};
+
 				table.add_row([
-					&file.mode.to_string(),
+					&mode_string(file.mode),
 					&file.nlink.to_string(),
 					&username,
 					&group,
-					&file.size.to_string(),
-					&file.mtime.to_string(),
+					&size,
+					&mtime,
 					file.name.to_string_lossy().as_ref(),
 				]);
 			}
@@ -167,3 +343,48 @@ fn main() {
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use chrono::{TimeZone, Utc};
+
+	use super::{format_mtime, human_size, mode_string};
+
+	#[test]
+	fn test_mode_string() {
+		assert_eq!(mode_string(0o040755), "drwxr-xr-x");
+		assert_eq!(mode_string(0o100644), "-rw-r--r--");
+		assert_eq!(mode_string(0o120777), "lrwxrwxrwx");
+		assert_eq!(mode_string(0o104755), "-rwsr-xr-x");
+		assert_eq!(mode_string(0o102644), "-rw-r-Sr--");
+		assert_eq!(mode_string(0o041777), "drwxrwxrwt");
+		assert_eq!(mode_string(0o020620), "crw--w----");
+	}
+
+	#[test]
+	fn test_format_mtime() {
+		let now = Utc.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap();
+		assert_eq!(
+			format_mtime(Utc.with_ymd_and_hms(2024, 3, 5, 9, 7, 0).unwrap(), now),
+			"Mar  5 09:07"
+		);
+		assert_eq!(
+			format_mtime(Utc.with_ymd_and_hms(2023, 11, 20, 9, 7, 0).unwrap(), now),
+			"Nov 20  2023"
+		);
+		assert_eq!(
+			format_mtime(Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap(), now),
+			"Jul  1  2024"
+		);
+	}
+
+	#[test]
+	fn test_human_size() {
+		assert_eq!(human_size(0), "0");
+		assert_eq!(human_size(1023), "1023");
+		assert_eq!(human_size(1024), "1.0K");
+		assert_eq!(human_size(1536), "1.5K");
+		assert_eq!(human_size(4097), "4.1K");
+		assert_eq!(human_size(15 * 1024 * 1024 + 1), "16M");
+	}
+}
@@ -0,0 +1,183 @@
+use std::{
+	collections::HashMap,
+	fs::{self, read_dir, read_to_string},
+	io,
+	path::Path,
+};
+
+use anyhow::{Context, Result};
+
+/// The directory in sysfs that has an entry for every block device, including partitions.
+pub const SYSFS_BLOCK_CLASS: &str = "/sys/class/block";
+
+/// Block device sizes in sysfs are always in 512 byte sectors, regardless of the device's logical sector size.
+const SYSFS_SECTOR_SIZE: u64 = 512;
+
+/// The kind of a block device, shown in the TYPE column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+	Disk,
+	Partition,
+	Loop,
+	Rom,
+	Raid,
+	Lvm,
+	Crypt,
+	DeviceMapper,
+}
+
+impl DeviceKind {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Disk => "disk",
+			Self::Partition => "part",
+			Self::Loop => "loop",
+			Self::Rom => "rom",
+			Self::Raid => "raid",
+			Self::Lvm => "lvm",
+			Self::Crypt => "crypt",
+			Self::DeviceMapper => "dm",
+		}
+	}
+}
+
+/// A block device, as described by sysfs.
+#[derive(Debug)]
+pub struct BlockDevice {
+	/// The kernel name of the device, e.g. sda1.
+	pub name: String,
+	pub major: u32,
+	pub minor: u32,
+
+	/// The size of the device, in bytes.
+	pub size: u64,
+	pub read_only: bool,
+	pub removable: bool,
+	pub kind: DeviceKind,
+
+	/// The number of the partition, if this device is one.
+	pub partition: Option<u32>,
+
+	/// The disk that this partition is on, if this device is a partition.
+	pub parent: Option<String>,
+
+	/// The devices that are built on top of this one, e.g. device mapper or RAID devices.
+	pub holders: Vec<String>,
+
+	/// The devices that this one is built on top of.
+	pub slaves: Vec<String>,
+}
+
+impl BlockDevice {
+	/// Reads the device with the given entry in /sys/class/block.
+	fn read(sysfs_path: &Path) -> Result<Self> {
+		let name = sysfs_path
+			.file_name()
+			.map(|name| name.to_string_lossy().into_owned())
+			.context("block device has no name")?;
+
+		let dev = read_attribute(sysfs_path, "dev")?.context("block device has no device number")?;
+		let (major, minor) = dev
+			.split_once(':')
+			.and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+			.with_context(|| format!("invalid device number for {}: {}", name, dev))?;
+
+		let size = read_attribute(sysfs_path, "size")?
+			.and_then(|size| size.parse::<u64>().ok())
+			.unwrap_or(0);
+
+		let partition = read_attribute(sysfs_path, "partition")?.and_then(|partition| partition.parse().ok());
+
+		// Partitions live in the directory of their disk, so the disk is the parent in the real path.
+		let parent = match partition {
+			Some(_) => fs::canonicalize(sysfs_path)?
+				.parent()
+				.and_then(|parent| parent.file_name())
+				.map(|parent| parent.to_string_lossy().into_owned()),
+			None => None,
+		};
+
+		let kind = match partition {
+			Some(_) => DeviceKind::Partition,
+			None => Self::kind(sysfs_path, &name)?,
+		};
+
+		Ok(Self {
+			major,
+			minor,
+			size: size * SYSFS_SECTOR_SIZE,
+			read_only: read_attribute(sysfs_path, "ro")?.is_some_and(|ro| ro == "1"),
+			removable: read_attribute(sysfs_path, "removable")?.is_some_and(|removable| removable == "1"),
+			kind,
+			partition,
+			parent,
+			holders: list_dir(&sysfs_path.join("holders"))?,
+			slaves: list_dir(&sysfs_path.join("slaves"))?,
+			name,
+		})
+	}
+
+	/// Works out the kind of a whole (i.e. non partition) device.
+	fn kind(sysfs_path: &Path, name: &str) -> Result<DeviceKind> {
+		// Device mapper devices have a UUID prefixed by the subsystem that created them.
+		if let Some(uuid) = read_attribute(&sysfs_path.join("dm"), "uuid")? {
+			return Ok(match uuid.split_once('-').map(|(prefix, _)| prefix) {
+				Some("LVM") => DeviceKind::Lvm,
+				Some("CRYPT") => DeviceKind::Crypt,
+				_ => DeviceKind::DeviceMapper,
+			});
+		}
+
+		if sysfs_path.join("md").exists() {
+			return Ok(DeviceKind::Raid);
+		}
+
+		if name.starts_with("loop") {
+			Ok(DeviceKind::Loop)
+		} else if name.starts_with("sr") {
+			Ok(DeviceKind::Rom)
+		} else {
+			Ok(DeviceKind::Disk)
+		}
+	}
+}
+
+/// Reads every block device in sysfs, keyed by name.
+pub fn read_block_devices() -> Result<HashMap<String, BlockDevice>> {
+	let mut devices = HashMap::new();
+	let entries = read_dir(SYSFS_BLOCK_CLASS).with_context(|| format!("failed to read {}", SYSFS_BLOCK_CLASS))?;
+	for entry in entries {
+		let entry = entry?;
+		let device =
+			BlockDevice::read(&entry.path()).with_context(|| format!("failed to read {}", entry.path().display()))?;
+		devices.insert(device.name.clone(), device);
+	}
+
+	Ok(devices)
+}
+
+/// Reads a sysfs attribute of the device, returning None if the device doesn't have it.
+fn read_attribute(sysfs_path: &Path, attribute: &str) -> io::Result<Option<String>> {
+	match read_to_string(sysfs_path.join(attribute)) {
+		Ok(value) => Ok(Some(value.trim().to_owned())),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(e),
+	}
+}
+
+/// Lists the names of the entries in the given directory, which is empty if it doesn't exist.
+fn list_dir(path: &Path) -> io::Result<Vec<String>> {
+	let entries = match read_dir(path) {
+		Ok(entries) => entries,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(e),
+	};
+
+	let mut names = Vec::new();
+	for entry in entries {
+		names.push(entry?.file_name().to_string_lossy().into_owned());
+	}
+
+	names.sort();
+	Ok(names)
+}
@@ -0,0 +1,256 @@
+mod block;
+mod mounts;
+
+use std::{
+	collections::{HashMap, HashSet},
+	path::Path,
+	process::ExitCode,
+};
+
+use anyhow::Result;
+use block::{read_block_devices, BlockDevice};
+use clap::{Arg, ArgAction, Command};
+use mounts::{read_mountpoints, read_swaps};
+use superblocks::Device;
+use tables::{OutputFormat, Table};
+
+/// The directory that device nodes live in.
+const DEV_ROOT: &str = "/dev";
+
+/// The maximum depth of the device tree, so that a loop of holders can't recurse forever.
+const MAX_DEPTH: usize = 16;
+
+const HEADERS: [&str; 10] = [
+	"NAME",
+	"MAJ:MIN",
+	"RM",
+	"SIZE",
+	"RO",
+	"TYPE",
+	"FSTYPE",
+	"LABEL",
+	"UUID",
+	"MOUNTPOINTS",
+];
+
+struct LsblkArgs {
+	all: bool,
+	bytes: bool,
+	output: OutputFormat,
+}
+
+/// Everything known about the devices on the system, used to build the tree.
+struct Devices {
+	devices: HashMap<String, BlockDevice>,
+	mountpoints: HashMap<(u32, u32), Vec<String>>,
+	swaps: HashSet<String>,
+}
+
+impl Devices {
+	/// Returns the devices at the top of the tree: those that aren't partitions, and aren't built on other devices.
+	fn roots(&self, args: &LsblkArgs) -> Vec<&BlockDevice> {
+		let mut roots: Vec<_> = self
+			.devices
+			.values()
+			.filter(|device| device.parent.is_none() && device.slaves.is_empty())
+			// Unbound loop devices, and empty drives, have no size and are noise.
+			.filter(|device| args.all || device.size > 0)
+			.collect();
+
+		roots.sort_by_key(|device| (device.major, device.minor));
+		roots
+	}
+
+	/// Returns the devices below the given one in the tree: its partitions, then the devices built on it.
+	fn children(&self, device: &BlockDevice) -> Vec<&BlockDevice> {
+		let mut partitions: Vec<_> = self
+			.devices
+			.values()
+			.filter(|child| child.parent.as_ref() == Some(&device.name))
+			.collect();
+		partitions.sort_by_key(|partition| partition.partition);
+
+		let holders = device.holders.iter().filter_map(|holder| self.devices.get(holder));
+		partitions.into_iter().chain(holders).collect()
+	}
+
+	fn mountpoints(&self, device: &BlockDevice) -> String {
+		let mut mountpoints = self
+			.mountpoints
+			.get(&(device.major, device.minor))
+			.cloned()
+			.unwrap_or_default();
+
+		if self.swaps.contains(&device.name) {
+			mountpoints.push(String::from("[SWAP]"));
+		}
+
+		mountpoints.join(",")
+	}
+}
+
+/// Adds the device, and everything below it, to the table. `prefix` is drawn before the device's own branch, to
+/// continue the branches of its ancestors.
+fn add_device(
+	table: &mut Table<10>,
+	devices: &Devices,
+	args: &LsblkArgs,
+	device: &BlockDevice,
+	prefix: &str,
+	branch: &str,
+	depth: usize,
+) {
+	// Devices that can't be read (e.g. when not running as root) are still listed, without their filesystem.
+	let filesystem = match device.size {
+		0 => None,
+		_ => Device::new(&Path::new(DEV_ROOT).join(&device.name))
+			.probe()
+			.ok()
+			.flatten(),
+	};
+
+	let size = if args.bytes {
+		device.size.to_string()
+	} else {
+		human_size(device.size)
+	};
+
+	let (fs_type, label, uuid) = match &filesystem {
+		Some(filesystem) => (
+			filesystem.filesystem_type.clone(),
+			filesystem.label.clone(),
+			filesystem.uuid_string(),
+		),
+		None => (String::new(), String::new(), String::new()),
+	};
+
+	// The tree is only drawn for people, programs would have to strip it back off the names.
+	let name = match args.output {
+		OutputFormat::Table => format!("{}{}{}", prefix, branch, device.name),
+		_ => device.name.clone(),
+	};
+
+	table.add_row([
+		&name,
+		&format!("{}:{}", device.major, device.minor),
+		if device.removable { "1" } else { "0" },
+		&size,
+		if device.read_only { "1" } else { "0" },
+		device.kind.name(),
+		&fs_type,
+		&label,
+		&uuid,
+		&devices.mountpoints(device),
+	]);
+
+	if depth >= MAX_DEPTH {
+		return;
+	}
+
+	// The children's branches hang off this device's, so continue its line if it has siblings below it.
+	let child_prefix = match branch {
+		"|-" => format!("{}| ", prefix),
+		"`-" => format!("{}  ", prefix),
+		_ => prefix.to_owned(),
+	};
+
+	let children = devices.children(device);
+	for (i, child) in children.iter().enumerate() {
+		let branch = if i == children.len() - 1 { "`-" } else { "|-" };
+		add_device(table, devices, args, child, &child_prefix, branch, depth + 1);
+	}
+}
+
+/// Formats a size in bytes with a binary unit suffix, e.g. 1.5G.
+fn human_size(bytes: u64) -> String {
+	const UNITS: [&str; 7] = ["B", "K", "M", "G", "T", "P", "E"];
+
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+
+	let size = format!("{:.1}", size);
+	format!("{}{}", size.trim_end_matches(".0"), UNITS[unit])
+}
+
+fn lsblk(args: &LsblkArgs) -> Result<Table<10>> {
+	let devices = Devices {
+		devices: read_block_devices()?,
+		mountpoints: read_mountpoints()?,
+		swaps: read_swaps()?,
+	};
+
+	let mut table = Table::new_with_headers(HEADERS);
+	for root in devices.roots(args) {
+		add_device(&mut table, &devices, args, root, "", "", 0);
+	}
+
+	Ok(table)
+}
+
+fn main() -> ExitCode {
+	let matches = Command::new("lsblk")
+		.version("0.1.0")
+		.author("Colin Douch <colin@quirl.co.nz>")
+		.about("List block devices")
+		.arg(
+			Arg::new("all")
+				.short('a')
+				.long("all")
+				.help("Include empty devices")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("bytes")
+				.short('b')
+				.long("bytes")
+				.help("Print sizes in bytes")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("output")
+				.long("output")
+				.help("The format to print the devices in")
+				.value_parser(OutputFormat::NAMES)
+				.default_value("table"),
+		)
+		.get_matches();
+
+	let args = LsblkArgs {
+		all: matches.get_flag("all"),
+		bytes: matches.get_flag("bytes"),
+		output: matches
+			.get_one::<String>("output")
+			.expect("BUG: output has a default")
+			.parse()
+			.expect("BUG: output is one of the format names"),
+	};
+
+	match lsblk(&args) {
+		Ok(table) => {
+			print!("{}", table.render(args.output));
+			ExitCode::SUCCESS
+		}
+		Err(e) => {
+			eprintln!("lsblk: {:#}", e);
+			ExitCode::FAILURE
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::human_size;
+
+	#[test]
+	fn test_human_size() {
+		assert_eq!(human_size(0), "0B");
+		assert_eq!(human_size(512), "512B");
+		assert_eq!(human_size(1536), "1.5K");
+		assert_eq!(human_size(20 * 1024 * 1024 * 1024), "20G");
+		assert_eq!(human_size(512110190592), "476.9G");
+	}
+}
@@ -0,0 +1,45 @@
+use std::{
+	collections::{HashMap, HashSet},
+	fs::{self, read_to_string},
+	io,
+};
+
+use mount::mounts::{read_mounts, unescape, MountsError};
+
+/// The active swap areas.
+const SWAPS_PATH: &str = "/proc/swaps";
+
+/// Reads the mount points of every mounted device, keyed by device number. mountinfo is used over /proc/mounts as it
+/// has the device number, which doesn't depend on what path the device was mounted from.
+pub fn read_mountpoints() -> Result<HashMap<(u32, u32), Vec<String>>, MountsError> {
+	let mut mountpoints: HashMap<_, Vec<_>> = HashMap::new();
+	for mount in read_mounts()? {
+		mountpoints
+			.entry(mount.device)
+			.or_default()
+			.push(mount.mount_point.to_string_lossy().into_owned());
+	}
+
+	Ok(mountpoints)
+}
+
+/// Reads the names of the devices that are in use as swap.
+pub fn read_swaps() -> io::Result<HashSet<String>> {
+	let swaps = match read_to_string(SWAPS_PATH) {
+		Ok(swaps) => swaps,
+		// Kernels without swap support don't have the file at all.
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+		Err(e) => return Err(e),
+	};
+
+	// The first line is a header.
+	let names = swaps
+		.lines()
+		.skip(1)
+		.filter_map(|line| line.split_ascii_whitespace().next())
+		.filter_map(|path| fs::canonicalize(unescape(path)).ok())
+		.filter_map(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+		.collect();
+
+	Ok(names)
+}
@@ -21,6 +21,13 @@ fn main() -> ExitCode {
 				.help("the parameters to pass to the module")
 				.num_args(0..),
 		)
+		.arg(
+			Arg::new("quiet")
+				.short('q')
+				.long("quiet")
+				.action(ArgAction::SetTrue)
+				.help("don't print an error if the module can't be loaded, as when the kernel runs modprobe"),
+		)
 		.arg(
 			Arg::new("modules_path")
 				.long("modules-path")
@@ -54,7 +61,10 @@ fn main() -> ExitCode {
 	match load_module(&logger, &modules_path, module_name, &parameters) {
 		Ok(()) => ExitCode::SUCCESS,
 		Err(e) => {
-			eprintln!("failed to load module: {}", e);
+			if !matches.get_flag("quiet") {
+				eprintln!("failed to load module: {}", e);
+			}
+
 			ExitCode::FAILURE
 		}
 	}
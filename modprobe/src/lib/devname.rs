@@ -0,0 +1,111 @@
+use std::{
+	fs::File,
+	io::{self, BufRead, BufReader},
+	path::Path,
+};
+
+use slog::warn;
+
+/// Whether a device node is a character or block device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceNodeKind {
+	Char,
+	Block,
+}
+
+/// A device node that should exist before the module that provides it is loaded, so that opening the node makes the
+/// kernel load the module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticDeviceNode {
+	/// The name of the module that provides the device.
+	pub module: String,
+
+	/// The path of the node, relative to /dev.
+	pub devname: String,
+
+	pub kind: DeviceNodeKind,
+	pub major: u32,
+	pub minor: u32,
+}
+
+impl StaticDeviceNode {
+	/// Parses a line of modules.devname, in the form `<module> <devname> <c|b><major>:<minor>`.
+	fn parse(line: &str) -> Option<Self> {
+		let mut parts = line.split_ascii_whitespace();
+		let (module, devname, number) = (parts.next()?, parts.next()?, parts.next()?);
+		if parts.next().is_some() {
+			return None;
+		}
+
+		let kind = match number.chars().next()? {
+			'c' => DeviceNodeKind::Char,
+			'b' => DeviceNodeKind::Block,
+			_ => return None,
+		};
+
+		let (major, minor) = number[1..].split_once(':')?;
+
+		// The devname is joined onto /dev, so mustn't be able to escape it.
+		if devname.starts_with('/') || devname.split('/').any(|part| part == "..") {
+			return None;
+		}
+
+		Some(Self {
+			module: module.to_owned(),
+			devname: devname.to_owned(),
+			kind,
+			major: major.parse().ok()?,
+			minor: minor.parse().ok()?,
+		})
+	}
+}
+
+/// Loads the modules.devname file, returning the device nodes that should be created before their modules are loaded.
+pub fn load_static_nodes(logger: &slog::Logger, devname_path: &Path) -> io::Result<Vec<StaticDeviceNode>> {
+	let devname_file = BufReader::new(File::open(devname_path)?);
+	let mut nodes = Vec::new();
+
+	for line in devname_file.lines() {
+		let line = line?;
+		if line.starts_with('#') || line.trim().is_empty() {
+			continue;
+		}
+
+		match StaticDeviceNode::parse(&line) {
+			Some(node) => nodes.push(node),
+			None => warn!(logger, "invalid line in modules.devname: {}", line),
+		}
+	}
+
+	Ok(nodes)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{DeviceNodeKind, StaticDeviceNode};
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(
+			StaticDeviceNode::parse("fuse fuse c10:229"),
+			Some(StaticDeviceNode {
+				module: String::from("fuse"),
+				devname: String::from("fuse"),
+				kind: DeviceNodeKind::Char,
+				major: 10,
+				minor: 229,
+			})
+		);
+
+		let node = StaticDeviceNode::parse("floppy fd0 b2:0").unwrap();
+		assert_eq!(node.kind, DeviceNodeKind::Block);
+
+		assert_eq!(
+			StaticDeviceNode::parse("tun net/tun c10:200").unwrap().devname,
+			"net/tun"
+		);
+		assert_eq!(StaticDeviceNode::parse("fuse fuse x10:229"), None);
+		assert_eq!(StaticDeviceNode::parse("fuse fuse c10"), None);
+		assert_eq!(StaticDeviceNode::parse("evil ../etc/passwd c1:3"), None);
+	}
+}
@@ -1,4 +1,6 @@
-#![feature(hash_extract_if)]
+mod devname;
+
+pub use devname::*;
 use lzma_rs::xz_decompress;
 use std::{
 	collections::HashMap,
@@ -8,7 +10,10 @@ use std::{
 	path::{Path, PathBuf},
 };
 
-use nix::kmod::init_module;
+use nix::{
+	kmod::{delete_module, init_module, DeleteModuleFlags},
+	sys::utsname::uname,
+};
 use slog::{debug, warn};
 use thiserror::Error;
 
@@ -27,6 +32,44 @@ pub enum ModuleLoadError {
 	ModuleLoadError(#[from] nix::Error),
 }
 
+/// The directory that has the modules of each kernel release, in a directory named after the release.
+pub const MODULES_ROOT: &str = "/lib/modules";
+
+/// The file that lists the modules that are loaded, one per line starting with the name of the module.
+pub const PROC_MODULES: &str = "/proc/modules";
+
+/// Returns the directory of the modules of the running kernel.
+pub fn default_modules_path() -> nix::Result<PathBuf> {
+	Ok(Path::new(MODULES_ROOT).join(uname()?.release()))
+}
+
+/// The name that the kernel knows a module by, which has underscores where its file name might have dashes.
+fn normalize_module_name(name: &str) -> String {
+	name.replace('-', "_")
+}
+
+/// Whether the module with the given name is loaded, i.e. it's in /proc/modules. Modules built into the kernel
+/// aren't listed there.
+pub fn is_module_loaded(name: &str) -> io::Result<bool> {
+	let name = normalize_module_name(name);
+	let modules = BufReader::new(File::open(PROC_MODULES)?);
+	for line in modules.lines() {
+		if line?.split_ascii_whitespace().next() == Some(name.as_str()) {
+			return Ok(true);
+		}
+	}
+
+	Ok(false)
+}
+
+/// Unloads the module with the given name, failing if it's in use rather than waiting for it not to be.
+pub fn unload_module(name: &str) -> Result<(), ModuleLoadError> {
+	let name =
+		CString::new(normalize_module_name(name)).map_err(|_| ModuleLoadError::UnknownModule(name.to_owned()))?;
+	delete_module(&name, DeleteModuleFlags::O_NONBLOCK)?;
+	Ok(())
+}
+
 fn load_file(path: &Path) -> io::Result<Vec<u8>> {
 	let mut file = BufReader::new(File::open(path)?);
 	let mut buffer = Vec::new();
@@ -43,15 +86,42 @@ fn load_file(path: &Path) -> io::Result<Vec<u8>> {
 	Ok(buffer)
 }
 
-/// Intelligently loads the module with the given name, resolving dependencies and paths.
+/// Intelligently loads the module with the given name, resolving dependencies and paths. If there's no module with
+/// the name, it's looked up as an alias instead, e.g. the `char-major-10-229` that the kernel asks for when a
+/// static device node is opened.
 pub fn load_module(
 	logger: &slog::Logger,
 	module_base_path: &Path,
 	mod_name: &str,
 	parameters: &[String],
 ) -> Result<(), ModuleLoadError> {
-	let modules_to_load = find_modules_to_load(logger, mod_name, &module_base_path.join("modules.dep"))?;
 	let module_paths = load_module_names(logger, &module_base_path.join("modules.name"))?;
+	if module_paths.contains_key(mod_name) {
+		return load_module_with_dependencies(logger, module_base_path, &module_paths, mod_name, parameters);
+	}
+
+	let modules = find_modules_for_alias(logger, mod_name, &module_base_path.join("modules.alias"))?;
+	if modules.is_empty() {
+		return Err(ModuleLoadError::UnknownModule(mod_name.to_owned()));
+	}
+
+	for module in modules {
+		debug!(logger, "resolved alias"; "alias" => mod_name, "name" => &module);
+		load_module_with_dependencies(logger, module_base_path, &module_paths, &module, parameters)?;
+	}
+
+	Ok(())
+}
+
+/// Loads the module with the given name, after the modules that it depends on.
+fn load_module_with_dependencies(
+	logger: &slog::Logger,
+	module_base_path: &Path,
+	module_paths: &HashMap<String, PathBuf>,
+	mod_name: &str,
+	parameters: &[String],
+) -> Result<(), ModuleLoadError> {
+	let modules_to_load = find_modules_to_load(logger, mod_name, &module_base_path.join("modules.dep"))?;
 
 	for module in modules_to_load {
 		let path = match module_paths.get(&module) {
@@ -70,6 +140,32 @@ pub fn load_module(
 	Ok(())
 }
 
+/// Finds the modules that have the given alias in the modules.alias file. Only aliases without wildcards are
+/// matched, which covers those that the kernel requests by name.
+fn find_modules_for_alias(logger: &slog::Logger, alias: &str, mod_alias_path: &Path) -> io::Result<Vec<String>> {
+	let mod_alias_file = BufReader::new(File::open(mod_alias_path)?);
+	let mut modules = Vec::new();
+
+	for line in mod_alias_file.lines() {
+		let line = line?;
+		if line.starts_with('#') {
+			continue;
+		}
+
+		// Lines in modules.alias are in the form `alias <alias> <module>`
+		match line.split_ascii_whitespace().collect::<Vec<_>>()[..] {
+			["alias", line_alias, module] => {
+				if line_alias == alias && !modules.iter().any(|m| m == module) {
+					modules.push(module.to_owned());
+				}
+			}
+			_ => warn!(logger, "invalid line in modules.alias: {}", line),
+		}
+	}
+
+	Ok(modules)
+}
+
 /// Starting with the given modules, calculates the order of modules to load that satisfies all the dependencies that each modules has.
 pub fn find_modules_to_load(
 	logger: &slog::Logger,
@@ -101,12 +197,12 @@ pub fn find_modules_to_load(
 			return Err(ModuleLoadError::DependencyError(deps));
 		}
 
-		for (_, v) in deps.iter_mut() {
+		for v in deps.values_mut() {
 			for mod_name in ok_to_start.iter() {
 				v.retain(|s| s != mod_name);
 			}
 		}
-		mods_to_load.extend(ok_to_start.into_iter());
+		mods_to_load.extend(ok_to_start);
 	}
 
 	Ok(mods_to_load)
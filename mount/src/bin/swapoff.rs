@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use mount::{
+	fstab::resolve_source,
+	swap::{read_swaps, swap_off},
+};
+
+#[derive(Parser)]
+#[command(about = "stop using swap areas")]
+struct Cli {
+	/// The swap areas to stop using, as paths, or as UUID= or LABEL= tags.
+	devices: Vec<String>,
+
+	#[arg(short, long, help = "stop using every swap area")]
+	all: bool,
+}
+
+/// Stops using the swap area at the given path. Returns whether that worked.
+fn disable(path: &Path) -> bool {
+	match swap_off(path) {
+		Ok(()) => true,
+		Err(e) => {
+			eprintln!("swapoff: Error: failed to stop using {}: {}", path.display(), e);
+			false
+		}
+	}
+}
+
+fn main() {
+	let cli = Cli::parse();
+	if !cli.all && cli.devices.is_empty() {
+		eprintln!("swapoff: Error: Expected a swap area, or --all");
+		std::process::exit(1);
+	}
+
+	let mut paths: Vec<PathBuf> = Vec::new();
+	let mut ok = true;
+	if cli.all {
+		match read_swaps() {
+			Ok(swaps) => paths.extend(swaps.into_iter().map(|swap| swap.path)),
+			Err(e) => {
+				eprintln!("swapoff: Error: {}", e);
+				ok = false;
+			}
+		}
+	}
+
+	for device in cli.devices.iter() {
+		match resolve_source(device) {
+			Ok(Some(path)) => paths.push(path),
+			Ok(None) => {
+				eprintln!("swapoff: Error: no device matches {}", device);
+				ok = false;
+			}
+			Err(e) => {
+				eprintln!("swapoff: Error: failed to find {}: {}", device, e);
+				ok = false;
+			}
+		}
+	}
+
+	for path in paths.iter() {
+		ok &= disable(path);
+	}
+
+	if !ok {
+		std::process::exit(1);
+	}
+}
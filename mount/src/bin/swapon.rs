@@ -0,0 +1,124 @@
+use clap::Parser;
+use mount::{
+	fstab::{read_fstab, resolve_source, FSTAB_PATH},
+	swap::{is_active, read_swaps, swap_on, Discard, SwapOptions, MAX_SWAP_PRIORITY},
+};
+use tables::{Table, TableSetting};
+
+#[derive(Parser)]
+#[command(about = "start using swap areas, or show the swap areas in use if no arguments are given")]
+struct Cli {
+	/// The swap areas to use, as paths, or as UUID= or LABEL= tags.
+	devices: Vec<String>,
+
+	#[arg(short, long, help = "use every swap area in /etc/fstab that isn't noauto")]
+	all: bool,
+
+	#[arg(short, long, help = "the priority of the swap areas, with higher ones used first", value_parser = clap::value_parser!(u16).range(..=i64::from(MAX_SWAP_PRIORITY)))]
+	priority: Option<u16>,
+
+	#[arg(short, long, num_args = 0..=1, default_missing_value = "", require_equals = true, help = "discard freed pages on SSDs, or only discard the whole area `once`, or only freed `pages`")]
+	discard: Option<String>,
+}
+
+/// Prints the swap areas that are in use.
+fn show_swaps() {
+	let swaps = match read_swaps() {
+		Ok(swaps) => swaps,
+		Err(e) => {
+			eprintln!("swapon: Error: {}", e);
+			return;
+		}
+	};
+
+	let mut table =
+		Table::new_with_headers(["NAME", "TYPE", "SIZE", "USED", "PRIO"]).with_setting(TableSetting::HeaderSeperator);
+	for swap in swaps {
+		table.add_row([
+			&swap.path.to_string_lossy(),
+			&swap.kind,
+			&format!("{}K", swap.size),
+			&format!("{}K", swap.used),
+			&swap.priority.to_string(),
+		]);
+	}
+
+	print!("{}", table);
+}
+
+/// Starts using the swap area that the source refers to, unless it's already in use. Returns whether it's in use.
+fn enable(source: &str, options: &SwapOptions) -> bool {
+	let path = match resolve_source(source) {
+		Ok(Some(path)) => path,
+		Ok(None) => {
+			eprintln!("swapon: Error: no device matches {}", source);
+			return false;
+		}
+		Err(e) => {
+			eprintln!("swapon: Error: failed to find {}: {}", source, e);
+			return false;
+		}
+	};
+
+	if is_active(&path).unwrap_or(false) {
+		return true;
+	}
+
+	match swap_on(&path, options) {
+		Ok(()) => true,
+		Err(e) => {
+			eprintln!("swapon: Error: failed to use {}: {}", path.display(), e);
+			false
+		}
+	}
+}
+
+fn main() {
+	let cli = Cli::parse();
+	if !cli.all && cli.devices.is_empty() {
+		show_swaps();
+		return;
+	}
+
+	let discard = match cli.discard.as_deref().map(str::parse::<Discard>).transpose() {
+		Ok(discard) => discard,
+		Err(e) => {
+			eprintln!("swapon: Error: invalid discard policy: {}", e);
+			std::process::exit(1);
+		}
+	};
+
+	let mut ok = true;
+	if cli.all {
+		let entries = match read_fstab(FSTAB_PATH) {
+			Ok(entries) => entries,
+			Err(e) => {
+				eprintln!("swapon: Error: {}", e);
+				std::process::exit(1);
+			}
+		};
+
+		for entry in entries.iter().filter(|entry| entry.is_swap() && entry.is_auto()) {
+			match SwapOptions::from_fstab(&entry.options) {
+				Ok(options) => ok &= enable(&entry.source, &options),
+				Err(e) => {
+					eprintln!("swapon: Error: {}: {}", entry.source, e);
+					ok = false;
+				}
+			}
+		}
+	}
+
+	let options = SwapOptions {
+		priority: cli.priority,
+		discard,
+	};
+
+	for device in cli.devices.iter() {
+		ok &= enable(device, &options);
+	}
+
+	if !ok {
+		std::process::exit(1);
+	}
+}
@@ -0,0 +1,199 @@
+use std::{
+	fs::{read_dir, read_to_string},
+	io,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use superblocks::{Device, ProbeResult};
+use thiserror::Error;
+
+use crate::mounts::unescape;
+
+/// The filesystems that are mounted, and the swap areas that are used, at boot.
+pub const FSTAB_PATH: &str = "/etc/fstab";
+
+/// The block devices that `UUID=` and `LABEL=` sources are looked for on.
+const SYS_BLOCK_PATH: &str = "/sys/class/block";
+
+/// The filesystem type of fstab entries for swap areas.
+pub const SWAP_FS_TYPE: &str = "swap";
+
+#[derive(Debug, Error)]
+pub enum FstabError {
+	#[error("failed to read fstab: {0}")]
+	IOError(#[from] io::Error),
+
+	#[error("invalid fstab line `{0}`: {1}")]
+	Invalid(String, &'static str),
+}
+
+/// A filesystem or swap area, from a line of fstab.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FstabEntry {
+	/// The device to mount, e.g. `/dev/sda1`, or a tag that identifies it, e.g. `UUID=...` or `LABEL=...`.
+	pub source: String,
+
+	/// Where to mount the filesystem, which is `none` for swap areas.
+	pub mount_point: PathBuf,
+	pub fs_type: String,
+	pub options: Vec<String>,
+
+	/// Whether the filesystem should be dumped, which nothing uses any more.
+	pub dump: u32,
+
+	/// The order that filesystems are checked in at boot, or 0 if it isn't checked.
+	pub pass: u32,
+}
+
+impl FstabEntry {
+	pub fn is_swap(&self) -> bool {
+		self.fs_type == SWAP_FS_TYPE
+	}
+
+	/// Whether the entry is used at boot, which it isn't if it has the `noauto` option.
+	pub fn is_auto(&self) -> bool {
+		!self.options.iter().any(|option| option == "noauto")
+	}
+
+	/// Finds the device that the source refers to, returning None if it's a tag that no device has.
+	pub fn device(&self) -> io::Result<Option<PathBuf>> {
+		resolve_source(&self.source)
+	}
+}
+
+impl FromStr for FstabEntry {
+	type Err = FstabError;
+
+	fn from_str(line: &str) -> Result<Self, Self::Err> {
+		// e.g. `UUID=... none swap sw,pri=10 0 0`, where the dump and pass fields are optional.
+		let invalid = |reason| FstabError::Invalid(line.to_owned(), reason);
+		let mut fields = line.split_whitespace();
+		let mut next = |name| fields.next().ok_or_else(|| invalid(name));
+		let source = unescape(next("missing source")?);
+		let mount_point = PathBuf::from(unescape(next("missing mount point")?));
+		let fs_type = unescape(next("missing filesystem type")?);
+		let options = next("missing options")?
+			.split(',')
+			.filter(|option| !option.is_empty())
+			.map(unescape)
+			.collect();
+
+		let dump = fields
+			.next()
+			.map_or(Ok(0), str::parse)
+			.map_err(|_| invalid("invalid dump"))?;
+		let pass = fields
+			.next()
+			.map_or(Ok(0), str::parse)
+			.map_err(|_| invalid("invalid pass"))?;
+		if fields.next().is_some() {
+			return Err(invalid("too many fields"));
+		}
+
+		Ok(Self {
+			source,
+			mount_point,
+			fs_type,
+			options,
+			dump,
+			pass,
+		})
+	}
+}
+
+/// Parses the contents of an fstab file, skipping blank lines and comments.
+pub fn parse_fstab(fstab: &str) -> Result<Vec<FstabEntry>, FstabError> {
+	fstab
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(str::parse)
+		.collect()
+}
+
+/// Reads the entries of the fstab at the given path.
+pub fn read_fstab<P: AsRef<Path>>(path: P) -> Result<Vec<FstabEntry>, FstabError> {
+	parse_fstab(&read_to_string(path)?)
+}
+
+/// A tag that identifies a device by the filesystem or swap area on it.
+enum Tag<'a> {
+	Uuid(&'a str),
+	Label(&'a str),
+}
+
+impl Tag<'_> {
+	fn matches(&self, probed: &ProbeResult) -> bool {
+		match self {
+			Tag::Uuid(uuid) => probed.uuid_string().eq_ignore_ascii_case(uuid),
+			Tag::Label(label) => probed.label == *label,
+		}
+	}
+}
+
+/// Finds the device that an fstab source refers to. Paths are returned as they are, and `UUID=` and `LABEL=` tags are
+/// looked up by probing every block device, returning None if none of them match.
+pub fn resolve_source(source: &str) -> io::Result<Option<PathBuf>> {
+	let tag = if let Some(uuid) = source.strip_prefix("UUID=") {
+		Tag::Uuid(uuid)
+	} else if let Some(label) = source.strip_prefix("LABEL=") {
+		Tag::Label(label)
+	} else {
+		return Ok(Some(PathBuf::from(source)));
+	};
+
+	let mut devices = read_dir(SYS_BLOCK_PATH)?
+		.map(|entry| Ok(Path::new("/dev").join(entry?.file_name())))
+		.collect::<io::Result<Vec<_>>>()?;
+	devices.sort();
+
+	for device in devices {
+		// Devices that can't be read, like empty CD drives, can't be the one that we're looking for.
+		if let Ok(Some(probed)) = Device::new(&device).probe() {
+			if tag.matches(&probed) {
+				return Ok(Some(device));
+			}
+		}
+	}
+
+	Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::PathBuf;
+
+	use super::{parse_fstab, FstabEntry};
+
+	#[test]
+	fn test_parse_fstab() {
+		let entries = parse_fstab(
+			"# <source> <mount point> <type> <options> <dump> <pass>\n\n/dev/sda1 / ext4 rw,noatime 0 1\nUUID=0a1b none swap sw,pri=10\n\tLABEL=scratch\\040swap none swap noauto 0 0\n",
+		)
+		.unwrap();
+
+		assert_eq!(entries.len(), 3);
+		assert_eq!(
+			entries[0],
+			FstabEntry {
+				source: "/dev/sda1".to_owned(),
+				mount_point: PathBuf::from("/"),
+				fs_type: "ext4".to_owned(),
+				options: vec!["rw".to_owned(), "noatime".to_owned()],
+				dump: 0,
+				pass: 1,
+			}
+		);
+		assert!(!entries[0].is_swap());
+		assert!(entries[1].is_swap());
+		assert!(entries[1].is_auto());
+		assert_eq!(entries[1].options, vec!["sw", "pri=10"]);
+		assert_eq!(entries[2].source, "LABEL=scratch swap");
+		assert!(!entries[2].is_auto());
+
+		assert!(parse_fstab("/dev/sda1 / ext4\n").is_err());
+		assert!(parse_fstab("/dev/sda1 / ext4 rw zero 1\n").is_err());
+		assert!(parse_fstab("/dev/sda1 / ext4 rw 0 1 2\n").is_err());
+	}
+}
@@ -0,0 +1,3 @@
+pub mod fstab;
+pub mod mounts;
+pub mod swap;
@@ -1,27 +1,97 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::SystemTime};
 
 use clap::Parser;
-use superblocks::Device;
+use mount::mounts::read_mounts;
+use superblocks::{Device, FilesystemState};
+use tables::{Table, TableSetting};
 
-use nix::mount::{mount, MsFlags};
+use nix::mount::MsFlags;
 
 #[derive(Parser)]
-#[command(about = "mount a filesystem")]
+#[command(about = "mount a filesystem, or show the mounted filesystems if no arguments are given")]
 struct Cli {
-	device: PathBuf,
-	mount_point: PathBuf,
+	device: Option<PathBuf>,
+	mount_point: Option<PathBuf>,
 
 	#[arg(short, long, help="limit the set of filesystem types", default_value_t = String::from("auto"))]
 	types: String,
 }
 
+/// Prints the mounted filesystems, optionally only those of the given type.
+fn show_mounts(types: &str) {
+	let mounts = match read_mounts() {
+		Ok(mounts) => mounts,
+		Err(e) => {
+			eprintln!("mount: Error: {}", e);
+			return;
+		}
+	};
+
+	let mut table = Table::new_with_headers(["SOURCE", "TARGET", "FSTYPE", "OPTIONS", "PROPAGATION"])
+		.with_setting(TableSetting::HeaderSeperator);
+
+	for mount in mounts {
+		if types != "auto" && mount.fs_type != types {
+			continue;
+		}
+
+		let propagation = if mount.propagation.is_empty() {
+			"private".to_owned()
+		} else {
+			mount
+				.propagation
+				.iter()
+				.map(ToString::to_string)
+				.collect::<Vec<_>>()
+				.join(",")
+		};
+
+		table.add_row([
+			&mount.source,
+			&mount.mount_point.to_string_lossy(),
+			&mount.fs_type,
+			&mount.all_options().join(","),
+			&propagation,
+		]);
+	}
+
+	print!("{}", table);
+}
+
 fn main() {
 	let cli = Cli::parse();
 
+	let (device, mount_point) = match (cli.device, cli.mount_point) {
+		(None, None) => {
+			show_mounts(&cli.types);
+			return;
+		}
+		(Some(device), Some(mount_point)) => (device, mount_point),
+		_ => {
+			eprintln!("mount: Error: Expected both a device and a mount point");
+			return;
+		}
+	};
+
 	let filesystem_type = if cli.types == "auto" {
-		let device = Device::new(&cli.device);
+		let device = Device::new(&device);
 		match device.probe() {
-			Ok(Some(filesystem)) => filesystem.filesystem_type,
+			Ok(Some(filesystem)) => {
+				if let Some(health) = filesystem.health.filter(|health| health.needs_check(SystemTime::now())) {
+					let reason = match health.state {
+						FilesystemState::Clean => String::from("it's due a check"),
+						state => format!("it's {}", state),
+					};
+
+					eprintln!(
+						"mount: Warning: {} should be checked before it's mounted, as {}",
+						filesystem.path.display(),
+						reason
+					);
+				}
+
+				filesystem.filesystem_type
+			}
 			Ok(None) => {
 				eprintln!("mount: Error: Unknown filesystem type");
 				return;
@@ -35,8 +105,8 @@ fn main() {
 		cli.types
 	};
 
-	let device = cli.device.to_str();
-	let mount_point = match cli.mount_point.to_str() {
+	let device = device.to_str();
+	let mount_point = match mount_point.to_str() {
 		Some(mount_point) => mount_point,
 		None => {
 			eprintln!("mount: Error: Invalid mount point");
@@ -44,7 +114,7 @@ fn main() {
 		}
 	};
 
-	match mount::<_, _, str, str>(device, mount_point, Some(&filesystem_type), MsFlags::empty(), None) {
+	match nix::mount::mount::<_, _, str, str>(device, mount_point, Some(&filesystem_type), MsFlags::empty(), None) {
 		Ok(()) => {}
 		Err(errno) => {
 			eprintln!("mount: Error: {}", errno);
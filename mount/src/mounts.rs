@@ -0,0 +1,252 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	fs::read_to_string,
+	io,
+	path::PathBuf,
+	str::FromStr,
+};
+
+use thiserror::Error;
+
+/// The mounts in the mount namespace of this process.
+pub const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+#[derive(Debug, Error)]
+pub enum MountsError {
+	#[error("failed to read mounts: {0}")]
+	IOError(#[from] io::Error),
+
+	#[error("invalid mountinfo line `{0}`: {1}")]
+	Invalid(String, &'static str),
+}
+
+/// How mount and unmount events propagate between a mount and its peers, from the optional fields of mountinfo.
+/// A mount with none of these is private.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+	/// The mount shares events with the other mounts in the given peer group.
+	Shared(u32),
+
+	/// The mount receives events from the given peer group.
+	Master(u32),
+
+	/// The mount receives events from the given peer group, which isn't visible in this namespace.
+	PropagateFrom(u32),
+
+	/// The mount can't be bind mounted.
+	Unbindable,
+}
+
+impl Display for Propagation {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Propagation::Shared(group) => write!(f, "shared:{}", group),
+			Propagation::Master(group) => write!(f, "master:{}", group),
+			Propagation::PropagateFrom(group) => write!(f, "propagate_from:{}", group),
+			Propagation::Unbindable => write!(f, "unbindable"),
+		}
+	}
+}
+
+impl FromStr for Propagation {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s == "unbindable" {
+			return Ok(Propagation::Unbindable);
+		}
+
+		let (tag, group) = s.split_once(':').ok_or("invalid optional field")?;
+		let group = group.parse().map_err(|_| "invalid peer group")?;
+		match tag {
+			"shared" => Ok(Propagation::Shared(group)),
+			"master" => Ok(Propagation::Master(group)),
+			"propagate_from" => Ok(Propagation::PropagateFrom(group)),
+			_ => Err("unknown optional field"),
+		}
+	}
+}
+
+/// A mounted filesystem, from a line of mountinfo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+	pub id: u32,
+	pub parent_id: u32,
+
+	/// The device number of the filesystem, as (major, minor).
+	pub device: (u32, u32),
+
+	/// The path in the filesystem that is mounted, which is `/` unless it's a bind mount of a subdirectory.
+	pub root: PathBuf,
+	pub mount_point: PathBuf,
+
+	/// The options of this mount, e.g. `rw` or `noatime`.
+	pub options: Vec<String>,
+	pub propagation: Vec<Propagation>,
+	pub fs_type: String,
+
+	/// Where the filesystem was mounted from, e.g. a device path, or `none`.
+	pub source: String,
+
+	/// The options of the filesystem itself, shared by every mount of it.
+	pub super_options: Vec<String>,
+}
+
+impl Mount {
+	/// All the options of the mount: the options of the mount, followed by those of the filesystem that aren't already
+	/// set on the mount.
+	pub fn all_options(&self) -> Vec<&str> {
+		let mut options: Vec<&str> = self.options.iter().map(String::as_str).collect();
+		for option in self.super_options.iter() {
+			if !options.contains(&option.as_str()) {
+				options.push(option);
+			}
+		}
+
+		options
+	}
+}
+
+impl FromStr for Mount {
+	type Err = MountsError;
+
+	fn from_str(line: &str) -> Result<Self, Self::Err> {
+		// e.g. `36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue`, where there are any
+		// number of optional fields before the `-`.
+		let invalid = |reason| MountsError::Invalid(line.to_owned(), reason);
+		let (mount_fields, super_fields) = line.split_once(" - ").ok_or_else(|| invalid("missing seperator"))?;
+
+		let mut fields = mount_fields.split(' ');
+		let mut next = |name| fields.next().ok_or_else(|| invalid(name));
+		let id = next("missing mount id")?
+			.parse()
+			.map_err(|_| invalid("invalid mount id"))?;
+		let parent_id = next("missing parent id")?
+			.parse()
+			.map_err(|_| invalid("invalid parent id"))?;
+		let device = next("missing device")?
+			.split_once(':')
+			.and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+			.ok_or_else(|| invalid("invalid device"))?;
+		let root = PathBuf::from(unescape(next("missing root")?));
+		let mount_point = PathBuf::from(unescape(next("missing mount point")?));
+		let options = split_options(next("missing options")?);
+		let propagation = fields
+			.map(|field| field.parse().map_err(invalid))
+			.collect::<Result<_, _>>()?;
+
+		let mut fields = super_fields.split(' ');
+		let mut next = |name| fields.next().ok_or_else(|| invalid(name));
+		let fs_type = unescape(next("missing filesystem type")?);
+		let source = unescape(next("missing source")?);
+		let super_options = split_options(next("missing super options")?);
+
+		Ok(Self {
+			id,
+			parent_id,
+			device,
+			root,
+			mount_point,
+			options,
+			propagation,
+			fs_type,
+			source,
+			super_options,
+		})
+	}
+}
+
+/// Parses the contents of a mountinfo file.
+pub fn parse_mountinfo(mountinfo: &str) -> Result<Vec<Mount>, MountsError> {
+	mountinfo
+		.lines()
+		.filter(|line| !line.is_empty())
+		.map(str::parse)
+		.collect()
+}
+
+/// Reads the mounts in the mount namespace of this process, in the order that they were mounted.
+pub fn read_mounts() -> Result<Vec<Mount>, MountsError> {
+	parse_mountinfo(&read_to_string(MOUNTINFO_PATH)?)
+}
+
+fn split_options(options: &str) -> Vec<String> {
+	options.split(',').filter(|o| !o.is_empty()).map(unescape).collect()
+}
+
+/// Undoes the octal escaping (e.g. `\040` for a space) that the kernel applies to paths in mountinfo and swaps.
+pub fn unescape(path: &str) -> String {
+	let bytes = path.as_bytes();
+	let mut unescaped = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'\\' && i + 3 < bytes.len() {
+			let digits = std::str::from_utf8(&bytes[i + 1..i + 4]).unwrap_or_default();
+			if let Ok(byte) = u8::from_str_radix(digits, 8) {
+				unescaped.push(byte);
+				i += 4;
+				continue;
+			}
+		}
+
+		unescaped.push(bytes[i]);
+		i += 1;
+	}
+
+	String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::PathBuf;
+
+	use super::{parse_mountinfo, unescape, Mount, Propagation};
+
+	#[test]
+	fn test_parse_mount() {
+		let mount: Mount =
+			"36 35 98:0 /mnt1 /mnt\\0402 rw,noatime master:1 shared:7 - ext3 /dev/root rw,errors=continue"
+				.parse()
+				.unwrap();
+
+		assert_eq!(
+			mount,
+			Mount {
+				id: 36,
+				parent_id: 35,
+				device: (98, 0),
+				root: PathBuf::from("/mnt1"),
+				mount_point: PathBuf::from("/mnt 2"),
+				options: vec!["rw".to_owned(), "noatime".to_owned()],
+				propagation: vec![Propagation::Master(1), Propagation::Shared(7)],
+				fs_type: "ext3".to_owned(),
+				source: "/dev/root".to_owned(),
+				super_options: vec!["rw".to_owned(), "errors=continue".to_owned()],
+			}
+		);
+		assert_eq!(mount.all_options(), vec!["rw", "noatime", "errors=continue"]);
+	}
+
+	#[test]
+	fn test_parse_mountinfo() {
+		let mounts = parse_mountinfo(
+			"23 28 0:22 / /proc rw,relatime - proc proc rw\n25 28 0:6 / /dev rw,relatime shared:2 - devtmpfs devtmpfs rw,mode=755\n",
+		)
+		.unwrap();
+
+		assert_eq!(mounts.len(), 2);
+		assert_eq!(mounts[0].mount_point, PathBuf::from("/proc"));
+		assert!(mounts[0].propagation.is_empty());
+		assert_eq!(mounts[1].propagation, vec![Propagation::Shared(2)]);
+
+		assert!(parse_mountinfo("23 28 0:22 / /proc rw,relatime proc proc rw\n").is_err());
+		assert!(parse_mountinfo("23 28 0:22 / /proc rw,relatime bogus:1 - proc proc rw\n").is_err());
+	}
+
+	#[test]
+	fn test_unescape() {
+		assert_eq!(unescape("/mnt/with\\040space"), "/mnt/with space");
+		assert_eq!(unescape("/mnt/back\\134slash"), "/mnt/back\\slash");
+		assert_eq!(unescape("/mnt/trailing\\04"), "/mnt/trailing\\04");
+	}
+}
@@ -0,0 +1,255 @@
+use std::{
+	ffi::CString,
+	fs::{canonicalize, read_to_string},
+	io,
+	os::unix::ffi::OsStrExt,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use nix::{errno::Errno, libc};
+use superblocks::Device;
+use thiserror::Error;
+
+use crate::mounts::unescape;
+
+/// The swap areas that are in use.
+pub const SWAPS_PATH: &str = "/proc/swaps";
+
+/// The highest priority that a swap area can be given.
+pub const MAX_SWAP_PRIORITY: u16 = 0x7fff;
+
+/// The flags of swapon(2), from linux/swap.h.
+const SWAP_FLAG_PREFER: libc::c_int = 0x8000;
+const SWAP_FLAG_PRIO_MASK: libc::c_int = 0x7fff;
+const SWAP_FLAG_DISCARD: libc::c_int = 0x10000;
+const SWAP_FLAG_DISCARD_ONCE: libc::c_int = 0x20000;
+const SWAP_FLAG_DISCARD_PAGES: libc::c_int = 0x40000;
+
+#[derive(Debug, Error)]
+pub enum SwapError {
+	#[error("{0}")]
+	IOError(#[from] io::Error),
+
+	#[error("{0}")]
+	Errno(#[from] Errno),
+
+	#[error("{0} isn't a swap area")]
+	NotSwap(PathBuf),
+
+	#[error("invalid swap option `{0}`: {1}")]
+	InvalidOption(String, &'static str),
+
+	#[error("invalid /proc/swaps line `{0}`: {1}")]
+	Invalid(String, &'static str),
+}
+
+/// Which pages of a swap area are discarded, for swap areas on SSDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discard {
+	/// The whole area is discarded when it's enabled, and pages are discarded as they're freed.
+	All,
+
+	/// The whole area is discarded when it's enabled.
+	Once,
+
+	/// Pages are discarded as they're freed.
+	Pages,
+}
+
+impl FromStr for Discard {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"" => Ok(Discard::All),
+			"once" => Ok(Discard::Once),
+			"pages" => Ok(Discard::Pages),
+			_ => Err("expected once or pages"),
+		}
+	}
+}
+
+/// How a swap area is used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SwapOptions {
+	/// The priority of the area, with higher priority areas being used before lower ones. If unset, the kernel gives
+	/// the area a priority below every other area's.
+	pub priority: Option<u16>,
+
+	pub discard: Option<Discard>,
+}
+
+impl SwapOptions {
+	/// Parses the options of a swap area in fstab, e.g. `sw,pri=10,discard=once`. Options that aren't about swap,
+	/// like `sw` or `noauto`, are skipped.
+	pub fn from_fstab<S: AsRef<str>>(options: &[S]) -> Result<Self, SwapError> {
+		let mut parsed = Self::default();
+		for option in options {
+			let option = option.as_ref();
+			let (key, value) = option.split_once('=').unwrap_or((option, ""));
+			let invalid = |reason| SwapError::InvalidOption(option.to_owned(), reason);
+			match key {
+				"pri" => {
+					let priority = value.parse().map_err(|_| invalid("expected a number"))?;
+					if priority > MAX_SWAP_PRIORITY {
+						return Err(invalid("priorities can't be over 32767"));
+					}
+
+					parsed.priority = Some(priority);
+				}
+				"discard" => parsed.discard = Some(value.parse().map_err(invalid)?),
+				_ => {}
+			}
+		}
+
+		Ok(parsed)
+	}
+
+	/// The flags to pass to swapon(2).
+	fn flags(&self) -> libc::c_int {
+		let mut flags = match self.priority {
+			Some(priority) => SWAP_FLAG_PREFER | (libc::c_int::from(priority) & SWAP_FLAG_PRIO_MASK),
+			None => 0,
+		};
+
+		flags |= match self.discard {
+			Some(Discard::All) => SWAP_FLAG_DISCARD,
+			Some(Discard::Once) => SWAP_FLAG_DISCARD | SWAP_FLAG_DISCARD_ONCE,
+			Some(Discard::Pages) => SWAP_FLAG_DISCARD | SWAP_FLAG_DISCARD_PAGES,
+			None => 0,
+		};
+
+		flags
+	}
+}
+
+/// A swap area that is in use, from a line of /proc/swaps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveSwap {
+	pub path: PathBuf,
+
+	/// Whether the area is a `partition` or a `file`.
+	pub kind: String,
+
+	/// The size of the area, and how much of it is used, in KiB.
+	pub size: u64,
+	pub used: u64,
+	pub priority: i32,
+}
+
+impl FromStr for ActiveSwap {
+	type Err = SwapError;
+
+	fn from_str(line: &str) -> Result<Self, Self::Err> {
+		// e.g. `/dev/sda2  partition  1048572  0  -2`
+		let invalid = |reason| SwapError::Invalid(line.to_owned(), reason);
+		let mut fields = line.split_whitespace();
+		let mut next = |name| fields.next().ok_or_else(|| invalid(name));
+		let path = PathBuf::from(unescape(next("missing filename")?));
+		let kind = next("missing type")?.to_owned();
+		let size = next("missing size")?.parse().map_err(|_| invalid("invalid size"))?;
+		let used = next("missing used")?.parse().map_err(|_| invalid("invalid used"))?;
+		let priority = next("missing priority")?
+			.parse()
+			.map_err(|_| invalid("invalid priority"))?;
+
+		Ok(Self {
+			path,
+			kind,
+			size,
+			used,
+			priority,
+		})
+	}
+}
+
+/// Parses the contents of /proc/swaps, which starts with a header line.
+pub fn parse_swaps(swaps: &str) -> Result<Vec<ActiveSwap>, SwapError> {
+	swaps
+		.lines()
+		.skip(1)
+		.filter(|line| !line.trim().is_empty())
+		.map(str::parse)
+		.collect()
+}
+
+/// Reads the swap areas that are in use. Kernels without swap support don't have /proc/swaps, so have none.
+pub fn read_swaps() -> Result<Vec<ActiveSwap>, SwapError> {
+	match read_to_string(SWAPS_PATH) {
+		Ok(swaps) => parse_swaps(&swaps),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Whether the swap area at the given path is in use.
+pub fn is_active(path: &Path) -> Result<bool, SwapError> {
+	let path = canonicalize(path)?;
+	Ok(read_swaps()?.iter().any(|swap| swap.path == path))
+}
+
+/// Starts using the swap area at the given path, after checking that it has a swap header, so that a typo can't hand
+/// a filesystem over to the kernel to swap over.
+pub fn swap_on(path: &Path, options: &SwapOptions) -> Result<(), SwapError> {
+	match Device::new(path).probe()? {
+		Some(probed) if probed.filesystem_type == "swap" => {}
+		_ => return Err(SwapError::NotSwap(path.to_owned())),
+	}
+
+	let path = CString::new(path.as_os_str().as_bytes()).map_err(io::Error::from)?;
+	Errno::result(unsafe { libc::swapon(path.as_ptr(), options.flags()) })?;
+	Ok(())
+}
+
+/// Stops using the swap area at the given path, moving the pages in it back into memory.
+pub fn swap_off(path: &Path) -> Result<(), SwapError> {
+	let path = CString::new(path.as_os_str().as_bytes()).map_err(io::Error::from)?;
+	Errno::result(unsafe { libc::swapoff(path.as_ptr()) })?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::PathBuf;
+
+	use super::{parse_swaps, Discard, SwapOptions, SWAP_FLAG_DISCARD, SWAP_FLAG_DISCARD_ONCE, SWAP_FLAG_PREFER};
+
+	#[test]
+	fn test_swap_options() {
+		let options = SwapOptions::from_fstab(&["sw", "pri=10", "discard=once"]).unwrap();
+		assert_eq!(options.priority, Some(10));
+		assert_eq!(options.discard, Some(Discard::Once));
+		assert_eq!(
+			options.flags(),
+			SWAP_FLAG_PREFER | 10 | SWAP_FLAG_DISCARD | SWAP_FLAG_DISCARD_ONCE
+		);
+
+		let options = SwapOptions::from_fstab(&["defaults", "discard"]).unwrap();
+		assert_eq!(options.priority, None);
+		assert_eq!(options.discard, Some(Discard::All));
+		assert_eq!(options.flags(), SWAP_FLAG_DISCARD);
+
+		assert_eq!(SwapOptions::from_fstab::<&str>(&[]).unwrap().flags(), 0);
+		assert!(SwapOptions::from_fstab(&["pri=high"]).is_err());
+		assert!(SwapOptions::from_fstab(&["pri=32768"]).is_err());
+		assert!(SwapOptions::from_fstab(&["discard=always"]).is_err());
+	}
+
+	#[test]
+	fn test_parse_swaps() {
+		let swaps = parse_swaps(
+			"Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n/dev/sda2                               partition\t1048572\t\t512\t\t-2\n/swap\\040file                              file\t\t65532\t\t0\t\t10\n",
+		)
+		.unwrap();
+
+		assert_eq!(swaps.len(), 2);
+		assert_eq!(swaps[0].path, PathBuf::from("/dev/sda2"));
+		assert_eq!(swaps[0].kind, "partition");
+		assert_eq!((swaps[0].size, swaps[0].used, swaps[0].priority), (1048572, 512, -2));
+		assert_eq!(swaps[1].path, PathBuf::from("/swap file"));
+		assert_eq!(swaps[1].priority, 10);
+
+		assert!(parse_swaps("Filename Type Size Used Priority\n/dev/sda2 partition lots 0 -2\n").is_err());
+	}
+}
@@ -0,0 +1,40 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::{Arg, Command};
+use common::fsops::{destination, move_path};
+
+fn main() -> ExitCode {
+	let matches = Command::new("mv")
+		.about("move (rename) files")
+		.author("Colin Douch")
+		.version("0.1")
+		.arg(
+			Arg::new("paths")
+				.required(true)
+				.num_args(2..)
+				.help("the files to move, and then where to move them to"),
+		)
+		.get_matches();
+
+	let mut paths: Vec<PathBuf> = matches
+		.get_many::<String>("paths")
+		.unwrap()
+		.map(PathBuf::from)
+		.collect();
+	let dst = paths.pop().expect("BUG: at least two paths are required");
+	if paths.len() > 1 && !dst.is_dir() {
+		eprintln!("mv: target '{}' is not a directory", dst.display());
+		return ExitCode::FAILURE;
+	}
+
+	let mut code = ExitCode::SUCCESS;
+	for src in paths {
+		let target = destination(&src, &dst);
+		if let Err(e) = move_path(&src, &target) {
+			eprintln!("mv: cannot move '{}' to '{}': {}", src.display(), target.display(), e);
+			code = ExitCode::FAILURE;
+		}
+	}
+
+	code
+}
@@ -1,10 +1,52 @@
-use std::ops::Deref;
+mod ports;
 
-use clap::{Arg, ArgMatches, Command};
+use std::{
+	collections::HashMap,
+	io::{stdout, Write},
+	ops::Deref,
+	time::Duration,
+};
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use common::io::{terminal_size, STDOUT_FD};
+use escapes::Capabilities;
 use netlink::{
-	rtnetlink::{Interface, InterfaceFlags, NetlinkRoute, RTNetlink, RTNetlinkGroups},
+	rtnetlink::{
+		AddressFamily, AddressKind, BridgeVlanFlags, Interface, InterfaceFlags, NetlinkRoute, NetlinkWatcher,
+		RTNetlink, RTNetlinkGroups,
+	},
 	NetlinkSocket,
 };
+use ports::MasterKind;
+use tables::{OutputFormat, Table, WatchRenderer};
+
+/// How often `--watch` checks whether the terminal has been resized, while waiting for changes.
+const RESIZE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The number of lines to assume the screen has if stdout isn't a terminal.
+const DEFAULT_TERMINAL_HEIGHT: usize = 24;
+
+/// Builds a `show` subcommand, which can keep refreshing what it shows with `--watch`.
+fn show_command(about: &'static str) -> Command {
+	Command::new("show")
+		.about(about)
+		.arg(
+			Arg::new("watch")
+				.help("keep refreshing the output in place")
+				.short('w')
+				.long("watch")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("output")
+				.help("the format to print the output in")
+				.short('o')
+				.long("output")
+				.value_parser(OutputFormat::NAMES)
+				.default_value("table")
+				.conflicts_with("watch"),
+		)
+}
 
 fn main() {
 	let link_set_command = Command::new("set")
@@ -26,13 +68,25 @@ fn main() {
 
 	let link_command = Command::new("link")
 		.about("manage network links")
-		.subcommand(Command::new("show").about("show the currently active links"))
+		.subcommand(show_command("show the currently active links"))
 		.subcommand(link_set_command)
 		.subcommand_required(true);
 
 	let address_command = Command::new("addr")
 		.about("manage network addresses")
-		.subcommand(Command::new("show").about("show the currently active addresses"))
+		.subcommand(show_command("show the currently active addresses"))
+		.subcommand_required(true);
+
+	let bond_command = Command::new("bond")
+		.about("manage bonds, which spread traffic over several links")
+		.subcommand(show_command("show the bonds and their ports"))
+		.subcommands(MasterKind::Bond.commands())
+		.subcommand_required(true);
+
+	let bridge_command = Command::new("bridge")
+		.about("manage bridges, which switch traffic between links")
+		.subcommand(show_command("show the bridges and their ports"))
+		.subcommands(MasterKind::Bridge.commands())
 		.subcommand_required(true);
 
 	let app = Command::new("netc")
@@ -40,20 +94,51 @@ fn main() {
 		.author("Colin Douch <colin@quirl.co.nz>")
 		.subcommand(link_command)
 		.subcommand(address_command)
+		.subcommand(bond_command)
+		.subcommand(bridge_command)
 		.subcommand_required(true)
 		.get_matches();
 
 	let mut netlink_socket = NetlinkSocket::<NetlinkRoute>::new(RTNetlinkGroups::RTMGRP_NONE).unwrap();
 	match app.subcommand() {
 		Some(("link", matches)) => match matches.subcommand() {
-			Some(("show", _matches)) => show_links(&mut netlink_socket),
+			Some(("show", matches)) => show(matches, RTNetlinkGroups::RTMGRP_LINK, || {
+				link_table(&mut netlink_socket)
+			}),
 			Some(("set", matches)) => set_link(&mut netlink_socket, matches),
 			_ => panic!("unknown links subcommand"),
 		},
 		Some(("addr", matches)) => match matches.subcommand() {
-			Some(("show", _matches)) => show_addresses(&mut netlink_socket),
+			Some(("show", matches)) => show(
+				matches,
+				RTNetlinkGroups::RTMGRP_LINK
+					| RTNetlinkGroups::RTMGRP_IPV4_IFADDR
+					| RTNetlinkGroups::RTMGRP_IPV6_IFADDR,
+				|| address_table(&mut netlink_socket),
+			),
 			_ => panic!("unknown addr subcommand"),
 		},
+		Some((kind @ ("bond" | "bridge"), matches)) => {
+			let kind = match kind {
+				"bond" => MasterKind::Bond,
+				_ => MasterKind::Bridge,
+			};
+
+			match matches.subcommand() {
+				Some(("show", matches)) => match kind {
+					MasterKind::Bond => show(matches, RTNetlinkGroups::RTMGRP_LINK, || {
+						ports::bond_table(&mut netlink_socket)
+					}),
+					MasterKind::Bridge => show(matches, RTNetlinkGroups::RTMGRP_LINK, || {
+						ports::bridge_table(&mut netlink_socket)
+					}),
+				},
+				Some(("create", matches)) => ports::create(&mut netlink_socket, kind, matches),
+				Some(("add", matches)) => ports::add_port(&mut netlink_socket, kind, matches),
+				Some(("remove", matches)) => ports::remove_port(&mut netlink_socket, kind, matches),
+				_ => panic!("unknown {} subcommand", kind.name()),
+			}
+		}
 		_ => panic!("unknown subcommand"),
 	}
 }
@@ -99,56 +184,211 @@ fn set_link(netlink_socket: &mut NetlinkSocket<NetlinkRoute>, matches: &ArgMatch
 	println!("{:?}", err);
 }
 
-fn show_links(netlink_socket: &mut NetlinkSocket<NetlinkRoute>) {
-	let mut table = tables::Table::new_with_headers(["Index", "Name", "Flags", "State", "MTU", "QDisc"])
-		.with_setting(tables::TableSetting::ColumnSeperators)
-		.with_setting(tables::TableSetting::HeaderSeperator);
+/// Prints the table built by `build` in the format given by `--output`, or, with `--watch`, redraws it in place
+/// whenever the kernel announces a change to the given multicast groups.
+fn show<const COLS: usize>(matches: &ArgMatches, groups: RTNetlinkGroups, mut build: impl FnMut() -> Table<COLS>) {
+	if !matches.get_flag("watch") {
+		let format: OutputFormat = matches
+			.get_one::<String>("output")
+			.expect("BUG: output has a default")
+			.parse()
+			.expect("BUG: output is one of the format names");
+		print!("{}", build().render(format));
+		return;
+	}
+
+	// Subscribe before the first draw, so that no changes are missed between drawing and waiting.
+	let watcher = match NetlinkWatcher::new(groups) {
+		Ok(watcher) => watcher,
+		Err(e) => {
+			eprintln!("failed to watch for changes: {}", e);
+			return;
+		}
+	};
+
+	let height = || terminal_size(STDOUT_FD).map_or(DEFAULT_TERMINAL_HEIGHT, |(rows, _)| rows);
+	let mut renderer = WatchRenderer::new(height()).with_capabilities(Capabilities::from_env());
+	let mut last_height = height();
+	let mut changed = true;
+	loop {
+		let current_height = height();
+		if current_height != last_height {
+			renderer.resize(current_height);
+			last_height = current_height;
+			changed = true;
+		}
+
+		if changed {
+			let mut stdout = stdout();
+			if write!(stdout, "{}", renderer.render(&build()))
+				.and_then(|_| stdout.flush())
+				.is_err()
+			{
+				return;
+			}
+		}
+
+		changed = match watcher.next_event(Some(RESIZE_INTERVAL)) {
+			Ok(event) => event.is_some(),
+			Err(e) => {
+				eprintln!("failed to read changes: {}", e);
+				return;
+			}
+		};
+
+		// Changes tend to come in bursts, e.g. a link coming up along with its addresses and routes, so redraw once
+		// for all of them.
+		while changed && matches!(watcher.next_event(Some(Duration::ZERO)), Ok(Some(_))) {}
+	}
+}
+
+fn link_table(netlink_socket: &mut NetlinkSocket<NetlinkRoute>) -> Table<9> {
+	let mut table = tables::Table::new_with_headers([
+		"Index",
+		"Name",
+		"Flags",
+		"State",
+		"MTU",
+		"QDisc",
+		"Master",
+		"Port State",
+		"VLANs",
+	])
+	.with_setting(tables::TableSetting::ColumnSeperators)
+	.with_setting(tables::TableSetting::HeaderSeperator);
 
 	let links = netlink_socket.get_links().unwrap();
-	for i in links {
+
+	// The bridges only describe their ports in their own dump, which is empty if there aren't any bridges.
+	let ports: HashMap<i32, Interface> = netlink_socket
+		.get_bridge_ports()
+		.unwrap_or_default()
+		.into_iter()
+		.map(|port| (port.index, port))
+		.collect();
+
+	for i in links.iter() {
 		let index = &format!("{}", i.index);
 		let name = i.attributes.name.as_deref().unwrap_or("<unknown>");
 		let flags = &format!("{}", i.flags);
 		let mtu = &format!("{}", i.attributes.mtu.unwrap_or(0));
 		let qdisc = i.attributes.qdisc.as_deref().unwrap_or("<unknown>");
 		let state = i.attributes.operational_state.as_ref().map(ToString::to_string);
-		table.add_row([index, name, flags, state.as_deref().unwrap_or("<unknown>"), mtu, qdisc])
+		let master = i.attributes.master.map(|master| {
+			links
+				.iter()
+				.find(|link| link.index as u32 == master)
+				.and_then(|link| link.attributes.name.clone())
+				.unwrap_or_else(|| master.to_string())
+		});
+
+		let port = ports.get(&i.index);
+		let port_state = port
+			.and_then(|port| port.bridge_port().ok().flatten())
+			.and_then(|port| port.state)
+			.map(|state| state.to_string());
+
+		// A VLAN link is in its own VLAN, while a bridge port can be in many, of which untagged packets go into the
+		// PVID (marked with a *).
+		let vlans = match i
+			.attributes
+			.link_info
+			.as_ref()
+			.and_then(|info| info.vlan_id().ok().flatten())
+		{
+			Some(id) => id.to_string(),
+			None => port
+				.and_then(|port| port.bridge().ok().flatten())
+				.map(|bridge| {
+					bridge
+						.vlans
+						.iter()
+						.map(
+							|vlan| match vlan.flags.contains(BridgeVlanFlags::BRIDGE_VLAN_INFO_PVID) {
+								true => format!("{}*", vlan.id),
+								false => vlan.id.to_string(),
+							},
+						)
+						.collect::<Vec<_>>()
+						.join(",")
+				})
+				.unwrap_or_default(),
+		};
+
+		table.add_row([
+			index,
+			name,
+			flags,
+			state.as_deref().unwrap_or("<unknown>"),
+			mtu,
+			qdisc,
+			master.as_deref().unwrap_or("-"),
+			port_state.as_deref().unwrap_or("-"),
+			if vlans.is_empty() { "-" } else { &vlans },
+		])
 	}
 
-	print!("{}", table);
+	table
 }
 
-fn show_addresses(netlink_socket: &mut NetlinkSocket<NetlinkRoute>) {
-	let mut table = tables::Table::new_with_headers(["Interface", "Address", "Broadcast", "Scope", "Proto", "Flags"])
-		.with_setting(tables::TableSetting::ColumnSeperators)
-		.with_setting(tables::TableSetting::HeaderSeperator);
+fn address_table(netlink_socket: &mut NetlinkSocket<NetlinkRoute>) -> Table<8> {
+	let mut table = tables::Table::new_with_headers([
+		"Interface",
+		"Label",
+		"Address",
+		"Type",
+		"Broadcast",
+		"Scope",
+		"Proto",
+		"Flags",
+	])
+	.with_setting(tables::TableSetting::ColumnSeperators)
+	.with_setting(tables::TableSetting::HeaderSeperator);
+
+	// Not every kernel can dump anycast and multicast addresses of every family (e.g. IPv4 multicast groups), so
+	// those are shown where they can be.
+	let mut addresses = netlink_socket.get_addrs().unwrap();
+	for family in [AddressFamily::IPv4, AddressFamily::IPv6] {
+		addresses.extend(netlink_socket.get_anycast_addrs(family).unwrap_or_default());
+		addresses.extend(netlink_socket.get_multicast_addrs(family).unwrap_or_default());
+	}
 
-	let addresses = netlink_socket.get_addrs().unwrap();
 	for addr in addresses {
 		let interface = &format!("{}", addr.interface_index);
-		let address = &format!(
-			"{}/{}",
-			addr.attributes.address.expect("ip address"),
-			addr.prefix_length
-		);
+		let label = addr.attributes.label.as_deref().unwrap_or("-");
+		let kind = addr.kind();
+		let address = &match (addr.ip(), kind) {
+			(Some(ip), AddressKind::Unicast) => format!("{}/{}", ip, addr.prefix_length),
+			(Some(ip), _) => format!("{}", ip),
+			(None, _) => String::from("<unknown>"),
+		};
 
-		let broadcast = if let Some(addr) = addr.attributes.broadcast_address {
+		let broadcast = if let Some(addr) = &addr.attributes.broadcast_address {
 			&format!("{}", addr)
 		} else {
 			"<None>"
 		};
 
 		let scope = &format!("{:?}", addr.scope);
-		let proto = if let Some(proto) = addr.attributes.protocol {
+		let proto = if let Some(proto) = &addr.attributes.protocol {
 			&format!("{:?}", proto)
 		} else {
 			"<None>"
 		};
 
-		let flags = &format!("{}", addr.flags);
+		let flags = &addr.flags().names(&addr.family).join(",");
 
-		table.add_row([interface, address, broadcast, scope, proto, flags]);
+		table.add_row([
+			interface,
+			label,
+			address,
+			&kind.to_string(),
+			broadcast,
+			scope,
+			proto,
+			flags,
+		]);
 	}
 
-	println!("{}", table);
+	table
 }
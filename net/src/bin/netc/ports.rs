@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use clap::{value_parser, Arg, ArgMatches, Command};
+use netlink::{
+	rtnetlink::{
+		BondAttributes, BondMode, Interface, InterfaceAttributes, InterfaceFlags, InterfaceInfoMessage, LinkInfo,
+		NetlinkRoute, RTNetlink,
+	},
+	NetlinkError, NetlinkSocket,
+};
+use tables::Table;
+
+/// A kind of virtual link that other links can be added to as its ports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MasterKind {
+	Bond,
+	Bridge,
+}
+
+impl MasterKind {
+	/// The kind of the link, as the kernel names it.
+	pub fn name(&self) -> &'static str {
+		match self {
+			MasterKind::Bond => "bond",
+			MasterKind::Bridge => "bridge",
+		}
+	}
+
+	/// Whether the link is one of this kind.
+	fn is_kind_of(&self, link: &Interface) -> bool {
+		link.attributes
+			.link_info
+			.as_ref()
+			.is_some_and(|info| info.kind.as_deref() == Some(self.name()))
+	}
+
+	/// Builds the `create`, `add`, and `remove` subcommands for links of this kind.
+	pub fn commands(&self) -> [Command; 3] {
+		let name = self.name();
+		let mut create = Command::new("create").about(format!("create a {}", name)).arg(
+			Arg::new("name")
+				.help(format!("the name of the {} to create", name))
+				.num_args(1)
+				.required(true),
+		);
+
+		if *self == MasterKind::Bond {
+			create = create
+				.arg(
+					Arg::new("mode")
+						.help("how packets are spread over the ports of the bond")
+						.long("mode")
+						.num_args(1)
+						.value_parser(BondMode::NAMES),
+				)
+				.arg(
+					Arg::new("miimon")
+						.help("how often to check the carrier of each port, in milliseconds")
+						.long("miimon")
+						.num_args(1)
+						.value_parser(value_parser!(u32)),
+				);
+		}
+
+		let port_command = |command: &'static str, about: String| {
+			Command::new(command)
+				.about(about)
+				.arg(
+					Arg::new("master")
+						.help(format!("the name of the {}", name))
+						.num_args(1)
+						.required(true),
+				)
+				.arg(
+					Arg::new("port")
+						.help("the name of the link to use as a port")
+						.num_args(1)
+						.required(true),
+				)
+		};
+
+		[
+			create,
+			port_command("add", format!("add a link to a {}", name)),
+			port_command("remove", format!("remove a link from a {}", name)),
+		]
+	}
+}
+
+/// Returns an interface message for changing the attributes of the link with the given index, leaving its flags alone.
+fn link_update(index: i32, attributes: InterfaceAttributes) -> Interface {
+	let message = InterfaceInfoMessage::empty();
+	Interface {
+		family: message.family,
+		ty: message.ty,
+		index,
+		flags: InterfaceFlags::empty(),
+		change: 0,
+		attributes,
+	}
+}
+
+/// Describes why a link request failed, including the kernel's explanation if it gave one.
+fn describe(e: &NetlinkError<NetlinkRoute, Interface>) -> String {
+	match e {
+		NetlinkError::NetlinkError(errno, contents) => match &contents.attributes.msg {
+			Some(msg) => format!("{} ({})", errno.desc(), msg),
+			None => errno.desc().to_owned(),
+		},
+		NetlinkError::IOError(e) => e.to_string(),
+		NetlinkError::Timeout(attempts) => format!("timed out after {} attempts", attempts),
+	}
+}
+
+/// Creates a link of the given kind.
+pub fn create(netlink_socket: &mut NetlinkSocket<NetlinkRoute>, kind: MasterKind, matches: &ArgMatches) {
+	let name: &String = matches.get_one("name").expect("BUG: name is required");
+
+	let link_info = match kind {
+		MasterKind::Bond => {
+			let mut bond = BondAttributes::default();
+			bond.mode = matches
+				.get_one::<String>("mode")
+				.map(|mode| mode.parse().expect("BUG: mode is one of the mode names"));
+			bond.mii_monitor_interval = matches.get_one("miimon").copied();
+			LinkInfo::new_bond(&bond).expect("BUG: bond attributes can be written to a vec")
+		}
+		MasterKind::Bridge => LinkInfo::new(kind.name(), None),
+	};
+
+	let mut attributes = InterfaceAttributes::default();
+	attributes.name = Some(name.clone());
+	attributes.link_info = Some(link_info);
+
+	if let Err(e) = netlink_socket.create_link(link_update(0, attributes)) {
+		eprintln!("failed to create {} {}: {}", kind.name(), name, describe(&e));
+	}
+}
+
+/// Finds the links given by the `master` and `port` arguments, checking that the master is of the given kind.
+fn find_master_and_port(
+	netlink_socket: &mut NetlinkSocket<NetlinkRoute>,
+	kind: MasterKind,
+	matches: &ArgMatches,
+) -> Option<(Interface, Interface)> {
+	let master_name: &String = matches.get_one("master").expect("BUG: master is required");
+	let port_name: &String = matches.get_one("port").expect("BUG: port is required");
+
+	let links = match netlink_socket.get_links() {
+		Ok(links) => links,
+		Err(e) => {
+			eprintln!("failed to read links: {}", describe(&e));
+			return None;
+		}
+	};
+
+	let mut master = None;
+	let mut port = None;
+	for link in links {
+		match link.attributes.name.as_deref() {
+			Some(name) if name == master_name => master = Some(link),
+			Some(name) if name == port_name => port = Some(link),
+			_ => {}
+		}
+	}
+
+	match (master, port) {
+		(None, _) => eprintln!("no such device: {}", master_name),
+		(_, None) => eprintln!("no such device: {}", port_name),
+		(Some(master), _) if !kind.is_kind_of(&master) => eprintln!("{} isn't a {}", master_name, kind.name()),
+		(Some(master), Some(port)) => return Some((master, port)),
+	}
+
+	None
+}
+
+/// Adds a link to a bond or bridge as a port.
+pub fn add_port(netlink_socket: &mut NetlinkSocket<NetlinkRoute>, kind: MasterKind, matches: &ArgMatches) {
+	let Some((master, port)) = find_master_and_port(netlink_socket, kind, matches) else {
+		return;
+	};
+
+	let master_name = master.attributes.name.as_deref().unwrap_or_default();
+	let port_name = port.attributes.name.as_deref().unwrap_or_default();
+
+	// Bonds only take ports that are down, and bring them back up themselves.
+	if kind == MasterKind::Bond && port.flags.contains(InterfaceFlags::IFF_UP) {
+		let mut down = link_update(port.index, InterfaceAttributes::default());
+		down.change = InterfaceFlags::IFF_UP.bits();
+		if let Err(e) = netlink_socket.new_link(down) {
+			eprintln!("failed to bring {} down: {}", port_name, describe(&e));
+			return;
+		}
+	}
+
+	let mut attributes = InterfaceAttributes::default();
+	attributes.master = Some(master.index as u32);
+	if let Err(e) = netlink_socket.new_link(link_update(port.index, attributes)) {
+		eprintln!("failed to add {} to {}: {}", port_name, master_name, describe(&e));
+	}
+}
+
+/// Removes a link from the bond or bridge that it's a port of.
+pub fn remove_port(netlink_socket: &mut NetlinkSocket<NetlinkRoute>, kind: MasterKind, matches: &ArgMatches) {
+	let Some((master, port)) = find_master_and_port(netlink_socket, kind, matches) else {
+		return;
+	};
+
+	let master_name = master.attributes.name.as_deref().unwrap_or_default();
+	let port_name = port.attributes.name.as_deref().unwrap_or_default();
+	if port.attributes.master != Some(master.index as u32) {
+		eprintln!("{} isn't a port of {}", port_name, master_name);
+		return;
+	}
+
+	// A master of 0 releases the link from the one that it has.
+	let mut attributes = InterfaceAttributes::default();
+	attributes.master = Some(0);
+	if let Err(e) = netlink_socket.new_link(link_update(port.index, attributes)) {
+		eprintln!("failed to remove {} from {}: {}", port_name, master_name, describe(&e));
+	}
+}
+
+/// Returns the links of the given kind, along with the links that are their ports.
+fn masters_and_ports(links: &[Interface], kind: MasterKind) -> Vec<(&Interface, Vec<&Interface>)> {
+	links
+		.iter()
+		.filter(|link| kind.is_kind_of(link))
+		.map(|master| {
+			let ports = links
+				.iter()
+				.filter(|link| link.attributes.master == Some(master.index as u32))
+				.collect();
+			(master, ports)
+		})
+		.collect()
+}
+
+fn link_name(link: &Interface) -> &str {
+	link.attributes.name.as_deref().unwrap_or("<unknown>")
+}
+
+fn operational_state(link: &Interface) -> String {
+	link.attributes
+		.operational_state
+		.as_ref()
+		.map_or_else(|| String::from("<unknown>"), ToString::to_string)
+}
+
+pub fn bond_table(netlink_socket: &mut NetlinkSocket<NetlinkRoute>) -> Table<7> {
+	let mut table = tables::Table::new_with_headers(["Bond", "Mode", "State", "Port", "Port State", "MII", "Failures"])
+		.with_setting(tables::TableSetting::ColumnSeperators)
+		.with_setting(tables::TableSetting::HeaderSeperator);
+
+	let links = match netlink_socket.get_links() {
+		Ok(links) => links,
+		Err(e) => {
+			eprintln!("failed to read links: {}", describe(&e));
+			return table;
+		}
+	};
+	for (bond, ports) in masters_and_ports(&links, MasterKind::Bond) {
+		let attributes = bond
+			.attributes
+			.link_info
+			.as_ref()
+			.and_then(|info| info.bond().ok().flatten());
+		let mode = attributes
+			.as_ref()
+			.and_then(|attributes| attributes.mode)
+			.map_or_else(|| String::from("<unknown>"), |mode| mode.to_string());
+		let bond_state = operational_state(bond);
+
+		if ports.is_empty() {
+			table.add_row([link_name(bond), &mode, &bond_state, "-", "-", "-", "-"]);
+			continue;
+		}
+
+		for port in ports {
+			let port_attributes = port
+				.attributes
+				.link_info
+				.as_ref()
+				.and_then(|info| info.bond_port().ok().flatten());
+			let port_state = port_attributes
+				.as_ref()
+				.and_then(|attributes| attributes.state.as_ref())
+				.map(ToString::to_string);
+			let mii_status = port_attributes
+				.as_ref()
+				.and_then(|attributes| attributes.mii_status.as_ref())
+				.map(ToString::to_string);
+			let failures = port_attributes
+				.as_ref()
+				.and_then(|attributes| attributes.link_failure_count)
+				.map(|count| count.to_string());
+
+			table.add_row([
+				link_name(bond),
+				&mode,
+				&bond_state,
+				link_name(port),
+				port_state.as_deref().unwrap_or("-"),
+				mii_status.as_deref().unwrap_or("-"),
+				failures.as_deref().unwrap_or("-"),
+			]);
+		}
+	}
+
+	table
+}
+
+pub fn bridge_table(netlink_socket: &mut NetlinkSocket<NetlinkRoute>) -> Table<5> {
+	let mut table = tables::Table::new_with_headers(["Bridge", "State", "Port", "Link State", "Port State"])
+		.with_setting(tables::TableSetting::ColumnSeperators)
+		.with_setting(tables::TableSetting::HeaderSeperator);
+
+	let links = match netlink_socket.get_links() {
+		Ok(links) => links,
+		Err(e) => {
+			eprintln!("failed to read links: {}", describe(&e));
+			return table;
+		}
+	};
+
+	// The spanning tree state of ports is only in the bridges' own dump.
+	let port_states: HashMap<i32, String> = netlink_socket
+		.get_bridge_ports()
+		.unwrap_or_default()
+		.into_iter()
+		.filter_map(|port| {
+			let state = port.bridge_port().ok().flatten()?.state?;
+			Some((port.index, state.to_string()))
+		})
+		.collect();
+
+	for (bridge, ports) in masters_and_ports(&links, MasterKind::Bridge) {
+		let bridge_state = operational_state(bridge);
+		if ports.is_empty() {
+			table.add_row([link_name(bridge), &bridge_state, "-", "-", "-"]);
+			continue;
+		}
+
+		for port in ports {
+			table.add_row([
+				link_name(bridge),
+				&bridge_state,
+				link_name(port),
+				&operational_state(port),
+				port_states.get(&port.index).map_or("-", String::as_str),
+			]);
+		}
+	}
+
+	table
+}
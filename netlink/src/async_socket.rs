@@ -3,9 +3,13 @@ use std::{
 	task::{ready, Context, Poll},
 };
 
+use bytestruct::{ReadFromWithEndian, WriteToWithEndian};
+use common::rand::rand_u32;
 use tokio::io::{self, unix::AsyncFd, AsyncRead, AsyncWrite, ReadBuf};
 
-use crate::{NetlinkSockType, NetlinkSocket};
+use crate::{
+	encode_body, NetlinkError, NetlinkMessageHeader, NetlinkResponse, NetlinkSockType, NetlinkSocket, RequestPolicy,
+};
 
 /// An async wrapper around a Netlink socket.
 pub struct AsyncNetlinkSocket<T: NetlinkSockType>(AsyncFd<NetlinkSocket<T>>);
@@ -17,6 +21,103 @@ impl<T: NetlinkSockType> AsyncNetlinkSocket<T> {
 
 		Ok(Self(async_fd))
 	}
+
+	/// Sets how long requests made through this socket wait for responses, and how many times they are resent.
+	pub fn set_request_policy(&mut self, policy: RequestPolicy) {
+		self.0.get_mut().set_request_policy(policy);
+	}
+
+	/// Sends a request and reads the responses to it, up to and including the first one that `is_last` returns true
+	/// for. Timeouts behave the same as `NetlinkSocket::request`, and the request is only sent once.
+	pub async fn request<M: WriteToWithEndian, E: ReadFromWithEndian>(
+		&self,
+		header: NetlinkMessageHeader<T>,
+		msg: M,
+		is_last: impl Fn(&NetlinkMessageHeader<T>) -> bool,
+	) -> Result<Vec<NetlinkResponse<T>>, NetlinkError<T, E>> {
+		self.send_request(header, msg, is_last, 1).await
+	}
+
+	/// Sends a request like `request`, resending it if its responses don't arrive in time, as with
+	/// `NetlinkSocket::request_with_retries`. This is only safe for requests that can be acted on more than once.
+	pub async fn request_with_retries<M: WriteToWithEndian, E: ReadFromWithEndian>(
+		&self,
+		header: NetlinkMessageHeader<T>,
+		msg: M,
+		is_last: impl Fn(&NetlinkMessageHeader<T>) -> bool,
+	) -> Result<Vec<NetlinkResponse<T>>, NetlinkError<T, E>> {
+		let attempts = self.0.get_ref().request_policy().attempts();
+		self.send_request(header, msg, is_last, attempts).await
+	}
+
+	async fn send_request<M: WriteToWithEndian, E: ReadFromWithEndian>(
+		&self,
+		mut header: NetlinkMessageHeader<T>,
+		msg: M,
+		is_last: impl Fn(&NetlinkMessageHeader<T>) -> bool,
+		attempts: u32,
+	) -> Result<Vec<NetlinkResponse<T>>, NetlinkError<T, E>> {
+		let body = encode_body(msg)?;
+		for _ in 0..attempts {
+			self.0.get_ref().write_netlink_body(&mut header, &body)?;
+			if let Some(responses) = self.read_responses(header.sequence_number, &is_last).await? {
+				return Ok(responses);
+			}
+
+			header.sequence_number = rand_u32()?;
+		}
+
+		Err(NetlinkError::Timeout(attempts))
+	}
+
+	/// Reads the responses to the request with the given sequence number, returning None if the policy timeout
+	/// expires while waiting for one of them. The timeout applies to each response, rather than all of them, so that
+	/// large dumps aren't cut short.
+	async fn read_responses(
+		&self,
+		sequence_number: u32,
+		is_last: impl Fn(&NetlinkMessageHeader<T>) -> bool,
+	) -> io::Result<Option<Vec<NetlinkResponse<T>>>> {
+		let timeout = self.0.get_ref().request_policy().timeout;
+		let mut responses = Vec::new();
+		loop {
+			let message = self.read_netlink_message();
+			let (header, body) = match timeout {
+				Some(timeout) => match tokio::time::timeout(timeout, message).await {
+					Ok(message) => message?,
+					Err(_) => return Ok(None),
+				},
+				None => message.await?,
+			};
+
+			// Skip anything that isn't a response to this request, e.g. late responses to an earlier attempt.
+			if header.sequence_number != sequence_number {
+				continue;
+			}
+
+			let last = is_last(&header);
+			responses.push((header, body));
+			if last {
+				return Ok(Some(responses));
+			}
+		}
+	}
+
+	/// Reads a single Netlink message from the socket.
+	pub async fn read_netlink_message(&self) -> io::Result<NetlinkResponse<T>> {
+		loop {
+			// A previous read may have buffered more messages than it consumed, which won't wake the fd up.
+			if self.0.get_ref().has_buffered_data() {
+				return self.0.get_ref().read_netlink_message();
+			}
+
+			let mut guard = self.0.readable().await?;
+			match guard.try_io(|inner| inner.get_ref().read_netlink_message()) {
+				Ok(result) => return result,
+				Err(_would_block) => continue,
+			}
+		}
+	}
 }
 
 impl<T: NetlinkSockType> AsyncRead for AsyncNetlinkSocket<T> {
@@ -16,6 +16,9 @@ pub enum NetlinkError<T: NetlinkSockType, M: ReadFromWithEndian> {
 
 	#[error("Netlink Error ({0}): {1}")]
 	NetlinkError(Errno, NetlinkErrorContents<T, M>),
+
+	#[error("Timed out waiting for a response after {0} attempts")]
+	Timeout(u32),
 }
 
 pub type NetlinkResult<T, M> = Result<(), NetlinkError<T, M>>;
@@ -77,7 +80,7 @@ int_enum! {
 	Policy = 4,
 	MissType = 5,
 	MissNest = 6,
-  Unknown = 9999,
+  _ => Unknown(u16),
   }
 }
 
@@ -105,7 +108,7 @@ impl ReadFromWithEndian for ErrorAttributes {
 impl ErrorAttributes {
 	pub(crate) fn read_attribute<T: Read>(&mut self, source: &mut T, endian: Endian) -> io::Result<()> {
 		let (attr_type, data_buffer) = read_attribute(source, endian)?;
-		match AttributeType::try_from(attr_type).unwrap_or(AttributeType::Unknown) {
+		match AttributeType::from(attr_type) {
 			AttributeType::Msg => self.msg = Some(new_string(&data_buffer)?),
 			AttributeType::AttributeOffset => self.attribute_offset = Some(new_u32(&data_buffer)?),
 			_ => self.unknowns.push((attr_type, data_buffer)),
@@ -1,5 +1,3 @@
-#![feature(buf_read_has_data_left)]
-
 #[cfg(feature = "async")]
 mod async_socket;
 #[cfg(feature = "async")]
@@ -11,23 +9,62 @@ pub use error::*;
 pub mod rtnetlink;
 
 use std::{
-	io::{self, BufReader, Cursor, ErrorKind, Read, Write},
+	io::{self, BufReader, ErrorKind, Read, Write},
 	marker::PhantomData,
 	os::fd::{AsRawFd, OwnedFd},
 	sync::Mutex,
+	time::Duration,
 };
 
 use bitflags::{bitflags, Flags};
-use bytestruct::{int_enum, Endian, ReadFromWithEndian, Size, WriteToWithEndian};
-use bytestruct_derive::{ByteStruct, Size};
+use bytestruct::{int_enum, Endian, FromBytes, FromBytesError, ReadFromWithEndian, Size, WriteToWithEndian};
+use bytestruct_derive::{ByteStruct, FromBytes, Size};
 use nix::{
+	errno::Errno,
 	libc::{setsockopt, NETLINK_EXT_ACK, SOL_NETLINK},
+	poll::{poll, PollFd, PollFlags},
 	sys::socket::{self, AddressFamily, NetlinkAddr, SockFlag, SockProtocol, SockType},
 	unistd::{getpid, write},
 };
 
 use common::{io::RawFdReader, rand::rand_u32};
 
+/// The default amount of time to wait for each response to a request.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default number of times to resend a request that doesn't get a response in time.
+pub const DEFAULT_REQUEST_RETRIES: u32 = 2;
+
+/// How long requests wait for responses, and how many times they are resent if the responses don't arrive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestPolicy {
+	/// How long to wait for each response. If None, we wait forever.
+	pub timeout: Option<Duration>,
+
+	/// How many times to resend a request made with `request_with_retries` that times out, before giving up.
+	pub retries: u32,
+}
+
+impl RequestPolicy {
+	pub fn new(timeout: Option<Duration>, retries: u32) -> Self {
+		Self { timeout, retries }
+	}
+
+	/// The total number of times a request made with `request_with_retries` is sent, including the first.
+	pub fn attempts(&self) -> u32 {
+		self.retries + 1
+	}
+}
+
+impl Default for RequestPolicy {
+	fn default() -> Self {
+		Self::new(Some(DEFAULT_REQUEST_TIMEOUT), DEFAULT_REQUEST_RETRIES)
+	}
+}
+
+/// A message read from a Netlink socket, along with its (unparsed) body.
+pub type NetlinkResponse<T> = (NetlinkMessageHeader<T>, Vec<u8>);
+
 /// A socket for communicating with the kernel over Netlink.
 pub struct NetlinkSocket<T: NetlinkSockType> {
 	socket_fd: OwnedFd,
@@ -35,6 +72,9 @@ pub struct NetlinkSocket<T: NetlinkSockType> {
 	/// A BufReader over the socket connection.
 	reader: Mutex<BufReader<RawFdReader>>,
 
+	/// The timeout and retry policy for requests made with `request`.
+	policy: RequestPolicy,
+
 	_phantom: PhantomData<T>,
 }
 
@@ -48,7 +88,8 @@ impl<T: NetlinkSockType> NetlinkSocket<T> {
 			T::SOCK_PROTOCOL,
 		)?;
 
-		let address = NetlinkAddr::new(getpid().as_raw() as u32, groups.bits());
+		// Let the kernel pick the port ID, as using our PID would stop a process from opening more than one socket.
+		let address = NetlinkAddr::new(0, groups.bits());
 
 		socket::bind(socket_fd.as_raw_fd(), &address)?;
 
@@ -65,10 +106,7 @@ impl<T: NetlinkSockType> NetlinkSocket<T> {
 		};
 
 		if errcode != 0 {
-			return Err(io::Error::new(
-				ErrorKind::Other,
-				format!("failed to set NETLINK_EXT_ACK: {}", errcode),
-			));
+			return Err(io::Error::other(format!("failed to set NETLINK_EXT_ACK: {}", errcode)));
 		}
 
 		Ok(Self {
@@ -76,18 +114,32 @@ impl<T: NetlinkSockType> NetlinkSocket<T> {
 			// socket,
 			reader: Mutex::new(BufReader::new(RawFdReader::new(socket_fd.as_raw_fd()))),
 			socket_fd,
+			policy: RequestPolicy::default(),
 			_phantom: PhantomData,
 		})
 	}
 
+	/// Sets how long requests made through this socket wait for responses, and how many times they are resent.
+	pub fn set_request_policy(&mut self, policy: RequestPolicy) {
+		self.policy = policy;
+	}
+
+	/// Gets the timeout and retry policy for requests made through this socket.
+	pub fn request_policy(&self) -> RequestPolicy {
+		self.policy
+	}
+
 	pub fn write_netlink_message<M: WriteToWithEndian>(
 		&self,
 		mut header: NetlinkMessageHeader<T>,
 		msg: M,
 	) -> io::Result<usize> {
-		let mut body = Vec::new();
-		msg.write_to_with_endian(&mut body, bytestruct::Endian::Little)?;
+		let body = encode_body(msg)?;
+		self.write_netlink_body(&mut header, &body)
+	}
 
+	/// Writes the header, followed by the already encoded body, setting the length in the header.
+	pub(crate) fn write_netlink_body(&self, header: &mut NetlinkMessageHeader<T>, body: &[u8]) -> io::Result<usize> {
 		header.length = (header.size() + body.len()) as u32;
 		let mut buf = Vec::new();
 		header.write_to_with_endian(&mut buf, bytestruct::Endian::Little)?;
@@ -96,15 +148,108 @@ impl<T: NetlinkSockType> NetlinkSocket<T> {
 		self.uwrite(&buf)
 	}
 
-	pub fn read_netlink_message(&self) -> io::Result<(NetlinkMessageHeader<T>, Vec<u8>)> {
+	/// Sends a request and reads the responses to it, up to and including the first one that `is_last` returns true
+	/// for. If one of the responses doesn't arrive within the timeout of the request policy, `NetlinkError::Timeout`
+	/// is returned. The request is only sent once, as it may have been acted on even if its responses are lost.
+	pub fn request<M: WriteToWithEndian, E: ReadFromWithEndian>(
+		&self,
+		header: NetlinkMessageHeader<T>,
+		msg: M,
+		is_last: impl Fn(&NetlinkMessageHeader<T>) -> bool,
+	) -> Result<Vec<NetlinkResponse<T>>, NetlinkError<T, E>> {
+		self.send_request(header, msg, is_last, 1)
+	}
+
+	/// Sends a request like `request`, but if one of the responses doesn't arrive in time, the request is resent with
+	/// a new sequence number, up to the number of retries in the request policy. This is only safe for requests that
+	/// can be acted on more than once, e.g. dumps.
+	pub fn request_with_retries<M: WriteToWithEndian, E: ReadFromWithEndian>(
+		&self,
+		header: NetlinkMessageHeader<T>,
+		msg: M,
+		is_last: impl Fn(&NetlinkMessageHeader<T>) -> bool,
+	) -> Result<Vec<NetlinkResponse<T>>, NetlinkError<T, E>> {
+		self.send_request(header, msg, is_last, self.policy.attempts())
+	}
+
+	fn send_request<M: WriteToWithEndian, E: ReadFromWithEndian>(
+		&self,
+		mut header: NetlinkMessageHeader<T>,
+		msg: M,
+		is_last: impl Fn(&NetlinkMessageHeader<T>) -> bool,
+		attempts: u32,
+	) -> Result<Vec<NetlinkResponse<T>>, NetlinkError<T, E>> {
+		let body = encode_body(msg)?;
+		for _ in 0..attempts {
+			self.write_netlink_body(&mut header, &body)?;
+			if let Some(responses) = self.read_responses(header.sequence_number, &is_last)? {
+				return Ok(responses);
+			}
+
+			header.sequence_number = rand_u32()?;
+		}
+
+		Err(NetlinkError::Timeout(attempts))
+	}
+
+	/// Reads the responses to the request with the given sequence number, returning None if the policy timeout
+	/// expires while waiting for one of them.
+	fn read_responses(
+		&self,
+		sequence_number: u32,
+		is_last: impl Fn(&NetlinkMessageHeader<T>) -> bool,
+	) -> io::Result<Option<Vec<NetlinkResponse<T>>>> {
+		let mut responses = Vec::new();
+		loop {
+			if !self.wait_readable(self.policy.timeout)? {
+				return Ok(None);
+			}
+
+			let (header, body) = self.read_netlink_message()?;
+
+			// Skip anything that isn't a response to this request, e.g. late responses to an earlier attempt.
+			if header.sequence_number != sequence_number {
+				continue;
+			}
+
+			let last = is_last(&header);
+			responses.push((header, body));
+			if last {
+				return Ok(Some(responses));
+			}
+		}
+	}
+
+	/// Waits up to the given timeout for a message to be available to read, returning whether one is.
+	pub(crate) fn wait_readable(&self, timeout: Option<Duration>) -> io::Result<bool> {
+		if self.has_buffered_data() {
+			return Ok(true);
+		}
+
+		let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+		let mut fds = [PollFd::new(&self.socket_fd, PollFlags::POLLIN)];
+		loop {
+			match poll(&mut fds, timeout) {
+				Ok(ready) => return Ok(ready > 0),
+				Err(Errno::EINTR) => continue,
+				Err(e) => return Err(e.into()),
+			}
+		}
+	}
+
+	/// Whether there is data that has already been read from the socket, but not yet consumed.
+	pub(crate) fn has_buffered_data(&self) -> bool {
+		!self.reader.lock().unwrap().buffer().is_empty()
+	}
+
+	pub fn read_netlink_message(&self) -> io::Result<NetlinkResponse<T>> {
 		let mut header = [0; 16];
 		let n = self.uread(&mut header)?;
 		if n != 16 {
 			return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid read for header"));
 		}
 
-		let header =
-			NetlinkMessageHeader::read_from_with_endian(&mut Cursor::new(&header), bytestruct::Endian::Little)?;
+		let (header, _) = NetlinkMessageHeader::from_bytes(&header, bytestruct::Endian::Little)?;
 		let mut body = vec![0; header.length as usize - header.size()];
 		if self.uread(&mut body)? != body.len() {
 			return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid read for body"));
@@ -145,6 +290,13 @@ impl<T: NetlinkSockType> AsRawFd for NetlinkSocket<T> {
 	}
 }
 
+/// Encodes the given message into the body of a Netlink message.
+pub(crate) fn encode_body<M: WriteToWithEndian>(msg: M) -> io::Result<Vec<u8>> {
+	let mut body = Vec::new();
+	msg.write_to_with_endian(&mut body, bytestruct::Endian::Little)?;
+	Ok(body)
+}
+
 /// The Netlink socket type for receiving kernel uevents.
 pub struct NetlinkKObjectUEvent;
 
@@ -165,10 +317,10 @@ pub trait NetlinkSockType {
 	const SOCK_PROTOCOL: SockProtocol;
 
 	type SockGroups: Flags<Bits = u32>;
-	type MessageType: ReadFromWithEndian + WriteToWithEndian + Size + std::fmt::Debug;
+	type MessageType: ReadFromWithEndian + for<'a> FromBytes<'a> + WriteToWithEndian + Size + std::fmt::Debug;
 }
 
-#[derive(Debug, ByteStruct, Size)]
+#[derive(Debug, ByteStruct, FromBytes, Size)]
 pub struct NetlinkMessageHeader<T: NetlinkSockType> {
 	pub length: u32,
 	pub message_type: T::MessageType,
@@ -230,6 +382,13 @@ impl ReadFromWithEndian for NetlinkFlags {
 	}
 }
 
+impl<'a> FromBytes<'a> for NetlinkFlags {
+	fn from_bytes(bytes: &'a [u8], endian: Endian) -> Result<(Self, &'a [u8]), FromBytesError> {
+		let (bits, rest) = u16::from_bytes(bytes, endian)?;
+		Ok((Self::from_bits_retain(bits), rest))
+	}
+}
+
 impl Size for NetlinkFlags {
 	fn size(&self) -> usize {
 		2
@@ -271,6 +430,10 @@ bitflags! {
 const ATTRIBUTE_SIZE: usize = 4;
 const ATTRIBUTE_ALIGN_TO: usize = 4;
 
+/// The top two bits of an attribute's type are flags (whether it's nested, or in network byte order), rather than
+/// part of the type.
+const ATTRIBUTE_TYPE_MASK: u16 = 0x3FFF;
+
 pub(crate) fn read_attribute<T: Read>(source: &mut T, endian: Endian) -> io::Result<(u16, Vec<u8>)> {
 	let length = u16::read_from_with_endian(source, endian)? as usize;
 	if length < ATTRIBUTE_SIZE {
@@ -283,7 +446,7 @@ pub(crate) fn read_attribute<T: Read>(source: &mut T, endian: Endian) -> io::Res
 		));
 	}
 
-	let attr_type = u16::read_from_with_endian(source, endian)?;
+	let attr_type = u16::read_from_with_endian(source, endian)? & ATTRIBUTE_TYPE_MASK;
 	let padding_length = ((length + ATTRIBUTE_ALIGN_TO - 1) & !(ATTRIBUTE_ALIGN_TO - 1)) - length;
 
 	let mut data_buffer = vec![0; length - ATTRIBUTE_SIZE];
@@ -323,12 +486,38 @@ pub(crate) fn write_attribute<W: Write, T: Into<u16>, D: WriteToWithEndian>(
 	Ok(())
 }
 
+/// The payload of an attribute that has already been encoded, e.g. nested attributes whose meaning depends on the
+/// kind of link that they're for.
+pub(crate) struct RawAttribute(pub Vec<u8>);
+
+impl WriteToWithEndian for RawAttribute {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, _: Endian) -> io::Result<()> {
+		target.write_all(&self.0)
+	}
+}
+
 pub(crate) fn new_string(buffer: &[u8]) -> io::Result<String> {
 	Ok(std::str::from_utf8(&buffer[0..buffer.len() - 1])
 		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
 		.to_owned())
 }
 
+pub(crate) fn new_u8(buffer: &[u8]) -> io::Result<u8> {
+	match buffer {
+		[value] => Ok(*value),
+		_ => Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			format!("expected 1 byte, got {}", buffer.len()),
+		)),
+	}
+}
+
+pub(crate) fn new_u16(buffer: &[u8]) -> io::Result<u16> {
+	Ok(u16::from_le_bytes(buffer.try_into().map_err(|e| {
+		io::Error::new(io::ErrorKind::InvalidData, format!("expected 2 bytes, got {:?}", e))
+	})?))
+}
+
 pub(crate) fn new_u32(buffer: &[u8]) -> io::Result<u32> {
 	Ok(u32::from_le_bytes(buffer.try_into().map_err(|e| {
 		io::Error::new(io::ErrorKind::InvalidData, format!("expected 4 bytes, got {:?}", e))
@@ -34,7 +34,7 @@ impl WriteToWithEndian for MacAddress {
 }
 
 int_enum! {
-	#[derive(Debug)]
+	#[derive(Debug, Clone, Copy, PartialEq)]
 	pub enum AddressFamily: u8 {
 		Unspecified = 0,
 		IPv4 = 2,
@@ -43,8 +43,10 @@ int_enum! {
 }
 
 bitflags! {
-	#[derive(Debug)]
-	pub struct AddressFlags : u8 {
+	/// The flags of an address. The message header only has room for the lower eight, so the full set is sent in the
+	/// IFA_FLAGS attribute.
+	#[derive(Debug, Clone, Copy)]
+	pub struct AddressFlags : u32 {
 		const IFA_F_SECONDARY = 0x01;
 		const IFA_F_NODAD = 0x02;
 		const IFA_F_OPTIMISTIC = 0x04;
@@ -53,6 +55,52 @@ bitflags! {
 		const IFA_F_DEPRECATED = 0x20;
 		const IFA_F_TENTATIVE = 0x40;
 		const IFA_F_PERMANENT = 0x80;
+		const IFA_F_MANAGETEMPADDR = 0x100;
+		const IFA_F_NOPREFIXROUTE = 0x200;
+		const IFA_F_MCAUTOJOIN = 0x400;
+		const IFA_F_STABLE_PRIVACY = 0x800;
+	}
+}
+
+impl AddressFlags {
+	/// The flags with the names that `ip addr` shows them with, other than the secondary flag (see `names`).
+	const NAMES: [(AddressFlags, &'static str); 11] = [
+		(Self::IFA_F_NODAD, "nodad"),
+		(Self::IFA_F_OPTIMISTIC, "optimistic"),
+		(Self::IFA_F_DADFAILED, "dadfailed"),
+		(Self::IFA_F_HOMEADDRESS, "home"),
+		(Self::IFA_F_DEPRECATED, "deprecated"),
+		(Self::IFA_F_TENTATIVE, "tentative"),
+		(Self::IFA_F_PERMANENT, "permanent"),
+		(Self::IFA_F_MANAGETEMPADDR, "mngtmpaddr"),
+		(Self::IFA_F_NOPREFIXROUTE, "noprefixroute"),
+		(Self::IFA_F_MCAUTOJOIN, "autojoin"),
+		(Self::IFA_F_STABLE_PRIVACY, "stable-privacy"),
+	];
+
+	/// Returns the readable names of the flags. IPv6 uses the secondary flag to mark temporary (privacy) addresses,
+	/// so it's named for the family of the address. Any flags that aren't known are shown in hex.
+	pub fn names(&self, family: &AddressFamily) -> Vec<String> {
+		let mut names = Vec::new();
+		if self.contains(Self::IFA_F_SECONDARY) {
+			names.push(String::from(match family {
+				AddressFamily::IPv6 => "temporary",
+				_ => "secondary",
+			}));
+		}
+
+		for (flag, name) in Self::NAMES {
+			if self.contains(flag) {
+				names.push(String::from(name));
+			}
+		}
+
+		let unknown = self.bits() & !Self::all().bits();
+		if unknown != 0 {
+			names.push(format!("{:#x}", unknown));
+		}
+
+		names
 	}
 }
 
@@ -62,16 +110,17 @@ impl Display for AddressFlags {
 	}
 }
 
+// In the message header, the flags are a single byte.
 impl WriteToWithEndian for AddressFlags {
 	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: bytestruct::Endian) -> io::Result<()> {
-		self.bits().write_to_with_endian(target, endian)
+		(self.bits() as u8).write_to_with_endian(target, endian)
 	}
 }
 
 impl ReadFromWithEndian for AddressFlags {
 	fn read_from_with_endian<T: Read>(source: &mut T, endian: bytestruct::Endian) -> io::Result<Self> {
 		let val = u8::read_from_with_endian(source, endian)?;
-		Ok(Self::from_bits_retain(val))
+		Ok(Self::from_bits_retain(val as u32))
 	}
 }
 
@@ -89,6 +138,7 @@ int_enum! {
 		Link = 253,
 		Host = 254,
 		Nowhere = 255,
+		_ => Unknown(u8),
 	}
 }
 
@@ -226,7 +276,7 @@ int_enum! {
 		RoutePriority = 9,
 		TargetNewNetNamespaceID = 10,
 		Protocol = 11,
-		Unknown = 9999,
+		_ => Unknown(u16),
 	}
 }
 
@@ -273,7 +323,12 @@ impl WriteToWithEndian for AddressAttributes {
 		write_attribute(target, endian, AttributeType::Broadcast, &self.broadcast_address)?;
 		write_attribute(target, endian, AttributeType::Anycast, &self.anycast_address)?;
 		write_attribute(target, endian, AttributeType::Multicast, &self.multicast)?;
-		write_attribute(target, endian, AttributeType::Flags, &self.flags)?;
+		write_attribute(
+			target,
+			endian,
+			AttributeType::Flags,
+			&self.flags.map(|flags| flags.bits()),
+		)?;
 		write_attribute(target, endian, AttributeType::RoutePriority, &self.priority)?;
 		write_attribute(target, endian, AttributeType::Protocol, &self.protocol)?;
 
@@ -285,7 +340,7 @@ impl AddressAttributes {
 	pub(crate) fn read_attribute<T: Read>(&mut self, source: &mut T, endian: Endian) -> io::Result<()> {
 		let (attr_type, data_buffer) = read_attribute(source, endian)?;
 
-		match AttributeType::try_from(attr_type).unwrap_or(AttributeType::Unknown) {
+		match AttributeType::from(attr_type) {
 			AttributeType::Address => self.address = Some(IPAddress::new(&data_buffer)?),
 			AttributeType::Local => self.local_address = Some(IPAddress::new(&data_buffer)?),
 			AttributeType::Label => self.label = Some(new_string(&data_buffer)?),
@@ -298,12 +353,7 @@ impl AddressAttributes {
 				)?)
 			}
 			AttributeType::Multicast => self.multicast = Some(IPAddress::new(&data_buffer)?),
-			AttributeType::Flags => {
-				self.flags = Some(AddressFlags::read_from_with_endian(
-					&mut Cursor::new(&data_buffer),
-					endian,
-				)?)
-			}
+			AttributeType::Flags => self.flags = Some(AddressFlags::from_bits_retain(new_u32(&data_buffer)?)),
 			AttributeType::RoutePriority => self.priority = Some(new_u32(&data_buffer)?),
 			AttributeType::TargetNewNetNamespaceID => self.new_net_namespace_id = Some(new_u32(&data_buffer)?),
 			AttributeType::Protocol => {
@@ -319,6 +369,29 @@ impl AddressAttributes {
 	}
 }
 
+/// What an address is used for, which determines the attribute that it's sent in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressKind {
+	/// An address of the interface, in IFA_ADDRESS, dumped with RTM_GETADDR.
+	Unicast,
+
+	/// An anycast address that the interface answers to, in IFA_ANYCAST, dumped with RTM_GETANYCAST.
+	Anycast,
+
+	/// A multicast group that the interface has joined, in IFA_MULTICAST, dumped with RTM_GETMULTICAST.
+	Multicast,
+}
+
+impl Display for AddressKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Unicast => f.write_str("unicast"),
+			Self::Anycast => f.write_str("anycast"),
+			Self::Multicast => f.write_str("multicast"),
+		}
+	}
+}
+
 #[derive(Debug, ByteStruct)]
 pub struct CacheInfo {
 	preferred: u32,
@@ -333,5 +406,6 @@ int_enum! {
 		Loopback = 1,
 		RouterAnnouncement = 2,
 		LinkLocal = 3,
+		_ => Unknown(u8),
 	}
 }
@@ -0,0 +1,223 @@
+use std::{
+	fmt::Display,
+	io::{self, ErrorKind, Read, Write},
+	str::FromStr,
+};
+
+use bytestruct::{int_enum, Endian, ReadFromWithEndian, WriteToWithEndian};
+
+use crate::{new_u32, new_u8, read_attribute, write_attribute};
+
+int_enum! {
+	enum BondAttributeType: u16 {
+		Mode = 1,
+		ActivePort = 2,
+		MiiMonitorInterval = 3,
+		_ => Unknown(u16),
+	}
+}
+
+/// The nested rtattr's in the IFLA_INFO_DATA of a bond.
+#[derive(Debug, Default)]
+pub struct BondAttributes {
+	/// How packets are spread over the ports of the bond.
+	pub mode: Option<BondMode>,
+	/// The index of the port that packets are sent through, in modes that only use one at a time.
+	pub active_port: Option<u32>,
+	/// How often (in milliseconds) the carrier of each port is checked, or 0 if it isn't.
+	pub mii_monitor_interval: Option<u32>,
+
+	unknown: Vec<(u16, Vec<u8>)>,
+}
+
+impl WriteToWithEndian for BondAttributes {
+	fn write_to_with_endian<T: Write>(&self, t: &mut T, e: Endian) -> io::Result<()> {
+		write_attribute(t, e, BondAttributeType::Mode, &self.mode)?;
+		write_attribute(t, e, BondAttributeType::MiiMonitorInterval, &self.mii_monitor_interval)?;
+		Ok(())
+	}
+}
+
+impl ReadFromWithEndian for BondAttributes {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut attributes = Self::default();
+		loop {
+			match attributes.read_attribute(source, endian) {
+				Ok(_) => {}
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(attributes)
+	}
+}
+
+impl BondAttributes {
+	pub(crate) fn read_attribute<T: Read>(&mut self, source: &mut T, endian: Endian) -> io::Result<()> {
+		let (attr_type, data_buffer) = read_attribute(source, endian)?;
+
+		match BondAttributeType::from(attr_type) {
+			BondAttributeType::Mode => {
+				self.mode = Some(
+					BondMode::try_from(new_u8(&data_buffer)?)
+						.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+				)
+			}
+			BondAttributeType::ActivePort => self.active_port = Some(new_u32(&data_buffer)?),
+			BondAttributeType::MiiMonitorInterval => self.mii_monitor_interval = Some(new_u32(&data_buffer)?),
+			BondAttributeType::Unknown(attr_type) => self.unknown.push((attr_type, data_buffer)),
+		}
+
+		Ok(())
+	}
+}
+
+int_enum! {
+	#[derive(Debug, Clone, Copy, PartialEq)]
+	pub enum BondMode: u8 {
+		BalanceRoundRobin = 0,
+		ActiveBackup = 1,
+		BalanceXor = 2,
+		Broadcast = 3,
+		LinkAggregation = 4,
+		BalanceTransmit = 5,
+		BalanceAdaptive = 6,
+	}
+}
+
+impl BondMode {
+	/// The names of the modes, as they're written in bonding's sysfs files and by `FromStr`.
+	pub const NAMES: [&'static str; 7] = [
+		"balance-rr",
+		"active-backup",
+		"balance-xor",
+		"broadcast",
+		"802.3ad",
+		"balance-tlb",
+		"balance-alb",
+	];
+}
+
+impl Display for BondMode {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(Self::NAMES[u8::from(self) as usize])
+	}
+}
+
+impl FromStr for BondMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match Self::NAMES.iter().position(|name| *name == s) {
+			Some(mode) => Self::try_from(mode as u8),
+			None => Err(format!(
+				"invalid bond mode `{}`, expected one of {}",
+				s,
+				Self::NAMES.join(", ")
+			)),
+		}
+	}
+}
+
+int_enum! {
+	enum BondPortAttributeType: u16 {
+		State = 1,
+		MiiStatus = 2,
+		LinkFailureCount = 3,
+		_ => Unknown(u16),
+	}
+}
+
+/// The nested rtattr's in the IFLA_INFO_SLAVE_DATA of a port of a bond.
+#[derive(Debug, Default)]
+pub struct BondPortAttributes {
+	/// Whether the port is being used to send packets, or is a backup in case the active ports fail.
+	pub state: Option<BondPortState>,
+	/// The state of the carrier of the port, as the bond's link monitoring sees it.
+	pub mii_status: Option<BondLinkState>,
+	/// How many times the link monitoring has seen the port fail.
+	pub link_failure_count: Option<u32>,
+
+	unknown: Vec<(u16, Vec<u8>)>,
+}
+
+impl ReadFromWithEndian for BondPortAttributes {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut attributes = Self::default();
+		loop {
+			match attributes.read_attribute(source, endian) {
+				Ok(_) => {}
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(attributes)
+	}
+}
+
+impl BondPortAttributes {
+	pub(crate) fn read_attribute<T: Read>(&mut self, source: &mut T, endian: Endian) -> io::Result<()> {
+		let (attr_type, data_buffer) = read_attribute(source, endian)?;
+
+		match BondPortAttributeType::from(attr_type) {
+			BondPortAttributeType::State => {
+				self.state = Some(
+					BondPortState::try_from(new_u8(&data_buffer)?)
+						.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+				)
+			}
+			BondPortAttributeType::MiiStatus => {
+				self.mii_status = Some(
+					BondLinkState::try_from(new_u8(&data_buffer)?)
+						.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+				)
+			}
+			BondPortAttributeType::LinkFailureCount => self.link_failure_count = Some(new_u32(&data_buffer)?),
+			BondPortAttributeType::Unknown(attr_type) => self.unknown.push((attr_type, data_buffer)),
+		}
+
+		Ok(())
+	}
+}
+
+int_enum! {
+	#[derive(Debug)]
+	pub enum BondPortState: u8 {
+		Active = 0,
+		Backup = 1,
+	}
+}
+
+impl Display for BondPortState {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let out = match self {
+			Self::Active => "active",
+			Self::Backup => "backup",
+		};
+
+		f.write_str(out)
+	}
+}
+
+int_enum! {
+	#[derive(Debug)]
+	pub enum BondLinkState: u8 {
+		Up = 0,
+		Failing = 1,
+		Down = 2,
+		Recovering = 3,
+	}
+}
+
+impl Display for BondLinkState {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let out = match self {
+			Self::Up => "up",
+			Self::Failing => "failing",
+			Self::Down => "down",
+			Self::Recovering => "recovering",
+		};
+
+		f.write_str(out)
+	}
+}
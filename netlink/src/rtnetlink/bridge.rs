@@ -0,0 +1,287 @@
+use std::{
+	fmt::Display,
+	io::{self, Cursor, ErrorKind, Read, Write},
+};
+
+use bitflags::bitflags;
+use bytestruct::{int_enum, Endian, ReadFromWithEndian, Size, WriteToWithEndian};
+use bytestruct_derive::{ByteStruct, Size};
+
+use crate::{new_u16, new_u32, new_u8, read_attribute};
+
+int_enum! {
+	enum PortAttributeType: u16 {
+		State = 1,
+		Priority = 2,
+		Cost = 3,
+		_ => Unknown(u16),
+	}
+}
+
+/// The nested rtattr's in the IFLA_PROTINFO of a bridge port, as received from a dump of the bridge family.
+#[derive(Debug, Default)]
+pub struct BridgePortAttributes {
+	// Where the port is in the spanning tree protocol, i.e. whether it's forwarding packets.
+	pub state: Option<BridgePortState>,
+	// The priority of the port in the spanning tree protocol, where lower priorities are preferred.
+	pub priority: Option<u16>,
+	// The cost of the path through the port in the spanning tree protocol.
+	pub cost: Option<u32>,
+
+	unknown: Vec<(u16, Vec<u8>)>,
+}
+
+impl ReadFromWithEndian for BridgePortAttributes {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut attributes = Self::default();
+		loop {
+			match attributes.read_attribute(source, endian) {
+				Ok(_) => {}
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(attributes)
+	}
+}
+
+impl BridgePortAttributes {
+	pub(crate) fn read_attribute<T: Read>(&mut self, source: &mut T, endian: Endian) -> io::Result<()> {
+		let (attr_type, data_buffer) = read_attribute(source, endian)?;
+
+		match PortAttributeType::from(attr_type) {
+			PortAttributeType::State => {
+				self.state = Some(
+					BridgePortState::try_from(new_u8(&data_buffer)?)
+						.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+				)
+			}
+			PortAttributeType::Priority => self.priority = Some(new_u16(&data_buffer)?),
+			PortAttributeType::Cost => self.cost = Some(new_u32(&data_buffer)?),
+			PortAttributeType::Unknown(attr_type) => self.unknown.push((attr_type, data_buffer)),
+		}
+
+		Ok(())
+	}
+}
+
+int_enum! {
+	#[derive(Debug)]
+	pub enum BridgePortState: u8 {
+		Disabled = 0,
+		Listening = 1,
+		Learning = 2,
+		Forwarding = 3,
+		Blocking = 4,
+	}
+}
+
+impl Display for BridgePortState {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let out = match self {
+			Self::Disabled => "disabled",
+			Self::Listening => "listening",
+			Self::Learning => "learning",
+			Self::Forwarding => "forwarding",
+			Self::Blocking => "blocking",
+		};
+
+		f.write_str(out)
+	}
+}
+
+int_enum! {
+	enum BridgeAttributeType: u16 {
+		Flags = 0,
+		Mode = 1,
+		VlanInfo = 2,
+		_ => Unknown(u16),
+	}
+}
+
+/// The nested rtattr's in the IFLA_AF_SPEC of a bridge, or bridge port, as received from a dump of the bridge family.
+#[derive(Debug, Default)]
+pub struct BridgeAttributes {
+	pub flags: Option<u16>,
+	pub mode: Option<u16>,
+	// The VLANs that the port is a member of. These are only sent if they're asked for.
+	pub vlans: Vec<BridgeVlanInfo>,
+
+	unknown: Vec<(u16, Vec<u8>)>,
+}
+
+impl ReadFromWithEndian for BridgeAttributes {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut attributes = Self::default();
+		loop {
+			match attributes.read_attribute(source, endian) {
+				Ok(_) => {}
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(attributes)
+	}
+}
+
+impl BridgeAttributes {
+	pub(crate) fn read_attribute<T: Read>(&mut self, source: &mut T, endian: Endian) -> io::Result<()> {
+		let (attr_type, data_buffer) = read_attribute(source, endian)?;
+
+		match BridgeAttributeType::from(attr_type) {
+			BridgeAttributeType::Flags => self.flags = Some(new_u16(&data_buffer)?),
+			BridgeAttributeType::Mode => self.mode = Some(new_u16(&data_buffer)?),
+			BridgeAttributeType::VlanInfo => self.vlans.push(BridgeVlanInfo::read_from_with_endian(
+				&mut Cursor::new(data_buffer),
+				endian,
+			)?),
+			BridgeAttributeType::Unknown(attr_type) => self.unknown.push((attr_type, data_buffer)),
+		}
+
+		Ok(())
+	}
+}
+
+/// A VLAN that a bridge port is a member of.
+#[derive(Debug, ByteStruct, Size)]
+pub struct BridgeVlanInfo {
+	pub flags: BridgeVlanFlags,
+	pub id: u16,
+}
+
+bitflags! {
+	#[derive(Debug)]
+	pub struct BridgeVlanFlags: u16 {
+		const BRIDGE_VLAN_INFO_MASTER = 0x1;		/* Operate on the bridge device as well.  */
+		const BRIDGE_VLAN_INFO_PVID = 0x2;		/* Untagged packets that arrive on the port are in this VLAN.  */
+		const BRIDGE_VLAN_INFO_UNTAGGED = 0x4;	/* Packets in this VLAN leave the port untagged.  */
+		const BRIDGE_VLAN_INFO_RANGE_BEGIN = 0x8;	/* The start of a range of VLANs.  */
+		const BRIDGE_VLAN_INFO_RANGE_END = 0x10;	/* The end of a range of VLANs.  */
+		const BRIDGE_VLAN_INFO_BRENTRY = 0x20;	/* The VLAN is also on the bridge itself.  */
+	}
+}
+
+impl WriteToWithEndian for BridgeVlanFlags {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: Endian) -> io::Result<()> {
+		self.bits().write_to_with_endian(target, endian)
+	}
+}
+
+impl ReadFromWithEndian for BridgeVlanFlags {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let val = u16::read_from_with_endian(source, endian)?;
+		Ok(Self::from_bits_retain(val))
+	}
+}
+
+impl Size for BridgeVlanFlags {
+	fn size(&self) -> usize {
+		2
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::Cursor;
+
+	use bytestruct::{Endian, ReadFromWithEndian};
+
+	use super::{BridgePortState, BridgeVlanFlags};
+	use crate::rtnetlink::Interface;
+
+	/// The RTM_NEWLINK of veth0, a port of br0, from a dump of the bridge family, trimmed to its name, master and
+	/// IFLA_PROTINFO.
+	const PORT: &str = concat!(
+		"070001000400000043100100000000000a000300766574683000000008000a000200000050010c800500010003000000",
+		"060002002000000008000300070000000500040000000000050005000000000005000600000000000500070000000000",
+		"05001c00000000000500080001000000050009000100000005001b000100000005001e000100000005000a0000000000",
+		"05000c00000000000c000d00800042dfe4d5a2340c000e00800042dfe4d5a23406000f00018000000600100000000000",
+		"060011000180000006001200010000000500130000000000050014000000000005001d000000000006001f0000000000",
+		"050020000000000005002300000000000500240000000000050021000000000005002700000000000500280000000000",
+		"05002b00000000000c00150000000000000000000c001600a9050000000000000c001700000000000000000005001900",
+		"0100000008002500000200000800260000000000080029000100000008002a0000000000",
+	);
+
+	/// The IFLA_AF_SPEC of a port in VLANs 1 (untagged, and the PVID) and 10. The kernel that the other messages
+	/// were captured from was built without VLAN filtering, so this is laid out as `br_fill_ifvlaninfo` writes it.
+	const PORT_VLANS: &str = "14001a8008000200060001000800020000000a00";
+
+	/// The RTM_NEWLINK of br0, from a dump of every link, trimmed to its name and IFLA_LINKINFO.
+	const BRIDGE: &str = concat!(
+		"0000010002000000431001000000000008000300627230009c0112000b00010062726964676500008c0102000c001000",
+		"00000000000000000c00110000000000000000000c00120000000000000000000c001300027500000000000008000100",
+		"dc05000008000200c800000008000300d007000008000400307500000800050000000000060006000080000005000700",
+		"0000000006000900000000000c000b00800042dfe4d5a2340c000a00800042dfe4d5a23406000c000000000008000d00",
+		"0000000005000e000000000005000f00000000000a0014000180c200000000000c002e00000000001f00000008003000",
+		"010000000800310000000000050016000100000005001700010000000500180000000000050019000000000005002a00",
+		"0000000008001a001000000008001b000010000008001c000200000008001d000200000005002b000200000005002c00",
+		"010000000c001e0064000000000000000c001f0090650000000000000c0020009c630000000000000c002100d4300000",
+		"000000000c002200e8030000000000000c002300340c0000000000000500240000000000050025000000000005002600",
+		"00000000",
+	);
+
+	/// The RTM_NEWLINK of veth0, from a dump of every link, trimmed to its name, master and IFLA_LINKINFO.
+	const VETH: &str = concat!(
+		"000001000400000043100100000000000a000300766574683000000008000a00020000006c0112000900010076657468",
+		"000000000b00040062726964676500005001050005000100030000000600020020000000080003000700000005000400",
+		"0000000005000500000000000500060000000000050007000000000005001c0000000000050008000100000005000900",
+		"0100000005001b000100000005001e000100000005000a000000000005000c00000000000c000d00800042dfe4d5a234",
+		"0c000e00800042dfe4d5a23406000f000180000006001000000000000600110001800000060012000100000005001300",
+		"00000000050014000000000005001d000000000006001f00000000000500200000000000050023000000000005002400",
+		"0000000005002100000000000500270000000000050028000000000005002b00000000000c0015000000000000000000",
+		"0c001600a9050000000000000c0017000000000000000000050019000100000008002500000200000800260000000000",
+		"080029000100000008002a0000000000",
+	);
+
+	fn decode(payload: &str) -> Interface {
+		let bytes = (0..payload.len())
+			.step_by(2)
+			.map(|i| u8::from_str_radix(&payload[i..i + 2], 16).unwrap())
+			.collect::<Vec<_>>();
+		Interface::read_from_with_endian(&mut Cursor::new(bytes), Endian::Little).unwrap()
+	}
+
+	#[test]
+	fn test_bridge_port() {
+		let port = decode(PORT);
+		assert_eq!(port.index, 4);
+		assert_eq!(port.attributes.name.as_deref(), Some("veth0"));
+		assert_eq!(port.attributes.master, Some(2));
+
+		let attributes = port.bridge_port().unwrap().unwrap();
+		assert!(matches!(attributes.state, Some(BridgePortState::Forwarding)));
+		assert_eq!(attributes.priority, Some(32));
+		assert_eq!(attributes.cost, Some(7));
+		assert!(port.bridge().unwrap().is_none());
+	}
+
+	#[test]
+	fn test_bridge_vlans() {
+		let port = decode(&format!("{}{}", PORT, PORT_VLANS));
+		let vlans = port.bridge().unwrap().unwrap().vlans;
+		assert_eq!(vlans.iter().map(|vlan| vlan.id).collect::<Vec<_>>(), vec![1, 10]);
+		assert_eq!(
+			vlans[0].flags.bits(),
+			(BridgeVlanFlags::BRIDGE_VLAN_INFO_PVID | BridgeVlanFlags::BRIDGE_VLAN_INFO_UNTAGGED).bits()
+		);
+		assert!(vlans[1].flags.is_empty());
+	}
+
+	#[test]
+	fn test_bridge_link_info() {
+		// Outside of the bridge family, the port and bridge attributes aren't there to decode.
+		let bridge = decode(BRIDGE);
+		assert!(bridge.bridge_port().unwrap().is_none());
+		let link_info = bridge.attributes.link_info.unwrap();
+		assert_eq!(link_info.kind.as_deref(), Some("bridge"));
+		assert!(link_info.data.is_some());
+		assert!(link_info.port_kind.is_none());
+
+		let veth = decode(VETH);
+		assert_eq!(veth.attributes.master, Some(2));
+		let link_info = veth.attributes.link_info.unwrap();
+		assert_eq!(link_info.kind.as_deref(), Some("veth"));
+		assert_eq!(link_info.port_kind.as_deref(), Some("bridge"));
+		assert!(link_info.port_data.is_some());
+	}
+}
@@ -7,9 +7,14 @@ use bitflags::bitflags;
 use bytestruct::{int_enum, Endian, NullTerminatedString, ReadFromWithEndian, Size, WriteToWithEndian};
 use bytestruct_derive::{ByteStruct, Size};
 
-use crate::{new_string, new_u32, read_attribute, rtnetlink::parsing::new_mac_address, write_attribute};
+use crate::{
+	new_string, new_u16, new_u32, read_attribute, rtnetlink::parsing::new_mac_address, write_attribute, RawAttribute,
+};
 
-use super::address::MacAddress;
+use super::{
+	address::MacAddress,
+	bond::{BondAttributes, BondPortAttributes},
+};
 
 int_enum! {
 	enum AttributeType: u16 {
@@ -17,13 +22,19 @@ int_enum! {
 		BroadcastAddress = 2,
 		Name = 3,
 		MTU = 4,
+		Link = 5,
 		QDisc = 6,
 		Stats = 7,
+		Master = 10,
+		ProtocolInfo = 12,
 		TransmitQueueLength = 13,
 		OperationalState = 16,
 		LinkMode = 17,
+		LinkInfo = 18,
 		Stats64 = 23,
+		AFSpec = 26,
 		Group = 27,
+		ExtendedMask = 29,
 		Promiscuity = 30,
 		NumTransmitQueues = 31,
 		GenericSegmentOffloadMaxSegments = 40,
@@ -31,7 +42,7 @@ int_enum! {
 		NewInterfaceIndex = 50,
 		MinimumMTU = 51,
 		TCPSegmentOffloadMaxSegments = 61,
-		Unknown = 9999,
+		_ => Unknown(u16),
 	}
 }
 
@@ -62,6 +73,20 @@ pub struct InterfaceAttributes {
 	pub new_interface_index: Option<u32>,
 	pub minimum_mtu: Option<u32>,
 	pub tcp_segment_offload_max_segments: Option<u32>,
+	// The index of the link that this one sits on top of, e.g. the parent of a VLAN link.
+	pub link: Option<u32>,
+	// The index of the link that this one is enslaved to, e.g. the bridge that it's a port of.
+	pub master: Option<u32>,
+	// What kind of virtual link this is (e.g. a bridge, or a VLAN), and its settings.
+	pub link_info: Option<LinkInfo>,
+	// Which extra information to send in a dump, as a set of RTEXT_FILTER_* flags. This is only sent in requests.
+	pub extended_mask: Option<u32>,
+
+	// The nested protocol specific information of the link. What's in it depends on the family of the message, so
+	// it's parsed by the `Interface` that it's in.
+	pub(crate) protocol_info: Option<Vec<u8>>,
+	// The nested address family specific information of the link, which also depends on the family of the message.
+	pub(crate) af_spec: Option<Vec<u8>>,
 
 	unknown: Vec<(u16, Vec<u8>)>,
 }
@@ -80,15 +105,9 @@ impl WriteToWithEndian for InterfaceAttributes {
 		write_attribute(
 			t,
 			e,
-			AttributeType::Name,
+			AttributeType::QDisc,
 			&self.qdisc.clone().map(NullTerminatedString::<0>),
 		)?;
-		write_attribute(
-			t,
-			e,
-			AttributeType::Name,
-			&self.name.clone().map(NullTerminatedString::<0>),
-		)?;
 		write_attribute(t, e, AttributeType::TransmitQueueLength, &self.transmit_queue_length)?;
 		write_attribute(t, e, AttributeType::OperationalState, &self.operational_state)?;
 		write_attribute(t, e, AttributeType::LinkMode, &self.link_mode)?;
@@ -109,6 +128,10 @@ impl WriteToWithEndian for InterfaceAttributes {
 		)?;
 		write_attribute(t, e, AttributeType::NewInterfaceIndex, &self.new_interface_index)?;
 		write_attribute(t, e, AttributeType::MinimumMTU, &self.minimum_mtu)?;
+		write_attribute(t, e, AttributeType::Link, &self.link)?;
+		write_attribute(t, e, AttributeType::Master, &self.master)?;
+		write_attribute(t, e, AttributeType::LinkInfo, &self.link_info)?;
+		write_attribute(t, e, AttributeType::ExtendedMask, &self.extended_mask)?;
 		Ok(())
 	}
 }
@@ -131,7 +154,7 @@ impl InterfaceAttributes {
 	pub(crate) fn read_attribute<T: Read>(&mut self, source: &mut T, endian: Endian) -> io::Result<()> {
 		let (attr_type, data_buffer) = read_attribute(source, endian)?;
 
-		match AttributeType::try_from(attr_type).unwrap_or(AttributeType::Unknown) {
+		match AttributeType::from(attr_type) {
 			AttributeType::MacAddress => self.mac_address = Some(new_mac_address(&data_buffer)?),
 			AttributeType::BroadcastAddress => self.broadcast_address = Some(new_mac_address(&data_buffer)?),
 			AttributeType::Name => self.name = Some(new_string(&data_buffer)?),
@@ -173,11 +196,151 @@ impl InterfaceAttributes {
 			AttributeType::TCPSegmentOffloadMaxSegments => {
 				self.tcp_segment_offload_max_segments = Some(new_u32(&data_buffer)?)
 			}
-			AttributeType::Unknown => self.unknown.push((attr_type, data_buffer)),
+			AttributeType::Link => self.link = Some(new_u32(&data_buffer)?),
+			AttributeType::Master => self.master = Some(new_u32(&data_buffer)?),
+			AttributeType::LinkInfo => {
+				self.link_info = Some(LinkInfo::read_from_with_endian(&mut Cursor::new(data_buffer), endian)?)
+			}
+			AttributeType::ExtendedMask => self.extended_mask = Some(new_u32(&data_buffer)?),
+			AttributeType::ProtocolInfo => self.protocol_info = Some(data_buffer),
+			AttributeType::AFSpec => self.af_spec = Some(data_buffer),
+			AttributeType::Unknown(attr_type) => self.unknown.push((attr_type, data_buffer)),
+		}
+
+		Ok(())
+	}
+}
+
+int_enum! {
+	enum LinkInfoAttributeType: u16 {
+		Kind = 1,
+		Data = 2,
+		PortKind = 4,
+		PortData = 5,
+		_ => Unknown(u16),
+	}
+}
+
+int_enum! {
+	enum VlanAttributeType: u16 {
+		Id = 1,
+		_ => Unknown(u16),
+	}
+}
+
+/// The nested rtattr's in the IFLA_LINKINFO of a link, which describe virtual links, e.g. bridges and VLANs.
+#[derive(Debug, Default)]
+pub struct LinkInfo {
+	// The kind of the link, e.g. `bridge` or `vlan`.
+	pub kind: Option<String>,
+	// The nested settings of the link, which depend on its kind.
+	pub data: Option<Vec<u8>>,
+	// The kind of link that this one is a port of, e.g. `bond`, if it's enslaved to one.
+	pub port_kind: Option<String>,
+	// The nested state of the link as a port, which depends on the kind of link that it's a port of.
+	pub port_data: Option<Vec<u8>>,
+
+	unknown: Vec<(u16, Vec<u8>)>,
+}
+
+impl WriteToWithEndian for LinkInfo {
+	fn write_to_with_endian<T: Write>(&self, t: &mut T, e: Endian) -> io::Result<()> {
+		write_attribute(
+			t,
+			e,
+			LinkInfoAttributeType::Kind,
+			&self.kind.clone().map(NullTerminatedString::<0>),
+		)?;
+		write_attribute(t, e, LinkInfoAttributeType::Data, &self.data.clone().map(RawAttribute))?;
+		Ok(())
+	}
+}
+
+impl ReadFromWithEndian for LinkInfo {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut info = Self::default();
+		loop {
+			match info.read_attribute(source, endian) {
+				Ok(_) => {}
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(info)
+	}
+}
+
+impl LinkInfo {
+	pub(crate) fn read_attribute<T: Read>(&mut self, source: &mut T, endian: Endian) -> io::Result<()> {
+		let (attr_type, data_buffer) = read_attribute(source, endian)?;
+
+		match LinkInfoAttributeType::from(attr_type) {
+			LinkInfoAttributeType::Kind => self.kind = Some(new_string(&data_buffer)?),
+			LinkInfoAttributeType::Data => self.data = Some(data_buffer),
+			LinkInfoAttributeType::PortKind => self.port_kind = Some(new_string(&data_buffer)?),
+			LinkInfoAttributeType::PortData => self.port_data = Some(data_buffer),
+			LinkInfoAttributeType::Unknown(attr_type) => self.unknown.push((attr_type, data_buffer)),
 		}
 
 		Ok(())
 	}
+
+	/// Returns the info for creating a link of the given kind, e.g. `bridge`, with the given (encoded) settings.
+	pub fn new(kind: &str, data: Option<Vec<u8>>) -> Self {
+		Self {
+			kind: Some(kind.to_owned()),
+			data,
+			..Self::default()
+		}
+	}
+
+	/// Returns the info for creating a bond with the given settings.
+	pub fn new_bond(attributes: &BondAttributes) -> io::Result<Self> {
+		let mut data = Vec::new();
+		attributes.write_to_with_endian(&mut data, Endian::Little)?;
+		Ok(Self::new("bond", Some(data)))
+	}
+
+	/// Returns the settings of the link, if it's a bond.
+	pub fn bond(&self) -> io::Result<Option<BondAttributes>> {
+		match (self.kind.as_deref(), &self.data) {
+			(Some("bond"), Some(data)) => {
+				BondAttributes::read_from_with_endian(&mut Cursor::new(data), Endian::Little).map(Some)
+			}
+			_ => Ok(None),
+		}
+	}
+
+	/// Returns the state of the link as a port of a bond, if it's one.
+	pub fn bond_port(&self) -> io::Result<Option<BondPortAttributes>> {
+		match (self.port_kind.as_deref(), &self.port_data) {
+			(Some("bond"), Some(data)) => {
+				BondPortAttributes::read_from_with_endian(&mut Cursor::new(data), Endian::Little).map(Some)
+			}
+			_ => Ok(None),
+		}
+	}
+
+	/// Returns the VLAN ID of the link, if it's a VLAN.
+	pub fn vlan_id(&self) -> io::Result<Option<u16>> {
+		let data = match (self.kind.as_deref(), &self.data) {
+			(Some("vlan"), Some(data)) => data,
+			_ => return Ok(None),
+		};
+
+		let mut source = Cursor::new(data);
+		loop {
+			let (attr_type, data_buffer) = match read_attribute(&mut source, Endian::Little) {
+				Ok(attribute) => attribute,
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+				Err(e) => return Err(e),
+			};
+
+			if let VlanAttributeType::Id = VlanAttributeType::from(attr_type) {
+				return new_u16(&data_buffer).map(Some);
+			}
+		}
+	}
 }
 
 int_enum! {
@@ -269,7 +432,6 @@ int_enum! {
 		MCTP = 290,
 		Ppp = 512,
 		Cisco = 513,
-		Hdlc = 513,
 		LapB = 516,
 		Ddcmp = 517,
 		RawHDLC = 518,
@@ -296,6 +458,7 @@ int_enum! {
 		FCFabric = 787,
 		Void =   0xFFFF,
 		None =   0xFFFE,
+		_ => Unknown(u16),
 	}
 }
 
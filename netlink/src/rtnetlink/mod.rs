@@ -1,20 +1,29 @@
 mod address;
+mod bond;
+mod bridge;
 mod interface;
 mod parsing;
+mod route;
+mod watch;
 
+pub use address::{AddressFamily, AddressKind};
 use bitflags::bitflags;
+pub use bond::*;
+pub use bridge::*;
 use bytestruct_derive::ByteStruct;
 pub use interface::*;
+pub use watch::*;
 
 use std::io::{self, Cursor, ErrorKind};
 
-use address::{AddressAttributes, AddressFamily, AddressFlags, AddressScope, InterfaceAddressMessage};
+use address::{AddressAttributes, AddressFlags, AddressScope, IPAddress, InterfaceAddressMessage};
 use bytestruct::{int_enum, ReadFromWithEndian};
 use nix::sys::socket::SockProtocol;
+use route::RouteAttributes;
 
 use crate::{
-	read_netlink_result, NetlinkError, NetlinkFlags, NetlinkMessageHeader, NetlinkResult, NetlinkSockType,
-	NetlinkSocket,
+	read_netlink_result, NetlinkError, NetlinkFlags, NetlinkMessageHeader, NetlinkResponse, NetlinkResult,
+	NetlinkSockType, NetlinkSocket,
 };
 
 /// The Netlink socket type for sending and receiving route information.
@@ -79,6 +88,7 @@ int_enum! {
 		NewNeighborTable = 64,
 		GetNeighborTable = 66,
 		SetNeighborTable = 67,
+		_ => Unknown(u16),
 	}
 }
 
@@ -103,6 +113,12 @@ bitflags! {
 	}
 }
 
+/// The family of the link messages that describe bridges and their ports, in a dump from `get_bridge_ports`.
+const AF_BRIDGE: u16 = 7;
+
+/// Asks for the VLANs of each bridge port to be included in a dump of the bridge family.
+const RTEXT_FILTER_BRVLAN: u32 = 1 << 1;
+
 #[derive(Debug, ByteStruct)]
 pub struct Interface {
 	pub family: u16,
@@ -113,6 +129,31 @@ pub struct Interface {
 	pub attributes: InterfaceAttributes,
 }
 
+impl Interface {
+	/// Returns the spanning tree state of the link, if it's a bridge port. This is only sent in the bridge's view of
+	/// its ports, from `get_bridge_ports`.
+	pub fn bridge_port(&self) -> io::Result<Option<BridgePortAttributes>> {
+		match (self.family, &self.attributes.protocol_info) {
+			(AF_BRIDGE, Some(info)) => {
+				BridgePortAttributes::read_from_with_endian(&mut Cursor::new(info), bytestruct::Endian::Little)
+					.map(Some)
+			}
+			_ => Ok(None),
+		}
+	}
+
+	/// Returns the bridge information of the link, including the VLANs that it's a member of, if it's a bridge or
+	/// bridge port. Like `bridge_port`, this is only sent from `get_bridge_ports`.
+	pub fn bridge(&self) -> io::Result<Option<BridgeAttributes>> {
+		match (self.family, &self.attributes.af_spec) {
+			(AF_BRIDGE, Some(spec)) => {
+				BridgeAttributes::read_from_with_endian(&mut Cursor::new(spec), bytestruct::Endian::Little).map(Some)
+			}
+			_ => Ok(None),
+		}
+	}
+}
+
 #[derive(Debug, ByteStruct)]
 pub struct Address {
 	pub family: AddressFamily,
@@ -123,87 +164,226 @@ pub struct Address {
 	pub attributes: AddressAttributes,
 }
 
+impl Address {
+	/// Returns what the address is used for, from the attribute that it was sent in.
+	pub fn kind(&self) -> AddressKind {
+		if self.attributes.multicast.is_some() {
+			AddressKind::Multicast
+		} else if self.attributes.anycast_address.is_some() {
+			AddressKind::Anycast
+		} else {
+			AddressKind::Unicast
+		}
+	}
+
+	/// Returns the address itself, from the attribute for its kind.
+	pub fn ip(&self) -> Option<&IPAddress> {
+		match self.kind() {
+			AddressKind::Multicast => self.attributes.multicast.as_ref(),
+			AddressKind::Anycast => self.attributes.anycast_address.as_ref(),
+			AddressKind::Unicast => self.attributes.address.as_ref(),
+		}
+	}
+
+	/// Returns all of the flags of the address. The header only has the lower eight, so the attribute is preferred if
+	/// the kernel sent it.
+	pub fn flags(&self) -> AddressFlags {
+		self.attributes.flags.unwrap_or(self.flags)
+	}
+}
+
+#[derive(Debug, ByteStruct)]
+pub struct Route {
+	pub family: AddressFamily,
+	pub destination_length: u8,
+	pub source_length: u8,
+	pub tos: u8,
+	pub table: u8,
+
+	/// Where the route came from, e.g. 2 for the kernel, or 3 for the boot process.
+	pub protocol: u8,
+	pub scope: AddressScope,
+
+	/// The type of the route, e.g. 1 for a unicast route, or 2 for a local one.
+	pub route_type: u8,
+	pub flags: u32,
+	pub attributes: RouteAttributes,
+}
+
 pub trait RTNetlink {
 	// Get all the links on the system.
-	fn get_links(&mut self) -> io::Result<Vec<Interface>>;
+	#[allow(clippy::result_large_err)]
+	fn get_links(&mut self) -> Result<Vec<Interface>, NetlinkError<NetlinkRoute, Interface>>;
 
-	// Create, or update a link on the system.
+	// Update a link on the system.
+	#[allow(clippy::result_large_err)]
 	fn new_link(&mut self, i: Interface) -> NetlinkResult<NetlinkRoute, Interface>;
 
+	// Create a virtual link (e.g. a bridge, or a bond), of the kind given in its link info. This fails if a link with
+	// its name already exists.
+	#[allow(clippy::result_large_err)]
+	fn create_link(&mut self, i: Interface) -> NetlinkResult<NetlinkRoute, Interface>;
+
+	// Get the bridges and bridge ports on the system, as the bridges see them. Unlike `get_links`, these include the
+	// spanning tree state and VLANs of each port.
+	#[allow(clippy::result_large_err)]
+	fn get_bridge_ports(&mut self) -> Result<Vec<Interface>, NetlinkError<NetlinkRoute, Interface>>;
+
 	// Get all the addresses on all the links of the system.
-	fn get_addrs(&mut self) -> io::Result<Vec<Address>>;
+	#[allow(clippy::result_large_err)]
+	fn get_addrs(&mut self) -> Result<Vec<Address>, NetlinkError<NetlinkRoute, Address>>;
+
+	// Get all the anycast addresses of the given family that the links of the system answer to. Unlike other
+	// addresses, these can't be dumped for every family at once.
+	#[allow(clippy::result_large_err)]
+	fn get_anycast_addrs(&mut self, family: AddressFamily)
+		-> Result<Vec<Address>, NetlinkError<NetlinkRoute, Address>>;
+
+	// Get all the multicast groups of the given family that the links of the system have joined. Unlike other
+	// addresses, these can't be dumped for every family at once.
+	#[allow(clippy::result_large_err)]
+	fn get_multicast_addrs(
+		&mut self,
+		family: AddressFamily,
+	) -> Result<Vec<Address>, NetlinkError<NetlinkRoute, Address>>;
 }
 
-impl RTNetlink for NetlinkSocket<NetlinkRoute> {
-	fn get_links(&mut self) -> io::Result<Vec<Interface>> {
-		let header = NetlinkMessageHeader::<NetlinkRoute>::new(
-			RTNetlinkMessageType::GetLink,
-			NetlinkFlags::NLM_F_REQUEST | NetlinkFlags::NLM_F_MATCH | NetlinkFlags::NLM_F_EXCL,
-		);
-		let msg = InterfaceInfoMessage::empty();
-
-		self.write_netlink_message(header, msg)?;
+/// Whether the given response is the last one to a dump request.
+fn is_end_of_dump(header: &NetlinkMessageHeader<NetlinkRoute>) -> bool {
+	matches!(
+		header.message_type,
+		RTNetlinkMessageType::Done | RTNetlinkMessageType::Error
+	)
+}
 
-		let mut interfaces = Vec::new();
-		loop {
-			let (header, body) = self.read_netlink_message()?;
-			if matches!(header.message_type, RTNetlinkMessageType::Done) {
+/// Parses the responses to a dump request, which are terminated by either a Done or an Error message.
+#[allow(clippy::result_large_err)]
+fn parse_dump<M: ReadFromWithEndian>(
+	responses: Vec<NetlinkResponse<NetlinkRoute>>,
+) -> Result<Vec<M>, NetlinkError<NetlinkRoute, M>> {
+	let mut parsed = Vec::new();
+	for (header, body) in responses {
+		match header.message_type {
+			RTNetlinkMessageType::Done => break,
+			RTNetlinkMessageType::Error => {
+				read_netlink_result(&mut Cursor::new(body), bytestruct::Endian::Little)?;
 				break;
 			}
-
-			let mut cursor = Cursor::new(&body);
-			let interface = Interface::read_from_with_endian(&mut cursor, bytestruct::Endian::Little)?;
-
-			interfaces.push(interface);
+			_ => parsed.push(M::read_from_with_endian(
+				&mut Cursor::new(&body),
+				bytestruct::Endian::Little,
+			)?),
 		}
-
-		Ok(interfaces)
 	}
 
-	fn new_link(&mut self, i: Interface) -> NetlinkResult<NetlinkRoute, Interface> {
-		let header = NetlinkMessageHeader::new(
-			RTNetlinkMessageType::NewLink,
-			NetlinkFlags::NLM_F_REQUEST | NetlinkFlags::NLM_F_ACK,
-		);
-
-		self.write_netlink_message(header, i)?;
-
-		let (header, msg) = self.read_netlink_message()?;
-		if header.message_type != RTNetlinkMessageType::Error {
-			return Err(NetlinkError::IOError(io::Error::new(
-				ErrorKind::InvalidData,
-				format!("invalid message header in response: {:?}", header.message_type),
-			)));
-		}
+	Ok(parsed)
+}
 
-		let mut msg = Cursor::new(msg);
+impl RTNetlink for NetlinkSocket<NetlinkRoute> {
+	fn get_links(&mut self) -> Result<Vec<Interface>, NetlinkError<NetlinkRoute, Interface>> {
+		let header = NetlinkMessageHeader::<NetlinkRoute>::new(
+			RTNetlinkMessageType::GetLink,
+			NetlinkFlags::NLM_F_REQUEST | NetlinkFlags::NLM_F_MATCH | NetlinkFlags::NLM_F_EXCL,
+		);
+		let msg = InterfaceInfoMessage::empty();
 
-		read_netlink_result(&mut msg, bytestruct::Endian::Little)
+		parse_dump(self.request_with_retries(header, msg, is_end_of_dump)?)
 	}
 
-	fn get_addrs(&mut self) -> io::Result<Vec<Address>> {
+	fn get_bridge_ports(&mut self) -> Result<Vec<Interface>, NetlinkError<NetlinkRoute, Interface>> {
 		let header = NetlinkMessageHeader::<NetlinkRoute>::new(
-			RTNetlinkMessageType::GetAddress,
+			RTNetlinkMessageType::GetLink,
 			NetlinkFlags::NLM_F_REQUEST | NetlinkFlags::NLM_F_MATCH | NetlinkFlags::NLM_F_EXCL,
 		);
+		let mut attributes = InterfaceAttributes::default();
+		attributes.extended_mask = Some(RTEXT_FILTER_BRVLAN);
 
-		let msg = InterfaceAddressMessage::empty();
+		let msg = InterfaceInfoMessage::empty();
+		let msg = Interface {
+			family: AF_BRIDGE,
+			ty: msg.ty,
+			index: msg.index,
+			flags: msg.flags,
+			change: msg.change,
+			attributes,
+		};
+
+		// Without the bridge module, the kernel answers with every link in the unspecified family instead.
+		let links: Vec<Interface> = parse_dump(self.request_with_retries(header, msg, is_end_of_dump)?)?;
+		Ok(links.into_iter().filter(|link| link.family == AF_BRIDGE).collect())
+	}
 
-		self.write_netlink_message(header, msg)?;
+	fn new_link(&mut self, i: Interface) -> NetlinkResult<NetlinkRoute, Interface> {
+		send_link(self, i, NetlinkFlags::empty())
+	}
 
-		let mut addresses = Vec::new();
+	fn create_link(&mut self, i: Interface) -> NetlinkResult<NetlinkRoute, Interface> {
+		send_link(self, i, NetlinkFlags::NLM_F_CREATE | NetlinkFlags::NLM_F_EXCL)
+	}
 
-		loop {
-			let (header, body) = self.read_netlink_message()?;
-			if matches!(header.message_type, RTNetlinkMessageType::Done) {
-				break;
-			}
+	fn get_addrs(&mut self) -> Result<Vec<Address>, NetlinkError<NetlinkRoute, Address>> {
+		dump_addrs(self, RTNetlinkMessageType::GetAddress, AddressFamily::Unspecified)
+	}
 
-			let address = Address::read_from_with_endian(&mut Cursor::new(&body), bytestruct::Endian::Little)?;
+	fn get_anycast_addrs(
+		&mut self,
+		family: AddressFamily,
+	) -> Result<Vec<Address>, NetlinkError<NetlinkRoute, Address>> {
+		dump_addrs(self, RTNetlinkMessageType::GetAnycast, family)
+	}
 
-			addresses.push(address);
-		}
+	fn get_multicast_addrs(
+		&mut self,
+		family: AddressFamily,
+	) -> Result<Vec<Address>, NetlinkError<NetlinkRoute, Address>> {
+		dump_addrs(self, RTNetlinkMessageType::GetMulticast, family)
+	}
+}
 
-		Ok(addresses)
+/// Sends a NewLink request with the given extra flags, and waits for it to be acknowledged.
+#[allow(clippy::result_large_err)]
+fn send_link(
+	socket: &mut NetlinkSocket<NetlinkRoute>,
+	i: Interface,
+	flags: NetlinkFlags,
+) -> NetlinkResult<NetlinkRoute, Interface> {
+	let header = NetlinkMessageHeader::new(
+		RTNetlinkMessageType::NewLink,
+		NetlinkFlags::NLM_F_REQUEST | NetlinkFlags::NLM_F_ACK | flags,
+	);
+
+	// The only response is the ack.
+	let mut responses = socket.request(header, i, |_| true)?;
+	let (header, msg) = responses.remove(0);
+	if header.message_type != RTNetlinkMessageType::Error {
+		return Err(NetlinkError::IOError(io::Error::new(
+			ErrorKind::InvalidData,
+			format!("invalid message header in response: {:?}", header.message_type),
+		)));
 	}
+
+	let mut msg = Cursor::new(msg);
+
+	read_netlink_result(&mut msg, bytestruct::Endian::Little)
+}
+
+/// Dumps the addresses of the given family (or every family, if it's unspecified) with the given request, which all
+/// respond with address messages.
+#[allow(clippy::result_large_err)]
+fn dump_addrs(
+	socket: &mut NetlinkSocket<NetlinkRoute>,
+	message_type: RTNetlinkMessageType,
+	family: AddressFamily,
+) -> Result<Vec<Address>, NetlinkError<NetlinkRoute, Address>> {
+	let header = NetlinkMessageHeader::<NetlinkRoute>::new(
+		message_type,
+		NetlinkFlags::NLM_F_REQUEST | NetlinkFlags::NLM_F_MATCH | NetlinkFlags::NLM_F_EXCL,
+	);
+
+	let msg = InterfaceAddressMessage {
+		family,
+		..InterfaceAddressMessage::empty()
+	};
+
+	parse_dump(socket.request_with_retries(header, msg, is_end_of_dump)?)
 }
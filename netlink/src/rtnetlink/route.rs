@@ -0,0 +1,93 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use bytestruct::{int_enum, Endian, ReadFromWithEndian, WriteToWithEndian};
+
+use crate::{new_u32, read_attribute, write_attribute};
+
+use super::address::IPAddress;
+
+int_enum! {
+	enum AttributeType: u16 {
+		Destination = 1,
+		Source = 2,
+		InputInterface = 3,
+		OutputInterface = 4,
+		Gateway = 5,
+		Priority = 6,
+		PreferredSource = 7,
+		Table = 15,
+		_ => Unknown(u16),
+	}
+}
+
+/// The rtattr's that can apply to a route, as received from Netlink.
+#[derive(Debug, Default)]
+pub struct RouteAttributes {
+	// The network that the route is to, which is missing for the default route.
+	pub destination: Option<IPAddress>,
+	// The network that the route is from, for source routing.
+	pub source: Option<IPAddress>,
+	// The index of the interface that packets on the route arrive on.
+	pub input_interface: Option<u32>,
+	// The index of the interface that packets on the route are sent out of.
+	pub output_interface: Option<u32>,
+	// The router that packets on the route are sent via.
+	pub gateway: Option<IPAddress>,
+	// The metric of the route, where lower metrics are preferred.
+	pub priority: Option<u32>,
+	// The source address to prefer when sending packets on the route.
+	pub preferred_source: Option<IPAddress>,
+	// The routing table that the route is in, which may not fit in the table field of the route.
+	pub table: Option<u32>,
+
+	unknown: Vec<(u16, Vec<u8>)>,
+}
+
+impl ReadFromWithEndian for RouteAttributes {
+	fn read_from_with_endian<T: Read>(source: &mut T, endian: Endian) -> io::Result<Self> {
+		let mut attributes = Self::default();
+		loop {
+			match attributes.read_attribute(source, endian) {
+				Ok(_) => {}
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(attributes)
+	}
+}
+
+impl WriteToWithEndian for RouteAttributes {
+	fn write_to_with_endian<T: Write>(&self, target: &mut T, endian: Endian) -> io::Result<()> {
+		write_attribute(target, endian, AttributeType::Destination, &self.destination)?;
+		write_attribute(target, endian, AttributeType::Source, &self.source)?;
+		write_attribute(target, endian, AttributeType::InputInterface, &self.input_interface)?;
+		write_attribute(target, endian, AttributeType::OutputInterface, &self.output_interface)?;
+		write_attribute(target, endian, AttributeType::Gateway, &self.gateway)?;
+		write_attribute(target, endian, AttributeType::Priority, &self.priority)?;
+		write_attribute(target, endian, AttributeType::PreferredSource, &self.preferred_source)?;
+		write_attribute(target, endian, AttributeType::Table, &self.table)?;
+
+		Ok(())
+	}
+}
+
+impl RouteAttributes {
+	pub(crate) fn read_attribute<T: Read>(&mut self, source: &mut T, endian: Endian) -> io::Result<()> {
+		let (attr_type, data_buffer) = read_attribute(source, endian)?;
+
+		match AttributeType::from(attr_type) {
+			AttributeType::Destination => self.destination = Some(IPAddress::new(&data_buffer)?),
+			AttributeType::Source => self.source = Some(IPAddress::new(&data_buffer)?),
+			AttributeType::InputInterface => self.input_interface = Some(new_u32(&data_buffer)?),
+			AttributeType::OutputInterface => self.output_interface = Some(new_u32(&data_buffer)?),
+			AttributeType::Gateway => self.gateway = Some(IPAddress::new(&data_buffer)?),
+			AttributeType::Priority => self.priority = Some(new_u32(&data_buffer)?),
+			AttributeType::PreferredSource => self.preferred_source = Some(IPAddress::new(&data_buffer)?),
+			AttributeType::Table => self.table = Some(new_u32(&data_buffer)?),
+			AttributeType::Unknown(attr_type) => self.unknown.push((attr_type, data_buffer)),
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,156 @@
+use std::{
+	io::{self, Cursor},
+	time::Duration,
+};
+
+use bytestruct::ReadFromWithEndian;
+use nix::errno::Errno;
+
+use crate::{NetlinkMessageHeader, NetlinkSocket};
+
+use super::{Address, Interface, NetlinkRoute, RTNetlinkGroups, RTNetlinkMessageType, Route};
+
+/// The value of the change mask of a link that the kernel sends when the link is first registered.
+const NEW_LINK_CHANGE: u32 = u32::MAX;
+
+/// A change to the network configuration of the system, announced by the kernel.
+#[derive(Debug)]
+pub enum NetlinkEvent {
+	/// A link was added.
+	LinkAdded(Interface),
+
+	/// The state of a link changed, e.g. it was brought up, or it gained or lost its carrier.
+	LinkChanged(Interface),
+
+	/// A link was removed.
+	LinkRemoved(Interface),
+
+	/// An address was added to, or removed from, a link.
+	AddrChanged { address: Address, removed: bool },
+
+	/// A route was added or removed.
+	RouteChanged { route: Route, removed: bool },
+
+	/// The kernel dropped events because they weren't read quickly enough, so anything tracking the state of the
+	/// network should read it again.
+	Overrun,
+}
+
+impl NetlinkEvent {
+	/// Parses an event from a message sent to one of the rtnetlink multicast groups, returning None if the message
+	/// isn't an event.
+	pub fn parse(header: &NetlinkMessageHeader<NetlinkRoute>, body: &[u8]) -> io::Result<Option<Self>> {
+		let interface = || Interface::read_from_with_endian(&mut Cursor::new(body), bytestruct::Endian::Little);
+		let address = || Address::read_from_with_endian(&mut Cursor::new(body), bytestruct::Endian::Little);
+		let route = || Route::read_from_with_endian(&mut Cursor::new(body), bytestruct::Endian::Little);
+
+		let event = match header.message_type {
+			RTNetlinkMessageType::NewLink => {
+				let interface = interface()?;
+				if interface.change == NEW_LINK_CHANGE {
+					Self::LinkAdded(interface)
+				} else {
+					Self::LinkChanged(interface)
+				}
+			}
+			RTNetlinkMessageType::DeleteLink => Self::LinkRemoved(interface()?),
+			RTNetlinkMessageType::NewAddress => Self::AddrChanged {
+				address: address()?,
+				removed: false,
+			},
+			RTNetlinkMessageType::DeleteAddress => Self::AddrChanged {
+				address: address()?,
+				removed: true,
+			},
+			RTNetlinkMessageType::NewRoute => Self::RouteChanged {
+				route: route()?,
+				removed: false,
+			},
+			RTNetlinkMessageType::DeleteRoute => Self::RouteChanged {
+				route: route()?,
+				removed: true,
+			},
+			RTNetlinkMessageType::Overrun => Self::Overrun,
+			_ => return Ok(None),
+		};
+
+		Ok(Some(event))
+	}
+}
+
+/// Reads the changes to the network configuration of the system, as the kernel announces them.
+pub struct NetlinkWatcher {
+	socket: NetlinkSocket<NetlinkRoute>,
+}
+
+impl NetlinkWatcher {
+	/// Subscribes to the changes announced to the given multicast groups.
+	pub fn new(groups: RTNetlinkGroups) -> io::Result<Self> {
+		Ok(Self {
+			socket: NetlinkSocket::new(groups)?,
+		})
+	}
+
+	/// Waits up to the given timeout (or forever, if it's None) for the next event, returning None if the timeout
+	/// expires first.
+	pub fn next_event(&self, timeout: Option<Duration>) -> io::Result<Option<NetlinkEvent>> {
+		loop {
+			if !self.socket.wait_readable(timeout)? {
+				return Ok(None);
+			}
+
+			let (header, body) = match self.socket.read_netlink_message() {
+				Ok(message) => message,
+				Err(e) if e.raw_os_error() == Some(Errno::ENOBUFS as i32) => return Ok(Some(NetlinkEvent::Overrun)),
+				Err(e) => return Err(e),
+			};
+
+			if let Some(event) = NetlinkEvent::parse(&header, &body)? {
+				return Ok(Some(event));
+			}
+		}
+	}
+}
+
+#[cfg(feature = "async")]
+mod async_watch {
+	use std::io;
+
+	use nix::errno::Errno;
+
+	use crate::{rtnetlink::NetlinkRoute, AsyncNetlinkSocket};
+
+	use super::{NetlinkEvent, RTNetlinkGroups};
+
+	/// Reads the changes to the network configuration of the system, as the kernel announces them, asynchronously.
+	pub struct AsyncNetlinkWatcher {
+		socket: AsyncNetlinkSocket<NetlinkRoute>,
+	}
+
+	impl AsyncNetlinkWatcher {
+		/// Subscribes to the changes announced to the given multicast groups.
+		pub fn new(groups: RTNetlinkGroups) -> io::Result<Self> {
+			Ok(Self {
+				socket: AsyncNetlinkSocket::new(groups)?,
+			})
+		}
+
+		/// Waits for the next event.
+		pub async fn next_event(&self) -> io::Result<NetlinkEvent> {
+			loop {
+				let (header, body) = match self.socket.read_netlink_message().await {
+					Ok(message) => message,
+					Err(e) if e.raw_os_error() == Some(Errno::ENOBUFS as i32) => return Ok(NetlinkEvent::Overrun),
+					Err(e) => return Err(e),
+				};
+
+				if let Some(event) = NetlinkEvent::parse(&header, &body)? {
+					return Ok(event);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(feature = "async")]
+pub use async_watch::*;
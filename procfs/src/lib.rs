@@ -0,0 +1,39 @@
+mod meminfo;
+mod process;
+mod stat;
+
+use std::io;
+
+pub use meminfo::*;
+pub use process::*;
+pub use stat::*;
+use thiserror::Error;
+
+/// The path that procfs is mounted at.
+pub const PROC_PATH: &str = "/proc";
+
+#[derive(Debug, Error)]
+pub enum ProcfsError {
+	#[error("failed to read procfs: {0}")]
+	IOError(#[from] io::Error),
+
+	#[error("invalid {file}: {reason}")]
+	Invalid { file: &'static str, reason: String },
+}
+
+impl ProcfsError {
+	fn invalid(file: &'static str, reason: impl Into<String>) -> Self {
+		Self::Invalid {
+			file,
+			reason: reason.into(),
+		}
+	}
+}
+
+/// Parses the field with the given name out of a whitespace seperated line, e.g. the `utime` field of a stat file.
+fn parse_field<T: std::str::FromStr>(file: &'static str, name: &str, field: Option<&str>) -> Result<T, ProcfsError> {
+	let field = field.ok_or_else(|| ProcfsError::invalid(file, format!("missing {}", name)))?;
+	field
+		.parse()
+		.map_err(|_| ProcfsError::invalid(file, format!("invalid {}: {}", name, field)))
+}
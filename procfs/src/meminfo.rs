@@ -0,0 +1,68 @@
+use std::{fs, path::Path, str::FromStr};
+
+use crate::{parse_field, ProcfsError, PROC_PATH};
+
+/// The amount of memory on the system, and how it's used, from /proc/meminfo. All the sizes are in bytes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemInfo {
+	pub total: u64,
+	pub free: u64,
+
+	/// An estimate of the memory that's available to start new processes without swapping.
+	pub available: u64,
+	pub buffers: u64,
+	pub cached: u64,
+	pub swap_total: u64,
+	pub swap_free: u64,
+}
+
+impl MemInfo {
+	/// Reads the memory information of the running system.
+	pub fn read() -> Result<Self, ProcfsError> {
+		fs::read_to_string(Path::new(PROC_PATH).join("meminfo"))?.parse()
+	}
+
+	/// The memory that is in use, and can't be reclaimed.
+	pub fn used(&self) -> u64 {
+		self.total.saturating_sub(self.available)
+	}
+}
+
+impl FromStr for MemInfo {
+	type Err = ProcfsError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut info = MemInfo::default();
+		for line in s.lines() {
+			let Some((key, value)) = line.split_once(':') else {
+				continue;
+			};
+
+			let field = match key {
+				"MemTotal" => &mut info.total,
+				"MemFree" => &mut info.free,
+				"MemAvailable" => &mut info.available,
+				"Buffers" => &mut info.buffers,
+				"Cached" => &mut info.cached,
+				"SwapTotal" => &mut info.swap_total,
+				"SwapFree" => &mut info.swap_free,
+				_ => continue,
+			};
+
+			// Sizes are given in kibibytes, e.g. `MemTotal:       16316412 kB`.
+			let mut parts = value.split_whitespace();
+			let size: u64 = parse_field("meminfo", key, parts.next())?;
+			*field = match parts.next() {
+				Some("kB") => size * 1024,
+				None => size,
+				Some(unit) => return Err(ProcfsError::invalid("meminfo", format!("unknown unit: {}", unit))),
+			};
+		}
+
+		if info.total == 0 {
+			return Err(ProcfsError::invalid("meminfo", "missing MemTotal"));
+		}
+
+		Ok(info)
+	}
+}
@@ -0,0 +1,282 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use nix::unistd::{sysconf, SysconfVar};
+
+use crate::{parse_field, ProcfsError, PROC_PATH};
+
+/// The size of a page of memory, which the memory usage of processes is given in.
+pub fn page_size() -> u64 {
+	match sysconf(SysconfVar::PAGE_SIZE) {
+		Ok(Some(size)) if size > 0 => size as u64,
+		_ => 4096,
+	}
+}
+
+/// The number of clock ticks in a second, which the CPU times of processes are given in.
+pub fn clock_ticks() -> u64 {
+	match sysconf(SysconfVar::CLK_TCK) {
+		Ok(Some(ticks)) if ticks > 0 => ticks as u64,
+		_ => 100,
+	}
+}
+
+/// The status of a process, from /proc/<pid>/stat.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessStat {
+	/// The name of the executable, truncated by the kernel to 15 characters.
+	pub comm: String,
+
+	/// The state of the process, e.g. R (running), S (sleeping), or Z (zombie).
+	pub state: char,
+	pub ppid: i32,
+
+	/// The ID of the process group that the process is in.
+	pub pgrp: i32,
+
+	/// The ID of the session that the process is in.
+	pub session: i32,
+
+	/// The device number of the controlling terminal of the process, or 0 if it doesn't have one.
+	pub tty: u32,
+
+	/// The time the process has spent in user mode, in clock ticks.
+	pub utime: u64,
+
+	/// The time the process has spent in kernel mode, in clock ticks.
+	pub stime: u64,
+	pub nice: i64,
+	pub num_threads: i64,
+
+	/// The time the process started after boot, in clock ticks.
+	pub start_time: u64,
+
+	/// The size of the virtual memory of the process, in bytes.
+	pub vsize: u64,
+
+	/// The number of pages the process has in real memory.
+	pub rss: u64,
+}
+
+impl FromStr for ProcessStat {
+	type Err = ProcfsError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		// The command is wrapped in parens, and can contain anything (including spaces and parens) so is found by
+		// the first and last parens, e.g. `1234 (my (weird) cmd) S 1 ...`.
+		let (start, end) = match (s.find('('), s.rfind(')')) {
+			(Some(start), Some(end)) if start < end => (start, end),
+			_ => return Err(ProcfsError::invalid("stat", "missing command")),
+		};
+
+		let comm = s[start + 1..end].to_owned();
+		let fields: Vec<&str> = s[end + 1..].split_whitespace().collect();
+		let field = |index: usize| fields.get(index).copied();
+
+		Ok(Self {
+			comm,
+			state: parse_field("stat", "state", field(0))?,
+			ppid: parse_field("stat", "ppid", field(1))?,
+			pgrp: parse_field("stat", "pgrp", field(2))?,
+			session: parse_field("stat", "session", field(3))?,
+			tty: parse_field::<i32>("stat", "tty_nr", field(4))? as u32,
+			utime: parse_field("stat", "utime", field(11))?,
+			stime: parse_field("stat", "stime", field(12))?,
+			nice: parse_field("stat", "nice", field(16))?,
+			num_threads: parse_field("stat", "num_threads", field(17))?,
+			start_time: parse_field("stat", "starttime", field(19))?,
+			vsize: parse_field("stat", "vsize", field(20))?,
+			rss: parse_field::<i64>("stat", "rss", field(21))?.max(0) as u64,
+		})
+	}
+}
+
+/// The IDs of a process, from /proc/<pid>/status.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProcessStatus {
+	/// The real user ID of the process.
+	pub uid: u32,
+
+	/// The real group ID of the process.
+	pub gid: u32,
+}
+
+impl FromStr for ProcessStatus {
+	type Err = ProcfsError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut uid = None;
+		let mut gid = None;
+		for line in s.lines() {
+			// e.g. `Uid:	1000	1000	1000	1000`, the real, effective, saved and filesystem IDs.
+			match line.split_once(':') {
+				Some(("Uid", ids)) => uid = Some(parse_field("status", "Uid", ids.split_whitespace().next())?),
+				Some(("Gid", ids)) => gid = Some(parse_field("status", "Gid", ids.split_whitespace().next())?),
+				_ => {}
+			}
+		}
+
+		match (uid, gid) {
+			(Some(uid), Some(gid)) => Ok(Self { uid, gid }),
+			_ => Err(ProcfsError::invalid("status", "missing Uid or Gid")),
+		}
+	}
+}
+
+/// A process running on the system.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Process {
+	pub pid: i32,
+	pub stat: ProcessStat,
+	pub status: ProcessStatus,
+
+	/// The arguments the process was started with, which is empty for kernel threads.
+	pub cmdline: Vec<String>,
+}
+
+impl Process {
+	/// Reads the process with the given PID.
+	pub fn read(pid: i32) -> Result<Self, ProcfsError> {
+		let directory = process_directory(pid);
+		let cmdline = fs::read(directory.join("cmdline"))?;
+
+		Ok(Self {
+			pid,
+			stat: fs::read_to_string(directory.join("stat"))?.parse()?,
+			status: fs::read_to_string(directory.join("status"))?.parse()?,
+			cmdline: cmdline
+				.split(|&b| b == 0)
+				.filter(|arg| !arg.is_empty())
+				.map(|arg| String::from_utf8_lossy(arg).into_owned())
+				.collect(),
+		})
+	}
+
+	/// The command the process is running: its arguments, or its name in brackets if it doesn't have any (e.g. it's a
+	/// kernel thread).
+	pub fn command(&self) -> String {
+		if self.cmdline.is_empty() {
+			format!("[{}]", self.stat.comm)
+		} else {
+			self.cmdline.join(" ")
+		}
+	}
+
+	/// The total time the process has spent on a CPU, in clock ticks.
+	pub fn cpu_time(&self) -> u64 {
+		self.stat.utime + self.stat.stime
+	}
+
+	/// The memory the process has in real memory, in bytes.
+	pub fn rss_bytes(&self) -> u64 {
+		self.stat.rss * page_size()
+	}
+}
+
+/// Returns the name of a terminal (relative to /dev) from its device number, as given in the `tty_nr` field of a
+/// process's stat file, e.g. `pts/0` or `ttyS0`. Returns None if the process doesn't have a terminal.
+pub fn tty_name(tty: u32) -> Option<String> {
+	if tty == 0 {
+		return None;
+	}
+
+	// The major number is bits 8-15, and the minor number is bits 0-7 and 20-31.
+	let major = (tty >> 8) & 0xfff;
+	let minor = (tty & 0xff) | ((tty >> 12) & 0xfff00);
+	let name = match (major, minor) {
+		(4, 0..=63) => format!("tty{}", minor),
+		(4, _) => format!("ttyS{}", minor - 64),
+		(5, 0) => String::from("tty"),
+		(5, 1) => String::from("console"),
+		(136..=143, _) => format!("pts/{}", (major - 136) * 256 + minor),
+		_ => format!("{}:{}", major, minor),
+	};
+
+	Some(name)
+}
+
+/// Returns the directory in procfs that holds the information about the given process.
+pub fn process_directory(pid: i32) -> PathBuf {
+	Path::new(PROC_PATH).join(pid.to_string())
+}
+
+/// Reads all the processes running on the system, sorted by PID. Processes that exit while they're being read are
+/// skipped.
+pub fn processes() -> Result<Vec<Process>, ProcfsError> {
+	let mut processes = Vec::new();
+	for entry in fs::read_dir(PROC_PATH)? {
+		let pid = match entry?.file_name().to_str().map(str::parse::<i32>) {
+			Some(Ok(pid)) => pid,
+			_ => continue,
+		};
+
+		match Process::read(pid) {
+			Ok(process) => processes.push(process),
+			Err(ProcfsError::IOError(_)) => continue,
+			Err(e) => return Err(e),
+		}
+	}
+
+	processes.sort_by_key(|process| process.pid);
+	Ok(processes)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{tty_name, ProcessStat, ProcessStatus};
+
+	#[test]
+	fn test_process_stat() {
+		let stat: ProcessStat = "1234 (my (weird) cmd) S 1 1234 1234 34816 1234 4194304 100 0 0 0 250 50 0 0 20 -5 3 0 9876 10485760 512 18446744073709551615 0 0 0 0 0 0 0 0 0 0 0 0 17 2 0 0 0 0 0"
+			.parse()
+			.unwrap();
+
+		assert_eq!(stat.comm, "my (weird) cmd");
+		assert_eq!(stat.state, 'S');
+		assert_eq!(stat.ppid, 1);
+		assert_eq!(stat.pgrp, 1234);
+		assert_eq!(stat.session, 1234);
+		assert_eq!(stat.tty, 34816);
+		assert_eq!(stat.utime, 250);
+		assert_eq!(stat.stime, 50);
+		assert_eq!(stat.nice, -5);
+		assert_eq!(stat.num_threads, 3);
+		assert_eq!(stat.start_time, 9876);
+		assert_eq!(stat.vsize, 10485760);
+		assert_eq!(stat.rss, 512);
+
+		assert!("1234 (cmd) S 1".parse::<ProcessStat>().is_err());
+		assert!("1234 cmd S 1".parse::<ProcessStat>().is_err());
+	}
+
+	#[test]
+	fn test_tty_name() {
+		assert_eq!(tty_name(0), None);
+		assert_eq!(tty_name(34816).as_deref(), Some("pts/0"));
+		assert_eq!(tty_name(34819).as_deref(), Some("pts/3"));
+		assert_eq!(tty_name(1025).as_deref(), Some("tty1"));
+		assert_eq!(tty_name(1088).as_deref(), Some("ttyS0"));
+		assert_eq!(tty_name(1281).as_deref(), Some("console"));
+	}
+
+	#[test]
+	fn test_process_status() {
+		let status: ProcessStatus =
+			"Name:\tbash\nState:\tS (sleeping)\nUid:\t1000\t0\t0\t0\nGid:\t100\t100\t100\t100\n"
+				.parse()
+				.unwrap();
+		assert_eq!(status, ProcessStatus { uid: 1000, gid: 100 });
+
+		assert!("Name:\tbash\n".parse::<ProcessStatus>().is_err());
+	}
+
+	#[test]
+	fn test_read_self() {
+		let process = super::Process::read(std::process::id() as i32).unwrap();
+		assert_eq!(process.pid, std::process::id() as i32);
+		assert!(!process.cmdline.is_empty());
+	}
+}
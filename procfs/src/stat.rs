@@ -0,0 +1,152 @@
+use std::{fs, path::Path, str::FromStr};
+
+use crate::{parse_field, ProcfsError, PROC_PATH};
+
+/// The time that a CPU (or all of them) has spent in each state since boot, in clock ticks.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CpuTimes {
+	pub user: u64,
+	pub nice: u64,
+	pub system: u64,
+	pub idle: u64,
+	pub iowait: u64,
+	pub irq: u64,
+	pub softirq: u64,
+	pub steal: u64,
+}
+
+impl CpuTimes {
+	/// The time spent in every state.
+	pub fn total(&self) -> u64 {
+		self.busy() + self.idle + self.iowait
+	}
+
+	/// The time spent doing work.
+	pub fn busy(&self) -> u64 {
+		self.user + self.nice + self.system + self.irq + self.softirq + self.steal
+	}
+
+	/// Parses the times out of a `cpu` line of /proc/stat, without the leading `cpu` label.
+	fn parse<'a>(mut fields: impl Iterator<Item = &'a str>) -> Result<Self, ProcfsError> {
+		let mut next = |name| parse_field("stat", name, fields.next());
+		Ok(Self {
+			user: next("user")?,
+			nice: next("nice")?,
+			system: next("system")?,
+			idle: next("idle")?,
+			// Older kernels don't report the later states.
+			iowait: next("iowait").unwrap_or(0),
+			irq: next("irq").unwrap_or(0),
+			softirq: next("softirq").unwrap_or(0),
+			steal: next("steal").unwrap_or(0),
+		})
+	}
+}
+
+/// Statistics about the system as a whole, from /proc/stat.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SystemStat {
+	/// The times of all the CPUs combined.
+	pub cpu: CpuTimes,
+
+	/// The times of each CPU.
+	pub cpus: Vec<CpuTimes>,
+
+	/// The time the system booted, in seconds since the epoch.
+	pub boot_time: u64,
+}
+
+impl SystemStat {
+	/// Reads the statistics of the running system.
+	pub fn read() -> Result<Self, ProcfsError> {
+		fs::read_to_string(Path::new(PROC_PATH).join("stat"))?.parse()
+	}
+}
+
+impl FromStr for SystemStat {
+	type Err = ProcfsError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut stat = SystemStat::default();
+		for line in s.lines() {
+			let mut fields = line.split_whitespace();
+			match fields.next() {
+				Some("cpu") => stat.cpu = CpuTimes::parse(fields)?,
+				Some(label) if label.starts_with("cpu") => stat.cpus.push(CpuTimes::parse(fields)?),
+				Some("btime") => stat.boot_time = parse_field("stat", "btime", fields.next())?,
+				_ => {}
+			}
+		}
+
+		Ok(stat)
+	}
+}
+
+/// The load average of the system, from /proc/loadavg.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadAverage {
+	pub one: f64,
+	pub five: f64,
+	pub fifteen: f64,
+}
+
+impl LoadAverage {
+	/// Reads the load average of the running system.
+	pub fn read() -> Result<Self, ProcfsError> {
+		fs::read_to_string(Path::new(PROC_PATH).join("loadavg"))?.parse()
+	}
+}
+
+impl FromStr for LoadAverage {
+	type Err = ProcfsError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut fields = s.split_whitespace();
+		Ok(Self {
+			one: parse_field("loadavg", "1 minute load", fields.next())?,
+			five: parse_field("loadavg", "5 minute load", fields.next())?,
+			fifteen: parse_field("loadavg", "15 minute load", fields.next())?,
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{CpuTimes, LoadAverage, SystemStat};
+
+	#[test]
+	fn test_system_stat() {
+		let stat: SystemStat = "cpu  10 1 5 100 4 0 2 0 0 0\ncpu0 6 1 3 50 2 0 1 0 0 0\ncpu1 4 0 2 50 2 0 1 0 0 0\nintr 1234 0 0\nbtime 1700000000\nprocesses 300\n"
+			.parse()
+			.unwrap();
+
+		assert_eq!(
+			stat.cpu,
+			CpuTimes {
+				user: 10,
+				nice: 1,
+				system: 5,
+				idle: 100,
+				iowait: 4,
+				irq: 0,
+				softirq: 2,
+				steal: 0,
+			}
+		);
+		assert_eq!(stat.cpu.busy(), 18);
+		assert_eq!(stat.cpu.total(), 122);
+		assert_eq!(stat.cpus.len(), 2);
+		assert_eq!(stat.cpus[1].user, 4);
+		assert_eq!(stat.boot_time, 1700000000);
+
+		assert!("cpu 1 2\n".parse::<SystemStat>().is_err());
+	}
+
+	#[test]
+	fn test_load_average() {
+		let load: LoadAverage = "0.52 0.58 0.59 1/389 12345\n".parse().unwrap();
+		assert_eq!(load.one, 0.52);
+		assert_eq!(load.five, 0.58);
+		assert_eq!(load.fifteen, 0.59);
+	}
+}
@@ -0,0 +1,85 @@
+use std::{collections::HashMap, process::ExitCode};
+
+use auth::User;
+use clap::{Arg, ArgAction, Command};
+use procfs::{page_size, processes, tty_name, Process};
+use tables::{Alignment, OutputFormat, Table, TableSetting};
+
+const HEADERS: [&str; 7] = ["PID", "USER", "STAT", "VSZ", "RSS", "TTY", "COMMAND"];
+
+/// Builds the table of processes. `all` includes kernel threads, which don't have a command line.
+fn ps_table(processes: &[Process], all: bool) -> Table<7> {
+	let mut table = Table::new_with_headers(HEADERS)
+		.with_setting(TableSetting::Alignment(0, Alignment::Right))
+		.with_setting(TableSetting::Alignment(3, Alignment::Right))
+		.with_setting(TableSetting::Alignment(4, Alignment::Right))
+		.with_setting(TableSetting::FitTerminal);
+
+	let mut usernames: HashMap<u32, String> = HashMap::new();
+	let page_size = page_size();
+	for process in processes.iter().filter(|process| all || !process.cmdline.is_empty()) {
+		let uid = process.status.uid;
+		let username = usernames.entry(uid).or_insert_with(|| match User::from_uid(uid) {
+			Ok(Some(user)) => user.username,
+			_ => uid.to_string(),
+		});
+
+		table.add_row([
+			&process.pid.to_string(),
+			username,
+			&process.stat.state.to_string(),
+			&(process.stat.vsize / 1024).to_string(),
+			&(process.stat.rss * page_size / 1024).to_string(),
+			&tty_name(process.stat.tty).unwrap_or_else(|| String::from("?")),
+			&printable(&process.command()),
+		]);
+	}
+
+	table
+}
+
+/// Replaces the control characters in the text with `?`s, as procps does, so that commands with newlines or terminal
+/// escapes in them (which any user can start) can't break the table, or mess with the terminal it's shown on.
+fn printable(text: &str) -> String {
+	text.chars().map(|c| if c.is_control() { '?' } else { c }).collect()
+}
+
+fn main() -> ExitCode {
+	let matches = Command::new("ps")
+		.about("report a snapshot of the running processes")
+		.author("Colin Douch")
+		.version("0.1")
+		.arg(
+			Arg::new("all")
+				.short('e')
+				.short_alias('A')
+				.long("all")
+				.help("include kernel threads")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("output")
+				.long("output")
+				.help("the format to print the processes in")
+				.value_parser(OutputFormat::NAMES)
+				.default_value("table"),
+		)
+		.get_matches();
+
+	let format: OutputFormat = matches
+		.get_one::<String>("output")
+		.expect("BUG: output has a default")
+		.parse()
+		.expect("BUG: output is one of the format names");
+
+	let processes = match processes() {
+		Ok(processes) => processes,
+		Err(e) => {
+			eprintln!("ps: {}", e);
+			return ExitCode::FAILURE;
+		}
+	};
+
+	print!("{}", ps_table(&processes, matches.get_flag("all")).render(format));
+	ExitCode::SUCCESS
+}
@@ -0,0 +1,288 @@
+mod tree;
+
+use std::{
+	collections::BTreeMap,
+	fs,
+	process::ExitCode,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use common::qinit::{QinitRequest, RunState, ServiceInstance, ServiceStatus, QINIT_CONTROL_SOCKET};
+use control::protocol::{read_frame, request, request_remote, ProtocolError, Reply, DEFAULT_REQUEST_TIMEOUT};
+use tokio::io::AsyncRead;
+
+/// Where qinit is listening for requests.
+enum Target {
+	/// qinit's control socket on this machine.
+	Local(String),
+
+	/// qinit's remote control listener on another machine, along with the token it wants.
+	Remote { addr: String, token: String },
+}
+
+impl Target {
+	/// Sends the request to qinit, returning the connection once it's accepted.
+	async fn request(&self, request_body: &QinitRequest) -> Result<Box<dyn AsyncRead + Unpin>, ProtocolError> {
+		Ok(match self {
+			Target::Local(socket_path) => Box::new(request(socket_path, request_body, DEFAULT_REQUEST_TIMEOUT).await?),
+			Target::Remote { addr, token } => {
+				Box::new(request_remote(addr.as_str(), token, request_body, DEFAULT_REQUEST_TIMEOUT).await?)
+			}
+		})
+	}
+}
+
+/// Builds a subcommand that acts on a service instance, e.g. `qctl enable getty TTY=/dev/tty2`.
+fn instance_command(name: &'static str, about: &'static str) -> Command {
+	Command::new(name)
+		.about(about)
+		.arg(
+			Arg::new("service")
+				.required(true)
+				.num_args(1)
+				.help("The service to act on"),
+		)
+		.arg(
+			Arg::new("arguments")
+				.num_args(0..)
+				.help("The arguments of the service instance, as KEY=VALUE pairs"),
+		)
+}
+
+/// Builds the argument that shows statuses as a tree of the services that each service needs.
+fn tree_arg() -> Arg {
+	Arg::new("tree")
+		.long("tree")
+		.action(ArgAction::SetTrue)
+		.help("Show the services as trees of the services that they need, grouped by sphere")
+}
+
+/// Parses the service instance from the arguments of a subcommand.
+fn parse_instance(matches: &ArgMatches) -> Result<ServiceInstance, String> {
+	let name: &String = matches.get_one("service").unwrap();
+	let mut arguments = BTreeMap::new();
+	for argument in matches.get_many::<String>("arguments").into_iter().flatten() {
+		match argument.split_once('=') {
+			Some((key, value)) => arguments.insert(key.to_owned(), value.to_owned()),
+			None => return Err(format!("invalid argument '{}', expected KEY=VALUE", argument)),
+		};
+	}
+
+	Ok(ServiceInstance {
+		name: name.to_owned(),
+		arguments,
+	})
+}
+
+/// Sends the request to qinit, and waits for the result of it.
+async fn send(target: &Target, request_body: &QinitRequest) -> Result<(), ProtocolError> {
+	let mut stream = target.request(request_body).await?;
+
+	// Once the request is accepted, qinit replies again with the result of acting on it.
+	let reply: Reply = read_frame(&mut stream).await?;
+	Ok(reply?)
+}
+
+/// Asks qinit for the status of services, and waits for them.
+async fn fetch_statuses(target: &Target, request_body: &QinitRequest) -> Result<Vec<ServiceStatus>, ProtocolError> {
+	let mut stream = target.request(request_body).await?;
+	let reply: Reply<Vec<ServiceStatus>> = read_frame(&mut stream).await?;
+	Ok(reply?)
+}
+
+/// Formats a number of seconds as the two largest units of it, e.g. `1h5m`.
+fn format_duration(secs: u64) -> String {
+	let units = [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+	let parts = units
+		.iter()
+		.filter(|(_, size)| secs >= *size)
+		.take(2)
+		.scan(secs, |remaining, (unit, size)| {
+			let count = *remaining / size;
+			*remaining %= size;
+			Some(format!("{}{}", count, unit))
+		})
+		.collect::<String>();
+
+	if parts.is_empty() {
+		String::from("0s")
+	} else {
+		parts
+	}
+}
+
+/// The current time, in seconds since the Unix epoch.
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |now| now.as_secs())
+}
+
+/// Prints the statuses as a table.
+fn print_statuses(statuses: &[ServiceStatus]) {
+	let now = now();
+
+	let mut table =
+		tables::Table::new_with_headers(["Service", "State", "PID", "Since", "Restarts", "Session", "Reason"])
+			.with_setting(tables::TableSetting::ColumnSeperators)
+			.with_setting(tables::TableSetting::HeaderSeperator);
+
+	for status in statuses {
+		let reason = match &status.state {
+			RunState::Failed { reason, .. } => reason.as_str(),
+			_ => "",
+		};
+
+		table.add_row([
+			&status.instance.to_string(),
+			&status.state.to_string(),
+			&status.pid.map_or(String::from("-"), |pid| pid.to_string()),
+			&format_duration(now.saturating_sub(status.since)),
+			&status.restarts.to_string(),
+			&status.session.map_or(String::from("-"), |session| session.to_string()),
+			reason,
+		]);
+	}
+
+	print!("{}", table);
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+	let matches = Command::new("qctl")
+		.version("0.1.0")
+		.author("Colin Douch <colin@quirl.co.nz>")
+		.about("Control the services managed by qinit")
+		.arg(
+			Arg::new("socket")
+				.long("socket")
+				.num_args(1)
+				.default_value(QINIT_CONTROL_SOCKET)
+				.help("The path to qinit's control socket"),
+		)
+		.arg(
+			Arg::new("remote")
+				.long("remote")
+				.num_args(1)
+				.requires("token_file")
+				.help("The address of a remote qinit to control, e.g. one booted with qinit.remote=<addr>"),
+		)
+		.arg(
+			Arg::new("token_file")
+				.long("token-file")
+				.num_args(1)
+				.requires("remote")
+				.help("A file holding the token of the remote qinit"),
+		)
+		.subcommand_required(true)
+		.subcommand(instance_command(
+			"start",
+			"Start a service instance now, whether or not it's enabled",
+		))
+		.subcommand(instance_command(
+			"stop",
+			"Stop a service instance, killing every process it started",
+		))
+		.subcommand(instance_command("enable", "Start a service instance on boot"))
+		.subcommand(instance_command(
+			"disable",
+			"Stop starting a service instance on boot, even if it's in the boot sphere",
+		))
+		.subcommand(
+			Command::new("list")
+				.about("Show the status of every service")
+				.arg(tree_arg()),
+		)
+		.subcommand(
+			instance_command(
+				"status",
+				"Show the status of the instances of a service that match the given arguments, or of every service",
+			)
+			.mut_arg("service", |arg| arg.required(false))
+			.arg(tree_arg()),
+		)
+		.get_matches();
+
+	let target = match matches.get_one::<String>("remote") {
+		Some(addr) => {
+			let token_file: &String = matches.get_one("token_file").unwrap();
+			match fs::read_to_string(token_file) {
+				Ok(token) => Target::Remote {
+					addr: addr.to_owned(),
+					token: token.trim().to_owned(),
+				},
+				Err(e) => {
+					eprintln!("qctl: failed to read token from {}: {}", token_file, e);
+					return ExitCode::FAILURE;
+				}
+			}
+		}
+		None => Target::Local(matches.get_one::<String>("socket").unwrap().to_owned()),
+	};
+
+	let (subcommand, sub_matches) = matches.subcommand().unwrap();
+	let tree = sub_matches
+		.try_get_one::<bool>("tree")
+		.ok()
+		.flatten()
+		.copied()
+		.unwrap_or(false);
+	if subcommand == "list" || (subcommand == "status" && !sub_matches.contains_id("service")) {
+		return show_statuses(&target, &QinitRequest::List, tree).await;
+	}
+
+	let instance = match parse_instance(sub_matches) {
+		Ok(instance) => instance,
+		Err(e) => {
+			eprintln!("qctl: {}", e);
+			return ExitCode::FAILURE;
+		}
+	};
+
+	let request_body = match subcommand {
+		"start" => QinitRequest::Start(instance),
+		"stop" => QinitRequest::Stop(instance),
+		"enable" => QinitRequest::Enable(instance),
+		"disable" => QinitRequest::Disable(instance),
+		"status" => return show_statuses(&target, &QinitRequest::Status(instance), tree).await,
+		_ => unreachable!("unknown subcommand {}", subcommand),
+	};
+
+	match send(&target, &request_body).await {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(e) => {
+			eprintln!("qctl: failed to {}: {}", subcommand, e);
+			ExitCode::FAILURE
+		}
+	}
+}
+
+/// Fetches and prints the statuses that the request asks for, as a table or a tree. Trees need the statuses of the
+/// services that the requested ones need too, so every status is fetched, and filtered here.
+async fn show_statuses(target: &Target, request_body: &QinitRequest, tree: bool) -> ExitCode {
+	let filter = match request_body {
+		QinitRequest::Status(instance) if tree => Some(instance),
+		_ => None,
+	};
+
+	let request_body = if tree { &QinitRequest::List } else { request_body };
+	match fetch_statuses(target, request_body).await {
+		Ok(statuses) if statuses.is_empty() || filter.is_some_and(|f| !statuses.iter().any(|s| s.matches(f))) => {
+			eprintln!("qctl: no matching services");
+			ExitCode::FAILURE
+		}
+		Ok(statuses) if tree => {
+			print!("{}", tree::build_tree(&statuses, filter, now()));
+			ExitCode::SUCCESS
+		}
+		Ok(statuses) => {
+			print_statuses(&statuses);
+			ExitCode::SUCCESS
+		}
+		Err(e) => {
+			eprintln!("qctl: failed to get statuses: {}", e);
+			ExitCode::FAILURE
+		}
+	}
+}
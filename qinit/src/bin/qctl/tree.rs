@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use common::qinit::{RunState, ServiceInstance, ServiceStatus};
+use tables::{Table, TableSetting};
+
+use crate::format_duration;
+
+/// The branch drawn before a node that has more siblings after it.
+const BRANCH: &str = "├─ ";
+
+/// The branch drawn before the last node of its siblings.
+const LAST_BRANCH: &str = "└─ ";
+
+/// What's drawn below a node that has more siblings after it, to continue the branch past its children.
+const CONTINUATION: &str = "│  ";
+
+/// What's drawn below the last node of its siblings.
+const NO_CONTINUATION: &str = "   ";
+
+/// The glyph that shows the state of a service at a glance.
+fn state_glyph(state: &RunState) -> &'static str {
+	match state {
+		RunState::Running => "●",
+		RunState::Starting => "◐",
+		RunState::Queued => "○",
+		RunState::Failed { .. } => "✗",
+		RunState::Stopped => "■",
+	}
+}
+
+/// Builds a table of the services as trees of the services they need, grouped by the sphere that they were started
+/// as part of. The roots of the trees are the services that match the filter, or the services that nothing else needs
+/// if there isn't one. `now` is the current time in seconds since the Unix epoch, to work out uptimes.
+pub fn build_tree(statuses: &[ServiceStatus], filter: Option<&ServiceInstance>, now: u64) -> Table<3> {
+	let is_root = |status: &ServiceStatus| match filter {
+		Some(instance) => status.matches(instance),
+		None => !statuses
+			.iter()
+			.any(|other| other.needs.iter().any(|need| status.matches(need))),
+	};
+
+	let mut spheres: BTreeMap<Option<&str>, Vec<&ServiceStatus>> = BTreeMap::new();
+	for status in statuses.iter().filter(|status| is_root(status)) {
+		spheres.entry(status.sphere.as_deref()).or_default().push(status);
+	}
+
+	let mut table = Table::new_with_headers(["Service", "State", "Uptime"]).with_setting(TableSetting::HeaderSeperator);
+	for (sphere, roots) in spheres {
+		match sphere {
+			Some(sphere) => table.add_group(&format!("sphere {}", sphere)),
+			None => table.add_group("started outside of a sphere"),
+		}
+
+		for root in roots {
+			add_node(&mut table, statuses, root, "", "", &mut Vec::new(), now);
+		}
+	}
+
+	table
+}
+
+/// Adds a row for the service to the table, and then rows for the services it needs below it. `lead` is drawn before
+/// the node and its children to continue the branches of its ancestors, and `branch` connects it to its parent.
+/// `path` is the services between the root and this one, so that a cycle doesn't recurse forever.
+fn add_node<'a>(
+	table: &mut Table<3>,
+	statuses: &'a [ServiceStatus],
+	status: &'a ServiceStatus,
+	lead: &str,
+	branch: &str,
+	path: &mut Vec<&'a ServiceInstance>,
+	now: u64,
+) {
+	let uptime = match status.state {
+		RunState::Running | RunState::Starting => format_duration(now.saturating_sub(status.since)),
+		_ => String::from("-"),
+	};
+
+	table.add_row([
+		&format!("{}{}{} {}", lead, branch, state_glyph(&status.state), status.instance),
+		&status.state.to_string(),
+		&uptime,
+	]);
+
+	if path.contains(&&status.instance) {
+		return;
+	}
+
+	let lead = match branch {
+		BRANCH => format!("{}{}", lead, CONTINUATION),
+		LAST_BRANCH => format!("{}{}", lead, NO_CONTINUATION),
+		_ => lead.to_owned(),
+	};
+
+	// A service that hasn't been queued (e.g. because it's disabled) doesn't have a status, but is still shown, as it
+	// may be what's holding things up.
+	let children = status
+		.needs
+		.iter()
+		.flat_map(|need| {
+			let matched: Vec<_> = statuses.iter().filter(|s| s.matches(need)).map(Ok).collect();
+			if matched.is_empty() {
+				vec![Err(need)]
+			} else {
+				matched
+			}
+		})
+		.collect::<Vec<_>>();
+
+	path.push(&status.instance);
+	for (i, child) in children.iter().enumerate() {
+		let branch = if i == children.len() - 1 { LAST_BRANCH } else { BRANCH };
+		match child {
+			Ok(child) => add_node(table, statuses, child, &lead, branch, path, now),
+			Err(need) => table.add_row([&format!("{}{}? {}", lead, branch, need), "not queued", "-"]),
+		}
+	}
+	path.pop();
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::BTreeMap;
+
+	use common::qinit::{RunState, ServiceInstance, ServiceStatus};
+
+	use super::build_tree;
+
+	fn instance(name: &str, arguments: &[(&str, &str)]) -> ServiceInstance {
+		ServiceInstance {
+			name: name.to_owned(),
+			arguments: arguments
+				.iter()
+				.map(|(key, value)| (key.to_string(), value.to_string()))
+				.collect::<BTreeMap<_, _>>(),
+		}
+	}
+
+	fn status(instance: ServiceInstance, state: RunState, needs: Vec<ServiceInstance>) -> ServiceStatus {
+		ServiceStatus {
+			instance,
+			state,
+			pid: None,
+			since: 0,
+			restarts: 0,
+			needs,
+			sphere: Some(String::from("base")),
+			session: None,
+		}
+	}
+
+	#[test]
+	fn test_build_tree() {
+		let statuses = vec![
+			status(instance("loggerd", &[]), RunState::Running, Vec::new()),
+			status(
+				instance("udevd", &[]),
+				RunState::Starting,
+				vec![instance("loggerd", &[])],
+			),
+			status(
+				instance("getty", &[("TTY", "tty1")]),
+				RunState::Queued,
+				vec![instance("udevd", &[]), instance("auth", &[])],
+			),
+		];
+
+		let tree = build_tree(&statuses, None, 65).to_string();
+		assert_eq!(
+			tree,
+			"Service          State      Uptime\n\
+			 ----------------------------------\n\
+			 sphere base\n\
+			 ○ getty TTY=tty1 queued     -     \n\
+			 ├─ ◐ udevd       starting   1m5s  \n\
+			 │  └─ ● loggerd  running    1m5s  \n\
+			 └─ ? auth        not queued -     \n",
+			"\n{}",
+			tree
+		);
+
+		// With a filter, the trees start from the matching services.
+		let tree = build_tree(&statuses, Some(&instance("udevd", &[])), 65).to_string();
+		assert!(tree.contains("◐ udevd"));
+		assert!(!tree.contains("getty"));
+	}
+}
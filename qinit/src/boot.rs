@@ -0,0 +1,198 @@
+use std::{io, net::SocketAddr, thread, time::Duration};
+
+use common::{
+	cmdline::KernelCmdline,
+	io::{prompt_with_timeout, IOTriple},
+};
+use slog::{error, info, warn};
+use tokio::process::Command;
+
+/// The sphere that is started on boot, unless another is given on the kernel command line.
+pub const DEFAULT_BOOT_SPHERE: &str = "user";
+
+/// The shell that is started when booting into rescue mode.
+pub const RESCUE_SHELL: &str = "/bin/qsh";
+
+/// How long to wait before trying to start the emergency shell again, if it can't be started at all.
+const EMERGENCY_SHELL_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long to wait for a question on the console to be answered, before going with the default answer.
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How to boot the system, from the kernel command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootOptions {
+	/// The sphere to start, from `qinit.sphere=<name>`.
+	pub sphere: String,
+
+	/// Whether to log debug messages, from `qinit.debug`.
+	pub debug: bool,
+
+	/// Whether to start a rescue shell before starting the sphere, from `single`.
+	pub rescue: bool,
+
+	/// Whether to ask on the console which sphere to start, from `qinit.interactive`.
+	pub interactive: bool,
+
+	/// The address to listen for remote control requests on, from `qinit.remote=<addr>`. Remote control is off
+	/// unless this is given.
+	pub remote: Option<SocketAddr>,
+}
+
+impl Default for BootOptions {
+	fn default() -> Self {
+		Self {
+			sphere: DEFAULT_BOOT_SPHERE.to_owned(),
+			debug: false,
+			rescue: false,
+			interactive: false,
+			remote: None,
+		}
+	}
+}
+
+impl BootOptions {
+	pub fn from_cmdline(cmdline: &KernelCmdline) -> Self {
+		let mut options = Self {
+			rescue: cmdline.has("single"),
+			..Self::default()
+		};
+
+		for (option, value) in cmdline.options("qinit") {
+			match (option, value) {
+				("sphere", Some(sphere)) if !sphere.is_empty() => options.sphere = sphere.to_owned(),
+				("debug", None) => options.debug = true,
+				("debug", Some(value)) => options.debug = !matches!(value, "0" | "false" | "no"),
+				("interactive", None) => options.interactive = true,
+				("remote", Some(addr)) => options.remote = addr.parse().ok(),
+				_ => {}
+			}
+		}
+
+		options
+	}
+}
+
+/// Asks a question on the console, going with the default answer if it isn't answered in time, or can't be asked at
+/// all (e.g. because there's nothing on the other end of the console).
+async fn ask(logger: &slog::Logger, question: String, default: &str) -> String {
+	let answer_default = default.to_owned();
+	let asked = tokio::task::spawn_blocking(move || {
+		prompt_with_timeout(&IOTriple::default(), &question, PROMPT_TIMEOUT, &answer_default)
+	})
+	.await;
+
+	match asked.map_err(io::Error::from).and_then(|answer| answer) {
+		Ok(answer) => answer,
+		Err(e) => {
+			warn!(logger, "failed to ask on the console, using the default"; "default" => default, "error" => e.to_string());
+			default.to_owned()
+		}
+	}
+}
+
+/// Asks on the console which sphere to start, starting the given one if nobody answers in time.
+pub async fn choose_sphere(logger: &slog::Logger, sphere: &str) -> String {
+	let question = format!(
+		"qinit: sphere to start [{}] (starting in {}s unless a key is pressed):",
+		sphere,
+		PROMPT_TIMEOUT.as_secs()
+	);
+
+	let chosen = ask(logger, question, sphere).await;
+	info!(logger, "chose boot sphere"; "sphere" => &chosen);
+	chosen
+}
+
+/// Asks on the console whether to start a rescue shell, after something went wrong while booting. If nobody answers
+/// in time, one is started, so that the problem can be looked into.
+pub async fn confirm_rescue(logger: &slog::Logger) -> bool {
+	let question = format!(
+		"qinit: start a rescue shell? [Y/n] (starting in {}s unless a key is pressed):",
+		PROMPT_TIMEOUT.as_secs()
+	);
+
+	is_yes(&ask(logger, question, "y").await)
+}
+
+/// Whether the answer to a yes or no question is yes.
+fn is_yes(answer: &str) -> bool {
+	!answer.trim().to_lowercase().starts_with('n')
+}
+
+/// Runs a rescue shell on the console, waiting for it to exit.
+pub async fn rescue_shell(logger: &slog::Logger) {
+	info!(logger, "starting rescue shell"; "shell" => RESCUE_SHELL);
+	eprintln!("qinit: starting a rescue shell. Exit the shell to continue booting.");
+
+	let result = match Command::new(RESCUE_SHELL).spawn() {
+		Ok(mut child) => child.wait().await,
+		Err(e) => Err(e),
+	};
+
+	match result {
+		Ok(status) => info!(logger, "rescue shell exited"; "status" => status.to_string()),
+		Err(e) => error!(logger, "failed to run rescue shell"; "error" => e.to_string()),
+	}
+}
+
+/// Runs rescue shells on the console forever. This is for when qinit can't carry on, but can't exit either, as the
+/// kernel panics if PID 1 exits.
+pub fn emergency_shell() -> ! {
+	eprintln!("qinit: qinit has crashed, and can't continue. Starting a rescue shell.");
+	loop {
+		if let Err(e) = std::process::Command::new(RESCUE_SHELL).status() {
+			eprintln!("qinit: failed to run rescue shell: {}", e);
+			thread::sleep(EMERGENCY_SHELL_RETRY_INTERVAL);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use common::cmdline::KernelCmdline;
+
+	use super::{is_yes, BootOptions, DEFAULT_BOOT_SPHERE};
+
+	#[test]
+	fn test_boot_options() {
+		let options = BootOptions::from_cmdline(&KernelCmdline::parse("root=/dev/sda1 quiet"));
+		assert_eq!(options, BootOptions::default());
+		assert_eq!(options.sphere, DEFAULT_BOOT_SPHERE);
+
+		let options = BootOptions::from_cmdline(&KernelCmdline::parse("qinit.sphere=minimal qinit.debug single"));
+		assert_eq!(
+			options,
+			BootOptions {
+				sphere: String::from("minimal"),
+				debug: true,
+				rescue: true,
+				interactive: false,
+				remote: None,
+			}
+		);
+
+		let options = BootOptions::from_cmdline(&KernelCmdline::parse("qinit.interactive"));
+		assert!(options.interactive);
+
+		let options = BootOptions::from_cmdline(&KernelCmdline::parse("qinit.remote=127.0.0.1:4000"));
+		assert_eq!(options.remote, Some("127.0.0.1:4000".parse().unwrap()));
+
+		let options = BootOptions::from_cmdline(&KernelCmdline::parse("qinit.remote=nonsense"));
+		assert_eq!(options.remote, None);
+
+		let options = BootOptions::from_cmdline(&KernelCmdline::parse("qinit.sphere= qinit.debug=0"));
+		assert_eq!(options, BootOptions::default());
+	}
+
+	#[test]
+	fn test_is_yes() {
+		for answer in ["y", "Y", "yes", ""] {
+			assert!(is_yes(answer), "{:?} should be yes", answer);
+		}
+
+		for answer in ["n", "N", "no", " No "] {
+			assert!(!is_yes(answer), "{:?} should be no", answer);
+		}
+	}
+}
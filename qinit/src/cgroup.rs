@@ -0,0 +1,181 @@
+use std::{
+	collections::HashMap,
+	fs::{create_dir_all, read_to_string, remove_dir, write},
+	io::{self, ErrorKind},
+	path::{Path, PathBuf},
+};
+
+use nix::{
+	sys::signal::{kill, Signal},
+	unistd::Pid,
+};
+
+use crate::config::Resources;
+
+/// Where the cgroup v2 hierarchy is mounted.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The cgroup, under the root, that holds the cgroups of every service.
+pub const QINIT_CGROUP: &str = "qinit";
+
+/// The controllers that are enabled for services, so that their limits can be set.
+const CONTROLLERS: [&str; 3] = ["memory", "cpu", "pids"];
+
+/// Whether the cgroup v2 hierarchy is mounted at the given root. On hybrid setups, the root is a tmpfs of v1
+/// hierarchies, so it has no `cgroup.controllers`.
+pub fn available(root: &Path) -> bool {
+	root.join("cgroup.controllers").exists()
+}
+
+/// The cgroup of a service, which every process of the service is in.
+#[derive(Debug, Clone)]
+pub struct Cgroup {
+	path: PathBuf,
+}
+
+impl Cgroup {
+	/// Creates (or reuses) the cgroup for the given service instance under the root, enabling the controllers that
+	/// limits are set with.
+	pub fn create(root: &Path, name: &str, args: &HashMap<String, String>) -> io::Result<Self> {
+		let parent = root.join(QINIT_CGROUP);
+		create_dir_all(&parent)?;
+
+		// Controllers have to be enabled in every ancestor for them to be usable in the service's cgroup.
+		enable_controllers(root)?;
+		enable_controllers(&parent)?;
+
+		let path = parent.join(cgroup_name(name, args));
+		match std::fs::create_dir(&path) {
+			Ok(()) => {}
+			Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+			Err(e) => return Err(e),
+		}
+
+		Ok(Self { path })
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Sets the given limits on the cgroup.
+	pub fn set_limits(&self, resources: &Resources) -> io::Result<()> {
+		if let Some(memory_max) = &resources.memory_max {
+			write(self.path.join("memory.max"), memory_max)?;
+		}
+
+		if let Some(cpu_max) = &resources.cpu_max {
+			write(self.path.join("cpu.max"), cpu_max)?;
+		}
+
+		if let Some(pids_max) = resources.pids_max {
+			write(self.path.join("pids.max"), pids_max.to_string())?;
+		}
+
+		Ok(())
+	}
+
+	/// Moves the given process into the cgroup. A PID of 0 moves the calling process.
+	pub fn add_process(&self, pid: Pid) -> io::Result<()> {
+		write(self.path.join("cgroup.procs"), pid.to_string())
+	}
+
+	/// The processes that are in the cgroup.
+	pub fn processes(&self) -> io::Result<Vec<Pid>> {
+		Ok(read_to_string(self.path.join("cgroup.procs"))?
+			.lines()
+			.filter_map(|line| line.parse().ok())
+			.map(Pid::from_raw)
+			.collect())
+	}
+
+	/// Whether there are any processes left in the cgroup.
+	pub fn is_populated(&self) -> io::Result<bool> {
+		Ok(!self.processes()?.is_empty())
+	}
+
+	/// Sends the given signal to every process in the cgroup.
+	pub fn signal(&self, signal: Signal) -> io::Result<()> {
+		for pid in self.processes()? {
+			match kill(pid, signal) {
+				// The process exited between listing and signalling it.
+				Ok(()) | Err(nix::errno::Errno::ESRCH) => {}
+				Err(e) => return Err(e.into()),
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Kills every process in the cgroup, including any that are forked while it's being killed. Uses `cgroup.kill`
+	/// where the kernel has it (5.14+), falling back to SIGKILLing the processes one by one.
+	pub fn kill(&self) -> io::Result<()> {
+		match write(self.path.join("cgroup.kill"), "1") {
+			Ok(()) => Ok(()),
+			Err(e) if e.kind() == ErrorKind::NotFound => {
+				while self.is_populated()? {
+					self.signal(Signal::SIGKILL)?;
+				}
+
+				Ok(())
+			}
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Removes the cgroup, which fails if there are still processes in it.
+	pub fn remove(&self) -> io::Result<()> {
+		match remove_dir(&self.path) {
+			Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// Enables the controllers that are available in the given cgroup for its children.
+fn enable_controllers(cgroup: &Path) -> io::Result<()> {
+	let available = read_to_string(cgroup.join("cgroup.controllers"))?;
+	let enable = available
+		.split_whitespace()
+		.filter(|controller| CONTROLLERS.contains(controller))
+		.map(|controller| format!("+{}", controller))
+		.collect::<Vec<_>>();
+
+	if enable.is_empty() {
+		return Ok(());
+	}
+
+	write(cgroup.join("cgroup.subtree_control"), enable.join(" "))
+}
+
+/// The name of the cgroup of a service instance, e.g. `getty@TTY=_dev_tty1` for the `getty` service with the argument
+/// `TTY=/dev/tty1`. Arguments are sorted so that the name is stable.
+fn cgroup_name(name: &str, args: &HashMap<String, String>) -> String {
+	let mut args = args.iter().collect::<Vec<_>>();
+	args.sort();
+
+	let mut cgroup = name.replace('/', "_");
+	for (key, value) in args {
+		cgroup.push_str(&format!("@{}={}", key, value).replace('/', "_"));
+	}
+
+	cgroup
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::HashMap;
+
+	use super::cgroup_name;
+
+	#[test]
+	fn test_cgroup_name() {
+		assert_eq!(cgroup_name("busd", &HashMap::new()), "busd");
+
+		let args = HashMap::from([
+			("TTY".to_owned(), "/dev/tty1".to_owned()),
+			("BAUD".to_owned(), "9600".to_owned()),
+		]);
+		assert_eq!(cgroup_name("getty", &args), "getty@BAUD=9600@TTY=_dev_tty1");
+	}
+}
@@ -1,19 +1,24 @@
 mod service;
 
 use std::{
-	collections::{HashMap, VecDeque},
+	collections::{BTreeMap, HashMap, VecDeque},
 	error::Error,
 	fmt::{self, Display, Formatter},
-	fs,
+	fs, io,
 	path::{Path, PathBuf},
 };
 
+use common::qinit::ServiceInstance;
+use mount::fstab::{read_fstab, FstabError};
 use service::SphereDefinition;
-pub use service::{Dependency, Permissions, ServiceConfig, StartMode};
+pub use service::{Dependency, Permissions, Resources, Sandbox, ServiceConfig, ServiceKind, StartMode};
 
 const SERVICE_FILE_EXTENSION: &str = "service";
 const SPHERE_FILE_EXTENSION: &str = "sphere";
 
+/// The sphere of the services that are generated for the swap areas in fstab, which is started at boot.
+pub const SWAP_SPHERE: &str = "swap";
+
 /// An error that occurred while validating a service definition.
 #[derive(Debug, Clone, PartialEq)]
 pub struct ValidationError {
@@ -133,6 +138,55 @@ impl Config {
 		self.spheres.get(name)
 	}
 
+	/// Resolves the instance of the given service with the given arguments, filling in the defaults of any arguments
+	/// that aren't given, so that instances can be compared regardless of how they were written.
+	pub fn resolve_instance<'a, I: IntoIterator<Item = (&'a String, &'a String)>>(
+		&self,
+		name: &str,
+		arguments: I,
+	) -> Result<ServiceInstance, ValidationError> {
+		let service = match self.get_service_config(name) {
+			Some(service) => service,
+			None => return Err(ValidationError::new_fatal(&format!("Service {} doesn't exist", name))),
+		};
+
+		let mut resolved = BTreeMap::new();
+		for (key, value) in arguments {
+			if !service.service.has_argument(key) {
+				return Err(ValidationError::new_fatal(&format!(
+					"Service {} has no argument {}",
+					name, key
+				)));
+			}
+
+			resolved.insert(key.clone(), value.clone());
+		}
+
+		for argument in service.service.arguments.iter() {
+			if resolved.contains_key(&argument.name) {
+				continue;
+			}
+
+			match &argument.default {
+				Some(default) => {
+					resolved.insert(argument.name.clone(), default.clone());
+				}
+				None if argument.required => {
+					return Err(ValidationError::new_fatal(&format!(
+						"Service {} needs argument {}",
+						name, argument.name
+					)));
+				}
+				None => {}
+			}
+		}
+
+		Ok(ServiceInstance {
+			name: name.to_owned(),
+			arguments: resolved,
+		})
+	}
+
 	/// Loads all the services from .service files in the given directory
 	/// and adds them to the configuration.
 	fn load_services_from_directory(&mut self, path: &Path) -> ValidationResult {
@@ -278,6 +332,65 @@ impl Config {
 		errors
 	}
 
+	/// Generates a service for every swap area in the fstab at the given path, and the swap sphere of the ones that
+	/// aren't `noauto`. Machines without an fstab have no swap areas.
+	fn load_fstab(&mut self, path: &Path) -> ValidationResult {
+		let mut errors = ValidationResult::new();
+		let entries = match read_fstab(path) {
+			Ok(entries) => entries,
+			Err(FstabError::IOError(e)) if e.kind() == io::ErrorKind::NotFound => return errors,
+			Err(e) => {
+				errors.add_error(ValidationError::new(&format!(
+					"Failed to read swap areas from {}: {}",
+					path.display(),
+					e
+				)));
+				return errors;
+			}
+		};
+
+		let mut services = Vec::new();
+		for entry in entries.iter().filter(|entry| entry.is_swap()) {
+			let service = ServiceConfig::for_swap(entry);
+			let dependency = Dependency {
+				name: service.name.clone(),
+				arguments: HashMap::new(),
+			};
+
+			let result = self.add_service(service);
+			if !result.is_fatal() && entry.is_auto() {
+				services.push(dependency);
+			}
+
+			errors.merge(result);
+		}
+
+		if services.is_empty() {
+			return errors;
+		}
+
+		if self.spheres.contains_key(SWAP_SPHERE) {
+			errors.add_error(ValidationError::new_fatal(&format!(
+				"Sphere with name {} already exists, but it's generated from {}",
+				SWAP_SPHERE,
+				path.display()
+			)));
+			return errors;
+		}
+
+		self.spheres.insert(
+			SWAP_SPHERE.to_owned(),
+			SphereDefinition {
+				name: SWAP_SPHERE.to_owned(),
+				description: format!("The swap areas in {}", path.display()),
+				services,
+				needs: Vec::new(),
+			},
+		);
+
+		errors
+	}
+
 	/// Validates the configuration.
 	pub fn validate(&self) -> ValidationResult {
 		// We assume that the _individual_ services are already validated.
@@ -404,8 +517,9 @@ impl Config {
 	}
 }
 
-/// Loads all the service definitions from the given directories and returns the configuration.
-pub fn load_config<T: IntoIterator<Item = PathBuf>>(config_directories: T) -> (Config, ValidationResult) {
+/// Loads all the service definitions from the given directories, along with the services for the swap areas in the
+/// given fstab, and returns the configuration.
+pub fn load_config<T: IntoIterator<Item = PathBuf>>(config_directories: T, fstab: &Path) -> (Config, ValidationResult) {
 	let mut config = Config::empty();
 
 	let mut errors = ValidationResult::new();
@@ -414,6 +528,8 @@ pub fn load_config<T: IntoIterator<Item = PathBuf>>(config_directories: T) -> (C
 		errors.merge(config.load_services_from_directory(&path));
 	}
 
+	errors.merge(config.load_fstab(fstab));
+
 	(config, errors)
 }
 
@@ -438,6 +554,24 @@ mod test {
 		assert_eq!(config.services.get("test").unwrap().name, "test");
 	}
 
+	#[test]
+	fn test_config_kmod() {
+		let definition = r#"
+      name = "kmod"
+      kind = "kmod"
+      service = { command = "${MODULE}", arguments = [{ name = "MODULE", required = true }] }
+    "#;
+		let mut service: ServiceConfig = toml::from_str(definition).unwrap();
+		assert_eq!(service.kind, ServiceKind::Kmod);
+		assert!(!service.validate().is_error());
+
+		// Options for processes are ignored, with a warning.
+		service.service.tty = Some(String::from("tty1"));
+		let errors = service.validate();
+		assert!(errors.is_error());
+		assert!(!errors.is_fatal());
+	}
+
 	#[test]
 	fn test_config_missing_name() {
 		let mut config = Config::empty();
@@ -506,6 +640,12 @@ mod test {
 					default: None,
 				},
 			],
+			working_directory: None,
+			umask: None,
+			tty: None,
+			environment: HashMap::new(),
+			environment_files: Vec::new(),
+			coalesce_logs: None,
 		};
 
 		let errors = service.validate();
@@ -641,6 +781,268 @@ mod test {
 		assert!(config.validate().is_error());
 	}
 
+	#[test]
+	fn test_config_needs_device() {
+		let mut config = Config::empty();
+		let definition = r#"
+			name = "test"
+			description = "Test service"
+			service = { command = "echo" }
+			needs_device = [ "/dev/ttyS0", "subsystem:net:eth*" ]
+		"#;
+		let errors = config.add_service(toml::from_str(definition).unwrap());
+		assert!(!errors.is_error());
+		assert_eq!(config.services.get("test").unwrap().device_timeout, 30);
+		assert_eq!(config.services.get("test").unwrap().start_mode, StartMode::Exec);
+		assert_eq!(config.services.get("test").unwrap().start_timeout, 90);
+
+		let definition = r#"
+			name = "invalid"
+			description = "Test service"
+			service = { command = "echo" }
+			needs_device = [ "subsystem:net" ]
+		"#;
+		let errors = config.add_service(toml::from_str(definition).unwrap());
+		assert!(errors.is_fatal());
+	}
+
+	#[test]
+	fn test_config_start_mode() {
+		let mut config = Config::empty();
+		for (name, start_mode, expected) in [
+			("exec", "exec", StartMode::Exec),
+			("legacy", "run", StartMode::Exec),
+			("notify", "notify", StartMode::Notify),
+			("done", "done", StartMode::Done),
+			("forking", "fork-exits", StartMode::ForkExits),
+		] {
+			let definition = format!(
+				"name = \"{}\"\nservice = {{ command = \"echo\" }}\nstart_mode = \"{}\"\nstart_timeout = 5",
+				name, start_mode
+			);
+			let errors = config.add_service(toml::from_str(&definition).unwrap());
+			assert!(!errors.is_error());
+
+			let service = config.services.get(name).unwrap();
+			assert_eq!(service.start_mode, expected);
+			assert_eq!(service.start_timeout, 5);
+		}
+
+		assert!(toml::from_str::<ServiceConfig>(
+			"name = \"bad\"\nservice = { command = \"echo\" }\nstart_mode = \"forking\""
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn test_config_working_directory() {
+		let mut config = Config::empty();
+		let definition = "name = \"daemon\"\nservice = { command = \"echo\", working_directory = \"/var/lib/${NAME}\", umask = 0o027 }\npermissions = { user = \"daemon\" }";
+		let errors = config.add_service(toml::from_str(definition).unwrap());
+		assert!(!errors.is_error());
+
+		let service = config.services.get("daemon").unwrap();
+		assert_eq!(
+			service.service.working_directory,
+			Some(String::from("/var/lib/${NAME}"))
+		);
+		assert_eq!(service.service.umask, Some(0o027));
+		assert_eq!(service.permissions.user, "daemon");
+		assert_eq!(service.permissions.group, None);
+
+		for service in [
+			"{ command = \"echo\", working_directory = \"relative\" }",
+			"{ command = \"echo\", umask = 0o1777 }",
+		] {
+			let definition = format!("name = \"bad\"\nservice = {}", service);
+			let errors = config.add_service(toml::from_str(&definition).unwrap());
+			assert!(errors.is_error(), "expected {} to be invalid", service);
+		}
+	}
+
+	#[test]
+	fn test_config_tty() {
+		let mut config = Config::empty();
+		let definition = "name = \"getty\"\nservice = { command = \"/sbin/getty ${TTY}\", tty = \"${TTY}\" }";
+		let errors = config.add_service(toml::from_str(definition).unwrap());
+		assert!(!errors.is_error());
+		assert_eq!(
+			config.services.get("getty").unwrap().service.tty,
+			Some(String::from("${TTY}"))
+		);
+
+		let definition = "name = \"bad\"\nservice = { command = \"/sbin/getty\", tty = \"\" }";
+		assert!(config.add_service(toml::from_str(definition).unwrap()).is_error());
+	}
+
+	#[test]
+	fn test_config_environment() {
+		let mut config = Config::empty();
+		let definition = "name = \"env\"\n[service]\ncommand = \"echo\"\nenvironment_files = [\"/etc/default/${NAME}\", \"-/etc/env.local\"]\n[service.environment]\nLANG = \"C\"\nNAME = \"${NAME}\"";
+		let errors = config.add_service(toml::from_str(definition).unwrap());
+		assert!(!errors.is_error());
+
+		let service = config.services.get("env").unwrap();
+		assert_eq!(
+			service.service.environment,
+			HashMap::from([
+				(String::from("LANG"), String::from("C")),
+				(String::from("NAME"), String::from("${NAME}")),
+			])
+		);
+		assert_eq!(
+			service.service.environment_files,
+			vec![String::from("/etc/default/${NAME}"), String::from("-/etc/env.local")]
+		);
+
+		for service in [
+			"{ command = \"echo\", environment = { \"A=B\" = \"c\" } }",
+			"{ command = \"echo\", environment = { \"\" = \"c\" } }",
+			"{ command = \"echo\", environment_files = [\"relative\"] }",
+		] {
+			let definition = format!("name = \"bad\"\nservice = {}", service);
+			let errors = config.add_service(toml::from_str(&definition).unwrap());
+			assert!(errors.is_error(), "expected {} to be invalid", service);
+		}
+	}
+
+	#[test]
+	fn test_config_resources() {
+		let mut config = Config::empty();
+		let definition = "name = \"limited\"\nservice = { command = \"echo\" }\n[resources]\nmemory_max = \"512M\"\ncpu_max = \"50000 100000\"\npids_max = 64";
+		let errors = config.add_service(toml::from_str(definition).unwrap());
+		assert!(!errors.is_error());
+
+		let service = config.services.get("limited").unwrap();
+		assert_eq!(
+			service.resources,
+			Resources {
+				memory_max: Some(String::from("512M")),
+				cpu_max: Some(String::from("50000 100000")),
+				pids_max: Some(64),
+			}
+		);
+
+		for resources in [
+			"memory_max = \"lots\"",
+			"memory_max = \"M\"",
+			"cpu_max = \"half\"",
+			"cpu_max = \"max 1 2\"",
+		] {
+			let definition = format!(
+				"name = \"bad\"\nservice = {{ command = \"echo\" }}\n[resources]\n{}",
+				resources
+			);
+			let errors = config.add_service(toml::from_str(&definition).unwrap());
+			assert!(errors.is_error(), "expected {} to be invalid", resources);
+		}
+	}
+
+	#[test]
+	fn test_config_sandbox() {
+		let definition = "name = \"sandboxed\"\nservice = { command = \"/bin/daemon\" }\n[sandbox]\nprivate_tmp = true\nprotect_system = true\nprivate_network = true\nno_new_privileges = true";
+		let mut service: ServiceConfig = toml::from_str(definition).unwrap();
+		assert!(!service.validate().is_error());
+		assert!(service.sandbox.needs_mount_namespace());
+		assert!(service.sandbox.private_network && service.sandbox.no_new_privileges);
+
+		// Services without a process have nothing to sandbox.
+		service.kind = ServiceKind::Kmod;
+		let errors = service.validate();
+		assert!(errors.is_error());
+		assert!(!errors.is_fatal());
+
+		let definition = "name = \"bad\"\nservice = { command = \"/bin/daemon\" }\n[sandbox]\nprivate_devices = true";
+		assert!(toml::from_str::<ServiceConfig>(definition).is_err());
+	}
+
+	#[test]
+	fn test_load_fstab() {
+		let mut config = Config::empty();
+		let errors = config.load_fstab(&PathBuf::from("./testdata/fstab/fstab"));
+		assert!(!errors.is_error(), "{}", errors);
+		assert!(!config.validate().is_error());
+
+		let service = config.services.get("swap-dev-sda2").unwrap();
+		assert_eq!(service.kind, ServiceKind::Swap);
+		assert_eq!(service.service.command, "/dev/sda2 sw,pri=10");
+		assert_eq!(service.needs_device, vec!["/dev/sda2"]);
+
+		let service = config.services.get("swap-LABEL-scratch-swap").unwrap();
+		assert_eq!(service.service.command, "LABEL=scratch\\040swap noauto");
+		assert!(service.needs_device.is_empty());
+
+		// Only the swap areas that aren't noauto are started at boot.
+		let sphere = config.get_sphere(SWAP_SPHERE).unwrap();
+		assert_eq!(
+			sphere.services.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+			vec!["swap-dev-sda2"]
+		);
+
+		let mut config = Config::empty();
+		assert!(!config.load_fstab(&PathBuf::from("./testdata/fstab/missing")).is_error());
+		assert!(config.get_sphere(SWAP_SPHERE).is_none());
+	}
+
+	#[test]
+	fn test_config_swap() {
+		let mut config = Config::empty();
+		let definition = "name = \"swap\"\nkind = \"swap\"\nservice = { command = \"/dev/${DEVICE} pri=${PRIORITY}\" }";
+		assert!(!config.add_service(toml::from_str(definition).unwrap()).is_error());
+
+		for command in ["/dev/sda2 pri=lots", "/dev/sda2 discard=sometimes"] {
+			let definition = format!(
+				"name = \"bad\"\nkind = \"swap\"\nservice = {{ command = \"{}\" }}",
+				command
+			);
+			assert!(config.add_service(toml::from_str(&definition).unwrap()).is_fatal());
+		}
+	}
+
+	#[test]
+	fn test_config_without_command() {
+		let mut config = Config::empty();
+		for kind in ["sysctl", "modules", "hostname"] {
+			let definition = format!("name = \"{}\"\nkind = \"{}\"\nservice = {{}}", kind, kind);
+			let errors = config.add_service(toml::from_str(&definition).unwrap());
+			assert!(!errors.is_error(), "{}", errors);
+		}
+
+		let definition = "name = \"sysctl-extra\"\nkind = \"sysctl\"\nservice = { command = \"/etc/sysctl.extra\" }";
+		assert!(!config.add_service(toml::from_str(definition).unwrap()).is_error());
+
+		// Only the services that have defaults can leave their command out.
+		for kind in ["process", "kmod", "swap"] {
+			let definition = format!("name = \"bad-{}\"\nkind = \"{}\"\nservice = {{}}", kind, kind);
+			assert!(config.add_service(toml::from_str(&definition).unwrap()).is_fatal());
+		}
+
+		let definition = "name = \"bad-hostname\"\nkind = \"hostname\"\nservice = { command = \"two words\" }";
+		assert!(config.add_service(toml::from_str(definition).unwrap()).is_fatal());
+	}
+
+	#[test]
+	fn test_resolve_instance() {
+		let mut config = Config::empty();
+		let errors = config.load_services_from_directory(&PathBuf::from("./testdata/basic-service"));
+		assert!(!errors.is_error());
+
+		let arguments = HashMap::from([("TTY".to_string(), "/dev/tty1".to_string())]);
+		let instance = config.resolve_instance("getty-${TTY}", &arguments).unwrap();
+		assert_eq!(
+			instance.arguments,
+			BTreeMap::from([
+				("TTY".to_string(), "/dev/tty1".to_string()),
+				("Baud".to_string(), "9600".to_string())
+			])
+		);
+
+		assert!(config.resolve_instance("getty-${TTY}", &HashMap::new()).is_err());
+		let arguments = HashMap::from([("Parity".to_string(), "even".to_string())]);
+		assert!(config.resolve_instance("getty-${TTY}", &arguments).is_err());
+		assert!(config.resolve_instance("missing", &HashMap::new()).is_err());
+	}
+
 	#[test]
 	fn test_load_basic_service() {
 		let mut config = Config::empty();
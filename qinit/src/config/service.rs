@@ -1,15 +1,26 @@
 use std::collections::{HashMap, HashSet};
 
 use super::{ValidationError, ValidationResult};
+use crate::{devices::DeviceSpec, environment};
+use mount::{fstab::FstabEntry, swap::SwapOptions};
 use serde::Deserialize;
 
+/// The default number of seconds to wait for the devices a service needs to appear.
+const DEFAULT_DEVICE_TIMEOUT_SECS: u64 = 30;
+
+/// The default number of seconds to wait for a service to become ready.
+const DEFAULT_START_TIMEOUT_SECS: u64 = 90;
+
 /// The StartMode of a service, that defines what must happen for the
 /// service to be considered "started".
 #[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
-#[serde(rename_all = "lowercase")]
+#[serde(rename_all = "kebab-case")]
+#[derive(Default)]
 pub enum StartMode {
-	/// The service is considered started immediately once itsbeen exec'd.
-	Run,
+	/// The service is considered started immediately once its been exec'd.
+	#[default]
+	#[serde(alias = "run")]
+	Exec,
 
 	/// The service must manually notify the control socket that it has started.
 	Notify,
@@ -17,11 +28,54 @@ pub enum StartMode {
 	/// The service must exit sucessfully before being considered started.
 	/// This is useful for "OneShot" type services.
 	Done,
+
+	/// The service forks into the background, and is considered started once the process we started exits
+	/// successfully. This is useful for traditional daemons.
+	ForkExits,
 }
 
-impl Default for StartMode {
-	fn default() -> Self {
-		Self::Run
+/// What starting a service does.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServiceKind {
+	/// The command is run as a process.
+	#[default]
+	Process,
+
+	/// A kernel module is loaded, along with the modules that it depends on. The command is the name of the module,
+	/// followed by its parameters (e.g. `e1000e InterruptThrottleRate=3000`). The service is ready as soon as the
+	/// module is in /proc/modules, and stopping it unloads the module.
+	Kmod,
+
+	/// A swap area is used. The command is the swap area, as a path or a `UUID=` or `LABEL=` tag, followed by its
+	/// options as they'd be written in fstab (e.g. `/dev/sda2 pri=10,discard`). The service is ready as soon as the
+	/// area is in use, and stopping it stops using the area. Services for the swap areas in fstab are generated.
+	Swap,
+
+	/// Kernel parameters are set from sysctl.d files. The command is the files and directories to read them from, or
+	/// empty to read the usual ones (/etc/sysctl.d, /run/sysctl.d, /usr/lib/sysctl.d and so on, then
+	/// /etc/sysctl.conf). The service fails if any parameter can't be set, other than those prefixed with `-`.
+	Sysctl,
+
+	/// The kernel modules listed in modules-load.d files are loaded, along with the modules that they depend on. The
+	/// command is the files and directories to read them from, or empty to read the usual ones
+	/// (/etc/modules-load.d, /run/modules-load.d and /usr/lib/modules-load.d). The service fails if any of them can't
+	/// be loaded.
+	Modules,
+
+	/// The hostname is set. The command is the hostname, or empty to read it from /etc/hostname.
+	Hostname,
+}
+
+impl ServiceKind {
+	/// Whether starting the service runs its command as a process, rather than qinit doing the work itself.
+	pub fn has_process(&self) -> bool {
+		*self == ServiceKind::Process
+	}
+
+	/// Whether the service needs a command, rather than falling back on the usual configuration files.
+	fn needs_command(&self) -> bool {
+		matches!(self, ServiceKind::Process | ServiceKind::Kmod | ServiceKind::Swap)
 	}
 }
 
@@ -63,19 +117,75 @@ impl Argument {
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct ServiceDefinition {
-	/// The command to run.
+	/// The command to run. Only the services that don't run a process can leave it out.
+	#[serde(default)]
 	pub command: String,
 
 	/// The arguments to the command.
 	#[serde(default)]
 	pub arguments: Vec<Argument>,
+
+	/// The directory to run the command in, which must exist and is entered after dropping privileges. Arguments are
+	/// templated into it. If unset, the command is run in the runtime directory if there is one, or `/` if not.
+	pub working_directory: Option<String>,
+
+	/// The umask to run the command with, e.g. `0o027`. If unset, the command inherits qinit's.
+	pub umask: Option<u32>,
+
+	/// The terminal to run the command on, e.g. `/dev/tty1` (relative paths are relative to /dev), for getty-like
+	/// services. The command gets it as its controlling terminal, in a new session, and as its stdin, stdout, and
+	/// stderr, rather than having its output logged. Arguments are templated into it, and the service waits for it
+	/// to exist before starting.
+	pub tty: Option<String>,
+
+	/// The environment variables to run the command with, which override those from the `environment_files`.
+	/// Arguments are templated into the values.
+	#[serde(default)]
+	pub environment: HashMap<String, String>,
+
+	/// Files of `KEY=VALUE` lines to read environment variables from, in order, with later files overriding earlier
+	/// ones. Arguments are templated into the paths, and paths prefixed with `-` are skipped if they don't exist.
+	#[serde(default)]
+	pub environment_files: Vec<String>,
+
+	/// If set, identical consecutive lines that the command logs within this many seconds of each other are written
+	/// to loggerd as a single entry, tagged with how many times the line was repeated.
+	pub coalesce_logs: Option<u32>,
 }
 
 impl ServiceDefinition {
 	pub fn validate(&self) -> ValidationResult {
 		let mut result = ValidationResult::new();
-		if self.command.is_empty() {
-			result.add_error(ValidationError::new_fatal("Command cannot be empty"));
+		if self.working_directory.as_ref().is_some_and(|dir| !dir.starts_with('/')) {
+			result.add_error(ValidationError::new_fatal("Working directory must be an absolute path"));
+		}
+
+		if let Some(umask) = self.umask.filter(|umask| *umask > 0o777) {
+			result.add_error(ValidationError::new_fatal(&format!("Invalid umask: {:#o}", umask)));
+		}
+
+		if self.tty.as_ref().is_some_and(String::is_empty) {
+			result.add_error(ValidationError::new_fatal("TTY cannot be empty"));
+		}
+
+		if self.coalesce_logs == Some(0) {
+			result.add_error(ValidationError::new_fatal("Log coalescing window cannot be zero"));
+		}
+
+		for key in self.environment.keys().filter(|key| !environment::is_valid_key(key)) {
+			result.add_error(ValidationError::new_fatal(&format!(
+				"Invalid environment variable name: {:?}",
+				key
+			)));
+		}
+
+		for file in self.environment_files.iter() {
+			if !file.trim_start_matches('-').starts_with('/') {
+				result.add_error(ValidationError::new_fatal(&format!(
+					"Environment file must be an absolute path: {}",
+					file
+				)));
+			}
 		}
 
 		let mut existing_args = HashSet::new();
@@ -116,6 +226,16 @@ fn default_root() -> String {
 	"root".to_string()
 }
 
+/// The default number of seconds to wait for the devices a service needs.
+fn default_device_timeout() -> u64 {
+	DEFAULT_DEVICE_TIMEOUT_SECS
+}
+
+/// The default number of seconds to wait for a service to become ready.
+fn default_start_timeout() -> u64 {
+	DEFAULT_START_TIMEOUT_SECS
+}
+
 /// The users and group to start the service with.
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(deny_unknown_fields)]
@@ -124,9 +244,9 @@ pub struct Permissions {
 	#[serde(default = "default_root")]
 	pub user: String,
 
-	/// The group to start the service as.
-	#[serde(default = "default_root")]
-	pub group: String,
+	/// The group to start the service as. If unset, the primary group of the user is used. The service also gets the
+	/// supplementary groups that the user is a member of.
+	pub group: Option<String>,
 
 	/// Whether or not to _create_ the service / group if it exists. If false,
 	/// and the user / group doesn't exist, the service fails.
@@ -141,7 +261,7 @@ impl Permissions {
 			result.add_error(ValidationError::new_fatal("User cannot be empty"));
 		}
 
-		if self.group.is_empty() {
+		if self.group.as_ref().is_some_and(String::is_empty) {
 			result.add_error(ValidationError::new_fatal("Group cannot be empty"));
 		}
 
@@ -153,12 +273,90 @@ impl Default for Permissions {
 	fn default() -> Self {
 		Permissions {
 			user: default_root(),
-			group: default_root(),
+			group: None,
 			create: false,
 		}
 	}
 }
 
+/// The limits on the resources that a service can use, which are applied to its cgroup. Values are in the format
+/// of the cgroup files they are written to, and unset limits are left as they are.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Resources {
+	/// The most memory the service can use before it is OOM killed, in bytes with an optional K, M, G, or T suffix,
+	/// or "max". Written to `memory.max`.
+	pub memory_max: Option<String>,
+
+	/// The CPU bandwidth of the service, as "<quota> <period>" in microseconds (or just the quota, with the default
+	/// period), where the quota can be "max". Written to `cpu.max`.
+	pub cpu_max: Option<String>,
+
+	/// The most processes that the service can have. Written to `pids.max`.
+	pub pids_max: Option<u64>,
+}
+
+impl Resources {
+	fn validate(&self) -> ValidationResult {
+		let mut result = ValidationResult::new();
+		if let Some(memory_max) = &self.memory_max {
+			let number = memory_max.trim_end_matches(['K', 'M', 'G', 'T']);
+			if memory_max != "max" && (number.is_empty() || number.parse::<u64>().is_err()) {
+				result.add_error(ValidationError::new_fatal(&format!(
+					"Invalid memory_max: {}",
+					memory_max
+				)));
+			}
+		}
+
+		if let Some(cpu_max) = &self.cpu_max {
+			let mut parts = cpu_max.split(' ');
+			let quota = parts.next().unwrap_or_default();
+			let period = parts.next();
+			if (quota != "max" && quota.parse::<u64>().is_err())
+				|| period.is_some_and(|p| p.parse::<u64>().is_err())
+				|| parts.next().is_some()
+			{
+				result.add_error(ValidationError::new_fatal(&format!("Invalid cpu_max: {}", cpu_max)));
+			}
+		}
+
+		result
+	}
+}
+
+/// The isolation that a service is started with, which is set up in the service's process before it drops its
+/// privileges and executes the command. Everything it starts is isolated in the same way.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Sandbox {
+	/// Whether the service gets empty /tmp and /var/tmp directories of its own, rather than sharing them with the
+	/// rest of the system. What it writes to them is gone once it stops.
+	#[serde(default)]
+	pub private_tmp: bool,
+
+	/// Whether /usr and /boot are read-only for the service, so that it can't change the programs on the system.
+	#[serde(default)]
+	pub protect_system: bool,
+
+	/// Whether the service gets a network of its own, with only a loopback link, so that it can't reach the network
+	/// or any sockets bound to addresses outside of it.
+	#[serde(default)]
+	pub private_network: bool,
+
+	/// Whether the service (and everything it starts) is kept from gaining privileges, e.g. through setuid programs
+	/// or file capabilities.
+	#[serde(default)]
+	pub no_new_privileges: bool,
+}
+
+impl Sandbox {
+	/// Whether the service needs a mount namespace of its own.
+	pub fn needs_mount_namespace(&self) -> bool {
+		self.private_tmp || self.protect_system
+	}
+}
+
 /// A service definition.
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(deny_unknown_fields)]
@@ -169,6 +367,10 @@ pub struct ServiceConfig {
 	/// A description of the service.
 	pub description: Option<String>,
 
+	/// What starting the service does.
+	#[serde(default)]
+	pub kind: ServiceKind,
+
 	/// The service definition.
 	pub service: ServiceDefinition,
 
@@ -192,6 +394,18 @@ pub struct ServiceConfig {
 	#[serde(default)]
 	pub needs: Vec<Dependency>,
 
+	/// The devices that must exist before the service is started. Each is either a path to a device node
+	/// (e.g. "/dev/ttyS0", with relative paths being relative to /dev), or "subsystem:<subsystem>:<name pattern>"
+	/// to match any device in the subsystem whose name matches the glob pattern (e.g. "subsystem:net:eth*").
+	/// Arguments are templated into the devices, so "${TTY}" is valid.
+	#[serde(default)]
+	pub needs_device: Vec<String>,
+
+	/// The number of seconds to wait for the `needs_device` devices to appear. If they don't appear in time, the
+	/// service fails to start.
+	#[serde(default = "default_device_timeout")]
+	pub device_timeout: u64,
+
 	/// The permissions that the service will get when it is started.
 	#[serde(default)]
 	pub permissions: Permissions,
@@ -204,6 +418,19 @@ pub struct ServiceConfig {
 	#[serde(default)]
 	pub start_mode: StartMode,
 
+	/// The number of seconds to wait for the service to be considered started, according to its start mode. If it
+	/// isn't started in time, the service fails, along with anything waiting on it. Zero waits forever.
+	#[serde(default = "default_start_timeout")]
+	pub start_timeout: u64,
+
+	/// The limits on the resources that the service (and everything it starts) can use.
+	#[serde(default)]
+	pub resources: Resources,
+
+	/// How the service is isolated from the rest of the system.
+	#[serde(default)]
+	pub sandbox: Sandbox,
+
 	/// The result of validating this service.
 	#[serde(skip)]
 	pub errors: ValidationResult,
@@ -216,13 +443,139 @@ impl ServiceConfig {
 			result.add_error(ValidationError::new_fatal("Service name cannot be empty"));
 		}
 
+		if self.kind.needs_command() && self.service.command.trim().is_empty() {
+			result.add_error(ValidationError::new_fatal("Command cannot be empty"));
+		}
+
 		result.merge(self.service.validate());
 		result.merge(self.permissions.validate());
+		result.merge(self.resources.validate());
+
+		match self.kind {
+			ServiceKind::Process => {}
+			ServiceKind::Kmod => result.merge(self.validate_without_process("kmod")),
+			ServiceKind::Swap => {
+				result.merge(self.validate_without_process("swap"));
+				result.merge(self.validate_swap());
+			}
+			ServiceKind::Sysctl => result.merge(self.validate_without_process("sysctl")),
+			ServiceKind::Modules => result.merge(self.validate_without_process("modules")),
+			ServiceKind::Hostname => {
+				result.merge(self.validate_without_process("hostname"));
+				if self.service.command.split_whitespace().count() > 1 {
+					result.add_error(ValidationError::new_fatal("Hostnames cannot contain whitespace"));
+				}
+			}
+		}
+
+		for device in self.needs_device.iter() {
+			if DeviceSpec::parse(device).is_none() {
+				result.add_error(ValidationError::new_fatal(&format!("Invalid device: {}", device)));
+			}
+		}
 
 		self.errors = result.clone();
 
 		result.with_context(&format!("Service {}", self.name))
 	}
+
+	/// Warns about the options that don't mean anything for services that don't run a process, as there's no process
+	/// to apply them to.
+	fn validate_without_process(&self, kind: &str) -> ValidationResult {
+		let mut result = ValidationResult::new();
+		let ignored = [
+			("tty", self.service.tty.is_some()),
+			("working_directory", self.service.working_directory.is_some()),
+			("umask", self.service.umask.is_some()),
+			("environment", !self.service.environment.is_empty()),
+			("environment_files", !self.service.environment_files.is_empty()),
+			("runtime_directory", self.runtime_directory.is_some()),
+			("start_mode", self.start_mode != StartMode::Exec),
+			("resources", self.resources != Resources::default()),
+			("sandbox", self.sandbox != Sandbox::default()),
+			("permissions", self.permissions != Permissions::default()),
+		];
+
+		for (option, _) in ignored.iter().filter(|(_, set)| *set) {
+			result.add_error(ValidationError::new(&format!(
+				"{} is ignored by {} services",
+				option, kind
+			)));
+		}
+
+		result
+	}
+
+	/// Checks that a swap service has a swap area, and that its options are valid if they don't need templating.
+	fn validate_swap(&self) -> ValidationResult {
+		let mut result = ValidationResult::new();
+		let mut parts = self.service.command.split_whitespace();
+		if parts.next().is_none() {
+			result.add_error(ValidationError::new_fatal("Swap services need a swap area"));
+		}
+
+		let options = parts.collect::<Vec<_>>().join(",");
+		if !options.contains("${") {
+			let options = options.split(',').collect::<Vec<_>>();
+			if let Err(e) = SwapOptions::from_fstab(&options) {
+				result.add_error(ValidationError::new_fatal(&e.to_string()));
+			}
+		}
+
+		result
+	}
+
+	/// The service that uses the swap area of an fstab entry, named after where the area is, e.g. `swap-dev-sda2` for
+	/// `/dev/sda2`, or `swap-LABEL-swap` for `LABEL=swap`. Spaces in the source are escaped the way that they are in
+	/// fstab, so that the command can still be split on whitespace.
+	pub fn for_swap(entry: &FstabEntry) -> Self {
+		let name = format!(
+			"swap-{}",
+			entry.source.trim_start_matches('/').replace(['/', '=', ' '], "-")
+		);
+		let source = entry.source.replace('\\', "\\134").replace(' ', "\\040");
+		let command = if entry.options.is_empty() {
+			source
+		} else {
+			format!("{} {}", source, entry.options.join(","))
+		};
+
+		// Devices that are given by path are waited for, like the devices of any other service. Tags can only be
+		// matched once the device is there, so swap areas given by them fail if they aren't there when the service
+		// starts.
+		let needs_device = if entry.source.starts_with("/dev/") {
+			vec![entry.source.clone()]
+		} else {
+			Vec::new()
+		};
+
+		Self {
+			name,
+			description: Some(format!("Swap on {}", entry.source)),
+			kind: ServiceKind::Swap,
+			service: ServiceDefinition {
+				command,
+				arguments: Vec::new(),
+				working_directory: None,
+				umask: None,
+				tty: None,
+				environment: HashMap::new(),
+				environment_files: Vec::new(),
+				coalesce_logs: None,
+			},
+			wants: Vec::new(),
+			needs: Vec::new(),
+			needs_device,
+			device_timeout: default_device_timeout(),
+			permissions: Permissions::default(),
+			runtime_directory: None,
+			start_mode: StartMode::default(),
+			start_timeout: default_start_timeout(),
+			resources: Resources::default(),
+			sandbox: Sandbox::default(),
+			errors: ValidationResult::new(),
+		}
+	}
 }
 
 /// The definition of a sphere; a group of services that should be started at the same time.
@@ -0,0 +1,205 @@
+use std::{
+	collections::HashMap,
+	fmt::{self, Display, Formatter},
+	fs,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use bus::BusClient;
+use common::glob::Glob;
+use tokio::{sync::mpsc, time::sleep};
+
+/// The bus topic that udevd publishes device events to.
+const UDEV_EVENTS_TOPIC: &str = "udev_events";
+
+/// The directory that device nodes live in. Relative device paths are resolved against this.
+pub const DEV_ROOT: &str = "/dev";
+
+/// The prefix of device specs that match on the subsystem and name of a device, rather than its path.
+const SUBSYSTEM_PREFIX: &str = "subsystem:";
+
+/// How often to retry connecting to the bus while it isn't available.
+const BUS_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A device that a service needs to exist before it can start.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceSpec {
+	/// A device node (or symlink to one), e.g. `/dev/ttyS0`.
+	Path(PathBuf),
+
+	/// Any device in the given subsystem whose name matches a glob pattern, e.g. `subsystem:net:eth*`.
+	Subsystem { subsystem: String, pattern: String },
+}
+
+impl DeviceSpec {
+	/// Parses a device spec, either a path (relative paths are taken to be relative to /dev), or
+	/// `subsystem:<subsystem>:<name pattern>`.
+	pub fn parse(spec: &str) -> Option<Self> {
+		if let Some(rest) = spec.strip_prefix(SUBSYSTEM_PREFIX) {
+			let (subsystem, pattern) = rest.split_once(':')?;
+			if subsystem.is_empty() || pattern.is_empty() {
+				return None;
+			}
+
+			return Some(Self::Subsystem {
+				subsystem: subsystem.to_owned(),
+				pattern: pattern.to_owned(),
+			});
+		}
+
+		if spec.is_empty() {
+			return None;
+		}
+
+		Some(Self::Path(Path::new(DEV_ROOT).join(spec)))
+	}
+
+	/// Whether the device currently exists on the system.
+	pub fn exists(&self) -> bool {
+		match self {
+			Self::Path(path) => path.exists(),
+			Self::Subsystem { subsystem, pattern } => {
+				// Class devices (e.g. net, tty, block) are under /sys/class, but bus devices (e.g. usb) aren't.
+				let dirs = [
+					PathBuf::from("/sys/class").join(subsystem),
+					PathBuf::from("/sys/bus").join(subsystem).join("devices"),
+				];
+
+				let pattern = Glob::new(pattern);
+				dirs.iter()
+					.filter_map(|dir| fs::read_dir(dir).ok())
+					.flatten()
+					.flatten()
+					.any(|entry| pattern.matches(&entry.file_name().to_string_lossy()))
+			}
+		}
+	}
+
+	/// Whether the given udev event is for this device.
+	pub fn matches_event(&self, event: &HashMap<String, String>) -> bool {
+		match self {
+			Self::Path(path) => {
+				let devname = event.get("DEVNAME").map(|name| Path::new(DEV_ROOT).join(name));
+				let devlinks = event.get("DEVLINKS").map(String::as_str).unwrap_or("");
+
+				devname.as_deref() == Some(path.as_path()) || devlinks.split(' ').any(|link| Path::new(link) == path)
+			}
+			Self::Subsystem { subsystem, pattern } => {
+				if event.get("SUBSYSTEM") != Some(subsystem) {
+					return false;
+				}
+
+				// Network interfaces don't have device nodes, so their name comes from INTERFACE.
+				let name = event
+					.get("INTERFACE")
+					.or(event.get("DEVNAME"))
+					.or(event.get("DEVPATH"))
+					.map(|name| name.rsplit('/').next().unwrap_or(name));
+
+				name.is_some_and(|name| Glob::new(pattern).matches(name))
+			}
+		}
+	}
+}
+
+impl Display for DeviceSpec {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			Self::Path(path) => write!(f, "{}", path.display()),
+			Self::Subsystem { subsystem, pattern } => write!(f, "{}{}:{}", SUBSYSTEM_PREFIX, subsystem, pattern),
+		}
+	}
+}
+
+/// Subscribes to udev events on the bus, forwarding them into the returned channel. If the bus isn't running yet
+/// (it's started by us after all), this keeps retrying until it is.
+pub fn watch_udev_events() -> mpsc::Receiver<HashMap<String, String>> {
+	let (tx, rx) = mpsc::channel(100);
+	tokio::spawn(async move {
+		while !tx.is_closed() {
+			let client = match BusClient::new().await {
+				Ok(client) => client,
+				Err(_) => {
+					sleep(BUS_RETRY_INTERVAL).await;
+					continue;
+				}
+			};
+
+			// Replay the events we missed while we weren't connected.
+			let mut hook = match client.subscribe_with_replay(UDEV_EVENTS_TOPIC).await {
+				Ok(hook) => hook,
+				Err(_) => {
+					sleep(BUS_RETRY_INTERVAL).await;
+					continue;
+				}
+			};
+
+			let mut message = Vec::new();
+			while hook.read_message_into(&mut message).await.is_ok() {
+				let event = match serde_json::from_slice(&message) {
+					Ok(event) => event,
+					Err(_) => continue,
+				};
+
+				if tx.send(event).await.is_err() {
+					return;
+				}
+			}
+		}
+	});
+
+	rx
+}
+
+#[cfg(test)]
+mod test {
+	use std::{collections::HashMap, path::PathBuf};
+
+	use super::DeviceSpec;
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(
+			DeviceSpec::parse("/dev/ttyS0"),
+			Some(DeviceSpec::Path(PathBuf::from("/dev/ttyS0")))
+		);
+		assert_eq!(
+			DeviceSpec::parse("ttyS0"),
+			Some(DeviceSpec::Path(PathBuf::from("/dev/ttyS0")))
+		);
+		assert_eq!(
+			DeviceSpec::parse("subsystem:net:eth*"),
+			Some(DeviceSpec::Subsystem {
+				subsystem: String::from("net"),
+				pattern: String::from("eth*"),
+			})
+		);
+		assert_eq!(DeviceSpec::parse("subsystem:net"), None);
+		assert_eq!(DeviceSpec::parse("subsystem::eth0"), None);
+		assert_eq!(DeviceSpec::parse(""), None);
+	}
+
+	#[test]
+	fn test_matches_event() {
+		let event: HashMap<String, String> = [
+			("SUBSYSTEM", "tty"),
+			("DEVNAME", "ttyUSB0"),
+			("DEVPATH", "/devices/pci0000:00/usb1/1-1/ttyUSB0/tty/ttyUSB0"),
+			("DEVLINKS", "/dev/serial/by-id/usb-foo /dev/modem"),
+		]
+		.into_iter()
+		.map(|(k, v)| (k.to_owned(), v.to_owned()))
+		.collect();
+
+		assert!(DeviceSpec::parse("/dev/ttyUSB0").unwrap().matches_event(&event));
+		assert!(DeviceSpec::parse("/dev/modem").unwrap().matches_event(&event));
+		assert!(DeviceSpec::parse("subsystem:tty:ttyUSB*")
+			.unwrap()
+			.matches_event(&event));
+		assert!(!DeviceSpec::parse("subsystem:net:ttyUSB*")
+			.unwrap()
+			.matches_event(&event));
+		assert!(!DeviceSpec::parse("/dev/ttyS0").unwrap().matches_event(&event));
+	}
+}
@@ -0,0 +1,209 @@
+use std::{
+	fs::{self, DirBuilder},
+	io::{self, ErrorKind},
+	os::unix::fs::{symlink, DirBuilderExt, PermissionsExt},
+	path::{Path, PathBuf},
+};
+
+use mount::mounts::read_mounts;
+use nix::{
+	errno::Errno,
+	mount::{mount, MsFlags},
+	unistd::sethostname,
+};
+use thiserror::Error;
+
+use crate::cgroup::CGROUP_ROOT;
+
+/// The file that the hostname of the machine is read from.
+pub const HOSTNAME_PATH: &str = "/etc/hostname";
+
+/// A filesystem that the kernel provides, which everything else expects to be mounted.
+struct ApiFilesystem {
+	fs_type: &'static str,
+	target: &'static str,
+	flags: MsFlags,
+	data: Option<&'static str>,
+}
+
+/// The API filesystems that are mounted at boot, in the order that they're mounted, so that each one's mount point is
+/// on a filesystem that's already mounted.
+const API_FILESYSTEMS: [ApiFilesystem; 7] = [
+	ApiFilesystem {
+		fs_type: "proc",
+		target: "/proc",
+		flags: MsFlags::MS_NOSUID.union(MsFlags::MS_NODEV).union(MsFlags::MS_NOEXEC),
+		data: None,
+	},
+	ApiFilesystem {
+		fs_type: "sysfs",
+		target: "/sys",
+		flags: MsFlags::MS_NOSUID.union(MsFlags::MS_NODEV).union(MsFlags::MS_NOEXEC),
+		data: None,
+	},
+	ApiFilesystem {
+		fs_type: "devtmpfs",
+		target: "/dev",
+		flags: MsFlags::MS_NOSUID,
+		data: Some("mode=0755"),
+	},
+	ApiFilesystem {
+		fs_type: "devpts",
+		target: "/dev/pts",
+		flags: MsFlags::MS_NOSUID.union(MsFlags::MS_NOEXEC),
+		data: Some("mode=0620,ptmxmode=0666"),
+	},
+	ApiFilesystem {
+		fs_type: "tmpfs",
+		target: "/dev/shm",
+		flags: MsFlags::MS_NOSUID.union(MsFlags::MS_NODEV),
+		data: Some("mode=1777"),
+	},
+	ApiFilesystem {
+		fs_type: "tmpfs",
+		target: "/run",
+		flags: MsFlags::MS_NOSUID.union(MsFlags::MS_NODEV),
+		data: Some("mode=0755"),
+	},
+	ApiFilesystem {
+		fs_type: "cgroup2",
+		target: CGROUP_ROOT,
+		flags: MsFlags::MS_NOSUID.union(MsFlags::MS_NODEV).union(MsFlags::MS_NOEXEC),
+		data: None,
+	},
+];
+
+/// The directories that are created once the API filesystems are mounted, along with their modes.
+const DIRECTORIES: [(&str, u32); 2] = [("/run/lock", 0o1777), ("/tmp", 0o1777)];
+
+/// The symlinks in /dev that programs expect, and what they point to.
+const DEV_SYMLINKS: [(&str, &str); 4] = [
+	("/dev/fd", "/proc/self/fd"),
+	("/dev/stdin", "/proc/self/fd/0"),
+	("/dev/stdout", "/proc/self/fd/1"),
+	("/dev/stderr", "/proc/self/fd/2"),
+];
+
+#[derive(Debug, Error)]
+pub enum EarlyBootError {
+	#[error("failed to mount {0} at {1}: {2}")]
+	Mount(&'static str, &'static str, Errno),
+
+	#[error("failed to create {0}: {1}")]
+	Create(PathBuf, io::Error),
+
+	#[error("failed to read the hostname from {0}: {1}")]
+	ReadHostname(PathBuf, io::Error),
+
+	#[error("failed to set the hostname to {0}: {1}")]
+	SetHostname(String, Errno),
+}
+
+/// Sets up what qinit and its services expect to exist when it's started straight by the kernel, rather than after
+/// switching root: the API filesystems, the standard directories and symlinks, and the hostname. Anything that's
+/// already there is left alone. Failures don't stop the rest from being set up, so every one of them is returned.
+pub fn setup() -> Vec<EarlyBootError> {
+	let mut errors = Vec::new();
+	for filesystem in API_FILESYSTEMS.iter() {
+		if let Err(e) = mount_api_filesystem(filesystem) {
+			errors.push(e);
+		}
+	}
+
+	for (path, mode) in DIRECTORIES {
+		if let Err(e) = create_directory(Path::new(path), mode) {
+			errors.push(e);
+		}
+	}
+
+	for (link, target) in DEV_SYMLINKS {
+		match symlink(target, link) {
+			Err(e) if e.kind() != ErrorKind::AlreadyExists => {
+				errors.push(EarlyBootError::Create(PathBuf::from(link), e))
+			}
+			_ => {}
+		}
+	}
+
+	if let Err(e) = set_hostname(Path::new(HOSTNAME_PATH)) {
+		errors.push(e);
+	}
+
+	errors
+}
+
+/// Mounts the filesystem, unless something is already mounted where it goes.
+fn mount_api_filesystem(filesystem: &ApiFilesystem) -> Result<(), EarlyBootError> {
+	// Until /proc is mounted, nothing can be known to be mounted.
+	let mounted = read_mounts().is_ok_and(|mounts| {
+		mounts
+			.iter()
+			.any(|mount| mount.mount_point == Path::new(filesystem.target))
+	});
+	if mounted {
+		return Ok(());
+	}
+
+	create_directory(Path::new(filesystem.target), 0o755)?;
+	mount(
+		Some(filesystem.fs_type),
+		filesystem.target,
+		Some(filesystem.fs_type),
+		filesystem.flags,
+		filesystem.data,
+	)
+	.map_err(|e| EarlyBootError::Mount(filesystem.fs_type, filesystem.target, e))
+}
+
+/// Creates the directory with the given mode, if it doesn't exist.
+fn create_directory(path: &Path, mode: u32) -> Result<(), EarlyBootError> {
+	if path.is_dir() {
+		return Ok(());
+	}
+
+	let create = || {
+		DirBuilder::new().recursive(true).mode(mode).create(path)?;
+		// The mode given to mkdir is masked by the umask, which would drop the sticky and world-writable bits.
+		fs::set_permissions(path, fs::Permissions::from_mode(mode))
+	};
+
+	create().map_err(|e| EarlyBootError::Create(path.to_owned(), e))
+}
+
+/// Sets the hostname to the one in the given file, if there is one.
+pub fn set_hostname(path: &Path) -> Result<(), EarlyBootError> {
+	let contents = match fs::read_to_string(path) {
+		Ok(contents) => contents,
+		Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+		Err(e) => return Err(EarlyBootError::ReadHostname(path.to_owned(), e)),
+	};
+
+	match parse_hostname(&contents) {
+		Some(hostname) => sethostname(hostname).map_err(|e| EarlyBootError::SetHostname(hostname.to_owned(), e)),
+		None => Ok(()),
+	}
+}
+
+/// Parses the contents of /etc/hostname, which is the hostname on the first line that isn't blank or a comment.
+fn parse_hostname(contents: &str) -> Option<&str> {
+	contents
+		.lines()
+		.map(str::trim)
+		.find(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+#[cfg(test)]
+mod test {
+	use super::parse_hostname;
+
+	#[test]
+	fn test_parse_hostname() {
+		assert_eq!(parse_hostname("qos\n"), Some("qos"));
+		assert_eq!(
+			parse_hostname("# the name of the machine\n\n  build-01  \nignored\n"),
+			Some("build-01")
+		);
+		assert_eq!(parse_hostname(""), None);
+		assert_eq!(parse_hostname("# nothing here\n"), None);
+	}
+}
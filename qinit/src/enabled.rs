@@ -0,0 +1,200 @@
+use std::{
+	collections::BTreeMap,
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use common::qinit::ServiceInstance;
+use serde::{Deserialize, Serialize};
+use slog::warn;
+
+/// The directory that the enabled state of service instances is stored in, one file per instance.
+pub const ENABLED_DIRECTORY: &str = "/etc/qinit/enabled.d";
+
+const ENABLED_FILE_EXTENSION: &str = "toml";
+
+/// Whether a service instance is enabled, as stored in its file in the enabled directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnabledState {
+	/// The name of the service.
+	name: String,
+
+	/// The arguments of the instance.
+	#[serde(default)]
+	arguments: BTreeMap<String, String>,
+
+	/// Whether the instance is started on boot.
+	enabled: bool,
+}
+
+/// The enabled state of service instances. Instances in the boot sphere are started unless they're explicitly
+/// disabled, and instances outside of it are only started on boot if they're explicitly enabled.
+#[derive(Debug)]
+pub struct EnabledServices {
+	directory: PathBuf,
+	states: Vec<EnabledState>,
+}
+
+impl EnabledServices {
+	/// Loads the enabled state of every instance in the given directory. State that can't be read is logged and
+	/// skipped, so that it can't stop the system from booting.
+	pub fn load(logger: &slog::Logger, directory: &Path) -> Self {
+		let mut enabled = Self {
+			directory: directory.to_path_buf(),
+			states: Vec::new(),
+		};
+
+		let entries = match fs::read_dir(directory) {
+			Ok(entries) => entries,
+			// Nothing has been enabled or disabled yet.
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return enabled,
+			Err(e) => {
+				warn!(logger, "failed to read enabled services"; "path" => directory.display().to_string(), "error" => e.to_string());
+				return enabled;
+			}
+		};
+
+		for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+			if path.extension().is_none_or(|ext| ext != ENABLED_FILE_EXTENSION) {
+				continue;
+			}
+
+			let state = fs::read_to_string(&path)
+				.map_err(|e| e.to_string())
+				.and_then(|contents| toml::from_str(&contents).map_err(|e| e.to_string()));
+
+			match state {
+				Ok(state) => enabled.states.push(state),
+				Err(e) => warn!(logger, "invalid enabled state"; "path" => path.display().to_string(), "error" => e),
+			}
+		}
+
+		enabled
+	}
+
+	/// Returns whether the given (resolved) instance has been explicitly disabled.
+	pub fn is_disabled(&self, instance: &ServiceInstance) -> bool {
+		self.state(instance).is_some_and(|state| !state.enabled)
+	}
+
+	/// Returns the instances that have been explicitly enabled.
+	pub fn enabled(&self) -> impl Iterator<Item = ServiceInstance> + '_ {
+		self.states
+			.iter()
+			.filter(|state| state.enabled)
+			.map(|state| ServiceInstance {
+				name: state.name.clone(),
+				arguments: state.arguments.clone(),
+			})
+	}
+
+	/// Sets whether the given (resolved) instance is enabled, persisting it so that it survives a reboot.
+	pub fn set_enabled(&mut self, instance: &ServiceInstance, enabled: bool) -> io::Result<()> {
+		let state = EnabledState {
+			name: instance.name.clone(),
+			arguments: instance.arguments.clone(),
+			enabled,
+		};
+
+		let contents = toml::to_string(&state).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+		// Write to a temporary file and rename it, so that a crash can't leave a half written file behind.
+		fs::create_dir_all(&self.directory)?;
+		let path = self.directory.join(file_name(instance));
+		let temp_path = path.with_extension("tmp");
+		fs::write(&temp_path, contents)?;
+		fs::rename(&temp_path, &path)?;
+
+		self.states
+			.retain(|state| state.name != instance.name || state.arguments != instance.arguments);
+		self.states.push(state);
+		Ok(())
+	}
+
+	fn state(&self, instance: &ServiceInstance) -> Option<&EnabledState> {
+		self.states
+			.iter()
+			.find(|state| state.name == instance.name && state.arguments == instance.arguments)
+	}
+}
+
+/// Returns the name of the file that the state of the instance is stored in, e.g. `getty@TTY=%2Fdev%2Ftty1.toml`.
+/// Anything that could be confused with the seperators, or escape the directory, is percent encoded.
+fn file_name(instance: &ServiceInstance) -> String {
+	let mut name = escape(&instance.name);
+	for (i, (key, value)) in instance.arguments.iter().enumerate() {
+		name.push(if i == 0 { '@' } else { ',' });
+		name.push_str(&format!("{}={}", escape(key), escape(value)));
+	}
+
+	format!("{}.{}", name, ENABLED_FILE_EXTENSION)
+}
+
+fn escape(s: &str) -> String {
+	let mut escaped = String::new();
+	for byte in s.bytes() {
+		match byte {
+			b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => escaped.push(byte as char),
+			_ => escaped.push_str(&format!("%{:02X}", byte)),
+		}
+	}
+
+	escaped
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::BTreeMap;
+
+	use common::{qinit::ServiceInstance, testing::TempDir};
+	use slog::{o, Discard, Logger};
+
+	use super::{file_name, EnabledServices};
+
+	fn instance(name: &str, arguments: &[(&str, &str)]) -> ServiceInstance {
+		ServiceInstance {
+			name: name.to_owned(),
+			arguments: arguments
+				.iter()
+				.map(|(k, v)| (k.to_string(), v.to_string()))
+				.collect::<BTreeMap<_, _>>(),
+		}
+	}
+
+	#[test]
+	fn test_file_name() {
+		assert_eq!(file_name(&instance("udevd", &[])), "udevd.toml");
+		assert_eq!(
+			file_name(&instance("getty", &[("TTY", "/dev/tty1"), ("Baud", "9600")])),
+			"getty@Baud=9600,TTY=%2Fdev%2Ftty1.toml"
+		);
+		assert_eq!(file_name(&instance("..", &[])), "%2E%2E.toml");
+	}
+
+	#[test]
+	fn test_enabled_services() {
+		let logger = Logger::root(Discard, o!());
+		let root = TempDir::new("qinit-enabled").unwrap();
+		let directory = root.join("enabled");
+		let tty1 = instance("getty", &[("TTY", "/dev/tty1")]);
+		let tty2 = instance("getty", &[("TTY", "/dev/tty2")]);
+
+		let mut enabled = EnabledServices::load(&logger, &directory);
+		assert!(!enabled.is_disabled(&tty1));
+		assert_eq!(enabled.enabled().count(), 0);
+
+		enabled.set_enabled(&tty1, false).unwrap();
+		enabled.set_enabled(&tty2, true).unwrap();
+
+		// The state survives being reloaded, as it would on reboot.
+		let mut enabled = EnabledServices::load(&logger, &directory);
+		assert!(enabled.is_disabled(&tty1));
+		assert!(!enabled.is_disabled(&tty2));
+		assert_eq!(enabled.enabled().collect::<Vec<_>>(), vec![tty2.clone()]);
+
+		enabled.set_enabled(&tty2, false).unwrap();
+		assert!(enabled.is_disabled(&tty2));
+		assert_eq!(enabled.enabled().count(), 0);
+	}
+}
@@ -0,0 +1,79 @@
+use std::{fs::read_to_string, io::ErrorKind};
+
+use anyhow::{anyhow, Context, Result};
+
+/// Reads the variables from an environment file. A path prefixed with `-` is skipped if the file doesn't exist,
+/// rather than failing.
+pub fn read_environment_file(path: &str) -> Result<Vec<(String, String)>> {
+	let (path, optional) = match path.strip_prefix('-') {
+		Some(path) => (path, true),
+		None => (path, false),
+	};
+
+	match read_to_string(path) {
+		Ok(contents) => parse_environment(&contents).with_context(|| format!("invalid environment file: {}", path)),
+		Err(e) if optional && e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+		Err(e) => Err(e).with_context(|| format!("failed to read environment file: {}", path)),
+	}
+}
+
+/// Parses the contents of an environment file, which has a `KEY=VALUE` pair on each line. Blank lines and lines
+/// starting with `#` are ignored, and values can be wrapped in single or double quotes to keep leading or trailing
+/// whitespace.
+fn parse_environment(contents: &str) -> Result<Vec<(String, String)>> {
+	let mut variables = Vec::new();
+	for (number, line) in contents.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		let (key, value) = line
+			.split_once('=')
+			.ok_or_else(|| anyhow!("line {}: expected KEY=VALUE", number + 1))?;
+
+		let key = key.trim();
+		if !is_valid_key(key) {
+			return Err(anyhow!("line {}: invalid variable name: {}", number + 1, key));
+		}
+
+		let value = value.trim();
+		let value = ['"', '\'']
+			.iter()
+			.find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+			.unwrap_or(value);
+
+		variables.push((key.to_owned(), value.to_owned()));
+	}
+
+	Ok(variables)
+}
+
+/// Whether the given name can be used as the name of an environment variable.
+pub fn is_valid_key(key: &str) -> bool {
+	!key.is_empty() && !key.contains(['=', '\0']) && !key.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod test {
+	use super::parse_environment;
+
+	#[test]
+	fn test_parse_environment() {
+		let contents = "# A comment\n\nFOO=bar\n  BAZ = \" spaced \" \nEMPTY=\nURL=http://host/?a=b\nQUOTED='it'\n";
+		assert_eq!(
+			parse_environment(contents).unwrap(),
+			vec![
+				(String::from("FOO"), String::from("bar")),
+				(String::from("BAZ"), String::from(" spaced ")),
+				(String::from("EMPTY"), String::new()),
+				(String::from("URL"), String::from("http://host/?a=b")),
+				(String::from("QUOTED"), String::from("it")),
+			]
+		);
+
+		assert!(parse_environment("NOVALUE").is_err());
+		assert!(parse_environment("=value").is_err());
+		assert!(parse_environment("TWO WORDS=value").is_err());
+	}
+}
@@ -1,37 +1,111 @@
-#![feature(extract_if)]
+mod boot;
+mod cgroup;
 mod config;
+mod devices;
+mod early;
+mod enabled;
+mod environment;
+mod sandbox;
 mod service;
+mod sysconfig;
 
 use std::{
 	collections::HashMap,
+	future::Future,
 	io::{self, stderr},
-	path::PathBuf,
+	net::SocketAddr,
+	os::unix::fs::MetadataExt,
+	panic::{self, AssertUnwindSafe},
+	path::{Path, PathBuf},
 	process::ExitCode,
 	sync::Arc,
 	time::Duration,
 };
 
 use anyhow::{anyhow, Result};
+use auth::LoginSession;
+use boot::{choose_sphere, confirm_rescue, emergency_shell, rescue_shell, BootOptions};
 use clap::{Arg, Command};
-use common::obs::assemble_logger;
-use config::{load_config, Dependency};
-use control::listen::{Action, ActionFactory, ControlSocket};
-use nix::unistd::Pid;
+use common::{
+	cmdline::KernelCmdline,
+	obs::{self, assemble_logger_with_level},
+	qinit::{QinitRequest, ServiceInstance, ServiceStatus, QINIT_CONTROL_SOCKET},
+};
+use config::{load_config, Config, Dependency, ValidationError, SWAP_SPHERE};
+use control::{
+	listen::{Action, ActionFactory, ControlSocket, RemoteControlSocket, RequestContext},
+	protocol::{write_frame, ErrorKind, ErrorReply, Reply},
+};
+use enabled::{EnabledServices, ENABLED_DIRECTORY};
+use mount::fstab::FSTAB_PATH;
+use nix::unistd::{getpid, Pid};
 use service::{Service, ServiceManager};
-use slog::{error, info};
-use tokio::{fs::create_dir_all, net::unix::UCred, time::sleep};
+use slog::{debug, error, info, warn, Level};
+use thiserror::Error;
+use tokio::{fs::create_dir_all, sync::Mutex, time::sleep};
+
+/// The file holding the token that remote control requests have to carry. It's read from a file, rather than the
+/// kernel command line, so that it isn't readable by everyone through /proc/cmdline.
+const REMOTE_TOKEN_FILE: &str = "/etc/qinit/remote-token";
+
+/// How long to wait before restarting a supervision task that panicked, so that one that keeps panicking doesn't spin.
+const SUPERVISOR_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+fn main() -> ExitCode {
+	let runtime = tokio::runtime::Runtime::new().expect("failed to start the tokio runtime");
+	match panic::catch_unwind(AssertUnwindSafe(|| runtime.block_on(run()))) {
+		Ok(code) => code,
+		// The panic hook has already logged the panic. The services that are running carry on being supervised by
+		// the tasks on the runtime, but there's nothing more that qinit itself can do.
+		Err(_) if getpid().as_raw() == 1 => emergency_shell(),
+		Err(payload) => panic::resume_unwind(payload),
+	}
+}
 
-#[tokio::main]
-async fn main() -> ExitCode {
+async fn run() -> ExitCode {
 	let matches = Command::new("qinit")
-		.arg(Arg::new("socket").num_args(1).default_value("/run/qinit/control.sock"))
+		.arg(
+			Arg::new("socket")
+				.long("socket")
+				.num_args(1)
+				.default_value(QINIT_CONTROL_SOCKET),
+		)
+		.arg(
+			// The kernel passes the parameters it doesn't understand to init, which are read from /proc/cmdline
+			// instead so that they survive switching root.
+			Arg::new("kernel_args").num_args(0..).hide(true),
+		)
 		.get_matches();
 
-	let logger = assemble_logger(stderr());
+	// Without switching root first, nothing has mounted /proc, /sys, or /dev yet, which everything (starting with
+	// reading the kernel command line) needs.
+	let early_errors = if getpid().as_raw() == 1 {
+		early::setup()
+	} else {
+		Vec::new()
+	};
+
+	let (mut boot, cmdline_error) = match KernelCmdline::read() {
+		Ok(cmdline) => (BootOptions::from_cmdline(&cmdline), None),
+		Err(e) => (BootOptions::default(), Some(e)),
+	};
+
+	let level = if boot.debug { Level::Debug } else { Level::Info };
+	let logger = assemble_logger_with_level(stderr(), level);
+	obs::install_panic_hook();
+	for e in early_errors {
+		error!(logger, "failed to set up early boot"; "error" => e.to_string());
+	}
+
+	if let Some(e) = cmdline_error {
+		warn!(logger, "failed to read kernel command line, using the default boot options"; "error" => e.to_string());
+	}
+
+	info!(logger, "booting"; "sphere" => &boot.sphere, "debug" => boot.debug, "rescue" => boot.rescue, "interactive" => boot.interactive, "remote" => boot.remote.map(|addr| addr.to_string()));
 
 	let config_directories = ["./configs/services", "/etc/qinit/services"].map(PathBuf::from);
 
-	let (config, errors) = load_config(config_directories);
+	let (config, errors) = load_config(config_directories, Path::new(FSTAB_PATH));
 	if errors.is_error() {
 		error!(logger, "Error loading configuration"; "errors" => format!("{:?}", errors));
 	}
@@ -44,23 +118,95 @@ async fn main() -> ExitCode {
 		}
 	}
 
+	let config = Arc::new(config);
+	let enabled = Arc::new(Mutex::new(EnabledServices::load(&logger, Path::new(ENABLED_DIRECTORY))));
 	let manager = Arc::new(ServiceManager::new(logger.clone()));
 
 	let socket_path: &String = matches.get_one("socket").unwrap();
-	if let Err(e) = open_control_socket(socket_path, manager.clone()).await {
+	let factory = ControlFactory::new(manager.clone(), config.clone(), enabled.clone());
+	if let Err(e) = open_control_socket(socket_path, factory.clone(), logger.clone()).await {
 		error!(logger, "failed to open control socket"; "error" => e);
 		return ExitCode::FAILURE;
 	}
 
-	start_sphere(&logger, manager.clone(), &config, "user").await.unwrap();
+	// Failing to listen remotely isn't fatal, as the machine can still be managed locally.
+	if let Some(addr) = boot.remote {
+		match open_remote_control_socket(addr, factory, logger.clone()).await {
+			Ok(()) => info!(logger, "listening for remote control requests"; "addr" => addr.to_string()),
+			Err(e) => {
+				error!(logger, "failed to open remote control socket"; "addr" => addr.to_string(), "error" => e.to_string())
+			}
+		}
+	}
+
+	if boot.rescue {
+		rescue_shell(&logger).await;
+	}
+
+	if boot.interactive {
+		boot.sphere = choose_sphere(&logger, &boot.sphere).await;
+	}
+
+	// Swap areas are used alongside the boot sphere, waiting for their devices like any other service, so that
+	// services that need a lot of memory can wait on them.
+	if config.get_sphere(SWAP_SPHERE).is_some() {
+		let started = start_sphere(&logger, manager.clone(), &config, &*enabled.lock().await, SWAP_SPHERE).await;
+		if let Err(e) = started {
+			error!(logger, "failed to start swap sphere"; "error" => e.to_string());
+		}
+	}
+
+	// The enabled services aren't kept locked while in a rescue shell, so that they can be changed from it.
+	let started = start_sphere(&logger, manager.clone(), &config, &*enabled.lock().await, &boot.sphere).await;
+	if let Err(e) = started {
+		error!(logger, "failed to start boot sphere"; "sphere" => &boot.sphere, "error" => e.to_string());
+		if confirm_rescue(&logger).await {
+			rescue_shell(&logger).await;
+		}
+	}
 
-	sleep(Duration::from_secs(5)).await;
+	start_enabled(&logger, manager.clone(), &config, &*enabled.lock().await).await;
+
+	tokio::join!(
+		supervise(&logger, "reaper", || {
+			let manager = manager.clone();
+			async move { manager.reaper().await }
+		}),
+		supervise(&logger, "device_watcher", || {
+			let manager = manager.clone();
+			async move { manager.device_watcher().await }
+		}),
+		supervise(&logger, "start_watcher", || {
+			let manager = manager.clone();
+			async move { manager.start_watcher().await }
+		}),
+	);
 
-	manager.reaper().await;
 	ExitCode::SUCCESS
 }
 
-async fn open_control_socket(socket_path: &str, manager: Arc<ServiceManager>) -> io::Result<()> {
+/// Runs a supervision task until it finishes. If it panics, PID 1 can't die with it, so a rescue shell is started on
+/// the console to look into what happened, and the task is restarted once the shell exits.
+async fn supervise<F, Fut>(logger: &slog::Logger, name: &'static str, task: F)
+where
+	F: Fn() -> Fut,
+	Fut: Future<Output = ()> + Send + 'static,
+{
+	loop {
+		match tokio::spawn(task()).await {
+			Err(e) if e.is_panic() => {
+				let payload = e.into_panic();
+				error!(logger, "supervision task panicked, starting a rescue shell"; "task" => name, "message" => obs::panic_message(&*payload));
+				rescue_shell(logger).await;
+				sleep(SUPERVISOR_RESTART_DELAY).await;
+				info!(logger, "restarting supervision task"; "task" => name);
+			}
+			_ => return,
+		}
+	}
+}
+
+async fn open_control_socket(socket_path: &str, factory: ControlFactory, logger: slog::Logger) -> io::Result<()> {
 	let socket_path = PathBuf::from(socket_path);
 
 	if let Some(parent) = socket_path.parent() {
@@ -69,68 +215,291 @@ async fn open_control_socket(socket_path: &str, manager: Arc<ServiceManager>) ->
 		}
 	}
 
-	let socket = ControlSocket::open(&socket_path, ControlFactory::new(manager))?;
+	let socket = ControlSocket::open(&socket_path, factory, logger)?;
+
+	tokio::spawn(async move { socket.listen().await });
+	Ok(())
+}
+
+/// Listens for control requests over TCP on the given address, authenticated with the token in `REMOTE_TOKEN_FILE`.
+async fn open_remote_control_socket(addr: SocketAddr, factory: ControlFactory, logger: slog::Logger) -> io::Result<()> {
+	// Anyone that can read the token has root over the network, so refuse to use one that isn't kept private.
+	let metadata = tokio::fs::metadata(REMOTE_TOKEN_FILE).await?;
+	if metadata.uid() != 0 || metadata.mode() & 0o077 != 0 {
+		return Err(io::Error::new(
+			io::ErrorKind::PermissionDenied,
+			format!(
+				"{} has to be owned by root, and not readable by anyone else",
+				REMOTE_TOKEN_FILE
+			),
+		));
+	}
+
+	let token = tokio::fs::read_to_string(REMOTE_TOKEN_FILE).await?;
+	let socket = RemoteControlSocket::open(addr, token.trim(), factory, logger).await?;
 
 	tokio::spawn(async move { socket.listen().await });
 	Ok(())
 }
 
-enum ControlActionType {
-	Ready,
+/// Errors that can occur when handling a control request.
+#[derive(Debug, Error)]
+enum ControlError {
+	#[error("unsupported action: {0}")]
+	UnknownAction(String),
+
+	#[error("missing argument: {0}")]
+	MissingArgument(&'static str),
+
+	#[error("invalid service instance: {0}")]
+	InvalidInstance(#[from] ValidationError),
+
+	#[error("only root can {0} services")]
+	PermissionDenied(&'static str),
+
+	#[error("only local services can report that they're running")]
+	NotLocal,
+
+	#[error("failed to save enabled state: {0}")]
+	IOError(#[from] io::Error),
+
+	#[error("failed to start {0}: {1}")]
+	StartFailed(ServiceInstance, anyhow::Error),
+
+	#[error("failed to stop {0}: {1}")]
+	StopFailed(ServiceInstance, anyhow::Error),
+}
+
+impl ControlError {
+	fn kind(&self) -> ErrorKind {
+		match self {
+			ControlError::UnknownAction(_) => ErrorKind::UnknownRequest,
+			ControlError::MissingArgument(_) | ControlError::InvalidInstance(_) => ErrorKind::InvalidRequest,
+			ControlError::PermissionDenied(_) | ControlError::NotLocal => ErrorKind::PermissionDenied,
+			ControlError::IOError(_) | ControlError::StartFailed(..) | ControlError::StopFailed(..) => {
+				ErrorKind::Internal
+			}
+		}
+	}
+}
+
+impl From<ControlError> for ErrorReply {
+	fn from(value: ControlError) -> Self {
+		ErrorReply::new(value.kind(), value)
+	}
 }
 
 struct ControlAction {
-	ty: ControlActionType,
+	request: QinitRequest,
 	manager: Arc<ServiceManager>,
+	config: Arc<Config>,
+	enabled: Arc<Mutex<EnabledServices>>,
 }
 
 impl ControlAction {
-	fn new(ty: ControlActionType, manager: Arc<ServiceManager>) -> Self {
-		Self { ty, manager }
+	fn new(
+		request: QinitRequest,
+		manager: Arc<ServiceManager>,
+		config: Arc<Config>,
+		enabled: Arc<Mutex<EnabledServices>>,
+	) -> Self {
+		Self {
+			request,
+			manager,
+			config,
+			enabled,
+		}
+	}
+
+	/// The statuses that the request asks for, if it's a request for statuses.
+	async fn statuses(&self) -> Option<Vec<ServiceStatus>> {
+		match &self.request {
+			QinitRequest::List => Some(self.manager.statuses(None).await),
+			QinitRequest::Status(instance) => {
+				let arguments = instance.arguments.clone().into_iter().collect();
+				Some(self.manager.statuses(Some((&instance.name, &arguments))).await)
+			}
+			_ => None,
+		}
+	}
+
+	/// Starts, stops, enables, or disables a service instance.
+	async fn manage(&self, ctx: &RequestContext) -> Result<(), ControlError> {
+		match &self.request {
+			QinitRequest::Running | QinitRequest::List | QinitRequest::Status(_) => Ok(()),
+			QinitRequest::Start(instance) => {
+				info!(ctx.logger, "starting service on request"; "service" => instance.to_string());
+				let arguments = instance.arguments.clone().into_iter().collect();
+				// The service is started on behalf of the login session that asked for it, if any.
+				let session = ctx.peer.pid().and_then(LoginSession::of_process);
+				start_service(
+					self.manager.clone(),
+					&self.config,
+					&instance.name,
+					arguments,
+					None,
+					None,
+					session,
+				)
+				.await
+				.map_err(|e| ControlError::StartFailed(instance.clone(), e))
+			}
+			QinitRequest::Stop(instance) => {
+				info!(ctx.logger, "stopping service on request"; "service" => instance.to_string());
+				let arguments = instance.arguments.clone().into_iter().collect();
+				self.manager
+					.stop(&instance.name, &arguments)
+					.await
+					.map_err(|e| ControlError::StopFailed(instance.clone(), e))
+			}
+			QinitRequest::Enable(instance) => {
+				info!(ctx.logger, "enabling service"; "service" => instance.to_string());
+				Ok(self.enabled.lock().await.set_enabled(instance, true)?)
+			}
+			QinitRequest::Disable(instance) => {
+				info!(ctx.logger, "disabling service"; "service" => instance.to_string());
+				Ok(self.enabled.lock().await.set_enabled(instance, false)?)
+			}
+		}
 	}
 }
 
 impl Action for ControlAction {
-	type Error = anyhow::Error;
+	type Error = ControlError;
+
+	async fn authorize(&self, ctx: &RequestContext) -> Result<(), Self::Error> {
+		let verb = match self.request {
+			// Running is reported by the process of a service, so it has to be one on this machine.
+			QinitRequest::Running if ctx.peer.pid().is_none() => return Err(ControlError::NotLocal),
+			// Anyone can see what's running.
+			QinitRequest::Running | QinitRequest::List | QinitRequest::Status(_) => return Ok(()),
+			QinitRequest::Start(_) => "start",
+			QinitRequest::Stop(_) => "stop",
+			QinitRequest::Enable(_) => "enable",
+			QinitRequest::Disable(_) => "disable",
+		};
+
+		if ctx.peer.uid() != 0 {
+			return Err(ControlError::PermissionDenied(verb));
+		}
+
+		Ok(())
+	}
 
 	async fn run<
 		R: tokio::io::AsyncBufRead + Unpin + Send + 'static,
 		W: tokio::io::AsyncWrite + Unpin + Send + 'static,
 	>(
 		self,
-		peer: UCred,
+		ctx: RequestContext,
 		_reader: R,
-		_writer: W,
+		mut writer: W,
 	) -> Result<(), Self::Error> {
-		match self.ty {
-			ControlActionType::Ready => {
-				let pid = peer.pid().expect("failed to get pid");
-				self.manager.mark_service_running(Pid::from_raw(pid)).await;
-				Ok(())
+		if let QinitRequest::Running = self.request {
+			let pid = ctx.peer.pid().expect("BUG: running was authorized without a pid");
+			debug!(ctx.logger, "service reported running"; "pid" => pid);
+			self.manager.mark_service_running(Pid::from_raw(pid)).await;
+			return Ok(());
+		}
+
+		if let Some(statuses) = self.statuses().await {
+			let reply: Reply<Vec<ServiceStatus>> = Ok(statuses);
+			if let Err(e) = write_frame(&mut writer, &reply).await {
+				warn!(ctx.logger, "failed to send statuses"; "error" => e.to_string());
 			}
+
+			return Ok(());
 		}
+
+		// Managing a service happens after the request is accepted, so its outcome is sent in a second reply.
+		let result = self.manage(&ctx).await;
+		let reply: Reply = match &result {
+			Ok(()) => Ok(()),
+			Err(e) => Err(ErrorReply::new(e.kind(), e).with_request_id(ctx.id)),
+		};
+
+		if let Err(e) = write_frame(&mut writer, &reply).await {
+			warn!(ctx.logger, "failed to send result"; "error" => e.to_string());
+		}
+
+		result
 	}
 }
 
 #[derive(Clone)]
 struct ControlFactory {
 	manager: Arc<ServiceManager>,
+	config: Arc<Config>,
+	enabled: Arc<Mutex<EnabledServices>>,
 }
 
 impl ActionFactory for ControlFactory {
 	type Action = ControlAction;
+	type Request = QinitRequest;
+
+	fn build(&self, action: &str, args: &[(&str, &str)]) -> Result<Self::Action, <Self::Action as Action>::Error> {
+		match action {
+			"running" => return self.build_request(QinitRequest::Running),
+			"list" => return self.build_request(QinitRequest::List),
+			_ => {}
+		}
+
+		let name = args
+			.iter()
+			.find(|(key, _)| *key == "SERVICE")
+			.map(|(_, value)| value.to_string())
+			.ok_or(ControlError::MissingArgument("SERVICE"))?;
+
+		let instance = ServiceInstance {
+			name,
+			arguments: args
+				.iter()
+				.filter(|(key, _)| !matches!(*key, "ACTION" | "SERVICE"))
+				.map(|(key, value)| (key.to_string(), value.to_string()))
+				.collect(),
+		};
 
-	fn build(&self, action: &str, _args: &[(&str, &str)]) -> Result<Self::Action, <Self::Action as Action>::Error> {
 		match action {
-			"running" => Ok(ControlAction::new(ControlActionType::Ready, self.manager.clone())),
-			_ => Err(anyhow!("unsupported action: {}", action)),
+			"start" => self.build_request(QinitRequest::Start(instance)),
+			"stop" => self.build_request(QinitRequest::Stop(instance)),
+			"enable" => self.build_request(QinitRequest::Enable(instance)),
+			"disable" => self.build_request(QinitRequest::Disable(instance)),
+			"status" => self.build_request(QinitRequest::Status(instance)),
+			_ => Err(ControlError::UnknownAction(action.to_owned())),
 		}
 	}
+
+	fn build_request(&self, request: QinitRequest) -> Result<Self::Action, <Self::Action as Action>::Error> {
+		// Instances are resolved up front, so that invalid ones are rejected before the request is accepted, and so
+		// that the enabled state matches the instances in spheres however the arguments were written.
+		let resolve = |instance: ServiceInstance| self.config.resolve_instance(&instance.name, &instance.arguments);
+		let request = match request {
+			QinitRequest::Running => QinitRequest::Running,
+			QinitRequest::List => QinitRequest::List,
+			// Statuses are filtered by whatever arguments are given, so they don't need to be complete.
+			QinitRequest::Status(instance) => QinitRequest::Status(instance),
+			QinitRequest::Start(instance) => QinitRequest::Start(resolve(instance)?),
+			QinitRequest::Stop(instance) => QinitRequest::Stop(resolve(instance)?),
+			QinitRequest::Enable(instance) => QinitRequest::Enable(resolve(instance)?),
+			QinitRequest::Disable(instance) => QinitRequest::Disable(resolve(instance)?),
+		};
+
+		Ok(ControlAction::new(
+			request,
+			self.manager.clone(),
+			self.config.clone(),
+			self.enabled.clone(),
+		))
+	}
 }
 
 impl ControlFactory {
-	fn new(manager: Arc<ServiceManager>) -> Self {
-		ControlFactory { manager }
+	fn new(manager: Arc<ServiceManager>, config: Arc<Config>, enabled: Arc<Mutex<EnabledServices>>) -> Self {
+		ControlFactory {
+			manager,
+			config,
+			enabled,
+		}
 	}
 }
 
@@ -138,6 +507,7 @@ async fn start_sphere(
 	logger: &slog::Logger,
 	manager: Arc<ServiceManager>,
 	config: &config::Config,
+	enabled: &EnabledServices,
 	sphere_name: &str,
 ) -> anyhow::Result<()> {
 	info!(logger, "queuing sphere"; "name" => sphere_name);
@@ -177,13 +547,21 @@ async fn start_sphere(
 
 			let mut new_deps = Vec::new();
 			for dep in startable.services.iter() {
+				let instance = config.resolve_instance(&dep.name, &dep.arguments)?;
+				if enabled.is_disabled(&instance) {
+					// Disabled services aren't started, so nothing after them in the sphere can wait on them either.
+					info!(logger, "skipping disabled service"; "service" => instance.to_string());
+					continue;
+				}
+
 				start_service(
-					logger,
 					manager.clone(),
 					config,
 					&dep.name,
 					dep.arguments.clone(),
 					Some(&deps),
+					Some(&startable.name),
+					None,
 				)
 				.await?;
 
@@ -201,14 +579,38 @@ async fn start_sphere(
 	Ok(())
 }
 
+/// Starts the service instances that have been explicitly enabled, outside of the boot sphere.
+async fn start_enabled(
+	logger: &slog::Logger,
+	manager: Arc<ServiceManager>,
+	config: &config::Config,
+	enabled: &EnabledServices,
+) {
+	for instance in enabled.enabled() {
+		// Services can be removed after they're enabled, so this isn't fatal.
+		if let Err(e) = config.resolve_instance(&instance.name, &instance.arguments) {
+			warn!(logger, "not starting invalid enabled service"; "service" => instance.to_string(), "error" => e.to_string());
+			continue;
+		}
+
+		let arguments = instance.arguments.clone().into_iter().collect();
+		if let Err(e) = start_service(manager.clone(), config, &instance.name, arguments, None, None, None).await {
+			error!(logger, "failed to start enabled service"; "service" => instance.to_string(), "error" => e.to_string());
+		}
+	}
+}
+
 /// Starts a service and its dependencies, returning an error if the service can't be started due to dependency issues.
+/// The services are noted as part of the given sphere, if they're being started for one, and the given login session, if
+/// they're being started on behalf of one.
 async fn start_service(
-	_logger: &slog::Logger,
 	manager: Arc<ServiceManager>,
 	config: &config::Config,
 	service_name: &str,
 	service_args: HashMap<String, String>,
 	extra_deps: Option<&Vec<Dependency>>,
+	sphere: Option<&str>,
+	session: Option<LoginSession>,
 ) -> anyhow::Result<()> {
 	let service_config = match config.get_service_config(service_name) {
 		Some(conf) => conf,
@@ -227,7 +629,9 @@ async fn start_service(
 		{
 			continue;
 		}
-		let dep_service = Service::new(service_config, args);
+		let dep_service = Service::new(service_config, args)
+			.with_sphere(sphere)
+			.with_session(session);
 
 		let mut dependencies: Vec<Service> = Vec::new();
 		for dep in service_config.needs.iter().chain(extra_deps) {
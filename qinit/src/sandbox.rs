@@ -0,0 +1,162 @@
+use std::{io, path::Path};
+
+use anyhow::{Context, Result};
+use mount::mounts::{read_mounts, Mount};
+use netlink::{
+	rtnetlink::{
+		Interface, InterfaceAttributes, InterfaceFlags, InterfaceInfoMessage, NetlinkRoute, RTNetlink, RTNetlinkGroups,
+	},
+	NetlinkError, NetlinkSocket,
+};
+use nix::{
+	mount::{mount, MsFlags},
+	sched::{unshare, CloneFlags},
+	sys::prctl::set_no_new_privs,
+};
+
+use crate::config::Sandbox;
+
+/// The directories that services with `private_tmp` get empty directories of their own in place of.
+const PRIVATE_TMP_DIRECTORIES: [&str; 2] = ["/tmp", "/var/tmp"];
+
+/// The directories that services with `protect_system` can't write to.
+const PROTECTED_DIRECTORIES: [&str; 2] = ["/usr", "/boot"];
+
+/// The index of the loopback link, which is the first link of every network namespace.
+const LOOPBACK_INDEX: i32 = 1;
+
+/// Isolates the calling process as the sandbox asks. This needs root, so it has to happen before the process drops
+/// its privileges, and only affects the process and the ones it starts, so it has to happen after forking.
+pub fn apply(sandbox: &Sandbox) -> Result<()> {
+	let mut flags = CloneFlags::empty();
+	if sandbox.needs_mount_namespace() {
+		flags |= CloneFlags::CLONE_NEWNS;
+	}
+
+	if sandbox.private_network {
+		flags |= CloneFlags::CLONE_NEWNET;
+	}
+
+	if !flags.is_empty() {
+		unshare(flags).with_context(|| "failed to create namespaces")?;
+	}
+
+	if sandbox.needs_mount_namespace() {
+		// The new namespace starts with copies of qinit's mounts, which share mount events with them if the root is
+		// shared, so stop the service's mounts from leaking back out.
+		mount(
+			None::<&str>,
+			"/",
+			None::<&str>,
+			MsFlags::MS_REC | MsFlags::MS_SLAVE,
+			None::<&str>,
+		)
+		.with_context(|| "failed to stop mounts propagating out of the service")?;
+	}
+
+	if sandbox.private_tmp {
+		for directory in PRIVATE_TMP_DIRECTORIES
+			.iter()
+			.filter(|directory| Path::new(directory).is_dir())
+		{
+			mount(
+				Some("tmpfs"),
+				*directory,
+				Some("tmpfs"),
+				MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+				Some("mode=1777"),
+			)
+			.with_context(|| format!("failed to mount a private {}", directory))?;
+		}
+	}
+
+	if sandbox.protect_system {
+		for directory in PROTECTED_DIRECTORIES
+			.iter()
+			.filter(|directory| Path::new(directory).is_dir())
+		{
+			make_read_only(directory).with_context(|| format!("failed to make {} read-only", directory))?;
+		}
+	}
+
+	if sandbox.private_network {
+		// The loopback link of a new network namespace starts down, which would break services that talk to
+		// themselves over localhost.
+		bring_up_loopback().with_context(|| "failed to bring up the loopback link")?;
+	}
+
+	if sandbox.no_new_privileges {
+		set_no_new_privs().with_context(|| "failed to stop the service gaining privileges")?;
+	}
+
+	Ok(())
+}
+
+/// Makes the directory, and everything mounted under it, read-only, by bind mounting it over itself. Only the flags of
+/// the bind mount can be changed, and they can only be changed by remounting it, which only changes the top mount at
+/// the path, so each of the mounts under the directory that the bind copied is remounted as well.
+fn make_read_only(directory: &str) -> Result<()> {
+	mount(
+		Some(directory),
+		directory,
+		None::<&str>,
+		MsFlags::MS_BIND | MsFlags::MS_REC,
+		None::<&str>,
+	)?;
+
+	let mounts = read_mounts().with_context(|| "failed to read the mounts")?;
+	for submount in mounts
+		.iter()
+		.filter(|submount| submount.mount_point.starts_with(directory))
+	{
+		mount(
+			None::<&str>,
+			&submount.mount_point,
+			None::<&str>,
+			MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY | preserved_flags(submount),
+			None::<&str>,
+		)
+		.with_context(|| format!("failed to remount {}", submount.mount_point.display()))?;
+	}
+
+	Ok(())
+}
+
+/// The flags of the mount that remounting it would otherwise clear, from its options.
+fn preserved_flags(submount: &Mount) -> MsFlags {
+	submount.options.iter().fold(MsFlags::empty(), |flags, option| {
+		flags
+			| match option.as_str() {
+				"nosuid" => MsFlags::MS_NOSUID,
+				"nodev" => MsFlags::MS_NODEV,
+				"noexec" => MsFlags::MS_NOEXEC,
+				"noatime" => MsFlags::MS_NOATIME,
+				"nodiratime" => MsFlags::MS_NODIRATIME,
+				"relatime" => MsFlags::MS_RELATIME,
+				_ => MsFlags::empty(),
+			}
+	})
+}
+
+/// Brings up the loopback link of the network namespace that the calling process is in.
+fn bring_up_loopback() -> io::Result<()> {
+	let mut socket = NetlinkSocket::<NetlinkRoute>::new(RTNetlinkGroups::RTMGRP_NONE)?;
+	let message = InterfaceInfoMessage::empty();
+	let link = Interface {
+		family: message.family,
+		ty: message.ty,
+		index: LOOPBACK_INDEX,
+		flags: InterfaceFlags::IFF_UP,
+		change: InterfaceFlags::IFF_UP.bits(),
+		attributes: InterfaceAttributes::default(),
+	};
+
+	socket.new_link(link).map_err(|e| match e {
+		NetlinkError::IOError(e) => e,
+		NetlinkError::NetlinkError(errno, _) => io::Error::from(errno),
+		NetlinkError::Timeout(attempts) => io::Error::new(
+			io::ErrorKind::TimedOut,
+			format!("timed out bringing up the loopback link after {} attempts", attempts),
+		),
+	})
+}
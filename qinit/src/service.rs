@@ -1,34 +1,73 @@
 use std::{
 	collections::HashMap,
 	env::set_current_dir,
-	ffi::{CStr, CString},
+	ffi::CString,
 	fmt::Display,
-	fs::create_dir_all,
+	fs::{self, create_dir_all},
 	future::Future,
-	mem,
-	os::fd::AsRawFd,
-	path::PathBuf,
+	io, mem,
+	os::{fd::AsRawFd, unix::net::UnixStream},
+	path::{Path, PathBuf},
 	pin::Pin,
 	task::Poll,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use auth::{Group, User};
-use common::io::{STDERR_FD, STDOUT_FD};
-use loggerd::{control::start_write_stream_sync, DEFAULT_CONTROL_SOCKET_PATH, KV};
+use auth::{Group, LoginSession, User};
+use common::{
+	io::{STDERR_FD, STDIN_FD, STDOUT_FD},
+	qinit::{RunState, ServiceInstance, ServiceStatus},
+};
+use loggerd::{
+	control::{start_coalesced_write_stream_sync, start_write_stream_sync},
+	DEFAULT_CONTROL_SOCKET_PATH, KV,
+};
+use mount::{
+	fstab::resolve_source,
+	mounts::unescape,
+	swap::{self, SwapOptions},
+};
 use slog::{error, info, warn};
-use tokio::sync::{oneshot, Mutex, Notify};
+use tokio::{
+	sync::{oneshot, Mutex, Notify},
+	time::{sleep, timeout},
+};
 
 use anyhow::{anyhow, Context, Result};
 use nix::{
 	errno::Errno,
+	fcntl::{open, OFlag},
+	libc,
 	sys::{
-		signal::Signal,
+		signal::{kill, Signal},
+		stat::{umask, Mode},
 		wait::{waitpid, WaitPidFlag, WaitStatus},
 	},
-	unistd::{chown, close, dup2, execve, fork, setgid, setuid, ForkResult, Gid, Pid, Uid},
+	unistd::{
+		chown, close, dup2, execve, fork, setgid, setgroups, sethostname, setsid, setuid, ForkResult, Gid, Pid, Uid,
+	},
 };
 
-use crate::config::{Permissions, ServiceConfig, StartMode};
+use crate::{
+	cgroup::{self, Cgroup, CGROUP_ROOT},
+	config::{Permissions, Resources, Sandbox, ServiceConfig, ServiceKind, StartMode},
+	devices::{watch_udev_events, DeviceSpec, DEV_ROOT},
+	early::{self, HOSTNAME_PATH},
+	environment::read_environment_file,
+	sandbox,
+	sysconfig::{self, config_files, parse_modules_load, parse_sysctl},
+};
+
+nix::ioctl_write_int_bad!(set_controlling_tty, libc::TIOCSCTTY);
+
+/// How often to check whether the devices that pending services need have appeared, in case we miss the udev event.
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait for the processes of a service to exit after asking them to stop, before killing them.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to check whether the processes of a service that is stopping have exited.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)] // Some of the variants aren't used yet, but will be once we have a ctl binary.
@@ -41,6 +80,14 @@ pub enum ServiceState {
 
 	// The service has been started, and has finished its startup process.
 	Running(Pid),
+
+	// The process we started forked the service into the background and exited successfully, so the service is
+	// running under a PID that we don't know.
+	Forked,
+
+	// The kernel module of a kmod service is loaded, the swap area of a swap service is in use, or the settings of a
+	// sysctl, modules, or hostname service have been applied.
+	Loaded,
 	Signaled(Pid, Signal),
 	Terminated(i32),
 }
@@ -49,12 +96,69 @@ pub enum ServiceState {
 pub struct Service {
 	name: String,
 	args: HashMap<String, String>,
+
+	/// What starting the service does.
+	kind: ServiceKind,
 	command: String,
 	state: ServiceState,
 
 	permissions: Permissions,
 	runtime_directory: Option<String>,
+
+	/// The (untemplated) directory to run the command in.
+	working_directory: Option<String>,
+
+	/// The umask to run the command with.
+	umask: Option<u32>,
+
+	/// The (untemplated) terminal to run the command on.
+	tty: Option<String>,
+
+	/// The (untemplated) environment variables to run the command with.
+	environment: HashMap<String, String>,
+
+	/// The (untemplated) paths of the files to read environment variables from.
+	environment_files: Vec<String>,
+
+	/// The window, in seconds, that repeated lines of the service's output are coalesced within, if they are.
+	coalesce_logs: Option<u32>,
 	start_mode: StartMode,
+
+	/// The (untemplated) devices that must exist before the service can start.
+	needs_device: Vec<String>,
+
+	/// How long to wait for the devices to appear.
+	device_timeout: Duration,
+
+	/// How long to wait for the service to become ready, if it isn't ready as soon as it's started.
+	start_timeout: Option<Duration>,
+
+	/// When to give up waiting for the service to become ready, once it has been started.
+	start_deadline: Option<Instant>,
+
+	/// The limits on the resources that the service can use.
+	resources: Resources,
+
+	/// How the service is isolated from the rest of the system.
+	sandbox: Sandbox,
+
+	/// The cgroup that the processes of the service are in, if cgroups are available.
+	cgroup: Option<Cgroup>,
+
+	/// When the service entered its current state.
+	since: SystemTime,
+
+	/// How many times the service has been started, including after being stopped.
+	starts: u32,
+
+	/// The services that this service needs, from its config.
+	needs: Vec<ServiceInstance>,
+
+	/// The sphere that the service is being started as part of, if any.
+	sphere: Option<String>,
+
+	/// The login session that asked for the service to be started, if any.
+	session: Option<LoginSession>,
 }
 
 impl Service {
@@ -62,14 +166,167 @@ impl Service {
 		Self {
 			name: config.name.clone(),
 			args,
+			kind: config.kind,
 			command: config.service.command.clone(),
 			state: ServiceState::Stopped,
 			permissions: config.permissions.clone(),
 			runtime_directory: config.runtime_directory.clone(),
+			working_directory: config.service.working_directory.clone(),
+			umask: config.service.umask,
+			tty: config.service.tty.clone(),
+			environment: config.service.environment.clone(),
+			environment_files: config.service.environment_files.clone(),
+			coalesce_logs: config.service.coalesce_logs,
 			start_mode: config.start_mode,
+			needs_device: config.needs_device.clone(),
+			device_timeout: Duration::from_secs(config.device_timeout),
+			start_timeout: match (config.start_mode, config.start_timeout) {
+				(StartMode::Exec, _) | (_, 0) => None,
+				(_, timeout) => Some(Duration::from_secs(timeout)),
+			},
+			start_deadline: None,
+			resources: config.resources.clone(),
+			sandbox: config.sandbox.clone(),
+			cgroup: None,
+			since: SystemTime::now(),
+			starts: 0,
+			needs: config
+				.needs
+				.iter()
+				.map(|dep| ServiceInstance {
+					name: dep.name.clone(),
+					arguments: dep.arguments.clone().into_iter().collect(),
+				})
+				.collect(),
+			sphere: None,
+			session: None,
+		}
+	}
+
+	/// Notes that the service is being started as part of the given sphere.
+	pub fn with_sphere(mut self, sphere: Option<&str>) -> Self {
+		self.sphere = sphere.map(ToOwned::to_owned);
+		self
+	}
+
+	/// Notes that the service is being started on behalf of the given login session. The service is placed in the
+	/// session, so that what it does can be traced back to the login.
+	pub fn with_session(mut self, session: Option<LoginSession>) -> Self {
+		self.session = session;
+		self
+	}
+
+	/// Moves the service into the given state, noting when it happened.
+	fn set_state(&mut self, state: ServiceState) {
+		self.state = state;
+		self.since = SystemTime::now();
+	}
+
+	/// The status of the service, as reported to clients. Services that are waiting to start are `queued`.
+	fn status(&self, queued: bool) -> ServiceStatus {
+		let state = match &self.state {
+			ServiceState::Stopped if queued => RunState::Queued,
+			ServiceState::Stopped | ServiceState::Terminated(0) => RunState::Stopped,
+			ServiceState::Started(_) => RunState::Starting,
+			ServiceState::Running(_) | ServiceState::Forked | ServiceState::Loaded => RunState::Running,
+			ServiceState::Error(reason) => RunState::Failed {
+				exit_code: None,
+				reason: reason.clone(),
+			},
+			ServiceState::Terminated(code) => RunState::Failed {
+				exit_code: Some(*code),
+				reason: format!("exited with status {}", code),
+			},
+			ServiceState::Signaled(_, signal) => RunState::Failed {
+				exit_code: Some(128 + *signal as i32),
+				reason: format!("killed by {}", signal),
+			},
+		};
+
+		ServiceStatus {
+			instance: ServiceInstance {
+				name: self.name.clone(),
+				arguments: self.args.clone().into_iter().collect(),
+			},
+			state,
+			pid: self.main_pid().map(Pid::as_raw),
+			since: self.since.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()),
+			restarts: self.starts.saturating_sub(1),
+			needs: self.needs.clone(),
+			sphere: self.sphere.clone(),
+			session: self.session.map(|session| session.session_id),
+		}
+	}
+
+	/// The PID of the process that was started for the service, if it's still running.
+	fn main_pid(&self) -> Option<Pid> {
+		match self.state {
+			ServiceState::Started(pid) | ServiceState::Running(pid) => Some(pid),
+			_ => None,
+		}
+	}
+
+	/// Whether any process of the service is still running. Without a cgroup, only the process that was started can
+	/// be tracked.
+	fn is_alive(&self) -> bool {
+		if matches!(self.state, ServiceState::Loaded) {
+			return true;
+		}
+
+		match &self.cgroup {
+			Some(cgroup) => cgroup.is_populated().unwrap_or(false),
+			None => self.main_pid().is_some(),
+		}
+	}
+
+	/// Removes the cgroup of the service once its processes have all exited. Forked services are only tracked by their
+	/// cgroup, so theirs is kept.
+	fn remove_cgroup(&self) {
+		if let (Some(cgroup), false) = (&self.cgroup, matches!(self.state, ServiceState::Forked)) {
+			// This fails if anything the service started is still running, in which case the cgroup is kept so that
+			// the service can still be stopped.
+			let _ = cgroup.remove();
+		}
+	}
+
+	/// Sends the given signal to every process of the service, or just the process that was started if the service
+	/// doesn't have a cgroup.
+	fn signal(&self, signal: Signal) -> Result<()> {
+		match (&self.cgroup, self.main_pid()) {
+			(Some(cgroup), _) => cgroup.signal(signal)?,
+			(None, Some(pid)) => kill(pid, signal)?,
+			(None, None) => {}
+		}
+
+		Ok(())
+	}
+
+	/// Whether the service has finished starting up, so that the services waiting on it can start.
+	fn is_ready(&self) -> bool {
+		match self.state {
+			ServiceState::Running(_) | ServiceState::Forked | ServiceState::Loaded => true,
+			ServiceState::Terminated(0) => self.start_mode == StartMode::Done,
+			_ => false,
 		}
 	}
 
+	/// The devices that must exist before the service can start, with the arguments templated in. This includes the
+	/// terminal that the service runs on, if it has one.
+	fn devices(&self) -> Vec<DeviceSpec> {
+		self.needs_device
+			.iter()
+			.filter_map(|device| DeviceSpec::parse(&self.template(device)))
+			.chain(self.tty().map(DeviceSpec::Path))
+			.collect()
+	}
+
+	/// The terminal that the service runs on, with the arguments templated in.
+	fn tty(&self) -> Option<PathBuf> {
+		self.tty
+			.as_ref()
+			.map(|tty| Path::new(DEV_ROOT).join(self.template(tty)))
+	}
+
 	pub fn matches(&self, name: &str, arguments: &HashMap<String, String>) -> bool {
 		if self.name != name {
 			return false;
@@ -84,6 +341,156 @@ impl Service {
 		true
 	}
 
+	/// The module of a kmod service, and its parameters, with the arguments templated in.
+	fn module(&self) -> Option<(String, Vec<String>)> {
+		let mut parts = self.command.split_whitespace().map(|part| self.template(part));
+		Some((parts.next()?, parts.collect()))
+	}
+
+	/// Starts a kmod service, loading its module (unless it's already loaded), and then checking that it's in
+	/// /proc/modules. This blocks while the module is loaded.
+	fn load_module(&mut self, logger: &slog::Logger) -> Result<()> {
+		let (module, parameters) = self.module().ok_or_else(|| anyhow!("no module to load"))?;
+		if !modprobe::is_module_loaded(&module)? {
+			let modules_path = modprobe::default_modules_path()?;
+			modprobe::load_module(logger, &modules_path, &module, &parameters)
+				.with_context(|| format!("failed to load module {}", module))?;
+		}
+
+		if !modprobe::is_module_loaded(&module)? {
+			return Err(anyhow!(
+				"module {} isn't in {} after loading it",
+				module,
+				modprobe::PROC_MODULES
+			));
+		}
+
+		self.set_state(ServiceState::Loaded);
+		self.starts += 1;
+		Ok(())
+	}
+
+	/// The swap area of a swap service, and its options, with the arguments templated in.
+	fn swap_area(&self) -> Result<(String, SwapOptions)> {
+		let (source, options) = self.module().ok_or_else(|| anyhow!("no swap area to use"))?;
+		let options = options
+			.iter()
+			.flat_map(|options| options.split(','))
+			.collect::<Vec<_>>();
+		Ok((unescape(&source), SwapOptions::from_fstab(&options)?))
+	}
+
+	/// Finds the device that the swap area of a swap service is on.
+	fn swap_device(&self) -> Result<PathBuf> {
+		let (source, _) = self.swap_area()?;
+		resolve_source(&source)
+			.with_context(|| format!("failed to find {}", source))?
+			.ok_or_else(|| anyhow!("no device matches {}", source))
+	}
+
+	/// Starts a swap service, using its swap area (unless it's already in use). This blocks while the area is
+	/// checked.
+	fn swap_on(&mut self) -> Result<()> {
+		let (_, options) = self.swap_area()?;
+		let device = self.swap_device()?;
+		if !swap::is_active(&device)? {
+			swap::swap_on(&device, &options).with_context(|| format!("failed to use {}", device.display()))?;
+		}
+
+		self.set_state(ServiceState::Loaded);
+		self.starts += 1;
+		Ok(())
+	}
+
+	/// The files and directories that a sysctl or modules service reads, with the arguments templated in, or the
+	/// given defaults if the service doesn't have any.
+	fn config_paths(&self, defaults: fn() -> Vec<PathBuf>) -> Vec<PathBuf> {
+		let paths = self
+			.command
+			.split_whitespace()
+			.map(|path| PathBuf::from(self.template(path)))
+			.collect::<Vec<_>>();
+
+		if paths.is_empty() {
+			defaults()
+		} else {
+			paths
+		}
+	}
+
+	/// Starts a sysctl service, setting the kernel parameters in its files. Every parameter is tried, even once one
+	/// has failed, and the service fails if any of them did. This blocks while the parameters are set.
+	fn apply_sysctls(&mut self, logger: &slog::Logger) -> Result<()> {
+		let mut failed = 0;
+		for file in config_files(&self.config_paths(sysconfig::default_sysctl_paths))? {
+			let contents = fs::read_to_string(&file).with_context(|| format!("failed to read {}", file.display()))?;
+			let sysctls = parse_sysctl(&contents).map_err(|e| anyhow!("failed to parse {}: {}", file.display(), e))?;
+			for sysctl in sysctls {
+				match sysctl.apply() {
+					Err(e) if !sysctl.ignore_failure => {
+						warn!(logger, "failed to set kernel parameter"; "service" => self.to_string(), "key" => &sysctl.key, "file" => file.display().to_string(), "error" => e.to_string());
+						failed += 1;
+					}
+					_ => {}
+				}
+			}
+		}
+
+		if failed > 0 {
+			return Err(anyhow!("failed to set {} kernel parameters", failed));
+		}
+
+		self.set_state(ServiceState::Loaded);
+		self.starts += 1;
+		Ok(())
+	}
+
+	/// Starts a modules service, loading the modules in its files (other than those that are already loaded). Every
+	/// module is tried, even once one has failed, and the service fails if any of them did. This blocks while the
+	/// modules are loaded.
+	fn load_modules(&mut self, logger: &slog::Logger) -> Result<()> {
+		let modules_path = modprobe::default_modules_path()?;
+		let mut failed = 0;
+		for file in config_files(&self.config_paths(sysconfig::default_modules_load_paths))? {
+			let contents = fs::read_to_string(&file).with_context(|| format!("failed to read {}", file.display()))?;
+			for module in parse_modules_load(&contents) {
+				let loaded = match modprobe::is_module_loaded(&module) {
+					Ok(true) => Ok(()),
+					Ok(false) => modprobe::load_module(logger, &modules_path, &module, &[]),
+					Err(e) => Err(e.into()),
+				};
+
+				if let Err(e) = loaded {
+					warn!(logger, "failed to load module"; "service" => self.to_string(), "module" => &module, "file" => file.display().to_string(), "error" => e.to_string());
+					failed += 1;
+				}
+			}
+		}
+
+		if failed > 0 {
+			return Err(anyhow!("failed to load {} modules", failed));
+		}
+
+		self.set_state(ServiceState::Loaded);
+		self.starts += 1;
+		Ok(())
+	}
+
+	/// Starts a hostname service, setting the hostname to its command, or the one in /etc/hostname if it doesn't have
+	/// one.
+	fn set_hostname(&mut self) -> Result<()> {
+		let hostname = self.template(self.command.trim());
+		if hostname.is_empty() {
+			early::set_hostname(Path::new(HOSTNAME_PATH))?;
+		} else {
+			sethostname(&hostname).with_context(|| format!("failed to set the hostname to {}", hostname))?;
+		}
+
+		self.set_state(ServiceState::Loaded);
+		self.starts += 1;
+		Ok(())
+	}
+
 	/// Splits the command into arguments that can be passed to `execve`.
 	fn split_args(&self) -> Result<Option<Vec<CString>>> {
 		let mut parts = self.command.split_whitespace().peekable();
@@ -108,7 +515,36 @@ impl Service {
 		command
 	}
 
-	/// Sets the user and group for the service.
+	/// Builds the environment of the command from the environment files and variables of the service, as
+	/// `KEY=VALUE` strings that can be passed to `execve`. The service doesn't inherit any of qinit's environment, other
+	/// than the login session it was started on behalf of.
+	fn environment(&self) -> Result<Vec<CString>> {
+		let mut environment = HashMap::new();
+		for file in self.environment_files.iter() {
+			environment.extend(read_environment_file(&self.template(file))?);
+		}
+
+		for (key, value) in self.environment.iter() {
+			environment.insert(key.clone(), self.template(value));
+		}
+
+		for (key, value) in self.session.iter().flat_map(LoginSession::environment) {
+			environment.insert(key.to_owned(), value);
+		}
+
+		let mut environment = environment.into_iter().collect::<Vec<_>>();
+		environment.sort();
+
+		environment
+			.into_iter()
+			.map(|(key, value)| {
+				CString::new(format!("{}={}", key, value))
+					.with_context(|| format!("invalid value for environment variable {}", key))
+			})
+			.collect()
+	}
+
+	/// Sets the user and group for the service, along with the supplementary groups of the user.
 	fn set_user_group(&self) -> Result<()> {
 		let user = match User::from_username(&self.permissions.user)? {
 			Some(user) => user,
@@ -123,20 +559,32 @@ impl Service {
 			None => return Err(anyhow!(format!("User not found: {}", self.permissions.user))),
 		};
 
-		let group = match Group::from_groupname(&self.permissions.group)? {
-			Some(group) => group,
-			None if self.permissions.create => Group::create(&self.permissions.group, None)?,
-			None => return Err(anyhow!(format!("Group not found: {}", self.permissions.group))),
+		let gid = match &self.permissions.group {
+			Some(name) => match Group::from_groupname(name)? {
+				Some(group) => group.gid,
+				None if self.permissions.create => Group::create(name, None)?.gid,
+				None => return Err(anyhow!(format!("Group not found: {}", name))),
+			},
+			None => user.gid,
 		};
 
 		let uid = Uid::from_raw(user.uid);
-		let gid = Gid::from_raw(group.gid);
+		let gid = Gid::from_raw(gid);
+
+		// Replace qinit's supplementary groups, so that the service doesn't keep root's.
+		let mut groups = Group::for_member(&user.username)?
+			.into_iter()
+			.map(|group| Gid::from_raw(group.gid))
+			.collect::<Vec<_>>();
+		groups.push(gid);
 
 		// Change the ownership of the runtime directory.
 		if let Some(runtime_dir) = &self.runtime_directory {
 			chown(runtime_dir.as_str(), Some(uid), Some(gid))?;
 		}
 
+		// The groups have to be set before the user, because once we drop out of root we can't change them anymore.
+		setgroups(&groups)?;
 		setgid(gid)?;
 		setuid(uid)?;
 
@@ -147,21 +595,65 @@ impl Service {
 		if let Some(ref directory) = self.runtime_directory {
 			// Create the directory if it doesn't exist.
 			create_dir_all(directory).with_context(|| format!("failed to create runtime directory: {}", directory))?;
+		}
+
+		Ok(())
+	}
 
-			// Change the working directory.
-			set_current_dir(directory).with_context(|| format!("failed to set runtime directory: {}", directory))?;
+	/// Enters the working directory of the service (or its runtime directory if it doesn't have one), and sets its
+	/// umask. This happens after dropping privileges, so that the service can't start in a directory it can't enter.
+	fn set_working_directory(&self) -> Result<()> {
+		let directory = match (&self.working_directory, &self.runtime_directory) {
+			(Some(directory), _) => self.template(directory),
+			(None, Some(directory)) => directory.clone(),
+			(None, None) => String::from("/"),
+		};
+
+		set_current_dir(&directory).with_context(|| format!("failed to set working directory: {}", directory))?;
+
+		if let Some(mask) = self.umask {
+			umask(Mode::from_bits_truncate(mask));
 		}
 
 		Ok(())
 	}
 
+	/// Makes the given terminal the controlling terminal of the service, and its stdin, stdout, and stderr. The
+	/// service is put in a new session first, so that it doesn't share qinit's controlling terminal.
+	fn set_controlling_terminal(tty: &Path) -> Result<()> {
+		setsid().with_context(|| "failed to start a new session")?;
+
+		let fd = open(tty, OFlag::O_RDWR | OFlag::O_NOCTTY, Mode::empty())
+			.with_context(|| format!("failed to open {}", tty.display()))?;
+		unsafe { set_controlling_tty(fd, 0) }.with_context(|| format!("failed to take {}", tty.display()))?;
+
+		for target in [STDIN_FD, STDOUT_FD, STDERR_FD] {
+			dup2(fd, target).with_context(|| format!("failed to copy {} to fd {}", tty.display(), target))?;
+		}
+
+		if fd > STDERR_FD {
+			close(fd).with_context(|| "failed to close old tty fd")?;
+		}
+
+		Ok(())
+	}
+
+	/// Starts a write stream to loggerd with the given fields, coalescing repeated lines if the service asks for it.
+	fn start_log_stream(&self, fields: Vec<KV>) -> io::Result<UnixStream> {
+		let socket_path = PathBuf::from(DEFAULT_CONTROL_SOCKET_PATH);
+		match self.coalesce_logs {
+			Some(window) => start_coalesced_write_stream_sync(&socket_path, fields, window),
+			None => start_write_stream_sync(&socket_path, fields),
+		}
+	}
+
 	fn pipe_logging(&self) -> Result<()> {
 		let stdout_map = vec![
 			KV::new(String::from("SERVICE"), self.name.clone()),
 			KV::new(String::from("STREAM"), String::from("stdout")),
 		];
 
-		if let Ok(stream) = start_write_stream_sync(&PathBuf::from(DEFAULT_CONTROL_SOCKET_PATH), stdout_map) {
+		if let Ok(stream) = self.start_log_stream(stdout_map) {
 			let fd = stream.as_raw_fd();
 			mem::forget(stream);
 			dup2(fd, STDOUT_FD).with_context(|| "failed to pipe stdout to loggerd")?;
@@ -173,7 +665,7 @@ impl Service {
 			KV::new(String::from("STREAM"), String::from("stdout")),
 		];
 
-		if let Ok(stream) = start_write_stream_sync(&PathBuf::from(DEFAULT_CONTROL_SOCKET_PATH), stderr_map) {
+		if let Ok(stream) = self.start_log_stream(stderr_map) {
 			let fd = stream.as_raw_fd();
 			mem::forget(stream);
 			dup2(fd, STDERR_FD).with_context(|| "failed to pipe stdout to loggerd")?;
@@ -186,14 +678,55 @@ impl Service {
 	/// Starts the service, forking and executing the command.
 	pub fn start(&mut self) -> Result<()> {
 		let args = self.split_args()?.unwrap();
+
+		// Read the environment before forking, so that a bad environment file fails the start rather than the child.
+		let environment = self.environment()?;
 		match unsafe { fork()? } {
 			ForkResult::Parent { child } => {
-				self.state = ServiceState::Started(child);
+				self.set_state(ServiceState::Started(child));
+				self.starts += 1;
+				self.start_deadline = self.start_timeout.map(|timeout| Instant::now() + timeout);
 			}
 			ForkResult::Child => {
 				// Setup all the pre-execution stuff. `unwrap` is fine here because we absolutely shouldn't return
 				// in the child process.
 
+				// Move into the cgroup first, so that anything the child does is accounted for and limited.
+				if let Some(cgroup) = &self.cgroup {
+					cgroup
+						.add_process(Pid::from_raw(0))
+						.with_context(|| {
+							format!(
+								"failed to start service name: {}, args: {:?}: failed to join cgroup {}",
+								self.name,
+								self.args,
+								cgroup.path().display()
+							)
+						})
+						.unwrap();
+				}
+
+				if let Some(tty) = self.tty() {
+					Self::set_controlling_terminal(&tty)
+						.with_context(|| {
+							format!(
+								"failed to start service name: {}, args: {:?}: failed to set controlling terminal",
+								self.name, self.args
+							)
+						})
+						.unwrap();
+				}
+
+				// Isolate the service while we're still root, which it needs.
+				sandbox::apply(&self.sandbox)
+					.with_context(|| {
+						format!(
+							"failed to start service name: {}, args: {:?}: failed to set up sandbox",
+							self.name, self.args
+						)
+					})
+					.unwrap();
+
 				self.set_runtime_directory()
 					.with_context(|| {
 						format!(
@@ -203,7 +736,7 @@ impl Service {
 					})
 					.unwrap();
 
-				// Set the user and group. This should be last as it may drop permissions and we wont be root anymore.
+				// Set the user and group. This should come after anything that needs root, as we wont be root anymore.
 				self.set_user_group()
 					.with_context(|| {
 						format!(
@@ -213,9 +746,21 @@ impl Service {
 					})
 					.unwrap();
 
-				self.pipe_logging().unwrap();
+				self.set_working_directory()
+					.with_context(|| {
+						format!(
+							"failed to start service name: {}, args: {:?}: failed to set working directory",
+							self.name, self.args
+						)
+					})
+					.unwrap();
+
+				// Services on a terminal talk to it, rather than to the logs.
+				if self.tty.is_none() {
+					self.pipe_logging().unwrap();
+				}
 
-				execve::<_, &CStr>(&args[0], &args, &[])
+				execve(&args[0], &args, &environment)
 					.with_context(|| format!("failed to start service name: {}, args: {:?}", self.name, self.args))
 					.unwrap();
 			}
@@ -249,20 +794,50 @@ pub struct ServiceManager {
 	/// A notify that is triggered when a new service is started.
 	new_service_notify: Notify,
 
+	/// A notify that is triggered when a service starts waiting for devices.
+	device_notify: Notify,
+
+	/// A notify that is triggered when a service starts waiting to become ready.
+	start_notify: Notify,
+
+	/// The root of the cgroup v2 hierarchy that services are put in, if it's mounted.
+	cgroup_root: Option<PathBuf>,
+
 	logger: slog::Logger,
 }
 
 impl ServiceManager {
 	pub fn new(logger: slog::Logger) -> Self {
+		let cgroup_root = PathBuf::from(CGROUP_ROOT);
+		let cgroup_root = if cgroup::available(&cgroup_root) {
+			Some(cgroup_root)
+		} else {
+			warn!(logger, "cgroup v2 isn't mounted, so services won't be put in cgroups"; "path" => CGROUP_ROOT);
+			None
+		};
+
 		Self {
 			services: Mutex::new(Vec::new()),
 			pending_services: Mutex::new(Vec::new()),
 			new_service_notify: Notify::new(),
+			device_notify: Notify::new(),
+			start_notify: Notify::new(),
+			cgroup_root,
 			logger,
 		}
 	}
 
-	/// Checks if there is a service running that satisfies the given service.
+	/// Creates the cgroup for the given service, and sets its resource limits.
+	fn create_cgroup(&self, root: &Path, service: &Service) -> Result<Cgroup> {
+		let cgroup = Cgroup::create(root, &service.name, &service.args)
+			.with_context(|| format!("failed to create cgroup for {}", service))?;
+		cgroup
+			.set_limits(&service.resources)
+			.with_context(|| format!("failed to set resource limits in {}", cgroup.path().display()))?;
+		Ok(cgroup)
+	}
+
+	/// Checks if there is a service that satisfies the given service, and has finished starting up.
 	pub async fn is_running(&self, wants: &Service) -> bool {
 		let services = self.services.lock().await;
 		for s in services.iter() {
@@ -270,7 +845,7 @@ impl ServiceManager {
 				continue;
 			}
 
-			return matches!(s.state, ServiceState::Running(_));
+			return s.is_ready();
 		}
 
 		false
@@ -278,9 +853,21 @@ impl ServiceManager {
 
 	/// Adds the given service to the queue of services to start, starting it if
 	/// all its dependencies are running, or putting it in a pending state if not.
-	pub async fn queue(&self, service: Service, dependencies: Vec<Service>) {
+	pub async fn queue(&self, mut service: Service, dependencies: Vec<Service>) {
 		{
-			let services = self.services.lock().await;
+			let mut services = self.services.lock().await;
+
+			// Services that have been stopped are started again, keeping count of how many times that's happened.
+			let stopped = services
+				.extract_if(.., |s| {
+					s.matches(&service.name, &service.args) && matches!(s.state, ServiceState::Stopped)
+				})
+				.map(|s| s.starts)
+				.max();
+			if let Some(starts) = stopped {
+				service.starts = starts;
+			}
+
 			if services.iter().any(|s| s.matches(&service.name, &service.args)) {
 				return;
 			}
@@ -303,21 +890,52 @@ impl ServiceManager {
 			}
 		}
 
-		if unmet_dependencies.is_empty() {
+		let missing_devices = service
+			.devices()
+			.into_iter()
+			.filter(|device| !device.exists())
+			.collect::<Vec<DeviceSpec>>();
+
+		if unmet_dependencies.is_empty() && missing_devices.is_empty() {
 			self.start(service).await;
 		} else {
+			let waiting_on_devices = !missing_devices.is_empty();
+			if waiting_on_devices {
+				let devices = missing_devices.iter().map(ToString::to_string).collect::<Vec<_>>();
+				info!(self.logger, "service is waiting for devices"; "service" => service.to_string(), "devices" => devices.join(", "));
+			}
+
 			let mut pending_services = self.pending_services.lock().await;
-			let watcher = ServiceWaiter::new(service, unmet_dependencies);
+			let watcher = ServiceWaiter::new(service, unmet_dependencies, missing_devices);
 			pending_services.push(watcher);
+
+			if waiting_on_devices {
+				self.device_notify.notify_one();
+			}
 		}
 	}
 
 	/// Starts the given service, forking a child and handling start modes.
 	async fn start(&self, mut service: Service) {
 		info!(self.logger, "starting service"; "service" => service.to_string());
+		if !service.kind.has_process() {
+			Box::pin(self.start_without_process(service)).await;
+			return;
+		}
+
 		let start_future = async move {
+			if let Some(root) = &self.cgroup_root {
+				match self.create_cgroup(root, &service) {
+					Ok(cgroup) => service.cgroup = Some(cgroup),
+					Err(e) => {
+						error!(self.logger, "failed to start service"; "service" => service.to_string(), "error" => format!("{:#}", e));
+						return;
+					}
+				}
+			}
+
 			if let Err(e) = service.start() {
-				service.state = ServiceState::Error(e.to_string());
+				error!(self.logger, "failed to start service"; "service" => service.to_string(), "error" => e.to_string());
 				return;
 			}
 
@@ -333,8 +951,10 @@ impl ServiceManager {
 				services.push(service);
 			}
 
-			if start_mode == StartMode::Run {
+			if start_mode == StartMode::Exec {
 				self.mark_service_running(pid).await;
+			} else {
+				self.start_notify.notify_one();
 			}
 
 			// Notify the reaper that it should start listening for chiildren again.
@@ -346,6 +966,42 @@ impl ServiceManager {
 		Box::pin(start_future).await;
 	}
 
+	/// Starts a service that doesn't run a process, e.g. loading the module of a kmod service or using the swap area of
+	/// a swap service, off of the runtime so that a slow start doesn't hold up anything else, and then starting
+	/// anything that was waiting on it (or failing it, if the service can't be started).
+	async fn start_without_process(&self, mut service: Service) {
+		let logger = self.logger.clone();
+		let (mut service, result) = match tokio::task::spawn_blocking(move || {
+			let result = match service.kind {
+				ServiceKind::Swap => service.swap_on(),
+				ServiceKind::Sysctl => service.apply_sysctls(&logger),
+				ServiceKind::Modules => service.load_modules(&logger),
+				ServiceKind::Hostname => service.set_hostname(),
+				_ => service.load_module(&logger),
+			};
+			(service, result)
+		})
+		.await
+		{
+			Ok(started) => started,
+			Err(e) => {
+				error!(self.logger, "failed to start service"; "error" => e.to_string());
+				return;
+			}
+		};
+
+		if let Err(e) = result {
+			error!(self.logger, "failed to start service"; "service" => service.to_string(), "error" => format!("{:#}", e));
+			service.set_state(ServiceState::Error(format!("{:#}", e)));
+			self.services.lock().await.push(service.clone());
+			self.fail_dependents(&service).await;
+			return;
+		}
+
+		self.services.lock().await.push(service.clone());
+		self.trigger_start_sweep(&service).await;
+	}
+
 	/// Marks the given service as running, notifying any pending services.
 	pub async fn mark_service_running(&self, pid: Pid) {
 		let mut services = self.services.lock().await;
@@ -365,7 +1021,7 @@ impl ServiceManager {
 				return;
 			}
 
-			service.state = ServiceState::Running(pid);
+			service.set_state(ServiceState::Running(pid));
 			start_sweep = Some(service.clone());
 		} else {
 			warn!(
@@ -384,7 +1040,7 @@ impl ServiceManager {
 	async fn trigger_start_sweep(&self, started: &Service) {
 		let mut pending = self.pending_services.lock().await;
 		let to_start = pending
-			.extract_if(|w| {
+			.extract_if(.., |w| {
 				w.notify_service_started(started);
 				w.done()
 			})
@@ -397,6 +1053,283 @@ impl ServiceManager {
 		}
 	}
 
+	/// Fails the pending services that are waiting on the given service, which will now never become ready, along with
+	/// anything waiting on them in turn.
+	async fn fail_dependents(&self, failed: &Service) {
+		let mut pending = self.pending_services.lock().await;
+		let dependents = pending
+			.extract_if(.., |w| w.waiting_on(failed))
+			.collect::<Vec<ServiceWaiter>>();
+
+		drop(pending);
+
+		for waiter in dependents {
+			error!(self.logger, "dependency failed to start"; "service" => waiter.service.to_string(), "dependency" => failed.to_string());
+
+			let mut service = waiter.service;
+			service.set_state(ServiceState::Error(format!("dependency {} failed to start", failed)));
+			self.services.lock().await.push(service.clone());
+			Box::pin(self.fail_dependents(&service)).await;
+		}
+	}
+
+	/// Fails the services that have been started, but haven't become ready before their start timeout, killing their
+	/// processes.
+	async fn sweep_starting(&self) {
+		let now = Instant::now();
+		let mut services = self.services.lock().await;
+		let mut timed_out = Vec::new();
+		for service in services.iter_mut() {
+			if !matches!(service.state, ServiceState::Started(_)) {
+				continue;
+			}
+
+			if service.start_deadline.is_none_or(|deadline| now < deadline) {
+				continue;
+			}
+
+			error!(self.logger, "timed out waiting for service to become ready"; "service" => service.to_string());
+			if let Err(e) = service.signal(Signal::SIGTERM) {
+				warn!(self.logger, "failed to kill service"; "service" => service.to_string(), "error" => e.to_string());
+			}
+
+			service.set_state(ServiceState::Error(String::from("timed out waiting to become ready")));
+
+			timed_out.push(service.clone());
+		}
+
+		drop(services);
+
+		for service in timed_out {
+			self.fail_dependents(&service).await;
+		}
+	}
+
+	/// Infinitely watches the services that are starting up, failing any that don't become ready before their start
+	/// timeout.
+	pub async fn start_watcher(&self) {
+		loop {
+			let deadline = {
+				let services = self.services.lock().await;
+				services
+					.iter()
+					.filter(|s| matches!(s.state, ServiceState::Started(_)))
+					.filter_map(|s| s.start_deadline)
+					.min()
+			};
+
+			match deadline {
+				Some(deadline) => {
+					// Wake up early if another service starts, in case its deadline is sooner.
+					let _ = timeout(
+						deadline.saturating_duration_since(Instant::now()),
+						self.start_notify.notified(),
+					)
+					.await;
+				}
+				None => self.start_notify.notified().await,
+			}
+
+			self.sweep_starting().await;
+		}
+	}
+
+	/// Checks the devices that pending services are waiting on, starting any services that are no longer waiting on
+	/// anything, and failing any that have waited too long for their devices.
+	async fn sweep_devices(&self, event: Option<&HashMap<String, String>>) {
+		let now = Instant::now();
+		let mut pending = self.pending_services.lock().await;
+		let to_start = pending
+			.extract_if(.., |w| {
+				w.notify_devices(event);
+				w.done()
+			})
+			.collect::<Vec<ServiceWaiter>>();
+
+		let timed_out = pending
+			.extract_if(.., |w| w.devices_timed_out(now))
+			.collect::<Vec<ServiceWaiter>>();
+
+		drop(pending);
+
+		for waiter in timed_out {
+			let devices = waiter
+				.waiting_devices
+				.iter()
+				.map(ToString::to_string)
+				.collect::<Vec<_>>()
+				.join(", ");
+			error!(self.logger, "timed out waiting for devices"; "service" => waiter.service.to_string(), "devices" => &devices);
+
+			let mut service = waiter.service;
+			service.set_state(ServiceState::Error(format!(
+				"timed out waiting for devices: {}",
+				devices
+			)));
+			self.services.lock().await.push(service);
+		}
+
+		for waiter in to_start {
+			self.start(waiter.service).await;
+		}
+	}
+
+	/// Infinitely watches for the devices that pending services need, starting the services once their devices
+	/// exist, or failing them if the devices don't appear in time.
+	pub async fn device_watcher(&self) {
+		let mut events = None;
+		loop {
+			let waiting = {
+				let pending = self.pending_services.lock().await;
+				pending.iter().any(|w| !w.waiting_devices.is_empty())
+			};
+
+			if !waiting {
+				// Nothing needs any devices, so stop listening to udev events until something does.
+				events = None;
+				self.device_notify.notified().await;
+				continue;
+			}
+
+			let events = events.get_or_insert_with(watch_udev_events);
+			let event = match timeout(DEVICE_POLL_INTERVAL, events.recv()).await {
+				Ok(Some(event)) if event.get("ACTION").is_none_or(|action| action != "remove") => Some(event),
+				_ => None,
+			};
+
+			self.sweep_devices(event.as_ref()).await;
+		}
+	}
+
+	/// Stops the service that matches the given name and arguments, asking every one of its processes to exit, and
+	/// killing them if they don't in time. The service is kept in the stopped state, so it can be started again.
+	pub async fn stop(&self, name: &str, args: &HashMap<String, String>) -> Result<()> {
+		let service = {
+			let services = self.services.lock().await;
+			services
+				.iter()
+				.find(|s| s.matches(name, args) && s.is_alive())
+				.cloned()
+				.ok_or_else(|| anyhow!("{} isn't running", name))?
+		};
+
+		match service.kind {
+			ServiceKind::Kmod => return self.unload_module(&service).await,
+			ServiceKind::Swap => return self.swap_off(&service).await,
+			ServiceKind::Sysctl | ServiceKind::Modules | ServiceKind::Hostname => {
+				// What these services set stays set, so there's nothing to undo, but starting them again reapplies it.
+				info!(self.logger, "stopping service"; "service" => service.to_string());
+				self.mark_stopped(&service).await;
+				return Ok(());
+			}
+			ServiceKind::Process => {}
+		}
+
+		if service.cgroup.is_none() && service.main_pid().is_none() {
+			return Err(anyhow!(
+				"{} forked into the background, and can't be tracked without cgroups",
+				service
+			));
+		}
+
+		info!(self.logger, "stopping service"; "service" => service.to_string());
+		service.signal(Signal::SIGTERM)?;
+
+		// Without a cgroup, whether the service is alive comes from its state, which the reaper updates, so check the
+		// service in the list rather than our copy of it.
+		let deadline = Instant::now() + STOP_TIMEOUT;
+		while Instant::now() < deadline
+			&& self
+				.services
+				.lock()
+				.await
+				.iter()
+				.any(|s| s.matches(name, args) && s.is_alive())
+		{
+			sleep(STOP_POLL_INTERVAL).await;
+		}
+
+		let mut services = self.services.lock().await;
+		let stopped = services.iter_mut().filter(|s| s.matches(&service.name, &service.args));
+		for stopped in stopped {
+			if stopped.is_alive() {
+				warn!(self.logger, "service didn't stop in time, killing it"; "service" => stopped.to_string());
+				match &stopped.cgroup {
+					Some(cgroup) => cgroup.kill()?,
+					None => stopped.signal(Signal::SIGKILL)?,
+				}
+			}
+
+			if let Some(cgroup) = &stopped.cgroup {
+				// Killed processes take a moment to leave the cgroup, so this can fail. The cgroup is reused if the
+				// service is started again anyway.
+				let _ = cgroup.remove();
+			}
+
+			stopped.set_state(ServiceState::Stopped);
+		}
+
+		Ok(())
+	}
+
+	/// Stops a kmod service by unloading its module, which fails if the module is in use.
+	async fn unload_module(&self, service: &Service) -> Result<()> {
+		let (module, _) = service.module().ok_or_else(|| anyhow!("no module to unload"))?;
+		info!(self.logger, "unloading module"; "service" => service.to_string(), "module" => &module);
+		modprobe::unload_module(&module).with_context(|| format!("failed to unload module {}", module))?;
+		self.mark_stopped(service).await;
+		Ok(())
+	}
+
+	/// Stops a swap service by no longer using its swap area, which moves the pages in it back into memory, and fails
+	/// if there isn't enough memory for them.
+	async fn swap_off(&self, service: &Service) -> Result<()> {
+		let device = service.swap_device()?;
+		info!(self.logger, "no longer using swap area"; "service" => service.to_string(), "device" => device.display().to_string());
+		let swap_off = device.clone();
+		tokio::task::spawn_blocking(move || swap::swap_off(&swap_off))
+			.await?
+			.with_context(|| format!("failed to stop using {}", device.display()))?;
+		self.mark_stopped(service).await;
+		Ok(())
+	}
+
+	/// Moves every instance of a service that doesn't run a process into the stopped state.
+	async fn mark_stopped(&self, service: &Service) {
+		let mut services = self.services.lock().await;
+		for stopped in services.iter_mut().filter(|s| s.matches(&service.name, &service.args)) {
+			stopped.set_state(ServiceState::Stopped);
+		}
+	}
+
+	/// The status of every service that matches the given name and arguments, or of every service if there's no
+	/// filter, sorted by instance.
+	pub async fn statuses(&self, filter: Option<(&str, &HashMap<String, String>)>) -> Vec<ServiceStatus> {
+		let included = |service: &Service| filter.is_none_or(|(name, args)| service.matches(name, args));
+
+		let mut statuses = self
+			.services
+			.lock()
+			.await
+			.iter()
+			.filter(|s| included(s))
+			.map(|s| s.status(false))
+			.collect::<Vec<_>>();
+
+		statuses.extend(
+			self.pending_services
+				.lock()
+				.await
+				.iter()
+				.filter(|w| included(&w.service))
+				.map(|w| w.service.status(true)),
+		);
+
+		statuses
+			.sort_by(|a, b| (&a.instance.name, &a.instance.arguments).cmp(&(&b.instance.name, &b.instance.arguments)));
+		statuses
+	}
+
 	/// Sets the status of a process.
 	async fn set_process_status(&self, status: WaitStatus) {
 		// If there is no PID, we can't do anything.
@@ -413,23 +1346,45 @@ impl ServiceManager {
 		});
 
 		if let Some(service) = service {
+			let starting = matches!(service.state, ServiceState::Started(_));
 			match status {
 				WaitStatus::Exited(_, status) => {
-					service.state = ServiceState::Terminated(status);
-					if status == 0 && service.start_mode == StartMode::Done {
-						// Done services are considered "started" when they exit. This is a bit ick because `trigger_start_sweep`
-						// can lock the services list again to start more things, so we need to clone + drop the lock here so
-						// that that doesn't deadlock.
+					service.set_state(match (status, service.start_mode) {
+						(0, StartMode::ForkExits) => ServiceState::Forked,
+						(status, _) => ServiceState::Terminated(status),
+					});
+
+					service.remove_cgroup();
+
+					if service.is_ready() {
+						// Done and fork-exits services are considered "started" when they exit. This is a bit ick because
+						// `trigger_start_sweep` can lock the services list again to start more things, so we need to
+						// clone + drop the lock here so that that doesn't deadlock.
 						let service = service.clone();
 						drop(services);
 						self.trigger_start_sweep(&service).await;
+					} else if starting {
+						error!(self.logger, "service exited before becoming ready"; "service" => service.to_string(), "status" => status);
+						let service = service.clone();
+						drop(services);
+						self.fail_dependents(&service).await;
 					}
 				}
-				WaitStatus::Signaled(_, signal, _) | WaitStatus::Stopped(_, signal) => {
-					service.state = ServiceState::Signaled(pid, signal);
+				WaitStatus::Signaled(_, signal, _) => {
+					service.set_state(ServiceState::Signaled(pid, signal));
+					service.remove_cgroup();
+					if starting {
+						error!(self.logger, "service was killed before becoming ready"; "service" => service.to_string(), "signal" => signal.to_string());
+						let service = service.clone();
+						drop(services);
+						self.fail_dependents(&service).await;
+					}
+				}
+				WaitStatus::Stopped(_, signal) => {
+					service.set_state(ServiceState::Signaled(pid, signal));
 				}
 				WaitStatus::Continued(_) => {
-					service.state = ServiceState::Running(pid);
+					service.set_state(ServiceState::Running(pid));
 				}
 				_ => {
 					error!(self.logger, "Unknown status: {:?}", status);
@@ -514,22 +1469,49 @@ struct ServiceWaiter {
 
 	/// The remaining dependencies for the service, if any.
 	waiting_dependencies: Vec<Service>,
+
+	/// The devices that the service is still waiting on, if any.
+	waiting_devices: Vec<DeviceSpec>,
+
+	/// When to give up waiting for the devices.
+	device_deadline: Instant,
 }
 
 impl ServiceWaiter {
-	fn new(service: Service, dependencies: Vec<Service>) -> Self {
+	fn new(service: Service, dependencies: Vec<Service>, devices: Vec<DeviceSpec>) -> Self {
+		let device_deadline = Instant::now() + service.device_timeout;
 		Self {
 			service,
 			waiting_dependencies: dependencies,
+			waiting_devices: devices,
+			device_deadline,
 		}
 	}
 
+	/// Remove the devices that now exist, or that the given udev event announces, from the set of devices.
+	fn notify_devices(&mut self, event: Option<&HashMap<String, String>>) {
+		self.waiting_devices
+			.retain(|d| !(event.is_some_and(|e| d.matches_event(e)) || d.exists()));
+	}
+
+	/// Whether the service is still waiting on devices after the deadline for them has passed.
+	fn devices_timed_out(&self, now: Instant) -> bool {
+		!self.waiting_devices.is_empty() && now >= self.device_deadline
+	}
+
+	/// Whether the given service is one of the dependencies that this service is still waiting on.
+	fn waiting_on(&self, service: &Service) -> bool {
+		self.waiting_dependencies
+			.iter()
+			.any(|s| service.matches(&s.name, &s.args))
+	}
+
 	/// Remove the given service from the set of dependencies.
 	fn notify_service_started(&mut self, started: &Service) {
 		self.waiting_dependencies.retain(|s| !started.matches(&s.name, &s.args));
 	}
 
 	fn done(&self) -> bool {
-		self.waiting_dependencies.is_empty()
+		self.waiting_dependencies.is_empty() && self.waiting_devices.is_empty()
 	}
 }
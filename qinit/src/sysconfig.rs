@@ -0,0 +1,231 @@
+use std::{
+	collections::BTreeMap,
+	fs,
+	io::{self, ErrorKind},
+	path::{Path, PathBuf},
+};
+
+/// The directories that sysctl services read `.conf` files from by default, most important first.
+pub const SYSCTL_DIRECTORIES: [&str; 5] = [
+	"/etc/sysctl.d",
+	"/run/sysctl.d",
+	"/usr/local/lib/sysctl.d",
+	"/usr/lib/sysctl.d",
+	"/lib/sysctl.d",
+];
+
+/// The file that sysctl services read after the files in the `SYSCTL_DIRECTORIES`, so its values win.
+pub const SYSCTL_CONF: &str = "/etc/sysctl.conf";
+
+/// The directories that modules services read `.conf` files from by default, most important first.
+pub const MODULES_LOAD_DIRECTORIES: [&str; 4] = [
+	"/etc/modules-load.d",
+	"/run/modules-load.d",
+	"/usr/local/lib/modules-load.d",
+	"/usr/lib/modules-load.d",
+];
+
+/// The directory that kernel parameters are written to, as files named after the parameter.
+pub const PROC_SYS: &str = "/proc/sys";
+
+/// The paths that sysctl services read by default.
+pub fn default_sysctl_paths() -> Vec<PathBuf> {
+	SYSCTL_DIRECTORIES
+		.iter()
+		.chain([SYSCTL_CONF].iter())
+		.map(PathBuf::from)
+		.collect()
+}
+
+/// The paths that modules services read by default.
+pub fn default_modules_load_paths() -> Vec<PathBuf> {
+	MODULES_LOAD_DIRECTORIES.iter().map(PathBuf::from).collect()
+}
+
+/// Finds the configuration files in the given paths. The `.conf` files in the directories are read in order of their
+/// names, and a file in one directory hides any file with the same name in the directories after it, so that e.g.
+/// `/etc/sysctl.d/10-network.conf` replaces `/usr/lib/sysctl.d/10-network.conf`. Paths that are files are read after
+/// the directories, in the order that they're given. Paths that don't exist are skipped.
+pub fn config_files(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+	let mut in_directories = BTreeMap::new();
+	let mut files = Vec::new();
+	for path in paths {
+		let entries = match fs::read_dir(path) {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == ErrorKind::NotFound => continue,
+			Err(e) if e.kind() == ErrorKind::NotADirectory => {
+				files.push(path.clone());
+				continue;
+			}
+			Err(e) => return Err(e),
+		};
+
+		for entry in entries {
+			let path = entry?.path();
+			if path.extension().is_some_and(|extension| extension == "conf") {
+				if let Some(name) = path.file_name() {
+					in_directories.entry(name.to_owned()).or_insert(path);
+				}
+			}
+		}
+	}
+
+	Ok(in_directories.into_values().chain(files).collect())
+}
+
+/// The lines of a configuration file that aren't blank or comments, which start with `#` or `;`.
+fn config_lines(contents: &str) -> impl Iterator<Item = &str> {
+	contents
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with(['#', ';']))
+}
+
+/// A kernel parameter to set, from a line of a sysctl.d file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sysctl {
+	/// The name of the parameter, with `/` separators, e.g. `net/ipv4/ip_forward`.
+	pub key: String,
+	pub value: String,
+
+	/// Whether failing to set the parameter is ignored, which is written as a `-` before the key, e.g. for parameters
+	/// of modules that might not be loaded.
+	pub ignore_failure: bool,
+}
+
+impl Sysctl {
+	/// The file in /proc/sys that the parameter is set through.
+	pub fn path(&self) -> PathBuf {
+		Path::new(PROC_SYS).join(&self.key)
+	}
+
+	/// Sets the parameter.
+	pub fn apply(&self) -> io::Result<()> {
+		fs::write(self.path(), &self.value)
+	}
+}
+
+/// Parses the contents of a sysctl.d file, which has `key = value` lines. Keys are separated by `.`s, or by `/`s if
+/// that comes first, so that parts of the key can have `.`s in them (e.g. `net/ipv4/conf/eth0.100/forwarding`).
+pub fn parse_sysctl(contents: &str) -> Result<Vec<Sysctl>, String> {
+	let mut sysctls = Vec::new();
+	for line in config_lines(contents) {
+		let (key, value) = line
+			.split_once('=')
+			.ok_or_else(|| format!("invalid sysctl line `{}`: expected key = value", line))?;
+		let (key, ignore_failure) = match key.trim().strip_prefix('-') {
+			Some(key) => (key.trim(), true),
+			None => (key.trim(), false),
+		};
+
+		let slash_separated = key
+			.find('/')
+			.is_some_and(|slash| key.find('.').is_none_or(|dot| slash < dot));
+		let key = if slash_separated {
+			key.to_owned()
+		} else {
+			key.replace('.', "/")
+		};
+
+		if key.is_empty() || key.split('/').any(|part| part.is_empty() || part == "..") {
+			return Err(format!("invalid sysctl line `{}`: invalid key", line));
+		}
+
+		sysctls.push(Sysctl {
+			key,
+			value: value.trim().to_owned(),
+			ignore_failure,
+		});
+	}
+
+	Ok(sysctls)
+}
+
+/// Parses the contents of a modules-load.d file, which has the name of a module to load on each line.
+pub fn parse_modules_load(contents: &str) -> Vec<String> {
+	config_lines(contents).map(str::to_owned).collect()
+}
+
+#[cfg(test)]
+mod test {
+	use std::{fs, path::PathBuf};
+
+	use common::testing::TempDir;
+
+	use super::{config_files, parse_modules_load, parse_sysctl, Sysctl};
+
+	#[test]
+	fn test_parse_sysctl() {
+		let sysctls = parse_sysctl(
+			"# Forwarding\nnet.ipv4.ip_forward = 1\n; more\n\n-net.ipv4.conf.all.rp_filter=2\nnet/ipv4/conf/eth0.100/forwarding = 1\nkernel.printk = 3 4 1 3\n",
+		)
+		.unwrap();
+
+		assert_eq!(
+			sysctls,
+			vec![
+				Sysctl {
+					key: String::from("net/ipv4/ip_forward"),
+					value: String::from("1"),
+					ignore_failure: false,
+				},
+				Sysctl {
+					key: String::from("net/ipv4/conf/all/rp_filter"),
+					value: String::from("2"),
+					ignore_failure: true,
+				},
+				Sysctl {
+					key: String::from("net/ipv4/conf/eth0.100/forwarding"),
+					value: String::from("1"),
+					ignore_failure: false,
+				},
+				Sysctl {
+					key: String::from("kernel/printk"),
+					value: String::from("3 4 1 3"),
+					ignore_failure: false,
+				},
+			]
+		);
+		assert_eq!(sysctls[0].path(), PathBuf::from("/proc/sys/net/ipv4/ip_forward"));
+
+		assert!(parse_sysctl("net.ipv4.ip_forward\n").is_err());
+		assert!(parse_sysctl("= 1\n").is_err());
+		assert!(parse_sysctl("net/../../etc/passwd = 1\n").is_err());
+	}
+
+	#[test]
+	fn test_parse_modules_load() {
+		assert_eq!(
+			parse_modules_load("# Load at boot\nloop\n\n  br_netfilter  \n; tun\n"),
+			vec!["loop", "br_netfilter"]
+		);
+	}
+
+	#[test]
+	fn test_config_files() {
+		let root = TempDir::new("qinit-config-files").unwrap();
+		let (etc, lib) = (root.join("etc"), root.join("lib"));
+		fs::create_dir_all(&etc).unwrap();
+		fs::create_dir_all(&lib).unwrap();
+		for path in [
+			etc.join("20-local.conf"),
+			etc.join("README"),
+			lib.join("10-defaults.conf"),
+			lib.join("20-local.conf"),
+			root.join("extra.conf"),
+		] {
+			fs::write(path, "").unwrap();
+		}
+
+		let files = config_files(&[etc.clone(), root.join("missing"), root.join("extra.conf"), lib.clone()]).unwrap();
+
+		assert_eq!(
+			files,
+			vec![
+				lib.join("10-defaults.conf"),
+				etc.join("20-local.conf"),
+				root.join("extra.conf")
+			]
+		);
+	}
+}
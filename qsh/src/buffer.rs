@@ -1,146 +1,321 @@
-use std::{
-	cmp::Ordering,
-	io::{self, Read, Write},
+use std::io::{self, Read, Write};
+
+use common::{
+	io::{terminal_size, STDOUT_FD},
+	lineedit::{Key, LineEditor},
 };
+use escapes::Capabilities;
+use tables::RowTable;
 
-use escapes::{ANSIEscapeSequence, CursorBack, CursorForward, EraseInLine, ESC};
+use crate::{
+	completion::{common_prefix, Completer},
+	history::History,
+};
 
-// The ASCII character for DEL.
-const DELETE_CHAR: char = '\u{7f}';
+// The width that completion candidates are listed in if the width of the terminal can't be found.
+const DEFAULT_TERMINAL_WIDTH: usize = 80;
 
-/// Buffer is a wrapper around a Read that handles terminal IO.
+/// Buffer reads lines from the terminal, adding history and completion to the basic line editing.
 pub struct Buffer<R: Read, W: Write> {
-	/// The currently buffered input.
-	buffer: String,
+	editor: LineEditor<R, W>,
+
+	/// The lines that have been read, which can be recalled with the arrow keys or Ctrl-R.
+	history: History,
 
-	/// The position of the cursor in the buffer.
-	position: usize,
-	reader: R,
-	writer: W,
+	/// The entry of the history that's in the buffer, if the user has moved up into the history.
+	history_index: Option<usize>,
+
+	/// The line that was being edited before moving into the history, to restore when moving back out of it.
+	draft: String,
+
+	/// Completes the word under the cursor when Tab is pressed.
+	completer: Completer,
 }
 
 impl<R: Read, W: Write> Buffer<R, W> {
 	pub fn new(reader: R, writer: W) -> Self {
 		Buffer {
-			buffer: String::new(),
-			position: 0,
-			reader,
-			writer,
+			editor: LineEditor::new(reader, writer),
+			history: History::default(),
+			history_index: None,
+			draft: String::new(),
+			completer: Completer::default(),
 		}
 	}
 
+	pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+		self.editor = self.editor.with_capabilities(capabilities);
+		self
+	}
+
+	pub fn set_completer(&mut self, completer: Completer) {
+		self.completer = completer;
+	}
+
+	pub fn with_history(mut self, history: History) -> Self {
+		self.history = history;
+		self
+	}
+
+	pub fn history(&self) -> &History {
+		&self.history
+	}
+
 	/// Read a line from the buffer.
 	pub fn read(&mut self, prompt: &str) -> io::Result<String> {
-		write!(self.writer, "{}", prompt).expect("Failed to write to stdout");
+		self.editor.start(prompt);
+		let mut last_was_tab = false;
 		loop {
-			let c = self.read_char()?;
-			if c == '\n' {
-				writeln!(self.writer).expect("Failed to write to stdout");
-				return Ok(self.flush());
-			} else if c == DELETE_CHAR {
-				self.backspace();
-			} else if c == ESC {
-				self.handle_escape_sequence()?;
-			} else {
-				self.push_char(c);
+			let key = self.editor.read_key()?;
+			match key {
+				Key::Enter => return Ok(self.finish()),
+				Key::Tab => self.complete(last_was_tab),
+				Key::Ctrl('r') => {
+					if let Some(line) = self.reverse_search()? {
+						return Ok(line);
+					}
+				}
+				key => self.handle_key(key),
 			}
+
+			last_was_tab = key == Key::Tab;
 		}
 	}
 
-	/// Handle an ANSI escape sequence.
-	fn handle_escape_sequence(&mut self) -> io::Result<()> {
-		let escape = ANSIEscapeSequence::read(&mut self.reader).map_err(|e| {
-			io::Error::new(
-				io::ErrorKind::InvalidData,
-				format!("Failed to parse ANSI escape sequence: {}", e),
-			)
-		})?;
-
-		match escape {
-			ANSIEscapeSequence::CursorForward(amt) => self.move_cursor(amt.0 as isize),
-			ANSIEscapeSequence::CursorBack(amt) => self.move_cursor(-(amt.0 as isize)),
-			_ => (),
+	/// Handles a key that doesn't finish the line, moving through the history, or editing the line.
+	fn handle_key(&mut self, key: Key) {
+		match key {
+			Key::Up => self.history_previous(),
+			Key::Down => self.history_next(),
+			key => {
+				self.editor.edit(key);
+			}
 		}
-
-		Ok(())
 	}
 
-	/// Move the cursor by the given amount across the buffer.
-	fn move_cursor(&mut self, amt: isize) {
-		// Find the new position and clamp it to the bounds of the buffer.
-		let mut new_position = self.position as isize + amt;
-		if new_position < 0 {
-			new_position = 0;
-		} else if new_position > self.buffer.len() as isize {
-			new_position = self.buffer.len() as isize;
-		}
+	/// Completes the word under the cursor as far as it can be, i.e. to the candidate if there's only one, or the
+	/// prefix that all of the candidates share. If that doesn't add anything, a second Tab lists the candidates.
+	fn complete(&mut self, list: bool) {
+		let position = self.editor.position();
+		let (start, candidates) = self.completer.complete(self.editor.line(), position);
+		let word = &self.editor.line()[start..position];
 
-		let new_position = new_position as usize;
+		let completion = match candidates.as_slice() {
+			[] => return,
+			// Finish the word, unless it's a directory that completion can continue into.
+			[candidate] if !candidate.ends_with('/') => format!("{} ", candidate),
+			_ => common_prefix(&candidates).to_owned(),
+		};
 
-		match new_position.cmp(&self.position) {
-			Ordering::Less => write!(self.writer, "{}", CursorBack((self.position - new_position) as u8))
-				.expect("Failed to write to stdout"),
-			Ordering::Greater => write!(self.writer, "{}", CursorForward((new_position - self.position) as u8))
-				.expect("Failed to write to stdout"),
-			Ordering::Equal => (),
+		if let Some(rest) = completion.strip_prefix(word).filter(|rest| !rest.is_empty()) {
+			let rest = rest.to_owned();
+			self.editor.insert_str(&rest);
+		} else if list && candidates.len() > 1 {
+			self.list_candidates(&candidates);
 		}
-
-		self.position = new_position;
 	}
 
-	/// Add a character to the buffer at the current position.
-	fn push_char(&mut self, c: char) {
-		if self.position == self.buffer.len() {
-			self.buffer.push(c);
-		} else {
-			self.buffer.insert(self.position, c);
+	/// Lists the completion candidates below the line, by their file name, and then redraws the line under them.
+	fn list_candidates(&mut self, candidates: &[String]) {
+		let width = terminal_size(STDOUT_FD).map_or(DEFAULT_TERMINAL_WIDTH, |(_, columns)| columns);
+		let mut table = RowTable::new(width);
+		for candidate in candidates {
+			let name = candidate.trim_end_matches('/');
+			let name = &candidate[name.rfind('/').map_or(0, |index| index + 1)..];
+			if table.add_value(name.to_owned()).is_err() {
+				// A candidate wider than the terminal can't be aligned, so it's only listed on its own.
+				return;
+			}
 		}
 
-		self.position += 1;
-		self.rerender();
+		self.editor.print_above(&table.to_string());
+	}
+
+	/// Ends the line, adding it to the history and returning it.
+	fn finish(&mut self) -> String {
+		let line = self.editor.finish();
+		self.history.push(&line);
+		self.history_index = None;
+		self.draft.clear();
+		line
+	}
+
+	/// Replaces the line with the previous entry in the history, remembering the line being edited if this is the
+	/// first move into the history.
+	fn history_previous(&mut self) {
+		let index = match self.history_index {
+			Some(0) => return,
+			Some(index) => index - 1,
+			None if self.history.is_empty() => return,
+			None => {
+				self.draft = self.editor.line().to_owned();
+				self.history.len() - 1
+			}
+		};
+
+		self.history_index = Some(index);
+		let line = self.history.get(index).unwrap_or_default().to_owned();
+		self.editor.replace_line(&line);
 	}
 
-	// Remove a character from the buffer at the current position.
-	fn backspace(&mut self) {
-		if self.position == 0 {
+	/// Replaces the line with the next entry in the history, or the line that was being edited after the last one.
+	fn history_next(&mut self) {
+		let Some(index) = self.history_index else {
 			return;
+		};
+
+		let line = match self.history.get(index + 1) {
+			Some(line) => {
+				self.history_index = Some(index + 1);
+				line.to_owned()
+			}
+			None => {
+				self.history_index = None;
+				std::mem::take(&mut self.draft)
+			}
+		};
+
+		self.editor.replace_line(&line);
+	}
+
+	/// Searches back through the history for lines containing what the user types. Ctrl-R moves to the next older
+	/// match, Enter runs the match (which is returned), Ctrl-G cancels the search, and anything else stops searching,
+	/// leaving the match in the buffer to be edited.
+	fn reverse_search(&mut self) -> io::Result<Option<String>> {
+		let original = self.editor.line().to_owned();
+		let mut query = String::new();
+		let mut found = None;
+		loop {
+			let matched = found
+				.and_then(|index| self.history.get(index))
+				.unwrap_or_default()
+				.to_owned();
+			let failing = if matched.contains(&query) { "" } else { "failing " };
+			self.editor
+				.write_line(&format!("({}reverse-i-search)`{}': {}", failing, query, matched));
+
+			match self.editor.read_key()? {
+				Key::Ctrl('r') => {
+					found = self
+						.history
+						.search(&query, found.unwrap_or(self.history.len()))
+						.or(found)
+				}
+				Key::Ctrl('g') => {
+					self.editor.redraw(original);
+					return Ok(None);
+				}
+				Key::Backspace => {
+					query.pop();
+					found = self.history.search(&query, self.history.len());
+				}
+				Key::Enter => {
+					self.editor.redraw(matched);
+					return Ok(Some(self.finish()));
+				}
+				Key::Char(c) => {
+					query.push(c);
+					// The current match is searched again, as it may still match the longer query.
+					found = self
+						.history
+						.search(&query, found.map_or(self.history.len(), |index| index + 1))
+						.or(found);
+				}
+				// Other control characters only stop searching, but keys sent as escape sequences (e.g. the arrow keys)
+				// act on the match.
+				Key::Ctrl(_) | Key::Tab => {
+					self.editor.redraw(matched);
+					return Ok(None);
+				}
+				key => {
+					self.editor.redraw(matched);
+					self.handle_key(key);
+					return Ok(None);
+				}
+			}
 		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::{self, create_dir_all};
+
+	use common::testing::TempDir;
+	use escapes::Capabilities;
 
-		if self.position == self.buffer.len() {
-			self.buffer.pop();
-		} else {
-			self.buffer.remove(self.position - 1);
+	use super::Buffer;
+	use crate::completion::Completer;
+
+	/// Reads every line from the given input, as if it was typed at the terminal.
+	fn read_lines(input: &[u8]) -> Vec<String> {
+		let mut buffer = Buffer::new(input, Vec::new());
+		let mut lines = Vec::new();
+		while let Ok(line) = buffer.read("$ ") {
+			lines.push(line);
 		}
 
-		write!(self.writer, "{}", CursorBack(2)).expect("Failed to write to stdout");
-		self.position -= 1;
-		self.rerender();
+		lines
 	}
 
-	// Rewrite the current line, starting from the current position.
-	fn rerender(&mut self) {
-		let start = if self.position == 0 { 0 } else { self.position - 1 };
-		write!(self.writer, "{}{}", EraseInLine(0), &self.buffer[start..]).expect("Failed to write to stdout");
+	#[test]
+	fn test_history_navigation() {
+		// Up twice recalls the first line, and down past the end of the history restores the line being edited.
+		let lines = read_lines(b"ls\npwd\n\x1b[A\x1b[A\nec\x1b[A\x1b[Bho\n");
+		assert_eq!(lines, vec!["ls", "pwd", "ls", "echo"]);
 
-		// After rewriting a line, we are at the end of it. If we were in the middle of the string, we need to move the cursor back.
-		if self.buffer.len() > self.position {
-			write!(self.writer, "{}", CursorBack((self.buffer.len() - self.position) as u8))
-				.expect("Failed to write to stdout");
-		}
+		// Up stops at the oldest entry, and down does nothing outside of the history.
+		let lines = read_lines(b"ls\n\x1b[A\x1b[A\x1b[A\n\x1b[Bcd\n");
+		assert_eq!(lines, vec!["ls", "ls", "cd"]);
+	}
+
+	#[test]
+	fn test_reverse_search() {
+		// Ctrl-R finds the latest match, and again finds the one before it.
+		let lines = read_lines(b"ls -l\npwd\nls -a\n\x12ls\n\x12ls\x12\n");
+		assert_eq!(lines, vec!["ls -l", "pwd", "ls -a", "ls -a", "ls -l"]);
+
+		// Ctrl-G cancels the search, and other keys stop searching to edit the match.
+		let lines = read_lines(b"echo hi\nab\x12ec\x07c\n\x12hi\x01 there\n");
+		assert_eq!(lines, vec!["echo hi", "abc", "echo hi there"]);
 	}
 
-	/// Flush the buffer and return the contents.
-	fn flush(&mut self) -> String {
-		let buffer = self.buffer.clone();
-		self.buffer.clear();
-		self.position = 0;
-		buffer
+	#[test]
+	fn test_completion() {
+		let dir = TempDir::new("qsh-buffer-completion").unwrap();
+		create_dir_all(dir.join("src")).unwrap();
+		fs::write(dir.join("src/main.rs"), "").unwrap();
+		fs::write(dir.join("src/mod.rs"), "").unwrap();
+
+		// Completes to the only candidate, into directories, and as far as the candidates agree.
+		let input = format!("cle\tcat {}/s\t\ta\t\nca\x1b[D\x1b[Dex\t\n", dir.display());
+		let mut buffer = Buffer::new(input.as_bytes(), Vec::new());
+		buffer.set_completer(Completer::new(
+			vec![String::from("clear"), String::from("exit")],
+			String::new(),
+		));
+
+		assert_eq!(
+			buffer.read("$ ").unwrap(),
+			format!("clear cat {}/src/main.rs ", dir.display())
+		);
+
+		// Completion in the middle of the line inserts at the cursor.
+		assert_eq!(buffer.read("$ ").unwrap(), "exit ca");
 	}
 
-	/// Read a single character from the input.
-	fn read_char(&mut self) -> io::Result<char> {
-		let mut char_buffer = [0; 1];
-		self.reader.read_exact(&mut char_buffer)?;
-		Ok(char_buffer[0] as char)
+	#[test]
+	fn test_dumb_terminal() {
+		// Edits rewrite the line with carriage returns and backspaces, rather than escape sequences.
+		let mut output = Vec::new();
+		let mut buffer =
+			Buffer::new(&b"abd\x1b[D\x1b[Dc\x7f\x1b[C\x7f\n"[..], &mut output).with_capabilities(Capabilities::DUMB);
+		assert_eq!(buffer.read("$ ").unwrap(), "ad");
+		drop(buffer);
+
+		assert_eq!(
+			String::from_utf8(output).unwrap(),
+			"$ abd\x08\x08\r$ acbd\x08\x08\r$ abd \x08\x08\x08b\r$ ad \x08\x08\n"
+		);
 	}
 }
@@ -0,0 +1,211 @@
+use std::{
+	fs::{self, read_dir},
+	os::unix::fs::PermissionsExt,
+	path::Path,
+};
+
+/// The characters that end a command, so that the word after them is a command name.
+const COMMAND_SEPARATORS: &[char] = &['|', '&', ';'];
+
+/// Completes the word under the cursor: the names of builtins and executables in `$PATH` for the first word of a
+/// command, and file paths otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct Completer {
+	/// The names of the shell's builtins.
+	builtins: Vec<String>,
+
+	/// The value of `$PATH`, searched for executables.
+	path: String,
+}
+
+impl Completer {
+	pub fn new(builtins: Vec<String>, path: String) -> Self {
+		Self { builtins, path }
+	}
+
+	/// Returns where the word ending at the cursor starts in the line, and the words that it could be completed to,
+	/// sorted and without duplicates.
+	pub fn complete(&self, line: &str, position: usize) -> (usize, Vec<String>) {
+		let start = line[..position]
+			.rfind(|c: char| c.is_whitespace() || COMMAND_SEPARATORS.contains(&c))
+			.map_or(0, |index| index + 1);
+		let word = &line[start..position];
+
+		let before = line[..start].trim_end();
+		let is_command = before.is_empty() || before.ends_with(COMMAND_SEPARATORS);
+
+		let mut candidates = if is_command && !word.contains('/') {
+			self.complete_command(word)
+		} else {
+			complete_path(word)
+		};
+
+		candidates.sort();
+		candidates.dedup();
+		(start, candidates)
+	}
+
+	/// Returns the names of all the builtins and executables in `$PATH`.
+	pub fn commands(&self) -> Vec<String> {
+		self.complete_command("")
+	}
+
+	/// Returns the builtins and executables in `$PATH` whose names start with the given prefix.
+	fn complete_command(&self, prefix: &str) -> Vec<String> {
+		let mut candidates: Vec<String> = self
+			.builtins
+			.iter()
+			.filter(|builtin| builtin.starts_with(prefix))
+			.cloned()
+			.collect();
+
+		for dir in self.path.split(':').filter(|dir| !dir.is_empty()) {
+			let Ok(entries) = read_dir(dir) else {
+				continue;
+			};
+
+			for entry in entries.flatten() {
+				let Ok(name) = entry.file_name().into_string() else {
+					continue;
+				};
+
+				if name.starts_with(prefix) && is_executable(&entry.path()) {
+					candidates.push(name);
+				}
+			}
+		}
+
+		candidates
+	}
+}
+
+/// Returns the paths that start with the given (partial) path. Directories end with a `/`, so that completion can
+/// continue into them, and hidden files are only included if the name being completed starts with a `.`.
+fn complete_path(word: &str) -> Vec<String> {
+	let (dir, prefix) = match word.rfind('/') {
+		Some(index) => word.split_at(index + 1),
+		None => ("", word),
+	};
+
+	let Ok(entries) = read_dir(if dir.is_empty() { "." } else { dir }) else {
+		return Vec::new();
+	};
+
+	entries
+		.flatten()
+		.filter_map(|entry| {
+			let name = entry.file_name().into_string().ok()?;
+			if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+				return None;
+			}
+
+			let suffix = if entry.path().is_dir() { "/" } else { "" };
+			Some(format!("{}{}{}", dir, name, suffix))
+		})
+		.collect()
+}
+
+/// Whether the path is a file that can be executed by someone.
+pub fn is_executable(path: &Path) -> bool {
+	fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Returns the longest prefix shared by all of the candidates.
+pub fn common_prefix(candidates: &[String]) -> &str {
+	let Some((first, rest)) = candidates.split_first() else {
+		return "";
+	};
+
+	let mut len = first.len();
+	for candidate in rest {
+		len = first
+			.char_indices()
+			.zip(candidate.chars())
+			.take_while(|((_, a), b)| a == b)
+			.last()
+			.map_or(0, |((index, c), _)| index + c.len_utf8())
+			.min(len);
+	}
+
+	&first[..len]
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		fs::{self, create_dir_all, set_permissions, Permissions},
+		os::unix::fs::PermissionsExt,
+	};
+
+	use common::testing::TempDir;
+
+	use super::{common_prefix, Completer};
+
+	#[test]
+	fn test_complete() {
+		let dir = TempDir::new("qsh-completion").unwrap();
+		let bin = dir.join("bin");
+		create_dir_all(&bin).unwrap();
+		create_dir_all(dir.join("src")).unwrap();
+		for name in ["cat", "cargo", "notexec"] {
+			fs::write(bin.join(name), "").unwrap();
+		}
+		for name in ["cat", "cargo"] {
+			set_permissions(bin.join(name), Permissions::from_mode(0o755)).unwrap();
+		}
+		fs::write(dir.join("Cargo.toml"), "").unwrap();
+		fs::write(dir.join(".hidden"), "").unwrap();
+
+		let completer = Completer::new(
+			vec![String::from("cd"), String::from("clear")],
+			bin.to_str().unwrap().to_owned(),
+		);
+
+		// Commands come from the builtins and $PATH, at the start of the line or after a separator.
+		assert_eq!(
+			completer.complete("c", 1),
+			(
+				0,
+				vec!["cargo", "cat", "cd", "clear"]
+					.into_iter()
+					.map(String::from)
+					.collect()
+			)
+		);
+		assert_eq!(
+			completer.complete("ls | ca", 7),
+			(5, vec![String::from("cargo"), String::from("cat")])
+		);
+		assert_eq!(completer.complete("no", 2), (0, Vec::new()));
+
+		// Other words are paths, with directories ending in a slash.
+		let prefix = format!("{}/", dir.display());
+		let line = format!("cat {}", prefix);
+		assert_eq!(
+			completer.complete(&line, line.len()),
+			(
+				4,
+				["Cargo.toml", "bin/", "src/"]
+					.iter()
+					.map(|name| format!("{}{}", prefix, name))
+					.collect()
+			)
+		);
+
+		let line = format!("cat {}.h", prefix);
+		assert_eq!(
+			completer.complete(&line, line.len()),
+			(4, vec![format!("{}.hidden", prefix)])
+		);
+	}
+
+	#[test]
+	fn test_common_prefix() {
+		let candidates = |names: &[&str]| names.iter().map(|name| String::from(*name)).collect::<Vec<_>>();
+		assert_eq!(common_prefix(&candidates(&["cargo", "cat"])), "ca");
+		assert_eq!(common_prefix(&candidates(&["cat"])), "cat");
+		assert_eq!(common_prefix(&candidates(&["cat", "ls"])), "");
+		assert_eq!(common_prefix(&candidates(&["src/", "src/main.rs"])), "src/");
+		assert_eq!(common_prefix(&[]), "");
+	}
+}
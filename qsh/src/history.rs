@@ -0,0 +1,133 @@
+use std::{
+	fs::{self, OpenOptions},
+	io::{self, Write},
+	os::unix::fs::OpenOptionsExt,
+	path::PathBuf,
+};
+
+/// The name of the history file in the user's home directory, used if `HISTFILE` isn't set.
+pub const HISTORY_FILE: &str = ".qsh_history";
+
+/// The maximum number of lines kept in the history.
+const MAX_HISTORY: usize = 1000;
+
+/// The lines that have been entered into the shell, oldest first.
+#[derive(Default)]
+pub struct History {
+	entries: Vec<String>,
+
+	/// The file that the history is loaded from and saved to, if it's kept between sessions.
+	path: Option<PathBuf>,
+}
+
+impl History {
+	/// Loads the history from the given file. A missing (or unreadable) file is an empty history, that's saved to the
+	/// file later.
+	pub fn load(path: PathBuf) -> Self {
+		let mut history = Self {
+			entries: Vec::new(),
+			path: None,
+		};
+
+		if let Ok(contents) = fs::read_to_string(&path) {
+			for line in contents.lines() {
+				history.push(line);
+			}
+		}
+
+		history.path = Some(path);
+		history
+	}
+
+	/// Saves the history to its file, if it has one. The file is only readable by the user, as commands can contain
+	/// secrets.
+	pub fn save(&self) -> io::Result<()> {
+		let Some(path) = &self.path else {
+			return Ok(());
+		};
+
+		let mut file = OpenOptions::new()
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.mode(0o600)
+			.open(path)?;
+
+		for entry in self.entries.iter() {
+			writeln!(file, "{}", entry)?;
+		}
+
+		Ok(())
+	}
+
+	/// Adds a line to the history. Blank lines, and repeats of the last line, aren't added.
+	pub fn push(&mut self, line: &str) {
+		if line.trim().is_empty() || self.entries.last().is_some_and(|last| last == line) {
+			return;
+		}
+
+		if self.entries.len() >= MAX_HISTORY {
+			self.entries.remove(0);
+		}
+
+		self.entries.push(line.to_owned());
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub fn get(&self, index: usize) -> Option<&str> {
+		self.entries.get(index).map(String::as_str)
+	}
+
+	/// Finds the most recent entry before `before` that contains the query, returning its index.
+	pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+		self.entries[..before.min(self.entries.len())]
+			.iter()
+			.rposition(|entry| entry.contains(query))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use common::testing::TempDir;
+
+	use super::History;
+
+	#[test]
+	fn test_push_and_search() {
+		let mut history = History::default();
+		for line in ["ls", "", "cd /tmp", "cd /tmp", "  ", "ls -la"] {
+			history.push(line);
+		}
+
+		assert_eq!(history.len(), 3);
+		assert_eq!(history.get(1), Some("cd /tmp"));
+
+		assert_eq!(history.search("ls", history.len()), Some(2));
+		assert_eq!(history.search("ls", 2), Some(0));
+		assert_eq!(history.search("ls", 0), None);
+		assert_eq!(history.search("missing", history.len()), None);
+	}
+
+	#[test]
+	fn test_load_and_save() {
+		let directory = TempDir::new("qsh-history").unwrap();
+		let path = directory.join("history");
+		let mut history = History::load(path.clone());
+		assert_eq!(history.len(), 0);
+
+		history.push("echo hello");
+		history.push("pwd");
+		history.save().unwrap();
+
+		let history = History::load(path.clone());
+		assert_eq!(history.len(), 2);
+		assert_eq!(history.get(0), Some("echo hello"));
+	}
+}
@@ -1,24 +1,70 @@
 mod buffer;
+mod completion;
+mod history;
+mod notfound;
 mod parser;
 mod process;
 mod shell;
 
 use std::{
+	fs,
 	io::{stderr, stdin},
 	os::fd::{AsFd, AsRawFd},
 };
 
-use common::obs::assemble_logger;
-use nix::{
-	sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg},
-	unistd,
-};
+use clap::{Arg, Command};
+use common::{lineedit::RawMode, obs::assemble_logger};
+use nix::unistd;
 
 use shell::Shell;
 use slog::error;
 
+/// The exit code when the script to run can't be read, as in other shells.
+const SCRIPT_NOT_FOUND_EXIT_CODE: i32 = 127;
+
 fn main() {
+	let matches = Command::new("qsh")
+		.about("The qos shell")
+		.arg(
+			Arg::new("command")
+				.help("run the given command, instead of a script or reading commands from the terminal")
+				.short('c')
+				.num_args(1),
+		)
+		.arg(
+			Arg::new("arguments")
+				.help("the script to run, and its arguments (or just the arguments, with -c)")
+				.num_args(0..)
+				.trailing_var_arg(true)
+				.allow_hyphen_values(true),
+		)
+		.get_matches();
+
 	let logger = assemble_logger(stderr());
+	let mut arguments: Vec<String> = matches
+		.get_many::<String>("arguments")
+		.map(|arguments| arguments.cloned().collect())
+		.unwrap_or_default();
+
+	if let Some(command) = matches.get_one::<String>("command") {
+		let mut shell = Shell::new().with_positional(arguments);
+		std::process::exit(shell.run_script("-c", command));
+	}
+
+	if !arguments.is_empty() {
+		let path = arguments.remove(0);
+		let script = match fs::read_to_string(&path) {
+			Ok(script) => script,
+			Err(e) => {
+				error!(logger, "Error reading script {}: {}", path, e);
+				std::process::exit(SCRIPT_NOT_FOUND_EXIT_CODE);
+			}
+		};
+
+		let mut shell = Shell::new().with_name(path.clone()).with_positional(arguments);
+		std::process::exit(shell.run_script(&path, &script));
+	}
+
 	let reader = stdin();
 
 	if !isatty(&reader) {
@@ -26,26 +72,21 @@ fn main() {
 		return;
 	}
 
-	let mut attrs = match tcgetattr(&reader) {
-		Ok(attrs) => attrs,
+	// The shell reads input a character at a time, to edit the line itself.
+	let raw = match RawMode::enable(reader.as_fd()) {
+		Ok(raw) => raw,
 		Err(e) => {
-			error!(&logger, "Error getting terminal attributes: {}", e);
+			error!(logger, "Error setting terminal attributes: {}", e);
 			return;
 		}
 	};
 
-	// Disable "Canonical mode" and "Echo".
-	// Canonical mode means that the terminal will buffer input until a newline is received, this allows us to read input one char at a time.
-	// Echo means that the terminal will print input back to the user, this allows us to read input without the user seeing it.
-	attrs.local_flags &= !(LocalFlags::ICANON | LocalFlags::ECHO);
-
-	if let Err(e) = tcsetattr(&reader, SetArg::TCSANOW, &attrs) {
-		error!(logger, "Error setting terminal attributes: {}", e);
-		return;
-	}
-
 	let mut shell = Shell::new();
-	shell.run();
+	let code = shell.run();
+
+	// Exiting doesn't run destructors, so the terminal has to be put back how it was first.
+	drop(raw);
+	std::process::exit(code);
 }
 
 fn isatty<T: AsFd>(fd: T) -> bool {
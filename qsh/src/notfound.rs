@@ -0,0 +1,109 @@
+use std::path::Path;
+
+use crate::completion::{is_executable, Completer};
+
+/// The directories that system binaries are installed to, which often aren't in the `$PATH` of users.
+const SYSTEM_BIN_DIRS: &[&str] = &["/sbin", "/usr/sbin"];
+
+/// The most commands that are suggested in place of one that can't be found.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Returns the lines that help the user find a command that can't be found: where it is if it's a system binary that
+/// isn't in their `$PATH`, or otherwise the builtins and commands in their `$PATH` with similar names.
+pub fn suggestions(command: &str, completer: &Completer) -> Vec<String> {
+	if command.contains('/') {
+		return Vec::new();
+	}
+
+	let system_binary = SYSTEM_BIN_DIRS
+		.iter()
+		.map(|dir| Path::new(dir).join(command))
+		.find(|path| is_executable(path));
+	if let Some(path) = system_binary {
+		return vec![format!("{} is at {}, which isn't in $PATH", command, path.display())];
+	}
+
+	let similar = similar_commands(command, completer.commands());
+	if similar.is_empty() {
+		return Vec::new();
+	}
+
+	vec![format!("did you mean: {}?", similar.join(", "))]
+}
+
+/// Returns the commands that are only a few typos away from the given one, closest first.
+fn similar_commands(command: &str, commands: Vec<String>) -> Vec<String> {
+	// Short names are only a couple of edits away from lots of other short names.
+	let max_distance = (command.chars().count() / 3).clamp(1, 2);
+	let mut similar: Vec<(usize, String)> = commands
+		.into_iter()
+		.filter(|candidate| candidate != command)
+		.map(|candidate| (edit_distance(command, &candidate), candidate))
+		.filter(|(distance, _)| *distance <= max_distance)
+		.collect();
+
+	similar.sort();
+	similar.dedup();
+	similar
+		.into_iter()
+		.take(MAX_SUGGESTIONS)
+		.map(|(_, candidate)| candidate)
+		.collect()
+}
+
+/// The number of insertions, deletions, substitutions, and swaps of adjacent characters that it takes to turn one
+/// string into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+	let a: Vec<char> = a.chars().collect();
+	let b: Vec<char> = b.chars().collect();
+
+	// distances[i][j] is the distance between the first i characters of a, and the first j characters of b.
+	let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+	for (i, row) in distances.iter_mut().enumerate() {
+		row[0] = i;
+	}
+	for (j, distance) in distances[0].iter_mut().enumerate() {
+		*distance = j;
+	}
+
+	for i in 1..=a.len() {
+		for j in 1..=b.len() {
+			let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+			let mut distance = substitution.min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1);
+			if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+				distance = distance.min(distances[i - 2][j - 2] + 1);
+			}
+
+			distances[i][j] = distance;
+		}
+	}
+
+	distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{edit_distance, similar_commands};
+
+	#[test]
+	fn test_edit_distance() {
+		assert_eq!(edit_distance("ls", "ls"), 0);
+		assert_eq!(edit_distance("sl", "ls"), 1);
+		assert_eq!(edit_distance("gerp", "grep"), 1);
+		assert_eq!(edit_distance("mkdr", "mkdir"), 1);
+		assert_eq!(edit_distance("kitten", "sitting"), 3);
+		assert_eq!(edit_distance("", "cat"), 3);
+	}
+
+	#[test]
+	fn test_similar_commands() {
+		let commands = ["ls", "lsblk", "cat", "cd", "grep", "ps", "ls"]
+			.map(String::from)
+			.to_vec();
+		assert_eq!(similar_commands("sl", commands.clone()), vec!["ls"]);
+		assert_eq!(similar_commands("lsbk", commands.clone()), vec!["lsblk"]);
+		assert_eq!(similar_commands("cta", commands.clone()), vec!["cat"]);
+		assert_eq!(similar_commands("cs", commands.clone()), vec!["cd", "ls", "ps"]);
+		assert!(similar_commands("qtop", commands).is_empty());
+	}
+}
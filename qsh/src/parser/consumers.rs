@@ -35,6 +35,30 @@ impl Consumer for Whitespace {
 	}
 }
 
+// Consumes a sequence of whitespace characters on the same line, i.e. not newlines, which separate commands.
+#[derive(Debug)]
+struct InlineWhitespace;
+
+impl Consumer for InlineWhitespace {
+	fn try_consume(input: &[char], start: usize) -> ParserResult<Self> {
+		let literal: String = input[start..]
+			.iter()
+			.take_while(|c| c.is_whitespace() && **c != '\n')
+			.collect();
+
+		if literal.is_empty() {
+			return Ok(None);
+		}
+
+		Ok(Some(Token {
+			length: literal.chars().count(),
+			literal,
+			start,
+			token: InlineWhitespace,
+		}))
+	}
+}
+
 // Consumes a single escaped character, e.g. "\x". Doesn't concern itself
 // with whether its a valid escape sequence or not, just that it's a \ followed by another character.
 #[derive(Debug)]
@@ -154,17 +178,17 @@ impl<const QUOTE: char> Consumer for EscapedStringChar<QUOTE> {
 			}))
 		} else if has_available_chars(input, start, 2) && input[start] == '\\' {
 			if input[start + 1] == QUOTE {
-				return Ok(Some(Token {
+				Ok(Some(Token {
 					literal: input[start..start + 2].iter().collect::<String>(),
 					start,
 					length: 2,
 					token: EscapedStringChar { decoded: QUOTE },
-				}));
+				}))
 			} else {
-				return Err(ParserError::new(
+				Err(ParserError::new(
 					&format!("Invalid escape sequence: \\{}", input[start + 1]),
 					start,
-				));
+				))
 			}
 		} else {
 			Ok(None)
@@ -224,7 +248,7 @@ pub type SingleQuotedString = QuotedString<'\''>;
 // Consumes a double quoted string, with escapes. e.g. "hello world", "foo\\", etc.
 pub type DoubleQuotedString = QuotedString<'"'>;
 
-// Consumes a single character that is not whitespace, a quote, or a backslash.
+// Consumes a single character that is not whitespace, a quote, a backslash, or a control operator.
 #[derive(Debug)]
 struct UnquotedCharacter {
 	decoded: char,
@@ -233,7 +257,7 @@ struct UnquotedCharacter {
 impl Consumer for UnquotedCharacter {
 	fn try_consume(input: &[char], start: usize) -> ParserResult<Self> {
 		let c = &input[start];
-		if c.is_whitespace() || c == &'\'' || c == &'"' || c == &'\\' || c == &'|' {
+		if c.is_whitespace() || c == &'\'' || c == &'"' || c == &'\\' || c == &'|' || c == &'&' || c == &';' {
 			return Ok(None);
 		}
 
@@ -374,6 +398,77 @@ impl Consumer for Pipe {
 	}
 }
 
+// Consumes the `&` at the end of a pipeline that runs it in the background.
+#[derive(Debug, PartialEq)]
+pub struct Ampersand;
+
+impl Consumer for Ampersand {
+	fn try_consume(input: &[char], start: usize) -> ParserResult<Self> {
+		if input[start] == '&' {
+			Ok(Some(Token {
+				literal: "&".to_string(),
+				start,
+				length: 1,
+				token: Ampersand,
+			}))
+		} else {
+			Ok(None)
+		}
+	}
+}
+
+// Consumes the `&&` or `||` that joins two pipelines, running the second only if the first succeeded or failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListOperator {
+	And,
+	Or,
+}
+
+impl Consumer for ListOperator {
+	fn try_consume(input: &[char], start: usize) -> ParserResult<Self> {
+		if !has_available_chars(input, start, 2) {
+			return Ok(None);
+		}
+
+		let token = match (input[start], input[start + 1]) {
+			('&', '&') => ListOperator::And,
+			('|', '|') => ListOperator::Or,
+			_ => return Ok(None),
+		};
+
+		Ok(Some(Token {
+			literal: input[start..start + 2].iter().collect(),
+			start,
+			length: 2,
+			token,
+		}))
+	}
+}
+
+// The reserved words that end a list of commands inside a compound command, e.g. the `then` after an `if` condition.
+const TERMINATING_WORDS: &[&str] = &["then", "elif", "else", "fi", "do", "done"];
+
+// Whether the given reserved word is at the start of the input, as a whole word.
+fn is_keyword(input: &[char], start: usize, word: &str) -> bool {
+	let len = word.chars().count();
+	has_available_chars(input, start, len)
+		&& input[start..start + len].iter().copied().eq(word.chars())
+		&& input
+			.get(start + len)
+			.is_none_or(|c| c.is_whitespace() || matches!(c, ';' | '&' | '|'))
+}
+
+// Consumes the given reserved word, and the whitespace before it, returning its length (including the whitespace), or
+// an error saying that it was expected.
+fn expect_keyword(input: &[char], start: usize, word: &str) -> Result<usize, ParserError> {
+	let whitespace = Whitespace::try_consume(input, start)?.map_or(0, |token| token.length);
+	if is_keyword(input, start + whitespace, word) {
+		Ok(whitespace + word.len())
+	} else {
+		Err(ParserError::new(&format!("Expected {}", word), start + whitespace))
+	}
+}
+
 // Consumes a string that is made up of component strings. e.g. "/bin/sh -c 'echo hello world'" would be parsed into 3 parts: "/bin/sh", "-c", and "'echo hello world'".
 #[derive(Debug, PartialEq)]
 pub struct Command {
@@ -387,7 +482,7 @@ impl Consumer for Command {
 		let mut length = 0;
 
 		while start + length < input.len() {
-			if let Some(c) = Whitespace::try_consume(input, start + length)? {
+			if let Some(c) = InlineWhitespace::try_consume(input, start + length)? {
 				literal.push_str(&c.literal);
 				length += c.length;
 			} else if let Some(token) = CombinedString::try_consume(input, start + length)? {
@@ -400,7 +495,7 @@ impl Consumer for Command {
 		}
 
 		literal = literal.trim().to_string();
-		length = literal.len();
+		length = literal.chars().count();
 
 		if parts.is_empty() {
 			return Ok(None);
@@ -418,6 +513,9 @@ impl Consumer for Command {
 #[derive(Debug, PartialEq)]
 pub struct Pipeline {
 	pub commands: Vec<Token<Command>>,
+
+	/// Whether the pipeline ends with a `&`, i.e. it should be run in the background.
+	pub background: bool,
 }
 
 impl Consumer for Pipeline {
@@ -431,11 +529,21 @@ impl Consumer for Pipeline {
 			Start,
 			Command,
 			Pipe,
+			Background,
 		}
 
 		let mut state = State::Start;
 		while start + length < input.len() {
 			match state {
+				// A reserved word where a command would start belongs to a compound command around the pipeline.
+				State::Start
+					if TERMINATING_WORDS
+						.iter()
+						.chain(["if", "while"].iter())
+						.any(|word| is_keyword(input, start + length, word)) =>
+				{
+					break;
+				}
 				State::Start | State::Pipe => {
 					if let Some(token) = Whitespace::try_consume(input, start + length)? {
 						length += token.length;
@@ -450,17 +558,36 @@ impl Consumer for Pipeline {
 					}
 				}
 				State::Command => {
-					if let Some(token) = Whitespace::try_consume(input, start + length)? {
+					if let Some(token) = InlineWhitespace::try_consume(input, start + length)? {
 						length += token.length;
 						literal.push_str(&token.literal);
+					} else if ListOperator::try_consume(input, start + length)?.is_some()
+						|| matches!(input[start + length], ';' | '\n')
+					{
+						// The end of the pipeline, which is part of a list.
+						break;
 					} else if let Some(token) = Pipe::try_consume(input, start + length)? {
 						length += token.length;
 						literal.push_str(&token.literal);
 						state = State::Pipe;
+					} else if let Some(token) = Ampersand::try_consume(input, start + length)? {
+						length += token.length;
+						literal.push_str(&token.literal);
+						state = State::Background;
 					} else {
 						return Err(ParserError::new("Expected pipe after command", start + length));
 					}
 				}
+				State::Background => {
+					if let Some(token) = InlineWhitespace::try_consume(input, start + length)? {
+						length += token.length;
+						literal.push_str(&token.literal);
+					} else if matches!(input[start + length], ';' | '\n') {
+						break;
+					} else {
+						return Err(ParserError::new("Unexpected input after &", start + length));
+					}
+				}
 			}
 		}
 
@@ -476,7 +603,226 @@ impl Consumer for Pipeline {
 			literal,
 			start,
 			length,
-			token: Pipeline { commands },
+			token: Pipeline {
+				commands,
+				background: state == State::Background,
+			},
+		}))
+	}
+}
+
+// Consumes a command that can be joined to others with `&&` and `||`: a pipeline, or a compound command.
+#[derive(Debug, PartialEq)]
+pub enum Statement {
+	Pipeline(Pipeline),
+	If(If),
+	While(While),
+}
+
+impl Consumer for Statement {
+	fn try_consume(input: &[char], start: usize) -> ParserResult<Self> {
+		if is_keyword(input, start, "if") {
+			return Ok(If::try_consume(input, start)?.map(|token| token.map(Statement::If)));
+		} else if is_keyword(input, start, "while") {
+			return Ok(While::try_consume(input, start)?.map(|token| token.map(Statement::While)));
+		}
+
+		Ok(Pipeline::try_consume(input, start)?.map(|token| token.map(Statement::Pipeline)))
+	}
+}
+
+// Consumes statements joined by `&&` and `||`, e.g. `mount /boot || echo "failed to mount /boot"`. The operators
+// have equal precedence, and are evaluated left to right.
+#[derive(Debug, PartialEq)]
+pub struct AndOr {
+	pub first: Token<Statement>,
+	pub rest: Vec<(ListOperator, Token<Statement>)>,
+}
+
+impl Consumer for AndOr {
+	fn try_consume(input: &[char], start: usize) -> ParserResult<Self> {
+		let Some(first) = Statement::try_consume(input, start)? else {
+			return Ok(None);
+		};
+
+		let mut length = first.length;
+		let mut rest = Vec::new();
+		loop {
+			let whitespace = InlineWhitespace::try_consume(input, start + length)?.map_or(0, |token| token.length);
+			let Some(operator) = ListOperator::try_consume(input, start + length + whitespace)? else {
+				break;
+			};
+
+			length += whitespace + operator.length;
+
+			// The next statement can be on the next line.
+			length += Whitespace::try_consume(input, start + length)?.map_or(0, |token| token.length);
+			let statement = match has_available_chars(input, start + length, 1) {
+				true => Statement::try_consume(input, start + length)?,
+				false => None,
+			};
+
+			let Some(statement) = statement else {
+				return Err(ParserError::new(
+					&format!("Expected command after {}", operator.literal),
+					start + length,
+				));
+			};
+
+			length += statement.length;
+			rest.push((operator.token, statement));
+		}
+
+		Ok(Some(Token {
+			literal: input[start..start + length].iter().collect(),
+			start,
+			length,
+			token: AndOr { first, rest },
+		}))
+	}
+}
+
+// Consumes a sequence of and-or lists, separated by `;` or newlines, up to the end of the input or a reserved word
+// that ends the list, e.g. the `then` after an `if` condition.
+#[derive(Debug, PartialEq)]
+pub struct List {
+	pub items: Vec<Token<AndOr>>,
+}
+
+impl Consumer for List {
+	fn try_consume(input: &[char], start: usize) -> ParserResult<Self> {
+		let mut items = Vec::new();
+		let mut length = 0;
+		let mut separated = true;
+
+		while start + length < input.len() {
+			if let Some(token) = InlineWhitespace::try_consume(input, start + length)? {
+				length += token.length;
+			} else if matches!(input[start + length], ';' | '\n') {
+				if input[start + length] == ';' && (separated || items.is_empty()) {
+					return Err(ParserError::new("Unexpected ;", start + length));
+				}
+
+				separated = true;
+				length += 1;
+			} else if TERMINATING_WORDS
+				.iter()
+				.any(|word| is_keyword(input, start + length, word))
+			{
+				break;
+			} else if !separated {
+				return Err(ParserError::new("Expected ; or newline", start + length));
+			} else if let Some(token) = AndOr::try_consume(input, start + length)? {
+				length += token.length;
+				items.push(token);
+				separated = false;
+			} else {
+				return Err(ParserError::new("Expected command", start + length));
+			}
+		}
+
+		if items.is_empty() {
+			return Ok(None);
+		}
+
+		Ok(Some(Token {
+			literal: input[start..start + length].iter().collect(),
+			start,
+			length,
+			token: List { items },
+		}))
+	}
+}
+
+// Consumes a list of commands that must be there, for the parts of compound commands, erroring with the given message
+// if it isn't.
+fn expect_list(input: &[char], start: usize, message: &str) -> Result<Token<List>, ParserError> {
+	match List::try_consume(input, start)? {
+		Some(list) => Ok(list),
+		None => Err(ParserError::new(message, start)),
+	}
+}
+
+// Consumes an `if` statement, e.g. `if test -d /boot; then echo yes; elif true; then echo maybe; else echo no; fi`.
+#[derive(Debug, PartialEq)]
+pub struct If {
+	// The conditions, and the commands that are run if they succeed, tried in order.
+	pub branches: Vec<(Token<List>, Token<List>)>,
+
+	// The commands that are run if none of the conditions succeed.
+	pub otherwise: Option<Token<List>>,
+}
+
+impl Consumer for If {
+	fn try_consume(input: &[char], start: usize) -> ParserResult<Self> {
+		if !is_keyword(input, start, "if") {
+			return Ok(None);
+		}
+
+		let mut length = 2;
+		let mut branches = Vec::new();
+		let mut otherwise = None;
+		loop {
+			let condition = expect_list(input, start + length, "Expected condition")?;
+			length += condition.length;
+			length += expect_keyword(input, start + length, "then")?;
+
+			let body = expect_list(input, start + length, "Expected commands after then")?;
+			length += body.length;
+			branches.push((condition, body));
+
+			if is_keyword(input, start + length, "elif") {
+				length += 4;
+				continue;
+			}
+
+			if is_keyword(input, start + length, "else") {
+				length += 4;
+				let body = expect_list(input, start + length, "Expected commands after else")?;
+				length += body.length;
+				otherwise = Some(body);
+			}
+
+			length += expect_keyword(input, start + length, "fi")?;
+			break;
+		}
+
+		Ok(Some(Token {
+			literal: input[start..start + length].iter().collect(),
+			start,
+			length,
+			token: If { branches, otherwise },
+		}))
+	}
+}
+
+// Consumes a `while` loop, e.g. `while test ! -e /run/ready; do sleep 1; done`.
+#[derive(Debug, PartialEq)]
+pub struct While {
+	pub condition: Token<List>,
+	pub body: Token<List>,
+}
+
+impl Consumer for While {
+	fn try_consume(input: &[char], start: usize) -> ParserResult<Self> {
+		if !is_keyword(input, start, "while") {
+			return Ok(None);
+		}
+
+		let mut length = 5;
+		let condition = expect_list(input, start + length, "Expected condition")?;
+		length += condition.length;
+		length += expect_keyword(input, start + length, "do")?;
+
+		let body = expect_list(input, start + length, "Expected commands after do")?;
+		length += body.length;
+		length += expect_keyword(input, start + length, "done")?;
+
+		Ok(Some(Token {
+			literal: input[start..start + length].iter().collect(),
+			start,
+			length,
+			token: While { condition, body },
 		}))
 	}
 }
@@ -833,6 +1179,23 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn test_pipeline_consumer_background() {
+		let chars = "sleep 10 | cat &  ".chars().collect::<Vec<char>>();
+		let token = Pipeline::try_consume(&chars, 0).unwrap().unwrap();
+		assert_eq!(token.token.commands.len(), 2);
+		assert!(token.token.background);
+
+		let chars = "sleep 10".chars().collect::<Vec<char>>();
+		assert!(!Pipeline::try_consume(&chars, 0).unwrap().unwrap().token.background);
+
+		let chars = "sleep 10 & cat".chars().collect::<Vec<char>>();
+		assert!(Pipeline::try_consume(&chars, 0).is_err());
+
+		let chars = "&".chars().collect::<Vec<char>>();
+		assert!(Pipeline::try_consume(&chars, 0).is_err());
+	}
+
 	#[test]
 	fn test_pipeline_consumer_missing_command() {
 		let input = "cat test |";
@@ -840,4 +1203,98 @@ mod tests {
 		let token = Pipeline::try_consume(&chars, 0);
 		assert!(token.is_err(), "Expected failure, but got {:?}", token.unwrap());
 	}
+
+	#[test]
+	fn test_and_or_consumer() {
+		let chars = "mount /boot || echo failed && true".chars().collect::<Vec<char>>();
+		let token = AndOr::try_consume(&chars, 0).unwrap().unwrap();
+		assert_eq!(token.length, chars.len());
+		assert_eq!(token.token.first.literal.trim(), "mount /boot");
+		assert_eq!(
+			token
+				.token
+				.rest
+				.iter()
+				.map(|(operator, statement)| (operator, statement.literal.trim()))
+				.collect::<Vec<_>>(),
+			vec![(&ListOperator::Or, "echo failed"), (&ListOperator::And, "true")]
+		);
+
+		let chars = "true &&".chars().collect::<Vec<char>>();
+		assert!(AndOr::try_consume(&chars, 0).is_err());
+	}
+
+	#[test]
+	fn test_list_consumer() {
+		let chars = "cd /; ls | cat\n\necho done;".chars().collect::<Vec<char>>();
+		let token = List::try_consume(&chars, 0).unwrap().unwrap();
+		assert_eq!(token.length, chars.len());
+		assert_eq!(
+			token
+				.token
+				.items
+				.iter()
+				.map(|item| item.literal.as_str())
+				.collect::<Vec<_>>(),
+			vec!["cd /", "ls | cat", "echo done"]
+		);
+
+		for input in ["; ls", "ls;; cat", "ls &&"] {
+			let chars = input.chars().collect::<Vec<char>>();
+			assert!(
+				List::try_consume(&chars, 0).is_err(),
+				"Expected failure for {:?}",
+				input
+			);
+		}
+	}
+
+	#[test]
+	fn test_if_consumer() {
+		let chars = "if test -d /boot; then echo yes; elif false; then echo maybe; else echo no; fi"
+			.chars()
+			.collect::<Vec<char>>();
+		let token = Statement::try_consume(&chars, 0).unwrap().unwrap();
+		assert_eq!(token.length, chars.len());
+		let Statement::If(statement) = token.token else {
+			panic!("Expected an if, got {:?}", token.token);
+		};
+
+		assert_eq!(statement.branches.len(), 2);
+		assert_eq!(statement.branches[0].0.literal, " test -d /boot; ");
+		assert_eq!(statement.branches[1].1.literal, " echo maybe; ");
+		assert_eq!(statement.otherwise.unwrap().literal, " echo no; ");
+
+		for input in ["if true; then echo yes", "if true; echo yes; fi", "if; then true; fi"] {
+			let chars = input.chars().collect::<Vec<char>>();
+			assert!(If::try_consume(&chars, 0).is_err(), "Expected failure for {:?}", input);
+		}
+
+		// Keywords are only keywords as whole words.
+		let chars = "iffy; fine".chars().collect::<Vec<char>>();
+		let token = List::try_consume(&chars, 0).unwrap().unwrap();
+		assert_eq!(token.token.items.len(), 2);
+	}
+
+	#[test]
+	fn test_while_consumer() {
+		let chars = "while test ! -e /run/ready\ndo\n  sleep 1\ndone && echo ready"
+			.chars()
+			.collect::<Vec<char>>();
+		let token = AndOr::try_consume(&chars, 0).unwrap().unwrap();
+		assert_eq!(token.length, chars.len());
+		let Statement::While(statement) = &token.token.first.token else {
+			panic!("Expected a while, got {:?}", token.token.first.token);
+		};
+
+		assert_eq!(statement.condition.token.items[0].literal, "test ! -e /run/ready");
+		assert_eq!(statement.body.token.items[0].literal, "sleep 1");
+
+		let chars = "while true; do sleep 1".chars().collect::<Vec<char>>();
+		assert!(While::try_consume(&chars, 0).is_err());
+
+		// A stray reserved word ends the list, leaving it unparsed.
+		let chars = "echo hi; done".chars().collect::<Vec<char>>();
+		assert_eq!(List::try_consume(&chars, 0).unwrap().unwrap().literal, "echo hi; ");
+	}
 }
@@ -33,6 +33,18 @@ pub struct Token<T> {
 	pub token: T,
 }
 
+impl<T> Token<T> {
+	/// Converts the token into another kind of token, covering the same input.
+	pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Token<U> {
+		Token {
+			literal: self.literal,
+			start: self.start,
+			length: self.length,
+			token: f(self.token),
+		}
+	}
+}
+
 pub type ParserResult<T> = Result<Option<Token<T>>, ParserError>;
 
 pub trait Consumer {
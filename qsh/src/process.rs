@@ -1,13 +1,25 @@
-use std::{ffi::CString, io};
+use std::{
+	env,
+	ffi::CString,
+	fs::OpenOptions,
+	io,
+	os::{fd::IntoRawFd, unix::fs::OpenOptionsExt},
+	path::PathBuf,
+};
 
 use nix::{
 	errno::Errno,
-	sys::wait::{waitid, Id, WaitPidFlag, WaitStatus},
-	unistd::{close, dup2, execvp, fork, setpgid, ForkResult, Pid},
+	sys::{
+		signal::{killpg, signal, SigHandler, Signal},
+		wait::{waitid, Id, WaitPidFlag, WaitStatus},
+	},
+	unistd::{close, dup2, execvp, fork, isatty, setpgid, ForkResult, Pid},
 };
 
 use common::io::{IOTriple, STDERR_FD, STDIN_FD, STDOUT_FD};
 
+use crate::{completion::Completer, notfound};
+
 use thiserror::Error;
 
 /// The exit code of a process.
@@ -41,11 +53,20 @@ pub enum ProcessState {
 	Terminated(ExitCode),
 }
 
+/// The file that the output of `nohup`ed processes is appended to, if it would otherwise go to a terminal.
+const NOHUP_OUTPUT_FILE: &str = "nohup.out";
+
 // A process that can be started and waited on.
 #[derive(Debug)]
 pub struct Process {
 	pub argv: Vec<String>,
 	pub state: ProcessState,
+
+	/// Whether the process ignores SIGHUP, so that it survives the shell exiting.
+	pub nohup: bool,
+
+	/// The commands that are suggested instead, if the command can't be found.
+	suggestions: Option<Completer>,
 }
 
 impl Process {
@@ -53,6 +74,54 @@ impl Process {
 		Process {
 			argv,
 			state: ProcessState::Unstarted,
+			nohup: false,
+			suggestions: None,
+		}
+	}
+
+	/// Suggests where the command might be, or what might have been meant instead, from the commands that the
+	/// completer knows about, if it can't be found.
+	pub fn with_suggestions(mut self, completer: Completer) -> Self {
+		self.suggestions = Some(completer);
+		self
+	}
+
+	/// Makes the process ignore SIGHUP, and redirects its output away from the terminal (which is going away).
+	pub fn with_nohup(mut self) -> Self {
+		self.nohup = true;
+		self
+	}
+
+	/// Ignores SIGHUP, and redirects stdout and stderr to `nohup.out` (in the current directory, or $HOME if that
+	/// isn't writable) if they are a terminal. Ignored signals stay ignored across `exec`.
+	fn detach_from_terminal() {
+		unsafe { signal(Signal::SIGHUP, SigHandler::SigIgn) }.unwrap();
+
+		if isatty(STDOUT_FD).unwrap_or(false) {
+			let mut paths = vec![PathBuf::from(NOHUP_OUTPUT_FILE)];
+			if let Ok(home) = env::var("HOME") {
+				paths.push(PathBuf::from(home).join(NOHUP_OUTPUT_FILE));
+			}
+
+			let file = paths
+				.iter()
+				.find_map(|path| OpenOptions::new().create(true).append(true).mode(0o600).open(path).ok());
+
+			match file {
+				Some(file) => {
+					let fd = file.into_raw_fd();
+					dup2(fd, STDOUT_FD).unwrap();
+					close(fd).unwrap();
+				}
+				None => {
+					eprintln!("qsh: nohup: failed to open {}", NOHUP_OUTPUT_FILE);
+					std::process::exit(127);
+				}
+			}
+		}
+
+		if isatty(STDERR_FD).unwrap_or(false) {
+			dup2(STDOUT_FD, STDERR_FD).unwrap();
 		}
 	}
 
@@ -74,6 +143,10 @@ impl Process {
 			close(triple.stderr).unwrap();
 		}
 
+		if self.nohup {
+			Self::detach_from_terminal();
+		}
+
 		let filename = CString::new(self.argv[0].as_str()).unwrap();
 		let args: Vec<CString> = self
 			.argv
@@ -81,25 +154,26 @@ impl Process {
 			.map(|arg| CString::new(arg.as_str()).unwrap())
 			.collect();
 
-		if let Err(e) = execvp(&filename, &args) {
-			if e == Errno::ENOENT {
-				std::process::exit(127);
+		// execvp only ever returns on failure.
+		let Err(e) = execvp(&filename, &args);
+		if e == Errno::ENOENT {
+			eprintln!("qsh: {}: command not found", self.argv[0]);
+			if let Some(completer) = &self.suggestions {
+				for suggestion in notfound::suggestions(&self.argv[0], completer) {
+					eprintln!("qsh: {}", suggestion);
+				}
 			}
 
-			std::process::exit(e as i32);
+			std::process::exit(127);
 		}
 
-		// We can never reach this point (because we've `exec`ed), but the compiler doesn't know that.
-		panic!("BUG: exec failed");
+		std::process::exit(e as i32);
 	}
 
 	pub fn handle_wait_status(&mut self, status: WaitStatus) {
 		match status {
 			WaitStatus::Exited(_, code) => {
 				self.state = ProcessState::Terminated(ExitCode::Success(code));
-				if code == 127 {
-					eprintln!("qsh: {}: command not found", self.argv[0]);
-				}
 			}
 			WaitStatus::Signaled(_, signal, _) => {
 				self.state = ProcessState::Terminated(ExitCode::Err(Errno::from_i32(signal as i32)));
@@ -116,14 +190,16 @@ impl Process {
 		unsafe {
 			match fork() {
 				Ok(ForkResult::Parent { child }) => {
-					if let Some(pgid) = pgid {
-						setpgid(child, pgid)?;
-					} else {
-						setpgid(child, child)?;
+					// The child sets its own process group too, so that it's in it before it execs. If it already
+					// has, setting it here fails with EACCES.
+					match setpgid(child, pgid.unwrap_or(child)) {
+						Ok(()) | Err(Errno::EACCES) => {}
+						Err(e) => return Err(e),
 					}
 					self.state = ProcessState::Running(child);
 				}
 				Ok(ForkResult::Child) => {
+					let _ = setpgid(Pid::from_raw(0), pgid.unwrap_or(Pid::from_raw(0)));
 					self.exec(triple);
 				}
 				Err(e) => {
@@ -174,6 +250,9 @@ impl ProcessPipeline {
 	// Execute the pipeline, starting each process in the pipeline.
 	pub fn execute(&mut self, triple: IOTriple) -> Result<(), WaitError> {
 		let (last, rest) = self.processes.split_last_mut().expect("BUG: empty commands");
+
+		// The caller owns the stdin of the pipeline, so it's only the pipes created here that get closed.
+		let input = triple.stdin;
 		let mut triple = triple;
 		let mut pgid = None;
 		for command in rest.iter_mut() {
@@ -189,7 +268,7 @@ impl ProcessPipeline {
 			}
 
 			// Close any pipe file descriptors, because they've been moved into the child process.
-			if write.stdin != STDIN_FD {
+			if write.stdin != STDIN_FD && write.stdin != input {
 				close(write.stdin)?;
 			}
 
@@ -245,14 +324,39 @@ impl ProcessPipeline {
 		None
 	}
 
+	/// The process group ID of the pipeline, if it's running.
+	pub fn pgid(&self) -> Option<Pid> {
+		match self.status {
+			PipelineState::Running(pgid) => Some(pgid),
+			_ => None,
+		}
+	}
+
+	/// Waits for every process in the pipeline to terminate.
 	pub fn wait(&mut self) -> Result<(), WaitError> {
+		self.wait_with_flags(WaitPidFlag::empty())?;
+		Ok(())
+	}
+
+	/// Reaps any processes in the pipeline that have terminated, without blocking. Returns whether the whole
+	/// pipeline has terminated.
+	pub fn try_wait(&mut self) -> Result<bool, WaitError> {
+		self.wait_with_flags(WaitPidFlag::WNOHANG)
+	}
+
+	fn wait_with_flags(&mut self, flags: WaitPidFlag) -> Result<bool, WaitError> {
 		let pgid = match self.status {
 			PipelineState::Running(pgid) => pgid,
-			_ => return Err(WaitError::NotRunning),
+			PipelineState::Terminated => return Ok(true),
+			PipelineState::Unstarted => return Err(WaitError::NotRunning),
 		};
 
 		while !self.has_terminated() {
-			let status = waitid(Id::PGid(pgid), WaitPidFlag::__WALL | WaitPidFlag::WEXITED)?;
+			let status = waitid(Id::PGid(pgid), WaitPidFlag::__WALL | WaitPidFlag::WEXITED | flags)?;
+			if status == WaitStatus::StillAlive {
+				return Ok(false);
+			}
+
 			if let Some(pid) = status.pid() {
 				match self.get_process_by_id(pid) {
 					Some(process) => process.handle_wait_status(status),
@@ -266,6 +370,16 @@ impl ProcessPipeline {
 
 		self.status = PipelineState::Terminated;
 
+		Ok(true)
+	}
+
+	/// Sends SIGHUP to every process in the pipeline, followed by SIGCONT so that stopped processes see it.
+	pub fn hangup(&self) -> Result<(), WaitError> {
+		if let Some(pgid) = self.pgid() {
+			killpg(pgid, Signal::SIGHUP)?;
+			killpg(pgid, Signal::SIGCONT)?;
+		}
+
 		Ok(())
 	}
 }
@@ -1,6 +1,7 @@
-use common::io::IOTriple;
-use escapes::{ANSIEscapeSequence, CursorPosition, EraseInDisplay};
-use std::io::Write;
+use common::io::{terminal_size, IOTriple, STDOUT_FD};
+use escapes::{ANSIEscapeSequence, Capabilities, CursorPosition, EraseInDisplay};
+use nix::unistd::{access, AccessFlags};
+use std::{env, fs, io::Write, path::PathBuf};
 
 use crate::process::WaitError;
 
@@ -9,15 +10,26 @@ use super::Shell;
 /// A builtin command, i.e. a command that runs inside the shell without executing a new process.
 /// This allows closer integration with the shell, such as changing the working directory.
 pub trait Builtin {
-	fn run(&self, args: &[String], triple: IOTriple, shell: &Shell) -> Result<i32, WaitError>;
+	fn run(&self, args: &[String], triple: IOTriple, shell: &mut Shell) -> Result<i32, WaitError>;
 }
 
+/// The number of lines that `clear` scrolls off the screen if the terminal can't be cleared, and its height isn't known.
+const DEFAULT_TERMINAL_HEIGHT: usize = 24;
+
 /// The `clear` builtin, which clears the terminal screen.
 pub struct Clear;
 
 impl Builtin for Clear {
-	fn run(&self, _args: &[String], triple: IOTriple, _shell: &Shell) -> Result<i32, WaitError> {
+	fn run(&self, _args: &[String], triple: IOTriple, _shell: &mut Shell) -> Result<i32, WaitError> {
 		let mut stdout = triple.stdout();
+		if !Capabilities::from_env().cursor_addressing {
+			// The best that can be done without cursor addressing is to scroll everything off the screen.
+			let height = terminal_size(STDOUT_FD).map_or(DEFAULT_TERMINAL_HEIGHT, |(rows, _)| rows);
+			write!(stdout, "{}", "\n".repeat(height))?;
+			stdout.flush()?;
+			return Ok(0);
+		}
+
 		write!(
 			stdout,
 			"{}{}",
@@ -33,7 +45,7 @@ impl Builtin for Clear {
 pub struct Cd;
 
 impl Builtin for Cd {
-	fn run(&self, args: &[String], _triple: IOTriple, _shell: &Shell) -> Result<i32, WaitError> {
+	fn run(&self, args: &[String], _triple: IOTriple, _shell: &mut Shell) -> Result<i32, WaitError> {
 		if args.len() != 2 {
 			eprintln!("cd: expected 1 argument, got {}", args.len() - 1);
 			return Ok(1);
@@ -48,3 +60,437 @@ impl Builtin for Cd {
 		Ok(0)
 	}
 }
+
+/// An argument to `pushd`/`popd`/`dirs` selecting an entry in the directory stack.
+/// `+N` counts from the top of the stack (i.e. the current directory), `-N` counts from the bottom.
+fn parse_stack_index(arg: &str, stack_len: usize) -> Option<Result<usize, String>> {
+	let (from_top, n) = match arg.as_bytes().first() {
+		Some(b'+') => (true, &arg[1..]),
+		Some(b'-') if arg.len() > 1 => (false, &arg[1..]),
+		_ => return None,
+	};
+
+	let n = match n.parse::<usize>() {
+		Ok(n) => n,
+		Err(_) => return Some(Err(format!("{}: invalid number", arg))),
+	};
+
+	if n >= stack_len {
+		return Some(Err(format!("{}: directory stack index out of range", arg)));
+	}
+
+	Some(Ok(if from_top { n } else { stack_len - 1 - n }))
+}
+
+/// Returns the full directory stack, with the current directory at the top.
+fn full_stack(shell: &Shell) -> std::io::Result<Vec<PathBuf>> {
+	let mut stack = vec![env::current_dir()?];
+	stack.extend(shell.dir_stack.iter().cloned());
+	Ok(stack)
+}
+
+/// Changes to the top of the given stack, storing the rest of it as the shells directory stack.
+fn set_stack(shell: &mut Shell, mut stack: Vec<PathBuf>, name: &str) -> bool {
+	let top = stack.remove(0);
+	if let Err(e) = env::set_current_dir(&top) {
+		eprintln!("{}: {}: {}", name, top.display(), e);
+		return false;
+	}
+
+	shell.dir_stack = stack;
+	true
+}
+
+/// Prints the directory stack on a single line, top first.
+fn print_stack(shell: &Shell, triple: IOTriple) -> Result<(), WaitError> {
+	let stack = full_stack(shell)?
+		.iter()
+		.map(|dir| shell.abbreviate_home(dir))
+		.collect::<Vec<_>>();
+	writeln!(triple.stdout(), "{}", stack.join(" "))?;
+	Ok(())
+}
+
+/// The `pushd` builtin, which adds a directory to the directory stack, or rotates the stack.
+pub struct Pushd;
+
+impl Builtin for Pushd {
+	fn run(&self, args: &[String], triple: IOTriple, shell: &mut Shell) -> Result<i32, WaitError> {
+		if args.len() > 2 {
+			eprintln!("pushd: too many arguments");
+			return Ok(1);
+		}
+
+		let mut stack = full_stack(shell)?;
+		match args.get(1) {
+			// With no arguments, swap the top two directories.
+			None => {
+				if stack.len() < 2 {
+					eprintln!("pushd: no other directory");
+					return Ok(1);
+				}
+
+				stack.swap(0, 1);
+			}
+			Some(arg) => match parse_stack_index(arg, stack.len()) {
+				Some(Ok(n)) => stack.rotate_left(n),
+				Some(Err(e)) => {
+					eprintln!("pushd: {}", e);
+					return Ok(1);
+				}
+				None => stack.insert(0, PathBuf::from(arg)),
+			},
+		}
+
+		if !set_stack(shell, stack, "pushd") {
+			return Ok(1);
+		}
+
+		print_stack(shell, triple)?;
+		Ok(0)
+	}
+}
+
+/// The `popd` builtin, which removes a directory from the directory stack.
+pub struct Popd;
+
+impl Builtin for Popd {
+	fn run(&self, args: &[String], triple: IOTriple, shell: &mut Shell) -> Result<i32, WaitError> {
+		if args.len() > 2 {
+			eprintln!("popd: too many arguments");
+			return Ok(1);
+		}
+
+		if shell.dir_stack.is_empty() {
+			eprintln!("popd: directory stack empty");
+			return Ok(1);
+		}
+
+		let mut stack = full_stack(shell)?;
+		let index = match args.get(1).map(|arg| parse_stack_index(arg, stack.len())) {
+			None => 0,
+			Some(Some(Ok(n))) => n,
+			Some(Some(Err(e))) => {
+				eprintln!("popd: {}", e);
+				return Ok(1);
+			}
+			Some(None) => {
+				eprintln!("popd: {}: invalid argument", args[1]);
+				return Ok(1);
+			}
+		};
+
+		stack.remove(index);
+		if !set_stack(shell, stack, "popd") {
+			return Ok(1);
+		}
+
+		print_stack(shell, triple)?;
+		Ok(0)
+	}
+}
+
+/// The `dirs` builtin, which displays the directory stack.
+pub struct Dirs;
+
+impl Builtin for Dirs {
+	fn run(&self, args: &[String], triple: IOTriple, shell: &mut Shell) -> Result<i32, WaitError> {
+		let mut verbose = false;
+		let mut long = false;
+		let mut index = None;
+		for arg in &args[1..] {
+			match arg.as_str() {
+				"-c" => {
+					shell.dir_stack.clear();
+					return Ok(0);
+				}
+				"-v" => verbose = true,
+				"-l" => long = true,
+				arg => match parse_stack_index(arg, shell.dir_stack.len() + 1) {
+					Some(Ok(n)) => index = Some(n),
+					Some(Err(e)) => {
+						eprintln!("dirs: {}", e);
+						return Ok(1);
+					}
+					None => {
+						eprintln!("dirs: {}: invalid argument", arg);
+						return Ok(1);
+					}
+				},
+			}
+		}
+
+		let stack = full_stack(shell)?
+			.iter()
+			.map(|dir| match long {
+				true => dir.display().to_string(),
+				false => shell.abbreviate_home(dir),
+			})
+			.collect::<Vec<_>>();
+
+		let mut stdout = triple.stdout();
+		if let Some(index) = index {
+			writeln!(stdout, "{}", stack[index])?;
+		} else if verbose {
+			for (i, dir) in stack.iter().enumerate() {
+				writeln!(stdout, "{:2}  {}", i, dir)?;
+			}
+		} else {
+			writeln!(stdout, "{}", stack.join(" "))?;
+		}
+
+		Ok(0)
+	}
+}
+
+/// The `jobs` builtin, which lists the jobs running in the background.
+pub struct Jobs;
+
+impl Builtin for Jobs {
+	fn run(&self, args: &[String], triple: IOTriple, shell: &mut Shell) -> Result<i32, WaitError> {
+		let mut show_pgid = false;
+		for arg in &args[1..] {
+			match arg.as_str() {
+				"-l" => show_pgid = true,
+				arg => {
+					eprintln!("jobs: {}: invalid argument", arg);
+					return Ok(1);
+				}
+			}
+		}
+
+		let mut stdout = triple.stdout();
+		for job in shell.jobs.jobs() {
+			match job.pipeline.pgid() {
+				Some(pgid) if show_pgid => writeln!(stdout, "[{}] {} Running\t{}", job.id, pgid, job.command)?,
+				_ => writeln!(stdout, "[{}] Running\t{}", job.id, job.command)?,
+			}
+		}
+
+		Ok(0)
+	}
+}
+
+/// The `disown` builtin, which stops the shell from sending SIGHUP to jobs when it exits.
+/// `-h` keeps the jobs in the job table, and `-a` applies to every job rather than the given (or current) job.
+pub struct Disown;
+
+impl Builtin for Disown {
+	fn run(&self, args: &[String], _triple: IOTriple, shell: &mut Shell) -> Result<i32, WaitError> {
+		let mut keep = false;
+		let mut all = false;
+		let mut specs = Vec::new();
+		for arg in &args[1..] {
+			match arg.as_str() {
+				"-h" => keep = true,
+				"-a" => all = true,
+				spec => specs.push(spec),
+			}
+		}
+
+		let mut indices = Vec::new();
+		if all {
+			indices.extend(0..shell.jobs.jobs().len());
+		} else if specs.is_empty() {
+			match shell.jobs.find(None) {
+				Some(index) => indices.push(index),
+				None => {
+					eprintln!("disown: no current job");
+					return Ok(1);
+				}
+			}
+		} else {
+			for spec in specs {
+				match shell.jobs.find(Some(spec)) {
+					Some(index) => indices.push(index),
+					None => {
+						eprintln!("disown: {}: no such job", spec);
+						return Ok(1);
+					}
+				}
+			}
+		}
+
+		// Remove from the back, so that removing one job doesn't move the others.
+		indices.sort_unstable();
+		indices.dedup();
+		for index in indices.into_iter().rev() {
+			if keep {
+				shell.jobs.set_nohup(index);
+			} else {
+				shell.jobs.disown(index);
+			}
+		}
+
+		Ok(0)
+	}
+}
+
+/// The `nohup` builtin. Commands prefixed with `nohup` are run with SIGHUP ignored, and their output sent to
+/// `nohup.out` rather than the terminal (see `Shell::execute`), so this only runs when there's no command.
+pub struct Nohup;
+
+impl Builtin for Nohup {
+	fn run(&self, _args: &[String], _triple: IOTriple, _shell: &mut Shell) -> Result<i32, WaitError> {
+		eprintln!("nohup: missing command");
+		Ok(125)
+	}
+}
+
+/// The `exit` builtin, which exits the shell with the given code, or the code of the last command.
+pub struct Exit;
+
+impl Builtin for Exit {
+	fn run(&self, args: &[String], _triple: IOTriple, shell: &mut Shell) -> Result<i32, WaitError> {
+		let code = match args.get(1).map(|code| code.parse::<i32>()) {
+			None => shell.last_exit_code(),
+			Some(Ok(code)) => code,
+			Some(Err(_)) => {
+				eprintln!("exit: {}: numeric argument required", args[1]);
+				return Ok(2);
+			}
+		};
+
+		shell.exit_code = Some(code);
+		Ok(code)
+	}
+}
+
+/// The `shift` builtin, which drops the first positional parameters (one, or the given number), so that `$1` becomes
+/// the parameter after them. Shifting more parameters than there are fails, and leaves them as they are.
+pub struct Shift;
+
+impl Builtin for Shift {
+	fn run(&self, args: &[String], _triple: IOTriple, shell: &mut Shell) -> Result<i32, WaitError> {
+		let count = match args.get(1).map(|count| count.parse::<usize>()) {
+			None => 1,
+			Some(Ok(count)) => count,
+			Some(Err(_)) => {
+				eprintln!("shift: {}: numeric argument required", args[1]);
+				return Ok(2);
+			}
+		};
+
+		if count > shell.positional.len() {
+			eprintln!("shift: can't shift that many");
+			return Ok(1);
+		}
+
+		shell.positional.drain(..count);
+		Ok(0)
+	}
+}
+
+/// The `test` builtin (also `[`, which must be closed with `]`), which evaluates a condition for `if` and `while`,
+/// succeeding if it's true.
+pub struct Test;
+
+impl Builtin for Test {
+	fn run(&self, args: &[String], _triple: IOTriple, _shell: &mut Shell) -> Result<i32, WaitError> {
+		let mut operands: Vec<&str> = args[1..].iter().map(String::as_str).collect();
+		if args[0] == "[" && operands.pop() != Some("]") {
+			eprintln!("[: missing ]");
+			return Ok(2);
+		}
+
+		match evaluate_test(&operands) {
+			Ok(true) => Ok(0),
+			Ok(false) => Ok(1),
+			Err(e) => {
+				eprintln!("{}: {}", args[0], e);
+				Ok(2)
+			}
+		}
+	}
+}
+
+/// Evaluates the operands of `test`: a string (true if it isn't empty), a unary test of a string or file (e.g.
+/// `-z "$FOO"` or `-d /boot`), or a binary comparison of strings or integers (e.g. `"$FOO" = bar`, or `$? -ne 0`),
+/// optionally negated with `!`.
+fn evaluate_test(operands: &[&str]) -> Result<bool, String> {
+	match operands {
+		[] => Ok(false),
+		["!", rest @ ..] => evaluate_test(rest).map(|result| !result),
+		[string] => Ok(!string.is_empty()),
+		[operator, operand] => unary_test(operator, operand),
+		[left, operator, right] => binary_test(left, operator, right),
+		_ => Err(String::from("too many arguments")),
+	}
+}
+
+fn unary_test(operator: &str, operand: &str) -> Result<bool, String> {
+	let metadata = || fs::metadata(operand).ok();
+	let accessible = |flags| access(operand, flags).is_ok();
+	match operator {
+		"-n" => Ok(!operand.is_empty()),
+		"-z" => Ok(operand.is_empty()),
+		"-e" => Ok(metadata().is_some()),
+		"-f" => Ok(metadata().is_some_and(|metadata| metadata.is_file())),
+		"-d" => Ok(metadata().is_some_and(|metadata| metadata.is_dir())),
+		"-s" => Ok(metadata().is_some_and(|metadata| metadata.len() > 0)),
+		"-L" | "-h" => Ok(fs::symlink_metadata(operand).is_ok_and(|metadata| metadata.is_symlink())),
+		"-r" => Ok(accessible(AccessFlags::R_OK)),
+		"-w" => Ok(accessible(AccessFlags::W_OK)),
+		"-x" => Ok(accessible(AccessFlags::X_OK)),
+		_ => Err(format!("{}: unary operator expected", operator)),
+	}
+}
+
+fn binary_test(left: &str, operator: &str, right: &str) -> Result<bool, String> {
+	let integer = |operand: &str| {
+		operand
+			.trim()
+			.parse::<i64>()
+			.map_err(|_| format!("{}: integer expression expected", operand))
+	};
+
+	match operator {
+		"=" | "==" => Ok(left == right),
+		"!=" => Ok(left != right),
+		"-eq" => Ok(integer(left)? == integer(right)?),
+		"-ne" => Ok(integer(left)? != integer(right)?),
+		"-lt" => Ok(integer(left)? < integer(right)?),
+		"-le" => Ok(integer(left)? <= integer(right)?),
+		"-gt" => Ok(integer(left)? > integer(right)?),
+		"-ge" => Ok(integer(left)? >= integer(right)?),
+		_ => Err(format!("{}: binary operator expected", operator)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{evaluate_test, parse_stack_index};
+
+	#[test]
+	fn test_parse_stack_index() {
+		assert_eq!(parse_stack_index("+0", 3), Some(Ok(0)));
+		assert_eq!(parse_stack_index("+2", 3), Some(Ok(2)));
+		assert_eq!(parse_stack_index("-0", 3), Some(Ok(2)));
+		assert_eq!(parse_stack_index("-2", 3), Some(Ok(0)));
+		assert!(matches!(parse_stack_index("+3", 3), Some(Err(_))));
+		assert!(matches!(parse_stack_index("+x", 3), Some(Err(_))));
+		assert_eq!(parse_stack_index("/tmp", 3), None);
+		assert_eq!(parse_stack_index("-", 3), None);
+	}
+
+	#[test]
+	fn test_evaluate_test() {
+		assert_eq!(evaluate_test(&[]), Ok(false));
+		assert_eq!(evaluate_test(&["foo"]), Ok(true));
+		assert_eq!(evaluate_test(&[""]), Ok(false));
+		assert_eq!(evaluate_test(&["-z", ""]), Ok(true));
+		assert_eq!(evaluate_test(&["!", "-n", ""]), Ok(true));
+		assert_eq!(evaluate_test(&["-d", "/"]), Ok(true));
+		assert_eq!(evaluate_test(&["-f", "/"]), Ok(false));
+		assert_eq!(evaluate_test(&["-e", "/does/not/exist"]), Ok(false));
+
+		assert_eq!(evaluate_test(&["a", "=", "a"]), Ok(true));
+		assert_eq!(evaluate_test(&["a", "!=", "a"]), Ok(false));
+		assert_eq!(evaluate_test(&["10", "-gt", "9"]), Ok(true));
+		assert_eq!(evaluate_test(&["1", "-ne", "1"]), Ok(false));
+
+		assert!(evaluate_test(&["a", "-lt", "1"]).is_err());
+		assert!(evaluate_test(&["-q", "a"]).is_err());
+		assert!(evaluate_test(&["a", "b", "c", "d"]).is_err());
+	}
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+
+use crate::parser::consumers::{CombinedString, QuotedOrUnquotedString};
+
+use super::glob::{glob, has_wildcards, PatternChar};
+
+/// The characters that fields are split on if `IFS` isn't set.
+const DEFAULT_IFS: &str = " \t\n";
+
+/// A parameter referenced by a `$` expansion.
+#[derive(Debug, PartialEq)]
+enum Parameter {
+	/// A variable, e.g. `$HOME` or `${HOME}`. Special parameters that are stored as variables (e.g. `$?`) are here too.
+	Variable(String),
+
+	/// A positional parameter, e.g. `$1`. `$0` is the name of the shell, or of the script it's running.
+	Positional(usize),
+
+	/// `$#`, the number of positional parameters.
+	Count,
+
+	/// `$@`, every positional parameter, which expands to a separate field for each parameter, even when quoted.
+	All,
+
+	/// `$*`, every positional parameter, which expands to a single field joined with the first character of `IFS`
+	/// when quoted.
+	AllJoined,
+}
+
+/// A piece of a string, either literal text or a parameter to expand.
+#[derive(Debug, PartialEq)]
+enum Segment {
+	Text(String),
+	Parameter(Parameter),
+}
+
+/// Splits a string into the literal text and the parameter expansions in it. A `$` that isn't followed by a valid
+/// parameter (or a `${` that isn't closed) is left as literal text.
+fn segments(s: &str) -> Vec<Segment> {
+	let chars = s.chars().collect::<Vec<_>>();
+	let mut segments = Vec::new();
+	let mut text = String::new();
+
+	let mut i = 0;
+	while i < chars.len() {
+		match parse_parameter(&chars[i..]) {
+			Some((parameter, length)) => {
+				if !text.is_empty() {
+					segments.push(Segment::Text(std::mem::take(&mut text)));
+				}
+
+				segments.push(Segment::Parameter(parameter));
+				i += length;
+			}
+			None => {
+				text.push(chars[i]);
+				i += 1;
+			}
+		}
+	}
+
+	if !text.is_empty() {
+		segments.push(Segment::Text(text));
+	}
+
+	segments
+}
+
+/// Parses the parameter expansion at the start of the input, returning it and its length.
+fn parse_parameter(input: &[char]) -> Option<(Parameter, usize)> {
+	if input.first() != Some(&'$') {
+		return None;
+	}
+
+	let (name, length) = match input.get(1)? {
+		'{' => {
+			let end = input.iter().position(|c| *c == '}')?;
+			(input[2..end].iter().collect::<String>(), end + 1)
+		}
+		c if is_name_char(*c) && !c.is_ascii_digit() => {
+			let name = input[1..].iter().take_while(|c| is_name_char(**c)).collect::<String>();
+			let length = name.chars().count() + 1;
+			(name, length)
+		}
+		// Special parameters are only ever one character, so `$10` is `$1` followed by a `0`.
+		c => (c.to_string(), 2),
+	};
+
+	let parameter = match name.as_str() {
+		"@" => Parameter::All,
+		"*" => Parameter::AllJoined,
+		"#" => Parameter::Count,
+		"?" => Parameter::Variable(name),
+		_ if !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()) => Parameter::Positional(name.parse().ok()?),
+		_ if !name.is_empty() && name.chars().all(is_name_char) => Parameter::Variable(name),
+		_ => return None,
+	};
+
+	Some((parameter, length))
+}
+
+fn is_name_char(c: char) -> bool {
+	c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// How the last field was ended, which decides whether a delimiter starts an empty field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Delimiter {
+	/// Nothing has been delimited in this word yet.
+	None,
+
+	/// The last field was ended by `IFS` whitespace, which a following non-whitespace delimiter joins with.
+	Whitespace,
+
+	/// The last field was ended by a non-whitespace `IFS` character, so another one means an empty field.
+	NonWhitespace,
+}
+
+/// Builds the fields that a word expands into, splitting the results of unquoted expansions on `IFS`.
+struct Fields<'a> {
+	ifs: &'a str,
+	fields: Vec<Vec<PatternChar>>,
+
+	/// The field being built. A field that has been started but is empty (e.g. from `""`) is still a field, whereas
+	/// an unquoted expansion that's empty disappears.
+	current: Option<Vec<PatternChar>>,
+	delimiter: Delimiter,
+}
+
+impl<'a> Fields<'a> {
+	fn new(ifs: &'a str) -> Self {
+		Self {
+			ifs,
+			fields: Vec::new(),
+			current: None,
+			delimiter: Delimiter::None,
+		}
+	}
+
+	/// Adds text that isn't split, marking whether it was quoted (so isn't globbed).
+	fn push_text(&mut self, text: &str, quoted: bool) {
+		self.current
+			.get_or_insert_with(Vec::new)
+			.extend(text.chars().map(|c| (c, quoted)));
+	}
+
+	/// Adds the result of an unquoted expansion, splitting it into fields on `IFS`.
+	fn push_split(&mut self, value: &str) {
+		for c in value.chars() {
+			if !self.ifs.contains(c) {
+				self.current.get_or_insert_with(Vec::new).push((c, false));
+			} else if c.is_whitespace() {
+				if self.current.is_some() {
+					self.end_field(Delimiter::Whitespace);
+				}
+			} else if self.current.is_some() {
+				self.end_field(Delimiter::NonWhitespace);
+			} else if self.delimiter == Delimiter::Whitespace {
+				// Whitespace around a non-whitespace delimiter is part of that delimiter.
+				self.delimiter = Delimiter::NonWhitespace;
+			} else {
+				self.current = Some(Vec::new());
+				self.end_field(Delimiter::NonWhitespace);
+			}
+		}
+	}
+
+	/// Ends the current field, if there is one.
+	fn end_field(&mut self, delimiter: Delimiter) {
+		if let Some(field) = self.current.take() {
+			self.fields.push(field);
+		}
+
+		self.delimiter = delimiter;
+	}
+
+	/// Starts a field, even if nothing is added to it.
+	fn start_field(&mut self) {
+		self.current.get_or_insert_with(Vec::new);
+	}
+
+	fn finish(mut self) -> Vec<Vec<PatternChar>> {
+		self.end_field(Delimiter::None);
+		self.fields
+	}
+}
+
+/// Expands words into the fields that they represent, e.g. the arguments of a command.
+///
+/// Parameters are expanded in unquoted and double quoted strings, but not in single quoted ones. The results of
+/// unquoted expansions are then split into separate fields on the characters in `IFS`, and fields with unquoted
+/// wildcards are replaced with the paths that they match (if there are any).
+pub struct Expander<'a> {
+	pub variables: &'a HashMap<String, String>,
+
+	/// The value of `$0`.
+	pub name: &'a str,
+	pub positional: &'a [String],
+}
+
+impl<'a> Expander<'a> {
+	pub fn expand(&self, word: &CombinedString) -> Vec<String> {
+		let mut fields = Fields::new(self.ifs());
+		for part in word.parts.iter() {
+			match &part.token {
+				QuotedOrUnquotedString::SingleQuoted(s) => {
+					fields.start_field();
+					fields.push_text(s, true);
+				}
+				QuotedOrUnquotedString::DoubleQuoted(s) => {
+					// `"$@"` with no positional parameters is no fields at all, rather than an empty one.
+					let segments = segments(s);
+					if segments.is_empty() || segments.iter().any(|s| *s != Segment::Parameter(Parameter::All)) {
+						fields.start_field();
+					}
+
+					for segment in segments {
+						self.expand_quoted(&mut fields, segment);
+					}
+				}
+				QuotedOrUnquotedString::Unquoted(s) => {
+					for segment in segments(s) {
+						self.expand_unquoted(&mut fields, segment);
+					}
+				}
+			}
+		}
+
+		let mut expanded = Vec::new();
+		for field in fields.finish() {
+			let matches = if has_wildcards(&field) {
+				glob(&field)
+			} else {
+				Vec::new()
+			};
+			if matches.is_empty() {
+				expanded.push(field.into_iter().map(|(c, _)| c).collect());
+			} else {
+				expanded.extend(matches);
+			}
+		}
+
+		expanded
+	}
+
+	fn expand_quoted(&self, fields: &mut Fields, segment: Segment) {
+		match segment {
+			Segment::Text(text) => fields.push_text(&text, true),
+			Segment::Parameter(Parameter::All) => {
+				for (i, value) in self.positional.iter().enumerate() {
+					if i > 0 {
+						fields.end_field(Delimiter::None);
+						fields.start_field();
+					}
+
+					fields.push_text(value, true);
+				}
+			}
+			Segment::Parameter(parameter) => fields.push_text(&self.value(&parameter), true),
+		}
+	}
+
+	fn expand_unquoted(&self, fields: &mut Fields, segment: Segment) {
+		match segment {
+			Segment::Text(text) => fields.push_text(&text, false),
+			Segment::Parameter(Parameter::All | Parameter::AllJoined) => {
+				for (i, value) in self.positional.iter().enumerate() {
+					if i > 0 {
+						fields.end_field(Delimiter::Whitespace);
+					}
+
+					fields.push_split(value);
+				}
+			}
+			Segment::Parameter(parameter) => fields.push_split(&self.value(&parameter)),
+		}
+	}
+
+	/// The value of a parameter, as a single string.
+	fn value(&self, parameter: &Parameter) -> String {
+		match parameter {
+			Parameter::Variable(name) => self.variables.get(name).cloned().unwrap_or_default(),
+			Parameter::Positional(0) => self.name.to_owned(),
+			Parameter::Positional(i) => self.positional.get(i - 1).cloned().unwrap_or_default(),
+			Parameter::Count => self.positional.len().to_string(),
+			Parameter::All | Parameter::AllJoined => {
+				let separator = self.ifs().chars().next().map(String::from).unwrap_or_default();
+				self.positional.join(&separator)
+			}
+		}
+	}
+
+	/// The characters that fields are split on.
+	fn ifs(&self) -> &str {
+		self.variables.get("IFS").map_or(DEFAULT_IFS, |ifs| ifs.as_str())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use crate::parser::{self, consumers::CombinedString};
+
+	use super::{segments, Expander, Parameter, Segment};
+
+	fn expand(input: &str, variables: &[(&str, &str)], positional: &[&str]) -> Vec<String> {
+		let variables = variables
+			.iter()
+			.map(|(k, v)| (k.to_string(), v.to_string()))
+			.collect::<HashMap<_, _>>();
+		let positional = positional.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+		let word = parser::try_parse::<CombinedString>(input).unwrap().unwrap();
+
+		Expander {
+			variables: &variables,
+			name: "script.sh",
+			positional: &positional,
+		}
+		.expand(&word.token)
+	}
+
+	#[test]
+	fn test_segments() {
+		assert_eq!(
+			segments("a$FOO${BAR}b$"),
+			vec![
+				Segment::Text("a".to_owned()),
+				Segment::Parameter(Parameter::Variable("FOO".to_owned())),
+				Segment::Parameter(Parameter::Variable("BAR".to_owned())),
+				Segment::Text("b$".to_owned()),
+			]
+		);
+
+		assert_eq!(
+			segments("$10$@${"),
+			vec![
+				Segment::Parameter(Parameter::Positional(1)),
+				Segment::Text("0".to_owned()),
+				Segment::Parameter(Parameter::All),
+				Segment::Text("${".to_owned()),
+			]
+		);
+	}
+
+	#[test]
+	fn test_expand_splitting() {
+		let vars = [("A", "  x  y "), ("EMPTY", "")];
+
+		// Unquoted expansions are split, and quoted ones aren't.
+		assert_eq!(expand("$A", &vars, &[]), vec!["x", "y"]);
+		assert_eq!(expand("\"$A\"", &vars, &[]), vec!["  x  y "]);
+		assert_eq!(expand("'$A'", &vars, &[]), vec!["$A"]);
+		assert_eq!(expand("pre$A", &vars, &[]), vec!["pre", "x", "y"]);
+		assert_eq!(expand("\"<\"$A\">\"", &vars, &[]), vec!["<", "x", "y", ">"]);
+
+		// Empty unquoted expansions disappear, but empty quoted strings are still a field.
+		assert!(expand("$EMPTY", &vars, &[]).is_empty());
+		assert!(expand("$UNSET", &vars, &[]).is_empty());
+		assert_eq!(expand("\"$EMPTY\"", &vars, &[]), vec![""]);
+		assert_eq!(expand("''$EMPTY", &vars, &[]), vec![""]);
+	}
+
+	#[test]
+	fn test_expand_ifs() {
+		// Non-whitespace delimiters delimit empty fields, apart from a trailing one.
+		let vars = [("IFS", ":"), ("A", "a::b:"), ("B", ":a")];
+		assert_eq!(expand("$A", &vars, &[]), vec!["a", "", "b"]);
+		assert_eq!(expand("$B", &vars, &[]), vec!["", "a"]);
+
+		// Whitespace next to a non-whitespace delimiter is part of it.
+		let vars = [("IFS", " :"), ("A", "a : b"), ("B", " :a")];
+		assert_eq!(expand("$A", &vars, &[]), vec!["a", "b"]);
+		assert_eq!(expand("$B", &vars, &[]), vec!["", "a"]);
+
+		// An empty IFS disables splitting.
+		let vars = [("IFS", ""), ("A", "a b")];
+		assert_eq!(expand("$A", &vars, &[]), vec!["a b"]);
+	}
+
+	#[test]
+	fn test_expand_positional() {
+		let params = ["a b", "", "c"];
+		assert_eq!(expand("\"$@\"", &[], &params), vec!["a b", "", "c"]);
+		assert_eq!(expand("\"<$@>\"", &[], &params), vec!["<a b", "", "c>"]);
+		assert_eq!(expand("$@", &[], &params), vec!["a", "b", "c"]);
+		assert_eq!(expand("\"$*\"", &[], &params), vec!["a b  c"]);
+		assert_eq!(expand("\"$*\"", &[("IFS", ",")], &params), vec!["a b,,c"]);
+		assert_eq!(expand("$1:$#", &[], &params), vec!["a", "b:3"]);
+		assert_eq!(expand("$0:$3$4", &[], &params), vec!["script.sh:c"]);
+		assert_eq!(expand("$10", &[], &params), vec!["a", "b0"]);
+
+		assert!(expand("\"$@\"", &[], &[]).is_empty());
+		assert_eq!(expand("''\"$@\"", &[], &[]), vec![""]);
+		assert_eq!(expand("\"x$@\"", &[], &[]), vec!["x"]);
+		assert_eq!(expand("\"$*\"", &[], &[]), vec![""]);
+	}
+}
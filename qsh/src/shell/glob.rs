@@ -0,0 +1,115 @@
+use std::{fs::read_dir, path::Path};
+
+use common::glob::Glob;
+
+/// A character of a pattern, and whether it was quoted. Quoted characters always match themselves, so that e.g.
+/// `'*'` isn't a wildcard.
+pub type PatternChar = (char, bool);
+
+/// Whether the pattern has any unquoted wildcards, i.e. whether it needs to be matched against the filesystem.
+pub fn has_wildcards(pattern: &[PatternChar]) -> bool {
+	pattern
+		.iter()
+		.any(|(c, quoted)| !quoted && matches!(c, '*' | '?' | '['))
+}
+
+/// Expands the pattern into the paths that it matches, sorted. Like other shells, wildcards don't match a leading `.`
+/// unless the pattern component starts with one, and never match a `/`.
+pub fn glob(pattern: &[PatternChar]) -> Vec<String> {
+	let mut paths = vec![String::new()];
+	for (i, component) in pattern.split(|(c, _)| *c == '/').enumerate() {
+		let mut next = Vec::new();
+		for path in paths.iter() {
+			let prefix = if i == 0 { String::new() } else { format!("{}/", path) };
+			if !has_wildcards(component) {
+				next.push(prefix + &component.iter().map(|(c, _)| c).collect::<String>());
+				continue;
+			}
+
+			let Ok(entries) = read_dir(if prefix.is_empty() { "." } else { &prefix }) else {
+				continue;
+			};
+
+			let hidden = matches!(component.first(), Some(('.', _)));
+			let glob = Glob::from_quoted(component);
+			for entry in entries.flatten() {
+				let name = entry.file_name().to_string_lossy().into_owned();
+				if name.starts_with('.') && !hidden {
+					continue;
+				}
+
+				if glob.matches(&name) {
+					next.push(format!("{}{}", prefix, name));
+				}
+			}
+		}
+
+		paths = next;
+	}
+
+	// Literal components (and trailing slashes) were taken at face value, so check that the paths actually exist.
+	paths.retain(|path| Path::new(path).symlink_metadata().is_ok());
+	paths.sort();
+	paths
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::{create_dir_all, File};
+
+	use common::{glob::Glob, testing::TempDir};
+
+	use super::{glob, PatternChar};
+
+	fn pattern(s: &str) -> Vec<PatternChar> {
+		s.chars().map(|c| (c, false)).collect()
+	}
+
+	fn is_match(p: &str, name: &str) -> bool {
+		Glob::from_quoted(&pattern(p)).matches(name)
+	}
+
+	#[test]
+	fn test_matches() {
+		assert!(is_match("*.rs", "main.rs"));
+		assert!(is_match("*", ""));
+		assert!(!is_match("*.rs", "main.rso"));
+		assert!(is_match("a?c", "abc"));
+		assert!(!is_match("a?c", "ac"));
+		assert!(is_match("[a-c]x", "bx"));
+		assert!(!is_match("[!a-c]x", "bx"));
+		assert!(is_match("[]]", "]"));
+		assert!(is_match("[a-]", "-"));
+		assert!(is_match("a[b", "a[b"));
+
+		// Quoted wildcards only match themselves.
+		let quoted = Glob::from_quoted(&[('*', true), ('.', false)]);
+		assert!(quoted.matches("*."));
+		assert!(!quoted.matches("a."));
+	}
+
+	#[test]
+	fn test_glob() {
+		let dir = TempDir::new("qsh-glob").unwrap();
+		create_dir_all(dir.join("sub")).unwrap();
+		for name in ["b.txt", "a.txt", ".hidden.txt", "c.rs", "sub/d.txt"] {
+			File::create(dir.join(name)).unwrap();
+		}
+
+		let root = dir.to_string_lossy();
+		let expand = |p: &str| {
+			glob(&pattern(&format!("{}/{}", root, p)))
+				.into_iter()
+				.map(|path| path[root.len() + 1..].to_owned())
+				.collect::<Vec<_>>()
+		};
+
+		assert_eq!(expand("*.txt"), vec!["a.txt", "b.txt"]);
+		assert_eq!(expand(".*.txt"), vec![".hidden.txt"]);
+		assert_eq!(expand("*/*.txt"), vec!["sub/d.txt"]);
+		assert_eq!(expand("*/"), vec!["sub/"]);
+		assert_eq!(expand("sub/*"), vec!["sub/d.txt"]);
+		assert!(expand("*.md").is_empty());
+		assert!(expand("missing/*").is_empty());
+	}
+}
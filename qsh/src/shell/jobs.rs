@@ -0,0 +1,140 @@
+use crate::process::{ExitCode, ProcessPipeline};
+
+/// A pipeline that is running in the background.
+pub struct Job {
+	/// The number of the job, as shown by `jobs` and used in job specs like `%1`.
+	pub id: usize,
+
+	/// The command line that started the job.
+	pub command: String,
+
+	pub pipeline: ProcessPipeline,
+
+	/// Whether the job is left alone when the shell exits, rather than being sent SIGHUP (`disown -h`).
+	pub nohup: bool,
+}
+
+impl Job {
+	/// Describes how the job finished, for reporting to the user.
+	pub fn describe_exit(&self) -> String {
+		match self.pipeline.get_exit_code() {
+			Some(ExitCode::Success(0)) => String::from("Done"),
+			Some(ExitCode::Success(code)) => format!("Exit {}", code),
+			Some(ExitCode::Err(errno)) => format!("Terminated ({})", errno as i32),
+			None => String::from("Running"),
+		}
+	}
+}
+
+/// The background jobs started by the shell.
+#[derive(Default)]
+pub struct JobTable {
+	/// The jobs that are still tracked, in the order they were started.
+	jobs: Vec<Job>,
+
+	/// Jobs that have been disowned. They're no longer shown or sent SIGHUP, but still need to be reaped.
+	disowned: Vec<ProcessPipeline>,
+}
+
+impl JobTable {
+	/// Adds a newly started pipeline to the table, returning its job number.
+	pub fn add(&mut self, command: String, pipeline: ProcessPipeline) -> usize {
+		let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+		self.jobs.push(Job {
+			id,
+			command,
+			pipeline,
+			nohup: false,
+		});
+
+		id
+	}
+
+	/// The jobs that are being tracked.
+	pub fn jobs(&self) -> &[Job] {
+		&self.jobs
+	}
+
+	/// Reaps any processes that have terminated, returning the jobs that have now finished.
+	pub fn reap(&mut self) -> Vec<Job> {
+		// A pipeline that can't be waited on has nothing left to wait for.
+		self.disowned
+			.retain_mut(|pipeline| !pipeline.try_wait().unwrap_or(true));
+
+		let mut finished = Vec::new();
+		let mut i = 0;
+		while i < self.jobs.len() {
+			if self.jobs[i].pipeline.try_wait().unwrap_or(true) {
+				finished.push(self.jobs.remove(i));
+			} else {
+				i += 1;
+			}
+		}
+
+		finished
+	}
+
+	/// Finds the job that the given job spec refers to: `%n` for job `n`, `%%` or `%+` for the most recent job, or
+	/// `%-` for the one before it. Without a spec, this is the most recent job.
+	pub fn find(&self, spec: Option<&str>) -> Option<usize> {
+		let from_end = |n: usize| self.jobs.len().checked_sub(n + 1);
+		match spec {
+			None | Some("%%") | Some("%+") | Some("%") => from_end(0),
+			Some("%-") => from_end(1),
+			Some(spec) => {
+				let id: usize = spec.strip_prefix('%').unwrap_or(spec).parse().ok()?;
+				self.jobs.iter().position(|job| job.id == id)
+			}
+		}
+	}
+
+	/// Stops tracking the job at the given index, so it's no longer sent SIGHUP when the shell exits.
+	pub fn disown(&mut self, index: usize) {
+		let job = self.jobs.remove(index);
+		self.disowned.push(job.pipeline);
+	}
+
+	/// Marks the job at the given index to not be sent SIGHUP when the shell exits, while still tracking it.
+	pub fn set_nohup(&mut self, index: usize) {
+		self.jobs[index].nohup = true;
+	}
+
+	/// Sends SIGHUP to every job that hasn't opted out of it. Called as the shell exits.
+	pub fn hangup(&mut self) {
+		self.reap();
+		for job in self.jobs.iter().filter(|job| !job.nohup) {
+			if let Err(e) = job.pipeline.hangup() {
+				eprintln!("qsh: failed to hang up job {}: {}", job.id, e);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::JobTable;
+	use crate::process::ProcessPipeline;
+
+	#[test]
+	fn test_find() {
+		let mut table = JobTable::default();
+		assert_eq!(table.find(None), None);
+
+		table.add(String::from("sleep 1"), ProcessPipeline::new(Vec::new()));
+		table.add(String::from("sleep 2"), ProcessPipeline::new(Vec::new()));
+		table.add(String::from("sleep 3"), ProcessPipeline::new(Vec::new()));
+
+		assert_eq!(table.find(None), Some(2));
+		assert_eq!(table.find(Some("%%")), Some(2));
+		assert_eq!(table.find(Some("%-")), Some(1));
+		assert_eq!(table.find(Some("%1")), Some(0));
+		assert_eq!(table.find(Some("2")), Some(1));
+		assert_eq!(table.find(Some("%4")), None);
+		assert_eq!(table.find(Some("%x")), None);
+
+		// Job numbers aren't reused while later jobs are still around.
+		table.disown(1);
+		assert_eq!(table.find(Some("%3")), Some(1));
+		assert_eq!(table.add(String::from("sleep 4"), ProcessPipeline::new(Vec::new())), 4);
+	}
+}
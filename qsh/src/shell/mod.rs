@@ -1,29 +1,93 @@
 mod builtins;
+mod expand;
+mod glob;
+mod jobs;
 
 use common::io::IOTriple;
-use std::{collections::HashMap, io::Write};
+use escapes::Capabilities;
+use nix::{
+	fcntl::{open, OFlag},
+	sys::{
+		signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal},
+		stat::Mode,
+	},
+	unistd::close,
+};
+use std::{
+	collections::HashMap,
+	io::{ErrorKind, Write},
+	path::{Path, PathBuf},
+	rc::Rc,
+	sync::atomic::{AtomicBool, Ordering},
+};
 use thiserror::Error;
 
 use crate::{
 	buffer::Buffer,
+	completion::Completer,
+	history::{History, HISTORY_FILE},
 	parser::{
 		self,
-		consumers::{Command, Pipeline, QuotedOrUnquotedString},
+		consumers::{AndOr, Command, List, ListOperator, Pipeline, Statement},
 		types::{ParserError, Token},
 	},
 	process::{ExitCode, Process, ProcessPipeline, WaitError},
 };
 
+use self::{expand::Expander, jobs::JobTable};
+
+/// The command prefix that runs a command with SIGHUP ignored, so that it survives the shell exiting.
+const NOHUP: &str = "nohup";
+
+/// The exit code of the shell when it exits because its terminal hung up, i.e. 128 + SIGHUP.
+const HANGUP_EXIT_CODE: i32 = 129;
+
+/// The value of `$0` when the shell isn't running a script.
+const SHELL_NAME: &str = "qsh";
+
+/// The exit code of a script with a syntax error, which stops it from running any further.
+const SYNTAX_ERROR_EXIT_CODE: i32 = 2;
+
+/// Set when the shell receives SIGHUP, i.e. its terminal has gone away.
+static HANGUP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_hangup(_: i32) {
+	HANGUP.store(true, Ordering::SeqCst);
+}
+
 pub struct Shell {
 	environment: HashMap<String, String>,
 	pub triple: IOTriple,
 
-	builtins: HashMap<String, Box<dyn builtins::Builtin>>,
+	builtins: HashMap<String, Rc<dyn builtins::Builtin>>,
+
+	/// The directory stack used by `pushd`, `popd`, and `dirs`. The current directory is implicitly the top of
+	/// the stack, so the first element here is the directory below it.
+	dir_stack: Vec<PathBuf>,
+
+	/// The pipelines running in the background.
+	jobs: JobTable,
+
+	/// Set by the `exit` builtin to the code that the shell should exit with.
+	exit_code: Option<i32>,
+
+	/// The name of the shell, or of the script it's running, i.e. `$0`.
+	name: String,
+
+	/// The positional parameters, i.e. `$1`, `$2`, etc.
+	positional: Vec<String>,
+
+	/// Whether the shell is reading commands from the terminal, rather than running a script, so that commands that
+	/// can't be found come with suggestions.
+	interactive: bool,
 }
 
 enum Executable {
 	Builtin(i32),
 	Pipeline(ProcessPipeline),
+
+	/// A pipeline that was started in the background, with the given job number.
+	Background(usize),
 }
 
 impl Shell {
@@ -32,38 +96,125 @@ impl Shell {
 			environment: default_environment_vars(),
 			triple: IOTriple::default(),
 			builtins: default_builtins(),
+			dir_stack: Vec::new(),
+			jobs: JobTable::default(),
+			exit_code: None,
+			name: SHELL_NAME.to_owned(),
+			positional: Vec::new(),
+			interactive: false,
+		}
+	}
+
+	/// Sets the name of the shell, i.e. `$0`, which is the path of the script when running one.
+	pub fn with_name(mut self, name: String) -> Self {
+		self.name = name;
+		self
+	}
+
+	/// Sets the positional parameters, i.e. the arguments of a script.
+	pub fn with_positional(mut self, positional: Vec<String>) -> Self {
+		self.positional = positional;
+		self
+	}
+
+	/// Runs the shell until it exits, returning the code it should exit with. Running jobs are sent SIGHUP as the
+	/// shell exits, except for those that have been `disown`ed.
+	pub fn run(&mut self) -> i32 {
+		// Don't restart reads after SIGHUP, so that we stop waiting for input.
+		let action = SigAction::new(SigHandler::Handler(handle_hangup), SaFlags::empty(), SigSet::empty());
+		if let Err(e) = unsafe { sigaction(Signal::SIGHUP, &action) } {
+			writeln!(self.triple.stderr(), "Error handling SIGHUP: {}", e).unwrap();
 		}
+
+		self.interactive = true;
+		let code = self.read_eval_loop();
+		self.jobs.hangup();
+		code
 	}
 
-	pub fn run(&mut self) {
+	/// Runs a script, without prompting or reading from the terminal, returning the code the shell should exit with:
+	/// the exit code of the last command, or the code given to `exit`. Each command is run as soon as all of it has
+	/// been read, so commands can span lines (e.g. an `if` and its `fi`), and a syntax error stops the script before
+	/// the command that has it. Lines starting with `#` (including a `#!` line) are comments. `name` is used to tell
+	/// the user where errors are.
+	pub fn run_script(&mut self, name: &str, script: &str) -> i32 {
+		let mut err = self.triple.stderr();
+		let mut pending = String::new();
+		let mut pending_line = 0;
+		for (number, line) in script.lines().enumerate() {
+			if self.exit_code.is_some() {
+				break;
+			}
+
+			if line.trim_start().starts_with('#') {
+				continue;
+			}
+
+			if pending.is_empty() {
+				pending_line = number + 1;
+			}
+
+			pending.push_str(line);
+			pending.push('\n');
+
+			let list = match parse(&pending) {
+				Ok(Some(list)) => list,
+				Ok(None) => {
+					pending.clear();
+					continue;
+				}
+				// An error at the end of the input is a command that continues on the next line.
+				Err(e) if e.start >= pending.trim_end().chars().count() => continue,
+				Err(e) => {
+					writeln!(err, "{}: line {}: {}", name, pending_line, e.message).unwrap();
+					return SYNTAX_ERROR_EXIT_CODE;
+				}
+			};
+
+			pending.clear();
+			if let Err(e) = self.run_list(&list.token) {
+				writeln!(err, "Error waiting for process: {}", e).unwrap();
+			}
+		}
+
+		if !pending.trim().is_empty() && self.exit_code.is_none() {
+			writeln!(err, "{}: line {}: Unexpected end of file", name, pending_line).unwrap();
+			return SYNTAX_ERROR_EXIT_CODE;
+		}
+
+		self.exit_code.unwrap_or_else(|| self.last_exit_code())
+	}
+
+	fn read_eval_loop(&mut self) -> i32 {
 		let input = self.triple.stdin();
 		let output = self.triple.stdout();
 		let mut err = self.triple.stderr();
-		let mut buffer = Buffer::new(input, output);
+		let mut buffer = Buffer::new(input, output)
+			.with_history(self.load_history())
+			.with_capabilities(Capabilities::from_env());
+
+		let code = loop {
+			self.report_finished_jobs();
+			if let Some(code) = self.exit_code {
+				break code;
+			}
 
-		loop {
 			self.update_working_directory();
-			let prompt = format!(
-				"{} {}",
-				self.environment.get("PWD").unwrap(),
-				self.environment.get("PS1").unwrap()
-			);
+			let prompt = self.expand_prompt(self.environment.get("PS1").map_or("", |s| s.as_str()));
+			buffer.set_completer(self.completer());
 
 			let line = match buffer.read(&prompt) {
 				Ok(line) => line,
+				Err(_) if HANGUP.load(Ordering::SeqCst) => break HANGUP_EXIT_CODE,
+				Err(e) if e.kind() == ErrorKind::Interrupted => continue,
 				Err(e) => {
 					writeln!(err, "Error reading input: {}", e).unwrap();
-					return;
+					break self.last_exit_code();
 				}
 			};
 
 			let exit_code = match self.evaluate(&line) {
-				Ok(Executable::Pipeline(pipeline)) => match pipeline.get_exit_code() {
-					Some(ExitCode::Success(code)) => code,
-					Some(ExitCode::Err(code)) => code as i32,
-					None => panic!("BUG: pipeline has terminated, but no exit code found"),
-				},
-				Ok(Executable::Builtin(code)) => code,
+				Ok(code) => code,
 				Err(PipelineError::ParserError(e)) => {
 					writeln!(err, "Error evaluating input: {}", e).unwrap();
 					continue;
@@ -76,6 +227,49 @@ impl Shell {
 			};
 
 			self.environment.insert("?".to_owned(), exit_code.to_string());
+		};
+
+		if let Err(e) = buffer.history().save() {
+			writeln!(err, "Error saving history: {}", e).unwrap();
+		}
+
+		code
+	}
+
+	/// Returns a completer for the builtins, and the executables in the current `$PATH`.
+	fn completer(&self) -> Completer {
+		Completer::new(
+			self.builtins.keys().cloned().collect(),
+			self.environment.get("PATH").cloned().unwrap_or_default(),
+		)
+	}
+
+	/// Loads the history from `$HISTFILE`, or `~/.qsh_history` if that isn't set. Without either, the history is only
+	/// kept for this session.
+	fn load_history(&self) -> History {
+		let path = std::env::var_os("HISTFILE").map(PathBuf::from).or_else(|| {
+			self.environment
+				.get("HOME")
+				.filter(|home| !home.is_empty())
+				.map(|home| Path::new(home).join(HISTORY_FILE))
+		});
+
+		path.map(History::load).unwrap_or_default()
+	}
+
+	/// The exit code of the last command, i.e. `$?`.
+	fn last_exit_code(&self) -> i32 {
+		self.environment
+			.get("?")
+			.and_then(|code| code.parse().ok())
+			.unwrap_or(0)
+	}
+
+	/// Tells the user about any background jobs that have finished since the last prompt.
+	fn report_finished_jobs(&mut self) {
+		let mut err = self.triple.stderr();
+		for job in self.jobs.reap() {
+			writeln!(err, "[{}] {}\t{}", job.id, job.describe_exit(), job.command).unwrap();
 		}
 	}
 
@@ -86,12 +280,59 @@ impl Shell {
 			.insert("PWD".to_owned(), path.to_string_lossy().to_string());
 	}
 
-	/// Evaluate the input as a shell expression.
-	fn evaluate(&mut self, input: &str) -> Result<Executable, PipelineError> {
+	/// Expand the escape sequences in a prompt string:
+	///  - `\w`: the current working directory, with $HOME abbreviated to `~`.
+	///  - `\W`: the basename of the current working directory.
+	///  - `\\`: a literal backslash.
+	fn expand_prompt(&self, prompt: &str) -> String {
+		let pwd = self.environment.get("PWD").map_or("", |s| s.as_str());
+		let mut expanded = String::new();
+		let mut chars = prompt.chars();
+		while let Some(c) = chars.next() {
+			if c != '\\' {
+				expanded.push(c);
+				continue;
+			}
+
+			match chars.next() {
+				Some('w') => expanded.push_str(&self.abbreviate_home(Path::new(pwd))),
+				Some('W') => match Path::new(pwd).file_name() {
+					Some(name) => expanded.push_str(&name.to_string_lossy()),
+					None => expanded.push_str(pwd),
+				},
+				Some('\\') => expanded.push('\\'),
+				Some(c) => {
+					expanded.push('\\');
+					expanded.push(c);
+				}
+				None => expanded.push('\\'),
+			}
+		}
+
+		expanded
+	}
+
+	/// Formats the given path, replacing a leading $HOME with `~`.
+	fn abbreviate_home(&self, path: &Path) -> String {
+		if let Some(home) = self.environment.get("HOME").filter(|h| !h.is_empty()) {
+			if let Ok(rest) = path.strip_prefix(home) {
+				if rest.as_os_str().is_empty() {
+					return String::from("~");
+				}
+
+				return format!("~/{}", rest.display());
+			}
+		}
+
+		path.display().to_string()
+	}
+
+	/// Evaluate the input as a shell expression, returning the exit code of the last command that was run.
+	fn evaluate(&mut self, input: &str) -> Result<i32, PipelineError> {
 		let mut err = self.triple.stderr();
 
-		let raw_pipe = match parser::try_parse::<Pipeline>(input) {
-			Ok(Some(expr)) => expr,
+		let list = match parse(input) {
+			Ok(Some(list)) => list,
 			Ok(None) => return Err(PipelineError::NoPipeline),
 			Err(e) => {
 				writeln!(err, "Error parsing input: {}", e).unwrap();
@@ -99,24 +340,133 @@ impl Shell {
 			}
 		};
 
-		if raw_pipe.token.commands.is_empty() {
-			return Err(PipelineError::NoPipeline);
+		Ok(self.run_list(&list.token)?)
+	}
+
+	/// Runs each of the and-or lists in the list in turn, returning the exit code of the last one. Stops early if the
+	/// shell is exiting.
+	fn run_list(&mut self, list: &List) -> Result<i32, WaitError> {
+		let mut code = 0;
+		for item in list.items.iter() {
+			if self.exit_code.is_some() {
+				break;
+			}
+
+			code = self.run_and_or(&item.token)?;
 		}
 
-		Ok(self.execute(raw_pipe, self.triple)?)
+		Ok(code)
 	}
 
-	fn execute(&mut self, raw_pipe: Token<Pipeline>, triple: IOTriple) -> Result<Executable, WaitError> {
-		let commands: Vec<Process> = raw_pipe
-			.token
+	/// Runs the statements of an and-or list, skipping a statement after `&&` if the last one that ran failed, or after
+	/// `||` if it succeeded.
+	fn run_and_or(&mut self, and_or: &AndOr) -> Result<i32, WaitError> {
+		let mut code = self.run_statement(&and_or.first.token)?;
+		for (operator, statement) in and_or.rest.iter() {
+			if self.exit_code.is_some() {
+				break;
+			}
+
+			let run = match operator {
+				ListOperator::And => code == 0,
+				ListOperator::Or => code != 0,
+			};
+
+			if run {
+				code = self.run_statement(&statement.token)?;
+			}
+		}
+
+		Ok(code)
+	}
+
+	/// Runs a pipeline or compound command, returning its exit code, which is also stored in `$?` so that later
+	/// commands (e.g. conditions) can see it.
+	fn run_statement(&mut self, statement: &Statement) -> Result<i32, WaitError> {
+		let code = match statement {
+			Statement::Pipeline(pipeline) => {
+				let executable = self.execute(pipeline, self.triple)?;
+				self.exit_code_of(executable)
+			}
+			Statement::If(statement) => {
+				let mut branch = statement.otherwise.as_ref();
+				for (condition, body) in statement.branches.iter() {
+					if self.run_list(&condition.token)? == 0 {
+						branch = Some(body);
+						break;
+					}
+				}
+
+				// Like other shells, an `if` that doesn't run anything succeeds.
+				match branch {
+					Some(body) if self.exit_code.is_none() => self.run_list(&body.token)?,
+					_ => 0,
+				}
+			}
+			Statement::While(statement) => {
+				let mut code = 0;
+				while self.exit_code.is_none() && self.run_list(&statement.condition.token)? == 0 {
+					code = self.run_list(&statement.body.token)?;
+				}
+
+				code
+			}
+		};
+
+		self.environment.insert("?".to_owned(), code.to_string());
+		Ok(code)
+	}
+
+	/// The exit code of something that was executed. Background jobs succeed as soon as they're started, after telling
+	/// the user about them.
+	fn exit_code_of(&mut self, executable: Executable) -> i32 {
+		match executable {
+			Executable::Pipeline(pipeline) => match pipeline.get_exit_code() {
+				Some(ExitCode::Success(code)) => code,
+				Some(ExitCode::Err(code)) => code as i32,
+				None => panic!("BUG: pipeline has terminated, but no exit code found"),
+			},
+			Executable::Builtin(code) => code,
+			Executable::Background(id) => {
+				if let Some(pgid) = self
+					.jobs
+					.jobs()
+					.iter()
+					.find(|job| job.id == id)
+					.and_then(|job| job.pipeline.pgid())
+				{
+					writeln!(self.triple.stderr(), "[{}] {}", id, pgid).unwrap();
+				}
+
+				0
+			}
+		}
+	}
+
+	fn execute(&mut self, raw_pipe: &Pipeline, triple: IOTriple) -> Result<Executable, WaitError> {
+		let mut commands: Vec<Process> = raw_pipe
 			.commands
 			.iter()
 			.map(|c| {
-				let args = self.concrete_arguments(c);
-				Process::new(args)
+				let mut args = self.concrete_arguments(c);
+
+				// Commands prefixed with `nohup` are run directly, but ignoring SIGHUP. On its own, `nohup` is left
+				// to the builtin to complain about.
+				if args.len() > 1 && args[0] == NOHUP {
+					args.remove(0);
+					return self.process(args).with_nohup();
+				}
+
+				self.process(args)
 			})
 			.collect();
 
+		// Commands that expand to nothing (e.g. an unset variable) do nothing.
+		commands.retain(|c| !c.argv.is_empty());
+		if commands.is_empty() {
+			return Ok(Executable::Builtin(0));
+		}
+
 		// If there's only one command, try to execute it as a builtin.
 		if commands.len() == 1 {
 			match self.try_execute_as_builtin(triple, &commands[0]) {
@@ -127,6 +477,22 @@ impl Shell {
 		}
 
 		let mut pipeline = ProcessPipeline::new(commands);
+		if raw_pipe.background {
+			// Without job control, background jobs can't read from the terminal, so they get /dev/null instead.
+			let null = open("/dev/null", OFlag::O_RDONLY | OFlag::O_CLOEXEC, Mode::empty())?;
+			let result = pipeline.execute(IOTriple { stdin: null, ..triple });
+			close(null)?;
+			result?;
+
+			let command = raw_pipe
+				.commands
+				.iter()
+				.map(|c| c.literal.as_str())
+				.collect::<Vec<_>>()
+				.join(" | ");
+			return Ok(Executable::Background(self.jobs.add(command, pipeline)));
+		}
+
 		pipeline.execute(triple)?;
 
 		pipeline.wait()?;
@@ -134,11 +500,20 @@ impl Shell {
 		Ok(Executable::Pipeline(pipeline))
 	}
 
+	/// Creates the process to run the arguments, with suggestions if it can't be found and the user is there to see
+	/// them.
+	fn process(&self, argv: Vec<String>) -> Process {
+		match self.interactive {
+			true => Process::new(argv).with_suggestions(self.completer()),
+			false => Process::new(argv),
+		}
+	}
+
 	/// Try to execute the command as a builtin, returning the exit code if it was able to be run.
 	fn try_execute_as_builtin(&mut self, triple: IOTriple, process: &Process) -> Result<Option<Executable>, WaitError> {
 		let argv = &process.argv;
 
-		if let Some(builtin) = self.builtins.get(&argv[0]) {
+		if let Some(builtin) = self.builtins.get(&argv[0]).cloned() {
 			let code = builtin.run(argv, triple, self)?;
 			return Ok(Some(Executable::Builtin(code)));
 		}
@@ -146,24 +521,33 @@ impl Shell {
 		Ok(None)
 	}
 
-	/// Construct the concrete expression from the token.
-	/// At the moment, this just takes each string literally, but eventually this will do variable interpolation etc.
+	/// Construct the concrete expression from the token, expanding each word into the arguments it represents.
 	fn concrete_arguments(&mut self, expression: &Token<Command>) -> Vec<String> {
-		let mut args = Vec::new();
-		for arg in expression.token.parts.iter() {
-			let mut build = String::new();
-			for token in arg.token.parts.iter() {
-				match &token.token {
-					QuotedOrUnquotedString::Unquoted(decoded)
-					| QuotedOrUnquotedString::SingleQuoted(decoded)
-					| QuotedOrUnquotedString::DoubleQuoted(decoded) => build.push_str(decoded),
-				}
-			}
+		let expander = Expander {
+			variables: &self.environment,
+			name: &self.name,
+			positional: &self.positional,
+		};
 
-			args.push(build);
-		}
+		expression
+			.token
+			.parts
+			.iter()
+			.flat_map(|arg| expander.expand(&arg.token))
+			.collect()
+	}
+}
+
+/// Parses the input as a list of commands, erroring if any of it is left over.
+fn parse(input: &str) -> Result<Option<Token<List>>, ParserError> {
+	let list = parser::try_parse::<List>(input)?;
 
-		args
+	// Lists stop at reserved words that end compound commands, so anything left over is one that's out of place.
+	let consumed = list.as_ref().map_or(0, |list| list.length);
+	let rest: String = input.chars().skip(consumed).collect();
+	match rest.split_whitespace().next() {
+		Some(word) => Err(ParserError::new(&format!("Unexpected {}", word), consumed)),
+		None => Ok(list),
 	}
 }
 
@@ -182,17 +566,40 @@ pub enum PipelineError {
 fn default_environment_vars() -> HashMap<String, String> {
 	let mut env = HashMap::new();
 	env.insert("PATH".to_string(), "/bin:/usr/bin".to_string());
-	env.insert("PS1".to_string(), "$ ".to_string());
+	env.insert("PS1".to_string(), "\\w $ ".to_string());
+	if let Ok(home) = std::env::var("HOME") {
+		env.insert("HOME".to_string(), home);
+	}
+
 	env
 }
 
-fn default_builtins() -> HashMap<String, Box<dyn builtins::Builtin>> {
+fn default_builtins() -> HashMap<String, Rc<dyn builtins::Builtin>> {
 	let mut builtins = HashMap::new();
 	builtins.insert(
 		"clear".to_string(),
-		Box::new(builtins::Clear) as Box<dyn builtins::Builtin>,
+		Rc::new(builtins::Clear) as Rc<dyn builtins::Builtin>,
+	);
+	builtins.insert("cd".to_string(), Rc::new(builtins::Cd) as Rc<dyn builtins::Builtin>);
+	builtins.insert(
+		"pushd".to_string(),
+		Rc::new(builtins::Pushd) as Rc<dyn builtins::Builtin>,
+	);
+	builtins.insert("popd".to_string(), Rc::new(builtins::Popd) as Rc<dyn builtins::Builtin>);
+	builtins.insert("dirs".to_string(), Rc::new(builtins::Dirs) as Rc<dyn builtins::Builtin>);
+	builtins.insert("jobs".to_string(), Rc::new(builtins::Jobs) as Rc<dyn builtins::Builtin>);
+	builtins.insert(
+		"disown".to_string(),
+		Rc::new(builtins::Disown) as Rc<dyn builtins::Builtin>,
+	);
+	builtins.insert(NOHUP.to_string(), Rc::new(builtins::Nohup) as Rc<dyn builtins::Builtin>);
+	builtins.insert("exit".to_string(), Rc::new(builtins::Exit) as Rc<dyn builtins::Builtin>);
+	builtins.insert(
+		"shift".to_string(),
+		Rc::new(builtins::Shift) as Rc<dyn builtins::Builtin>,
 	);
-	builtins.insert("cd".to_string(), Box::new(builtins::Cd) as Box<dyn builtins::Builtin>);
+	builtins.insert("test".to_string(), Rc::new(builtins::Test) as Rc<dyn builtins::Builtin>);
+	builtins.insert("[".to_string(), Rc::new(builtins::Test) as Rc<dyn builtins::Builtin>);
 	builtins
 }
 
@@ -215,5 +622,63 @@ mod tests {
 			shell.concrete_arguments(&parser::try_parse("echo'hello'\"world\"").unwrap().unwrap()),
 			vec!["echohelloworld"]
 		);
+
+		shell.environment.insert("FOO".to_owned(), "a  b".to_owned());
+		assert_eq!(
+			shell.concrete_arguments(&parser::try_parse("echo $FOO \"$FOO\" '$FOO'").unwrap().unwrap()),
+			vec!["echo", "a", "b", "a  b", "$FOO"]
+		);
+	}
+
+	#[test]
+	fn test_run_script() {
+		let mut shell = Shell::new();
+		let script =
+			"#!/bin/qsh\n# Comments are skipped.\nif test -d /\nthen\n\t# Even in commands.\n\texit 4\nfi\nexit 5\n";
+		assert_eq!(shell.run_script("test", script), 4);
+
+		let mut shell = Shell::new().with_positional(vec![String::from("a")]);
+		assert_eq!(shell.run_script("test", "test $1 = a && test $# -eq 1"), 0);
+		assert_eq!(shell.run_script("test", "test $1 = b"), 1);
+
+		let arguments = ["a", "b", "c"].map(String::from).to_vec();
+		let mut shell = Shell::new()
+			.with_name(String::from("script.sh"))
+			.with_positional(arguments);
+		assert_eq!(
+			shell.run_script("test", "test $0 = script.sh && shift && test $1$2 = bc"),
+			0
+		);
+		assert_eq!(
+			shell.run_script("test", "shift 2 && test $# -eq 0 && test \"$*\" = \"\""),
+			0
+		);
+		assert_eq!(shell.run_script("test", "shift"), 1);
+		assert_eq!(shell.run_script("test", "shift x"), 2);
+
+		// Syntax errors stop the script before the command that has them.
+		let mut shell = Shell::new();
+		assert_eq!(
+			shell.run_script("test", "test -d /\nfi\nexit 3"),
+			SYNTAX_ERROR_EXIT_CODE
+		);
+		assert_eq!(shell.run_script("test", "while true\ndo\n"), SYNTAX_ERROR_EXIT_CODE);
+	}
+
+	#[test]
+	fn test_expand_prompt() {
+		let mut shell = Shell::new();
+		shell.environment.insert("HOME".to_owned(), "/home/colin".to_owned());
+		shell.environment.insert("PWD".to_owned(), "/home/colin/src".to_owned());
+		assert_eq!(shell.expand_prompt("\\w $ "), "~/src $ ");
+		assert_eq!(shell.expand_prompt("\\W \\\\ \\x"), "src \\ \\x");
+
+		shell.environment.insert("PWD".to_owned(), "/home/colin".to_owned());
+		assert_eq!(shell.expand_prompt("\\w"), "~");
+
+		shell
+			.environment
+			.insert("PWD".to_owned(), "/home/colinother".to_owned());
+		assert_eq!(shell.expand_prompt("\\w"), "/home/colinother");
 	}
 }
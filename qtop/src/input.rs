@@ -0,0 +1,89 @@
+use std::{
+	io::{self, stdin, Read},
+	time::Duration,
+};
+
+use common::io::{RawFdReader, STDIN_FD};
+use escapes::{ANSIEscapeSequence, ESC};
+use nix::{
+	errno::Errno,
+	poll::{poll, PollFd, PollFlags},
+};
+
+/// How long to wait for the rest of an escape sequence after an ESC, before deciding that it was the escape key.
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// A key pressed by the user.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+	Char(char),
+	Up,
+	Down,
+	Enter,
+	Backspace,
+	Escape,
+
+	/// Ctrl-C, which is read as a key rather than a signal so that the terminal can be restored.
+	Interrupt,
+}
+
+/// Reads keys from stdin, which must be in non-canonical mode so that keys can be read as they're pressed.
+pub struct Input {
+	reader: RawFdReader,
+}
+
+impl Input {
+	pub fn new() -> Self {
+		Self {
+			reader: RawFdReader::new(STDIN_FD),
+		}
+	}
+
+	/// Waits up to the given timeout for a key to be pressed, returning None if one wasn't.
+	pub fn read_key(&mut self, timeout: Duration) -> io::Result<Option<Key>> {
+		if !wait_readable(timeout)? {
+			return Ok(None);
+		}
+
+		let mut byte = [0; 1];
+		if self.reader.read(&mut byte)? == 0 {
+			return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stdin closed"));
+		}
+
+		let key = match byte[0] {
+			b'\r' | b'\n' => Key::Enter,
+			0x7f | 0x08 => Key::Backspace,
+			0x03 => Key::Interrupt,
+			b if b == ESC as u8 => {
+				if !wait_readable(ESCAPE_TIMEOUT)? {
+					return Ok(Some(Key::Escape));
+				}
+
+				// Sequences we don't handle (e.g. function keys) are read and dropped, so they don't show up as keys.
+				match ANSIEscapeSequence::read(&mut self.reader) {
+					Ok(ANSIEscapeSequence::CursorUp(_)) => Key::Up,
+					Ok(ANSIEscapeSequence::CursorDown(_)) => Key::Down,
+					_ => return Ok(None),
+				}
+			}
+			b if b.is_ascii() && !b.is_ascii_control() => Key::Char(b as char),
+			_ => return Ok(None),
+		};
+
+		Ok(Some(key))
+	}
+}
+
+/// Waits up to the given timeout for stdin to be readable, returning whether it is.
+fn wait_readable(timeout: Duration) -> io::Result<bool> {
+	let stdin = stdin();
+	let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+	let mut fds = [PollFd::new(&stdin, PollFlags::POLLIN)];
+	loop {
+		match poll(&mut fds, timeout) {
+			Ok(ready) => return Ok(ready > 0),
+			Err(Errno::EINTR) => continue,
+			Err(e) => return Err(e.into()),
+		}
+	}
+}
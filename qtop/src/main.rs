@@ -0,0 +1,465 @@
+mod input;
+mod sample;
+
+use std::{
+	collections::HashMap,
+	io::{stdin, stdout, Write},
+	process::ExitCode,
+	str::FromStr,
+	time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use auth::User;
+use clap::{Arg, Command};
+use common::io::{terminal_size, STDOUT_FD};
+use escapes::{ANSIEscapeSequence, Capabilities, CursorPosition, EraseInDisplay, EraseInLine};
+use input::{Input, Key};
+use nix::{
+	sys::{
+		signal::{kill, Signal},
+		termios::{tcgetattr, tcsetattr, LocalFlags, SetArg, Termios},
+	},
+	unistd::Pid,
+};
+use procfs::clock_ticks;
+use sample::{Sample, Sampler};
+use tables::{Table, WatchRenderer};
+
+const HEADERS: [&str; 8] = ["PID", "USER", "S", "CPU%", "MEM%", "RSS", "TIME", "COMMAND"];
+
+/// The size of the screen to assume if stdout isn't a terminal, as (rows, columns).
+const DEFAULT_TERMINAL_SIZE: (usize, usize) = (24, 80);
+
+/// How long to wait between the first two samples, so that the first screen has CPU usage to show.
+const FIRST_SAMPLE_DELAY: Duration = Duration::from_millis(250);
+
+/// The key that the processes are sorted by, in descending order for usage, and ascending for everything else.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+	Cpu,
+	Memory,
+	Pid,
+	Command,
+}
+
+impl SortKey {
+	fn name(&self) -> &'static str {
+		match self {
+			SortKey::Cpu => "cpu",
+			SortKey::Memory => "mem",
+			SortKey::Pid => "pid",
+			SortKey::Command => "command",
+		}
+	}
+
+	fn from_name(name: &str) -> Option<Self> {
+		[SortKey::Cpu, SortKey::Memory, SortKey::Pid, SortKey::Command]
+			.into_iter()
+			.find(|key| key.name() == name)
+	}
+}
+
+/// What the user is currently being asked for on the status line.
+enum Prompt {
+	None,
+
+	/// The PID of the process to kill.
+	KillPid(String),
+
+	/// The signal to kill the given process with.
+	KillSignal(i32, String),
+}
+
+/// Puts the terminal into non-canonical mode so that keys can be read as they're pressed, restoring it when dropped.
+struct RawTerminal {
+	original: Termios,
+}
+
+impl RawTerminal {
+	fn new() -> Result<Self> {
+		let original = tcgetattr(stdin()).with_context(|| "failed to get terminal attributes")?;
+
+		// Disable canonical mode and echo so that keys aren't buffered or printed, and signals so that Ctrl-C can be
+		// handled by restoring the terminal before exiting.
+		let mut attrs = original.clone();
+		attrs.local_flags &= !(LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ISIG);
+		tcsetattr(stdin(), SetArg::TCSANOW, &attrs).with_context(|| "failed to set terminal attributes")?;
+
+		Ok(Self { original })
+	}
+}
+
+impl Drop for RawTerminal {
+	fn drop(&mut self) {
+		print!(
+			"{}{}",
+			ANSIEscapeSequence::EraseInDisplay(EraseInDisplay(2)),
+			ANSIEscapeSequence::CursorPosition(CursorPosition(1, 1))
+		);
+		stdout().flush().ok();
+		tcsetattr(stdin(), SetArg::TCSANOW, &self.original).ok();
+	}
+}
+
+struct Qtop {
+	sampler: Sampler,
+	sample: Sample,
+	sort: SortKey,
+	renderer: WatchRenderer,
+
+	/// The size of the terminal, as (rows, columns).
+	size: (usize, usize),
+
+	/// The names of users, by UID, so that the passwd file isn't read for every process on every refresh.
+	usernames: HashMap<u32, String>,
+	prompt: Prompt,
+
+	/// A message to show on the status line until the next key is pressed, e.g. the result of a kill.
+	message: Option<String>,
+
+	/// The status line currently on the screen, or None if it needs to be redrawn.
+	status_line: Option<String>,
+}
+
+impl Qtop {
+	fn new(sort: SortKey) -> Self {
+		let size = terminal_size(STDOUT_FD).unwrap_or(DEFAULT_TERMINAL_SIZE);
+		Self {
+			sampler: Sampler::default(),
+			sample: Sample::default(),
+			sort,
+			// The last line of the screen is used for the status line.
+			renderer: WatchRenderer::new(size.0.saturating_sub(1)).with_capabilities(Capabilities::from_env()),
+			size,
+			usernames: HashMap::new(),
+			prompt: Prompt::None,
+			message: None,
+			status_line: None,
+		}
+	}
+
+	/// Takes a new sample of the processes on the system.
+	fn refresh(&mut self) -> Result<()> {
+		self.sample = self.sampler.sample().with_context(|| "failed to read processes")?;
+		self.sort();
+		Ok(())
+	}
+
+	fn sort(&mut self) {
+		let processes = &mut self.sample.processes;
+		match self.sort {
+			SortKey::Cpu => processes.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then(a.pid.cmp(&b.pid))),
+			SortKey::Memory => processes.sort_by(|a, b| b.rss.cmp(&a.rss).then(a.pid.cmp(&b.pid))),
+			SortKey::Pid => processes.sort_by_key(|process| process.pid),
+			SortKey::Command => processes.sort_by(|a, b| a.command.cmp(&b.command).then(a.pid.cmp(&b.pid))),
+		}
+	}
+
+	fn username(&mut self, uid: u32) -> String {
+		self.usernames
+			.entry(uid)
+			.or_insert_with(|| match User::from_uid(uid) {
+				Ok(Some(user)) => user.username,
+				_ => uid.to_string(),
+			})
+			.clone()
+	}
+
+	fn table(&mut self) -> Table<8> {
+		let ticks = clock_ticks();
+		let processes = self.sample.processes.clone();
+		let mut rows = Vec::with_capacity(processes.len());
+		for process in processes {
+			rows.push([
+				process.pid.to_string(),
+				self.username(process.uid),
+				process.state.to_string(),
+				format!("{:.1}", process.cpu),
+				format!("{:.1}", process.memory),
+				human_size(process.rss),
+				format_cpu_time(process.cpu_time, ticks),
+				process.command,
+			]);
+		}
+
+		// Commands are truncated to the space left on the screen (leaving the last column free so the terminal doesn't
+		// wrap), as wrapped lines would push the table out of place.
+		let mut widths = HEADERS.map(str::len);
+		for row in rows.iter() {
+			for (width, cell) in widths.iter_mut().zip(row.iter()) {
+				*width = (*width).max(cell.chars().count());
+			}
+		}
+
+		let command_width = widths[..HEADERS.len() - 1]
+			.iter()
+			.fold(self.size.1.saturating_sub(1), |remaining, width| {
+				remaining.saturating_sub(width + 1)
+			})
+			.max(HEADERS[HEADERS.len() - 1].len());
+
+		let mut table = Table::new_with_headers(HEADERS);
+		for mut row in rows {
+			row[HEADERS.len() - 1] = row[HEADERS.len() - 1].chars().take(command_width).collect();
+			table.add_row(row.each_ref().map(String::as_str));
+		}
+
+		table
+	}
+
+	fn status(&self) -> String {
+		match &self.prompt {
+			Prompt::KillPid(input) => match self.sample.processes.first() {
+				Some(process) => format!("PID to kill [{}]: {}", process.pid, input),
+				None => format!("PID to kill: {}", input),
+			},
+			Prompt::KillSignal(pid, input) => format!("Signal to send to {} [TERM]: {}", pid, input),
+			Prompt::None => match &self.message {
+				Some(message) => message.clone(),
+				None => format!(
+					"{} processes | load {:.2} {:.2} {:.2} | cpu {:.1}% | mem {}/{} | sort: {} | q quit, k kill, P/M/N/C sort",
+					self.sample.processes.len(),
+					self.sample.load.one,
+					self.sample.load.five,
+					self.sample.load.fifteen,
+					self.sample.cpu,
+					human_size(self.sample.memory.used()),
+					human_size(self.sample.memory.total),
+					self.sort.name()
+				),
+			},
+		}
+	}
+
+	/// Draws the screen, redrawing only what changed since it was last drawn.
+	fn draw(&mut self) -> Result<()> {
+		let size = terminal_size(STDOUT_FD).unwrap_or(DEFAULT_TERMINAL_SIZE);
+		if size != self.size {
+			self.size = size;
+			self.renderer.resize(size.0.saturating_sub(1));
+			self.status_line = None;
+		}
+
+		let table = self.table();
+		let mut output = self.renderer.render(&table);
+
+		let status: String = self.status().chars().take(self.size.1.saturating_sub(1)).collect();
+		let status_row = self.size.0.clamp(1, u8::MAX as usize) as u8;
+		if self.status_line.as_ref() != Some(&status) {
+			output.push_str(&ANSIEscapeSequence::CursorPosition(CursorPosition(status_row, 1)).to_string());
+			output.push_str(&ANSIEscapeSequence::EraseInLine(EraseInLine(2)).to_string());
+			output.push_str(&status);
+		}
+
+		// Leave the cursor at the end of the status line, where anything typed into a prompt shows up.
+		let status_column = (status.chars().count() + 1).min(u8::MAX as usize) as u8;
+		output.push_str(&ANSIEscapeSequence::CursorPosition(CursorPosition(status_row, status_column)).to_string());
+		self.status_line = Some(status);
+
+		let mut stdout = stdout().lock();
+		stdout.write_all(output.as_bytes())?;
+		stdout.flush()?;
+		Ok(())
+	}
+
+	/// Handles a key pressed by the user, returning whether qtop should keep running.
+	fn handle_key(&mut self, key: Key) -> bool {
+		self.message = None;
+		match (&mut self.prompt, key) {
+			(_, Key::Interrupt) | (Prompt::None, Key::Char('q')) => return false,
+			(Prompt::None, Key::Char('k')) => self.prompt = Prompt::KillPid(String::new()),
+			(Prompt::None, Key::Char(c)) => {
+				let sort = match c {
+					'P' => SortKey::Cpu,
+					'M' => SortKey::Memory,
+					'N' => SortKey::Pid,
+					'C' => SortKey::Command,
+					_ => return true,
+				};
+
+				self.sort = sort;
+				self.sort();
+			}
+			(Prompt::None, Key::Up) => self.renderer.scroll_by(-1),
+			(Prompt::None, Key::Down) => self.renderer.scroll_by(1),
+			(Prompt::None, _) => {}
+			(_, Key::Escape) => self.prompt = Prompt::None,
+			(Prompt::KillPid(input) | Prompt::KillSignal(_, input), Key::Char(c)) => input.push(c),
+			(Prompt::KillPid(input) | Prompt::KillSignal(_, input), Key::Backspace) => {
+				input.pop();
+			}
+			(Prompt::KillPid(input), Key::Enter) => {
+				let pid = match input.as_str() {
+					"" => self.sample.processes.first().map(|process| process.pid),
+					input => input.parse().ok(),
+				};
+
+				self.prompt = match pid {
+					Some(pid) => Prompt::KillSignal(pid, String::new()),
+					None => {
+						self.message = Some(format!("invalid PID: {}", input));
+						Prompt::None
+					}
+				};
+			}
+			(Prompt::KillSignal(pid, input), Key::Enter) => {
+				let pid = *pid;
+				self.message = Some(match parse_signal(input) {
+					Ok(signal) => match kill(Pid::from_raw(pid), signal) {
+						Ok(()) => format!("sent {} to {}", signal, pid),
+						Err(e) => format!("failed to send {} to {}: {}", signal, pid, e),
+					},
+					Err(e) => e.to_string(),
+				});
+				self.prompt = Prompt::None;
+			}
+			(_, Key::Up | Key::Down) => {}
+		}
+
+		true
+	}
+}
+
+/// Parses a signal from its number, or its name with or without the SIG prefix, e.g. `9`, `KILL` or `SIGKILL`.
+/// An empty signal is SIGTERM.
+fn parse_signal(signal: &str) -> Result<Signal> {
+	let signal = signal.trim().to_uppercase();
+	if signal.is_empty() {
+		return Ok(Signal::SIGTERM);
+	}
+
+	if let Ok(number) = signal.parse::<i32>() {
+		return Signal::try_from(number).map_err(|_| anyhow!("invalid signal: {}", number));
+	}
+
+	let name = if signal.starts_with("SIG") {
+		signal.clone()
+	} else {
+		format!("SIG{}", signal)
+	};
+
+	Signal::from_str(&name).map_err(|_| anyhow!("invalid signal: {}", signal))
+}
+
+fn human_size(bytes: u64) -> String {
+	const UNITS: [&str; 7] = ["B", "K", "M", "G", "T", "P", "E"];
+
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+
+	let size = format!("{:.1}", size);
+	format!("{}{}", size.trim_end_matches(".0"), UNITS[unit])
+}
+
+/// Formats a CPU time in clock ticks as minutes, seconds, and hundredths, e.g. `12:03.45`.
+fn format_cpu_time(ticks: u64, ticks_per_second: u64) -> String {
+	let hundredths = ticks * 100 / ticks_per_second.max(1);
+	format!(
+		"{}:{:02}.{:02}",
+		hundredths / 6000,
+		hundredths / 100 % 60,
+		hundredths % 100
+	)
+}
+
+fn qtop(interval: Duration, sort: SortKey) -> Result<()> {
+	let mut qtop = Qtop::new(sort);
+	let mut input = Input::new();
+
+	qtop.refresh()?;
+	std::thread::sleep(FIRST_SAMPLE_DELAY);
+	qtop.refresh()?;
+
+	let _terminal = RawTerminal::new()?;
+	let mut next_refresh = Instant::now() + interval;
+	loop {
+		qtop.draw()?;
+
+		match input.read_key(next_refresh.saturating_duration_since(Instant::now()))? {
+			Some(key) => {
+				if !qtop.handle_key(key) {
+					return Ok(());
+				}
+			}
+			None => {
+				qtop.refresh()?;
+				next_refresh = Instant::now() + interval;
+			}
+		}
+	}
+}
+
+fn main() -> ExitCode {
+	let matches = Command::new("qtop")
+		.version("0.1.0")
+		.author("Colin Douch <colin@quirl.co.nz>")
+		.about("Monitor the processes running on the system")
+		.arg(
+			Arg::new("delay")
+				.short('d')
+				.long("delay")
+				.num_args(1)
+				.default_value("2")
+				.value_parser(clap::value_parser!(f64))
+				.help("The number of seconds between refreshes"),
+		)
+		.arg(
+			Arg::new("sort")
+				.short('s')
+				.long("sort")
+				.num_args(1)
+				.default_value("cpu")
+				.value_parser(["cpu", "mem", "pid", "command"])
+				.help("The key to sort processes by"),
+		)
+		.get_matches();
+
+	let delay: f64 = *matches.get_one("delay").expect("delay is missing");
+	let interval = match Duration::try_from_secs_f64(delay) {
+		Ok(interval) if !interval.is_zero() => interval,
+		_ => {
+			eprintln!("qtop: invalid delay: {}", delay);
+			return ExitCode::FAILURE;
+		}
+	};
+
+	let sort: &String = matches.get_one("sort").expect("sort is missing");
+	let sort = SortKey::from_name(sort).expect("sort is validated by clap");
+
+	match qtop(interval, sort) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(e) => {
+			eprintln!("qtop: {:#}", e);
+			ExitCode::FAILURE
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use nix::sys::signal::Signal;
+
+	use super::{format_cpu_time, parse_signal};
+
+	#[test]
+	fn test_parse_signal() {
+		assert_eq!(parse_signal("").unwrap(), Signal::SIGTERM);
+		assert_eq!(parse_signal("9").unwrap(), Signal::SIGKILL);
+		assert_eq!(parse_signal("hup").unwrap(), Signal::SIGHUP);
+		assert_eq!(parse_signal("SIGINT").unwrap(), Signal::SIGINT);
+		assert!(parse_signal("NOPE").is_err());
+		assert!(parse_signal("1000").is_err());
+	}
+
+	#[test]
+	fn test_format_cpu_time() {
+		assert_eq!(format_cpu_time(0, 100), "0:00.00");
+		assert_eq!(format_cpu_time(12345, 100), "2:03.45");
+		assert_eq!(format_cpu_time(72345, 100), "12:03.45");
+	}
+}
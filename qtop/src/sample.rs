@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use procfs::{processes, CpuTimes, LoadAverage, MemInfo, ProcfsError, SystemStat};
+
+/// The resources a process is using.
+#[derive(Debug, Clone)]
+pub struct ProcessUsage {
+	pub pid: i32,
+	pub uid: u32,
+	pub state: char,
+
+	/// The percentage of a CPU the process used since the last sample, which can be over 100 if it used more than one.
+	pub cpu: f64,
+
+	/// The percentage of the system's memory that the process has in real memory.
+	pub memory: f64,
+
+	/// The memory the process has in real memory, in bytes.
+	pub rss: u64,
+
+	/// The total time the process has spent on a CPU, in clock ticks.
+	pub cpu_time: u64,
+	pub command: String,
+}
+
+/// A snapshot of the resources being used on the system.
+#[derive(Debug, Clone, Default)]
+pub struct Sample {
+	pub processes: Vec<ProcessUsage>,
+
+	/// The percentage of the total CPU time that was spent doing work since the last sample.
+	pub cpu: f64,
+	pub memory: MemInfo,
+	pub load: LoadAverage,
+}
+
+/// Takes samples of the system, working out how much CPU was used between them.
+#[derive(Default)]
+pub struct Sampler {
+	cpu_times: Option<CpuTimes>,
+	process_times: HashMap<i32, u64>,
+}
+
+impl Sampler {
+	pub fn sample(&mut self) -> Result<Sample, ProcfsError> {
+		let stat = SystemStat::read()?;
+		let memory = MemInfo::read()?;
+		let load = LoadAverage::read()?;
+		let processes = processes()?;
+
+		let previous = self.cpu_times.replace(stat.cpu).unwrap_or_default();
+		let elapsed = stat.cpu.total().saturating_sub(previous.total());
+		let cpus = stat.cpus.len().max(1) as f64;
+		let percent_of = |ticks: u64, of: u64| {
+			if of == 0 {
+				0.0
+			} else {
+				ticks as f64 / of as f64 * 100.0
+			}
+		};
+
+		let mut process_times = HashMap::with_capacity(processes.len());
+		let mut usages = Vec::with_capacity(processes.len());
+		for process in processes {
+			let cpu_time = process.cpu_time();
+
+			// The CPU times of the system are summed over every CPU, so a process using all of one is using 1/cpus of it.
+			let used = cpu_time.saturating_sub(self.process_times.get(&process.pid).copied().unwrap_or(0));
+			process_times.insert(process.pid, cpu_time);
+
+			let rss = process.rss_bytes();
+			usages.push(ProcessUsage {
+				pid: process.pid,
+				uid: process.status.uid,
+				state: process.stat.state,
+				cpu: percent_of(used, elapsed) * cpus,
+				memory: percent_of(rss, memory.total),
+				rss,
+				cpu_time,
+				command: process.command(),
+			});
+		}
+
+		self.process_times = process_times;
+		Ok(Sample {
+			processes: usages,
+			cpu: percent_of(stat.cpu.busy().saturating_sub(previous.busy()), elapsed),
+			memory,
+			load,
+		})
+	}
+}
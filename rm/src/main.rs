@@ -0,0 +1,93 @@
+use std::{
+	fs,
+	io::ErrorKind,
+	path::{Path, PathBuf},
+	process::ExitCode,
+};
+
+use clap::{Arg, ArgAction, Command};
+use common::fsops::remove_tree;
+
+/// Whether the path ends in `.` or `..`, which can't be removed without removing the directory they're in (or its
+/// parent).
+fn is_dot(path: &str) -> bool {
+	let path = path.trim_end_matches('/');
+	let name = path.rsplit('/').next().unwrap_or(path);
+	name == "." || name == ".."
+}
+
+fn main() -> ExitCode {
+	let matches = Command::new("rm")
+		.about("remove files or directories")
+		.author("Colin Douch")
+		.version("0.1")
+		.arg(
+			Arg::new("recursive")
+				.short('r')
+				.short_alias('R')
+				.long("recursive")
+				.help("remove directories and their contents recursively")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("force")
+				.short('f')
+				.long("force")
+				.help("ignore nonexistent files")
+				.action(ArgAction::SetTrue),
+		)
+		.arg(
+			Arg::new("paths")
+				.required(true)
+				.num_args(1..)
+				.help("the files to remove"),
+		)
+		.get_matches();
+
+	let recursive = matches.get_flag("recursive");
+	let force = matches.get_flag("force");
+	let paths: Vec<&String> = matches.get_many("paths").unwrap().collect();
+
+	let mut code = ExitCode::SUCCESS;
+	for path in paths {
+		if is_dot(path) {
+			eprintln!("rm: refusing to remove '.' or '..' directory: skipping '{}'", path);
+			code = ExitCode::FAILURE;
+			continue;
+		}
+
+		let path = PathBuf::from(path);
+		if recursive && path.canonicalize().is_ok_and(|path| path == Path::new("/")) {
+			eprintln!("rm: it is dangerous to operate recursively on '/'");
+			code = ExitCode::FAILURE;
+			continue;
+		}
+
+		let metadata = match fs::symlink_metadata(&path) {
+			Ok(metadata) => metadata,
+			Err(e) if force && e.kind() == ErrorKind::NotFound => continue,
+			Err(e) => {
+				eprintln!("rm: cannot remove '{}': {}", path.display(), e);
+				code = ExitCode::FAILURE;
+				continue;
+			}
+		};
+
+		let result = if !metadata.is_dir() {
+			fs::remove_file(&path)
+		} else if recursive {
+			remove_tree(&path)
+		} else {
+			eprintln!("rm: cannot remove '{}': Is a directory", path.display());
+			code = ExitCode::FAILURE;
+			continue;
+		};
+
+		if let Err(e) = result {
+			eprintln!("rm: cannot remove '{}': {}", path.display(), e);
+			code = ExitCode::FAILURE;
+		}
+	}
+
+	code
+}
@@ -1,10 +1,19 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use bytestruct::NullTerminatedString;
 use bytestruct_derive::ByteStruct;
 
-use crate::types::Superblock;
+use crate::types::{FilesystemHealth, FilesystemState, Superblock};
 
 pub const EXT_MAGIC: u16 = 0xEF53;
 
+/// The filesystem was cleanly unmounted.
+pub const STATE_VALID_FS: u16 = 0x0001;
+/// Errors were detected in the filesystem.
+pub const STATE_ERROR_FS: u16 = 0x0002;
+/// Orphans are being recovered.
+pub const STATE_ORPHAN_FS: u16 = 0x0004;
+
 /// Sparse superblocks.
 pub const RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
 /// Allow storing files larger than 2GiB.
@@ -130,9 +139,18 @@ pub struct ExtSuperBlock {
 	pub prealloc_blocks: u8,
 	pub prealloc_dir_blocks: u8,
 	_unused: u16,
-	pub journal_uuid: [u8; 16],
-	pub journal_inode: u32,
-	pub journal_dev: u32,
+	#[present_if(field = "feature_compat", bit = COMPAT_HAS_JOURNAL)]
+	pub journal: Option<ExtJournal>,
+}
+
+/// Where the journal of an ext3 or ext4 filesystem is, which the superblock only says if it has COMPAT_HAS_JOURNAL.
+#[derive(ByteStruct)]
+pub struct ExtJournal {
+	pub uuid: [u8; 16],
+	pub inode: u32,
+	pub dev: u32,
+	/// The first of the inodes that were deleted while they were still open, which are cleaned up when the journal
+	/// is recovered.
 	pub orphan_inode_head: u32,
 }
 
@@ -165,6 +183,44 @@ impl Superblock for ExtSuperBlock {
 	fn uuid(&self) -> bytestruct::UUID {
 		self.uuid
 	}
+
+	fn health(&self) -> Option<FilesystemHealth> {
+		// The journal needing recovery means the filesystem is still mounted, or wasn't unmounted cleanly, even
+		// though the state may say otherwise as it's only updated on a check.
+		let state = if self.state & STATE_ERROR_FS != 0 {
+			FilesystemState::Errors
+		} else if self.state & STATE_VALID_FS == 0 || self.feature_incompat & INCOMPAT_RECOVER != 0 {
+			FilesystemState::Dirty
+		} else {
+			FilesystemState::Clean
+		};
+
+		// The max mount count is signed on disk, and a value of zero or -1 disables checks by mount count.
+		let max_mount_count = match self.max_mount_count as i16 {
+			max if max > 0 => Some(max as u32),
+			_ => None,
+		};
+
+		Some(FilesystemHealth {
+			state,
+			last_mounted: timestamp(self.mount_time),
+			last_checked: timestamp(self.last_check),
+			mount_count: self.mount_count as u32,
+			max_mount_count,
+			check_interval: match self.check_interval {
+				0 => None,
+				interval => Some(Duration::from_secs(interval as u64)),
+			},
+		})
+	}
+}
+
+/// Converts a timestamp from the superblock, where zero means never, to a time.
+fn timestamp(seconds: u32) -> Option<SystemTime> {
+	match seconds {
+		0 => None,
+		seconds => Some(UNIX_EPOCH + Duration::from_secs(seconds as u64)),
+	}
 }
 
 /// The type of the ext filesystem.
@@ -218,3 +274,87 @@ fn has_any(val: u32, features: &[u32]) -> bool {
 	}
 	false
 }
+
+#[cfg(test)]
+mod test {
+	use std::{
+		io::Cursor,
+		time::{Duration, UNIX_EPOCH},
+	};
+
+	use bytestruct::ReadFrom;
+
+	use super::{ExtSuperBlock, COMPAT_HAS_JOURNAL, EXT_MAGIC, INCOMPAT_RECOVER, STATE_ERROR_FS, STATE_VALID_FS};
+	use crate::{FilesystemState, Superblock};
+
+	/// Builds a superblock with the given state and mount bookkeeping, as `mke2fs` and the kernel would write it.
+	fn superblock(state: u16, incompat: u32, mount_count: u16, max_mount_count: i16) -> ExtSuperBlock {
+		let mut block = vec![0; ExtSuperBlock::size()];
+		block[0x2C..0x30].copy_from_slice(&1_700_000_000u32.to_le_bytes());
+		block[0x34..0x36].copy_from_slice(&mount_count.to_le_bytes());
+		block[0x36..0x38].copy_from_slice(&max_mount_count.to_le_bytes());
+		block[0x38..0x3A].copy_from_slice(&EXT_MAGIC.to_le_bytes());
+		block[0x3A..0x3C].copy_from_slice(&state.to_le_bytes());
+		block[0x40..0x44].copy_from_slice(&1_600_000_000u32.to_le_bytes());
+		block[0x44..0x48].copy_from_slice(&86400u32.to_le_bytes());
+		block[0x60..0x64].copy_from_slice(&incompat.to_le_bytes());
+		ExtSuperBlock::read_from(&mut Cursor::new(block)).unwrap()
+	}
+
+	#[test]
+	fn test_health() {
+		let clean = superblock(STATE_VALID_FS, 0, 3, -1).health().unwrap();
+		assert_eq!(clean.state, FilesystemState::Clean);
+		assert_eq!(
+			clean.last_mounted,
+			Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+		);
+		assert_eq!(
+			clean.last_checked,
+			Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+		);
+		assert_eq!(clean.mount_count, 3);
+		assert_eq!(clean.max_mount_count, None);
+		assert_eq!(clean.check_interval, Some(Duration::from_secs(86400)));
+
+		// An hour after the last check, and once the check interval has passed.
+		let checked = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+		assert!(!clean.needs_check(checked + Duration::from_secs(3600)));
+		assert!(clean.needs_check(checked + Duration::from_secs(86400)));
+
+		let recovering = superblock(STATE_VALID_FS, INCOMPAT_RECOVER, 0, -1).health().unwrap();
+		assert_eq!(recovering.state, FilesystemState::Dirty);
+		assert!(recovering.needs_check(checked));
+
+		assert_eq!(superblock(0, 0, 0, -1).health().unwrap().state, FilesystemState::Dirty);
+		assert_eq!(
+			superblock(STATE_VALID_FS | STATE_ERROR_FS, 0, 0, -1)
+				.health()
+				.unwrap()
+				.state,
+			FilesystemState::Errors
+		);
+
+		let mounted_out = superblock(STATE_VALID_FS, 0, 20, 20).health().unwrap();
+		assert_eq!(mounted_out.max_mount_count, Some(20));
+		assert!(mounted_out.needs_check(checked));
+	}
+
+	#[test]
+	fn test_journal() {
+		let mut block = vec![0; ExtSuperBlock::size()];
+		block[0x38..0x3A].copy_from_slice(&EXT_MAGIC.to_le_bytes());
+		block[0xD0..0xE0].copy_from_slice(&[0xAB; 16]);
+		block[0xE0..0xE4].copy_from_slice(&8u32.to_le_bytes());
+
+		let ext2 = ExtSuperBlock::read_from(&mut Cursor::new(&block)).unwrap();
+		assert!(ext2.journal.is_none());
+
+		block[0x5C..0x60].copy_from_slice(&COMPAT_HAS_JOURNAL.to_le_bytes());
+		let ext3 = ExtSuperBlock::read_from(&mut Cursor::new(&block)).unwrap();
+		let journal = ext3.journal.as_ref().unwrap();
+		assert_eq!(journal.uuid, [0xAB; 16]);
+		assert_eq!(journal.inode, 8);
+		assert_eq!(ext3.name(), "ext3");
+	}
+}
@@ -0,0 +1,284 @@
+use std::io::Cursor;
+
+use bytestruct::{ReadFrom, UUID};
+use bytestruct_derive::ByteStruct;
+
+use crate::types::Superblock;
+
+/// The signature at the end of every FAT boot sector.
+pub const FAT_SIGNATURE: u16 = 0xAA55;
+
+/// The extended boot signature that indicates the volume ID, label, and filesystem type are present.
+pub const EXTENDED_BOOT_SIGNATURE: u8 = 0x29;
+
+/// An older extended boot signature, after which only the volume ID is present.
+pub const OLD_EXTENDED_BOOT_SIGNATURE: u8 = 0x28;
+
+/// The label that FAT filesystems have when no label has been set.
+const NO_NAME_LABEL: &str = "NO NAME";
+
+/// The offset of the extended boot record in the extended BPB of a FAT32 filesystem. FAT12/16 filesystems have it
+/// at the start of the extended BPB.
+const FAT32_BOOT_RECORD_OFFSET: usize = 28;
+
+/// The size of a directory entry in the root directory of a FAT12/16 filesystem.
+const DIRECTORY_ENTRY_SIZE: u32 = 32;
+
+/// The boot sector of a FAT filesystem, which holds the BIOS Parameter Block (BPB) describing its layout.
+#[derive(ByteStruct)]
+#[little_endian]
+pub struct FatSuperBlock {
+	pub jump: [u8; 3],
+	pub oem_name: [u8; 8],
+	pub bytes_per_sector: u16,
+	pub sectors_per_cluster: u8,
+	pub reserved_sectors: u16,
+	pub fat_count: u8,
+	pub root_entry_count: u16,
+	pub total_sectors_16: u16,
+	pub media: u8,
+	pub sectors_per_fat_16: u16,
+	pub sectors_per_track: u16,
+	pub head_count: u16,
+	pub hidden_sectors: u32,
+	pub total_sectors_32: u32,
+	/// The extended BPB, whose layout differs between FAT32 and FAT12/16.
+	pub extended_bpb: [u8; 54],
+	pub boot_code: [u8; 420],
+	pub signature: u16,
+}
+
+/// The part of the extended BPB that is shared between FAT12/16 and FAT32, albeit at different offsets.
+#[derive(ByteStruct)]
+#[little_endian]
+pub struct FatBootRecord {
+	pub drive_number: u8,
+	_reserved: u8,
+	pub boot_signature: u8,
+	pub volume_id: u32,
+	pub volume_label: [u8; 11],
+	pub fs_type: [u8; 8],
+}
+
+/// The variant of FAT filesystem, which determines the size of the entries in the allocation table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+	Fat12,
+	Fat16,
+	Fat32,
+}
+
+impl Superblock for FatSuperBlock {
+	fn offset() -> u64 {
+		0
+	}
+
+	fn size() -> usize {
+		0x200
+	}
+
+	fn validate(&self) -> bool {
+		// FAT has no magic number of its own, so this checks that the BPB describes a plausible filesystem.
+		// exFAT and NTFS boot sectors zero fields that FAT requires, so they aren't mistaken for it.
+		self.signature == FAT_SIGNATURE
+			&& matches!(self.jump[0], 0xEB | 0xE9)
+			&& self.bytes_per_sector.is_power_of_two()
+			&& (512..=4096).contains(&self.bytes_per_sector)
+			&& self.sectors_per_cluster.is_power_of_two()
+			&& self.reserved_sectors > 0
+			&& self.fat_count > 0
+			&& (self.media == 0xF0 || self.media >= 0xF8)
+			&& self.sectors_per_fat() > 0
+			&& self.cluster_count() > 0
+	}
+
+	fn name(&self) -> String {
+		// The kernel's driver handles every FAT variant.
+		"vfat".to_string()
+	}
+
+	fn label(&self) -> String {
+		let record = match self.boot_record() {
+			Some(record) if record.boot_signature == EXTENDED_BOOT_SIGNATURE => record,
+			_ => return String::new(),
+		};
+
+		let label = String::from_utf8_lossy(&record.volume_label);
+		let label = label.trim_end_matches([' ', '\0']);
+		if label == NO_NAME_LABEL {
+			String::new()
+		} else {
+			label.to_string()
+		}
+	}
+
+	/// FAT filesystems have a 32 bit serial number rather than a UUID, which is returned big endian in the first four
+	/// bytes, so that it reads the same as the `ABCD-1234` form that tools display it in.
+	fn uuid(&self) -> UUID {
+		let mut uuid = UUID::default();
+		if let Some(record) = self.boot_record() {
+			uuid[..4].copy_from_slice(&record.volume_id.to_be_bytes());
+		}
+
+		uuid
+	}
+}
+
+impl FatSuperBlock {
+	/// Returns the type of the FAT filesystem. Per the FAT specification, this depends only on the number of clusters,
+	/// and not on the filesystem type string in the boot sector.
+	pub fn fat_type(&self) -> FatType {
+		match self.cluster_count() {
+			0..=4084 => FatType::Fat12,
+			4085..=65524 => FatType::Fat16,
+			_ => FatType::Fat32,
+		}
+	}
+
+	/// Returns the number of sectors in the filesystem.
+	pub fn total_sectors(&self) -> u32 {
+		if self.total_sectors_16 != 0 {
+			self.total_sectors_16 as u32
+		} else {
+			self.total_sectors_32
+		}
+	}
+
+	/// Returns the number of sectors in each copy of the allocation table.
+	pub fn sectors_per_fat(&self) -> u32 {
+		if self.sectors_per_fat_16 != 0 {
+			self.sectors_per_fat_16 as u32
+		} else {
+			// On FAT32, the 16 bit field is zero, and the size is the first field of the extended BPB.
+			u32::from_le_bytes([
+				self.extended_bpb[0],
+				self.extended_bpb[1],
+				self.extended_bpb[2],
+				self.extended_bpb[3],
+			])
+		}
+	}
+
+	/// Returns the first cluster of the root directory on FAT32. FAT12/16 filesystems have a fixed size root directory
+	/// before the data region instead, so this is 0 for them.
+	pub fn root_cluster(&self) -> u32 {
+		if self.sectors_per_fat_16 != 0 {
+			return 0;
+		}
+
+		u32::from_le_bytes([
+			self.extended_bpb[8],
+			self.extended_bpb[9],
+			self.extended_bpb[10],
+			self.extended_bpb[11],
+		])
+	}
+
+	/// Returns the number of clusters in the data region of the filesystem.
+	pub fn cluster_count(&self) -> u32 {
+		if self.bytes_per_sector == 0 || self.sectors_per_cluster == 0 {
+			return 0;
+		}
+
+		let root_dir_sectors =
+			(self.root_entry_count as u32 * DIRECTORY_ENTRY_SIZE).div_ceil(self.bytes_per_sector as u32);
+		// Sizes are read from a sector that may not be a FAT boot sector at all, so mustn't be trusted not to overflow.
+		let metadata_sectors = (self.fat_count as u32)
+			.saturating_mul(self.sectors_per_fat())
+			.saturating_add(self.reserved_sectors as u32)
+			.saturating_add(root_dir_sectors);
+
+		self.total_sectors().saturating_sub(metadata_sectors) / self.sectors_per_cluster as u32
+	}
+
+	/// Returns the extended boot record, holding the volume ID and label, if the boot sector has one.
+	pub fn boot_record(&self) -> Option<FatBootRecord> {
+		// FAT32 is told apart by its layout here, as the boot record has to be found before the cluster count is known
+		// to be trustworthy.
+		let offset = if self.sectors_per_fat_16 == 0 && self.root_entry_count == 0 {
+			FAT32_BOOT_RECORD_OFFSET
+		} else {
+			0
+		};
+
+		let record = FatBootRecord::read_from(&mut Cursor::new(&self.extended_bpb[offset..])).ok()?;
+		match record.boot_signature {
+			EXTENDED_BOOT_SIGNATURE | OLD_EXTENDED_BOOT_SIGNATURE => Some(record),
+			_ => None,
+		}
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+	use std::io::Cursor;
+
+	use bytestruct::ReadFrom;
+
+	use super::{FatSuperBlock, FatType, FAT32_BOOT_RECORD_OFFSET};
+	use crate::Superblock;
+
+	/// Builds a boot sector, as written by `mkfs.vfat`, with 512 byte sectors and no reserved clusters.
+	pub(crate) fn boot_sector(fat32: bool, total_sectors: u32, sectors_per_fat: u32, label: &[u8; 11]) -> Vec<u8> {
+		let mut sector = vec![0; 512];
+		sector[0..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+		sector[3..11].copy_from_slice(b"mkfs.fat");
+		sector[11..13].copy_from_slice(&512u16.to_le_bytes());
+		sector[13] = 1;
+		sector[14..16].copy_from_slice(&(if fat32 { 32u16 } else { 1u16 }).to_le_bytes());
+		sector[16] = 2;
+		sector[21] = 0xF8;
+		sector[32..36].copy_from_slice(&total_sectors.to_le_bytes());
+
+		let record = if fat32 {
+			sector[36..40].copy_from_slice(&sectors_per_fat.to_le_bytes());
+			36 + FAT32_BOOT_RECORD_OFFSET
+		} else {
+			sector[17..19].copy_from_slice(&512u16.to_le_bytes());
+			sector[22..24].copy_from_slice(&(sectors_per_fat as u16).to_le_bytes());
+			36
+		};
+
+		sector[record + 2] = 0x29;
+		sector[record + 3..record + 7].copy_from_slice(&0x1234ABCDu32.to_le_bytes());
+		sector[record + 7..record + 18].copy_from_slice(label);
+		sector[510..512].copy_from_slice(&[0x55, 0xAA]);
+		sector
+	}
+
+	fn parse(sector: Vec<u8>) -> FatSuperBlock {
+		FatSuperBlock::read_from(&mut Cursor::new(sector)).unwrap()
+	}
+
+	#[test]
+	fn test_fat_types() {
+		let fat12 = parse(boot_sector(false, 2880, 9, b"FLOPPY     "));
+		assert!(fat12.validate());
+		assert_eq!(fat12.fat_type(), FatType::Fat12);
+		assert_eq!(fat12.label(), "FLOPPY");
+
+		let fat16 = parse(boot_sector(false, 65536, 256, b"NO NAME    "));
+		assert!(fat16.validate());
+		assert_eq!(fat16.fat_type(), FatType::Fat16);
+		assert_eq!(fat16.label(), "");
+
+		let fat32 = parse(boot_sector(true, 1 << 20, 8192, b"EFI        "));
+		assert!(fat32.validate());
+		assert_eq!(fat32.fat_type(), FatType::Fat32);
+		assert_eq!(fat32.name(), "vfat");
+		assert_eq!(fat32.label(), "EFI");
+		assert_eq!(fat32.uuid()[..4], [0x12, 0x34, 0xAB, 0xCD]);
+	}
+
+	#[test]
+	fn test_not_fat() {
+		assert!(!parse(vec![0; 512]).validate());
+
+		// exFAT zeroes the BPB, keeping only the jump, OEM name, and signature.
+		let mut exfat = vec![0; 512];
+		exfat[0..3].copy_from_slice(&[0xEB, 0x76, 0x90]);
+		exfat[3..11].copy_from_slice(b"EXFAT   ");
+		exfat[510..512].copy_from_slice(&[0x55, 0xAA]);
+		assert!(!parse(exfat).validate());
+	}
+}
@@ -0,0 +1,642 @@
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use bytestruct::ReadFrom;
+use bytestruct_derive::ByteStruct;
+use thiserror::Error;
+
+use crate::{fat::FatType, types::Superblock, FatSuperBlock};
+
+/// The size of an entry in a directory.
+const DIRECTORY_ENTRY_SIZE: usize = 32;
+
+/// The first byte of the name of a directory entry that marks the end of the directory.
+const END_OF_DIRECTORY: u8 = 0x00;
+
+/// The first byte of the name of a directory entry that has been deleted.
+const DELETED_ENTRY: u8 = 0xE5;
+
+/// The first byte of the name of a directory entry whose name really starts with 0xE5, which is escaped so that it
+/// isn't mistaken for a deleted entry.
+const ESCAPED_E5: u8 = 0x05;
+
+/// The attributes of a long file name entry, which holds part of the long name of the entry after it.
+const LONG_NAME_ATTRIBUTES: u8 = 0x0F;
+
+/// The bit of the sequence number of a long file name entry that marks it as the last (and first stored) part.
+const LAST_LONG_NAME_ENTRY: u8 = 0x40;
+
+/// The offsets of the UCS-2 characters in a long file name entry.
+const LONG_NAME_CHARACTERS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// The bits of the reserved byte of a directory entry that Windows uses to mark the base name and extension of a short
+/// name as lowercase.
+const LOWERCASE_BASE: u8 = 0x08;
+const LOWERCASE_EXTENSION: u8 = 0x10;
+
+/// Entries in the allocation table at or above these values mark the end of a cluster chain.
+const FAT16_END_OF_CHAIN: u32 = 0xFFF8;
+const FAT32_END_OF_CHAIN: u32 = 0x0FFFFFF8;
+
+/// FAT32 only uses the low 28 bits of each entry in the allocation table.
+const FAT32_CLUSTER_MASK: u32 = 0x0FFFFFFF;
+
+/// The number of the first cluster in the data region. The first two entries in the allocation table are reserved.
+const FIRST_CLUSTER: u32 = 2;
+
+/// The attributes of a directory entry.
+pub mod attributes {
+	pub const READ_ONLY: u8 = 0x01;
+	pub const HIDDEN: u8 = 0x02;
+	pub const SYSTEM: u8 = 0x04;
+	pub const VOLUME_ID: u8 = 0x08;
+	pub const DIRECTORY: u8 = 0x10;
+	pub const ARCHIVE: u8 = 0x20;
+}
+
+#[derive(Debug, Error)]
+pub enum FatError {
+	#[error("failed to read filesystem: {0}")]
+	IOError(#[from] io::Error),
+
+	#[error("not a FAT filesystem")]
+	NotFat,
+
+	#[error("{0:?} filesystems can't be read")]
+	Unsupported(FatType),
+
+	#[error("{0}: no such file or directory")]
+	NotFound(String),
+
+	#[error("{0}: not a directory")]
+	NotADirectory(String),
+
+	#[error("{0}: is a directory")]
+	IsADirectory(String),
+
+	#[error("corrupt filesystem: {0}")]
+	Corrupt(String),
+}
+
+/// A directory entry, as it's stored on disk.
+#[derive(ByteStruct)]
+#[little_endian]
+struct RawDirectoryEntry {
+	name: [u8; 11],
+	attributes: u8,
+	/// Used by Windows to mark short names as lowercase.
+	case: u8,
+	_create_time_tenths: u8,
+	_create_time: u16,
+	_create_date: u16,
+	_access_date: u16,
+	first_cluster_high: u16,
+	_write_time: u16,
+	_write_date: u16,
+	first_cluster_low: u16,
+	size: u32,
+}
+
+/// A file or directory in a FAT filesystem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FatDirEntry {
+	/// The long name of the entry, or its short (8.3) name if it doesn't have one.
+	pub name: String,
+
+	/// The short (8.3) name of the entry, e.g. `BOOTX64.EFI`.
+	pub short_name: String,
+
+	/// The attributes of the entry (see `attributes`).
+	pub attributes: u8,
+
+	/// The size of the file in bytes, which is 0 for directories.
+	pub size: u32,
+
+	/// The first cluster of the contents of the entry, which is 0 for empty files.
+	first_cluster: u32,
+}
+
+impl FatDirEntry {
+	/// Whether the entry is a directory.
+	pub fn is_dir(&self) -> bool {
+		self.attributes & attributes::DIRECTORY != 0
+	}
+}
+
+/// Where the entries of a directory are.
+#[derive(Debug, Clone, Copy)]
+enum DirectoryLocation {
+	/// The fixed size root directory of a FAT12/16 filesystem, which comes before the data region.
+	FixedRoot,
+
+	/// A cluster chain in the data region, starting at the given cluster.
+	Chain(u32),
+}
+
+/// A read-only view of the files in a FAT16 or FAT32 filesystem, e.g. to check what's on an EFI system partition.
+/// Paths are `/` separated, and looked up ignoring case, as FAT does.
+pub struct FatFilesystem<R: Read + Seek> {
+	reader: R,
+	fat_type: FatType,
+
+	/// The number of bytes in each cluster.
+	cluster_size: u64,
+
+	/// The number of clusters in the data region.
+	cluster_count: u32,
+
+	/// The byte offset of the first allocation table.
+	fat_offset: u64,
+
+	/// The byte offset and size of the fixed root directory, on FAT16.
+	root_offset: u64,
+	root_size: u64,
+
+	/// The byte offset of the data region, where cluster 2 starts.
+	data_offset: u64,
+
+	/// The first cluster of the root directory, on FAT32.
+	root_cluster: u32,
+}
+
+impl<R: Read + Seek> FatFilesystem<R> {
+	/// Opens the FAT filesystem at the start of the reader.
+	pub fn open(mut reader: R) -> Result<Self, FatError> {
+		reader.seek(SeekFrom::Start(FatSuperBlock::offset()))?;
+		let mut buffer = vec![0; FatSuperBlock::size()];
+		reader.read_exact(&mut buffer)?;
+		let superblock = FatSuperBlock::read_from(&mut Cursor::new(buffer))?;
+		if !superblock.validate() {
+			return Err(FatError::NotFat);
+		}
+
+		let fat_type = superblock.fat_type();
+		if fat_type == FatType::Fat12 {
+			return Err(FatError::Unsupported(fat_type));
+		}
+
+		let bytes_per_sector = superblock.bytes_per_sector as u64;
+		let fat_offset = superblock.reserved_sectors as u64 * bytes_per_sector;
+		let root_offset =
+			fat_offset + superblock.fat_count as u64 * superblock.sectors_per_fat() as u64 * bytes_per_sector;
+		let root_size =
+			(superblock.root_entry_count as u64 * DIRECTORY_ENTRY_SIZE as u64).next_multiple_of(bytes_per_sector);
+
+		Ok(Self {
+			reader,
+			fat_type,
+			cluster_size: superblock.sectors_per_cluster as u64 * bytes_per_sector,
+			cluster_count: superblock.cluster_count(),
+			fat_offset,
+			root_offset,
+			root_size,
+			data_offset: root_offset + root_size,
+			root_cluster: superblock.root_cluster(),
+		})
+	}
+
+	/// Returns the type of the filesystem.
+	pub fn fat_type(&self) -> FatType {
+		self.fat_type
+	}
+
+	/// Returns the entries of the directory at the given path, other than `.` and `..`.
+	pub fn read_dir(&mut self, path: &str) -> Result<Vec<FatDirEntry>, FatError> {
+		let location = match self.entry(path)? {
+			None => self.root_location(),
+			Some(entry) if entry.is_dir() => self.directory_location(&entry),
+			Some(_) => return Err(FatError::NotADirectory(path.to_owned())),
+		};
+
+		self.read_directory(location)
+	}
+
+	/// Returns the entry of the file or directory at the given path.
+	pub fn metadata(&mut self, path: &str) -> Result<FatDirEntry, FatError> {
+		match self.entry(path)? {
+			Some(entry) => Ok(entry),
+			// The root directory doesn't have an entry of its own.
+			None => Ok(FatDirEntry {
+				name: String::from("/"),
+				short_name: String::from("/"),
+				attributes: attributes::DIRECTORY,
+				size: 0,
+				first_cluster: 0,
+			}),
+		}
+	}
+
+	/// Returns the contents of the file at the given path.
+	pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, FatError> {
+		let entry = match self.entry(path)? {
+			Some(entry) if !entry.is_dir() => entry,
+			_ => return Err(FatError::IsADirectory(path.to_owned())),
+		};
+
+		if entry.first_cluster == 0 {
+			return Ok(Vec::new());
+		}
+
+		let mut contents = self.read_chain(entry.first_cluster, Some(entry.size as u64))?;
+		if contents.len() < entry.size as usize {
+			return Err(FatError::Corrupt(format!(
+				"{} is {} bytes, but only has clusters for {}",
+				path,
+				entry.size,
+				contents.len()
+			)));
+		}
+
+		contents.truncate(entry.size as usize);
+		Ok(contents)
+	}
+
+	/// Looks up the entry at the given path, returning None for the root directory.
+	fn entry(&mut self, path: &str) -> Result<Option<FatDirEntry>, FatError> {
+		let mut location = self.root_location();
+		let mut entry = None;
+		for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+			if let Some(parent) = &entry {
+				if !FatDirEntry::is_dir(parent) {
+					return Err(FatError::NotADirectory(path.to_owned()));
+				}
+
+				location = self.directory_location(parent);
+			}
+
+			let found = self
+				.read_directory(location)?
+				.into_iter()
+				.find(|e| e.name.eq_ignore_ascii_case(component) || e.short_name.eq_ignore_ascii_case(component));
+			match found {
+				Some(found) => entry = Some(found),
+				None => return Err(FatError::NotFound(path.to_owned())),
+			}
+		}
+
+		Ok(entry)
+	}
+
+	fn root_location(&self) -> DirectoryLocation {
+		match self.fat_type {
+			FatType::Fat32 => DirectoryLocation::Chain(self.root_cluster),
+			_ => DirectoryLocation::FixedRoot,
+		}
+	}
+
+	fn directory_location(&self, entry: &FatDirEntry) -> DirectoryLocation {
+		// `..` entries that point at the root directory have a first cluster of 0.
+		match entry.first_cluster {
+			0 => self.root_location(),
+			cluster => DirectoryLocation::Chain(cluster),
+		}
+	}
+
+	/// Reads and parses the entries of a directory.
+	fn read_directory(&mut self, location: DirectoryLocation) -> Result<Vec<FatDirEntry>, FatError> {
+		let data = match location {
+			DirectoryLocation::FixedRoot => {
+				self.reader.seek(SeekFrom::Start(self.root_offset))?;
+				let mut data = vec![0; self.root_size as usize];
+				self.reader.read_exact(&mut data)?;
+				data
+			}
+			DirectoryLocation::Chain(cluster) => self.read_chain(cluster, None)?,
+		};
+
+		parse_directory(&data)
+	}
+
+	/// Reads the clusters of the chain that starts at the given cluster, stopping once `limit` bytes have been read.
+	fn read_chain(&mut self, first_cluster: u32, limit: Option<u64>) -> Result<Vec<u8>, FatError> {
+		let mut contents = Vec::new();
+		let mut cluster = Some(first_cluster);
+		while let Some(current) = cluster {
+			if limit.is_some_and(|limit| contents.len() as u64 >= limit) {
+				break;
+			}
+
+			// Every cluster can only be in a chain once, so a longer chain must loop.
+			if contents.len() as u64 >= self.cluster_count as u64 * self.cluster_size {
+				return Err(FatError::Corrupt(format!("cluster chain from {} loops", first_cluster)));
+			}
+
+			self.check_cluster(current)?;
+			let offset = self.data_offset + (current - FIRST_CLUSTER) as u64 * self.cluster_size;
+			self.reader.seek(SeekFrom::Start(offset))?;
+
+			let start = contents.len();
+			contents.resize(start + self.cluster_size as usize, 0);
+			self.reader.read_exact(&mut contents[start..])?;
+
+			cluster = self.next_cluster(current)?;
+		}
+
+		Ok(contents)
+	}
+
+	/// Returns the cluster after the given one in its chain, from the allocation table, or None if it's the last one.
+	fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, FatError> {
+		let (entry_size, end_of_chain) = match self.fat_type {
+			FatType::Fat32 => (4, FAT32_END_OF_CHAIN),
+			_ => (2, FAT16_END_OF_CHAIN),
+		};
+
+		self.reader
+			.seek(SeekFrom::Start(self.fat_offset + cluster as u64 * entry_size))?;
+		let mut entry = [0; 4];
+		self.reader.read_exact(&mut entry[..entry_size as usize])?;
+		let next = u32::from_le_bytes(entry) & FAT32_CLUSTER_MASK;
+
+		match next {
+			next if next >= end_of_chain => Ok(None),
+			next => {
+				self.check_cluster(next)?;
+				Ok(Some(next))
+			}
+		}
+	}
+
+	/// Checks that the cluster is in the data region.
+	fn check_cluster(&self, cluster: u32) -> Result<(), FatError> {
+		if cluster < FIRST_CLUSTER || cluster - FIRST_CLUSTER >= self.cluster_count {
+			return Err(FatError::Corrupt(format!("cluster {} is out of range", cluster)));
+		}
+
+		Ok(())
+	}
+}
+
+/// Parses the entries of a directory, joining long file names onto the entries they belong to. Volume labels, `.`,
+/// `..`, and deleted entries are left out.
+fn parse_directory(data: &[u8]) -> Result<Vec<FatDirEntry>, FatError> {
+	let mut entries = Vec::new();
+
+	// The parts of the long name of the next entry, from the last part to the first, along with the checksum of the
+	// short name that they belong to.
+	let mut long_name: Vec<[u16; 13]> = Vec::new();
+	let mut long_name_checksum = None;
+
+	for raw in data.chunks_exact(DIRECTORY_ENTRY_SIZE) {
+		match raw[0] {
+			END_OF_DIRECTORY => break,
+			DELETED_ENTRY => {
+				long_name.clear();
+				continue;
+			}
+			_ => {}
+		}
+
+		if raw[11] == LONG_NAME_ATTRIBUTES {
+			if raw[0] & LAST_LONG_NAME_ENTRY != 0 {
+				long_name.clear();
+			}
+
+			let mut characters = [0; 13];
+			for (character, offset) in characters.iter_mut().zip(LONG_NAME_CHARACTERS) {
+				*character = u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+			}
+
+			long_name.push(characters);
+			long_name_checksum = Some(raw[13]);
+			continue;
+		}
+
+		let entry = RawDirectoryEntry::read_from(&mut Cursor::new(raw))?;
+		let long = match long_name_checksum {
+			// Long names whose checksum doesn't match were left behind by something that doesn't know about them.
+			Some(checksum) if checksum == short_name_checksum(&entry.name) => decode_long_name(&long_name),
+			_ => None,
+		};
+		long_name.clear();
+		long_name_checksum = None;
+
+		if entry.attributes & attributes::VOLUME_ID != 0 || entry.name[0] == b'.' {
+			continue;
+		}
+
+		let short_name = decode_short_name(&entry.name, entry.case);
+		entries.push(FatDirEntry {
+			name: long.unwrap_or_else(|| short_name.clone()),
+			short_name,
+			attributes: entry.attributes,
+			size: entry.size,
+			first_cluster: (entry.first_cluster_high as u32) << 16 | entry.first_cluster_low as u32,
+		});
+	}
+
+	Ok(entries)
+}
+
+/// Decodes a long name from its parts, which are stored last part first. Names end at a NUL, and are padded with
+/// 0xFFFF after it.
+fn decode_long_name(parts: &[[u16; 13]]) -> Option<String> {
+	if parts.is_empty() {
+		return None;
+	}
+
+	let characters = parts
+		.iter()
+		.rev()
+		.flatten()
+		.copied()
+		.take_while(|&c| c != 0)
+		.collect::<Vec<_>>();
+	Some(String::from_utf16_lossy(&characters))
+}
+
+/// Decodes an 8.3 name, e.g. `BOOTX64 EFI` to `BOOTX64.EFI`.
+fn decode_short_name(name: &[u8; 11], case: u8) -> String {
+	let mut name = *name;
+	if name[0] == ESCAPED_E5 {
+		name[0] = DELETED_ENTRY;
+	}
+
+	let decode = |part: &[u8], lowercase: bool| {
+		let part = String::from_utf8_lossy(part).trim_end().to_owned();
+		if lowercase {
+			part.to_ascii_lowercase()
+		} else {
+			part
+		}
+	};
+
+	let base = decode(&name[..8], case & LOWERCASE_BASE != 0);
+	let extension = decode(&name[8..], case & LOWERCASE_EXTENSION != 0);
+	if extension.is_empty() {
+		base
+	} else {
+		format!("{}.{}", base, extension)
+	}
+}
+
+/// The checksum of a short name, which long file name entries hold to tie them to it.
+fn short_name_checksum(name: &[u8; 11]) -> u8 {
+	name.iter()
+		.fold(0u8, |sum, &b| (sum >> 1 | (sum & 1) << 7).wrapping_add(b))
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::{Cursor, Seek, SeekFrom, Write};
+
+	use super::{short_name_checksum, FatError, FatFilesystem, FIRST_CLUSTER};
+	use crate::fat::{test::boot_sector, FatType};
+
+	/// The size of the sectors (and clusters) of the test filesystems.
+	const SECTOR_SIZE: u64 = 512;
+
+	/// Builds a directory entry with the given 8.3 name.
+	fn entry(name: &[u8; 11], attributes: u8, case: u8, cluster: u32, size: u32) -> Vec<u8> {
+		let mut entry = vec![0; 32];
+		entry[..11].copy_from_slice(name);
+		entry[11] = attributes;
+		entry[12] = case;
+		entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+		entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+		entry[28..32].copy_from_slice(&size.to_le_bytes());
+		entry
+	}
+
+	/// Builds the long file name entries for the given name, tied to the given short name, in the order they're stored.
+	fn long_name_entries(name: &str, short_name: &[u8; 11]) -> Vec<u8> {
+		let mut characters = name.encode_utf16().collect::<Vec<_>>();
+		characters.push(0);
+		characters.resize(characters.len().next_multiple_of(13), 0xFFFF);
+
+		let parts = characters.chunks(13).collect::<Vec<_>>();
+		let mut entries = Vec::new();
+		for (i, part) in parts.iter().enumerate().rev() {
+			let mut entry = vec![0; 32];
+			entry[0] = (i + 1) as u8 | if i == parts.len() - 1 { 0x40 } else { 0 };
+			entry[11] = 0x0F;
+			entry[13] = short_name_checksum(short_name);
+			for (character, offset) in part.iter().zip(super::LONG_NAME_CHARACTERS) {
+				entry[offset..offset + 2].copy_from_slice(&character.to_le_bytes());
+			}
+
+			entries.extend(entry);
+		}
+
+		entries
+	}
+
+	/// Builds a FAT filesystem holding:
+	/// /EFI/BOOT/BOOTX64.EFI
+	/// /startup.nsh (a lowercase short name)
+	/// /Long File Name.conf (600 bytes, over two clusters)
+	/// /empty
+	fn filesystem(fat32: bool) -> Cursor<Vec<u8>> {
+		let (total_sectors, sectors_per_fat) = if fat32 { (70000, 560) } else { (4300, 17) };
+		let mut sector = boot_sector(fat32, total_sectors, sectors_per_fat, b"EFI        ");
+		if fat32 {
+			sector[44..48].copy_from_slice(&FIRST_CLUSTER.to_le_bytes());
+		}
+
+		let mut image = Cursor::new(vec![0; (total_sectors as u64 * SECTOR_SIZE) as usize]);
+		image.write_all(&sector).unwrap();
+
+		let reserved_sectors = if fat32 { 32 } else { 1 };
+		let fat_offset = reserved_sectors * SECTOR_SIZE;
+		let root_offset = fat_offset + 2 * sectors_per_fat as u64 * SECTOR_SIZE;
+		let data_offset = root_offset + if fat32 { 0 } else { 32 * SECTOR_SIZE };
+		let cluster_offset = |cluster: u32| data_offset + (cluster - FIRST_CLUSTER) as u64 * SECTOR_SIZE;
+
+		// The root directory is in cluster 2 on FAT32, so the rest of the clusters are the same on both.
+		let chains: &[(u32, u32)] = &[
+			(2, u32::MAX),
+			(3, u32::MAX),
+			(4, 5),
+			(5, u32::MAX),
+			(6, u32::MAX),
+			(7, u32::MAX),
+		];
+		for (cluster, next) in chains {
+			let (size, next) = if fat32 {
+				(4, *next & 0x0FFFFFFF)
+			} else {
+				(2, *next & 0xFFFF)
+			};
+			image
+				.seek(SeekFrom::Start(fat_offset + *cluster as u64 * size))
+				.unwrap();
+			image.write_all(&next.to_le_bytes()[..size as usize]).unwrap();
+		}
+
+		let mut root = entry(b"EFI        ", 0x08, 0, 0, 0);
+		root.extend(entry(b"EFI        ", 0x10, 0, 3, 0));
+		root.extend(entry(b"STARTUP NSH", 0x20, 0x18, 6, 5));
+		root.extend(long_name_entries("Long File Name.conf", b"LONGFI~1CON"));
+		root.extend(entry(b"LONGFI~1CON", 0x20, 0, 4, 600));
+		root.extend(entry(b"EMPTY      ", 0x20, 0, 0, 0));
+		// A deleted entry, which is skipped.
+		root.extend(entry(b"\xE5OLD    TXT", 0x20, 0, 0, 0));
+		let root_offset = if fat32 { cluster_offset(2) } else { root_offset };
+		image.seek(SeekFrom::Start(root_offset)).unwrap();
+		image.write_all(&root).unwrap();
+
+		let mut efi = entry(b".          ", 0x10, 0, 3, 0);
+		efi.extend(entry(b"..         ", 0x10, 0, 0, 0));
+		efi.extend(entry(b"BOOT       ", 0x10, 0, 7, 0));
+		image.seek(SeekFrom::Start(cluster_offset(3))).unwrap();
+		image.write_all(&efi).unwrap();
+
+		let boot = entry(b"BOOTX64 EFI", 0x20, 0, 6, 2);
+		image.seek(SeekFrom::Start(cluster_offset(7))).unwrap();
+		image.write_all(&boot).unwrap();
+
+		image.seek(SeekFrom::Start(cluster_offset(4))).unwrap();
+		image.write_all(&[b'a'; 512]).unwrap();
+		image.write_all(&[b'b'; 88]).unwrap();
+
+		image.seek(SeekFrom::Start(cluster_offset(6))).unwrap();
+		image.write_all(b"MZ\0\0\0").unwrap();
+
+		image
+	}
+
+	#[test]
+	fn test_read_filesystem() {
+		for (fat32, fat_type) in [(false, FatType::Fat16), (true, FatType::Fat32)] {
+			let mut fs = FatFilesystem::open(filesystem(fat32)).unwrap();
+			assert_eq!(fs.fat_type(), fat_type);
+
+			let names = fs
+				.read_dir("/")
+				.unwrap()
+				.into_iter()
+				.map(|entry| entry.name)
+				.collect::<Vec<_>>();
+			assert_eq!(names, ["EFI", "startup.nsh", "Long File Name.conf", "EMPTY"]);
+
+			let boot = fs.read_dir("/efi/boot").unwrap();
+			assert_eq!(boot.len(), 1);
+			assert_eq!(boot[0].name, "BOOTX64.EFI");
+			assert!(!boot[0].is_dir());
+
+			assert_eq!(fs.read_file("EFI/BOOT/BOOTX64.EFI").unwrap(), b"MZ");
+			assert_eq!(fs.read_file("/startup.nsh").unwrap(), b"MZ\0\0\0");
+
+			let long = fs.read_file("/long file name.conf").unwrap();
+			assert_eq!(long.len(), 600);
+			assert!(long[..512].iter().all(|&b| b == b'a') && long[512..].iter().all(|&b| b == b'b'));
+			assert_eq!(fs.read_file("/LONGFI~1.CON").unwrap(), long);
+
+			assert_eq!(fs.read_file("/empty").unwrap(), b"");
+			assert!(fs.metadata("/efi").unwrap().is_dir());
+			assert!(fs.metadata("/").unwrap().is_dir());
+
+			assert!(matches!(fs.read_file("/missing"), Err(FatError::NotFound(_))));
+			assert!(matches!(fs.read_file("/efi"), Err(FatError::IsADirectory(_))));
+			assert!(matches!(fs.read_dir("/empty"), Err(FatError::NotADirectory(_))));
+			assert!(matches!(fs.read_dir("/empty/x"), Err(FatError::NotADirectory(_))));
+		}
+	}
+
+	#[test]
+	fn test_not_fat() {
+		assert!(matches!(
+			FatFilesystem::open(Cursor::new(vec![0; 4096])),
+			Err(FatError::NotFat)
+		));
+	}
+}
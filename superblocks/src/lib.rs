@@ -1,6 +1,12 @@
 mod btrfs;
 mod ext;
+mod fat;
+mod fatfs;
+mod partitions;
+mod squashfs;
+mod swap;
 mod types;
+mod xfs;
 
 use std::{
 	fs::File,
@@ -11,7 +17,13 @@ use std::{
 pub use btrfs::*;
 use bytestruct::{ReadFrom, UUID};
 pub use ext::*;
-pub use types::Superblock;
+pub use fat::*;
+pub use fatfs::*;
+pub use partitions::*;
+pub use squashfs::*;
+pub use swap::*;
+pub use types::{FilesystemHealth, FilesystemState, Superblock};
+pub use xfs::*;
 
 /// A device that may contain a filesystem.
 pub struct Device {
@@ -29,13 +41,23 @@ impl Device {
 
 	/// Trys to probe the device to work out what type of filesystem it contains.
 	pub fn probe(&self) -> io::Result<Option<ProbeResult>> {
-		if let Some(result) = self.probe_fs::<ExtSuperBlock>()? {
-			Ok(Some(result))
-		} else if let Some(result) = self.probe_fs::<BtrfsSuperBlock>()? {
-			Ok(Some(result))
-		} else {
-			Ok(None)
+		// FAT is probed last, as it has no magic number and is only recognised by its BPB looking plausible.
+		let probes = [
+			Self::probe_fs::<ExtSuperBlock>,
+			Self::probe_fs::<BtrfsSuperBlock>,
+			Self::probe_fs::<XfsSuperBlock>,
+			Self::probe_fs::<SquashfsSuperBlock>,
+			Self::probe_fs::<SwapSuperBlock>,
+			Self::probe_fs::<FatSuperBlock>,
+		];
+
+		for probe in probes {
+			if let Some(result) = probe(self)? {
+				return Ok(Some(result));
+			}
 		}
+
+		Ok(None)
 	}
 
 	/// Trys to probe the device for a filesystem of the given type.
@@ -44,7 +66,12 @@ impl Device {
 		file.seek(SeekFrom::Start(T::offset()))?;
 
 		let mut buffer = vec![0; T::size()];
-		file.read_exact(&mut buffer)?;
+		match file.read_exact(&mut buffer) {
+			Ok(()) => {}
+			// The device is too small to hold a superblock at this offset, so can't contain this filesystem.
+			Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+			Err(e) => return Err(e),
+		}
 
 		let superblock = T::read_from(&mut Cursor::new(buffer))?;
 
@@ -54,6 +81,7 @@ impl Device {
 				filesystem_type: superblock.name(),
 				label: superblock.label(),
 				uuid: superblock.uuid(),
+				health: superblock.health(),
 			}))
 		} else {
 			Ok(None)
@@ -72,4 +100,27 @@ pub struct ProbeResult {
 	pub label: String,
 	/// The UUID of the filesystem.
 	pub uuid: UUID,
+	/// Whether the filesystem is clean, and when it was last mounted and checked, for filesystems that record it.
+	pub health: Option<FilesystemHealth>,
+}
+
+impl ProbeResult {
+	/// Formats the UUID the way that tools (and /dev/disk/by-uuid) display it, or returns an empty string if the
+	/// filesystem doesn't have one.
+	pub fn uuid_string(&self) -> String {
+		let u = &self.uuid;
+		if u.iter().all(|&b| b == 0) {
+			return String::new();
+		}
+
+		// FAT only has a 32 bit serial number, which is displayed as two groups of four hex digits.
+		if self.filesystem_type == "vfat" {
+			return format!("{:02X}{:02X}-{:02X}{:02X}", u[0], u[1], u[2], u[3]);
+		}
+
+		format!(
+			"{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+			u[0], u[1], u[2], u[3], u[4], u[5], u[6], u[7], u[8], u[9], u[10], u[11], u[12], u[13], u[14], u[15]
+		)
+	}
 }
@@ -0,0 +1,241 @@
+use std::io::{Cursor, Read, Seek};
+
+use bytestruct::{ReadFrom, UUID};
+use bytestruct_derive::ByteStruct;
+
+use super::{read_at, Partition, PartitionError, PartitionTable, PartitionTableType, PartitionType};
+
+/// "EFI PART"
+const GPT_SIGNATURE: [u8; 8] = *b"EFI PART";
+
+/// The size of the fields in a GPT header. The header can be larger, but the rest is reserved.
+const GPT_HEADER_MIN_SIZE: u32 = 92;
+
+/// The offset of the CRC of the header in the header, which is zeroed when the CRC is calculated.
+const GPT_HEADER_CRC_OFFSET: usize = 16;
+
+/// The size of the fields in a partition entry. Entries can be larger, but the rest is reserved.
+const GPT_ENTRY_MIN_SIZE: u32 = 128;
+
+/// The maximum size of the partition entry array that we'll read, to avoid allocating whatever a corrupt header says.
+const GPT_MAX_ENTRIES_SIZE: u64 = 1 << 20;
+
+/// The attribute bit of partitions that legacy BIOSes can boot from.
+const GPT_ATTRIBUTE_LEGACY_BIOS_BOOTABLE: u64 = 1 << 2;
+
+/// Well known partition type GUIDs, as formatted by `format_guid`, and their names.
+const GPT_TYPES: &[(&str, &str)] = &[
+	("C12A7328-F81F-11D2-BA4B-00A0C93EC93B", "EFI System"),
+	("21686148-6449-6E6F-744E-656564454649", "BIOS boot"),
+	("0FC63DAF-8483-4772-8E79-3D69D8477DE4", "Linux filesystem"),
+	("4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709", "Linux root (x86-64)"),
+	("B921B045-1DF0-41C3-AF44-4C6F280D3FAE", "Linux root (ARM64)"),
+	("933AC7E1-2EB4-4F13-B844-0E14E2AEF915", "Linux home"),
+	("0657FD6D-A4AB-43C4-84E5-0933C84B4F4F", "Linux swap"),
+	("E6D6D379-F507-44C2-A23C-238F2A3DF928", "Linux LVM"),
+	("A19D880F-05FC-4D3B-A006-743F0F84911E", "Linux RAID"),
+	("CA7D7CCB-63ED-4C53-861C-1742536059CC", "Linux LUKS"),
+	("EBD0A0A2-B9E5-4433-87C0-68B6B72699C7", "Microsoft basic data"),
+	("E3C9E316-0B5C-4DB8-817D-F92DF00215AE", "Microsoft reserved"),
+];
+
+/// The header of a GUID Partition Table, stored in the second sector of the disk, and again in the last.
+#[derive(ByteStruct)]
+#[little_endian]
+pub struct GptHeader {
+	pub signature: [u8; 8],
+	pub revision: u32,
+	pub header_size: u32,
+	pub header_crc32: u32,
+	_reserved: u32,
+	pub current_lba: u64,
+	pub backup_lba: u64,
+	pub first_usable_lba: u64,
+	pub last_usable_lba: u64,
+	pub disk_guid: UUID,
+	pub entries_lba: u64,
+	pub entry_count: u32,
+	pub entry_size: u32,
+	pub entries_crc32: u32,
+}
+
+/// An entry in the partition entry array of a GPT.
+#[derive(ByteStruct)]
+#[little_endian]
+pub struct GptEntry {
+	pub type_guid: UUID,
+	pub unique_guid: UUID,
+	pub first_lba: u64,
+	/// The last sector of the partition, inclusive.
+	pub last_lba: u64,
+	pub attributes: u64,
+	/// The name of the partition, in UTF-16LE.
+	pub name: [u16; 36],
+}
+
+/// Reads the GPT of the disk, assuming the given sector size. Returns None if there's no GPT signature where the
+/// header should be. If the primary header or its entries are corrupt, the backup at the end of the disk is used.
+pub(super) fn read_gpt<R: Read + Seek>(
+	disk: &mut R,
+	sector_size: u64,
+	disk_size: u64,
+) -> Result<Option<PartitionTable>, PartitionError> {
+	let disk_sectors = disk_size / sector_size;
+	if disk_sectors < 2 {
+		return Ok(None);
+	}
+
+	let primary = match read_header(disk, 1, sector_size)? {
+		Some(header) => header,
+		None => return Ok(None),
+	};
+
+	let primary_error = match read_table(disk, primary, 1, sector_size, disk_sectors) {
+		Ok(table) => return Ok(Some(table)),
+		Err(e) => e,
+	};
+
+	let backup_lba = disk_sectors - 1;
+	match read_header(disk, backup_lba, sector_size)? {
+		Some(header) => match read_table(disk, header, backup_lba, sector_size, disk_sectors) {
+			Ok(table) => Ok(Some(table)),
+			// The primary is the more interesting failure, as it's what everything else reads first.
+			Err(_) => Err(primary_error),
+		},
+		None => Err(primary_error),
+	}
+}
+
+/// Reads the raw bytes of the GPT header in the given sector, if the sector has the GPT signature. The header can't be
+/// parsed yet, as its CRC covers a size that's in the header.
+fn read_header<R: Read + Seek>(disk: &mut R, lba: u64, sector_size: u64) -> Result<Option<Vec<u8>>, PartitionError> {
+	let sector = read_at(disk, lba * sector_size, sector_size as usize)?;
+	if sector[0..GPT_SIGNATURE.len()] != GPT_SIGNATURE {
+		return Ok(None);
+	}
+
+	Ok(Some(sector))
+}
+
+/// Validates the GPT header in the given sector, and reads the partition table it describes.
+fn read_table<R: Read + Seek>(
+	disk: &mut R,
+	mut sector: Vec<u8>,
+	lba: u64,
+	sector_size: u64,
+	disk_sectors: u64,
+) -> Result<PartitionTable, PartitionError> {
+	let header = GptHeader::read_from(&mut Cursor::new(&sector))?;
+	if header.header_size < GPT_HEADER_MIN_SIZE || header.header_size as u64 > sector_size {
+		return Err(PartitionError::Invalid(format!(
+			"GPT header size {} is invalid",
+			header.header_size
+		)));
+	}
+
+	// The CRC is calculated with its own field zeroed.
+	sector[GPT_HEADER_CRC_OFFSET..GPT_HEADER_CRC_OFFSET + 4].fill(0);
+	let header_crc = crc32fast::hash(&sector[..header.header_size as usize]);
+	if header_crc != header.header_crc32 {
+		return Err(PartitionError::ChecksumMismatch {
+			what: "GPT header",
+			expected: header.header_crc32,
+			found: header_crc,
+		});
+	}
+
+	if header.current_lba != lba {
+		return Err(PartitionError::Invalid(format!(
+			"GPT header at sector {} says it's at sector {}",
+			lba, header.current_lba
+		)));
+	}
+
+	if header.entry_size < GPT_ENTRY_MIN_SIZE || header.entry_size % 8 != 0 {
+		return Err(PartitionError::Invalid(format!(
+			"GPT entry size {} is invalid",
+			header.entry_size
+		)));
+	}
+
+	let entries_size = header.entry_count as u64 * header.entry_size as u64;
+	let entries_sectors = entries_size.div_ceil(sector_size);
+	if entries_size > GPT_MAX_ENTRIES_SIZE || header.entries_lba.saturating_add(entries_sectors) > disk_sectors {
+		return Err(PartitionError::Invalid(format!(
+			"GPT partition entries at sector {} don't fit on the disk",
+			header.entries_lba
+		)));
+	}
+
+	let entries = read_at(disk, header.entries_lba * sector_size, entries_size as usize)?;
+	let entries_crc = crc32fast::hash(&entries);
+	if entries_crc != header.entries_crc32 {
+		return Err(PartitionError::ChecksumMismatch {
+			what: "GPT partition entries",
+			expected: header.entries_crc32,
+			found: entries_crc,
+		});
+	}
+
+	let mut partitions = Vec::new();
+	for (i, entry) in entries.chunks_exact(header.entry_size as usize).enumerate() {
+		let entry = GptEntry::read_from(&mut Cursor::new(entry))?;
+		if entry.type_guid == UUID::default() {
+			continue;
+		}
+
+		if entry.last_lba < entry.first_lba {
+			return Err(PartitionError::Invalid(format!(
+				"GPT partition {} ends before it starts",
+				i + 1
+			)));
+		}
+
+		let name = String::from_utf16_lossy(&entry.name);
+		partitions.push(Partition {
+			number: i as u32 + 1,
+			first_sector: entry.first_lba,
+			sector_count: entry.last_lba - entry.first_lba + 1,
+			ty: PartitionType::Gpt(entry.type_guid),
+			uuid: entry.unique_guid,
+			name: name.trim_end_matches('\0').to_string(),
+			bootable: entry.attributes & GPT_ATTRIBUTE_LEGACY_BIOS_BOOTABLE != 0,
+		});
+	}
+
+	Ok(PartitionTable {
+		ty: PartitionTableType::Gpt,
+		disk_id: header.disk_guid,
+		sector_size,
+		partitions,
+	})
+}
+
+/// Formats a GUID as it's stored in a GPT, where the first three groups are little endian.
+pub fn format_guid(guid: &UUID) -> String {
+	format!(
+		"{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+		guid[3],
+		guid[2],
+		guid[1],
+		guid[0],
+		guid[5],
+		guid[4],
+		guid[7],
+		guid[6],
+		guid[8],
+		guid[9],
+		guid[10],
+		guid[11],
+		guid[12],
+		guid[13],
+		guid[14],
+		guid[15]
+	)
+}
+
+/// Returns the name of a well known GPT partition type.
+pub fn gpt_type_name(guid: &UUID) -> Option<&'static str> {
+	let guid = format_guid(guid);
+	GPT_TYPES.iter().find(|(ty, _)| *ty == guid).map(|(_, name)| *name)
+}
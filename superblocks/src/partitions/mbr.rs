@@ -0,0 +1,190 @@
+use std::io::{Cursor, Read, Seek};
+
+use bytestruct::{ReadFrom, UUID};
+use bytestruct_derive::ByteStruct;
+
+use super::{read_at, Partition, PartitionError, PartitionTable, PartitionTableType, PartitionType};
+
+/// MBRs always describe the disk in 512 byte sectors.
+const MBR_SECTOR_SIZE: u64 = 512;
+
+/// The signature at the end of the MBR, and of every EBR.
+const MBR_SIGNATURE: u16 = 0xAA55;
+
+/// The partition type of the single partition in the protective MBR of a GPT disk.
+pub const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+/// Partition types that contain a chain of EBRs, describing logical partitions.
+const MBR_EXTENDED_TYPES: [u8; 3] = [0x05, 0x0F, 0x85];
+
+/// The status of a partition that legacy BIOSes can boot from.
+const MBR_STATUS_BOOTABLE: u8 = 0x80;
+
+/// The number of the first logical partition. Numbers 1 to 4 are reserved for the primary partitions.
+const FIRST_LOGICAL_PARTITION: u32 = 5;
+
+/// The maximum number of logical partitions that are read, so that a looping chain of EBRs can't hang us.
+const MAX_LOGICAL_PARTITIONS: u32 = 256;
+
+/// A Master Boot Record, or an Extended Boot Record, which share a layout.
+#[derive(ByteStruct)]
+#[little_endian]
+pub struct MasterBootRecord {
+	pub boot_code: [u8; 440],
+	pub disk_signature: u32,
+	_reserved: u16,
+	pub entries: [MbrEntry; 4],
+	pub signature: u16,
+}
+
+/// An entry in the partition table of an MBR. Its endianness comes from the record it's in.
+#[derive(ByteStruct, Clone, Copy)]
+pub struct MbrEntry {
+	pub status: u8,
+	pub chs_first: [u8; 3],
+	pub ty: u8,
+	pub chs_last: [u8; 3],
+	pub first_sector: u32,
+	pub sector_count: u32,
+}
+
+impl MbrEntry {
+	fn is_used(&self) -> bool {
+		self.ty != 0 && self.sector_count != 0
+	}
+
+	fn is_extended(&self) -> bool {
+		MBR_EXTENDED_TYPES.contains(&self.ty)
+	}
+}
+
+impl MasterBootRecord {
+	/// Whether this looks like a partition table. Boot sectors of unpartitioned filesystems (like FAT) have the
+	/// same signature, but have code where the partition entries would be, so their statuses are nonsense.
+	fn is_valid(&self, disk_sectors: u64) -> bool {
+		self.signature == MBR_SIGNATURE
+			&& self.entries.iter().all(|entry| {
+				matches!(entry.status, 0 | MBR_STATUS_BOOTABLE)
+					&& (!entry.is_used() || entry.first_sector as u64 + entry.sector_count as u64 <= disk_sectors)
+			})
+	}
+
+	fn read<R: Read + Seek>(disk: &mut R, sector: u64) -> Result<Self, PartitionError> {
+		let bytes = read_at(disk, sector * MBR_SECTOR_SIZE, MBR_SECTOR_SIZE as usize)?;
+		Ok(Self::read_from(&mut Cursor::new(bytes))?)
+	}
+}
+
+/// Reads the MBR partition table of the disk, returning None if there isn't one, or it's the protective MBR of a GPT
+/// disk (whose GPT couldn't be read).
+pub(super) fn read_mbr<R: Read + Seek>(disk: &mut R, disk_size: u64) -> Result<Option<PartitionTable>, PartitionError> {
+	let disk_sectors = disk_size / MBR_SECTOR_SIZE;
+	if disk_sectors == 0 {
+		return Ok(None);
+	}
+
+	let mbr = MasterBootRecord::read(disk, 0)?;
+	if !mbr.is_valid(disk_sectors) || mbr.entries.iter().any(|entry| entry.ty == MBR_TYPE_GPT_PROTECTIVE) {
+		return Ok(None);
+	}
+
+	let mut partitions = Vec::new();
+	let mut extended = None;
+	for (i, entry) in mbr.entries.iter().enumerate() {
+		if !entry.is_used() {
+			continue;
+		}
+
+		if entry.is_extended() && extended.is_none() {
+			extended = Some(entry.first_sector as u64);
+		}
+
+		partitions.push(partition(i as u32 + 1, 0, entry));
+	}
+
+	if let Some(extended_start) = extended {
+		read_logical_partitions(disk, extended_start, disk_sectors, &mut partitions)?;
+	}
+
+	let mut disk_id = UUID::default();
+	disk_id[..4].copy_from_slice(&mbr.disk_signature.to_be_bytes());
+
+	Ok(Some(PartitionTable {
+		ty: PartitionTableType::Mbr,
+		disk_id,
+		sector_size: MBR_SECTOR_SIZE,
+		partitions,
+	}))
+}
+
+/// Follows the chain of EBRs in the extended partition that starts at the given sector, adding the logical
+/// partitions that they describe. The first entry of each EBR is a logical partition, relative to the EBR, and the
+/// second points to the next EBR, relative to the start of the extended partition.
+fn read_logical_partitions<R: Read + Seek>(
+	disk: &mut R,
+	extended_start: u64,
+	disk_sectors: u64,
+	partitions: &mut Vec<Partition>,
+) -> Result<(), PartitionError> {
+	let mut ebr_sector = extended_start;
+	for number in FIRST_LOGICAL_PARTITION..FIRST_LOGICAL_PARTITION + MAX_LOGICAL_PARTITIONS {
+		if ebr_sector >= disk_sectors {
+			return Err(PartitionError::Invalid(format!(
+				"EBR at sector {} is past the end of the disk",
+				ebr_sector
+			)));
+		}
+
+		let ebr = MasterBootRecord::read(disk, ebr_sector)?;
+		if ebr.signature != MBR_SIGNATURE {
+			return Err(PartitionError::Invalid(format!(
+				"EBR at sector {} has an invalid signature",
+				ebr_sector
+			)));
+		}
+
+		if ebr.entries[0].is_used() {
+			partitions.push(partition(number, ebr_sector, &ebr.entries[0]));
+		}
+
+		let next = &ebr.entries[1];
+		if !next.is_used() {
+			return Ok(());
+		}
+
+		ebr_sector = extended_start + next.first_sector as u64;
+	}
+
+	Err(PartitionError::Invalid(String::from("too many logical partitions")))
+}
+
+/// Builds a partition from an MBR entry, whose first sector is relative to `base`.
+fn partition(number: u32, base: u64, entry: &MbrEntry) -> Partition {
+	Partition {
+		number,
+		first_sector: base + entry.first_sector as u64,
+		sector_count: entry.sector_count as u64,
+		ty: PartitionType::Mbr(entry.ty),
+		uuid: UUID::default(),
+		name: String::new(),
+		bootable: entry.status == MBR_STATUS_BOOTABLE,
+	}
+}
+
+/// Returns the name of a well known MBR partition type.
+pub fn mbr_type_name(ty: u8) -> Option<&'static str> {
+	match ty {
+		0x01 => Some("FAT12"),
+		0x05 | 0x0F | 0x85 => Some("Extended"),
+		0x06 | 0x0E => Some("FAT16"),
+		0x07 => Some("NTFS/exFAT"),
+		0x0B | 0x0C => Some("FAT32"),
+		0x82 => Some("Linux swap"),
+		0x83 => Some("Linux"),
+		0x8E => Some("Linux LVM"),
+		0xEE => Some("GPT protective"),
+		0xEF => Some("EFI System"),
+		0xFD => Some("Linux RAID"),
+		_ => None,
+	}
+}
@@ -0,0 +1,301 @@
+mod gpt;
+mod mbr;
+
+use std::{
+	fs::File,
+	io::{self, Read, Seek, SeekFrom},
+	path::{Path, PathBuf},
+};
+
+use bytestruct::UUID;
+pub use gpt::*;
+pub use mbr::*;
+use thiserror::Error;
+
+/// The sizes of logical sectors that disks are checked for. GPT headers are at the start of the second sector, so
+/// have to be looked for at each size.
+const SECTOR_SIZES: [u64; 2] = [512, 4096];
+
+/// A disk that may contain a partition table.
+pub struct Disk {
+	/// The absolute path to the disk.
+	path: PathBuf,
+}
+
+impl Disk {
+	/// Creates a new disk from the given path, e.g. /dev/sda.
+	pub fn new(path: &Path) -> Self {
+		Self {
+			path: path.to_path_buf(),
+		}
+	}
+
+	/// Reads the partition table of the disk, returning None if it doesn't have one.
+	pub fn partitions(&self) -> Result<Option<PartitionTable>, PartitionError> {
+		read_partition_table(&mut File::open(&self.path)?)
+	}
+}
+
+/// Reads the partition table from the given disk. GPT is preferred, as GPT disks also have a protective MBR.
+pub fn read_partition_table<R: Read + Seek>(disk: &mut R) -> Result<Option<PartitionTable>, PartitionError> {
+	let disk_size = disk.seek(SeekFrom::End(0))?;
+
+	for sector_size in SECTOR_SIZES {
+		if let Some(table) = read_gpt(disk, sector_size, disk_size)? {
+			return Ok(Some(table));
+		}
+	}
+
+	read_mbr(disk, disk_size)
+}
+
+/// Reads `len` bytes at the given offset of the disk.
+fn read_at<R: Read + Seek>(disk: &mut R, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+	disk.seek(SeekFrom::Start(offset))?;
+	let mut buffer = vec![0; len];
+	disk.read_exact(&mut buffer)?;
+	Ok(buffer)
+}
+
+/// The type of partition table on a disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionTableType {
+	Gpt,
+	Mbr,
+}
+
+/// The partition table of a disk.
+#[derive(Debug)]
+pub struct PartitionTable {
+	pub ty: PartitionTableType,
+
+	/// The GUID of the disk for GPT. For MBR, the 32 bit disk signature is in the first four bytes.
+	pub disk_id: UUID,
+
+	/// The size of the sectors that partitions are measured in, in bytes.
+	pub sector_size: u64,
+
+	pub partitions: Vec<Partition>,
+}
+
+/// A partition on a disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+	/// The number of the partition, as used in device names like /dev/sda1.
+	pub number: u32,
+
+	/// The first sector of the partition.
+	pub first_sector: u64,
+
+	/// The number of sectors in the partition.
+	pub sector_count: u64,
+
+	pub ty: PartitionType,
+
+	/// The GUID of the partition. MBR partitions don't have one, so this is all zeros.
+	pub uuid: UUID,
+
+	/// The name of the partition. MBR partitions don't have one, so this is empty.
+	pub name: String,
+
+	/// Whether the partition is marked as bootable by legacy BIOSes.
+	pub bootable: bool,
+}
+
+/// The type of a partition, which hints at what it contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+	/// A GPT partition type GUID.
+	Gpt(UUID),
+
+	/// An MBR partition type byte.
+	Mbr(u8),
+}
+
+impl PartitionType {
+	/// Returns a human readable name of the type, if it's a well known one.
+	pub fn name(&self) -> Option<&'static str> {
+		match self {
+			Self::Gpt(guid) => gpt_type_name(guid),
+			Self::Mbr(ty) => mbr_type_name(*ty),
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum PartitionError {
+	#[error("failed to read partition table: {0}")]
+	IOError(#[from] io::Error),
+
+	#[error("invalid partition table: {0}")]
+	Invalid(String),
+
+	#[error("{what} checksum mismatch: expected {expected:#010x}, found {found:#010x}")]
+	ChecksumMismatch {
+		what: &'static str,
+		expected: u32,
+		found: u32,
+	},
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::Cursor;
+
+	use super::{format_guid, read_partition_table, PartitionError, PartitionTableType, PartitionType};
+
+	const SECTOR: usize = 512;
+
+	/// Writes an MBR partition entry into the given sector.
+	fn mbr_entry(disk: &mut [u8], sector: usize, index: usize, status: u8, ty: u8, first: u32, count: u32) {
+		let offset = sector * SECTOR + 446 + index * 16;
+		disk[offset] = status;
+		disk[offset + 4] = ty;
+		disk[offset + 8..offset + 12].copy_from_slice(&first.to_le_bytes());
+		disk[offset + 12..offset + 16].copy_from_slice(&count.to_le_bytes());
+		disk[sector * SECTOR + 510..sector * SECTOR + 512].copy_from_slice(&[0x55, 0xAA]);
+	}
+
+	#[test]
+	fn test_mbr() {
+		let mut disk = vec![0; 4096 * SECTOR];
+		disk[440..444].copy_from_slice(&0xDEADBEEFu32.to_le_bytes());
+		mbr_entry(&mut disk, 0, 0, 0x80, 0x83, 2048, 1024);
+		mbr_entry(&mut disk, 0, 1, 0, 0x05, 3072, 1024);
+
+		// Two logical partitions, each 1 sector after their EBR.
+		mbr_entry(&mut disk, 3072, 0, 0, 0x82, 1, 255);
+		mbr_entry(&mut disk, 3072, 1, 0, 0x05, 256, 512);
+		mbr_entry(&mut disk, 3328, 0, 0, 0x83, 1, 511);
+
+		let table = read_partition_table(&mut Cursor::new(disk)).unwrap().unwrap();
+		assert_eq!(table.ty, PartitionTableType::Mbr);
+		assert_eq!(table.disk_id[..4], [0xDE, 0xAD, 0xBE, 0xEF]);
+
+		let partitions: Vec<_> = table
+			.partitions
+			.iter()
+			.map(|p| (p.number, p.first_sector, p.sector_count, p.ty, p.bootable))
+			.collect();
+		assert_eq!(
+			partitions,
+			vec![
+				(1, 2048, 1024, PartitionType::Mbr(0x83), true),
+				(2, 3072, 1024, PartitionType::Mbr(0x05), false),
+				(5, 3073, 255, PartitionType::Mbr(0x82), false),
+				(6, 3329, 511, PartitionType::Mbr(0x83), false),
+			]
+		);
+		assert_eq!(table.partitions[2].ty.name(), Some("Linux swap"));
+	}
+
+	#[test]
+	fn test_no_partition_table() {
+		assert!(read_partition_table(&mut Cursor::new(vec![0; 64 * SECTOR]))
+			.unwrap()
+			.is_none());
+	}
+
+	/// Parses a GUID in its usual text form into the mixed endian form it's stored in on disk.
+	fn guid(text: &str) -> [u8; 16] {
+		let hex: Vec<u8> = text
+			.replace('-', "")
+			.as_bytes()
+			.chunks(2)
+			.map(|b| u8::from_str_radix(std::str::from_utf8(b).unwrap(), 16).unwrap())
+			.collect();
+		let mut guid = [0; 16];
+		guid.copy_from_slice(&hex);
+		guid[0..4].reverse();
+		guid[4..6].reverse();
+		guid[6..8].reverse();
+		guid
+	}
+
+	/// Writes a GPT header, and its entries, at the given sector of a 256 sector disk.
+	fn gpt_header(disk: &mut [u8], lba: u64, backup_lba: u64, entries_lba: u64, entries: &[u8]) {
+		let entries_offset = entries_lba as usize * SECTOR;
+		disk[entries_offset..entries_offset + entries.len()].copy_from_slice(entries);
+
+		let offset = lba as usize * SECTOR;
+		let header = &mut disk[offset..offset + 92];
+		header[0..8].copy_from_slice(b"EFI PART");
+		header[8..12].copy_from_slice(&0x00010000u32.to_le_bytes());
+		header[12..16].copy_from_slice(&92u32.to_le_bytes());
+		header[24..32].copy_from_slice(&lba.to_le_bytes());
+		header[32..40].copy_from_slice(&backup_lba.to_le_bytes());
+		header[40..48].copy_from_slice(&34u64.to_le_bytes());
+		header[48..56].copy_from_slice(&222u64.to_le_bytes());
+		header[56..72].copy_from_slice(&guid("11111111-2222-3333-4444-555555555555"));
+		header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+		header[80..84].copy_from_slice(&((entries.len() / 128) as u32).to_le_bytes());
+		header[84..88].copy_from_slice(&128u32.to_le_bytes());
+		header[88..92].copy_from_slice(&crc32fast::hash(entries).to_le_bytes());
+		let crc = crc32fast::hash(header);
+		header[16..20].copy_from_slice(&crc.to_le_bytes());
+	}
+
+	fn gpt_disk() -> Vec<u8> {
+		let sectors = 256;
+		let mut disk = vec![0; sectors * SECTOR];
+
+		// The protective MBR, which must not be read as the partition table.
+		mbr_entry(&mut disk, 0, 0, 0, 0xEE, 1, sectors as u32 - 1);
+
+		let mut entries = vec![0; 128 * 128];
+		entries[0..16].copy_from_slice(&guid("C12A7328-F81F-11D2-BA4B-00A0C93EC93B"));
+		entries[16..32].copy_from_slice(&guid("AAAAAAAA-BBBB-CCCC-DDDD-EEEEEEEEEEEE"));
+		entries[32..40].copy_from_slice(&34u64.to_le_bytes());
+		entries[40..48].copy_from_slice(&99u64.to_le_bytes());
+		entries[48..56].copy_from_slice(&(1u64 << 2).to_le_bytes());
+		for (i, c) in "EFI".encode_utf16().enumerate() {
+			entries[56 + i * 2..58 + i * 2].copy_from_slice(&c.to_le_bytes());
+		}
+
+		// The second entry is unused, so the root partition is the third.
+		entries[256..272].copy_from_slice(&guid("4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709"));
+		entries[288..296].copy_from_slice(&100u64.to_le_bytes());
+		entries[296..304].copy_from_slice(&222u64.to_le_bytes());
+
+		gpt_header(&mut disk, 1, sectors as u64 - 1, 2, &entries);
+		gpt_header(&mut disk, sectors as u64 - 1, 1, sectors as u64 - 33, &entries);
+		disk
+	}
+
+	#[test]
+	fn test_gpt() {
+		let table = read_partition_table(&mut Cursor::new(gpt_disk())).unwrap().unwrap();
+		assert_eq!(table.ty, PartitionTableType::Gpt);
+		assert_eq!(format_guid(&table.disk_id), "11111111-2222-3333-4444-555555555555");
+		assert_eq!(table.partitions.len(), 2);
+
+		let efi = &table.partitions[0];
+		assert_eq!((efi.number, efi.first_sector, efi.sector_count), (1, 34, 66));
+		assert_eq!(efi.ty.name(), Some("EFI System"));
+		assert_eq!(format_guid(&efi.uuid), "AAAAAAAA-BBBB-CCCC-DDDD-EEEEEEEEEEEE");
+		assert_eq!(efi.name, "EFI");
+		assert!(efi.bootable);
+
+		let root = &table.partitions[1];
+		assert_eq!((root.number, root.first_sector, root.sector_count), (3, 100, 123));
+		assert_eq!(root.ty.name(), Some("Linux root (x86-64)"));
+		assert!(!root.bootable);
+	}
+
+	#[test]
+	fn test_gpt_backup() {
+		// A corrupt primary header falls back to the backup.
+		let mut disk = gpt_disk();
+		disk[SECTOR + 40] ^= 0xFF;
+		let table = read_partition_table(&mut Cursor::new(disk.clone())).unwrap().unwrap();
+		assert_eq!(table.partitions.len(), 2);
+
+		// With both corrupt, the primary's error is returned.
+		let last = disk.len() - SECTOR;
+		disk[last + 40] ^= 0xFF;
+		assert!(matches!(
+			read_partition_table(&mut Cursor::new(disk)),
+			Err(PartitionError::ChecksumMismatch { what: "GPT header", .. })
+		));
+	}
+}
@@ -0,0 +1,65 @@
+use bytestruct::UUID;
+use bytestruct_derive::ByteStruct;
+
+use crate::types::Superblock;
+
+/// "hsqs"
+pub const SQUASHFS_MAGIC: u32 = 0x73717368;
+
+/// The only major version of squashfs that the kernel can mount.
+const SQUASHFS_VERSION_MAJOR: u16 = 4;
+
+#[derive(ByteStruct)]
+#[little_endian]
+pub struct SquashfsSuperBlock {
+	pub magic: u32,
+	pub inode_count: u32,
+	pub modification_time: u32,
+	pub block_size: u32,
+	pub fragment_entry_count: u32,
+	pub compression_id: u16,
+	pub block_log: u16,
+	pub flags: u16,
+	pub id_count: u16,
+	pub version_major: u16,
+	pub version_minor: u16,
+	pub root_inode_ref: u64,
+	pub bytes_used: u64,
+	pub id_table_start: u64,
+	pub xattr_id_table_start: u64,
+	pub inode_table_start: u64,
+	pub directory_table_start: u64,
+	pub fragment_table_start: u64,
+	pub export_table_start: u64,
+}
+
+impl Superblock for SquashfsSuperBlock {
+	fn offset() -> u64 {
+		0
+	}
+
+	fn size() -> usize {
+		0x60
+	}
+
+	fn validate(&self) -> bool {
+		self.magic == SQUASHFS_MAGIC
+			&& self.version_major == SQUASHFS_VERSION_MAJOR
+			&& self.block_log < 32
+			&& self.block_size == 1 << self.block_log
+	}
+
+	fn name(&self) -> String {
+		"squashfs".to_string()
+	}
+
+	/// Squashfs images don't have labels.
+	fn label(&self) -> String {
+		String::new()
+	}
+
+	/// Squashfs images don't have UUIDs.
+	fn uuid(&self) -> UUID {
+		UUID::default()
+	}
+}
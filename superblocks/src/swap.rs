@@ -0,0 +1,85 @@
+use bytestruct::{NullTerminatedString, UUID};
+use bytestruct_derive::ByteStruct;
+
+use crate::types::Superblock;
+
+/// The signature at the end of the first page of a swap area made by a modern mkswap.
+pub const SWAP_MAGIC: [u8; 10] = *b"SWAPSPACE2";
+
+/// The version of the swap header that has a UUID and label.
+const SWAP_VERSION: u32 = 1;
+
+/// The size of the pages that the swap area was made with. The signature is at the end of the first page, so swap
+/// areas made on systems with larger pages aren't recognised.
+const SWAP_PAGE_SIZE: usize = 0x1000;
+
+/// The offset of the header in the first page, after space left for a boot loader.
+const SWAP_HEADER_OFFSET: usize = 0x400;
+
+/// The header of a swap area, in the first page of the device after the boot loader space. Swap headers are written in
+/// the native endianness, which for the systems we run on is little endian.
+#[derive(ByteStruct)]
+#[little_endian]
+pub struct SwapSuperBlock {
+	pub version: u32,
+	pub last_page: u32,
+	pub bad_page_count: u32,
+	pub uuid: UUID,
+	pub label: NullTerminatedString<16>,
+	/// The fields above take 44 bytes, and the signature is in the last bytes of the page.
+	_padding: [u8; SWAP_PAGE_SIZE - SWAP_HEADER_OFFSET - 44 - SWAP_MAGIC.len()],
+	pub magic: [u8; 10],
+}
+
+impl Superblock for SwapSuperBlock {
+	fn offset() -> u64 {
+		SWAP_HEADER_OFFSET as u64
+	}
+
+	fn size() -> usize {
+		SWAP_PAGE_SIZE - SWAP_HEADER_OFFSET
+	}
+
+	fn validate(&self) -> bool {
+		self.magic == SWAP_MAGIC && self.version == SWAP_VERSION
+	}
+
+	fn name(&self) -> String {
+		"swap".to_string()
+	}
+
+	fn label(&self) -> String {
+		self.label.0.clone()
+	}
+
+	fn uuid(&self) -> UUID {
+		self.uuid
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::Cursor;
+
+	use bytestruct::ReadFrom;
+
+	use super::{SwapSuperBlock, SWAP_HEADER_OFFSET, SWAP_PAGE_SIZE};
+	use crate::Superblock;
+
+	#[test]
+	fn test_swap_header() {
+		// The first page of a swap area, as made by `mkswap -L swap`.
+		let mut page = vec![0; SWAP_PAGE_SIZE];
+		page[0x400..0x404].copy_from_slice(&1u32.to_le_bytes());
+		page[0x404..0x408].copy_from_slice(&0x3FFFu32.to_le_bytes());
+		page[0x40C..0x41C].copy_from_slice(&[0xAB; 16]);
+		page[0x41C..0x420].copy_from_slice(b"swap");
+		page[SWAP_PAGE_SIZE - 10..].copy_from_slice(b"SWAPSPACE2");
+
+		let header = SwapSuperBlock::read_from(&mut Cursor::new(&page[SWAP_HEADER_OFFSET..])).unwrap();
+		assert!(header.validate());
+		assert_eq!(header.label(), "swap");
+		assert_eq!(header.uuid(), [0xAB; 16]);
+		assert_eq!(header.last_page, 0x3FFF);
+	}
+}
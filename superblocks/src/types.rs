@@ -1,3 +1,8 @@
+use std::{
+	fmt,
+	time::{Duration, SystemTime},
+};
+
 use bytestruct::UUID;
 
 /// A trait for filesystem superblocks.
@@ -12,4 +17,66 @@ pub trait Superblock {
 	fn name(&self) -> String;
 	fn label(&self) -> String;
 	fn uuid(&self) -> UUID;
+	/// Returns what the filesystem records about its mounts and checks, if it keeps track of them.
+	fn health(&self) -> Option<FilesystemHealth> {
+		None
+	}
+}
+
+/// Whether a filesystem was left in a consistent state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilesystemState {
+	/// The filesystem was cleanly unmounted.
+	Clean,
+	/// The filesystem wasn't cleanly unmounted (or is still mounted), so may be inconsistent.
+	Dirty,
+	/// The kernel found errors in the filesystem.
+	Errors,
+}
+
+impl fmt::Display for FilesystemState {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			FilesystemState::Clean => "clean",
+			FilesystemState::Dirty => "dirty",
+			FilesystemState::Errors => "errors",
+		})
+	}
+}
+
+/// The state of a filesystem, and the record of its mounts and checks, used to decide when it needs checking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilesystemHealth {
+	pub state: FilesystemState,
+	/// When the filesystem was last mounted, if it ever has been.
+	pub last_mounted: Option<SystemTime>,
+	/// When the filesystem was last checked (or created), if known.
+	pub last_checked: Option<SystemTime>,
+	/// The number of times the filesystem has been mounted since it was last checked.
+	pub mount_count: u32,
+	/// The number of mounts after which the filesystem should be checked, if there's a limit.
+	pub max_mount_count: Option<u32>,
+	/// How long the filesystem can go between checks, if there's a limit.
+	pub check_interval: Option<Duration>,
+}
+
+impl FilesystemHealth {
+	/// Returns true if the filesystem should be checked before it's mounted: because it's dirty or has errors, or
+	/// has gone too many mounts, or too long, since it was last checked.
+	pub fn needs_check(&self, now: SystemTime) -> bool {
+		if self.state != FilesystemState::Clean {
+			return true;
+		}
+
+		if self.max_mount_count.is_some_and(|max| self.mount_count >= max) {
+			return true;
+		}
+
+		match (self.check_interval, self.last_checked) {
+			(Some(interval), Some(last_checked)) => now
+				.duration_since(last_checked)
+				.is_ok_and(|elapsed| elapsed >= interval),
+			_ => false,
+		}
+	}
 }
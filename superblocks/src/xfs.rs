@@ -0,0 +1,74 @@
+use bytestruct::{NullTerminatedString, UUID};
+use bytestruct_derive::ByteStruct;
+
+use crate::types::Superblock;
+
+/// "XFSB"
+pub const XFS_MAGIC: u32 = 0x58465342;
+
+/// The mask of the version number in `version_num`, the rest of which are feature flags.
+const XFS_VERSION_MASK: u16 = 0x000F;
+
+/// The first part of an XFS superblock, up to and including the fields we care about.
+#[derive(ByteStruct)]
+#[big_endian]
+pub struct XfsSuperBlock {
+	pub magic: u32,
+	pub block_size: u32,
+	pub data_blocks: u64,
+	pub realtime_blocks: u64,
+	pub realtime_extents: u64,
+	pub uuid: UUID,
+	pub log_start: u64,
+	pub root_inode: u64,
+	pub realtime_bitmap_inode: u64,
+	pub realtime_summary_inode: u64,
+	pub realtime_extent_size: u32,
+	pub ag_blocks: u32,
+	pub ag_count: u32,
+	pub realtime_bitmap_blocks: u32,
+	pub log_blocks: u32,
+	pub version_num: u16,
+	pub sector_size: u16,
+	pub inode_size: u16,
+	pub inodes_per_block: u16,
+	pub label: NullTerminatedString<12>,
+	pub block_log: u8,
+	pub sector_log: u8,
+	pub inode_log: u8,
+	pub inodes_per_block_log: u8,
+	pub ag_block_log: u8,
+	pub realtime_extents_log: u8,
+	pub in_progress: u8,
+	pub inode_max_pct: u8,
+}
+
+impl Superblock for XfsSuperBlock {
+	fn offset() -> u64 {
+		0
+	}
+
+	fn size() -> usize {
+		0x200
+	}
+
+	fn validate(&self) -> bool {
+		// A filesystem that mkfs didn't finish making has `in_progress` set, and can't be mounted.
+		self.magic == XFS_MAGIC
+			&& (1..=5).contains(&(self.version_num & XFS_VERSION_MASK))
+			&& self.block_size.is_power_of_two()
+			&& self.in_progress == 0
+	}
+
+	fn name(&self) -> String {
+		"xfs".to_string()
+	}
+
+	fn label(&self) -> String {
+		self.label.0.clone()
+	}
+
+	fn uuid(&self) -> UUID {
+		self.uuid
+	}
+}
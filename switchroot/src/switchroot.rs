@@ -1,13 +1,40 @@
-use std::{ffi::CString, fs, io, path::PathBuf};
+use std::{
+	ffi::{CStr, CString},
+	fs::{self, File},
+	io,
+	os::fd::{AsRawFd, RawFd},
+	path::{Path, PathBuf},
+};
 
 use anyhow::{anyhow, Context, Result};
+use mount::mounts::{read_mounts, Mount};
 use nix::{
+	dir::Dir,
+	fcntl::{openat, AtFlags, OFlag},
 	mount::{mount, MsFlags},
-	sys::stat::Mode,
-	unistd::{chdir, chroot, execve, mkdir},
+	sys::{
+		stat::{fstat, fstatat, Mode, SFlag},
+		statfs::{fstatfs, FsType, TMPFS_MAGIC},
+	},
+	unistd::{chdir, chroot, close, execve, mkdir, unlinkat, UnlinkatFlags},
 };
 use superblocks::Device;
 
+/// The filesystem type of ramfs, which nix doesn't define.
+const RAMFS_MAGIC: FsType = FsType(0x858458f6_u32 as _);
+
+/// The API filesystems that are moved into the new root, so that they stay mounted after the switch.
+const API_FILESYSTEMS: [&str; 8] = [
+	"/dev",
+	"/dev/pts",
+	"/dev/shm",
+	"/proc",
+	"/sys",
+	"/sys/fs/cgroup",
+	"/run",
+	"/tmp",
+];
+
 /// A command to switch the root filesystem.
 pub struct SwitchrootCommand {
 	/// The new root filesystem that will be mounted.
@@ -54,31 +81,31 @@ impl SwitchrootCommand {
 		.with_context(|| {
 			format!(
 				"failed to mount {} at {}",
-				&self.new_root.display(),
-				&self.mount_path.display()
+				self.new_root.display(),
+				self.mount_path.display()
 			)
 		})?;
 		Ok(())
 	}
 
-	/// Move the device filesystems (/dev, /proc, /sys, /run) into the new root filesystem.
-	fn move_devices(&self) -> Result<()> {
-		for mount_dev in ["/dev", "/proc", "/sys", "/run", "/tmp"] {
-			let mount_dev = PathBuf::from(mount_dev);
+	/// Move the API filesystems (/dev, /proc, /sys, /run, ...) into the new root filesystem.
+	fn move_api_filesystems(&self) -> Result<()> {
+		let mounts = read_mounts().with_context(|| "failed to read mounts")?;
+		for source in api_mounts_to_move(&mounts, &self.mount_path) {
 			let target = self
 				.mount_path
-				.join(mount_dev.file_name().expect("mount device has filename"));
+				.join(source.strip_prefix("/").expect("API filesystems are absolute"));
 
 			if !target.exists() {
 				mkdir(&target, Mode::from_bits(0o755).expect("valid mount bits"))
-					.with_context(|| format!("failed to create {}", &target.display()))?;
+					.with_context(|| format!("failed to create {}", target.display()))?;
 			}
 
-			mount::<_, _, str, str>(Some(&mount_dev), &target, None, MsFlags::MS_MOVE, None).with_context(|| {
+			mount::<_, _, str, str>(Some(&source), &target, None, MsFlags::MS_MOVE, None).with_context(|| {
 				format!(
 					"failed to move system folder from {} to {}",
-					&mount_dev.display(),
-					&target.display()
+					source.display(),
+					target.display()
 				)
 			})?;
 		}
@@ -93,7 +120,10 @@ impl SwitchrootCommand {
 			.with_context(|| format!("failed to create directory: {}", self.mount_path.display()))?;
 
 		self.mount()?;
-		self.move_devices()?;
+		self.move_api_filesystems()?;
+
+		// Hold on to the old root, so that it can be cleaned up once it's no longer reachable by path.
+		let old_root = File::open("/").with_context(|| "failed to open the old root")?;
 
 		chdir(&self.mount_path).with_context(|| "failed to change directory to new root")?;
 
@@ -104,6 +134,12 @@ impl SwitchrootCommand {
 		chroot(".")?;
 		chdir("/")?;
 
+		// The old root is a ram filesystem, so anything left in it is memory that can never be used again.
+		if let Err(e) = remove_old_root(&old_root) {
+			eprintln!("Failed to clean up the old root: {:#}", e);
+		}
+		drop(old_root);
+
 		execve::<_, &CString>(&CString::new("/sbin/qinit")?, &[&CString::new("qinit")?], &[])
 			.with_context(|| "failed to execute /sbin/init")?;
 
@@ -111,6 +147,95 @@ impl SwitchrootCommand {
 	}
 }
 
+/// Returns the API filesystems that need to be moved into the new root, in the order to move them. Filesystems that
+/// aren't mounted are skipped, as are those inside another one that's being moved (e.g. /dev/pts in /dev), because
+/// moving a mount moves everything mounted inside of it too.
+fn api_mounts_to_move(mounts: &[Mount], new_root: &Path) -> Vec<PathBuf> {
+	let mut mounted: Vec<PathBuf> = API_FILESYSTEMS
+		.iter()
+		.map(PathBuf::from)
+		.filter(|path| mounts.iter().any(|mount| &mount.mount_point == path))
+		// A filesystem that the new root is in (or that's in the new root) can't be moved into it.
+		.filter(|path| !new_root.starts_with(path) && !path.starts_with(new_root))
+		.collect();
+
+	// Parents are moved before their children, so that children that are moved along with them can be skipped.
+	mounted.sort_by_key(|path| path.components().count());
+
+	let mut moves: Vec<PathBuf> = Vec::new();
+	for path in mounted {
+		if !moves.iter().any(|parent| path.starts_with(parent)) {
+			moves.push(path);
+		}
+	}
+
+	moves
+}
+
+/// Deletes everything in the old root, which must be a ram filesystem so that a real disk can't be wiped by mistake.
+fn remove_old_root(old_root: &File) -> Result<()> {
+	let filesystem = fstatfs(old_root).with_context(|| "failed to stat the old root filesystem")?;
+	if filesystem.filesystem_type() != RAMFS_MAGIC && filesystem.filesystem_type() != TMPFS_MAGIC {
+		return Err(anyhow!("the old root isn't a ram filesystem, so not removing it"));
+	}
+
+	let device = fstat(old_root.as_raw_fd())
+		.with_context(|| "failed to stat the old root")?
+		.st_dev;
+
+	remove_tree(old_root.as_raw_fd(), device).with_context(|| "failed to remove the old root")
+}
+
+/// Recursively deletes the contents of the directory open at `dirfd`, without crossing into other filesystems (e.g.
+/// mounts that were left behind). Deleting carries on past failures, returning the first one.
+fn remove_tree(dirfd: RawFd, device: u64) -> nix::Result<()> {
+	let mut dir = Dir::openat(dirfd, ".", OFlag::O_RDONLY | OFlag::O_DIRECTORY, Mode::empty())?;
+	let names: Vec<CString> = dir
+		.iter()
+		.filter_map(|entry| entry.ok())
+		.map(|entry| entry.file_name().to_owned())
+		.filter(|name| name.as_c_str() != c"." && name.as_c_str() != c"..")
+		.collect();
+
+	let mut result = Ok(());
+	for name in names {
+		if let Err(e) = remove_entry(dir.as_raw_fd(), &name, device) {
+			result = result.and(Err(e));
+		}
+	}
+
+	result
+}
+
+/// Deletes the given entry of the directory open at `dirfd`, and its contents if it's a directory on the given device.
+fn remove_entry(dirfd: RawFd, name: &CStr, device: u64) -> nix::Result<()> {
+	let stat = fstatat(dirfd, name, AtFlags::AT_SYMLINK_NOFOLLOW)?;
+	if stat.st_dev != device {
+		return Ok(());
+	}
+
+	let is_dir = SFlag::from_bits_truncate(stat.st_mode) & SFlag::S_IFMT == SFlag::S_IFDIR;
+	if is_dir {
+		let child = openat(
+			dirfd,
+			name,
+			OFlag::O_RDONLY | OFlag::O_DIRECTORY | OFlag::O_NOFOLLOW,
+			Mode::empty(),
+		)?;
+		let result = remove_tree(child, device);
+		close(child)?;
+		result?;
+	}
+
+	let flags = if is_dir {
+		UnlinkatFlags::RemoveDir
+	} else {
+		UnlinkatFlags::NoRemoveDir
+	};
+
+	unlinkat(Some(dirfd), name, flags)
+}
+
 /// Get the new root filesystem from the kernel command line.
 fn default_new_root() -> io::Result<Option<PathBuf>> {
 	let cmdline = fs::read_to_string("/proc/cmdline")?;
@@ -122,3 +247,71 @@ fn default_new_root() -> io::Result<Option<PathBuf>> {
 
 	Ok(None)
 }
+
+#[cfg(test)]
+mod test {
+	use std::{
+		fs::{self, File},
+		os::{fd::AsRawFd, unix::fs::MetadataExt},
+		path::{Path, PathBuf},
+	};
+
+	use common::testing::TempDir;
+	use mount::mounts::parse_mountinfo;
+
+	use super::{api_mounts_to_move, remove_tree};
+
+	#[test]
+	fn test_api_mounts_to_move() {
+		let mounts = parse_mountinfo(
+			"1 0 0:1 / / rw - rootfs rootfs rw
+2 1 0:2 / /dev rw - devtmpfs devtmpfs rw
+3 2 0:3 / /dev/pts rw - devpts devpts rw
+4 1 0:4 / /proc rw - proc proc rw
+5 1 0:5 / /sys/fs/cgroup rw - cgroup2 cgroup2 rw
+6 1 0:6 / /run rw - tmpfs tmpfs rw
+7 1 8:1 / /.root rw - ext4 /dev/sda1 rw
+8 7 0:7 / /.root/tmp rw - tmpfs tmpfs rw
+",
+		)
+		.unwrap();
+
+		// /dev/pts moves along with /dev, /sys isn't mounted so /sys/fs/cgroup has to be moved itself, and /tmp is
+		// only mounted in the new root.
+		assert_eq!(
+			api_mounts_to_move(&mounts, Path::new("/.root")),
+			vec![
+				PathBuf::from("/dev"),
+				PathBuf::from("/proc"),
+				PathBuf::from("/run"),
+				PathBuf::from("/sys/fs/cgroup"),
+			]
+		);
+
+		// The new root can't be moved into itself.
+		assert_eq!(
+			api_mounts_to_move(&mounts, Path::new("/run/root")),
+			vec![
+				PathBuf::from("/dev"),
+				PathBuf::from("/proc"),
+				PathBuf::from("/sys/fs/cgroup"),
+			]
+		);
+	}
+
+	#[test]
+	fn test_remove_tree() {
+		let root = TempDir::new("switchroot").unwrap();
+		fs::create_dir_all(root.join("a/b/c")).unwrap();
+		fs::write(root.join("a/b/c/file"), "contents").unwrap();
+		fs::write(root.join("file"), "contents").unwrap();
+		std::os::unix::fs::symlink("/etc/passwd", root.join("a/link")).unwrap();
+
+		let dir = File::open(&root).unwrap();
+		remove_tree(dir.as_raw_fd(), dir.metadata().unwrap().dev()).unwrap();
+
+		// The contents are gone, but the directory itself (and what the symlink pointed to) is left alone.
+		assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+		assert!(Path::new("/etc/passwd").exists());
+	}
+}
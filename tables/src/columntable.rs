@@ -1,4 +1,16 @@
-use std::fmt::{self, Display, Formatter};
+use std::{
+	fmt::{self, Display, Formatter},
+	io::stdout,
+	os::fd::AsRawFd,
+};
+
+use common::io::terminal_size;
+
+/// What is left of a truncated cell is followed by this, to show that it was cut short.
+const ELLIPSIS: char = '…';
+
+/// The narrowest that a column is shrunk to in order to fit the table into its maximum width.
+const MIN_SHRUNK_WIDTH: usize = 3;
 
 /// A setting that can be applied to a table.
 pub enum TableSetting {
@@ -10,16 +22,68 @@ pub enum TableSetting {
 
 	/// Add a border around the table.
 	Border,
+
+	/// Align the cells of the given column, e.g. to the right for numbers.
+	Alignment(usize, Alignment),
+
+	/// Limit the given column to a number of characters. Wider cells are truncated, unless the column wraps.
+	MaxColumnWidth(usize, usize),
+
+	/// Wrap the cells of the given column onto more lines when they're too wide for it, rather than truncating them.
+	/// Headers are always truncated, so that they stay on one line.
+	Wrap(usize),
+
+	/// Limit the whole table to a number of characters, by shrinking the widest columns until it fits.
+	MaxWidth(usize),
+
+	/// Limit the table to the width of the terminal that stdout is, if it is one.
+	FitTerminal,
+}
+
+/// How the cells of a column are lined up within it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Alignment {
+	#[default]
+	Left,
+	Right,
+	Center,
+}
+
+/// The settings of a single column.
+#[derive(Debug, Clone, Copy, Default)]
+struct ColumnSettings {
+	alignment: Alignment,
+
+	/// The most characters that the column can be, if it's limited.
+	max_width: Option<usize>,
+
+	/// Whether cells that are too wide are wrapped, rather than truncated.
+	wrap: bool,
+}
+
+/// A row of a table.
+enum Row<const COLS: usize> {
+	/// A row of cells, one for each column.
+	Cells([String; COLS]),
+
+	/// A heading that starts a group of rows, printed across the whole table.
+	Group(String),
 }
 
 /// A table that can be printed to the console.
 pub struct Table<const COLS: usize> {
 	headers: Option<[String; COLS]>,
-	rows: Vec<[String; COLS]>,
+	rows: Vec<Row<COLS>>,
 
 	// A memoized copy of the amaximum width of each column.
 	widths: [usize; COLS],
 
+	/// The settings of each column.
+	columns: [ColumnSettings; COLS],
+
+	/// The most characters that the whole table can be, if it's limited.
+	max_width: Option<usize>,
+
 	// Settings
 	/// Add a seperator between the headers and the rows.
 	header_seperator: bool,
@@ -44,6 +108,8 @@ impl<const COLS: usize> Table<COLS> {
 			headers: None,
 			rows: Vec::new(),
 			widths: [0; COLS],
+			columns: [ColumnSettings::default(); COLS],
+			max_width: None,
 
 			header_seperator: false,
 			column_seperators: false,
@@ -54,11 +120,13 @@ impl<const COLS: usize> Table<COLS> {
 	/// Create a new table with headers.
 	pub fn new_with_headers(headers: [&str; COLS]) -> Table<COLS> {
 		let headers = headers.map(|s| s.to_owned());
-		let widths = headers.clone().map(|s| s.len());
+		let widths = headers.clone().map(|s| s.chars().count());
 		Table {
 			headers: Some(headers),
 			rows: Vec::new(),
 			widths,
+			columns: [ColumnSettings::default(); COLS],
+			max_width: None,
 
 			header_seperator: false,
 			column_seperators: false,
@@ -66,19 +134,69 @@ impl<const COLS: usize> Table<COLS> {
 		}
 	}
 
-	/// Add a setting to the table.
+	/// Add a setting to the table. Column settings for columns that don't exist are ignored.
 	pub fn with_setting(mut self, setting: TableSetting) -> Self {
 		match setting {
 			TableSetting::HeaderSeperator => self.header_seperator = true,
 			TableSetting::ColumnSeperators => self.column_seperators = true,
 			TableSetting::Border => self.border = true,
+			TableSetting::Alignment(column, alignment) => {
+				if let Some(column) = self.columns.get_mut(column) {
+					column.alignment = alignment;
+				}
+			}
+			TableSetting::MaxColumnWidth(column, width) => {
+				if let Some(column) = self.columns.get_mut(column) {
+					column.max_width = Some(width.max(1));
+				}
+			}
+			TableSetting::Wrap(column) => {
+				if let Some(column) = self.columns.get_mut(column) {
+					column.wrap = true;
+				}
+			}
+			TableSetting::MaxWidth(width) => self.max_width = Some(width),
+			TableSetting::FitTerminal => {
+				if let Some((_, columns)) = terminal_size(stdout().as_raw_fd()) {
+					self.max_width = Some(columns);
+				}
+			}
 		}
 		self
 	}
 
-	fn width(&self) -> usize {
+	/// The widths that the columns are printed at: the width of their widest cells, limited to their maximum widths,
+	/// with the widest columns then shrunk until the table fits into its maximum width.
+	fn column_widths(&self) -> [usize; COLS] {
+		let mut widths = self.widths;
+		for (width, column) in widths.iter_mut().zip(self.columns.iter()) {
+			if let Some(max_width) = column.max_width {
+				*width = (*width).min(max_width);
+			}
+		}
+
+		if let Some(max_width) = self.max_width {
+			while self.width(&widths) > max_width {
+				let Some(width) = widths.iter_mut().max_by_key(|width| **width) else {
+					break;
+				};
+
+				// Columns that are already narrow are left alone, rather than shrunk to nothing.
+				if *width <= MIN_SHRUNK_WIDTH {
+					break;
+				}
+
+				*width -= 1;
+			}
+		}
+
+		widths
+	}
+
+	/// The width of the table when its columns are the given widths.
+	fn width(&self, widths: &[usize; COLS]) -> usize {
 		// The width of all the columns.
-		let mut base_width = self.widths.iter().sum::<usize>() + COLS - 1;
+		let mut base_width = widths.iter().sum::<usize>() + COLS - 1;
 
 		if self.border {
 			// If we have a border, we have two on each side of the table.
@@ -93,49 +211,172 @@ impl<const COLS: usize> Table<COLS> {
 		base_width
 	}
 
+	/// The number of lines that are printed above the rows of the table: the top border, headers, and seperator.
+	pub(crate) fn header_line_count(&self) -> usize {
+		let mut lines = 0;
+		if self.border {
+			lines += 1;
+		}
+
+		if self.headers.is_some() {
+			lines += 1;
+			if self.header_seperator {
+				lines += 1;
+			}
+		}
+
+		lines
+	}
+
+	/// The number of lines that are printed below the rows of the table: the bottom border.
+	pub(crate) fn footer_line_count(&self) -> usize {
+		if self.border {
+			1
+		} else {
+			0
+		}
+	}
+
 	pub fn add_row(&mut self, row: [&str; COLS]) {
 		let row = row.map(|s| s.to_owned());
 		for (i, cell) in row.iter().enumerate() {
-			self.widths[i] = self.widths[i].max(cell.len());
+			self.widths[i] = self.widths[i].max(cell.chars().count());
 		}
 
-		self.rows.push(row);
+		self.rows.push(Row::Cells(row));
+	}
+
+	/// The headers of the table, if it has them.
+	pub fn headers(&self) -> Option<&[String; COLS]> {
+		self.headers.as_ref()
 	}
 
-	fn write_row(&self, f: &mut fmt::Formatter, row: &[String]) -> fmt::Result {
+	/// The rows of cells in the table, leaving out the headings of groups.
+	pub fn rows(&self) -> impl Iterator<Item = &[String; COLS]> {
+		self.rows.iter().filter_map(|row| match row {
+			Row::Cells(cells) => Some(cells),
+			Row::Group(_) => None,
+		})
+	}
+
+	/// Add a heading that starts a new group of rows, e.g. the rows that share a category. The heading is printed on
+	/// its own line, and doesn't affect the widths of the columns.
+	pub fn add_group(&mut self, title: &str) {
+		self.rows.push(Row::Group(title.to_owned()));
+	}
+
+	fn write_group(&self, f: &mut fmt::Formatter, title: &str, widths: &[usize; COLS]) -> fmt::Result {
 		if self.border {
-			write!(f, "| ")?;
+			// The border is drawn around the inside of the table, so the title is padded out (or cut) to fit.
+			let inner_width = self.width(widths) - 4;
+			let title: String = title.chars().take(inner_width).collect();
+			writeln!(f, "| {:width$} |", title, width = inner_width)
+		} else {
+			writeln!(f, "{}", title)
 		}
+	}
 
-		for (i, cell) in row.iter().enumerate() {
-			write!(f, "{:width$}", cell, width = self.widths[i])?;
-			if i != row.len() - 1 {
-				write!(f, " ")?;
-				if self.column_seperators {
-					write!(f, "| ")?;
+	/// Writes a row, which takes up more than one line if any of its cells are wrapped. `wrap` is whether the cells of
+	/// columns that wrap can be, which headers can't.
+	fn write_row(&self, f: &mut fmt::Formatter, row: &[String], widths: &[usize; COLS], wrap: bool) -> fmt::Result {
+		let cells: Vec<Vec<String>> = row
+			.iter()
+			.enumerate()
+			.map(|(i, cell)| match wrap && self.columns[i].wrap {
+				true => wrap_cell(cell, widths[i]),
+				false => vec![truncate_cell(cell, widths[i])],
+			})
+			.collect();
+
+		let height = cells.iter().map(Vec::len).max().unwrap_or(1);
+		for line in 0..height {
+			if self.border {
+				write!(f, "| ")?;
+			}
+
+			for (i, cell) in cells.iter().enumerate() {
+				let text = cell.get(line).map(String::as_str).unwrap_or("");
+				let width = widths[i];
+				match self.columns[i].alignment {
+					Alignment::Left => write!(f, "{:<width$}", text)?,
+					Alignment::Right => write!(f, "{:>width$}", text)?,
+					Alignment::Center => write!(f, "{:^width$}", text)?,
+				}
+
+				if i != cells.len() - 1 {
+					write!(f, " ")?;
+					if self.column_seperators {
+						write!(f, "| ")?;
+					}
 				}
 			}
-		}
 
-		if self.border {
-			write!(f, " |")?;
+			if self.border {
+				write!(f, " |")?;
+			}
+
+			writeln!(f)?;
 		}
 
-		writeln!(f)?;
 		Ok(())
 	}
 }
 
+/// Cuts the cell down to the given number of characters, ending it with an ellipsis if anything was cut off.
+fn truncate_cell(cell: &str, width: usize) -> String {
+	if cell.chars().count() <= width {
+		return cell.to_owned();
+	}
+
+	let truncated: String = cell.chars().take(width.saturating_sub(1)).collect();
+	let mut truncated = truncated.trim_end().to_owned();
+	truncated.push(ELLIPSIS);
+	truncated
+}
+
+/// Splits the cell into lines of at most the given number of characters, breaking between words where it can.
+fn wrap_cell(cell: &str, width: usize) -> Vec<String> {
+	let mut lines = Vec::new();
+	let mut line = String::new();
+	for word in cell.split_whitespace() {
+		let mut word: Vec<char> = word.chars().collect();
+		let line_len = line.chars().count();
+		if line_len > 0 && line_len + 1 + word.len() <= width {
+			line.push(' ');
+			line.extend(word);
+			continue;
+		}
+
+		if line_len > 0 {
+			lines.push(std::mem::take(&mut line));
+		}
+
+		// Words that are too long for a line on their own are split across lines.
+		while word.len() > width {
+			lines.push(word.drain(..width).collect());
+		}
+
+		line.extend(word);
+	}
+
+	if !line.is_empty() || lines.is_empty() {
+		lines.push(line);
+	}
+
+	lines
+}
+
 impl<const COLS: usize> Display for Table<COLS> {
 	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-		let width = self.width();
+		let widths = self.column_widths();
+		let width = self.width(&widths);
 		let border_width = if self.column_seperators { width } else { width - 2 };
 		if self.border {
 			writeln!(f, "+{}+", "-".repeat(border_width))?;
 		}
 
 		if let Some(headers) = &self.headers {
-			self.write_row(f, headers)?;
+			self.write_row(f, headers, &widths, false)?;
 
 			if self.header_seperator {
 				if self.border {
@@ -147,7 +388,10 @@ impl<const COLS: usize> Display for Table<COLS> {
 		}
 
 		for row in &self.rows {
-			self.write_row(f, row)?;
+			match row {
+				Row::Cells(cells) => self.write_row(f, cells, &widths, true)?,
+				Row::Group(title) => self.write_group(f, title, &widths)?,
+			}
 		}
 
 		if self.border {
@@ -277,4 +521,114 @@ mod tests {
 							Jane  28 Nurse            \n"
 		);
 	}
+
+	#[test]
+	fn test_table_with_groups() {
+		let mut table = Table::new_with_headers(["Name", "State"]).with_setting(TableSetting::Border);
+		table.add_group("base");
+		table.add_row(["├─ ● udevd", "running"]);
+		table.add_row(["└─ ○ getty", "queued"]);
+		table.add_group("a group with a long title");
+
+		let output = format!("{}", table);
+		assert_eq!(
+			output,
+			"+--------------------+\n\
+							| Name       State   |\n\
+							| base               |\n\
+							| ├─ ● udevd running |\n\
+							| └─ ○ getty queued  |\n\
+							| a group with a lon |\n\
+							+--------------------+\n",
+			"\n{}",
+			output
+		);
+	}
+
+	#[test]
+	fn test_table_with_alignment() {
+		let mut table = Table::new_with_headers(["Name", "Size", "Type"])
+			.with_setting(TableSetting::Alignment(1, Alignment::Right))
+			.with_setting(TableSetting::Alignment(2, Alignment::Center));
+		table.add_row(["a.txt", "12", "file"]);
+		table.add_row(["src", "4096", "dir"]);
+
+		let output = format!("{}", table);
+		assert_eq!(
+			output,
+			"Name  Size Type\n\
+							a.txt   12 file\n\
+							src   4096 dir \n",
+			"\n{}",
+			output
+		);
+	}
+
+	#[test]
+	fn test_table_with_max_column_width() {
+		let mut table = Table::new_with_headers(["Name", "Description"])
+			.with_setting(TableSetting::MaxColumnWidth(1, 10))
+			.with_setting(TableSetting::MaxColumnWidth(0, 10));
+		table.add_row(["ls", "lists the files in a directory"]);
+
+		let output = format!("{}", table);
+		assert_eq!(
+			output,
+			"Name Descripti…\n\
+							ls   lists the…\n",
+			"\n{}",
+			output
+		);
+
+		let mut table = Table::new_with_headers(["Name", "Description"])
+			.with_setting(TableSetting::MaxColumnWidth(1, 10))
+			.with_setting(TableSetting::Wrap(1));
+		table.add_row(["ls", "lists the files in a directory"]);
+		table.add_row(["qsh", "a shell"]);
+
+		let output = format!("{}", table);
+		assert_eq!(
+			output,
+			"Name Descripti…\n\
+							ls   lists the \n\
+							\x20    files in a\n\
+							\x20    directory \n\
+							qsh  a shell   \n",
+			"\n{}",
+			output
+		);
+	}
+
+	#[test]
+	fn test_table_with_max_width() {
+		let mut table = Table::new_with_headers(["Name", "Age", "Occupation"])
+			.with_setting(TableSetting::MaxWidth(19))
+			.with_setting(TableSetting::Alignment(1, Alignment::Right));
+		table.add_row(["Colin", "25", "Software Engineer"]);
+		table.add_row(["John", "30", "Doctor"]);
+
+		// Only the widest column is shrunk, as far as it needs to be.
+		let output = format!("{}", table);
+		assert_eq!(
+			output,
+			"Name  Age Occupati…\n\
+							Colin  25 Software…\n\
+							John   30 Doctor   \n",
+			"\n{}",
+			output
+		);
+
+		// Columns aren't shrunk past a minimum, so a table can still be wider than asked for.
+		let mut table = Table::new().with_setting(TableSetting::MaxWidth(1));
+		table.add_row(["Colin", "25"]);
+		assert_eq!(format!("{}", table), "Co… 25\n");
+	}
+
+	#[test]
+	fn test_wrap_cell() {
+		assert_eq!(wrap_cell("", 5), vec![""]);
+		assert_eq!(wrap_cell("a b c", 3), vec!["a b", "c"]);
+		assert_eq!(wrap_cell("abcdefgh ij", 3), vec!["abc", "def", "gh", "ij"]);
+		assert_eq!(truncate_cell("a b c", 3), "a…");
+	}
 }
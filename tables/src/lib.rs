@@ -1,8 +1,12 @@
 mod columntable;
+mod output;
 mod rowtable;
+mod watch;
 
 pub use columntable::*;
+pub use output::*;
 pub use rowtable::*;
+pub use watch::*;
 
 use thiserror::Error;
 
@@ -13,4 +17,7 @@ pub enum TableError {
 
 	#[error("value too wide: max width is {0}, value is {1}")]
 	ValueTooWide(usize, usize),
+
+	#[error("unknown output format `{0}`: expected one of table, json, or tsv")]
+	UnknownOutputFormat(String),
 }
@@ -0,0 +1,169 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+};
+
+use serde::{
+	ser::{SerializeMap, SerializeSeq},
+	Serialize, Serializer,
+};
+
+use crate::{Table, TableError};
+
+/// The ways that a table can be written out: for people to read, or for other programs to parse.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+	/// Lined up in columns, with all of the table's settings applied.
+	#[default]
+	Table,
+
+	/// An array of objects, one for each row, keyed by the headers. Tables without headers are an array of arrays.
+	Json,
+
+	/// Tab separated values, with the headers (if there are any) as the first line.
+	Tsv,
+}
+
+impl OutputFormat {
+	/// The names of the formats, as they're parsed, for use in command line arguments.
+	pub const NAMES: [&'static str; 3] = ["table", "json", "tsv"];
+}
+
+impl FromStr for OutputFormat {
+	type Err = TableError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"table" => Ok(Self::Table),
+			"json" => Ok(Self::Json),
+			"tsv" => Ok(Self::Tsv),
+			_ => Err(TableError::UnknownOutputFormat(s.to_owned())),
+		}
+	}
+}
+
+impl Display for OutputFormat {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Table => write!(f, "table"),
+			Self::Json => write!(f, "json"),
+			Self::Tsv => write!(f, "tsv"),
+		}
+	}
+}
+
+impl<const COLS: usize> Table<COLS> {
+	/// Renders the table in the given format. The headings of groups are only shown in the `Table` format, as they
+	/// aren't part of any column.
+	pub fn render(&self, format: OutputFormat) -> String {
+		match format {
+			OutputFormat::Table => self.to_string(),
+			OutputFormat::Json => {
+				let mut json = serde_json::to_string(self).expect("BUG: tables of strings are always valid JSON");
+				json.push('\n');
+				json
+			}
+			OutputFormat::Tsv => {
+				let mut tsv = String::new();
+				for cells in self.headers().into_iter().chain(self.rows()) {
+					let cells: Vec<_> = cells.iter().map(|cell| escape_tsv(cell)).collect();
+					tsv.push_str(&cells.join("\t"));
+					tsv.push('\n');
+				}
+
+				tsv
+			}
+		}
+	}
+}
+
+/// Escapes the characters in a cell that would otherwise end it, or its row, early.
+fn escape_tsv(cell: &str) -> String {
+	let mut escaped = String::with_capacity(cell.len());
+	for c in cell.chars() {
+		match c {
+			'\\' => escaped.push_str("\\\\"),
+			'\t' => escaped.push_str("\\t"),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => escaped.push_str("\\r"),
+			c => escaped.push(c),
+		}
+	}
+
+	escaped
+}
+
+/// A row of a table, serialized as an object keyed by the headers.
+struct Object<'a, const COLS: usize> {
+	headers: &'a [String; COLS],
+	cells: &'a [String; COLS],
+}
+
+impl<const COLS: usize> Serialize for Object<'_, COLS> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(Some(COLS))?;
+		for (header, cell) in self.headers.iter().zip(self.cells.iter()) {
+			map.serialize_entry(header, cell)?;
+		}
+
+		map.end()
+	}
+}
+
+impl<const COLS: usize> Serialize for Table<COLS> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut seq = serializer.serialize_seq(None)?;
+		for cells in self.rows() {
+			match self.headers() {
+				Some(headers) => seq.serialize_element(&Object { headers, cells })?,
+				None => seq.serialize_element(cells.as_slice())?,
+			}
+		}
+
+		seq.end()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::OutputFormat;
+	use crate::{Table, TableSetting};
+
+	fn table() -> Table<3> {
+		let mut table = Table::new_with_headers(["Name", "Age", "Note"]).with_setting(TableSetting::Border);
+		table.add_group("people");
+		table.add_row(["Colin", "25", "likes \"quotes\""]);
+		table.add_row(["Jane", "28", "tab\there\\"]);
+		table
+	}
+
+	#[test]
+	fn test_parse_output_format() {
+		for name in OutputFormat::NAMES {
+			assert_eq!(name.parse::<OutputFormat>().unwrap().to_string(), name);
+		}
+
+		assert!("yaml".parse::<OutputFormat>().is_err());
+	}
+
+	#[test]
+	fn test_render_json() {
+		assert_eq!(
+			table().render(OutputFormat::Json),
+			"[{\"Name\":\"Colin\",\"Age\":\"25\",\"Note\":\"likes \\\"quotes\\\"\"},\
+			 {\"Name\":\"Jane\",\"Age\":\"28\",\"Note\":\"tab\\there\\\\\"}]\n"
+		);
+
+		let mut table = Table::new();
+		table.add_row(["a", "b"]);
+		assert_eq!(table.render(OutputFormat::Json), "[[\"a\",\"b\"]]\n");
+	}
+
+	#[test]
+	fn test_render_tsv() {
+		assert_eq!(
+			table().render(OutputFormat::Tsv),
+			"Name\tAge\tNote\nColin\t25\tlikes \"quotes\"\nJane\t28\ttab\\there\\\\\n"
+		);
+	}
+}
@@ -0,0 +1,224 @@
+use escapes::{ANSIEscapeSequence, Capabilities, CursorPosition, EraseInDisplay, EraseInLine};
+
+use crate::Table;
+
+/// The furthest row or column that the cursor can be moved to, as cursor positions are a byte each.
+const MAX_POSITION: usize = u8::MAX as usize;
+
+/// Renders a table in place on a terminal, for tools that periodically refresh what they show (e.g. `--watch` modes).
+/// The headers (and border) of the table stay fixed at the top of the screen, while the rows scroll beneath them.
+/// After the first render, only the parts of lines that changed are redrawn, so the screen doesn't flicker. Terminals
+/// that can't move the cursor get the whole table printed again instead.
+pub struct WatchRenderer {
+	/// The number of lines on the screen.
+	height: usize,
+
+	/// The index of the first row of the table that is shown.
+	scroll: usize,
+
+	/// The lines that are currently on the screen, or None if it needs to be redrawn from scratch.
+	screen: Option<Vec<String>>,
+
+	/// What the terminal can do.
+	capabilities: Capabilities,
+}
+
+impl WatchRenderer {
+	/// Creates a renderer for a screen with the given number of lines.
+	pub fn new(height: usize) -> Self {
+		Self {
+			height: height.min(MAX_POSITION),
+			scroll: 0,
+			screen: None,
+			capabilities: Capabilities::VT100,
+		}
+	}
+
+	pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+		self.capabilities = capabilities;
+		self
+	}
+
+	/// Changes the number of lines on the screen, e.g. after the terminal is resized.
+	pub fn resize(&mut self, height: usize) {
+		self.height = height.min(MAX_POSITION);
+		self.redraw();
+	}
+
+	/// Scrolls the rows of the table by the given number of lines, down if it's positive and up if it's negative.
+	/// Scrolling is clamped to the rows in the table at the next render.
+	pub fn scroll_by(&mut self, lines: isize) {
+		self.scroll = self.scroll.saturating_add_signed(lines);
+	}
+
+	/// Forces the next render to redraw the whole screen, e.g. if something else has written to it.
+	pub fn redraw(&mut self) {
+		self.screen = None;
+	}
+
+	/// Renders the table, returning what needs to be written to the terminal to show it.
+	pub fn render<const COLS: usize>(&mut self, table: &Table<COLS>) -> String {
+		if !self.capabilities.cursor_addressing {
+			return format!("{}\n", table);
+		}
+
+		let lines: Vec<String> = table.to_string().lines().map(ToOwned::to_owned).collect();
+		let header_lines = table.header_line_count().min(lines.len());
+		let footer_lines = table.footer_line_count().min(lines.len() - header_lines);
+		let (header, rest) = lines.split_at(header_lines);
+		let (body, footer) = rest.split_at(rest.len() - footer_lines);
+
+		let body_height = self.height.saturating_sub(header.len() + footer.len());
+		self.scroll = self.scroll.min(body.len().saturating_sub(body_height));
+
+		let screen: Vec<String> = header
+			.iter()
+			.chain(body.iter().skip(self.scroll).take(body_height))
+			.chain(footer.iter())
+			.take(self.height)
+			.cloned()
+			.collect();
+
+		let mut output = String::new();
+		let previous = match self.screen.take() {
+			Some(previous) => previous,
+			None => {
+				output.push_str(&ANSIEscapeSequence::EraseInDisplay(EraseInDisplay(2)).to_string());
+				Vec::new()
+			}
+		};
+
+		for (row, line) in screen.iter().enumerate() {
+			let old = previous.get(row).map(String::as_str).unwrap_or("");
+			if old != line {
+				output.push_str(&redraw_line(row, old, line));
+			}
+		}
+
+		// Clear the lines that the table no longer reaches.
+		for row in screen.len()..previous.len() {
+			output.push_str(&move_to(row, 0));
+			output.push_str(&ANSIEscapeSequence::EraseInLine(EraseInLine(2)).to_string());
+		}
+
+		// Park the cursor below the table, so that anything else written doesn't land in it.
+		output.push_str(&move_to(screen.len().min(self.height.saturating_sub(1)), 0));
+
+		self.screen = Some(screen);
+		output
+	}
+}
+
+/// Returns the escape sequence that moves the cursor to the given zero-indexed row and column.
+fn move_to(row: usize, column: usize) -> String {
+	let row = (row + 1).min(MAX_POSITION) as u8;
+	let column = (column + 1).min(MAX_POSITION) as u8;
+	ANSIEscapeSequence::CursorPosition(CursorPosition(row, column)).to_string()
+}
+
+/// Returns what needs to be written to turn the `old` line on the screen into the `new` one, rewriting only the span
+/// of characters between the first and last that differ.
+fn redraw_line(row: usize, old: &str, new: &str) -> String {
+	let old: Vec<char> = old.chars().collect();
+	let new: Vec<char> = new.chars().collect();
+
+	let mut start = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+	let mut end = new.len();
+	if old.len() == new.len() {
+		let suffix = old[start..]
+			.iter()
+			.rev()
+			.zip(new[start..].iter().rev())
+			.take_while(|(a, b)| a == b)
+			.count();
+		end -= suffix;
+	}
+
+	// Columns past the last one that the cursor can address can't be jumped to, so the line is rewritten from the
+	// start.
+	if start >= MAX_POSITION {
+		start = 0;
+	}
+
+	let mut output = move_to(row, start);
+	output.extend(&new[start..end]);
+	if old.len() > new.len() {
+		output.push_str(&ANSIEscapeSequence::EraseInLine(EraseInLine(0)).to_string());
+	}
+
+	output
+}
+
+#[cfg(test)]
+mod tests {
+	use escapes::Capabilities;
+
+	use super::WatchRenderer;
+	use crate::{Table, TableSetting};
+
+	fn table(rows: &[[&str; 2]]) -> Table<2> {
+		let mut table = Table::new_with_headers(["Name", "Age"]).with_setting(TableSetting::HeaderSeperator);
+		for row in rows {
+			table.add_row(*row);
+		}
+
+		table
+	}
+
+	#[test]
+	fn test_first_render() {
+		let mut renderer = WatchRenderer::new(24);
+		let output = renderer.render(&table(&[["Colin", "25"], ["John", "30"]]));
+		assert_eq!(
+			output,
+			"\x1b[2J\x1b[1;1HName  Age\x1b[2;1H---------\x1b[3;1HColin 25 \x1b[4;1HJohn  30 \x1b[5;1H"
+		);
+	}
+
+	#[test]
+	fn test_minimal_redraw() {
+		let mut renderer = WatchRenderer::new(24);
+		renderer.render(&table(&[["Colin", "25"], ["John", "30"]]));
+
+		// Nothing changed, so nothing is redrawn.
+		assert_eq!(renderer.render(&table(&[["Colin", "25"], ["John", "30"]])), "\x1b[5;1H");
+
+		// Only the changed cell is redrawn.
+		assert_eq!(
+			renderer.render(&table(&[["Colin", "26"], ["John", "30"]])),
+			"\x1b[3;8H6\x1b[5;1H"
+		);
+
+		// Removed rows are cleared.
+		assert_eq!(renderer.render(&table(&[["Colin", "26"]])), "\x1b[4;1H\x1b[2K\x1b[4;1H");
+	}
+
+	#[test]
+	fn test_scrolling() {
+		let rows = [["a", "1"], ["b", "2"], ["c", "3"], ["d", "4"]];
+		let mut renderer = WatchRenderer::new(4);
+		let output = renderer.render(&table(&rows));
+		assert!(output.contains("a    1"));
+		assert!(output.contains("b    2"));
+		assert!(!output.contains("c    3"));
+
+		// The header stays in place while the rows scroll beneath it.
+		renderer.scroll_by(10);
+		let output = renderer.render(&table(&rows));
+		assert_eq!(output, "\x1b[3;1Hc    3\x1b[4;1Hd    4\x1b[4;1H");
+
+		renderer.scroll_by(-10);
+		let output = renderer.render(&table(&rows));
+		assert_eq!(output, "\x1b[3;1Ha    1\x1b[4;1Hb    2\x1b[4;1H");
+	}
+
+	#[test]
+	fn test_dumb_terminal() {
+		let mut renderer = WatchRenderer::new(24).with_capabilities(Capabilities::DUMB);
+		renderer.render(&table(&[["Colin", "25"]]));
+		assert_eq!(
+			renderer.render(&table(&[["Colin", "25"]])),
+			"Name  Age\n---------\nColin 25 \n\n"
+		);
+	}
+}
@@ -8,10 +8,13 @@ use std::{
 use anyhow::anyhow;
 use bus::BusClient;
 use clap::{Arg, ArgAction, Command};
-use common::{obs::assemble_logger, qinit::mark_running};
+use common::{
+	glob::Glob,
+	obs::{self, assemble_logger},
+	qinit::mark_running,
+};
 use modprobe::load_module;
 use nix::sys::utsname::uname;
-use regex::Regex;
 use slog::error;
 use tokio::{
 	fs::File,
@@ -20,6 +23,9 @@ use tokio::{
 
 const BUS_TOPIC: &str = "udev_events";
 
+/// The filter on the events we subscribe to: only events for devices that have a module alias.
+const MODALIAS_FILTER: &str = "MODALIAS";
+
 #[tokio::main]
 async fn main() -> ExitCode {
 	let matches = Command::new("udev")
@@ -42,6 +48,7 @@ async fn main() -> ExitCode {
 		.get_matches();
 
 	let logger = assemble_logger(stderr());
+	obs::install_panic_hook();
 	let name = match uname() {
 		Ok(n) => n,
 		Err(e) => {
@@ -59,7 +66,14 @@ async fn main() -> ExitCode {
 	let topic = matches
 		.get_one::<String>("topic")
 		.expect("missing topic, even though it has a default");
-	let mut bus_socket = match BusClient::new().await.unwrap().subscribe(topic).await {
+	// Replay the retained events, so we load modules for devices that were added before we started. Only events with
+	// a MODALIAS can need a module, so busd doesn't have to wake us for the rest.
+	let bus_socket = BusClient::new()
+		.await
+		.unwrap()
+		.subscribe_filtered(topic, true, Some(MODALIAS_FILTER))
+		.await;
+	let mut bus_socket = match bus_socket {
 		Ok(s) => s,
 		Err(e) => {
 			error!(logger, "failed to open bus connection"; "error" => e.to_string());
@@ -77,9 +91,11 @@ async fn main() -> ExitCode {
 
 	mark_running().expect("failed to mark udev as running");
 
-	while let Ok(line) = bus_socket.read_message().await {
-		if let Ok(line) = String::from_utf8(line) {
-			let event = match serde_json::from_str::<HashMap<String, String>>(&line) {
+	// Every event is read into the same buffer, so that it doesn't need to be allocated again each time.
+	let mut message = Vec::new();
+	while bus_socket.read_message_into(&mut message).await.is_ok() {
+		if let Ok(line) = std::str::from_utf8(&message) {
+			let event = match serde_json::from_str::<HashMap<String, String>>(line) {
 				Ok(map) => map,
 				Err(e) => {
 					error!(logger, "failed to parse hashmap from message"; "msg" => line, "error" => e.to_string());
@@ -101,7 +117,7 @@ async fn main() -> ExitCode {
 }
 
 struct ModuleLoader {
-	aliases: Vec<(Regex, String)>,
+	aliases: Vec<(Glob, String)>,
 }
 
 impl ModuleLoader {
@@ -130,15 +146,7 @@ impl ModuleLoader {
 				continue;
 			}
 
-			let regex = match glob_to_regex(parts[1]) {
-				Ok(r) => r,
-				Err(e) => {
-					error!(logger, "failed to tranlate glob into regex"; "glob" => parts[1], "error" => e.to_string());
-					continue;
-				}
-			};
-
-			aliases.push((regex, parts[2].to_owned()));
+			aliases.push((Glob::new(parts[1]), parts[2].to_owned()));
 		}
 
 		Ok(Self { aliases })
@@ -147,17 +155,8 @@ impl ModuleLoader {
 	fn get_modules_for_device(&self, device: &str) -> Vec<&str> {
 		self.aliases
 			.iter()
-			.filter(|(r, _)| r.is_match(device))
+			.filter(|(glob, _)| glob.matches(device))
 			.map(|(_, s)| s.as_ref())
 			.collect()
 	}
 }
-
-/// mod alias's come in the form of globs, which Rust doesn't have a decent
-/// library to evaluate. This translates the glob into a regex that is a bit easier to work with,
-/// if not a bit slower.
-fn glob_to_regex(s: &str) -> Result<Regex, regex::Error> {
-	let regex = s.replace('*', ".*");
-	let regex = regex.replace('?', ".");
-	Regex::new(&format!("^{}$", regex))
-}
@@ -0,0 +1,245 @@
+use std::{
+	collections::HashMap,
+	io,
+	path::{Path, PathBuf},
+};
+
+use tokio::fs::{read_dir, read_link, read_to_string};
+
+/// The root of the sysfs device tree.
+pub const SYSFS_ROOT: &str = "/sys";
+
+/// The directory that device nodes (and symlinks to them) live in.
+const DEV_ROOT: &str = "/dev";
+
+/// The directories under `DEV_ROOT` that hold symlinks to device nodes, e.g. `/dev/disk/by-uuid`. Only these are
+/// searched for DEVLINKS, as walking all of /dev for every event is slow on machines with lots of devices.
+const DEVLINK_DIRECTORIES: [&str; 4] = ["disk", "block", "char", "serial"];
+
+/// Properties that describe a specific device, and so should never be inherited from an ancestor.
+const DEVICE_SPECIFIC_KEYS: &[&str] = &[
+	"summary",
+	"ACTION",
+	"DEVPATH",
+	"DEVNAME",
+	"DEVTYPE",
+	"DEVMODE",
+	"DEVLINKS",
+	"DRIVER",
+	"IFINDEX",
+	"INTERFACE",
+	"MAJOR",
+	"MINOR",
+	"MODALIAS",
+	"SEQNUM",
+	"SUBSYSTEM",
+];
+
+/// Sysfs attributes of USB devices, and the properties they are exposed as on their descendants.
+const USB_ATTRIBUTES: &[(&str, &str)] = &[
+	("idVendor", "ID_VENDOR_ID"),
+	("idProduct", "ID_MODEL_ID"),
+	("manufacturer", "ID_VENDOR"),
+	("product", "ID_MODEL"),
+	("serial", "ID_SERIAL_SHORT"),
+];
+
+/// Adds properties to the event that libudev consumers expect, but that the kernel doesn't send:
+/// properties inherited from the devices ancestors, and the DEVLINKS pointing at its device node.
+pub async fn enrich_event(event: &mut HashMap<String, String>) -> io::Result<()> {
+	if event.get("ACTION").is_some_and(|action| action == "remove") {
+		// The device is already gone from sysfs, so there's nothing to read.
+		return Ok(());
+	}
+
+	if let Some(devpath) = event.get("DEVPATH").cloned() {
+		inherit_parent_properties(event, &devpath).await?;
+	}
+
+	if let Some(devname) = event.get("DEVNAME").cloned() {
+		let dev_root = Path::new(DEV_ROOT);
+		let links = find_devlinks(dev_root, &dev_root.join(devname)).await?;
+		if !links.is_empty() {
+			event.insert(String::from("DEVLINKS"), links.join(" "));
+		}
+	}
+
+	Ok(())
+}
+
+/// Walks up the sysfs tree from the given device, adding the properties of each ancestor device that aren't already
+/// set on the event. Nearer ancestors take precedence over further ones.
+async fn inherit_parent_properties(event: &mut HashMap<String, String>, devpath: &str) -> io::Result<()> {
+	let device = PathBuf::from(format!("{}{}", SYSFS_ROOT, devpath));
+	for parent in device.ancestors().skip(1) {
+		if parent == Path::new(SYSFS_ROOT) {
+			break;
+		}
+
+		let properties = match read_uevent(parent).await? {
+			Some(properties) => properties,
+			// Not every directory in the chain is a device (e.g. `tty` in `.../ttyUSB0/tty/ttyUSB0`).
+			None => continue,
+		};
+
+		if properties.get("DEVTYPE").is_some_and(|t| t == "usb_device") {
+			for (attribute, key) in USB_ATTRIBUTES {
+				if event.contains_key(*key) {
+					continue;
+				}
+
+				if let Ok(value) = read_to_string(parent.join(attribute)).await {
+					event.insert(key.to_string(), value.trim().to_owned());
+				}
+			}
+		}
+
+		for (key, value) in properties {
+			if DEVICE_SPECIFIC_KEYS.contains(&key.as_str()) || event.contains_key(&key) {
+				continue;
+			}
+
+			event.insert(key, value);
+		}
+	}
+
+	Ok(())
+}
+
+/// Reads the properties from the `uevent` file in the given sysfs directory, returning None if the directory isn't a
+/// device.
+async fn read_uevent(path: &Path) -> io::Result<Option<HashMap<String, String>>> {
+	let contents = match read_to_string(path.join("uevent")).await {
+		Ok(contents) => contents,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+		Err(e) if e.kind() == io::ErrorKind::PermissionDenied => return Ok(None),
+		Err(e) => return Err(e),
+	};
+
+	Ok(Some(
+		contents
+			.lines()
+			.filter_map(|line| line.split_once('='))
+			.map(|(k, v)| (k.to_owned(), v.to_owned()))
+			.collect(),
+	))
+}
+
+/// Finds all the symlinks in the `DEVLINK_DIRECTORIES` under the given dev root that point at the given device node.
+async fn find_devlinks(dev_root: &Path, node: &Path) -> io::Result<Vec<String>> {
+	let mut links = Vec::new();
+	let mut queue: Vec<PathBuf> = DEVLINK_DIRECTORIES.iter().map(|dir| dev_root.join(dir)).collect();
+	while let Some(dir) = queue.pop() {
+		let mut entries = match read_dir(&dir).await {
+			Ok(entries) => entries,
+			Err(_) => continue,
+		};
+
+		while let Some(entry) = entries.next_entry().await? {
+			let path = entry.path();
+			let file_type = entry.file_type().await?;
+			if file_type.is_dir() {
+				queue.push(path);
+			} else if file_type.is_symlink() {
+				let target = match read_link(&path).await {
+					Ok(target) => target,
+					Err(_) => continue,
+				};
+
+				// Links are usually relative, e.g. /dev/disk/by-uuid/abcd -> ../../sda1.
+				let target = normalize(&path.parent().unwrap_or(dev_root).join(target));
+				if target == node {
+					links.push(path.display().to_string());
+				}
+			}
+		}
+	}
+
+	links.sort();
+	Ok(links)
+}
+
+/// Lexically normalizes the given path, resolving `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+	let mut out = PathBuf::new();
+	for component in path.components() {
+		match component {
+			std::path::Component::CurDir => {}
+			std::path::Component::ParentDir => {
+				out.pop();
+			}
+			c => out.push(c),
+		}
+	}
+
+	out
+}
+
+#[cfg(test)]
+mod test {
+	use std::{
+		collections::HashMap,
+		fs::{create_dir_all, write},
+		os::unix::fs::symlink,
+		path::{Path, PathBuf},
+	};
+
+	use common::testing::TempDir;
+
+	use super::{find_devlinks, normalize, read_uevent};
+
+	#[test]
+	fn test_normalize() {
+		assert_eq!(
+			normalize(Path::new("/dev/disk/by-uuid/../../sda1")),
+			PathBuf::from("/dev/sda1")
+		);
+		assert_eq!(normalize(Path::new("/dev/./block/../sda")), PathBuf::from("/dev/sda"));
+		assert_eq!(normalize(Path::new("/dev/../../sda")), PathBuf::from("/sda"));
+	}
+
+	#[tokio::test]
+	async fn test_read_uevent() {
+		let sysfs = TempDir::new("udevd-sysfs").unwrap();
+		let device = sysfs.join("devices/pci0000:00/usb1/1-1/ttyUSB0");
+		create_dir_all(device.join("tty/ttyUSB0")).unwrap();
+		write(
+			device.join("uevent"),
+			"DEVTYPE=usb_interface\nDRIVER=ftdi_sio\nMODALIAS=usb:v0403p6001\nnot a property\n",
+		)
+		.unwrap();
+
+		assert_eq!(
+			read_uevent(&device).await.unwrap(),
+			Some(HashMap::from([
+				(String::from("DEVTYPE"), String::from("usb_interface")),
+				(String::from("DRIVER"), String::from("ftdi_sio")),
+				(String::from("MODALIAS"), String::from("usb:v0403p6001")),
+			]))
+		);
+
+		// Directories in the chain that aren't devices don't have a uevent file.
+		assert_eq!(read_uevent(&device.join("tty")).await.unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn test_find_devlinks() {
+		let dev = TempDir::new("udevd-dev").unwrap();
+		create_dir_all(dev.join("disk/by-uuid")).unwrap();
+		create_dir_all(dev.join("block")).unwrap();
+		write(dev.join("sda1"), "").unwrap();
+		symlink("../../sda1", dev.join("disk/by-uuid/abcd")).unwrap();
+		symlink("../sda1", dev.join("block/8:1")).unwrap();
+		symlink("sda1", dev.join("root")).unwrap();
+		symlink("../../sda2", dev.join("disk/by-uuid/efgh")).unwrap();
+
+		// Links outside of the link directories aren't looked for.
+		assert_eq!(
+			find_devlinks(&dev, &dev.join("sda1")).await.unwrap(),
+			vec![
+				dev.join("block/8:1").display().to_string(),
+				dev.join("disk/by-uuid/abcd").display().to_string()
+			]
+		);
+	}
+}
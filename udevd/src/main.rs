@@ -1,37 +1,105 @@
+mod enrich;
+mod rename;
+mod watch;
+
 use std::{
 	collections::{HashMap, VecDeque},
+	fs::create_dir_all,
 	io::{self, stderr},
-	path::Path,
+	path::{Path, PathBuf},
+	sync::Arc,
 };
 
 use bus::{BusClient, PublishHook};
+use common::{config::ConfigSource, obs};
+use enrich::{enrich_event, SYSFS_ROOT};
+use modprobe::{load_static_nodes, DeviceNodeKind};
 use netlink::{AsyncNetlinkSocket, NetlinkKObjectUEvent, UEventNetlinkGroups};
-use slog::{error, info};
+use nix::{
+	errno::Errno,
+	sys::{
+		stat::{makedev, mknod, Mode, SFlag},
+		utsname::uname,
+	},
+};
+use rename::{NamingRules, Renamer, DEFAULT_NAMING_RULES_PATH};
+use slog::{debug, error, info, o, warn};
 use tokio::{
 	fs::{read_dir, OpenOptions},
 	io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+	sync::Mutex,
+	time::interval,
 };
+use watch::{WatchRules, Watcher, DEFAULT_WATCH_RULES_PATH};
 
 const BUSD_TOPIC: &str = "udev_events";
 
+// The number of events that busd retains, so that listeners that start after us still see the initial device adds.
+const RETAINED_EVENTS: usize = 4096;
+
+// The event that busd publishes if we go away, so that listeners know that they may miss events until we're back.
+const EXIT_EVENT: &str = r#"{"summary":"udevd exited","ACTION":"udevd-exit"}"#;
+
+// The mode of the device nodes that are created before their modules are loaded.
+const STATIC_NODE_MODE: u32 = 0o600;
+
 // The presence of the SEQ_NUM_KEY KV indicates the end of a single event.
 const SEQ_NUM_KEY: &str = "SEQNUM";
 
 #[tokio::main]
 async fn main() {
-	let logger = common::obs::assemble_logger(stderr());
+	let logger = obs::assemble_logger(stderr());
 	let socket = AsyncNetlinkSocket::<NetlinkKObjectUEvent>::new(UEventNetlinkGroups::UEvents).unwrap();
 
-	let bus_socket = BusClient::new().await.unwrap().publish(BUSD_TOPIC).await.unwrap();
+	let bus_socket = BusClient::new()
+		.await
+		.unwrap()
+		.publish_with_will(BUSD_TOPIC, RETAINED_EVENTS, EXIT_EVENT.as_bytes())
+		.await
+		.unwrap();
+	let output = Arc::new(Mutex::new(bus_socket));
 
-	let el_logger = logger.clone();
-	let hook = tokio::spawn(async move {
-		if let Err(e) = event_loop(&el_logger, socket, bus_socket).await {
-			error!(el_logger, "Error in event loop"; "error" => e.to_string());
+	let rules = match ConfigSource::new(DEFAULT_WATCH_RULES_PATH).load::<WatchRules>() {
+		Ok(rules) => rules,
+		Err(e) => {
+			error!(logger, "Failed to load watch rules, so no attributes will be watched"; "error" => e.to_string());
+			WatchRules::default()
+		}
+	};
+	let watcher = Arc::new(Mutex::new(Watcher::new(rules, SYSFS_ROOT)));
+
+	let naming_rules = match ConfigSource::new(DEFAULT_NAMING_RULES_PATH).load::<NamingRules>() {
+		Ok(rules) => rules,
+		Err(e) => {
+			error!(logger, "Failed to load naming rules, so network interfaces won't be renamed"; "error" => e.to_string());
+			NamingRules::default()
+		}
+	};
+	let renamer = Renamer::new(naming_rules, SYSFS_ROOT);
+
+	if !watcher.lock().await.is_empty() {
+		let (watcher, output) = (watcher.clone(), output.clone());
+		obs::spawn(o!("task" => "watch_loop"), async move {
+			let logger = obs::current();
+			if let Err(e) = watch_loop(&logger, watcher, output).await {
+				error!(logger, "Error in watch loop"; "error" => e.to_string());
+			}
+		});
+	}
+
+	let hook = obs::spawn(o!("task" => "event_loop"), async move {
+		let logger = obs::current();
+		if let Err(e) = event_loop(&logger, socket, renamer, watcher, output).await {
+			error!(logger, "Error in event loop"; "error" => e.to_string());
 		}
 	});
 
 	info!(logger, "Starting udevd");
+	match create_static_nodes(&logger) {
+		Ok(node_count) => info!(logger, "Created static device nodes"; "node_count" => node_count),
+		Err(e) => warn!(logger, "Failed to create static device nodes"; "error" => e.to_string()),
+	}
+
 	let device_count = match do_initial_device_add(&logger).await {
 		Ok(device_count) => device_count,
 		Err(e) => {
@@ -50,7 +118,9 @@ async fn main() {
 async fn event_loop<T: AsyncWrite + Unpin>(
 	logger: &slog::Logger,
 	socket: AsyncNetlinkSocket<NetlinkKObjectUEvent>,
-	mut output: PublishHook<T>,
+	renamer: Renamer,
+	watcher: Arc<Mutex<Watcher>>,
+	output: Arc<Mutex<PublishHook<T>>>,
 ) -> io::Result<()> {
 	let reader = BufReader::new(socket);
 	let mut segments = reader.split(b'\0');
@@ -91,6 +161,20 @@ async fn event_loop<T: AsyncWrite + Unpin>(
 		current_event.insert(key.to_owned(), value.to_owned());
 		if key == SEQ_NUM_KEY {
 			// SEQNUM is always the last key of an event, so flush it.
+			match renamer.handle_event(&mut current_event) {
+				Ok(Some(name)) => info!(logger, "Renamed network interface"; "name" => name),
+				Ok(None) => {}
+				Err(e) => {
+					error!(logger, "Failed to rename network interface"; "interface" => current_event.get("INTERFACE"), "error" => e.to_string())
+				}
+			}
+
+			if let Err(e) = enrich_event(&mut current_event).await {
+				error!(logger, "failed to enrich event"; "error" => e.to_string());
+			}
+
+			watcher.lock().await.handle_event(&current_event);
+
 			let output_event = match serde_json::to_string(&current_event) {
 				Ok(o) => o,
 				Err(e) => {
@@ -101,13 +185,87 @@ async fn event_loop<T: AsyncWrite + Unpin>(
 			};
 			current_event.clear();
 
-			output.publish_message(output_event.as_bytes()).await?;
+			output.lock().await.publish_message(output_event.as_bytes()).await?;
 		}
 	}
 
 	Ok(())
 }
 
+// Reads the watched sysfs attributes every interval, publishing a change event for each device whose attributes have
+// changed, so that consumers stay in sync with changes that the kernel doesn't send events for.
+async fn watch_loop<T: AsyncWrite + Unpin>(
+	logger: &slog::Logger,
+	watcher: Arc<Mutex<Watcher>>,
+	output: Arc<Mutex<PublishHook<T>>>,
+) -> io::Result<()> {
+	let mut ticker = interval(watcher.lock().await.interval());
+	loop {
+		ticker.tick().await;
+
+		let events = watcher.lock().await.poll();
+		for mut event in events {
+			debug!(logger, "Watched attributes changed"; "devpath" => event.get("DEVPATH"), "attributes" => event.get("CHANGED_ATTRIBUTES"));
+			if let Err(e) = enrich_event(&mut event).await {
+				error!(logger, "failed to enrich event"; "error" => e.to_string());
+			}
+
+			let output_event = match serde_json::to_string(&event) {
+				Ok(o) => o,
+				Err(e) => {
+					error!(logger, "failed to construct event"; "error" => e.to_string());
+					continue;
+				}
+			};
+
+			output.lock().await.publish_message(output_event.as_bytes()).await?;
+		}
+	}
+}
+
+// Creates the device nodes listed in modules.devname, for modules that haven't been loaded yet. Opening one of these
+// nodes makes the kernel load the module that provides it (e.g. /dev/fuse), which it wouldn't otherwise know to do,
+// as there's no device to send an event for until the module is loaded.
+fn create_static_nodes(logger: &slog::Logger) -> io::Result<usize> {
+	let release = uname()?.release().to_string_lossy().into_owned();
+	let devname_path = PathBuf::from("/lib/modules").join(release).join("modules.devname");
+	let mut node_count = 0;
+
+	for node in load_static_nodes(logger, &devname_path)? {
+		let path = Path::new("/dev").join(&node.devname);
+		if path.exists() {
+			continue;
+		}
+
+		if let Some(parent) = path.parent() {
+			create_dir_all(parent)?;
+		}
+
+		let kind = match node.kind {
+			DeviceNodeKind::Char => SFlag::S_IFCHR,
+			DeviceNodeKind::Block => SFlag::S_IFBLK,
+		};
+
+		match mknod(
+			&path,
+			kind,
+			Mode::from_bits_truncate(STATIC_NODE_MODE),
+			makedev(node.major as u64, node.minor as u64),
+		) {
+			// The module may have been loaded, and created the node, since we checked.
+			Ok(()) | Err(Errno::EEXIST) => {
+				debug!(logger, "Created static device node"; "path" => path.display(), "module" => node.module);
+				node_count += 1;
+			}
+			Err(e) => {
+				error!(logger, "Failed to create static device node"; "path" => path.display(), "error" => e.to_string())
+			}
+		}
+	}
+
+	Ok(node_count)
+}
+
 // This function is called when the udevd daemon starts up. It is responsible for
 // scanning the /sys directory and adding all devices that are already present.
 // This is done by calling the `add_device` function for each device.
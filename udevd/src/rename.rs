@@ -0,0 +1,342 @@
+use std::{collections::HashMap, fs, io, path::PathBuf};
+
+use netlink::{
+	rtnetlink::{Interface, InterfaceAttributes, InterfaceInfoMessage, NetlinkRoute, RTNetlink, RTNetlinkGroups},
+	NetlinkSocket,
+};
+use serde::Deserialize;
+
+/// The default path of the naming rules for network interfaces.
+pub const DEFAULT_NAMING_RULES_PATH: &str = "/etc/udevd/net.toml";
+
+/// The hardware type (in the sysfs `type` attribute) of ethernet-like interfaces, including wireless ones.
+const ARPHRD_ETHER: &str = "1";
+
+/// The longest name that an interface can have, not including the null terminator.
+const MAX_INTERFACE_NAME_LENGTH: usize = 15;
+
+/// How interfaces that don't have a name given in the mappings are named.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum NamingPolicy {
+	/// Interfaces keep the names the kernel gave them, e.g. `eth0`.
+	#[default]
+	Kernel,
+
+	/// Interfaces are named after their MAC address, e.g. `enx525400123456`.
+	Mac,
+
+	/// Interfaces are named after where they are on the PCI bus, e.g. `enp0s3`, which stays the same as long as
+	/// the hardware doesn't move, even if devices are probed in a different order. Interfaces that aren't on the PCI
+	/// bus keep their names.
+	Path,
+}
+
+/// A name to give the interface that matches it. Every condition that's given has to match, and at least one has to
+/// be given.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct NameMapping {
+	/// The MAC address of the interface, e.g. `52:54:00:12:34:56`.
+	pub mac: Option<String>,
+
+	/// The PCI address of the device that the interface is on, e.g. `0000:00:03.0`.
+	pub pci: Option<String>,
+
+	/// The name to give the interface.
+	pub name: String,
+}
+
+impl NameMapping {
+	fn matches(&self, link: &Link) -> bool {
+		if self.mac.is_none() && self.pci.is_none() {
+			return false;
+		}
+
+		let mac = match &self.mac {
+			Some(mac) => link
+				.mac
+				.as_deref()
+				.is_some_and(|link_mac| link_mac.eq_ignore_ascii_case(mac)),
+			None => true,
+		};
+
+		let pci = match &self.pci {
+			Some(pci) => link.pci_address() == Some(pci.as_str()),
+			None => true,
+		};
+
+		mac && pci
+	}
+}
+
+/// The rules for naming network interfaces as they're added. Interfaces that match a mapping get the name it gives,
+/// and the rest are named by the policy.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NamingRules {
+	#[serde(default)]
+	pub policy: NamingPolicy,
+
+	#[serde(default, rename = "link")]
+	pub mappings: Vec<NameMapping>,
+}
+
+/// What's known about an interface that's been added, from its event and its attributes in sysfs.
+struct Link<'a> {
+	devpath: &'a str,
+	devtype: Option<&'a str>,
+
+	/// The hardware type of the interface, e.g. `1` for ethernet.
+	hardware_type: Option<String>,
+	mac: Option<String>,
+}
+
+impl Link<'_> {
+	/// The PCI address of the device that the interface is on, which is the last one in its path, e.g. `0000:00:03.0`
+	/// for `/devices/pci0000:00/0000:00:03.0/virtio0/net/eth0`.
+	fn pci_address(&self) -> Option<&str> {
+		self.devpath
+			.rsplit('/')
+			.find(|component| parse_pci_address(component).is_some())
+	}
+
+	/// The prefix of the predictable names of the interface, for the kind of network that it's on.
+	fn prefix(&self) -> &'static str {
+		match self.devtype {
+			Some("wlan") => "wl",
+			Some("wwan") => "ww",
+			_ => "en",
+		}
+	}
+}
+
+/// Splits a PCI address, e.g. `0000:00:1c.2`, into its domain, bus, slot, and function.
+fn parse_pci_address(address: &str) -> Option<(u32, u32, u32, u32)> {
+	let (domain, rest) = address.split_once(':')?;
+	let (bus, rest) = rest.split_once(':')?;
+	let (slot, function) = rest.split_once('.')?;
+	if domain.len() != 4 || bus.len() != 2 || slot.len() != 2 || function.len() != 1 {
+		return None;
+	}
+
+	Some((
+		u32::from_str_radix(domain, 16).ok()?,
+		u32::from_str_radix(bus, 16).ok()?,
+		u32::from_str_radix(slot, 16).ok()?,
+		u32::from_str_radix(function, 16).ok()?,
+	))
+}
+
+impl NamingRules {
+	/// The name that the interface should have, or None if it should keep the name it has.
+	fn name_for(&self, link: &Link) -> Option<String> {
+		if let Some(mapping) = self.mappings.iter().find(|mapping| mapping.matches(link)) {
+			return Some(mapping.name.clone());
+		}
+
+		// Only physical ethernet-like interfaces have anything to be named after. Virtual ones are named by whoever
+		// created them.
+		if link.devpath.starts_with("/devices/virtual/") || link.hardware_type.as_deref() != Some(ARPHRD_ETHER) {
+			return None;
+		}
+
+		let name = match self.policy {
+			NamingPolicy::Kernel => return None,
+			NamingPolicy::Mac => {
+				let mac = link.mac.as_deref()?.replace(':', "").to_ascii_lowercase();
+				if mac.chars().all(|c| c == '0') {
+					return None;
+				}
+
+				format!("{}x{}", link.prefix(), mac)
+			}
+			NamingPolicy::Path => {
+				let (domain, bus, slot, function) = parse_pci_address(link.pci_address()?)?;
+				let mut name = String::from(link.prefix());
+				if domain != 0 {
+					name.push_str(&format!("P{}", domain));
+				}
+
+				name.push_str(&format!("p{}s{}", bus, slot));
+				if function != 0 {
+					name.push_str(&format!("f{}", function));
+				}
+
+				name
+			}
+		};
+
+		Some(name)
+	}
+}
+
+/// Renames network interfaces as they're added, by the naming rules.
+pub struct Renamer {
+	rules: NamingRules,
+
+	/// The root of the sysfs tree that DEVPATHs are relative to.
+	sysfs_root: PathBuf,
+}
+
+impl Renamer {
+	pub fn new<P: Into<PathBuf>>(rules: NamingRules, sysfs_root: P) -> Self {
+		Self {
+			rules,
+			sysfs_root: sysfs_root.into(),
+		}
+	}
+
+	/// Whether there are any rules that could rename an interface.
+	pub fn is_empty(&self) -> bool {
+		self.rules.policy == NamingPolicy::Kernel && self.rules.mappings.is_empty()
+	}
+
+	/// Renames the interface that the event is for if it has just been added and the rules give it a new name,
+	/// updating the event to match. Returns the new name, if it was renamed.
+	pub fn handle_event(&self, event: &mut HashMap<String, String>) -> io::Result<Option<String>> {
+		let is_net_add = event.get("ACTION").is_some_and(|action| action == "add")
+			&& event.get("SUBSYSTEM").is_some_and(|subsystem| subsystem == "net");
+		if self.is_empty() || !is_net_add {
+			return Ok(None);
+		}
+
+		let (Some(devpath), Some(interface), Some(index)) =
+			(event.get("DEVPATH"), event.get("INTERFACE"), event.get("IFINDEX"))
+		else {
+			return Ok(None);
+		};
+
+		let device_path = self.sysfs_root.join(devpath.trim_start_matches('/'));
+		let read = |attribute: &str| {
+			fs::read_to_string(device_path.join(attribute))
+				.ok()
+				.map(|value| value.trim().to_owned())
+		};
+
+		let link = Link {
+			devpath,
+			devtype: event.get("DEVTYPE").map(String::as_str),
+			hardware_type: read("type"),
+			mac: read("address"),
+		};
+
+		let name = match self.rules.name_for(&link) {
+			Some(name) if name != *interface => name,
+			_ => return Ok(None),
+		};
+
+		if name.is_empty() || name.len() > MAX_INTERFACE_NAME_LENGTH || name.contains(['/', ' ']) {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("invalid interface name: {}", name),
+			));
+		}
+
+		let index = index
+			.parse()
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("invalid IFINDEX: {}", index)))?;
+		rename_link(index, &name)?;
+
+		// The kernel announces the rename with a move event too, but the add event should already have the name that
+		// the interface ends up with.
+		let devpath = match devpath.rsplit_once('/') {
+			Some((parent, _)) => format!("{}/{}", parent, name),
+			None => devpath.clone(),
+		};
+		event.insert(String::from("DEVPATH"), devpath);
+		event.insert(String::from("INTERFACE"), name.clone());
+
+		Ok(Some(name))
+	}
+}
+
+/// Sets the name of the link with the given index.
+fn rename_link(index: i32, name: &str) -> io::Result<()> {
+	let mut socket = NetlinkSocket::<NetlinkRoute>::new(RTNetlinkGroups::RTMGRP_NONE)?;
+	let message = InterfaceInfoMessage::empty();
+	let mut attributes = InterfaceAttributes::default();
+	attributes.name = Some(name.to_owned());
+	let link = Interface {
+		family: message.family,
+		ty: message.ty,
+		index,
+		flags: message.flags,
+		// Leave the flags alone.
+		change: 0,
+		attributes,
+	};
+
+	socket.new_link(link).map_err(|e| match e {
+		netlink::NetlinkError::IOError(e) => e,
+		netlink::NetlinkError::NetlinkError(errno, _) => io::Error::from(errno),
+		netlink::NetlinkError::Timeout(attempts) => io::Error::new(
+			io::ErrorKind::TimedOut,
+			format!("timed out renaming the link after {} attempts", attempts),
+		),
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Link, NameMapping, NamingPolicy, NamingRules};
+
+	fn link<'a>(devpath: &'a str, mac: &str) -> Link<'a> {
+		Link {
+			devpath,
+			devtype: None,
+			hardware_type: Some(String::from("1")),
+			mac: Some(mac.to_owned()),
+		}
+	}
+
+	#[test]
+	fn test_name_for() {
+		let virtio = link("/devices/pci0000:00/0000:00:03.0/virtio0/net/eth0", "52:54:00:12:34:56");
+		let bridged = link(
+			"/devices/pci0000:00/0000:00:1c.0/0002:03:00.1/net/eth1",
+			"00:00:00:00:00:00",
+		);
+		let virtual_link = link("/devices/virtual/net/br0", "52:54:00:ab:cd:ef");
+
+		let mut rules = NamingRules::default();
+		assert_eq!(rules.name_for(&virtio), None);
+
+		rules.policy = NamingPolicy::Path;
+		assert_eq!(rules.name_for(&virtio).as_deref(), Some("enp0s3"));
+		assert_eq!(rules.name_for(&bridged).as_deref(), Some("enP2p3s0f1"));
+		assert_eq!(rules.name_for(&virtual_link), None);
+
+		rules.policy = NamingPolicy::Mac;
+		assert_eq!(rules.name_for(&virtio).as_deref(), Some("enx525400123456"));
+		assert_eq!(rules.name_for(&bridged), None);
+
+		let wireless = Link {
+			devtype: Some("wlan"),
+			..link("/devices/pci0000:00/0000:00:14.3/net/wlan0", "52:54:00:AA:BB:CC")
+		};
+		assert_eq!(rules.name_for(&wireless).as_deref(), Some("wlx525400aabbcc"));
+
+		// Mappings take precedence over the policy, and can name virtual interfaces too.
+		rules.mappings = vec![
+			NameMapping {
+				mac: Some(String::from("52:54:00:AB:CD:EF")),
+				pci: None,
+				name: String::from("uplink"),
+			},
+			NameMapping {
+				mac: None,
+				pci: Some(String::from("0000:00:03.0")),
+				name: String::from("lan0"),
+			},
+			NameMapping {
+				mac: None,
+				pci: None,
+				name: String::from("everything"),
+			},
+		];
+		assert_eq!(rules.name_for(&virtual_link).as_deref(), Some("uplink"));
+		assert_eq!(rules.name_for(&virtio).as_deref(), Some("lan0"));
+		assert_eq!(rules.name_for(&bridged), None);
+	}
+}
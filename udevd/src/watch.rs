@@ -0,0 +1,296 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	fs,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use common::glob::Glob;
+use serde::Deserialize;
+
+/// The default path of the watch rules. Drop-ins that add rules to it are read from `/etc/udevd/watch.d`.
+pub const DEFAULT_WATCH_RULES_PATH: &str = "/etc/udevd/watch.toml";
+
+/// How often watched attributes are read, unless the rules say otherwise.
+const DEFAULT_INTERVAL_MS: u64 = 2000;
+
+/// The properties of a device that are copied into the change events synthesized for it, so that consumers can tell
+/// which device changed without looking it up.
+const IDENTIFYING_KEYS: &[&str] = &[
+	"DEVPATH",
+	"SUBSYSTEM",
+	"DEVTYPE",
+	"DEVNAME",
+	"MAJOR",
+	"MINOR",
+	"IFINDEX",
+];
+
+fn default_interval_ms() -> u64 {
+	DEFAULT_INTERVAL_MS
+}
+
+/// A rule that watches attributes of the devices that match it. Every condition that's given has to match, and can
+/// be a glob.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WatchRule {
+	/// The SUBSYSTEM of the devices, e.g. `block`.
+	pub subsystem: Option<String>,
+
+	/// The DEVTYPE of the devices, e.g. `disk`.
+	pub devtype: Option<String>,
+
+	/// The kernel's name for the devices, i.e. the last part of their DEVPATH, e.g. `sd*`.
+	pub kernel: Option<String>,
+
+	/// The sysfs attributes to watch, relative to the device's directory, e.g. `size` or `queue/rotational`.
+	pub attributes: Vec<String>,
+}
+
+impl WatchRule {
+	fn matches(&self, event: &HashMap<String, String>) -> bool {
+		let kernel = event
+			.get("DEVPATH")
+			.and_then(|devpath| devpath.rsplit('/').next())
+			.map(str::to_owned);
+		[
+			(&self.subsystem, event.get("SUBSYSTEM").cloned()),
+			(&self.devtype, event.get("DEVTYPE").cloned()),
+			(&self.kernel, kernel),
+		]
+		.into_iter()
+		.all(|(pattern, value)| match pattern {
+			Some(pattern) => value.is_some_and(|value| Glob::new(pattern).matches(&value)),
+			None => true,
+		})
+	}
+}
+
+/// The rules for which sysfs attributes are watched. Most sysfs attributes don't support inotify, so watched
+/// attributes are read every `interval_ms`, and a change event is synthesized for a device when any of them change.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WatchRules {
+	#[serde(default = "default_interval_ms")]
+	pub interval_ms: u64,
+
+	#[serde(default, rename = "watch")]
+	pub rules: Vec<WatchRule>,
+}
+
+impl Default for WatchRules {
+	fn default() -> Self {
+		Self {
+			interval_ms: DEFAULT_INTERVAL_MS,
+			rules: Vec::new(),
+		}
+	}
+}
+
+impl WatchRules {
+	pub fn interval(&self) -> Duration {
+		Duration::from_millis(self.interval_ms)
+	}
+}
+
+/// A device with watched attributes.
+struct WatchedDevice {
+	/// The properties from the device's last event that identify it.
+	properties: BTreeMap<String, String>,
+
+	/// The last value of each watched attribute, or None if it couldn't be read.
+	values: BTreeMap<String, Option<Vec<u8>>>,
+}
+
+/// Keeps track of the devices that match the watch rules, from the events the kernel sends for them, and the values
+/// of their watched attributes.
+pub struct Watcher {
+	rules: WatchRules,
+
+	/// The root of the sysfs tree that DEVPATHs are relative to.
+	sysfs_root: PathBuf,
+
+	/// The watched devices, by DEVPATH.
+	devices: HashMap<String, WatchedDevice>,
+}
+
+impl Watcher {
+	pub fn new<P: Into<PathBuf>>(rules: WatchRules, sysfs_root: P) -> Self {
+		Self {
+			rules,
+			sysfs_root: sysfs_root.into(),
+			devices: HashMap::new(),
+		}
+	}
+
+	/// Starts or stops watching the device that the kernel event is for.
+	pub fn handle_event(&mut self, event: &HashMap<String, String>) {
+		let Some(devpath) = event.get("DEVPATH") else {
+			return;
+		};
+
+		if let Some(old_devpath) = event.get("DEVPATH_OLD") {
+			self.devices.remove(old_devpath);
+		}
+
+		if event.get("ACTION").is_some_and(|action| action == "remove") {
+			self.devices.remove(devpath);
+			return;
+		}
+
+		let mut attributes: Vec<&String> = self
+			.rules
+			.rules
+			.iter()
+			.filter(|rule| rule.matches(event))
+			.flat_map(|rule| rule.attributes.iter())
+			.collect();
+		if attributes.is_empty() {
+			self.devices.remove(devpath);
+			return;
+		}
+
+		attributes.sort();
+		attributes.dedup();
+
+		// The values are read again on every kernel event, so that a change the kernel has already announced isn't
+		// announced again.
+		let device_path = device_path(&self.sysfs_root, devpath);
+		let device = WatchedDevice {
+			properties: IDENTIFYING_KEYS
+				.iter()
+				.filter_map(|key| event.get(*key).map(|value| (key.to_string(), value.clone())))
+				.collect(),
+			values: attributes
+				.into_iter()
+				.map(|attribute| (attribute.clone(), fs::read(device_path.join(attribute)).ok()))
+				.collect(),
+		};
+
+		self.devices.insert(devpath.clone(), device);
+	}
+
+	/// Reads every watched attribute, returning change events for the devices whose attributes have changed since
+	/// they were last read.
+	pub fn poll(&mut self) -> Vec<HashMap<String, String>> {
+		let mut events = Vec::new();
+		for (devpath, device) in self.devices.iter_mut() {
+			let device_path = device_path(&self.sysfs_root, devpath);
+			let mut changed = Vec::new();
+			for (attribute, value) in device.values.iter_mut() {
+				let new_value = fs::read(device_path.join(attribute)).ok();
+				if *value != new_value {
+					*value = new_value;
+					changed.push(attribute.as_str());
+				}
+			}
+
+			if changed.is_empty() {
+				continue;
+			}
+
+			let mut event: HashMap<String, String> = device.properties.clone().into_iter().collect();
+			event.insert(String::from("summary"), format!("change@{}", devpath));
+			event.insert(String::from("ACTION"), String::from("change"));
+			event.insert(String::from("SYNTHETIC"), String::from("1"));
+			event.insert(String::from("CHANGED_ATTRIBUTES"), changed.join(" "));
+			events.push(event);
+		}
+
+		events
+	}
+
+	/// Whether there are any rules, i.e. whether there's any point polling.
+	pub fn is_empty(&self) -> bool {
+		self.rules.rules.is_empty()
+	}
+
+	pub fn interval(&self) -> Duration {
+		self.rules.interval()
+	}
+}
+
+/// The directory of the device in sysfs.
+fn device_path(sysfs_root: &Path, devpath: &str) -> PathBuf {
+	sysfs_root.join(devpath.trim_start_matches('/'))
+}
+
+#[cfg(test)]
+mod test {
+	use std::{collections::HashMap, fs};
+
+	use super::{WatchRule, WatchRules, Watcher};
+	use common::testing::TempDir;
+
+	fn event(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+		pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+	}
+
+	#[test]
+	fn test_watcher() {
+		let root = TempDir::new("udevd-watch").unwrap();
+		let device = root.join("devices/virtual/block/loop0");
+		fs::create_dir_all(&device).unwrap();
+		fs::write(device.join("size"), "0\n").unwrap();
+
+		let rules = WatchRules {
+			rules: vec![WatchRule {
+				subsystem: Some(String::from("block")),
+				devtype: None,
+				kernel: Some(String::from("loop*")),
+				attributes: vec![String::from("size"), String::from("ro")],
+			}],
+			..WatchRules::default()
+		};
+
+		let mut watcher = Watcher::new(rules, root.path());
+		let add = event(&[
+			("ACTION", "add"),
+			("DEVPATH", "/devices/virtual/block/loop0"),
+			("SUBSYSTEM", "block"),
+			("DEVNAME", "loop0"),
+			("SEQNUM", "10"),
+		]);
+		watcher.handle_event(&add);
+		watcher.handle_event(&event(&[
+			("ACTION", "add"),
+			("DEVPATH", "/devices/virtual/net/lo"),
+			("SUBSYSTEM", "net"),
+		]));
+		assert!(watcher.poll().is_empty());
+
+		fs::write(device.join("size"), "2048\n").unwrap();
+		fs::write(device.join("ro"), "0\n").unwrap();
+		let events = watcher.poll();
+		assert_eq!(
+			events,
+			vec![event(&[
+				("summary", "change@/devices/virtual/block/loop0"),
+				("ACTION", "change"),
+				("DEVPATH", "/devices/virtual/block/loop0"),
+				("SUBSYSTEM", "block"),
+				("DEVNAME", "loop0"),
+				("SYNTHETIC", "1"),
+				("CHANGED_ATTRIBUTES", "ro size"),
+			])]
+		);
+		assert!(watcher.poll().is_empty());
+
+		// A change that the kernel announced itself isn't announced again.
+		fs::write(device.join("size"), "4096\n").unwrap();
+		watcher.handle_event(&event(&[
+			("ACTION", "change"),
+			("DEVPATH", "/devices/virtual/block/loop0"),
+			("SUBSYSTEM", "block"),
+		]));
+		assert!(watcher.poll().is_empty());
+
+		fs::write(device.join("size"), "0\n").unwrap();
+		watcher.handle_event(&event(&[
+			("ACTION", "remove"),
+			("DEVPATH", "/devices/virtual/block/loop0"),
+		]));
+		assert!(watcher.poll().is_empty());
+	}
+}